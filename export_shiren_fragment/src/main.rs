@@ -1,8 +1,8 @@
 use std::fs::File;
 
-use pmd_wan::shiren::{shiren_export_fragment, ShirenFragment, ShirenPalette, ShirenWan};
-use spritebot_storage::{Animation, Frame, FrameOffset, Sprite};
 use image::imageops::flip_horizontal;
+use pmd_wan::shiren::{shiren_export_fragment, ShirenPalette, ShirenWan};
+use spritebot_storage::{Animation, Frame, FrameOffset, Sprite};
 use vfs::PhysicalFS;
 
 fn main() {
@@ -48,12 +48,14 @@ fn main() {
                     println!("frame {} does not have the 32 by 32 resolution", frame_id);
                 }
 
-                let mut image = shiren_export_fragment(fragment_to_use, fragment_bytes, &shiren_palette).unwrap();
+                let mut image =
+                    shiren_export_fragment(fragment_to_use, fragment_bytes, &shiren_palette)
+                        .unwrap();
                 if fragment_to_use.is_h_flip {
                     image = flip_horizontal(&image);
                 }
                 frames_to_add.push(Frame {
-                    duration: frame.frame_duration.try_into().unwrap(),
+                    duration: frame.frame_duration,
                     image,
                     offsets: FrameOffset {
                         center: (0, 0),
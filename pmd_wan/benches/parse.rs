@@ -1,10 +1,19 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use pmd_wan::WanImage;
+use pmd_wan::{decode_fragment_pixels, encode_fragment_pixels, GeneralResolution, WanImage};
 use std::fs::File;
 use std::io::Cursor;
 use std::io::Read;
 
 pub fn criterion_benchmark(c: &mut Criterion) {
+    let pixels: Vec<u8> = (0..64 * 64).map(|pixel| (pixel % 16) as u8).collect();
+    let encoded = encode_fragment_pixels(&pixels, GeneralResolution::new(64, 64)).unwrap();
+    c.bench_function("decode fragment pixels", |b| {
+        b.iter(|| decode_fragment_pixels(&encoded, GeneralResolution::new(64, 64)).unwrap())
+    });
+    c.bench_function("encode fragment pixels", |b| {
+        b.iter(|| encode_fragment_pixels(&pixels, GeneralResolution::new(64, 64)).unwrap())
+    });
+
     let mut wan_file = File::open("/home/marius/pmd_wan/bulbasaurEU.wan").unwrap();
     let mut wan_data = Vec::new();
     wan_file.read_to_end(&mut wan_data).unwrap();
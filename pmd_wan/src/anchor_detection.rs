@@ -0,0 +1,170 @@
+use image::RgbaImage;
+
+use crate::{
+    BoundingBox, FragmentBytesToImageError, FrameMask, FrameOffset, FrameOffsetPoint, WanImage,
+};
+
+impl FrameMask {
+    /// Return the area covered by the opaque pixels, relative to the origin of the [`crate::Frame`].
+    /// Return [`None`] if there is no opaque pixel.
+    pub fn opaque_bounds(&self) -> Option<BoundingBox> {
+        BoundingBox::of_opaque_pixels(self.width, self.height, |x, y| self.is_opaque(x, y))
+            .map(|bounds| bounds.translate(-self.origin_x, -self.origin_y))
+    }
+}
+
+impl FrameOffset {
+    /// Guess the anchor points from the bounding box of a sprite, relative to the origin of the frame.
+    ///
+    /// The head is at the top middle, the center in the middle, and the hands on the left and right border at mid-height (`hand_left` being on the left of the image).
+    pub fn from_bounding_box(bounding_box: BoundingBox) -> FrameOffset {
+        let BoundingBox {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        } = bounding_box;
+        let clamp = |value: i32| value.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        let middle_x = clamp(min_x + (max_x - min_x) / 2);
+        let middle_y = clamp(min_y + (max_y - min_y) / 2);
+        FrameOffset {
+            head: (middle_x, clamp(min_y)),
+            hand_left: (clamp(min_x), middle_y),
+            hand_right: (clamp(max_x - 1), middle_y),
+            center: (middle_x, middle_y),
+        }
+    }
+}
+
+/// Detect the anchor points of a sprite, with the origin of the frame at `origin_x` and `origin_y` in the images.
+///
+/// Points whose SpriteBot color (see [`FrameOffsetPoint::spritebot_color`]) appear in `markers` (an image of the same layout as `sprite`, usually the offsets sheet) are taken from there.
+/// The other ones are guessed with [`FrameOffset::from_bounding_box`] from the non fully transparent pixels of `sprite`.
+/// Markers aren't searched in the sprite itself, as colors like the black of the head are also commonly used for outlines.
+///
+/// Return [`None`] if there is neither a marker nor an opaque pixel.
+pub fn detect_anchors(
+    sprite: &RgbaImage,
+    markers: Option<&RgbaImage>,
+    origin_x: i32,
+    origin_y: i32,
+) -> Option<FrameOffset> {
+    let bounds = BoundingBox::of_opaque_pixels(sprite.width(), sprite.height(), |x, y| {
+        sprite.get_pixel(x, y)[3] != 0
+    })
+    .map(|bounds| bounds.translate(-origin_x, -origin_y));
+
+    let mut found_marker = false;
+    let mut result = match bounds {
+        Some(bounds) => FrameOffset::from_bounding_box(bounds),
+        None => FrameOffset::default(),
+    };
+    if let Some(markers) = markers {
+        for point in FrameOffsetPoint::ALL.iter().copied() {
+            let color = point.spritebot_color();
+            if let Some((x, y, _)) = markers
+                .enumerate_pixels()
+                .find(|(_, _, pixel)| **pixel == color)
+            {
+                result.set(
+                    point,
+                    ((x as i32 - origin_x) as i16, (y as i32 - origin_y) as i16),
+                );
+                found_marker = true;
+            }
+        }
+    }
+
+    if bounds.is_none() && !found_marker {
+        None
+    } else {
+        Some(result)
+    }
+}
+
+impl WanImage {
+    /// Fill the [`FrameOffset`] of every frame that doesn't have one with [`FrameOffset::from_bounding_box`], using the opaque pixels of the frame.
+    /// Fully transparent frames are left without [`FrameOffset`].
+    ///
+    /// Return the number of frames that got a new [`FrameOffset`].
+    pub fn fill_missing_frame_offsets(&mut self) -> Result<usize, FragmentBytesToImageError> {
+        let mut filled = 0;
+        for frame_id in 0..self.frame_store.frames.len() {
+            let frame = &self.frame_store.frames[frame_id];
+            if frame.frame_offset.is_some() {
+                continue;
+            }
+            let mask = FrameMask::new_from_frame_with_depth(
+                frame,
+                &self.fragment_bytes_store,
+                self.is_256_color,
+            )?;
+            if let Some(bounds) = mask.opaque_bounds() {
+                self.frame_store.frames[frame_id].frame_offset =
+                    Some(FrameOffset::from_bounding_box(bounds));
+                filled += 1;
+            }
+        }
+        Ok(filled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{
+        detect_anchors, insert_frame_in_wanimage, BoundingBox, FrameOffset, SpriteType, WanImage,
+        SPRITEBOT_HEAD_COLOR,
+    };
+
+    #[test]
+    fn test_detect_anchors() {
+        let mut sprite = RgbaImage::new(16, 16);
+        for y in 4..12 {
+            for x in 6..10 {
+                sprite.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+            }
+        }
+        let from_bounds = detect_anchors(&sprite, None, 8, 8).unwrap();
+        assert_eq!(
+            from_bounds,
+            FrameOffset {
+                head: (0, -4),
+                hand_left: (-2, 0),
+                hand_right: (1, 0),
+                center: (0, 0),
+            }
+        );
+
+        let mut markers = RgbaImage::new(16, 16);
+        markers.put_pixel(9, 1, SPRITEBOT_HEAD_COLOR);
+        let with_marker = detect_anchors(&sprite, Some(&markers), 8, 8).unwrap();
+        assert_eq!(with_marker.head, (1, -7));
+        assert_eq!(with_marker.center, from_bounds.center);
+
+        assert_eq!(detect_anchors(&RgbaImage::new(4, 4), None, 2, 2), None);
+    }
+
+    #[test]
+    fn test_fill_missing_frame_offsets() {
+        let mut wanimage = WanImage::new(SpriteType::Chara);
+        wanimage.palette.palette = vec![[0, 0, 0, 0], [255, 255, 255, 128]];
+        let mut pixels = vec![0; 16];
+        pixels[5] = 1;
+        pixels[10] = 1;
+        insert_frame_in_wanimage(pixels, 4, 4, &mut wanimage, 0).unwrap();
+        assert_eq!(wanimage.fill_missing_frame_offsets().unwrap(), 1);
+        // the image is centered on the origin, so the opaque pixels go from (-1, -1) to (0, 0)
+        assert_eq!(
+            wanimage.frame_store.frames[0].frame_offset,
+            Some(FrameOffset::from_bounding_box(BoundingBox {
+                min_x: -1,
+                min_y: -1,
+                max_x: 1,
+                max_y: 1
+            }))
+        );
+        assert_eq!(wanimage.fill_missing_frame_offsets().unwrap(), 0);
+    }
+}
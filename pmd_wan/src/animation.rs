@@ -1,15 +1,24 @@
-use crate::{AnimationFrame, WanError};
+use crate::{decode_options::check_limit, AnimationFrame, WanError};
 use std::io::{Read, Write};
 
 /// An [`Animation`] is a set of [`AnimationFrame`], that will be played one after the other, and that would loop most of the time.
 /// The duration between an [`AnimationFrame`] and the next one is contained in the [`AnimationFrame`]
-#[derive(Debug, PartialEq, Eq, Default)]
+#[derive(Debug, PartialEq, Eq, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Animation {
     pub frames: Vec<AnimationFrame>,
 }
 
 impl Animation {
     pub fn new<F: Read>(file: &mut F) -> Result<Animation, WanError> {
+        Self::new_with_limit(file, usize::MAX)
+    }
+
+    /// Like [`Animation::new`], but fail if there are more than `max_frames` [`AnimationFrame`]s
+    pub(crate) fn new_with_limit<F: Read>(
+        file: &mut F,
+        max_frames: usize,
+    ) -> Result<Animation, WanError> {
         let mut frames = Vec::new();
         loop {
             let current_frame = AnimationFrame::new(file)?;
@@ -17,6 +26,7 @@ impl Animation {
                 break;
             }
             frames.push(current_frame);
+            check_limit(frames.len(), max_frames, "frames in an animation")?;
         }
         Ok(Animation { frames })
     }
@@ -29,6 +39,47 @@ impl Animation {
         self.len() == 0
     }
 
+    /// The sum of the duration of all the [`AnimationFrame`]s
+    pub fn total_duration(&self) -> u32 {
+        self.frames.iter().map(|frame| frame.duration as u32).sum()
+    }
+
+    /// Multiply the duration of every [`AnimationFrame`] by the factor, rounding to the nearest value.
+    /// Durations are kept between 1 and 255, as an [`AnimationFrame`] with a duration of 0 may be read as the end of the [`Animation`].
+    pub fn scale_durations(&mut self, factor: f32) {
+        for frame in &mut self.frames {
+            frame.duration = (frame.duration as f32 * factor).round().clamp(1.0, 255.0) as u8;
+        }
+    }
+
+    /// Ensure every [`AnimationFrame`] last at least `min_duration`
+    pub fn clamp_min_duration(&mut self, min_duration: u8) {
+        for frame in &mut self.frames {
+            frame.duration = frame.duration.max(min_duration);
+        }
+    }
+
+    /// Change the durations so the [`Animation`] last `total_duration`, while keeping the relative timing of the [`AnimationFrame`]s.
+    ///
+    /// The rounding errors are distributed so the total is exact, unless it is impossible while keeping each duration between 1 and 255.
+    /// Return the new total duration. An [`Animation`] with a total duration of 0 is left untouched.
+    pub fn retime_to_total(&mut self, total_duration: u32) -> u32 {
+        let old_total = self.total_duration() as u64;
+        if old_total == 0 {
+            return 0;
+        }
+        let mut old_end = 0;
+        let mut new_end = 0;
+        for frame in &mut self.frames {
+            old_end += frame.duration as u64;
+            let target_end = (old_end * total_duration as u64 + old_total / 2) / old_total;
+            let duration = target_end.saturating_sub(new_end).clamp(1, 255);
+            frame.duration = duration as u8;
+            new_end += duration;
+        }
+        new_end as u32
+    }
+
     pub fn write<F: Write>(file: &mut F, animation: &Animation) -> Result<(), WanError> {
         for frame in &animation.frames {
             AnimationFrame::write(file, frame)?;
@@ -37,3 +88,46 @@ impl Animation {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::fixtures::animation_frame, Animation};
+
+    fn animation_with_durations(durations: &[u8]) -> Animation {
+        Animation {
+            frames: durations
+                .iter()
+                .map(|duration| animation_frame(1, *duration))
+                .collect(),
+        }
+    }
+
+    fn durations(animation: &Animation) -> Vec<u8> {
+        animation
+            .frames
+            .iter()
+            .map(|frame| frame.duration)
+            .collect()
+    }
+
+    #[test]
+    fn test_retiming() {
+        let mut animation = animation_with_durations(&[2, 4, 200]);
+        animation.scale_durations(1.5);
+        assert_eq!(durations(&animation), vec![3, 6, 255]);
+        animation.scale_durations(0.1);
+        assert_eq!(durations(&animation), vec![1, 1, 26]);
+        animation.clamp_min_duration(4);
+        assert_eq!(durations(&animation), vec![4, 4, 26]);
+
+        let mut animation = animation_with_durations(&[1, 1, 1]);
+        assert_eq!(animation.retime_to_total(10), 10);
+        assert_eq!(durations(&animation), vec![3, 4, 3]);
+        assert_eq!(animation.retime_to_total(2), 3);
+        assert_eq!(durations(&animation), vec![1, 1, 1]);
+
+        let mut animation = animation_with_durations(&[10, 30]);
+        assert_eq!(animation.retime_to_total(20), 20);
+        assert_eq!(durations(&animation), vec![5, 15]);
+    }
+}
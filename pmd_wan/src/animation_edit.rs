@@ -0,0 +1,332 @@
+use thiserror::Error;
+
+use crate::{Animation, AnimationFrame, AnimationStore};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AnimationEditError {
+    #[error("The animation group {0} doesn't exist")]
+    NoGroup(usize),
+    #[error("The animation {animation} doesn't exist in the animation group {group}")]
+    NoAnimation { group: usize, animation: usize },
+    #[error("The animation frame {animation_frame} doesn't exist in the animation {animation} of the animation group {group}")]
+    NoAnimationFrame {
+        group: usize,
+        animation: usize,
+        animation_frame: usize,
+    },
+    #[error("An animation frame with a duration of 0 and the frame id 0 would be read as the end of the animation")]
+    NullAnimationFrame,
+}
+
+/// Methods to edit the [`Animation`]s and [`AnimationFrame`]s of an [`AnimationStore`].
+///
+/// Insertion indices can be equal to the length of the list, to add at the end. [`AnimationStore::copied_on_previous`] is kept in sync with the [`Animation`]s.
+impl AnimationStore {
+    /// The index of the given [`Animation`] if all the groups were concatenated, as used by [`AnimationStore::copied_on_previous`]
+    fn flat_animation_index(&self, group: usize, animation: usize) -> usize {
+        self.anim_groups[..group]
+            .iter()
+            .map(|group| group.len())
+            .sum::<usize>()
+            + animation
+    }
+
+    fn insert_copy_flags(&mut self, flat_index: usize, count: usize) {
+        if let Some(copied_on_previous) = &mut self.copied_on_previous {
+            if flat_index <= copied_on_previous.len() {
+                copied_on_previous.splice(flat_index..flat_index, std::iter::repeat_n(true, count));
+            }
+        }
+    }
+
+    fn remove_copy_flags(&mut self, flat_index: usize, count: usize) {
+        if let Some(copied_on_previous) = &mut self.copied_on_previous {
+            let end = (flat_index + count).min(copied_on_previous.len());
+            if flat_index < end {
+                copied_on_previous.drain(flat_index..end);
+            }
+        }
+    }
+
+    fn check_animation(&self, group: usize, animation: usize) -> Result<(), AnimationEditError> {
+        if self
+            .anim_groups
+            .get(group)
+            .ok_or(AnimationEditError::NoGroup(group))?
+            .len()
+            <= animation
+        {
+            return Err(AnimationEditError::NoAnimation { group, animation });
+        }
+        Ok(())
+    }
+
+    fn get_animation_mut(
+        &mut self,
+        group: usize,
+        animation: usize,
+    ) -> Result<&mut Animation, AnimationEditError> {
+        self.check_animation(group, animation)?;
+        Ok(&mut self.anim_groups[group][animation])
+    }
+
+    /// Insert a new animation group at the given position, shifting the following ones
+    pub fn insert_group(
+        &mut self,
+        group: usize,
+        animations: Vec<Animation>,
+    ) -> Result<(), AnimationEditError> {
+        if group > self.anim_groups.len() {
+            return Err(AnimationEditError::NoGroup(group));
+        }
+        let flat_index = self.flat_animation_index(group, 0);
+        self.insert_copy_flags(flat_index, animations.len());
+        self.anim_groups.insert(group, animations);
+        Ok(())
+    }
+
+    /// Remove an animation group, shifting the following ones, and return its [`Animation`]s.
+    ///
+    /// Use [`Vec::clear`] on the group instead to keep the following groups at the same index.
+    pub fn remove_group(&mut self, group: usize) -> Result<Vec<Animation>, AnimationEditError> {
+        if group >= self.anim_groups.len() {
+            return Err(AnimationEditError::NoGroup(group));
+        }
+        let flat_index = self.flat_animation_index(group, 0);
+        self.remove_copy_flags(flat_index, self.anim_groups[group].len());
+        Ok(self.anim_groups.remove(group))
+    }
+
+    pub fn insert_animation(
+        &mut self,
+        group: usize,
+        animation: usize,
+        value: Animation,
+    ) -> Result<(), AnimationEditError> {
+        let group_len = self
+            .anim_groups
+            .get(group)
+            .ok_or(AnimationEditError::NoGroup(group))?
+            .len();
+        if animation > group_len {
+            return Err(AnimationEditError::NoAnimation { group, animation });
+        }
+        if value.frames.iter().any(|frame| frame.is_null()) {
+            return Err(AnimationEditError::NullAnimationFrame);
+        }
+        let flat_index = self.flat_animation_index(group, animation);
+        self.insert_copy_flags(flat_index, 1);
+        self.anim_groups[group].insert(animation, value);
+        Ok(())
+    }
+
+    pub fn remove_animation(
+        &mut self,
+        group: usize,
+        animation: usize,
+    ) -> Result<Animation, AnimationEditError> {
+        self.check_animation(group, animation)?;
+        let flat_index = self.flat_animation_index(group, animation);
+        self.remove_copy_flags(flat_index, 1);
+        Ok(self.anim_groups[group].remove(animation))
+    }
+
+    /// Insert a copy of the [`Animation`] just after it, and return the index of the copy
+    pub fn duplicate_animation(
+        &mut self,
+        group: usize,
+        animation: usize,
+    ) -> Result<usize, AnimationEditError> {
+        self.check_animation(group, animation)?;
+        let copy = self.anim_groups[group][animation].clone();
+        self.insert_animation(group, animation + 1, copy)?;
+        Ok(animation + 1)
+    }
+
+    /// Move an [`Animation`] inside its group, so it end at the index `to`
+    pub fn move_animation(
+        &mut self,
+        group: usize,
+        from: usize,
+        to: usize,
+    ) -> Result<(), AnimationEditError> {
+        self.check_animation(group, from)?;
+        self.check_animation(group, to)?;
+        let flat_from = self.flat_animation_index(group, from);
+        let flat_to = self.flat_animation_index(group, to);
+        if let Some(copied_on_previous) = &mut self.copied_on_previous {
+            if flat_from.max(flat_to) < copied_on_previous.len() {
+                let flag = copied_on_previous.remove(flat_from);
+                copied_on_previous.insert(flat_to, flag);
+            }
+        }
+        let value = self.anim_groups[group].remove(from);
+        self.anim_groups[group].insert(to, value);
+        Ok(())
+    }
+
+    pub fn insert_animation_frame(
+        &mut self,
+        group: usize,
+        animation: usize,
+        animation_frame: usize,
+        value: AnimationFrame,
+    ) -> Result<(), AnimationEditError> {
+        if value.is_null() {
+            return Err(AnimationEditError::NullAnimationFrame);
+        }
+        let frames = &mut self.get_animation_mut(group, animation)?.frames;
+        if animation_frame > frames.len() {
+            return Err(AnimationEditError::NoAnimationFrame {
+                group,
+                animation,
+                animation_frame,
+            });
+        }
+        frames.insert(animation_frame, value);
+        Ok(())
+    }
+
+    pub fn remove_animation_frame(
+        &mut self,
+        group: usize,
+        animation: usize,
+        animation_frame: usize,
+    ) -> Result<AnimationFrame, AnimationEditError> {
+        let frames = &mut self.get_animation_mut(group, animation)?.frames;
+        if animation_frame >= frames.len() {
+            return Err(AnimationEditError::NoAnimationFrame {
+                group,
+                animation,
+                animation_frame,
+            });
+        }
+        Ok(frames.remove(animation_frame))
+    }
+
+    /// Insert a copy of the [`AnimationFrame`] just after it, and return the index of the copy
+    pub fn duplicate_animation_frame(
+        &mut self,
+        group: usize,
+        animation: usize,
+        animation_frame: usize,
+    ) -> Result<usize, AnimationEditError> {
+        let frames = &mut self.get_animation_mut(group, animation)?.frames;
+        let copy = frames
+            .get(animation_frame)
+            .ok_or(AnimationEditError::NoAnimationFrame {
+                group,
+                animation,
+                animation_frame,
+            })?
+            .clone();
+        frames.insert(animation_frame + 1, copy);
+        Ok(animation_frame + 1)
+    }
+
+    /// Move an [`AnimationFrame`] inside its [`Animation`], so it end at the index `to`
+    pub fn move_animation_frame(
+        &mut self,
+        group: usize,
+        animation: usize,
+        from: usize,
+        to: usize,
+    ) -> Result<(), AnimationEditError> {
+        let frames = &mut self.get_animation_mut(group, animation)?.frames;
+        for animation_frame in [from, to] {
+            if animation_frame >= frames.len() {
+                return Err(AnimationEditError::NoAnimationFrame {
+                    group,
+                    animation,
+                    animation_frame,
+                });
+            }
+        }
+        let value = frames.remove(from);
+        frames.insert(to, value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::fixtures::animation_frame, Animation, AnimationEditError, AnimationStore};
+
+    fn animation(frame_ids: &[u16]) -> Animation {
+        Animation {
+            frames: frame_ids
+                .iter()
+                .copied()
+                .map(|frame_id| animation_frame(frame_id, 1))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_edit_animations() {
+        let mut store = AnimationStore {
+            copied_on_previous: Some(vec![false, false, false]),
+            anim_groups: vec![
+                vec![animation(&[0]), animation(&[1])],
+                vec![animation(&[2])],
+            ],
+        };
+        store.insert_animation(0, 1, animation(&[3])).unwrap();
+        assert_eq!(
+            store.copied_on_previous,
+            Some(vec![false, true, false, false])
+        );
+        assert_eq!(store.anim_groups[0][1], animation(&[3]));
+
+        assert_eq!(store.duplicate_animation(1, 0).unwrap(), 1);
+        assert_eq!(store.anim_groups[1], vec![animation(&[2]), animation(&[2])]);
+        assert_eq!(
+            store.copied_on_previous,
+            Some(vec![false, true, false, false, true])
+        );
+
+        store.move_animation(0, 0, 2).unwrap();
+        assert_eq!(
+            store.anim_groups[0],
+            vec![animation(&[3]), animation(&[1]), animation(&[0])]
+        );
+        assert_eq!(
+            store.copied_on_previous,
+            Some(vec![true, false, false, false, true])
+        );
+
+        assert_eq!(store.remove_group(0).unwrap().len(), 3);
+        assert_eq!(store.copied_on_previous, Some(vec![false, true]));
+        assert_eq!(
+            store.remove_animation(0, 5),
+            Err(AnimationEditError::NoAnimation {
+                group: 0,
+                animation: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_edit_animation_frames() {
+        let mut store = AnimationStore {
+            copied_on_previous: None,
+            anim_groups: vec![vec![animation(&[0, 1])]],
+        };
+        store
+            .insert_animation_frame(0, 0, 2, animation_frame(2, 1))
+            .unwrap();
+        assert_eq!(store.duplicate_animation_frame(0, 0, 0).unwrap(), 1);
+        store.move_animation_frame(0, 0, 3, 0).unwrap();
+        assert_eq!(store.anim_groups[0][0], animation(&[2, 0, 0, 1]));
+        assert_eq!(
+            store.remove_animation_frame(0, 0, 1).unwrap(),
+            animation_frame(0, 1)
+        );
+        let mut null_frame = animation_frame(0, 1);
+        null_frame.duration = 0;
+        assert_eq!(
+            store.insert_animation_frame(0, 0, 0, null_frame),
+            Err(AnimationEditError::NullAnimationFrame)
+        );
+    }
+}
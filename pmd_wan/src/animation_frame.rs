@@ -1,10 +1,63 @@
-use crate::{get_opt_le, WanError};
+use crate::{get_opt_le, Animation, WanError};
 use binwrite::BinWrite;
 use byteorder::{ReadBytesExt, LE};
 use std::io::{Read, Write};
+use thiserror::Error;
+
+/// The bit of [`AnimationFrame::flag`] that mark the frame an attack hit its target (or its effect is spawned), in the attack animations
+pub const ANIMATION_FRAME_FLAG_HIT: u8 = 0x01;
+/// The bit of [`AnimationFrame::flag`] that mark the frame a looping animation restart from
+pub const ANIMATION_FRAME_FLAG_RETURN_POINT: u8 = 0x02;
+/// Every bit of [`AnimationFrame::flag`] whose use is known
+pub const ANIMATION_FRAME_KNOWN_FLAGS: u8 =
+    ANIMATION_FRAME_FLAG_HIT | ANIMATION_FRAME_FLAG_RETURN_POINT;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AnimationFrameFlagError {
+    #[error("The animation frame {animation_frame} has the flag bits 0x{bits:02X}, whose use is unknown")]
+    UnknownBits { animation_frame: usize, bits: u8 },
+    #[error("The animation frames {first} and {second} are both marked as the return point")]
+    MultipleReturnPoints { first: usize, second: usize },
+}
+
+/// The bits of [`AnimationFrame::flag`], as returned by [`AnimationFrame::flags`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct AnimationFrameFlags {
+    /// See [`ANIMATION_FRAME_FLAG_HIT`]
+    pub hit: bool,
+    /// See [`ANIMATION_FRAME_FLAG_RETURN_POINT`]
+    pub return_point: bool,
+    /// The other bits, whose use is unknown, kept as they are. The known bits are ignored.
+    pub unknown: u8,
+}
+
+impl AnimationFrameFlags {
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            hit: bits & ANIMATION_FRAME_FLAG_HIT != 0,
+            return_point: bits & ANIMATION_FRAME_FLAG_RETURN_POINT != 0,
+            unknown: bits & !ANIMATION_FRAME_KNOWN_FLAGS,
+        }
+    }
+
+    pub fn to_bits(self) -> u8 {
+        (self.unknown & !ANIMATION_FRAME_KNOWN_FLAGS)
+            | if self.hit {
+                ANIMATION_FRAME_FLAG_HIT
+            } else {
+                0
+            }
+            | if self.return_point {
+                ANIMATION_FRAME_FLAG_RETURN_POINT
+            } else {
+                0
+            }
+    }
+}
 
 /// A single frame of an [`crate::Animation`]
 #[derive(Debug, PartialEq, Clone, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnimationFrame {
     pub duration: u8,
     pub flag: u8,
@@ -35,6 +88,24 @@ impl AnimationFrame {
         })
     }
 
+    /// true if the animation restart from this frame when looping, instead of from the first one
+    pub fn is_return_point(&self) -> bool {
+        self.flag & ANIMATION_FRAME_FLAG_RETURN_POINT != 0
+    }
+
+    /// true if the attack hit on this frame (see [`ANIMATION_FRAME_FLAG_HIT`])
+    pub fn is_hit(&self) -> bool {
+        self.flag & ANIMATION_FRAME_FLAG_HIT != 0
+    }
+
+    pub fn flags(&self) -> AnimationFrameFlags {
+        AnimationFrameFlags::from_bits(self.flag)
+    }
+
+    pub fn set_flags(&mut self, flags: AnimationFrameFlags) {
+        self.flag = flags.to_bits();
+    }
+
     pub fn is_null(&self) -> bool {
         self.duration == 0 && self.frame_id == 0
     }
@@ -69,3 +140,93 @@ impl AnimationFrame {
         )
     }
 }
+
+impl Animation {
+    /// The index of the [`AnimationFrame`]s marked with [`ANIMATION_FRAME_FLAG_HIT`]
+    pub fn hit_frames(&self) -> Vec<usize> {
+        self.frames
+            .iter()
+            .enumerate()
+            .filter(|(_, frame)| frame.is_hit())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Check the flags of the [`AnimationFrame`]s: only the bits of [`ANIMATION_FRAME_KNOWN_FLAGS`] should be set, and at most one frame should be the return point
+    pub fn validate_flags(&self) -> Result<(), AnimationFrameFlagError> {
+        let mut return_point = None;
+        for (animation_frame, frame) in self.frames.iter().enumerate() {
+            let bits = frame.flag & !ANIMATION_FRAME_KNOWN_FLAGS;
+            if bits != 0 {
+                return Err(AnimationFrameFlagError::UnknownBits {
+                    animation_frame,
+                    bits,
+                });
+            }
+            if frame.is_return_point() {
+                if let Some(first) = return_point {
+                    return Err(AnimationFrameFlagError::MultipleReturnPoints {
+                        first,
+                        second: animation_frame,
+                    });
+                }
+                return_point = Some(animation_frame);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::fixtures::animation_frame, Animation, AnimationFrame, AnimationFrameFlagError,
+        AnimationFrameFlags, ANIMATION_FRAME_FLAG_HIT, ANIMATION_FRAME_FLAG_RETURN_POINT,
+    };
+
+    #[test]
+    fn test_animation_frame_flags() {
+        let flags = AnimationFrameFlags::from_bits(0x83);
+        assert!(flags.hit && flags.return_point);
+        assert_eq!(flags.unknown, 0x80);
+        assert_eq!(flags.to_bits(), 0x83);
+
+        let flagged_frame = |flag| AnimationFrame {
+            flag,
+            ..animation_frame(0, 2)
+        };
+        let mut frame = flagged_frame(0);
+        frame.set_flags(AnimationFrameFlags {
+            hit: true,
+            ..Default::default()
+        });
+        assert_eq!(frame.flag, ANIMATION_FRAME_FLAG_HIT);
+        assert!(frame.is_hit() && !frame.is_return_point());
+
+        let mut animation = Animation {
+            frames: vec![
+                flagged_frame(0),
+                flagged_frame(ANIMATION_FRAME_FLAG_RETURN_POINT),
+                flagged_frame(ANIMATION_FRAME_FLAG_HIT),
+            ],
+        };
+        assert_eq!(animation.hit_frames(), vec![2]);
+        assert_eq!(animation.validate_flags(), Ok(()));
+        animation.frames[2].flag |= ANIMATION_FRAME_FLAG_RETURN_POINT;
+        assert_eq!(
+            animation.validate_flags(),
+            Err(AnimationFrameFlagError::MultipleReturnPoints {
+                first: 1,
+                second: 2
+            })
+        );
+        animation.frames[0].flag = 0x10;
+        assert_eq!(
+            animation.validate_flags(),
+            Err(AnimationFrameFlagError::UnknownBits {
+                animation_frame: 0,
+                bits: 0x10
+            })
+        );
+    }
+}
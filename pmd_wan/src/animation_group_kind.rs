@@ -0,0 +1,228 @@
+use thiserror::Error;
+
+use crate::{Animation, AnimationEditError, AnimationStore, Direction, SPRITEBOT_ANIMATION_NAMES};
+
+/// The number of directions of a directional animation group
+pub const DIRECTION_COUNT: usize = 8;
+
+/// The standard animation groups of the monster sprites of Explorers of Sky, in the order of their index.
+///
+/// Group usually contain one [`Animation`] per direction (see [`DIRECTION_COUNT`]). The names are the ones used by SpriteCollab, see [`SPRITEBOT_ANIMATION_NAMES`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AnimationGroupKind {
+    Walk,
+    Attack,
+    Kick,
+    Shoot,
+    Strike,
+    Sleep,
+    Hurt,
+    Idle,
+    Swing,
+    Double,
+    Hop,
+    Charge,
+    Rotate,
+    EventSleep,
+    Wake,
+    Eat,
+    Tumble,
+    Pose,
+    Pull,
+    Pain,
+    Float,
+    DeepBreath,
+    Nod,
+    Sit,
+    LookUp,
+    Sink,
+    Trip,
+    Laying,
+    LeapForth,
+    Head,
+    Cringe,
+    LostBalance,
+    TumbleBack,
+    Faint,
+    HitGround,
+}
+
+impl AnimationGroupKind {
+    /// All the kinds, in the order of their index
+    pub const ALL: [Self; 35] = [
+        Self::Walk,
+        Self::Attack,
+        Self::Kick,
+        Self::Shoot,
+        Self::Strike,
+        Self::Sleep,
+        Self::Hurt,
+        Self::Idle,
+        Self::Swing,
+        Self::Double,
+        Self::Hop,
+        Self::Charge,
+        Self::Rotate,
+        Self::EventSleep,
+        Self::Wake,
+        Self::Eat,
+        Self::Tumble,
+        Self::Pose,
+        Self::Pull,
+        Self::Pain,
+        Self::Float,
+        Self::DeepBreath,
+        Self::Nod,
+        Self::Sit,
+        Self::LookUp,
+        Self::Sink,
+        Self::Trip,
+        Self::Laying,
+        Self::LeapForth,
+        Self::Head,
+        Self::Cringe,
+        Self::LostBalance,
+        Self::TumbleBack,
+        Self::Faint,
+        Self::HitGround,
+    ];
+
+    /// The index of the animation group of this kind in [`AnimationStore::anim_groups`]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    /// The name used by SpriteCollab for this kind
+    pub fn name(self) -> &'static str {
+        SPRITEBOT_ANIMATION_NAMES[self.index()]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        SPRITEBOT_ANIMATION_NAMES
+            .iter()
+            .position(|kind_name| *kind_name == name)
+            .and_then(Self::from_index)
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AnimationGroupError {
+    #[error("The animation group {0} contain {1} animations, but it should contain 1 (for all directions) or 8 (one per direction)")]
+    InvalidDirectionCount(usize, usize),
+    #[error(transparent)]
+    EditError(#[from] AnimationEditError),
+}
+
+impl AnimationStore {
+    /// Return the [`Animation`]s of the group of the given kind, or [`None`] if it doesn't exist or is empty
+    pub fn get_group(&self, kind: AnimationGroupKind) -> Option<&[Animation]> {
+        self.anim_groups
+            .get(kind.index())
+            .filter(|group| !group.is_empty())
+            .map(|group| group.as_slice())
+    }
+
+    /// Return the [`Animation`] of the group of the given kind for the given direction, see [`AnimationStore::get_direction_animation`]
+    pub fn get_group_animation(
+        &self,
+        kind: AnimationGroupKind,
+        direction: Direction,
+    ) -> Option<&Animation> {
+        self.get_direction_animation(kind.index(), direction)
+    }
+
+    /// Replace the [`Animation`]s of the group of the given kind, adding empty groups before it if needed.
+    ///
+    /// The group should contain either 1 [`Animation`] (used for all directions) or 8 (one per direction), or be empty to remove it.
+    pub fn set_group(
+        &mut self,
+        kind: AnimationGroupKind,
+        animations: Vec<Animation>,
+    ) -> Result<(), AnimationGroupError> {
+        if ![0, 1, DIRECTION_COUNT].contains(&animations.len()) {
+            return Err(AnimationGroupError::InvalidDirectionCount(
+                kind.index(),
+                animations.len(),
+            ));
+        }
+        let index = kind.index();
+        while self.anim_groups.len() <= index {
+            self.insert_group(self.anim_groups.len(), Vec::new())?;
+        }
+        self.remove_group(index)?;
+        self.insert_group(index, animations)?;
+        Ok(())
+    }
+
+    /// Set the [`Animation`] of a direction of the group of the given kind, see [`AnimationStore::set_direction_animation`]
+    pub fn set_group_animation(
+        &mut self,
+        kind: AnimationGroupKind,
+        direction: Direction,
+        animation: Animation,
+    ) -> Result<(), AnimationGroupError> {
+        self.set_direction_animation(kind.index(), direction, animation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::fixtures::animation_frame, Animation, AnimationGroupError, AnimationGroupKind,
+        AnimationStore, Direction,
+    };
+
+    fn animation(frame_id: u16) -> Animation {
+        Animation {
+            frames: vec![animation_frame(frame_id, 1)],
+        }
+    }
+
+    #[test]
+    fn test_animation_group_kind() {
+        assert_eq!(AnimationGroupKind::Idle.index(), 7);
+        assert_eq!(AnimationGroupKind::Idle.name(), "Idle");
+        assert_eq!(
+            AnimationGroupKind::from_name("HitGround"),
+            Some(AnimationGroupKind::HitGround)
+        );
+        for (index, kind) in AnimationGroupKind::ALL.iter().enumerate() {
+            assert_eq!(kind.index(), index);
+        }
+    }
+
+    #[test]
+    fn test_set_group() {
+        let mut store = AnimationStore {
+            copied_on_previous: Some(vec![false]),
+            anim_groups: vec![vec![animation(0)]],
+        };
+        store
+            .set_group(AnimationGroupKind::Attack, vec![animation(1)])
+            .unwrap();
+        assert_eq!(store.anim_groups.len(), 2);
+        assert_eq!(
+            store.get_group_animation(AnimationGroupKind::Attack, Direction::UpLeft),
+            Some(&animation(1))
+        );
+        assert_eq!(store.get_group(AnimationGroupKind::Idle), None);
+
+        store
+            .set_group_animation(AnimationGroupKind::Attack, Direction::Right, animation(2))
+            .unwrap();
+        let group = store.get_group(AnimationGroupKind::Attack).unwrap();
+        assert_eq!(group.len(), 8);
+        assert_eq!(group[1], animation(1));
+        assert_eq!(group[2], animation(2));
+        assert_eq!(store.copied_on_previous.as_ref().unwrap().len(), 9);
+
+        assert_eq!(
+            store.set_group(AnimationGroupKind::Walk, vec![animation(0); 3]),
+            Err(AnimationGroupError::InvalidDirectionCount(0, 3))
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use thiserror::Error;
+
+use crate::{Animation, AnimationFrame, Frame, FrameOffset, WanImage};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AnimationPlayerError {
+    #[error("The animation {animation} of the animation group {group} doesn't exist")]
+    NoAnimation { group: usize, animation: usize },
+    #[error("The animation has no frame, or a total duration of 0")]
+    EmptyAnimation,
+}
+
+/// What to do once the last [`AnimationFrame`] of an [`Animation`] has been played
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopMode {
+    /// Stay on the last [`AnimationFrame`]
+    Once,
+    /// Restart from the return point (see [`AnimationFrame::is_return_point`]), or from the first [`AnimationFrame`] if there is none
+    Loop,
+}
+
+/// The state of an [`Animation`] at a given tick, as returned by [`AnimationPlayer::state_at`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaybackState<'a> {
+    /// The index of the active [`AnimationFrame`] in the [`Animation`]
+    pub animation_frame_index: usize,
+    pub animation_frame: &'a AnimationFrame,
+    /// The [`Frame`] to display, or [`None`] if the [`AnimationFrame`] reference a frame that doesn't exist
+    pub frame: Option<&'a Frame>,
+    /// The number of ticks since the [`AnimationFrame`] started
+    pub ticks_into_frame: u32,
+    /// The number of times the animation restarted from its return point
+    pub loop_count: u64,
+    /// true if the animation is over (only with [`LoopMode::Once`])
+    pub finished: bool,
+    /// The [`FrameOffset`] of the [`Frame`], moved by the offset of the [`AnimationFrame`], so it is relative to the position of the sprite
+    pub frame_offset: Option<FrameOffset>,
+}
+
+/// Compute which [`AnimationFrame`] of an [`Animation`] is displayed at a given time, like the game does.
+///
+/// The time is in ticks, the unit of [`AnimationFrame::duration`] (a frame of the DS screen, 1/60 second).
+/// The animation is first played from the start. If looping, it then restart from its return point once the end is reached.
+#[derive(Debug, Clone, Copy)]
+pub struct AnimationPlayer<'a> {
+    wan_image: &'a WanImage,
+    animation: &'a Animation,
+    loop_mode: LoopMode,
+    return_point: usize,
+    total_duration: u64,
+    loop_duration: u64,
+}
+
+impl<'a> AnimationPlayer<'a> {
+    pub fn new(
+        wan_image: &'a WanImage,
+        group: usize,
+        animation: usize,
+        loop_mode: LoopMode,
+    ) -> Result<Self, AnimationPlayerError> {
+        let animation_ref = wan_image
+            .animation_store
+            .anim_groups
+            .get(group)
+            .and_then(|group| group.get(animation))
+            .ok_or(AnimationPlayerError::NoAnimation { group, animation })?;
+        Self::new_for_animation(wan_image, animation_ref, loop_mode)
+    }
+
+    /// Play an [`Animation`] that may not be part of the [`WanImage`], using its [`Frame`]s
+    pub fn new_for_animation(
+        wan_image: &'a WanImage,
+        animation: &'a Animation,
+        loop_mode: LoopMode,
+    ) -> Result<Self, AnimationPlayerError> {
+        let total_duration = animation.total_duration() as u64;
+        if total_duration == 0 {
+            return Err(AnimationPlayerError::EmptyAnimation);
+        }
+        let return_point = animation
+            .frames
+            .iter()
+            .position(|frame| frame.is_return_point())
+            .unwrap_or(0);
+        let loop_duration = animation.frames[return_point..]
+            .iter()
+            .map(|frame| frame.duration as u64)
+            .sum();
+        Ok(Self {
+            wan_image,
+            animation,
+            loop_mode,
+            return_point,
+            total_duration,
+            loop_duration,
+        })
+    }
+
+    /// The index of the [`AnimationFrame`] the animation restart from when looping
+    pub fn return_point(&self) -> usize {
+        self.return_point
+    }
+
+    /// The duration of the first play of the animation
+    pub fn total_duration(&self) -> u64 {
+        self.total_duration
+    }
+
+    /// The duration of each loop after the first play (from the return point to the end)
+    pub fn loop_duration(&self) -> u64 {
+        self.loop_duration
+    }
+
+    /// Compute the state of the animation after the given number of ticks since it started
+    pub fn state_at(&self, tick: u64) -> PlaybackState<'a> {
+        let frames = &self.animation.frames;
+        let (animation_frame_index, ticks_into_frame, loop_count, finished) =
+            if tick < self.total_duration {
+                let (index, into) = find_frame(frames, 0, tick);
+                (index, into, 0, false)
+            } else if self.loop_mode == LoopMode::Loop && self.loop_duration > 0 {
+                let since_end = tick - self.total_duration;
+                let (index, into) =
+                    find_frame(frames, self.return_point, since_end % self.loop_duration);
+                (index, into, since_end / self.loop_duration + 1, false)
+            } else {
+                let last = frames.len() - 1;
+                let last_start = self.total_duration - frames[last].duration as u64;
+                (
+                    last,
+                    (tick - last_start).min(u32::MAX as u64) as u32,
+                    0,
+                    self.loop_mode == LoopMode::Once,
+                )
+            };
+
+        let animation_frame = &frames[animation_frame_index];
+        let frame = self
+            .wan_image
+            .frame_store
+            .frames
+            .get(animation_frame.frame_id as usize);
+        let frame_offset =
+            frame
+                .and_then(|frame| frame.frame_offset.as_ref())
+                .map(|frame_offset| {
+                    let shift = |(x, y): (i16, i16)| {
+                        (
+                            x.saturating_add(animation_frame.offset_x),
+                            y.saturating_add(animation_frame.offset_y),
+                        )
+                    };
+                    FrameOffset {
+                        head: shift(frame_offset.head),
+                        hand_left: shift(frame_offset.hand_left),
+                        hand_right: shift(frame_offset.hand_right),
+                        center: shift(frame_offset.center),
+                    }
+                });
+        PlaybackState {
+            animation_frame_index,
+            animation_frame,
+            frame,
+            ticks_into_frame,
+            loop_count,
+            finished,
+            frame_offset,
+        }
+    }
+}
+
+/// Find the [`AnimationFrame`] active `tick` ticks after the start of the frame `start`, and the number of ticks since it started.
+/// `tick` should be less than the total duration of the frames from `start`.
+fn find_frame(frames: &[AnimationFrame], start: usize, tick: u64) -> (usize, u32) {
+    let mut frame_start = 0;
+    for (index, frame) in frames.iter().enumerate().skip(start) {
+        let frame_end = frame_start + frame.duration as u64;
+        if tick < frame_end {
+            return (index, (tick - frame_start) as u32);
+        }
+        frame_start = frame_end;
+    }
+    (frames.len() - 1, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::fixtures::animation_frame,
+        tests::fixtures::{insert_filled_frame, test_wan_image},
+        Animation, AnimationFrame, AnimationPlayer, AnimationPlayerError, LoopMode, SpriteType,
+        WanImage, ANIMATION_FRAME_FLAG_RETURN_POINT,
+    };
+
+    fn player_frame(duration: u8, flag: u8) -> AnimationFrame {
+        AnimationFrame {
+            flag,
+            offset_x: 2,
+            ..animation_frame(0, duration)
+        }
+    }
+
+    fn create_wan_image() -> WanImage {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        insert_filled_frame(&mut wanimage, 2, 2);
+        wanimage.frame_store.frames[0].frame_offset = Some(crate::FrameOffset {
+            head: (0, -5),
+            hand_left: (0, 0),
+            hand_right: (0, 0),
+            center: (0, 0),
+        });
+        wanimage.animation_store.anim_groups = vec![vec![Animation {
+            frames: vec![
+                player_frame(2, 0),
+                player_frame(3, ANIMATION_FRAME_FLAG_RETURN_POINT),
+                player_frame(4, 0),
+            ],
+        }]];
+        wanimage
+    }
+
+    #[test]
+    fn test_animation_player_loop() {
+        let wanimage = create_wan_image();
+        let player = AnimationPlayer::new(&wanimage, 0, 0, LoopMode::Loop).unwrap();
+        assert_eq!(player.return_point(), 1);
+        assert_eq!(player.total_duration(), 9);
+        assert_eq!(player.loop_duration(), 7);
+
+        let state = player.state_at(1);
+        assert_eq!(state.animation_frame_index, 0);
+        assert_eq!(state.ticks_into_frame, 1);
+        assert_eq!(state.frame_offset.unwrap().head, (2, -5));
+        assert!(state.frame.is_some());
+
+        let state = player.state_at(8);
+        assert_eq!((state.animation_frame_index, state.loop_count), (2, 0));
+        // the first loop restart from the return point
+        let state = player.state_at(9);
+        assert_eq!(
+            (
+                state.animation_frame_index,
+                state.ticks_into_frame,
+                state.loop_count
+            ),
+            (1, 0, 1)
+        );
+        let state = player.state_at(9 + 7 + 3);
+        assert_eq!((state.animation_frame_index, state.loop_count), (2, 2));
+        assert!(!state.finished);
+    }
+
+    #[test]
+    fn test_animation_player_once() {
+        let wanimage = create_wan_image();
+        let player = AnimationPlayer::new(&wanimage, 0, 0, LoopMode::Once).unwrap();
+        let state = player.state_at(100);
+        assert_eq!(state.animation_frame_index, 2);
+        assert_eq!(state.ticks_into_frame, 95);
+        assert!(state.finished);
+        assert!(!player.state_at(8).finished);
+
+        assert_eq!(
+            AnimationPlayer::new(&wanimage, 0, 1, LoopMode::Once).unwrap_err(),
+            AnimationPlayerError::NoAnimation {
+                group: 0,
+                animation: 1
+            }
+        );
+        let empty = Animation::default();
+        assert_eq!(
+            AnimationPlayer::new_for_animation(&wanimage, &empty, LoopMode::Loop).unwrap_err(),
+            AnimationPlayerError::EmptyAnimation
+        );
+    }
+}
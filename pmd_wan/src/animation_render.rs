@@ -0,0 +1,204 @@
+use std::io::Write;
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, RgbaImage,
+};
+use thiserror::Error;
+
+use crate::{
+    image_tool::{upscale_image, write_apng, UpscaleFilter},
+    Animation, FrameRenderError, WanImage,
+};
+
+/// The number of [`crate::AnimationFrame::duration`] units in a second. The game run at 60 frames per second.
+pub const ANIMATION_FRAMES_PER_SECOND: u32 = 60;
+
+#[derive(Error, Debug)]
+pub enum AnimationRenderError {
+    #[error("Failed to render a frame of the animation")]
+    FrameRenderError(#[from] FrameRenderError),
+    #[error("Failed to encode the animation")]
+    ImageError(#[from] image::ImageError),
+    #[error("Failed to encode the animation as an APNG")]
+    PngError(#[from] png::EncodingError),
+    #[error("The animation doesn't have any frame")]
+    EmptyAnimation,
+}
+
+impl Animation {
+    /// Return the area covered by all the [`crate::AnimationFrame`]s, relative to the origin of the animation, as `(min_x, min_y, max_x, max_y)`, with the max being exclusive.
+    pub(crate) fn extent(
+        &self,
+        wan_image: &WanImage,
+    ) -> Result<Option<(i32, i32, i32, i32)>, FrameRenderError> {
+        let mut extent: Option<(i32, i32, i32, i32)> = None;
+        for animation_frame in &self.frames {
+            let frame = wan_image
+                .frame_store
+                .frames
+                .get(animation_frame.frame_id as usize)
+                .ok_or(FrameRenderError::NoFrame(animation_frame.frame_id as usize))?;
+            if let Some((min_x, min_y, max_x, max_y)) = frame.fragments_extent() {
+                let offset_x = animation_frame.offset_x as i32;
+                let offset_y = animation_frame.offset_y as i32;
+                let frame_extent = (
+                    min_x + offset_x,
+                    min_y + offset_y,
+                    max_x + offset_x,
+                    max_y + offset_y,
+                );
+                extent = Some(match extent {
+                    None => frame_extent,
+                    Some(extent) => (
+                        extent.0.min(frame_extent.0),
+                        extent.1.min(frame_extent.1),
+                        extent.2.max(frame_extent.2),
+                        extent.3.max(frame_extent.3),
+                    ),
+                });
+            }
+        }
+        Ok(extent)
+    }
+
+    /// Render every [`crate::AnimationFrame`], with its offset applied, returning each image with its duration.
+    ///
+    /// All the images have the same size, the smallest one that contains every frame of the animation, so they can be displayed one after the other.
+    pub fn render_frames(
+        &self,
+        wan_image: &WanImage,
+    ) -> Result<Vec<(RgbaImage, u8)>, FrameRenderError> {
+        let (min_x, min_y, max_x, max_y) = self.extent(wan_image)?.unwrap_or((0, 0, 1, 1));
+        let width = (max_x - min_x) as u32;
+        let height = (max_y - min_y) as u32;
+        let mut result = Vec::with_capacity(self.frames.len());
+        for animation_frame in &self.frames {
+            let mut image = RgbaImage::new(width, height);
+            // no panic: the frame existence is checked when computing the extent
+            wan_image.frame_store.frames[animation_frame.frame_id as usize]
+                .draw_on_image_with_depth(
+                    &wan_image.fragment_bytes_store,
+                    &wan_image.palette,
+                    wan_image.is_256_color,
+                    &mut image,
+                    animation_frame.offset_x as i32 - min_x,
+                    animation_frame.offset_y as i32 - min_y,
+                )?;
+            result.push((image, animation_frame.duration));
+        }
+        Ok(result)
+    }
+
+    /// Render this [`Animation`] as a looping animated GIF.
+    ///
+    /// GIF only store durations in hundredths of second, so they are rounded, and partially transparent pixels become either opaque or transparent.
+    pub fn render_to_gif<W: Write>(
+        &self,
+        wan_image: &WanImage,
+        writer: W,
+    ) -> Result<(), AnimationRenderError> {
+        self.render_to_gif_scaled(wan_image, writer, 1, UpscaleFilter::NearestNeighbor)
+    }
+
+    /// Like [`Animation::render_to_gif`], with the frames enlarged `factor` times by [`upscale_image`]
+    pub fn render_to_gif_scaled<W: Write>(
+        &self,
+        wan_image: &WanImage,
+        writer: W,
+        factor: u32,
+        filter: UpscaleFilter,
+    ) -> Result<(), AnimationRenderError> {
+        if self.frames.is_empty() {
+            return Err(AnimationRenderError::EmptyAnimation);
+        }
+        let mut encoder = GifEncoder::new(writer);
+        encoder.set_repeat(Repeat::Infinite)?;
+        for (image, duration) in self.render_frames(wan_image)? {
+            let delay =
+                Delay::from_numer_denom_ms(duration as u32 * 1000, ANIMATION_FRAMES_PER_SECOND);
+            let image = upscale_image(&image, factor, filter);
+            encoder.encode_frame(image::Frame::from_parts(image, 0, 0, delay))?;
+        }
+        Ok(())
+    }
+
+    /// Render this [`Animation`] as a looping APNG, with [`crate::image_tool::write_apng`]. Durations and transparency are preserved exactly.
+    pub fn render_to_apng<W: Write>(
+        &self,
+        wan_image: &WanImage,
+        writer: W,
+    ) -> Result<(), AnimationRenderError> {
+        if self.frames.is_empty() {
+            return Err(AnimationRenderError::EmptyAnimation);
+        }
+        write_apng(&self.render_frames(wan_image)?, writer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{codecs::gif::GifDecoder, AnimationDecoder, Rgba};
+    use std::io::Cursor;
+
+    use crate::{
+        image_tool::UpscaleFilter,
+        tests::fixtures::{animation_frame, insert_filled_frame, test_wan_image},
+        Animation, AnimationFrame, SpriteType,
+    };
+
+    #[test]
+    fn test_render_animation() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        insert_filled_frame(&mut wanimage, 2, 2);
+        let animation = Animation {
+            frames: vec![
+                animation_frame(0, 6),
+                AnimationFrame {
+                    offset_x: 2,
+                    ..animation_frame(0, 3)
+                },
+            ],
+        };
+
+        let frames = animation.render_frames(&wanimage).unwrap();
+        assert_eq!(frames.len(), 2);
+        // the 2×2 image is stored in a 8×8 fragment
+        assert_eq!(frames[0].0.dimensions(), (10, 8));
+        assert_eq!(frames[0].0.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frames[0].0.get_pixel(2, 0), &Rgba([0, 0, 0, 0]));
+        assert_eq!(frames[1].0.get_pixel(2, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frames[1].1, 3);
+
+        let mut gif = Vec::new();
+        animation.render_to_gif(&wanimage, &mut gif).unwrap();
+        let decoded = GifDecoder::new(Cursor::new(gif))
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].delay().numer_denom_ms(), (100, 1));
+
+        let mut gif = Vec::new();
+        animation
+            .render_to_gif_scaled(&wanimage, &mut gif, 3, UpscaleFilter::NearestNeighbor)
+            .unwrap();
+        let decoded = GifDecoder::new(Cursor::new(gif))
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(decoded[0].buffer().dimensions(), (30, 24));
+
+        let mut apng = Vec::new();
+        animation.render_to_apng(&wanimage, &mut apng).unwrap();
+        let mut reader = png::Decoder::new(Cursor::new(apng)).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut buffer).unwrap();
+        let frame_control = reader.info().frame_control().unwrap();
+        assert_eq!((frame_control.delay_num, frame_control.delay_den), (6, 60));
+        assert_eq!(reader.info().animation_control().unwrap().num_frames, 2);
+    }
+}
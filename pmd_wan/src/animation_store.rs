@@ -1,5 +1,9 @@
-use crate::{Animation, WanError};
+use crate::{
+    decode_options::check_limit, Animation, DecodeOptions, DecodeWarning, LocatedWanError,
+    WanError, WanSection,
+};
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::{Read, Seek, SeekFrom, Write};
 
 #[derive(Debug)]
@@ -9,9 +13,34 @@ struct AnimationGroupEntry {
     id: u16,
 }
 
+/// How much of the [`crate::Frame`]s used by an [`Animation`] are also used by other [`Animation`]s.
+/// Generated by [`AnimationStore::frame_reuse_statistics`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AnimationFrameReuse {
+    pub group_id: usize,
+    pub animation_id: usize,
+    /// The number of distinct frames only this animation use
+    pub unique_frames: usize,
+    /// The number of distinct frames this animation use that are also used by another animation
+    pub shared_frames: usize,
+}
+
+impl AnimationFrameReuse {
+    /// Proportion (from 0 to 1) of the distinct frames of this animation that are shared with other animations. 0 for animations without frames.
+    pub fn reuse_ratio(&self) -> f32 {
+        let total = self.unique_frames + self.shared_frames;
+        if total == 0 {
+            0.0
+        } else {
+            self.shared_frames as f32 / total as f32
+        }
+    }
+}
+
 /// Contain all the [`Animation`], as well as all the animation group (a.k.a animation table in ppmdu sprite editor).
 /// Animation group are a list of [`Animation`]. An animation group usually have 8 entry, one per rotation of the monster.
 #[derive(PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnimationStore {
     /// some stuff used to ensure perfect reproduçability. You should probably lease this to None
     pub copied_on_previous: Option<Vec<bool>>, //indicate if a sprite can copy on the previous. Will always copy if possible if None
@@ -24,13 +53,41 @@ impl AnimationStore {
         pointer_animation_groups_table: u64,
         amount_animation_group: u16,
     ) -> Result<(AnimationStore, u64), WanError> {
+        Self::new_with_options(
+            file,
+            pointer_animation_groups_table,
+            amount_animation_group,
+            &DecodeOptions::default(),
+            &mut Vec::new(),
+        )
+        .map_err(|error| error.error)
+    }
+
+    /// Like [`AnimationStore::new`], but in lenient mode, the [`Animation`]s that can't be read are replaced by empty ones
+    pub(crate) fn new_with_options<F: Read + Seek>(
+        file: &mut F,
+        pointer_animation_groups_table: u64,
+        amount_animation_group: u16,
+        options: &DecodeOptions,
+        warnings: &mut Vec<DecodeWarning>,
+    ) -> Result<(AnimationStore, u64), LocatedWanError> {
         //TODO: rewrite this function, it seem to be too complicated to understand
+        check_limit(
+            amount_animation_group as usize,
+            options.limits.max_animation_groups,
+            "animation groups",
+        )?;
         file.seek(SeekFrom::Start(pointer_animation_groups_table))?;
         let mut animation_group_entry: Vec<Option<AnimationGroupEntry>> = Vec::new();
         for animation_group_id in 0..amount_animation_group {
             let pointer = file.read_u32::<LE>()?;
             let length = file.read_u32::<LE>()?;
             if pointer != 0 && length != 0 {
+                check_limit(
+                    length as usize,
+                    options.limits.max_animations_per_group,
+                    "animations in a group",
+                )?;
                 animation_group_entry.push(Some(AnimationGroupEntry {
                     pointer,
                     group_lenght: length,
@@ -50,11 +107,11 @@ impl AnimationStore {
                     file.seek(SeekFrom::Start(animation_group.pointer as u64))?;
                     match particule_table_end {
                         Some(value) => {
-                            if file.seek(SeekFrom::Current(0))? < value {
-                                particule_table_end = Some(file.seek(SeekFrom::Current(0))?);
+                            if file.stream_position()? < value {
+                                particule_table_end = Some(file.stream_position()?);
                             }
                         }
-                        None => particule_table_end = Some(file.seek(SeekFrom::Current(0))?),
+                        None => particule_table_end = Some(file.stream_position()?),
                     };
 
                     let mut animation_ref = Vec::new();
@@ -72,7 +129,7 @@ impl AnimationStore {
         }
 
         let particule_table_end = match particule_table_end {
-            None => file.seek(SeekFrom::Current(0))?,
+            None => file.stream_position()?,
             Some(value) => value,
         };
 
@@ -81,17 +138,44 @@ impl AnimationStore {
         let mut anim_groups_result = Vec::new();
         let mut check_last_anim_pos = 0;
 
-        for animation_group in animation_groups {
+        for (group_id, animation_group) in animation_groups.into_iter().enumerate() {
             match animation_group {
                 None => anim_groups_result.push(Vec::new()),
                 Some(animation_group_table) => {
                     let mut animation_in_group = Vec::new();
-                    for animation in animation_group_table {
-                        file.seek(SeekFrom::Start(animation))?;
-                        copied_on_previous
-                            .push(file.seek(SeekFrom::Current(0))? == check_last_anim_pos);
-                        check_last_anim_pos = file.seek(SeekFrom::Current(0))?;
-                        animation_in_group.push(Animation::new(file)?);
+                    for (animation_id, animation) in animation_group_table.into_iter().enumerate() {
+                        copied_on_previous.push(animation == check_last_anim_pos);
+                        check_last_anim_pos = animation;
+                        let result = file
+                            .seek(SeekFrom::Start(animation))
+                            .map_err(WanError::from)
+                            .and_then(|_| {
+                                Animation::new_with_limit(
+                                    file,
+                                    options.limits.max_frames_per_animation,
+                                )
+                            });
+                        match result {
+                            Ok(animation) => animation_in_group.push(animation),
+                            Err(error) => {
+                                options
+                                    .recover(error, warnings, |error| {
+                                        DecodeWarning::InvalidAnimation {
+                                            group: group_id,
+                                            animation: animation_id,
+                                            error,
+                                        }
+                                    })
+                                    .map_err(|error| {
+                                        LocatedWanError::from(error).locate(
+                                            WanSection::Animations,
+                                            Some(group_id),
+                                            animation,
+                                        )
+                                    })?;
+                                animation_in_group.push(Animation::default());
+                            }
+                        }
                     }
                     anim_groups_result.push(animation_in_group)
                 }
@@ -119,7 +203,7 @@ impl AnimationStore {
                     None => true,
                     Some(value) => value.get(anim_counter).copied().unwrap_or(true),
                 };
-                let actual_pointer = file.seek(SeekFrom::Current(0))?;
+                let actual_pointer = file.stream_position()?;
 
                 if can_copy_on_previous {
                     if let Some(p_anim) = previous_animation {
@@ -171,22 +255,22 @@ impl AnimationStore {
             } else {
                 good_anim_group_meet = true;
                 anim_group_data.push(AnimGroupData {
-                    pointer: file.seek(SeekFrom::Current(0))? as u32,
+                    pointer: file.stream_position()? as u32,
                     lenght: anim_group.len() as u32,
                 });
                 for _ in anim_group {
-                    sir0_animation.push(file.seek(SeekFrom::Current(0))?);
+                    sir0_animation.push(file.stream_position()?);
                     file.write_u32::<LE>(animations_pointer[anim_counter] as u32)?;
                     anim_counter += 1;
                 }
             }
         }
 
-        let animation_group_reference_offset = file.seek(SeekFrom::Current(0))?;
+        let animation_group_reference_offset = file.stream_position()?;
 
         for data in anim_group_data {
             if data.pointer != 0 && data.lenght != 0 {
-                sir0_animation.push(file.seek(SeekFrom::Current(0))?);
+                sir0_animation.push(file.stream_position()?);
             }
             file.write_u32::<LE>(data.pointer)?;
             file.write_u32::<LE>(data.lenght)?;
@@ -194,4 +278,92 @@ impl AnimationStore {
 
         Ok((animation_group_reference_offset, sir0_animation))
     }
+
+    /// Multiply the duration of every [`crate::AnimationFrame`] of every [`Animation`] by the factor (see [`Animation::scale_durations`])
+    pub fn scale_durations(&mut self, factor: f32) {
+        for animation in self.anim_groups.iter_mut().flatten() {
+            animation.scale_durations(factor);
+        }
+    }
+
+    /// Compute, for each [`Animation`] (in group order), how many frames it shares with other animations.
+    /// Modifying or adding art to an animation with a low reuse ratio will grow the file the most.
+    pub fn frame_reuse_statistics(&self) -> Vec<AnimationFrameReuse> {
+        let mut users_of_frame: BTreeMap<u16, usize> = BTreeMap::new();
+        let mut frames_of_animations = Vec::new();
+        for (group_id, group) in self.anim_groups.iter().enumerate() {
+            for (animation_id, animation) in group.iter().enumerate() {
+                let frames: BTreeSet<u16> = animation
+                    .frames
+                    .iter()
+                    .map(|frame| frame.frame_id)
+                    .collect();
+                for frame_id in &frames {
+                    *users_of_frame.entry(*frame_id).or_default() += 1;
+                }
+                frames_of_animations.push((group_id, animation_id, frames));
+            }
+        }
+
+        frames_of_animations
+            .into_iter()
+            .map(|(group_id, animation_id, frames)| {
+                let shared_frames = frames
+                    .iter()
+                    .filter(|frame_id| users_of_frame[frame_id] > 1)
+                    .count();
+                AnimationFrameReuse {
+                    group_id,
+                    animation_id,
+                    unique_frames: frames.len() - shared_frames,
+                    shared_frames,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::fixtures::animation_frame, Animation, AnimationFrameReuse, AnimationStore};
+
+    fn animation_with_frames(frame_ids: &[u16]) -> Animation {
+        Animation {
+            frames: frame_ids
+                .iter()
+                .map(|frame_id| animation_frame(*frame_id, 1))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_frame_reuse_statistics() {
+        let store = AnimationStore {
+            copied_on_previous: None,
+            anim_groups: vec![
+                vec![animation_with_frames(&[0, 1, 1, 2])],
+                vec![],
+                vec![animation_with_frames(&[2, 3])],
+            ],
+        };
+        let statistics = store.frame_reuse_statistics();
+        assert_eq!(
+            statistics,
+            vec![
+                AnimationFrameReuse {
+                    group_id: 0,
+                    animation_id: 0,
+                    unique_frames: 2,
+                    shared_frames: 1,
+                },
+                AnimationFrameReuse {
+                    group_id: 2,
+                    animation_id: 0,
+                    unique_frames: 1,
+                    shared_frames: 1,
+                }
+            ]
+        );
+        assert_eq!(statistics[1].reuse_ratio(), 0.5);
+    }
 }
@@ -0,0 +1,263 @@
+use std::io::{self, Write};
+
+use byteorder::{WriteBytesExt, LE};
+use thiserror::Error;
+
+use crate::frame_render::palette_color_to_rgba;
+use crate::{
+    spritebot_animation_name, AnimationFrame, FragmentBytesToImageError, FrameRenderError,
+    WanImage, ANIMATION_FRAMES_PER_SECOND,
+};
+
+const ASEPRITE_MAGIC: u16 = 0xA5E0;
+const ASEPRITE_FRAME_MAGIC: u16 = 0xF1FA;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+
+#[derive(Error, Debug)]
+pub enum AsepriteError {
+    #[error("an input/output error happened")]
+    IOError(#[from] io::Error),
+    #[error("failed to render a frame")]
+    FrameRenderError(#[from] FrameRenderError),
+    #[error("the color {0} of the palette {1} can't be stored in an indexed Aseprite file, that only have 256 colors")]
+    ColorOutOfRange(u8, u16),
+    #[error("there are too many frames or animations to be stored in an Aseprite file")]
+    TooManyFrames,
+}
+
+impl From<FragmentBytesToImageError> for AsepriteError {
+    fn from(err: FragmentBytesToImageError) -> Self {
+        Self::FrameRenderError(FrameRenderError::CantRenderFragment(err))
+    }
+}
+
+fn write_string(buffer: &mut Vec<u8>, text: &str) -> io::Result<()> {
+    buffer.write_u16::<LE>(text.len() as u16)?;
+    buffer.write_all(text.as_bytes())
+}
+
+fn write_chunk(frame: &mut Vec<u8>, chunk_type: u16, data: &[u8]) -> io::Result<()> {
+    frame.write_u32::<LE>(data.len() as u32 + 6)?;
+    frame.write_u16::<LE>(chunk_type)?;
+    frame.write_all(data)
+}
+
+impl WanImage {
+    /// Write all the [`crate::Animation`]s in an indexed Aseprite file.
+    ///
+    /// Every [`crate::Animation`] become a tag (named after its group, with the index of the animation in the group), whose frames follow each others.
+    /// Each [`crate::Fragment`] is a cel on its own layer, with the first fragment on the top layer, so the original cutting is kept.
+    /// The [`crate::Palette`] is stored as is, with the color 0 as the transparent one, and the colors of a fragment are stored as `pal_idx * 16 + color` (or directly as the color for 256 colors sprites).
+    pub fn export_aseprite<W: Write>(&self, mut writer: W) -> Result<(), AsepriteError> {
+        let mut animation_frames: Vec<&AnimationFrame> = Vec::new();
+        let mut tags: Vec<(u16, u16, String)> = Vec::new();
+        for (group_id, group) in self.animation_store.anim_groups.iter().enumerate() {
+            for (animation_id, animation) in group.iter().enumerate() {
+                if animation.frames.is_empty() {
+                    continue;
+                }
+                let from = animation_frames.len();
+                animation_frames.extend(animation.frames.iter());
+                if animation_frames.len() > u16::MAX as usize {
+                    return Err(AsepriteError::TooManyFrames);
+                }
+                tags.push((
+                    from as u16,
+                    (animation_frames.len() - 1) as u16,
+                    format!("{}_{}", spritebot_animation_name(group_id), animation_id),
+                ));
+            }
+        }
+
+        // the canvas is centered on the origin of the animations
+        let mut half_width = 1;
+        let mut half_height = 1;
+        let mut layer_amount = 1;
+        for animation_frame in &animation_frames {
+            let frame = self
+                .frame_store
+                .frames
+                .get(animation_frame.frame_id as usize)
+                .ok_or(FrameRenderError::NoFrame(animation_frame.frame_id as usize))?;
+            layer_amount = layer_amount.max(frame.fragments.len());
+            if let Some((min_x, min_y, max_x, max_y)) = frame.fragments_extent() {
+                for x in [min_x, max_x] {
+                    half_width = half_width.max((x + animation_frame.offset_x as i32).abs());
+                }
+                for y in [min_y, max_y] {
+                    half_height = half_height.max((y + animation_frame.offset_y as i32).abs());
+                }
+            }
+        }
+        if layer_amount > u16::MAX as usize || tags.len() > u16::MAX as usize {
+            return Err(AsepriteError::TooManyFrames);
+        }
+
+        let mut frames_data: Vec<Vec<u8>> = Vec::new();
+        // Aseprite need at least one frame
+        let frame_amount = animation_frames.len().max(1);
+        for frame_nb in 0..frame_amount {
+            let mut chunks = Vec::new();
+            let mut chunk_amount = 0;
+            if frame_nb == 0 {
+                for layer_nb in 0..layer_amount {
+                    let mut layer = Vec::new();
+                    layer.write_u16::<LE>(3)?; // visible and editable
+                    layer.write_u16::<LE>(0)?; // normal layer
+                    layer.write_u16::<LE>(0)?; // child level
+                    layer.write_all(&[0; 6])?; // default size and blend mode
+                    layer.write_u8(255)?; // opacity
+                    layer.write_all(&[0; 3])?;
+                    write_string(
+                        &mut layer,
+                        &format!("Fragment {}", layer_amount - 1 - layer_nb),
+                    )?;
+                    write_chunk(&mut chunks, CHUNK_LAYER, &layer)?;
+                    chunk_amount += 1;
+                }
+
+                let mut palette = Vec::new();
+                palette.write_u32::<LE>(self.palette.palette.len() as u32)?;
+                palette.write_u32::<LE>(0)?;
+                palette.write_u32::<LE>(self.palette.palette.len().saturating_sub(1) as u32)?;
+                palette.write_all(&[0; 8])?;
+                for color in &self.palette.palette {
+                    palette.write_u16::<LE>(0)?;
+                    palette.write_all(&palette_color_to_rgba(*color).0)?;
+                }
+                write_chunk(&mut chunks, CHUNK_PALETTE, &palette)?;
+                chunk_amount += 1;
+
+                let mut tags_chunk = Vec::new();
+                tags_chunk.write_u16::<LE>(tags.len() as u16)?;
+                tags_chunk.write_all(&[0; 8])?;
+                for (from, to, name) in &tags {
+                    tags_chunk.write_u16::<LE>(*from)?;
+                    tags_chunk.write_u16::<LE>(*to)?;
+                    tags_chunk.write_u8(0)?; // forward
+                    tags_chunk.write_u16::<LE>(0)?; // repeat forever
+                    tags_chunk.write_all(&[0; 6])?;
+                    tags_chunk.write_all(&[0, 0, 0, 0])?; // deprecated color, and an extra byte
+                    write_string(&mut tags_chunk, name)?;
+                }
+                write_chunk(&mut chunks, CHUNK_TAGS, &tags_chunk)?;
+                chunk_amount += 1;
+            }
+
+            let mut duration_ms = 100;
+            if let Some(animation_frame) = animation_frames.get(frame_nb) {
+                duration_ms = (animation_frame.duration as u32 * 1000
+                    + ANIMATION_FRAMES_PER_SECOND / 2)
+                    / ANIMATION_FRAMES_PER_SECOND;
+                // no panic: frames existence was checked when computing the canvas size
+                let frame = &self.frame_store.frames[animation_frame.frame_id as usize];
+                for (fragment_nb, fragment) in frame.fragments.iter().enumerate() {
+                    let pixels = fragment.get_flipped_pixels_with_depth(
+                        &self.fragment_bytes_store,
+                        self.is_256_color,
+                    )?;
+                    let resolution = fragment.resolution.size();
+                    let mut cel = Vec::new();
+                    cel.write_u16::<LE>((layer_amount - 1 - fragment_nb) as u16)?;
+                    cel.write_i16::<LE>(
+                        (half_width + animation_frame.offset_x as i32 + fragment.offset_x as i32)
+                            as i16,
+                    )?;
+                    cel.write_i16::<LE>(
+                        (half_height + animation_frame.offset_y as i32 + fragment.offset_y as i32)
+                            as i16,
+                    )?;
+                    cel.write_u8(255)?; // opacity
+                    cel.write_u16::<LE>(0)?; // raw cel
+                    cel.write_i16::<LE>(0)?; // z-index
+                    cel.write_all(&[0; 5])?;
+                    cel.write_u16::<LE>(resolution.x as u16)?;
+                    cel.write_u16::<LE>(resolution.y as u16)?;
+                    for pixel in pixels {
+                        let index = if pixel == 0 || self.is_256_color {
+                            pixel as usize
+                        } else {
+                            fragment.pal_idx as usize * 16 + pixel as usize
+                        };
+                        if index > u8::MAX as usize {
+                            return Err(AsepriteError::ColorOutOfRange(pixel, fragment.pal_idx));
+                        }
+                        cel.write_u8(index as u8)?;
+                    }
+                    write_chunk(&mut chunks, CHUNK_CEL, &cel)?;
+                    chunk_amount += 1;
+                }
+            }
+
+            let mut frame_data = Vec::new();
+            frame_data.write_u32::<LE>(chunks.len() as u32 + 16)?;
+            frame_data.write_u16::<LE>(ASEPRITE_FRAME_MAGIC)?;
+            frame_data.write_u16::<LE>(chunk_amount.min(0xFFFF) as u16)?;
+            frame_data.write_u16::<LE>(duration_ms.min(u16::MAX as u32) as u16)?;
+            frame_data.write_all(&[0; 2])?;
+            frame_data.write_u32::<LE>(chunk_amount as u32)?;
+            frame_data.extend(chunks);
+            frames_data.push(frame_data);
+        }
+
+        let file_size = 128 + frames_data.iter().map(|frame| frame.len()).sum::<usize>();
+        let mut header = Vec::new();
+        header.write_u32::<LE>(file_size as u32)?;
+        header.write_u16::<LE>(ASEPRITE_MAGIC)?;
+        header.write_u16::<LE>(frame_amount as u16)?;
+        header.write_u16::<LE>((half_width * 2) as u16)?;
+        header.write_u16::<LE>((half_height * 2) as u16)?;
+        header.write_u16::<LE>(8)?; // indexed
+        header.write_u32::<LE>(1)?; // layer opacity is valid
+        header.write_u16::<LE>(100)?; // deprecated speed
+        header.write_all(&[0; 8])?;
+        header.write_u8(0)?; // transparent index
+        header.write_all(&[0; 3])?;
+        header.write_u16::<LE>(self.palette.palette.len().min(256) as u16 % 256)?;
+        header.write_u8(1)?; // pixel width
+        header.write_u8(1)?; // pixel height
+        header.write_all(&[0; 8])?; // grid
+        header.write_all(&[0; 84])?;
+        writer.write_all(&header)?;
+        for frame in frames_data {
+            writer.write_all(&frame)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use crate::{
+        tests::fixtures::{animation_frame, insert_filled_frame, test_wan_image},
+        Animation, SpriteType,
+    };
+
+    #[test]
+    fn test_export_aseprite() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        insert_filled_frame(&mut wanimage, 2, 2);
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![animation_frame(0, 6), animation_frame(0, 6)],
+        }]);
+
+        let mut file = Vec::new();
+        wanimage.export_aseprite(&mut file).unwrap();
+        let read_u16 =
+            |position: usize| u16::from_le_bytes(file[position..position + 2].try_into().unwrap());
+        assert_eq!(
+            u32::from_le_bytes(file[0..4].try_into().unwrap()) as usize,
+            file.len()
+        );
+        assert_eq!(read_u16(4), 0xA5E0);
+        assert_eq!(read_u16(6), 2);
+        // the first frame header
+        assert_eq!(read_u16(128 + 4), 0xF1FA);
+        assert_eq!(read_u16(128 + 8), 100);
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use anyhow::Context;
+use thiserror::Error;
+
+use crate::{PackFile, WanImage};
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+#[derive(Error, Debug)]
+pub enum BatchError {
+    #[error("can't list the content of the folder {0:?}")]
+    CantListFolder(PathBuf, #[source] io::Error),
+}
+
+/// A file (or pack file entry) that couldn't be processed, in a [`BatchReport`]
+#[derive(Error, Debug)]
+#[error("{item}: {error:#}")]
+pub struct BatchFailure {
+    /// The path of the file relative to the input folder, or `entry <n>` for a pack file entry
+    pub item: String,
+    pub error: anyhow::Error,
+}
+
+/// The output of [`process_folder`] and [`process_pack_file`]
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// The items (named like [`BatchFailure::item`]) that were processed and written, in order
+    pub succeeded: Vec<String>,
+    /// The items that failed, in order. The others were still processed.
+    pub failed: Vec<BatchFailure>,
+}
+
+impl BatchReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    fn push(&mut self, item: String, result: anyhow::Result<()>) {
+        match result {
+            Ok(()) => self.succeeded.push(item),
+            Err(error) => self.failed.push(BatchFailure { item, error }),
+        }
+    }
+}
+
+/// A summary with the number of succeeded and failed items, then one line per failure
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} succeeded, {} failed",
+            self.succeeded.len(),
+            self.failed.len()
+        )?;
+        for failure in &self.failed {
+            writeln!(f, "failed {}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+/// Apply `function` on each item, in parallel with the `rayon` feature, keeping the order of the results
+pub(crate) fn map_items<I: Sync, T: Send>(
+    items: &[I],
+    function: impl Fn(&I) -> T + Sync + Send,
+) -> Vec<T> {
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        items.par_iter().map(function).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    items.iter().map(function).collect()
+}
+
+/// Recursively list the `.wan` files of `folder`, as paths relative to `root`, sorted
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) fn list_wan_files(
+    root: &Path,
+    folder: &Path,
+    result: &mut Vec<PathBuf>,
+) -> Result<(), BatchError> {
+    let list_error = |error| BatchError::CantListFolder(folder.to_path_buf(), error);
+    let mut entries = fs::read_dir(folder)
+        .map_err(list_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(list_error)?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            list_wan_files(root, &path, result)?;
+        } else if path
+            .extension()
+            .map(|extension| extension.eq_ignore_ascii_case("wan"))
+            .unwrap_or(false)
+        {
+            // no panic: the path is inside of root
+            result.push(path.strip_prefix(root).unwrap().to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Decode every `.wan` file in `input` and its subfolders, apply `transform` on it, and write it at the same relative path in `output` (that may be the same folder).
+///
+/// `transform` receive the path relative to `input`. With the `rayon` feature, the files are processed in parallel.
+/// A file that fail to be read, transformed or written is reported in [`BatchReport::failed`], without stopping the others. Only an error while listing the files is returned directly.
+/// Not available on `wasm32-unknown-unknown`, that doesn't have a file system.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn process_folder<T>(
+    input: &Path,
+    output: &Path,
+    transform: T,
+) -> Result<BatchReport, BatchError>
+where
+    T: Fn(&mut WanImage, &Path) -> anyhow::Result<()> + Sync + Send,
+{
+    let mut files = Vec::new();
+    list_wan_files(input, input, &mut files)?;
+    let results = map_items(&files, |relative_path| -> anyhow::Result<()> {
+        let source = input.join(relative_path);
+        let bytes = fs::read(&source).with_context(|| format!("can't read {:?}", source))?;
+        let mut wanimage = WanImage::decode_wan_from_bytes(&bytes)?;
+        transform(&mut wanimage, relative_path)?;
+        let encoded = wanimage.write_to_vec()?;
+        let destination = output.join(relative_path);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("can't create the folder {:?}", parent))?;
+        }
+        fs::write(&destination, encoded)
+            .with_context(|| format!("can't write {:?}", destination))?;
+        Ok(())
+    });
+    let mut report = BatchReport::default();
+    for (relative_path, result) in files.iter().zip(results) {
+        report.push(relative_path.display().to_string(), result);
+    }
+    Ok(report)
+}
+
+/// Decode every non-empty entry of the [`PackFile`] (see [`PackFile::extract_wan`]), apply `transform` on it with its entry number, and store it back (see [`PackFile::replace_wan`]).
+///
+/// With the `rayon` feature, the entries are processed in parallel. An entry that fail to be decoded, transformed or encoded is left unchanged and reported in [`BatchReport::failed`].
+pub fn process_pack_file<T>(pack: &mut PackFile, transform: T) -> BatchReport
+where
+    T: Fn(&mut WanImage, usize) -> anyhow::Result<()> + Sync + Send,
+{
+    let entries: Vec<usize> = (0..pack.len())
+        .filter(|entry_nb| !pack.entries[*entry_nb].is_empty())
+        .collect();
+    let shared_pack: &PackFile = pack;
+    let results = map_items(&entries, |entry_nb| -> anyhow::Result<Vec<u8>> {
+        let mut wanimage = shared_pack.extract_wan(*entry_nb)?;
+        transform(&mut wanimage, *entry_nb)?;
+        shared_pack.encode_entry(*entry_nb, &wanimage)
+    });
+    let mut report = BatchReport::default();
+    for (entry_nb, result) in entries.into_iter().zip(results) {
+        let result = result.map(|encoded| pack.entries[entry_nb] = encoded);
+        report.push(format!("entry {}", entry_nb), result);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        process_folder, process_pack_file,
+        tests::fixtures::{single_frame_wan_bytes, TempDir},
+        CompressionMethod, PackFile,
+    };
+
+    #[test]
+    fn test_process_pack_file() {
+        let mut pack = PackFile {
+            entries: vec![single_frame_wan_bytes(), Vec::new(), vec![1, 2, 3]],
+            ..Default::default()
+        };
+        let report = process_pack_file(&mut pack, |wanimage, _| {
+            wanimage.palette.palette[1] = [0, 0, 255, 128];
+            Ok(())
+        });
+        assert_eq!(report.succeeded, vec!["entry 0".to_string()]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].item, "entry 2");
+        assert!(report
+            .to_string()
+            .starts_with("1 succeeded, 1 failed\nfailed entry 2: "));
+        assert_eq!(
+            pack.extract_wan(0).unwrap().palette.palette[1],
+            [0, 0, 255, 128]
+        );
+        assert_eq!(pack.entries[2], vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_process_folder() {
+        let input = TempDir::new("batch_input");
+        let output = TempDir::new("batch_output");
+        std::fs::create_dir_all(input.join("sub")).unwrap();
+        std::fs::write(input.join("a.wan"), single_frame_wan_bytes()).unwrap();
+        std::fs::write(input.join("sub").join("b.WAN"), single_frame_wan_bytes()).unwrap();
+        std::fs::write(input.join("sub").join("broken.wan"), [0; 4]).unwrap();
+        std::fs::write(input.join("other.txt"), "not a sprite").unwrap();
+
+        let report = process_folder(&input, &output, |wanimage, _| {
+            wanimage.compression = CompressionMethod::NoCompression;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(report.succeeded.len(), 2);
+        assert_eq!(report.failed.len(), 1);
+        assert!(!report.is_success());
+        assert!(output.join("a.wan").exists());
+        assert!(output.join("sub").join("b.WAN").exists());
+        assert!(!output.join("other.txt").exists());
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand};
+use pmd_wan::{
+    create_wan_from_multiple_images_with_animations, dump_wan,
+    image_tool::{image_to_paletted_bytes, upscale_image, ImageToPaletteBytesData, UpscaleFilter},
+    GeneralResolution, MultiImagesAnimation, SpriteType, WanImage,
+};
+use serde::Deserialize;
+
+#[derive(Parser, Debug)]
+#[clap(about = "Inspect, extract and build the wan sprites of Pokémon Mystery Dungeon")]
+struct Opts {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the structure of a wan file: its palette, frames, fragments and animations
+    Info { input: PathBuf },
+    /// Print a tree-like description of a wan file, with the offset of its tables, frames and animations
+    Dump { input: PathBuf },
+    /// Export a wan file as SpriteBot sheets, or as one PNG per frame with --frames
+    Extract {
+        input: PathBuf,
+        output: PathBuf,
+        #[clap(long)]
+        frames: bool,
+        /// Enlarge the PNGs of --frames this many times, with nearest neighbor scaling
+        #[clap(long, default_value = "1")]
+        scale: u32,
+    },
+    /// Create a wan file from the images and the manifest.json of a folder
+    Build { folder: PathBuf, output: PathBuf },
+    /// Render every animation of a wan file as GIFs, named group_<group>_animation_<animation>.gif
+    Preview {
+        input: PathBuf,
+        output: PathBuf,
+        /// Enlarge the GIFs this many times, with nearest neighbor scaling
+        #[clap(long, default_value = "1")]
+        scale: u32,
+    },
+}
+
+/// The manifest.json of a folder given to the `build` subcommand
+#[derive(Deserialize, Debug)]
+struct Manifest {
+    /// `"chara"`, `"props_ui"` or `"effect"`
+    sprite_type: String,
+    /// The path of the PNG images, relative to the folder. Each one become a frame, using at most 16 colors (including the transparent one) between all of them.
+    images: Vec<PathBuf>,
+    /// The animation groups, each containing 0, 1 or 8 animations
+    #[serde(default)]
+    groups: Vec<Vec<ManifestAnimation>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ManifestAnimation {
+    /// The index of the image in [`Manifest::images`] and the duration (in 1/60th of second) of each frame
+    frames: Vec<(usize, u8)>,
+    #[serde(default)]
+    return_point: Option<usize>,
+}
+
+fn read_wan(path: &Path) -> anyhow::Result<WanImage> {
+    let bytes = fs::read(path).with_context(|| format!("can't read {:?}", path))?;
+    WanImage::decode_wan_from_bytes(&bytes).with_context(|| format!("can't decode {:?}", path))
+}
+
+fn info(input: &Path) -> anyhow::Result<()> {
+    let wanimage = read_wan(input)?;
+    println!("sprite type: {:?}", wanimage.sprite_type);
+    println!(
+        "palette: {} colors in {} rows{}",
+        wanimage.palette.palette.len(),
+        wanimage.palette_row_count(),
+        if wanimage.is_256_color {
+            ", 256 colors mode"
+        } else {
+            ""
+        }
+    );
+    println!(
+        "fragment bytes: {}",
+        wanimage.fragment_bytes_store.fragment_bytes.len()
+    );
+    println!("frames: {}", wanimage.frame_store.frames.len());
+    for (frame_id, frame) in wanimage.frame_store.frames.iter().enumerate() {
+        println!("  frame {}: {} fragments", frame_id, frame.fragments.len());
+    }
+    println!(
+        "animation groups: {}",
+        wanimage.animation_store.anim_groups.len()
+    );
+    for (group_id, group) in wanimage.animation_store.anim_groups.iter().enumerate() {
+        let lengths: Vec<String> = group
+            .iter()
+            .map(|animation| animation.frames.len().to_string())
+            .collect();
+        println!(
+            "  group {}: {} animations ({} frames)",
+            group_id,
+            group.len(),
+            lengths.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn dump(input: &Path) -> anyhow::Result<()> {
+    let file = File::open(input).with_context(|| format!("can't open {:?}", input))?;
+    dump_wan(BufReader::new(file), &mut io::stdout().lock())
+        .with_context(|| format!("can't dump {:?}", input))?;
+    Ok(())
+}
+
+fn extract(input: &Path, output: &Path, frames: bool, scale: u32) -> anyhow::Result<()> {
+    let wanimage = read_wan(input)?;
+    fs::create_dir_all(output)?;
+    if frames {
+        for frame_id in 0..wanimage.frame_store.frames.len() {
+            upscale_image(
+                &wanimage.render_frame(frame_id)?,
+                scale,
+                UpscaleFilter::NearestNeighbor,
+            )
+            .save(output.join(format!("frame_{}.png", frame_id)))?;
+        }
+    } else {
+        wanimage.export_spritebot()?.write_to_folder(output)?;
+    }
+    Ok(())
+}
+
+fn build(folder: &Path, output: &Path) -> anyhow::Result<()> {
+    let manifest_path = folder.join("manifest.json");
+    let manifest: Manifest = serde_json::from_reader(
+        File::open(&manifest_path).with_context(|| format!("can't open {:?}", manifest_path))?,
+    )
+    .with_context(|| format!("can't parse {:?}", manifest_path))?;
+    let sprite_type = match manifest.sprite_type.as_str() {
+        "chara" => SpriteType::Chara,
+        "props_ui" => SpriteType::PropsUI,
+        "effect" => SpriteType::Effect,
+        other => bail!(
+            "unknown sprite type {:?}, expected \"chara\", \"props_ui\" or \"effect\"",
+            other
+        ),
+    };
+
+    let mut palette_data = ImageToPaletteBytesData::default();
+    let mut images = Vec::with_capacity(manifest.images.len());
+    for image_path in &manifest.images {
+        let image = image::open(folder.join(image_path))
+            .with_context(|| format!("can't open {:?}", image_path))?
+            .into_rgba8();
+        let pixels = image_to_paletted_bytes(&mut palette_data, &image)
+            .filter(|_| palette_data.ordered.len() <= 16)
+            .with_context(|| {
+                format!(
+                    "the images use more than 15 colors, the last one being {:?}",
+                    image_path
+                )
+            })?;
+        images.push((
+            pixels,
+            GeneralResolution::new(image.width(), image.height()),
+        ));
+    }
+    let groups: Vec<Vec<MultiImagesAnimation>> = manifest
+        .groups
+        .into_iter()
+        .map(|group| {
+            group
+                .into_iter()
+                .map(|animation| MultiImagesAnimation {
+                    frames: animation.frames,
+                    return_point: animation.return_point,
+                })
+                .collect()
+        })
+        .collect();
+    let images: Vec<_> = images
+        .iter()
+        .map(|(pixels, resolution)| (pixels.as_slice(), resolution.clone()))
+        .collect();
+    let mut wanimage =
+        create_wan_from_multiple_images_with_animations(&images, sprite_type, &groups)?;
+    palette_data.ordered.resize(16, [0, 0, 0, 0]);
+    wanimage.palette.palette = palette_data
+        .ordered
+        .iter()
+        .map(|color| [color[0], color[1], color[2], color[3] / 2 + color[3] % 2])
+        .collect();
+    fs::write(output, wanimage.write_to_vec()?)
+        .with_context(|| format!("can't write {:?}", output))?;
+    Ok(())
+}
+
+fn preview(input: &Path, output: &Path, scale: u32) -> anyhow::Result<()> {
+    let wanimage = read_wan(input)?;
+    fs::create_dir_all(output)?;
+    for (group_id, group) in wanimage.animation_store.anim_groups.iter().enumerate() {
+        for (animation_id, animation) in group.iter().enumerate() {
+            if animation.frames.is_empty() {
+                continue;
+            }
+            let path = output.join(format!("group_{}_animation_{}.gif", group_id, animation_id));
+            animation
+                .render_to_gif_scaled(
+                    &wanimage,
+                    BufWriter::new(File::create(&path)?),
+                    scale,
+                    UpscaleFilter::NearestNeighbor,
+                )
+                .with_context(|| format!("can't render {:?}", path))?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let opts = Opts::parse();
+    match opts.command {
+        Command::Info { input } => info(&input),
+        Command::Dump { input } => dump(&input),
+        Command::Extract {
+            input,
+            output,
+            frames,
+            scale,
+        } => extract(&input, &output, frames, scale),
+        Command::Build { folder, output } => build(&folder, &output),
+        Command::Preview {
+            input,
+            output,
+            scale,
+        } => preview(&input, &output, scale),
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{
+    Animation, FragmentBytesStore, FragmentBytesToImageError, Frame, FrameMask, FrameRenderError,
+    WanImage,
+};
+
+/// A rectangle, relative to the origin of a [`Frame`] or an [`Animation`], with the max being exclusive
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct BoundingBox {
+    pub min_x: i32,
+    pub min_y: i32,
+    pub max_x: i32,
+    pub max_y: i32,
+}
+
+impl BoundingBox {
+    pub fn width(&self) -> u32 {
+        (self.max_x - self.min_x) as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        (self.max_y - self.min_y) as u32
+    }
+
+    /// The smallest rectangle containing both
+    pub fn union(self, other: BoundingBox) -> BoundingBox {
+        BoundingBox {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+
+    pub fn translate(self, x: i32, y: i32) -> BoundingBox {
+        BoundingBox {
+            min_x: self.min_x + x,
+            min_y: self.min_y + y,
+            max_x: self.max_x + x,
+            max_y: self.max_y + y,
+        }
+    }
+
+    /// The smallest rectangle containing the pixels of a `width`×`height` area for which `is_opaque` return true, relative to its top-left pixel.
+    /// Return [`None`] if there is no such pixel.
+    pub fn of_opaque_pixels(
+        width: u32,
+        height: u32,
+        is_opaque: impl Fn(u32, u32) -> bool,
+    ) -> Option<BoundingBox> {
+        let mut result: Option<BoundingBox> = None;
+        for y in 0..height {
+            for x in 0..width {
+                if !is_opaque(x, y) {
+                    continue;
+                }
+                let pixel = BoundingBox {
+                    min_x: x as i32,
+                    min_y: y as i32,
+                    max_x: x as i32 + 1,
+                    max_y: y as i32 + 1,
+                };
+                result = Some(match result {
+                    Some(result) => result.union(pixel),
+                    None => pixel,
+                });
+            }
+        }
+        result
+    }
+
+    /// The size of the smallest canvas containing this rectangle with the origin at its center (at `(width / 2, height / 2)`), as `(width, height)`.
+    ///
+    /// The sizes are even, so the center is on a pixel.
+    pub fn centered_canvas_size(&self) -> (u32, u32) {
+        let half_width = self.min_x.unsigned_abs().max(self.max_x.unsigned_abs());
+        let half_height = self.min_y.unsigned_abs().max(self.max_y.unsigned_abs());
+        (half_width * 2, half_height * 2)
+    }
+
+    fn union_option(this: Option<BoundingBox>, other: Option<BoundingBox>) -> Option<BoundingBox> {
+        match (this, other) {
+            (Some(this), Some(other)) => Some(this.union(other)),
+            (this, other) => this.or(other),
+        }
+    }
+}
+
+impl Frame {
+    /// Return the smallest rectangle containing the opaque pixels of this frame once rendered, relative to its origin.
+    /// Return [`None`] if the frame is fully transparent.
+    ///
+    /// Unlike the image returned by [`Frame::render`], that contains all the [`crate::Fragment`]s, this exclude their transparent borders.
+    pub fn bounding_box(
+        &self,
+        fragment_bytes_store: &FragmentBytesStore,
+        is_256_color: bool,
+    ) -> Result<Option<BoundingBox>, FragmentBytesToImageError> {
+        Ok(
+            FrameMask::new_from_frame_with_depth(self, fragment_bytes_store, is_256_color)?
+                .opaque_bounds(),
+        )
+    }
+}
+
+impl Animation {
+    /// Return the smallest rectangle containing the opaque pixels of every [`crate::AnimationFrame`], with their offsets applied, relative to the origin of the animation.
+    /// Return [`None`] if every frame is fully transparent.
+    pub fn bounding_box(
+        &self,
+        wan_image: &WanImage,
+    ) -> Result<Option<BoundingBox>, FrameRenderError> {
+        let frame_boxes = wan_image.frame_bounding_boxes()?;
+        self.bounding_box_with(&frame_boxes)
+    }
+
+    fn bounding_box_with(
+        &self,
+        frame_boxes: &[Option<BoundingBox>],
+    ) -> Result<Option<BoundingBox>, FrameRenderError> {
+        let mut result = None;
+        for animation_frame in &self.frames {
+            let frame_box = frame_boxes
+                .get(animation_frame.frame_id as usize)
+                .ok_or(FrameRenderError::NoFrame(animation_frame.frame_id as usize))?
+                .map(|frame_box| {
+                    frame_box.translate(
+                        animation_frame.offset_x as i32,
+                        animation_frame.offset_y as i32,
+                    )
+                });
+            result = BoundingBox::union_option(result, frame_box);
+        }
+        Ok(result)
+    }
+}
+
+impl WanImage {
+    /// The [`Frame::bounding_box`] of the frame with the given id
+    pub fn frame_bounding_box(
+        &self,
+        frame_id: usize,
+    ) -> Result<Option<BoundingBox>, FrameRenderError> {
+        Ok(self
+            .frame_store
+            .frames
+            .get(frame_id)
+            .ok_or(FrameRenderError::NoFrame(frame_id))?
+            .bounding_box(&self.fragment_bytes_store, self.is_256_color)?)
+    }
+
+    fn frame_bounding_boxes(&self) -> Result<Vec<Option<BoundingBox>>, FrameRenderError> {
+        self.frame_store
+            .frames
+            .iter()
+            .map(|frame| Ok(frame.bounding_box(&self.fragment_bytes_store, self.is_256_color)?))
+            .collect()
+    }
+
+    /// The union of the [`Animation::bounding_box`] of every animation of every group, so a single canvas can be used to export all of them.
+    /// Return [`None`] if no animation show any opaque pixel.
+    ///
+    /// Each frame is only rendered once.
+    pub fn animations_bounding_box(&self) -> Result<Option<BoundingBox>, FrameRenderError> {
+        let frame_boxes = self.frame_bounding_boxes()?;
+        let mut result = None;
+        for animation in self.animation_store.anim_groups.iter().flatten() {
+            result = BoundingBox::union_option(result, animation.bounding_box_with(&frame_boxes)?);
+        }
+        Ok(result)
+    }
+
+    /// The size of the smallest canvas centered on the origin that can contain every animation, as given by [`BoundingBox::centered_canvas_size`] of [`WanImage::animations_bounding_box`].
+    ///
+    /// Return `(0, 0)` if no animation show any opaque pixel.
+    pub fn animations_canvas_size(&self) -> Result<(u32, u32), FrameRenderError> {
+        Ok(self
+            .animations_bounding_box()?
+            .map(|bounding_box| bounding_box.centered_canvas_size())
+            .unwrap_or((0, 0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage, tests::fixtures::animation_frame,
+        tests::fixtures::test_wan_image, Animation, AnimationFrame, BoundingBox, Frame, SpriteType,
+    };
+
+    #[test]
+    fn test_bounding_box() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        // a 4×2 opaque rectangle in a 16×16 frame, at (2, 8)
+        let mut pixels = vec![0; 16 * 16];
+        for y in 8..10 {
+            pixels[y * 16 + 2..y * 16 + 6].fill(1);
+        }
+        insert_frame_in_wanimage(pixels, 16, 16, &mut wanimage, 0).unwrap();
+        wanimage.frame_store.frames.push(Frame::default());
+
+        let frame_box = BoundingBox {
+            min_x: -6,
+            min_y: 0,
+            max_x: -2,
+            max_y: 2,
+        };
+        assert_eq!(wanimage.frame_bounding_box(0).unwrap(), Some(frame_box));
+        assert_eq!(wanimage.frame_bounding_box(1).unwrap(), None);
+        assert!(wanimage.frame_bounding_box(2).is_err());
+
+        wanimage.animation_store.anim_groups.push(vec![
+            Animation {
+                frames: vec![animation_frame(0, 1), animation_frame(1, 1)],
+            },
+            Animation {
+                frames: vec![AnimationFrame {
+                    offset_x: 10,
+                    ..animation_frame(0, 1)
+                }],
+            },
+        ]);
+        assert_eq!(
+            wanimage.animation_store.anim_groups[0][1]
+                .bounding_box(&wanimage)
+                .unwrap(),
+            Some(frame_box.translate(10, 0))
+        );
+        let union = wanimage.animations_bounding_box().unwrap().unwrap();
+        assert_eq!(
+            union,
+            BoundingBox {
+                min_x: -6,
+                min_y: 0,
+                max_x: 8,
+                max_y: 2
+            }
+        );
+        assert_eq!((union.width(), union.height()), (14, 2));
+        assert_eq!(wanimage.animations_canvas_size().unwrap(), (16, 4));
+    }
+}
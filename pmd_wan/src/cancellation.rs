@@ -0,0 +1,53 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use thiserror::Error;
+
+/// Returned by a long operation that was stopped with [`CancellationToken::cancel`]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("the operation has been cancelled")]
+pub struct Cancelled;
+
+/// Allow to stop a long operation (like [`crate::find_fragments_in_images_with_progress`]) from another thread, for example when the user close the import dialog of an editor.
+///
+/// Clones share the same state, so the token can be cancelled through any of them. The operation only check it from time to time, and then return [`Cancelled`].
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that isn't cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the operations using this token (or one of its clones) to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Return [`Cancelled`] if the token has been cancelled
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Two tokens are equal if they are clones of each other
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+impl Eq for CancellationToken {}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+// On wasm32-unknown-unknown, the standard library doesn't have access to a clock, and Instant::now panic.
+// The time measurements go through the types of this module, that estimate or skip them on this target.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+mod imp {
+    use std::time::{Duration, Instant};
+
+    pub(crate) struct Deadline {
+        end: Instant,
+    }
+
+    impl Deadline {
+        pub(crate) fn after(budget: Duration) -> Self {
+            Self {
+                end: Instant::now() + budget,
+            }
+        }
+
+        pub(crate) fn is_past(&mut self) -> bool {
+            Instant::now() > self.end
+        }
+    }
+
+    pub(crate) struct Stopwatch {
+        start: Instant,
+    }
+
+    impl Stopwatch {
+        pub(crate) fn start() -> Self {
+            Self {
+                start: Instant::now(),
+            }
+        }
+
+        pub(crate) fn elapsed(&self) -> Option<Duration> {
+            Some(self.start.elapsed())
+        }
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+mod imp {
+    use std::time::Duration;
+
+    /// The number of [`Deadline::is_past`] checks assumed to take a millisecond
+    const CHECKS_PER_MILLISECOND: u128 = 1;
+
+    pub(crate) struct Deadline {
+        remaining_checks: u128,
+    }
+
+    impl Deadline {
+        pub(crate) fn after(budget: Duration) -> Self {
+            Self {
+                remaining_checks: budget.as_millis() * CHECKS_PER_MILLISECOND,
+            }
+        }
+
+        pub(crate) fn is_past(&mut self) -> bool {
+            self.remaining_checks = self.remaining_checks.saturating_sub(1);
+            self.remaining_checks == 0
+        }
+    }
+
+    pub(crate) struct Stopwatch;
+
+    impl Stopwatch {
+        pub(crate) fn start() -> Self {
+            Self
+        }
+
+        pub(crate) fn elapsed(&self) -> Option<Duration> {
+            None
+        }
+    }
+}
+
+/// The moment a time budget end.
+///
+/// Without a clock, the time is estimated from the number of calls to [`Deadline::is_past`], assuming one per millisecond (the layout search check it about every thousand steps).
+pub(crate) struct Deadline(imp::Deadline);
+
+impl Deadline {
+    pub(crate) fn after(budget: Duration) -> Self {
+        Self(imp::Deadline::after(budget))
+    }
+
+    pub(crate) fn is_past(&mut self) -> bool {
+        self.0.is_past()
+    }
+}
+
+/// Measure the time elapsed since its creation
+pub(crate) struct Stopwatch(imp::Stopwatch);
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Self(imp::Stopwatch::start())
+    }
+
+    /// The elapsed time, or [`None`] if there is no clock
+    pub(crate) fn elapsed(&self) -> Option<Duration> {
+        self.0.elapsed()
+    }
+}
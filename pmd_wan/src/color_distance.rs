@@ -0,0 +1,276 @@
+use image::RgbaImage;
+
+use crate::{
+    image_tool::TransparencyOptions, insert_frame_in_wanimage, palette_file::component_to_8bit,
+    Palette, WanImage,
+};
+
+/// How the difference between two colors is measured when searching for the nearest one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorDistance {
+    /// The euclidean distance between the RGB components. Fast, but it doesn't match how the eye perceive differences, especially for dark colors.
+    #[default]
+    Euclidean,
+    /// The euclidean distance in the CIELAB color space (CIE76)
+    Cie76,
+    /// The CIEDE2000 difference, the most perceptually accurate, but also the slowest
+    Ciede2000,
+}
+
+impl ColorDistance {
+    /// Return the distance between two colors. Only the order of the results is meaningful, as each metric has its own scale.
+    pub fn distance(&self, first: [u8; 3], second: [u8; 3]) -> f64 {
+        match self {
+            Self::Euclidean => (0..3)
+                .map(|channel| (first[channel] as f64 - second[channel] as f64).powi(2))
+                .sum::<f64>()
+                .sqrt(),
+            Self::Cie76 => {
+                let (first, second) = (rgb_to_lab(first), rgb_to_lab(second));
+                (0..3)
+                    .map(|channel| (first[channel] - second[channel]).powi(2))
+                    .sum::<f64>()
+                    .sqrt()
+            }
+            Self::Ciede2000 => ciede2000(rgb_to_lab(first), rgb_to_lab(second)),
+        }
+    }
+
+    /// Return the index of the color of `colors` nearest to `target`, or [`None`] if `colors` is empty. The first one is returned in case of a tie.
+    pub fn nearest(&self, colors: &[[u8; 3]], target: [u8; 3]) -> Option<usize> {
+        let mut best: Option<(usize, f64)> = None;
+        for (index, color) in colors.iter().enumerate() {
+            let distance = self.distance(*color, target);
+            if best.is_none_or(|(_, best_distance)| distance < best_distance) {
+                best = Some((index, distance));
+            }
+        }
+        best.map(|(index, _)| index)
+    }
+}
+
+/// Convert a sRGB color to the CIELAB color space, with the D65 white point
+fn rgb_to_lab(color: [u8; 3]) -> [f64; 3] {
+    let [red, green, blue] = color.map(|component| {
+        let component = component as f64 / 255.0;
+        if component <= 0.04045 {
+            component / 12.92
+        } else {
+            ((component + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let x = (0.4124564 * red + 0.3575761 * green + 0.1804375 * blue) / 0.95047;
+    let y = 0.2126729 * red + 0.7151522 * green + 0.072175 * blue;
+    let z = (0.0193339 * red + 0.119192 * green + 0.9503041 * blue) / 1.08883;
+    let [fx, fy, fz] = [x, y, z].map(|t| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    });
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// The CIEDE2000 color difference between two CIELAB colors
+fn ciede2000(first: [f64; 3], second: [f64; 3]) -> f64 {
+    let [l1, a1, b1] = first;
+    let [l2, a2, b2] = second;
+    let pow7 = |value: f64| value.powi(7);
+    let chroma_mean = ((a1.hypot(b1) + a2.hypot(b2)) / 2.0).max(0.0);
+    let g = 0.5 * (1.0 - (pow7(chroma_mean) / (pow7(chroma_mean) + pow7(25.0))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |b: f64, a: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h.to_radians() / 2.0).sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+    let cos_deg = |degree: f64| degree.to_radians().cos();
+    let t = 1.0 - 0.17 * cos_deg(h_mean - 30.0)
+        + 0.24 * cos_deg(2.0 * h_mean)
+        + 0.32 * cos_deg(3.0 * h_mean + 6.0)
+        - 0.20 * cos_deg(4.0 * h_mean - 63.0);
+    let delta_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (pow7(c_mean) / (pow7(c_mean) + pow7(25.0))).sqrt();
+    let s_l = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean;
+    let s_h = 1.0 + 0.015 * c_mean * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).sqrt()
+}
+
+impl Palette {
+    /// Return the opaque color (from 1 to 15) of the row `row` nearest to `color` (with components in the 0-255 range), or [`None`] if the row has no opaque color.
+    pub fn nearest_color_in_row(
+        &self,
+        color: [u8; 3],
+        row: u16,
+        distance: ColorDistance,
+    ) -> Option<u8> {
+        let start = row as usize * 16 + 1;
+        let colors: Vec<[u8; 3]> = self
+            .palette
+            .iter()
+            .skip(start)
+            .take(15)
+            .map(|color| {
+                [
+                    component_to_8bit(color[0]),
+                    component_to_8bit(color[1]),
+                    component_to_8bit(color[2]),
+                ]
+            })
+            .collect();
+        distance
+            .nearest(&colors, color)
+            .map(|index| index as u8 + 1)
+    }
+}
+
+impl WanImage {
+    /// Insert an RGBA image as a new frame with [`insert_frame_in_wanimage`], mapping each pixel to the nearest color of the existing palette row `pal_id` according to `distance`.
+    ///
+    /// The transparent pixels are chosen with the [`TransparencyOptions`]. If the row has no opaque color, every pixel is transparent.
+    pub fn insert_frame_with_palette_row(
+        &mut self,
+        image: &RgbaImage,
+        pal_id: u16,
+        distance: ColorDistance,
+        transparency: &TransparencyOptions,
+    ) -> anyhow::Result<Option<usize>> {
+        let pixels = image
+            .pixels()
+            .map(|pixel| {
+                if transparency.is_transparent(*pixel) {
+                    return 0;
+                }
+                self.palette
+                    .nearest_color_in_row([pixel.0[0], pixel.0[1], pixel.0[2]], pal_id, distance)
+                    .unwrap_or(0)
+            })
+            .collect();
+        insert_frame_in_wanimage(
+            pixels,
+            image.width() as u16,
+            image.height() as u16,
+            self,
+            pal_id,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::{ciede2000, rgb_to_lab};
+    use crate::{
+        image_tool::TransparencyOptions, tests::fixtures::test_wan_image, ColorDistance, SpriteType,
+    };
+
+    #[test]
+    fn test_ciede2000() {
+        // from the test data of Sharma, Wu and Dalal
+        let distance = ciede2000([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485]);
+        assert!((distance - 2.0425).abs() < 0.0001);
+        let distance = ciede2000([50.0, 2.5, 0.0], [73.0, 25.0, -18.0]);
+        assert!((distance - 27.1492).abs() < 0.0001);
+        assert_eq!(ciede2000([50.0, 0.0, 0.0], [50.0, 0.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_rgb_to_lab() {
+        let white = rgb_to_lab([255, 255, 255]);
+        assert!((white[0] - 100.0).abs() < 0.01);
+        assert!(white[1].abs() < 0.01 && white[2].abs() < 0.01);
+        assert!(rgb_to_lab([0, 0, 0]).iter().all(|value| value.abs() < 0.01));
+    }
+
+    #[test]
+    fn test_nearest() {
+        let colors = [[0, 0, 0], [40, 40, 40], [255, 0, 0]];
+        for distance in [
+            ColorDistance::Euclidean,
+            ColorDistance::Cie76,
+            ColorDistance::Ciede2000,
+        ] {
+            assert_eq!(distance.nearest(&colors, [250, 10, 10]), Some(2));
+            assert_eq!(distance.nearest(&colors, [5, 5, 5]), Some(0));
+            assert_eq!(distance.nearest(&[], [5, 5, 5]), None);
+        }
+    }
+
+    #[test]
+    fn test_insert_frame_with_palette_row() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.push([0, 0, 248, 128]);
+        let mut image = RgbaImage::from_pixel(8, 8, Rgba([200, 30, 30, 255]));
+        image.put_pixel(1, 0, Rgba([10, 10, 180, 255]));
+        image.put_pixel(2, 0, Rgba([10, 10, 180, 0]));
+        // the color key
+        image.put_pixel(3, 0, Rgba([255, 0, 255, 255]));
+        assert_eq!(
+            wanimage
+                .palette
+                .nearest_color_in_row([0, 0, 255], 0, ColorDistance::Ciede2000),
+            Some(2)
+        );
+        let frame_id = wanimage
+            .insert_frame_with_palette_row(
+                &image,
+                0,
+                ColorDistance::Ciede2000,
+                &TransparencyOptions {
+                    alpha_threshold: 128,
+                    color_key: Some([255, 0, 255]),
+                },
+            )
+            .unwrap()
+            .unwrap();
+        let rendered = wanimage.render_frame(frame_id).unwrap();
+        assert_eq!(rendered.dimensions(), (8, 8));
+        assert_eq!(rendered.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(rendered.get_pixel(1, 0).0, [0, 0, 255, 255]);
+        assert_eq!(
+            wanimage
+                .pick_fragment_in_render(frame_id, 1, 0)
+                .unwrap()
+                .map(|hit| hit.color),
+            Some(2)
+        );
+        // the pixel under the alpha threshold and the color key are transparent
+        assert_eq!(rendered.get_pixel(2, 0).0, [0, 0, 0, 0]);
+        assert_eq!(rendered.get_pixel(3, 0).0, [0, 0, 0, 0]);
+    }
+}
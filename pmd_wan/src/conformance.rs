@@ -0,0 +1,213 @@
+use std::fmt;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use crate::batch::{list_wan_files, map_items};
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use crate::BatchError;
+use crate::{roundtrip::compare_wan_images, RoundtripDifference, RoundtripError, WanImage};
+
+/// The result of decoding and re-encoding a reference wan file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConformanceStatus {
+    /// The re-encoded file is bit-for-bit identical to the reference
+    Identical,
+    /// The re-encoded file differ, but decode to the same [`WanImage`]
+    SemanticallyEqual,
+    /// The reference couldn't be decoded or re-encoded, or the re-encoded file decode to a different [`WanImage`]
+    Mismatch,
+}
+
+impl ConformanceStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Identical => "identical",
+            Self::SemanticallyEqual => "semantically_equal",
+            Self::Mismatch => "mismatch",
+        }
+    }
+}
+
+/// The result of [`check_conformance`] for a single reference file
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConformanceEntry {
+    /// The path of the file relative to the checked folder, or the name given to [`check_conformance`]
+    pub name: String,
+    pub status: ConformanceStatus,
+    /// Why the status is [`ConformanceStatus::Mismatch`]
+    pub reason: Option<String>,
+    /// The differences found after decoding the re-encoded file, for a [`ConformanceStatus::Mismatch`]
+    pub differences: Vec<RoundtripDifference>,
+}
+
+/// The result of [`check_folder_conformance`], with an entry per reference file, sorted by path.
+///
+/// Its [`fmt::Display`] implementation write a line per entry, with the status, the name and the reason (if any) separated by tabulations, so it can be parsed or diffed between runs.
+/// It can also be serialized with the `serde` feature.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConformanceReport {
+    pub entries: Vec<ConformanceEntry>,
+}
+
+impl ConformanceReport {
+    /// The number of entries with the given status
+    pub fn count(&self, status: ConformanceStatus) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.status == status)
+            .count()
+    }
+
+    /// true if no entry is a [`ConformanceStatus::Mismatch`]
+    pub fn is_conformant(&self) -> bool {
+        self.count(ConformanceStatus::Mismatch) == 0
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            write!(f, "{}\t{}", entry.status.name(), entry.name)?;
+            if let Some(reason) = &entry.reason {
+                write!(f, "\t{}", reason.replace(['\t', '\n'], " "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode the reference wan file stored in `bytes`, re-encode it, and compare the result with the reference, first byte by byte, then by decoding it again.
+///
+/// `name` is only used to identify the [`ConformanceEntry`].
+pub fn check_conformance(name: String, bytes: &[u8]) -> ConformanceEntry {
+    let mismatch = |reason: String, differences| ConformanceEntry {
+        name: name.clone(),
+        status: ConformanceStatus::Mismatch,
+        reason: Some(reason),
+        differences,
+    };
+    let error_reason = |error: RoundtripError| format!("{:#}", anyhow::Error::from(error));
+
+    let original = match WanImage::decode_wan_from_bytes(bytes) {
+        Ok(original) => original,
+        Err(error) => {
+            return mismatch(
+                error_reason(RoundtripError::CantDecodeOriginal(error)),
+                Vec::new(),
+            )
+        }
+    };
+    let reencoded_bytes = match original.write_to_vec() {
+        Ok(reencoded_bytes) => reencoded_bytes,
+        Err(error) => return mismatch(error_reason(RoundtripError::CantEncode(error)), Vec::new()),
+    };
+    if reencoded_bytes == bytes {
+        return ConformanceEntry {
+            name,
+            status: ConformanceStatus::Identical,
+            reason: None,
+            differences: Vec::new(),
+        };
+    }
+    let reencoded = match WanImage::decode_wan_from_bytes(&reencoded_bytes) {
+        Ok(reencoded) => reencoded,
+        Err(error) => {
+            return mismatch(
+                error_reason(RoundtripError::CantDecodeReencoded(error)),
+                Vec::new(),
+            )
+        }
+    };
+    let differences = compare_wan_images(&original, &reencoded);
+    if differences.is_empty() {
+        ConformanceEntry {
+            name,
+            status: ConformanceStatus::SemanticallyEqual,
+            reason: None,
+            differences,
+        }
+    } else {
+        mismatch(
+            format!("the re-encoded file differ: {:?}", differences),
+            differences,
+        )
+    }
+}
+
+/// Run [`check_conformance`] on every `.wan` file of `folder` and its subfolders, in parallel with the `rayon` feature.
+///
+/// A file that can't be read is reported as a [`ConformanceStatus::Mismatch`]. Only an error while listing the files is returned directly.
+/// Not available on `wasm32-unknown-unknown`, that doesn't have a file system.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn check_folder_conformance(folder: &Path) -> Result<ConformanceReport, BatchError> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    list_wan_files(folder, folder, &mut files)?;
+    let entries = map_items(&files, |relative_path| {
+        let name = relative_path.display().to_string();
+        match fs::read(folder.join(relative_path)) {
+            Ok(bytes) => check_conformance(name, &bytes),
+            Err(error) => ConformanceEntry {
+                name,
+                status: ConformanceStatus::Mismatch,
+                reason: Some(format!("can't read the file: {}", error)),
+                differences: Vec::new(),
+            },
+        }
+    });
+    Ok(ConformanceReport { entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        check_folder_conformance,
+        tests::fixtures::{single_frame_wan_bytes, TempDir},
+        ConformanceStatus,
+    };
+
+    #[test]
+    fn test_check_folder_conformance() {
+        let folder = TempDir::new("conformance");
+        std::fs::create_dir_all(folder.join("sub")).unwrap();
+        std::fs::write(folder.join("identical.wan"), single_frame_wan_bytes()).unwrap();
+        // the padding at the end of the file is ignored by the decoder
+        let mut padded = single_frame_wan_bytes();
+        padded.extend_from_slice(&[0; 16]);
+        std::fs::write(folder.join("sub").join("padded.wan"), padded).unwrap();
+        std::fs::write(folder.join("broken.wan"), [0; 4]).unwrap();
+
+        let report = check_folder_conformance(&folder).unwrap();
+        let statuses: Vec<_> = report
+            .entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("broken.wan", ConformanceStatus::Mismatch),
+                ("identical.wan", ConformanceStatus::Identical),
+                (
+                    std::path::Path::new("sub")
+                        .join("padded.wan")
+                        .to_str()
+                        .unwrap(),
+                    ConformanceStatus::SemanticallyEqual
+                ),
+            ]
+        );
+        assert!(!report.is_conformant());
+        assert_eq!(report.count(ConformanceStatus::Identical), 1);
+        let text = report.to_string();
+        assert!(text.starts_with("mismatch\tbroken.wan\tFailed to decode the original wan file: "));
+        assert!(text.contains("\nidentical\tidentical.wan\n"));
+    }
+}
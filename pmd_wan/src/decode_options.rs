@@ -0,0 +1,321 @@
+use thiserror::Error;
+
+use crate::{FragmentBytes, WanError, WanImage};
+
+/// How [`WanImage::decode_wan_with_options`] should react to malformed data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// If true (the default), any malformed data is a hard error, like with [`WanImage::decode_wan`].
+    /// If false, the bad frames, fragments and animations are skipped or repaired, and a [`DecodeWarning`] is recorded for each of them.
+    pub strict: bool,
+    /// Exceeding one of those limits is always a hard error, even in lenient mode
+    pub limits: DecodeLimits,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            strict: true,
+            limits: DecodeLimits::default(),
+        }
+    }
+}
+
+impl DecodeOptions {
+    /// Options that skip or repair malformed data instead of failing
+    pub fn lenient() -> Self {
+        Self {
+            strict: false,
+            ..Default::default()
+        }
+    }
+
+    /// Return the error in strict mode, or if it is a [`WanError::LimitExceeded`]. Otherwise, record the warning built from it instead.
+    pub(crate) fn recover(
+        &self,
+        error: WanError,
+        warnings: &mut Vec<DecodeWarning>,
+        warning: impl FnOnce(WanError) -> DecodeWarning,
+    ) -> Result<(), WanError> {
+        if self.strict || matches!(error, WanError::LimitExceeded(_, _)) {
+            return Err(error);
+        }
+        warnings.push(warning(error));
+        Ok(())
+    }
+}
+
+/// Caps on the amount of data [`WanImage::decode_wan_with_options`] is allowed to read, so a corrupted or malicious file can't make it allocate huge amounts of memory or loop for a long time.
+///
+/// The default is unlimited, as with [`WanImage::decode_wan`]. Use [`DecodeLimits::untrusted`] for files from an untrusted source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeLimits {
+    pub max_frames: usize,
+    pub max_fragments_per_frame: usize,
+    /// The maximum number of [`FragmentBytes`]
+    pub max_fragment_bytes: usize,
+    /// The maximum number of values in the [`FragmentBytes::mixed_pixels`] of all the [`FragmentBytes`] together (256 colors sprites use two values per pixel)
+    pub max_image_bytes: usize,
+    pub max_animation_groups: usize,
+    pub max_animations_per_group: usize,
+    pub max_frames_per_animation: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_frames: usize::MAX,
+            max_fragments_per_frame: usize::MAX,
+            max_fragment_bytes: usize::MAX,
+            max_image_bytes: usize::MAX,
+            max_animation_groups: usize::MAX,
+            max_animations_per_group: usize::MAX,
+            max_frames_per_animation: usize::MAX,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Limits comfortably above what the sprites of the games use, that keep the decoded [`WanImage`] under a few dozens of megabytes
+    pub fn untrusted() -> Self {
+        Self {
+            max_frames: 4096,
+            // the number of OAM entries of the DS
+            max_fragments_per_frame: 128,
+            max_fragment_bytes: 8192,
+            max_image_bytes: 8 * 1024 * 1024,
+            max_animation_groups: 1024,
+            max_animations_per_group: 64,
+            max_frames_per_animation: 1024,
+        }
+    }
+}
+
+/// Return a [`WanError::LimitExceeded`] if there are more than `max` `what`
+pub(crate) fn check_limit(count: usize, max: usize, what: &'static str) -> Result<(), WanError> {
+    if count > max {
+        return Err(WanError::LimitExceeded(what, max));
+    }
+    Ok(())
+}
+
+/// A problem found and worked around while decoding in lenient mode (see [`DecodeOptions::strict`])
+#[derive(Debug, Error)]
+pub enum DecodeWarning {
+    #[error(
+        "this Chara sprite doesn't have a frame offset table, default frame offsets have been used"
+    )]
+    MissingFrameOffsets,
+    #[error(
+        "this sprite isn't a Chara sprite, but it has a frame offset table, which has been ignored"
+    )]
+    UnexpectedFrameOffsets,
+    #[error("the pointers to the frames aren't spaced by a multiple of 10 bytes")]
+    IrregularFramePointers,
+    #[error(
+        "the frame {frame} couldn't be fully read, only its {kept} first fragments have been kept"
+    )]
+    TruncatedFrame {
+        frame: usize,
+        kept: usize,
+        #[source]
+        error: WanError,
+    },
+    #[error(
+        "the FragmentBytes {index} couldn't be read, and has been replaced by transparent pixels"
+    )]
+    InvalidFragmentBytes {
+        index: usize,
+        #[source]
+        error: WanError,
+    },
+    #[error("the fragment {fragment} of the frame {frame} reference the FragmentBytes {fragment_bytes}, which doesn't exist. It has been removed")]
+    MissingFragmentBytes {
+        frame: usize,
+        fragment: usize,
+        fragment_bytes: usize,
+    },
+    #[error("the animation {animation} of the group {group} couldn't be read, and has been replaced by an empty animation")]
+    InvalidAnimation {
+        group: usize,
+        animation: usize,
+        #[source]
+        error: WanError,
+    },
+    #[error("an animation frame of the animation {animation} of the group {group} reference the frame {frame_id}, which doesn't exist. It has been removed")]
+    MissingFrame {
+        group: usize,
+        animation: usize,
+        frame_id: u16,
+    },
+}
+
+impl WanImage {
+    /// Fix the references that would make a decoded [`WanImage`] unusable, once everything has been read in lenient mode:
+    /// - the [`FragmentBytes`] that couldn't be read (listed in `invalid_fragment_bytes`) are replaced by transparent pixels, sized for the first [`crate::Fragment`] that use them,
+    /// - the [`crate::Fragment`]s that reference a non-existing [`FragmentBytes`] are removed,
+    /// - the [`crate::AnimationFrame`]s that reference a non-existing [`crate::Frame`] are removed.
+    pub(crate) fn repair_references(
+        &mut self,
+        invalid_fragment_bytes: &[usize],
+        warnings: &mut Vec<DecodeWarning>,
+    ) {
+        let fragment_bytes_count = self.fragment_bytes_store.len();
+        for (frame_id, frame) in self.frame_store.frames.iter_mut().enumerate() {
+            let mut fragment_id = 0;
+            frame.fragments.retain(|fragment| {
+                let exist = fragment.fragment_bytes_index < fragment_bytes_count;
+                if !exist {
+                    warnings.push(DecodeWarning::MissingFragmentBytes {
+                        frame: frame_id,
+                        fragment: fragment_id,
+                        fragment_bytes: fragment.fragment_bytes_index,
+                    });
+                }
+                fragment_id += 1;
+                exist
+            });
+        }
+
+        let depth_factor = if self.is_256_color { 2 } else { 1 };
+        for index in invalid_fragment_bytes {
+            let pixel_amount = self
+                .frame_store
+                .frames
+                .iter()
+                .flat_map(|frame| frame.fragments.iter())
+                .find(|fragment| fragment.fragment_bytes_index == *index)
+                .map(|fragment| {
+                    let size = fragment.resolution.size();
+                    (size.x * size.y) as usize
+                })
+                .unwrap_or(64);
+            self.fragment_bytes_store.fragment_bytes[*index] = FragmentBytes {
+                mixed_pixels: vec![0; pixel_amount * depth_factor],
+                z_index: 0,
+            };
+        }
+
+        let frame_count = self.frame_store.frames.len();
+        for (group_id, group) in self.animation_store.anim_groups.iter_mut().enumerate() {
+            for (animation_id, animation) in group.iter_mut().enumerate() {
+                animation.frames.retain(|animation_frame| {
+                    let exist = (animation_frame.frame_id as usize) < frame_count;
+                    if !exist {
+                        warnings.push(DecodeWarning::MissingFrame {
+                            group: group_id,
+                            animation: animation_id,
+                            frame_id: animation_frame.frame_id,
+                        });
+                    }
+                    exist
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        tests::fixtures::{animation_frame, single_frame_wan_bytes, single_frame_wan_image},
+        Animation, DecodeLimits, DecodeOptions, DecodeWarning, WanError, WanImage,
+    };
+
+    fn test_wan_bytes() -> Vec<u8> {
+        let mut wanimage = single_frame_wan_image();
+        // reference a FragmentBytes and a frame that doesn't exist
+        let mut broken_fragment = wanimage.frame_store.frames[0].fragments[0].clone();
+        broken_fragment.fragment_bytes_index = 5;
+        wanimage.frame_store.frames[0]
+            .fragments
+            .push(broken_fragment);
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![animation_frame(10, 1), animation_frame(0, 1)],
+        }]);
+        let mut bytes = wanimage.write_to_vec().unwrap();
+        // pretend this is a Chara sprite, which should have a frame offset table
+        let header = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        bytes[header + 8] = 1;
+        bytes
+    }
+
+    #[test]
+    fn test_strict_decode() {
+        let error = WanImage::decode_wan_from_bytes(&test_wan_bytes()).unwrap_err();
+        assert!(matches!(error, WanError::NonExistenceFrameOffsetForChara));
+    }
+
+    #[test]
+    fn test_lenient_decode() {
+        let (wanimage, warnings) = WanImage::decode_wan_with_options(
+            Cursor::new(test_wan_bytes()),
+            &DecodeOptions::lenient(),
+        )
+        .unwrap();
+        assert_eq!(warnings.len(), 3);
+        assert!(matches!(warnings[0], DecodeWarning::MissingFrameOffsets));
+        assert!(matches!(
+            warnings[1],
+            DecodeWarning::MissingFragmentBytes {
+                frame: 0,
+                fragment: 1,
+                fragment_bytes: 5
+            }
+        ));
+        assert!(matches!(
+            warnings[2],
+            DecodeWarning::MissingFrame {
+                group: 0,
+                animation: 0,
+                frame_id: 10
+            }
+        ));
+        assert_eq!(wanimage.frame_store.frames[0].fragments.len(), 1);
+        assert!(wanimage.frame_store.frames[0].frame_offset.is_some());
+        assert_eq!(
+            wanimage.animation_store.anim_groups[0][0].frames[0].frame_id,
+            0
+        );
+        assert!(wanimage.write_to_vec().is_ok());
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let bytes = single_frame_wan_bytes();
+        let untrusted = DecodeOptions {
+            limits: DecodeLimits::untrusted(),
+            ..DecodeOptions::lenient()
+        };
+        let (decoded, warnings) =
+            WanImage::decode_wan_with_options(Cursor::new(&bytes), &untrusted).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(decoded, WanImage::decode_wan_from_bytes(&bytes).unwrap());
+
+        // limits are enforced even in lenient mode
+        let small = DecodeOptions {
+            limits: DecodeLimits {
+                max_image_bytes: 32,
+                ..Default::default()
+            },
+            ..DecodeOptions::lenient()
+        };
+        let error = WanImage::decode_wan_with_options(Cursor::new(&bytes), &small).unwrap_err();
+        assert!(matches!(
+            error,
+            WanError::LimitExceeded("pixels in the FragmentBytes", 32)
+        ));
+
+        let no_frame = DecodeOptions {
+            limits: DecodeLimits {
+                max_frames: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let error = WanImage::decode_wan_with_options(Cursor::new(&bytes), &no_frame).unwrap_err();
+        assert!(matches!(error, WanError::LimitExceeded("frames", 0)));
+    }
+}
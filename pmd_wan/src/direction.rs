@@ -0,0 +1,180 @@
+use crate::{Animation, AnimationGroupError, AnimationStore, DIRECTION_COUNT};
+
+/// The direction a directional [`Animation`] face, in the order of the [`Animation`]s in their group.
+///
+/// They start from down, and turn counterclockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    Down,
+    DownRight,
+    Right,
+    UpRight,
+    Up,
+    UpLeft,
+    Left,
+    DownLeft,
+}
+
+impl Direction {
+    /// All the directions, in the order of their index
+    pub const ALL: [Self; DIRECTION_COUNT] = [
+        Self::Down,
+        Self::DownRight,
+        Self::Right,
+        Self::UpRight,
+        Self::Up,
+        Self::UpLeft,
+        Self::Left,
+        Self::DownLeft,
+    ];
+
+    /// The index of the [`Animation`] of this direction in its group
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    /// The horizontally mirrored direction (see [`crate::MIRRORED_DIRECTIONS`]). Down and up are their own mirror.
+    pub fn mirrored(self) -> Self {
+        Self::ALL[(DIRECTION_COUNT - self.index()) % DIRECTION_COUNT]
+    }
+
+    /// The direction after turning by `steps` eighth of a turn counterclockwise (negative for clockwise)
+    pub fn rotated(self, steps: i32) -> Self {
+        Self::ALL[(self.index() as i32 + steps).rem_euclid(DIRECTION_COUNT as i32) as usize]
+    }
+
+    /// The movement of one tile in this direction, with y going down like on screen
+    pub fn vector(self) -> (i8, i8) {
+        match self {
+            Self::Down => (0, 1),
+            Self::DownRight => (1, 1),
+            Self::Right => (1, 0),
+            Self::UpRight => (1, -1),
+            Self::Up => (0, -1),
+            Self::UpLeft => (-1, -1),
+            Self::Left => (-1, 0),
+            Self::DownLeft => (-1, 1),
+        }
+    }
+}
+
+impl AnimationStore {
+    /// Return the [`Animation`] of the given group for the given direction. A group with a single [`Animation`] use it for all directions.
+    ///
+    /// This work for any group index, including the ones without an [`crate::AnimationGroupKind`].
+    pub fn get_direction_animation(
+        &self,
+        group: usize,
+        direction: Direction,
+    ) -> Option<&Animation> {
+        let group = self.anim_groups.get(group)?;
+        if group.len() == 1 {
+            group.first()
+        } else {
+            group.get(direction.index())
+        }
+    }
+
+    /// Set the [`Animation`] of the given group for the given direction, adding empty groups before it if needed.
+    ///
+    /// If the group doesn't have one [`Animation`] per direction, it is first expanded to [`DIRECTION_COUNT`] copies of its [`Animation`] (or of an empty one).
+    pub fn set_direction_animation(
+        &mut self,
+        group: usize,
+        direction: Direction,
+        animation: Animation,
+    ) -> Result<(), AnimationGroupError> {
+        while self.anim_groups.len() <= group {
+            self.insert_group(self.anim_groups.len(), Vec::new())?;
+        }
+        let mut animations = self.anim_groups[group].clone();
+        if animations.len() != DIRECTION_COUNT {
+            let base = animations.first().cloned().unwrap_or_default();
+            animations = vec![base; DIRECTION_COUNT];
+        }
+        animations[direction.index()] = animation;
+        self.remove_group(group)?;
+        self.insert_group(group, animations)?;
+        Ok(())
+    }
+
+    /// Check that every group contain 0, 1 (for all directions) or [`DIRECTION_COUNT`] (one per direction) [`Animation`]s, as expected by the game
+    pub fn validate_direction_counts(&self) -> Result<(), AnimationGroupError> {
+        for (group_id, group) in self.anim_groups.iter().enumerate() {
+            if ![0, 1, DIRECTION_COUNT].contains(&group.len()) {
+                return Err(AnimationGroupError::InvalidDirectionCount(
+                    group_id,
+                    group.len(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::fixtures::animation_frame, Animation, AnimationGroupError, AnimationStore,
+        Direction, MIRRORED_DIRECTIONS,
+    };
+
+    fn animation(frame_id: u16) -> Animation {
+        Animation {
+            frames: vec![animation_frame(frame_id, 1)],
+        }
+    }
+
+    #[test]
+    fn test_direction() {
+        for (index, direction) in Direction::ALL.iter().enumerate() {
+            assert_eq!(direction.index(), index);
+            assert_eq!(direction.mirrored().mirrored(), *direction);
+        }
+        for (right, left) in MIRRORED_DIRECTIONS {
+            assert_eq!(Direction::ALL[right].mirrored().index(), left);
+        }
+        assert_eq!(Direction::Down.mirrored(), Direction::Down);
+        assert_eq!(Direction::Down.rotated(2), Direction::Right);
+        assert_eq!(Direction::Down.rotated(-1), Direction::DownLeft);
+        assert_eq!(Direction::UpLeft.vector(), (-1, -1));
+        assert_eq!(Direction::from_index(8), None);
+    }
+
+    #[test]
+    fn test_direction_animation() {
+        let mut store = AnimationStore {
+            copied_on_previous: None,
+            anim_groups: vec![vec![animation(0)]],
+        };
+        assert_eq!(
+            store.get_direction_animation(0, Direction::Left),
+            Some(&animation(0))
+        );
+        store
+            .set_direction_animation(40, Direction::Up, animation(1))
+            .unwrap();
+        assert_eq!(store.anim_groups.len(), 41);
+        assert_eq!(store.anim_groups[40].len(), 8);
+        assert_eq!(
+            store.get_direction_animation(40, Direction::Up),
+            Some(&animation(1))
+        );
+        assert_eq!(
+            store.get_direction_animation(40, Direction::Down),
+            Some(&Animation::default())
+        );
+        assert_eq!(store.validate_direction_counts(), Ok(()));
+
+        store.anim_groups[3] = vec![animation(2); 2];
+        assert_eq!(
+            store.validate_direction_counts(),
+            Err(AnimationGroupError::InvalidDirectionCount(3, 2))
+        );
+    }
+}
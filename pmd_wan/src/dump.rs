@@ -0,0 +1,257 @@
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use byteorder::{ReadBytesExt, LE};
+
+use crate::{wan_image::WanHeader, DecodeOptions, Fragment, WanError, WanImage};
+
+fn read_pointers<F: Read + Seek>(
+    file: &mut F,
+    start: u64,
+    amount: usize,
+) -> Result<Vec<u64>, WanError> {
+    file.seek(SeekFrom::Start(start))?;
+    (0..amount)
+        .map(|_| Ok(file.read_u32::<LE>()? as u64))
+        .collect()
+}
+
+fn describe_fragment(fragment: &Fragment) -> String {
+    let size = fragment.resolution.size();
+    let mut description = format!(
+        "image bytes {}, {}x{} at ({}, {}), palette {}, priority {}",
+        fragment.fragment_bytes_index,
+        size.x,
+        size.y,
+        fragment.offset_x,
+        fragment.offset_y,
+        fragment.pal_idx,
+        fragment.priority
+    );
+    if fragment.flip.flip_h {
+        description.push_str(", horizontal flip");
+    }
+    if fragment.flip.flip_v {
+        description.push_str(", vertical flip");
+    }
+    if fragment.is_mosaic {
+        description.push_str(", mosaic");
+    }
+    description
+}
+
+/// Write a tree-like description of the wan file (that should be decompressed) to `output`, for debugging.
+///
+/// It list the location of the headers and tables, then each frame with its fragments, each [`crate::FragmentBytes`], and each animation group with its animations and their frames, with the offset they are stored at.
+/// The file is decoded in lenient mode (see [`DecodeOptions::lenient`]), and the [`crate::DecodeWarning`]s are listed at the end.
+pub fn dump_wan<F: Read + Seek, W: Write>(mut file: F, output: &mut W) -> Result<(), WanError> {
+    let header = WanHeader::read(&mut file, &DecodeOptions::lenient(), &mut Vec::new())?;
+    let (image, warnings) =
+        WanImage::decode_wan_with_options(&mut file, &DecodeOptions::lenient())?;
+
+    writeln!(
+        output,
+        "sir0 header at 0x0: wan header at 0x{:X}, pointer list at 0x{:X}, file of {} bytes",
+        header.sir0_pointer_header, header.sir0_pointer_offset, header.source_file_lenght
+    )?;
+    writeln!(
+        output,
+        "wan header at 0x{:X}: {:?}, unk2 {}",
+        header.sir0_pointer_header, header.sprite_type, header.unk2
+    )?;
+    writeln!(
+        output,
+        "  animation info at 0x{:X}",
+        header.pointer_to_anim_info
+    )?;
+    writeln!(
+        output,
+        "    frames table at 0x{:X}",
+        header.pointer_frames_table
+    )?;
+    if header.frame_offset_table == 0 {
+        writeln!(output, "    no frame offsets table")?;
+    } else {
+        writeln!(
+            output,
+            "    frame offsets table at 0x{:X}",
+            header.frame_offset_table
+        )?;
+    }
+    writeln!(
+        output,
+        "    animation groups table at 0x{:X} ({} groups)",
+        header.pointer_animation_table, header.amount_animation_group
+    )?;
+    writeln!(
+        output,
+        "  image data info at 0x{:X}{}",
+        header.pointer_to_image_data_info,
+        if header.is_256_color {
+            ", 256 colors mode"
+        } else {
+            ""
+        }
+    )?;
+    writeln!(
+        output,
+        "    image bytes table at 0x{:X} ({} image bytes)",
+        header.pointer_image_data_pointer_table, header.amount_fragments
+    )?;
+    writeln!(
+        output,
+        "    palette at 0x{:X} ({} colors)",
+        header.pointer_palette,
+        image.palette.palette.len()
+    )?;
+
+    let frames = &image.frame_store.frames;
+    let frame_pointers = read_pointers(&mut file, header.pointer_frames_table, frames.len())?;
+    writeln!(output, "frames ({})", frames.len())?;
+    for (frame_id, (frame, pointer)) in frames.iter().zip(&frame_pointers).enumerate() {
+        writeln!(
+            output,
+            "  frame {} at 0x{:X}: {} fragments",
+            frame_id,
+            pointer,
+            frame.fragments.len()
+        )?;
+        for (fragment_id, fragment) in frame.fragments.iter().enumerate() {
+            writeln!(
+                output,
+                "    fragment {}: {}",
+                fragment_id,
+                describe_fragment(fragment)
+            )?;
+        }
+        if let Some(offset) = &frame.frame_offset {
+            writeln!(
+                output,
+                "    offsets: head {:?}, left hand {:?}, right hand {:?}, center {:?}",
+                offset.head, offset.hand_left, offset.hand_right, offset.center
+            )?;
+        }
+    }
+
+    let fragment_bytes = &image.fragment_bytes_store.fragment_bytes;
+    let fragment_bytes_pointers = read_pointers(
+        &mut file,
+        header.pointer_image_data_pointer_table,
+        fragment_bytes.len(),
+    )?;
+    writeln!(output, "image bytes ({})", fragment_bytes.len())?;
+    for (fragment_bytes_id, (bytes, pointer)) in fragment_bytes
+        .iter()
+        .zip(&fragment_bytes_pointers)
+        .enumerate()
+    {
+        writeln!(
+            output,
+            "  image bytes {} at 0x{:X}: {} pixels, z index {}",
+            fragment_bytes_id,
+            pointer,
+            bytes.mixed_pixels.len(),
+            bytes.z_index
+        )?;
+    }
+
+    file.seek(SeekFrom::Start(header.pointer_animation_table))?;
+    let mut group_entries = Vec::with_capacity(header.amount_animation_group as usize);
+    for _ in 0..header.amount_animation_group {
+        let pointer = file.read_u32::<LE>()? as u64;
+        let length = file.read_u32::<LE>()? as usize;
+        group_entries.push((pointer, length));
+    }
+    writeln!(output, "animation groups ({})", group_entries.len())?;
+    for (group_id, (pointer, length)) in group_entries.into_iter().enumerate() {
+        let animations = image
+            .animation_store
+            .anim_groups
+            .get(group_id)
+            .map(|group| group.as_slice())
+            .unwrap_or(&[]);
+        if pointer == 0 || length == 0 {
+            writeln!(output, "  group {}: empty", group_id)?;
+            continue;
+        }
+        writeln!(
+            output,
+            "  group {} at 0x{:X}: {} animations",
+            group_id, pointer, length
+        )?;
+        let animation_pointers = read_pointers(&mut file, pointer, length)?;
+        for (animation_id, (animation, animation_pointer)) in
+            animations.iter().zip(&animation_pointers).enumerate()
+        {
+            writeln!(
+                output,
+                "    animation {} at 0x{:X}: {} frames",
+                animation_id,
+                animation_pointer,
+                animation.frames.len()
+            )?;
+            for (frame_id, frame) in animation.frames.iter().enumerate() {
+                writeln!(
+                    output,
+                    "      frame {}: frame {} for {} ticks, offset ({}, {}), shadow ({}, {}), flag {}",
+                    frame_id,
+                    frame.frame_id,
+                    frame.duration,
+                    frame.offset_x,
+                    frame.offset_y,
+                    frame.shadow_offset_x,
+                    frame.shadow_offset_y,
+                    frame.flag
+                )?;
+            }
+        }
+    }
+
+    if !warnings.is_empty() {
+        writeln!(output, "warnings ({})", warnings.len())?;
+        for warning in &warnings {
+            writeln!(output, "  {}", warning)?;
+        }
+    }
+    Ok(())
+}
+
+/// Like [`dump_wan`], but return the description of the wan file stored in `bytes` as a [`String`]
+pub fn dump_wan_to_string(bytes: &[u8]) -> Result<String, WanError> {
+    let mut output = Vec::new();
+    dump_wan(Cursor::new(bytes), &mut output)?;
+    // no panic: everything written is valid UTF-8
+    Ok(String::from_utf8(output).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dump_wan_to_string,
+        tests::fixtures::{animation_frame, single_frame_wan_image},
+        Animation, AnimationFrame,
+    };
+
+    #[test]
+    fn test_dump() {
+        let mut wanimage = single_frame_wan_image();
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![AnimationFrame {
+                offset_x: 1,
+                offset_y: -2,
+                ..animation_frame(0, 4)
+            }],
+        }]);
+        let dump = dump_wan_to_string(&wanimage.write_to_vec().unwrap()).unwrap();
+        assert!(dump.starts_with("sir0 header at 0x0: wan header at 0x"));
+        assert!(dump.contains("\nframes (1)\n  frame 0 at 0x"));
+        assert!(dump.contains("    fragment 0: image bytes 0, 8x8 at "));
+        assert!(dump.contains("\nimage bytes (1)\n"));
+        assert!(dump.contains("\nanimation groups (1)\n  group 0 at 0x"));
+        assert!(dump.contains(
+            "      frame 0: frame 0 for 4 ticks, offset (1, -2), shadow (0, 0), flag 0\n"
+        ));
+        assert!(!dump.contains("warnings"));
+
+        assert!(dump_wan_to_string(&[0; 4]).is_err());
+    }
+}
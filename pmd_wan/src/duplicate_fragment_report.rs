@@ -0,0 +1,153 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Read, Seek};
+
+use crate::{flip_duplicates::FlipDuplicates, OamShape, WanError, WanImage};
+
+/// How much of the [`crate::FragmentBytes`] of a [`WanImage`] are duplicates of another one.
+///
+/// The sizes are in byte of uncompressed pixel data (two pixels per byte), which is an upper bound of what removing the duplicates would save in the written file.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DuplicateFragmentReport {
+    /// The number of [`crate::FragmentBytes`] analyzed
+    pub fragment_bytes_amount: usize,
+    /// The size of all the [`crate::FragmentBytes`]
+    pub total_bytes: usize,
+    /// The number of [`crate::FragmentBytes`] that are exactly identical to another one that appear before them
+    pub exact_duplicates: usize,
+    /// The number of [`crate::FragmentBytes`] that are a flipped version of another one that appear before them (not counting exact duplicates)
+    pub flip_duplicates: usize,
+    /// The size of all the duplicates [`crate::FragmentBytes`]
+    pub duplicate_bytes: usize,
+}
+
+impl DuplicateFragmentReport {
+    /// Proportion (from 0 to 1) of the pixel data that consist of duplicates
+    pub fn duplicate_ratio(&self) -> f32 {
+        if self.total_bytes == 0 {
+            0.0
+        } else {
+            self.duplicate_bytes as f32 / self.total_bytes as f32
+        }
+    }
+}
+
+impl WanImage {
+    /// Decode a wan file like [`WanImage::decode_wan`], and also compute a [`DuplicateFragmentReport`] for it.
+    pub fn decode_wan_with_duplicate_report<F: Read + Seek>(
+        file: F,
+    ) -> Result<(WanImage, DuplicateFragmentReport), WanError> {
+        let wan_image = WanImage::decode_wan(file)?;
+        let report = wan_image.duplicate_fragment_report();
+        Ok((wan_image, report))
+    }
+
+    /// Compute how much of the [`crate::FragmentBytes`] are duplicates, exact or flip-equivalent.
+    ///
+    /// Flip-equivalence is only checked for [`crate::FragmentBytes`] used by at least one [`crate::Fragment`], as their shape is otherwise unknown.
+    pub fn duplicate_fragment_report(&self) -> DuplicateFragmentReport {
+        let mut shape_of_fragment_bytes: HashMap<usize, OamShape> = HashMap::new();
+        for fragment in self
+            .frame_store
+            .frames
+            .iter()
+            .flat_map(|frame| frame.fragments.iter())
+        {
+            shape_of_fragment_bytes
+                .entry(fragment.fragment_bytes_index)
+                .or_insert(fragment.resolution);
+        }
+
+        let mut report = DuplicateFragmentReport::default();
+        let mut exact_seen: BTreeSet<&[u8]> = BTreeSet::new();
+        let mut flip_duplicates = FlipDuplicates::new();
+        for (fragment_bytes_index, fragment_bytes) in
+            self.fragment_bytes_store.fragment_bytes.iter().enumerate()
+        {
+            let byte_len = fragment_bytes.mixed_pixels.len().div_ceil(2);
+            report.fragment_bytes_amount += 1;
+            report.total_bytes += byte_len;
+
+            let is_exact_duplicate = !exact_seen.insert(fragment_bytes.mixed_pixels.as_slice());
+
+            let is_flip_duplicate =
+                if let Some(shape) = shape_of_fragment_bytes.get(&fragment_bytes_index) {
+                    let resolution = shape.size();
+                    match fragment_bytes.decode_pixels(resolution.clone(), self.is_256_color) {
+                        Ok(pixels) => {
+                            let key = (resolution.x, resolution.y);
+                            let found = flip_duplicates
+                                .find(&key, &pixels, resolution.clone())
+                                .is_some();
+                            if !found {
+                                flip_duplicates.insert(key, pixels, fragment_bytes_index);
+                            }
+                            found
+                        }
+                        Err(_) => false,
+                    }
+                } else {
+                    false
+                };
+
+            if is_exact_duplicate {
+                report.exact_duplicates += 1;
+                report.duplicate_bytes += byte_len;
+            } else if is_flip_duplicate {
+                report.flip_duplicates += 1;
+                report.duplicate_bytes += byte_len;
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        encode_fragment_pixels, Fragment, FragmentBytes, FragmentFlip, Frame, GeneralResolution,
+        OamShape, SpriteType, WanImage, DEFAULT_FRAGMENT_PRIORITY,
+    };
+
+    #[test]
+    fn test_duplicate_fragment_report() {
+        let mut wanimage = WanImage::new(SpriteType::PropsUI);
+        let mut pixels = [0; 64];
+        pixels[0] = 1;
+        let mut mirrored = [0; 64];
+        mirrored[7] = 1;
+        for pixels in [pixels, pixels, mirrored] {
+            wanimage
+                .fragment_bytes_store
+                .fragment_bytes
+                .push(FragmentBytes {
+                    mixed_pixels: encode_fragment_pixels(&pixels, GeneralResolution::new(8, 8))
+                        .unwrap(),
+                    z_index: 0,
+                });
+        }
+        let mut frame = Frame::default();
+        for fragment_bytes_index in 0..3 {
+            frame.fragments.push(Fragment {
+                unk1: 0,
+                unk3_4: None,
+                unk5: false,
+                fragment_bytes_index,
+                offset_y: 0,
+                offset_x: 0,
+                flip: FragmentFlip::standard(),
+                is_mosaic: false,
+                priority: DEFAULT_FRAGMENT_PRIORITY,
+                pal_idx: 0,
+                resolution: OamShape::new(0, 0).unwrap(),
+            });
+        }
+        wanimage.frame_store.frames.push(frame);
+
+        let report = wanimage.duplicate_fragment_report();
+        assert_eq!(report.fragment_bytes_amount, 3);
+        assert_eq!(report.total_bytes, 96);
+        assert_eq!(report.exact_duplicates, 1);
+        assert_eq!(report.flip_duplicates, 1);
+        assert_eq!(report.duplicate_bytes, 64);
+    }
+}
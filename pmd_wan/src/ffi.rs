@@ -0,0 +1,323 @@
+//! A C interface, enabled with the `ffi` feature, so tools written in other languages can read, render and write wan files.
+//!
+//! A decoded image is an opaque pointer to a [`WanImage`], that should be freed with [`pmd_wan_free`]. The buffers allocated by the library are returned as a [`PmdWanBuffer`], freed with [`pmd_wan_buffer_free`].
+//! Functions that can fail return a null pointer or `false`, and the reason can then be read with [`pmd_wan_last_error`].
+//!
+//! To get a shared or static library, build the crate with `cargo rustc --release --features ffi --crate-type cdylib` (or `staticlib`).
+
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fmt::Display;
+use std::os::raw::c_char;
+use std::ptr;
+
+use crate::{AnimationFrame, WanImage};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(error: impl Display) {
+    // no panic: the interior nul bytes are removed
+    let message = CString::new(error.to_string().replace('\0', "")).unwrap();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Return the message of the last error that happened in this thread, or null if there was none.
+///
+/// The string is valid until the next failing call in this thread, and should not be freed.
+#[no_mangle]
+pub extern "C" fn pmd_wan_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map(|message| message.as_ptr())
+            .unwrap_or(ptr::null())
+    })
+}
+
+/// Bytes allocated by the library, to free with [`pmd_wan_buffer_free`]. `data` is null on error.
+#[repr(C)]
+pub struct PmdWanBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl PmdWanBuffer {
+    fn new(bytes: Vec<u8>) -> Self {
+        let len = bytes.len();
+        let data = Box::into_raw(bytes.into_boxed_slice()) as *mut u8;
+        Self { data, len }
+    }
+
+    fn null() -> Self {
+        Self {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+}
+
+/// Free a buffer returned by the library. Does nothing if its data is null.
+///
+/// # Safety
+///
+/// `buffer` should have been returned by this library, and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn pmd_wan_buffer_free(buffer: PmdWanBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+}
+
+/// An [`AnimationFrame`], as returned by [`pmd_wan_animation_frame`]
+#[repr(C)]
+pub struct PmdWanAnimationFrame {
+    pub duration: u8,
+    pub flag: u8,
+    pub frame_id: u16,
+    pub offset_x: i16,
+    pub offset_y: i16,
+    pub shadow_offset_x: i16,
+    pub shadow_offset_y: i16,
+}
+
+impl From<&AnimationFrame> for PmdWanAnimationFrame {
+    fn from(frame: &AnimationFrame) -> Self {
+        Self {
+            duration: frame.duration,
+            flag: frame.flag,
+            frame_id: frame.frame_id,
+            offset_x: frame.offset_x,
+            offset_y: frame.offset_y,
+            shadow_offset_x: frame.shadow_offset_x,
+            shadow_offset_y: frame.shadow_offset_y,
+        }
+    }
+}
+
+/// Decode the wan file stored in the `len` bytes at `data`. Return null on error.
+///
+/// # Safety
+///
+/// `data` should point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pmd_wan_decode(data: *const u8, len: usize) -> *mut WanImage {
+    if data.is_null() {
+        set_last_error("the data pointer is null");
+        return ptr::null_mut();
+    }
+    let bytes = std::slice::from_raw_parts(data, len);
+    match WanImage::decode_wan_from_bytes(bytes) {
+        Ok(image) => Box::into_raw(Box::new(image)),
+        Err(error) => {
+            set_last_error(error);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free an image returned by [`pmd_wan_decode`]. Does nothing if it is null.
+///
+/// # Safety
+///
+/// `image` should have been returned by [`pmd_wan_decode`], and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn pmd_wan_free(image: *mut WanImage) {
+    if !image.is_null() {
+        drop(Box::from_raw(image));
+    }
+}
+
+/// Encode the image in a new wan file. The data of the buffer is null on error.
+///
+/// # Safety
+///
+/// `image` should be a valid image returned by [`pmd_wan_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn pmd_wan_encode(image: *const WanImage) -> PmdWanBuffer {
+    match (&*image).write_to_vec() {
+        Ok(bytes) => PmdWanBuffer::new(bytes),
+        Err(error) => {
+            set_last_error(format!("{:#}", error));
+            PmdWanBuffer::null()
+        }
+    }
+}
+
+/// The number of frames of the image
+///
+/// # Safety
+///
+/// `image` should be a valid image returned by [`pmd_wan_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn pmd_wan_frame_count(image: *const WanImage) -> usize {
+    (&*image).frame_store.frames.len()
+}
+
+/// Render the frame `frame_id` as RGBA pixels, line by line from the top-left one, and write its size in `width` and `height`.
+/// The data of the buffer is null on error.
+///
+/// # Safety
+///
+/// `image` should be a valid image returned by [`pmd_wan_decode`], and `width` and `height` should be writable.
+#[no_mangle]
+pub unsafe extern "C" fn pmd_wan_render_frame(
+    image: *const WanImage,
+    frame_id: usize,
+    width: *mut u32,
+    height: *mut u32,
+) -> PmdWanBuffer {
+    match (&*image).render_frame(frame_id) {
+        Ok(rendered) => {
+            *width = rendered.width();
+            *height = rendered.height();
+            PmdWanBuffer::new(rendered.into_raw())
+        }
+        Err(error) => {
+            set_last_error(error);
+            PmdWanBuffer::null()
+        }
+    }
+}
+
+/// The number of animation groups of the image
+///
+/// # Safety
+///
+/// `image` should be a valid image returned by [`pmd_wan_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn pmd_wan_animation_group_count(image: *const WanImage) -> usize {
+    (&*image).animation_store.anim_groups.len()
+}
+
+/// The number of animations of the group `group`, or 0 if it doesn't exist
+///
+/// # Safety
+///
+/// `image` should be a valid image returned by [`pmd_wan_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn pmd_wan_animation_count(image: *const WanImage, group: usize) -> usize {
+    (&*image)
+        .animation_store
+        .anim_groups
+        .get(group)
+        .map(|animations| animations.len())
+        .unwrap_or(0)
+}
+
+/// The number of frames of the animation `animation` of the group `group`, or 0 if it doesn't exist
+///
+/// # Safety
+///
+/// `image` should be a valid image returned by [`pmd_wan_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn pmd_wan_animation_frame_count(
+    image: *const WanImage,
+    group: usize,
+    animation: usize,
+) -> usize {
+    (&*image)
+        .animation_store
+        .anim_groups
+        .get(group)
+        .and_then(|animations| animations.get(animation))
+        .map(|animation| animation.frames.len())
+        .unwrap_or(0)
+}
+
+/// Write the frame `frame` of the animation `animation` of the group `group` in `output`. Return false if it doesn't exist.
+///
+/// # Safety
+///
+/// `image` should be a valid image returned by [`pmd_wan_decode`], and `output` should be writable.
+#[no_mangle]
+pub unsafe extern "C" fn pmd_wan_animation_frame(
+    image: *const WanImage,
+    group: usize,
+    animation: usize,
+    frame: usize,
+    output: *mut PmdWanAnimationFrame,
+) -> bool {
+    let animation_frame = (&*image)
+        .animation_store
+        .anim_groups
+        .get(group)
+        .and_then(|animations| animations.get(animation))
+        .and_then(|animation| animation.frames.get(frame));
+    match animation_frame {
+        Some(animation_frame) => {
+            *output = animation_frame.into();
+            true
+        }
+        None => {
+            set_last_error(format!(
+                "the frame {} of the animation {} of the group {} doesn't exist",
+                frame, animation, group
+            ));
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+    use crate::{
+        tests::fixtures::{animation_frame, single_frame_wan_image},
+        Animation,
+    };
+
+    #[test]
+    fn test_ffi() {
+        let mut wanimage = single_frame_wan_image();
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![AnimationFrame {
+                offset_x: 1,
+                offset_y: 2,
+                ..animation_frame(0, 3)
+            }],
+        }]);
+        let bytes = wanimage.write_to_vec().unwrap();
+
+        unsafe {
+            let image = pmd_wan_decode(bytes.as_ptr(), bytes.len());
+            assert!(!image.is_null());
+            assert_eq!(pmd_wan_frame_count(image), 1);
+            assert_eq!(pmd_wan_animation_group_count(image), 1);
+            assert_eq!(pmd_wan_animation_count(image, 0), 1);
+            assert_eq!(pmd_wan_animation_frame_count(image, 0, 0), 1);
+            let mut frame = PmdWanAnimationFrame::from(&animation_frame(0, 0));
+            assert!(pmd_wan_animation_frame(image, 0, 0, 0, &mut frame));
+            assert_eq!((frame.duration, frame.offset_y), (3, 2));
+            assert!(!pmd_wan_animation_frame(image, 0, 0, 1, &mut frame));
+
+            let (mut width, mut height) = (0, 0);
+            let rendered = pmd_wan_render_frame(image, 0, &mut width, &mut height);
+            assert_eq!((width, height), (8, 8));
+            assert_eq!(rendered.len, 8 * 8 * 4);
+            pmd_wan_buffer_free(rendered);
+            assert!(pmd_wan_render_frame(image, 5, &mut width, &mut height)
+                .data
+                .is_null());
+            assert!(!pmd_wan_last_error().is_null());
+
+            let encoded = pmd_wan_encode(image);
+            assert_eq!(
+                std::slice::from_raw_parts(encoded.data, encoded.len),
+                &bytes[..]
+            );
+            pmd_wan_buffer_free(encoded);
+            pmd_wan_free(image);
+
+            assert!(pmd_wan_decode(bytes.as_ptr(), 4).is_null());
+            assert!(!CStr::from_ptr(pmd_wan_last_error()).to_bytes().is_empty());
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::{collections::HashMap, hash::Hash};
+
+use crate::{frame_render::flip_pixels, FragmentFlip, GeneralResolution};
+
+/// The four [`FragmentFlip`]s, starting with the one that doesn't flip
+const ALL_FLIPS: [FragmentFlip; 4] = [
+    FragmentFlip::standard(),
+    FragmentFlip::horizontal(),
+    FragmentFlip::vertical(),
+    FragmentFlip::both(),
+];
+
+/// Known decoded pixels of [`crate::FragmentBytes`], to find the one that display some pixels under one of the four [`FragmentFlip`]s.
+///
+/// `K` is what, in addition to the pixels, should be identical, like the resolution or the z index.
+pub(crate) struct FlipDuplicates<K> {
+    known: HashMap<(K, Vec<u8>), usize>,
+}
+
+impl<K: Hash + Eq + Clone> FlipDuplicates<K> {
+    pub fn new() -> Self {
+        Self {
+            known: HashMap::new(),
+        }
+    }
+
+    /// Return the id of a known [`crate::FragmentBytes`] with the same `key` that display `pixels` once flipped, with this flip.
+    /// The non-flipped one is preferred.
+    pub fn find(
+        &self,
+        key: &K,
+        pixels: &[u8],
+        resolution: GeneralResolution,
+    ) -> Option<(usize, FragmentFlip)> {
+        ALL_FLIPS.iter().copied().find_map(|flip| {
+            // the flips are their own inverse
+            let flipped = flip_pixels(pixels, resolution.clone(), flip);
+            self.known
+                .get(&(key.clone(), flipped))
+                .map(|id| (*id, flip))
+        })
+    }
+
+    /// Add the pixels of a [`crate::FragmentBytes`]. If they are already known, the first id is kept.
+    pub fn insert(&mut self, key: K, pixels: Vec<u8>, id: usize) {
+        self.known.entry((key, pixels)).or_insert(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FlipDuplicates;
+    use crate::{FragmentFlip, GeneralResolution};
+
+    #[test]
+    fn test_flip_duplicates() {
+        let resolution = GeneralResolution::new(2, 2);
+        let mut duplicates = FlipDuplicates::new();
+        duplicates.insert(0, vec![1, 0, 0, 0], 5);
+        assert_eq!(
+            duplicates.find(&0, &[1, 0, 0, 0], resolution.clone()),
+            Some((5, FragmentFlip::standard()))
+        );
+        assert_eq!(
+            duplicates.find(&0, &[0, 1, 0, 0], resolution.clone()),
+            Some((5, FragmentFlip::horizontal()))
+        );
+        assert_eq!(
+            duplicates.find(&0, &[0, 0, 0, 1], resolution.clone()),
+            Some((5, FragmentFlip::both()))
+        );
+        assert_eq!(duplicates.find(&1, &[1, 0, 0, 0], resolution.clone()), None);
+        assert_eq!(duplicates.find(&0, &[1, 1, 0, 0], resolution), None);
+    }
+}
@@ -6,9 +6,32 @@ use anyhow::bail;
 use byteorder::WriteBytesExt;
 use byteorder::{ReadBytesExt, LE};
 use std::io::{Read, Write};
+use thiserror::Error;
+
+/// The priority of the [`Fragment`]s created by this library. This is the lowest one, and what is used by almost every sprite of the game.
+pub const DEFAULT_FRAGMENT_PRIORITY: u8 = 3;
+/// The highest value a [`Fragment::priority`] can have
+pub const MAX_FRAGMENT_PRIORITY: u8 = 3;
+/// The highest value a [`Fragment::pal_idx`] can have, as it is stored on 4 bits
+pub const MAX_FRAGMENT_PALETTE_INDEX: u16 = 15;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum FragmentAttributeError {
+    #[error(
+        "The priority {0} is greater than the maximum of {}",
+        MAX_FRAGMENT_PRIORITY
+    )]
+    InvalidPriority(u8),
+    #[error(
+        "The palette index {0} is greater than the maximum of {}",
+        MAX_FRAGMENT_PALETTE_INDEX
+    )]
+    InvalidPaletteIndex(u16),
+}
 
 /// A [`Fragment`] may reference an [`crate::FragmentBytes`], that will form a single (or all if small enought) part of an [`crate::Frame`]
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Fragment {
     pub unk1: u16,
     /// Two value with unknown property in the offset y data.
@@ -21,6 +44,9 @@ pub struct Fragment {
     pub offset_x: i16,
     pub flip: FragmentFlip,
     pub is_mosaic: bool,
+    /// The render priority relative to the background layers, from 0 (drawn above everything) to [`MAX_FRAGMENT_PRIORITY`]
+    pub priority: u8,
+    /// The palette bank used by this [`Fragment`], from 0 to [`MAX_FRAGMENT_PALETTE_INDEX`]
     pub pal_idx: u16,
     pub resolution: OamShape,
 }
@@ -85,6 +111,7 @@ impl Fragment {
 
         let alloc_and_palette = file.read_u16::<LE>()?;
         let pal_idx = (0xF000 & alloc_and_palette) >> 12;
+        let priority = ((0x0C00 & alloc_and_palette) >> 10) as u8;
 
         Ok((
             Fragment {
@@ -96,6 +123,7 @@ impl Fragment {
                 offset_y,
                 flip,
                 is_mosaic,
+                priority,
                 pal_idx,
                 resolution: match OamShape::new(size_indice_y, size_indice_x) {
                     Some(r) => r,
@@ -161,6 +189,14 @@ impl Fragment {
             );
         }
 
+        if self.priority > MAX_FRAGMENT_PRIORITY {
+            bail!(
+                "The priority of this metaframe is more than {} (it is {})",
+                MAX_FRAGMENT_PRIORITY,
+                self.priority
+            );
+        }
+
         let (v_flip, h_flip) = self.flip.to_bools();
 
         let offset_x_data: u16 = ((self.resolution.size_indice() as u16) << (8 + 6))
@@ -173,9 +209,33 @@ impl Fragment {
         file.write_u16::<LE>(offset_y_data)?;
         file.write_u16::<LE>(offset_x_data)?;
         file.write_u16::<LE>(
-            ((self.pal_idx & 0xF) << 12) + 0x0C00 + (fragment_alloc_counter & 0x3FF),
+            ((self.pal_idx & 0xF) << 12)
+                + ((self.priority as u16) << 10)
+                + (fragment_alloc_counter & 0x3FF),
         )?;
 
         Ok(())
     }
+
+    /// Set the render priority, checking it is at most [`MAX_FRAGMENT_PRIORITY`]
+    pub fn set_priority(&mut self, priority: u8) -> Result<(), FragmentAttributeError> {
+        if priority > MAX_FRAGMENT_PRIORITY {
+            return Err(FragmentAttributeError::InvalidPriority(priority));
+        }
+        self.priority = priority;
+        Ok(())
+    }
+
+    /// Set the palette bank, checking it is at most [`MAX_FRAGMENT_PALETTE_INDEX`]
+    pub fn set_palette_index(&mut self, pal_idx: u16) -> Result<(), FragmentAttributeError> {
+        if pal_idx > MAX_FRAGMENT_PALETTE_INDEX {
+            return Err(FragmentAttributeError::InvalidPaletteIndex(pal_idx));
+        }
+        self.pal_idx = pal_idx;
+        Ok(())
+    }
+
+    pub fn set_mosaic(&mut self, is_mosaic: bool) {
+        self.is_mosaic = is_mosaic;
+    }
 }
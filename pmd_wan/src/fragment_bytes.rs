@@ -1,11 +1,15 @@
-use anyhow::{bail, Context};
+use anyhow::bail;
 use binwrite::BinWrite;
 use byteorder::{ReadBytesExt, LE};
 use image::{ImageBuffer, Rgba};
+use std::convert::TryInto;
 use std::io::{Read, Seek, SeekFrom, Write};
 use thiserror::Error;
 
-use crate::{CompressionMethod, GeneralResolution, Palette, WanError};
+use crate::{
+    decode_options::check_limit, fragment_bytes_compression::compress_with,
+    FragmentBytesCompressionStats, FragmentCompressor, GeneralResolution, Palette, WanError,
+};
 
 #[derive(Error, Debug)]
 pub enum FragmentBytesToImageError {
@@ -56,7 +60,8 @@ impl FragmentBytesAssemblyEntry {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FragmentBytes {
     pub mixed_pixels: Vec<u8>,
     pub z_index: u32,
@@ -64,13 +69,22 @@ pub struct FragmentBytes {
 
 impl FragmentBytes {
     pub fn new_from_bytes<F: Read + Seek>(file: &mut F) -> Result<FragmentBytes, WanError> {
+        Self::new_from_bytes_with_limit(file, usize::MAX)
+    }
+
+    /// Like [`FragmentBytes::new_from_bytes`], but fail before reading the pixels if there would be more than `max_image_bytes` values in [`FragmentBytes::mixed_pixels`]
+    pub(crate) fn new_from_bytes_with_limit<F: Read + Seek>(
+        file: &mut F,
+        max_image_bytes: usize,
+    ) -> Result<FragmentBytes, WanError> {
         let mut fbytes_asm_table = Vec::new();
-        let mut fbytes_size = 0;
+        let mut fbytes_size: usize = 0;
 
         let mut last_pointer = None; //for check
         loop {
             let asm_entry = FragmentBytesAssemblyEntry::new_from_bytes(file)?;
-            fbytes_size += asm_entry.pixel_amount;
+            fbytes_size = fbytes_size.saturating_add(asm_entry.pixel_amount as usize);
+            check_limit(fbytes_size, max_image_bytes, "pixels in the FragmentBytes")?;
             if asm_entry.is_null() {
                 break;
             } else {
@@ -117,9 +131,7 @@ impl FragmentBytes {
                 file.seek(SeekFrom::Start(entry.pixel_src))?;
                 read_buffer.resize(entry.byte_amount as usize, 0);
                 file.read_exact(&mut read_buffer)?;
-                for pixel_pair in &read_buffer {
-                    mixed_pixels.extend([pixel_pair >> 4, pixel_pair & 0x0F]);
-                }
+                split_nibbles(&read_buffer, &mut mixed_pixels);
             };
             // check that all part of the image have the same z index
             if let Some(index) = z_index {
@@ -160,9 +172,29 @@ impl FragmentBytes {
     pub fn write<F: Write + Seek>(
         &self,
         file: &mut F,
-        compression_method: &CompressionMethod,
+        compressor: &dyn FragmentCompressor,
     ) -> Result<(u64, Vec<u64>), WanError> {
-        let mut assembly_table = compression_method.compress(self, &self.mixed_pixels, file)?;
+        let (assembly_table_offset, pointer, _) = self.write_with_stats(file, compressor)?;
+        Ok((assembly_table_offset, pointer))
+    }
+
+    /// Like [`FragmentBytes::write`], but also return statistics about how it was compressed
+    pub(crate) fn write_with_stats<F: Write + Seek>(
+        &self,
+        file: &mut F,
+        compressor: &dyn FragmentCompressor,
+    ) -> Result<(u64, Vec<u64>, FragmentBytesCompressionStats), WanError> {
+        let start_offset = file.stream_position()?;
+        let mut assembly_table = compress_with(compressor, self, &self.mixed_pixels, file)?;
+        let mut stats = FragmentBytesCompressionStats {
+            pixel_amount: self.mixed_pixels.len(),
+            assembly_entries: assembly_table.len(),
+            ..Default::default()
+        };
+        for entry in assembly_table.iter().filter(|entry| entry.pixel_src == 0) {
+            stats.transparent_runs += 1;
+            stats.transparent_pixels += entry.pixel_amount as usize;
+        }
 
         //insert empty entry
         assembly_table.push(FragmentBytesAssemblyEntry {
@@ -172,18 +204,19 @@ impl FragmentBytes {
             _z_index: 0,
         });
 
-        let assembly_table_offset = file.seek(SeekFrom::Current(0))?;
+        let assembly_table_offset = file.stream_position()?;
 
         //write assembly table
         let mut pointer = Vec::new();
         for entry in assembly_table {
             if entry.pixel_src != 0 {
-                pointer.push(file.seek(SeekFrom::Current(0))?);
+                pointer.push(file.stream_position()?);
             };
             entry.write(file)?;
         }
+        stats.bytes_written = (file.stream_position()? - start_offset) as usize;
 
-        Ok((assembly_table_offset, pointer))
+        Ok((assembly_table_offset, pointer, stats))
     }
 
     pub fn get_image(
@@ -191,6 +224,17 @@ impl FragmentBytes {
         palette: &Palette,
         resolution: GeneralResolution,
         palette_id: u16,
+    ) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, FragmentBytesToImageError> {
+        self.get_image_with_depth(palette, resolution, palette_id, false)
+    }
+
+    /// Like [`FragmentBytes::get_image`], but also support 256 colors [`FragmentBytes`], in which case `palette_id` is ignored.
+    pub fn get_image_with_depth(
+        &self,
+        palette: &Palette,
+        resolution: GeneralResolution,
+        palette_id: u16,
+        is_256_color: bool,
     ) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, FragmentBytesToImageError> {
         if resolution.x == 0 || resolution.y == 0 {
             return Err(FragmentBytesToImageError::ZeroSizedImage);
@@ -199,11 +243,11 @@ impl FragmentBytes {
         let mut pixels: Vec<u8> =
             Vec::with_capacity(resolution.x as usize * resolution.y as usize * 4);
 
-        for pixel in decode_fragment_pixels(&self.mixed_pixels, resolution.clone())? {
+        for pixel in self.decode_pixels(resolution.clone(), is_256_color)? {
             let mut color = if pixel == 0 {
                 [0, 0, 0, 0]
             } else {
-                match palette.get(pixel, palette_id) {
+                match palette.get_with_depth(pixel, palette_id, is_256_color) {
                     Some(c) => c,
                     None => return Err(FragmentBytesToImageError::UnknownColor(pixel, palette_id)),
                 }
@@ -212,7 +256,7 @@ impl FragmentBytes {
             pixels.extend(color);
         }
 
-        let img = match ImageBuffer::from_vec(resolution.x as u32, resolution.y as u32, pixels) {
+        let img = match ImageBuffer::from_vec(resolution.x, resolution.y, pixels) {
             Some(img) => img,
             None => return Err(FragmentBytesToImageError::CantCreateImage),
         };
@@ -231,17 +275,49 @@ pub enum DecodeFragmentBytesError {
     NoPixel,
 }
 
+const fn build_nibbles_table() -> [[u8; 2]; 256] {
+    let mut table = [[0; 2]; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        table[byte] = [(byte >> 4) as u8, (byte & 0x0F) as u8];
+        byte += 1;
+    }
+    table
+}
+
+/// The high and low nibbles of each byte value
+static NIBBLES_OF_BYTE: [[u8; 2]; 256] = build_nibbles_table();
+
+/// Append the high then the low nibble of each byte of `bytes` to `output`
+pub(crate) fn split_nibbles(bytes: &[u8], output: &mut Vec<u8>) {
+    let start = output.len();
+    output.resize(start + bytes.len() * 2, 0);
+    for (pair, byte) in output[start..].chunks_exact_mut(2).zip(bytes) {
+        pair.copy_from_slice(&NIBBLES_OF_BYTE[*byte as usize]);
+    }
+}
+
+/// Swap the two pixels of each pair of a line of 8 pixels, as they are stored swapped in the 16 colors fragments.
+///
+/// All of them are swapped at once in an `u64`.
+#[inline(always)]
+fn swap_pixel_pairs(line: &[u8]) -> [u8; 8] {
+    // no panic: the lines are always 8 pixels long
+    let value = u64::from_le_bytes(line.try_into().unwrap());
+    (((value & 0x00FF_00FF_00FF_00FF) << 8) | ((value >> 8) & 0x00FF_00FF_00FF_00FF)).to_le_bytes()
+}
+
 /// Take the raw encoded fragment (from an [`ImageBytes`]), and decode them into a list of pixels
 pub fn decode_fragment_pixels(
     pixels: &[u8],
     resolution: GeneralResolution,
 ) -> Result<Vec<u8>, DecodeFragmentBytesError> {
-    if resolution.x % 8 != 0 {
+    if !resolution.x.is_multiple_of(8) {
         return Err(DecodeFragmentBytesError::XResolutionNotMultipleEight(
             resolution.x,
         ));
     }
-    if resolution.y % 8 != 0 {
+    if !resolution.y.is_multiple_of(8) {
         return Err(DecodeFragmentBytesError::YResolutionNotMultipleEight(
             resolution.y,
         ));
@@ -250,28 +326,17 @@ pub fn decode_fragment_pixels(
         return Err(DecodeFragmentBytesError::NoPixel);
     }
     let mut dest = vec![0; resolution.x as usize * resolution.y as usize];
-    let mut chunk_x = 0;
-    let mut chunk_y = 0;
-    let max_chunk_x = resolution.x / 8 - 1;
-    'main: for chunk in pixels.chunks_exact(64) {
-        let mut pixel_for_chunk = chunk.iter();
-        for line in 0..8 {
-            let line_start_offset = (chunk_y as usize * 8 + line as usize) * resolution.x as usize
-                + chunk_x as usize * 8;
-            for row_pair in 0..4 {
-                //no panic : 64 elements are guaranted, and this is looped 8*4=32 times
-                match dest.get_mut(line_start_offset + row_pair * 2 + 1) {
-                    Some(entry) => *entry = *pixel_for_chunk.next().unwrap(),
-                    None => break 'main,
-                }
-                dest[line_start_offset + row_pair * 2] = *pixel_for_chunk.next().unwrap();
+    let chunk_per_line = resolution.x as usize / 8;
+    for (chunk_nb, chunk) in pixels.chunks_exact(64).enumerate() {
+        let chunk_x = chunk_nb % chunk_per_line;
+        let chunk_y = chunk_nb / chunk_per_line;
+        for (line, chunk_line) in chunk.chunks_exact(8).enumerate() {
+            let line_start = (chunk_y * 8 + line) * resolution.x as usize + chunk_x * 8;
+            match dest.get_mut(line_start..line_start + 8) {
+                Some(dest_line) => dest_line.copy_from_slice(&swap_pixel_pairs(chunk_line)),
+                None => return Ok(dest),
             }
         }
-        chunk_x += 1;
-        if chunk_x > max_chunk_x {
-            chunk_x = 0;
-            chunk_y += 1;
-        };
     }
     Ok(dest)
 }
@@ -280,7 +345,7 @@ pub fn encode_fragment_pixels(
     pixels: &[u8],
     resolution: GeneralResolution,
 ) -> anyhow::Result<Vec<u8>> {
-    if resolution.x % 8 != 0 || resolution.y % 8 != 0 {
+    if !resolution.x.is_multiple_of(8) || !resolution.y.is_multiple_of(8) {
         bail!(
             "The image resolution ({:?}) isn't a multiple of 8",
             resolution
@@ -292,33 +357,162 @@ pub fn encode_fragment_pixels(
             resolution
         )
     }
-    // will iterate over each line, placing them at the correct place in the output buffer
-    let mut output_buffer = vec![0; resolution.x as usize * resolution.y as usize];
-    let mut pixel_chunk_line_iter = pixels.chunks_exact(8);
-    for chunk_column in 0..(resolution.y / 8) {
-        for sub_chunk_line in 0..8 {
-            for chunk_row in 0..(resolution.x / 8) {
-                let chunk_row_data = pixel_chunk_line_iter
-                    .next()
-                    .context("The input buffer is too small")?;
-                let number_chunk_ahead: usize =
-                    chunk_column as usize * (resolution.x / 8) as usize + chunk_row as usize;
-                let pos_total = number_chunk_ahead * 64 + sub_chunk_line * 8;
-                if output_buffer.len() < pos_total + 8 {
-                    bail!("The input buffer is too small")
-                };
-                //no panic : chunk_row_data is always of length 8
-                output_buffer[pos_total] = chunk_row_data[1];
-                output_buffer[pos_total + 1] = chunk_row_data[0];
-                output_buffer[pos_total + 2] = chunk_row_data[3];
-                output_buffer[pos_total + 3] = chunk_row_data[2];
-                output_buffer[pos_total + 4] = chunk_row_data[5];
-                output_buffer[pos_total + 5] = chunk_row_data[4];
-                output_buffer[pos_total + 6] = chunk_row_data[7];
-                output_buffer[pos_total + 7] = chunk_row_data[6];
+    let pixel_amount = resolution.x as usize * resolution.y as usize;
+    if pixels.len() < pixel_amount {
+        bail!("The input buffer is too small")
+    }
+    let chunk_per_line = resolution.x as usize / 8;
+    let mut output_buffer = Vec::with_capacity(pixel_amount);
+    for chunk_nb in 0..pixel_amount / 64 {
+        let chunk_x = chunk_nb % chunk_per_line;
+        let chunk_y = chunk_nb / chunk_per_line;
+        for line in 0..8 {
+            let line_start = (chunk_y * 8 + line) * resolution.x as usize + chunk_x * 8;
+            output_buffer.extend_from_slice(&swap_pixel_pairs(&pixels[line_start..line_start + 8]));
+        }
+    }
+
+    Ok(output_buffer)
+}
+
+/// Take the raw encoded fragment of a 256 colors sprite, and decode them into a list of pixels.
+///
+/// The raw data is stored the same way as for 16 colors sprites (one halve of a byte per entry), but each pixel use a full byte, and a 8×8 chunk is thus 128 entries long.
+pub fn decode_fragment_pixels_8bpp(
+    pixels: &[u8],
+    resolution: GeneralResolution,
+) -> Result<Vec<u8>, DecodeFragmentBytesError> {
+    if !resolution.x.is_multiple_of(8) {
+        return Err(DecodeFragmentBytesError::XResolutionNotMultipleEight(
+            resolution.x,
+        ));
+    }
+    if !resolution.y.is_multiple_of(8) {
+        return Err(DecodeFragmentBytesError::YResolutionNotMultipleEight(
+            resolution.y,
+        ));
+    }
+    if resolution.x == 0 || resolution.y == 0 {
+        return Err(DecodeFragmentBytesError::NoPixel);
+    }
+    let mut dest = vec![0; resolution.x as usize * resolution.y as usize];
+    let chunk_per_line = resolution.x as usize / 8;
+    for (chunk_nb, chunk) in pixels.chunks_exact(128).enumerate() {
+        let chunk_x = chunk_nb % chunk_per_line;
+        let chunk_y = chunk_nb / chunk_per_line;
+        for (pixel_nb, pixel) in chunk.chunks_exact(2).enumerate() {
+            let position =
+                (chunk_y * 8 + pixel_nb / 8) * resolution.x as usize + chunk_x * 8 + pixel_nb % 8;
+            match dest.get_mut(position) {
+                Some(entry) => *entry = (pixel[0] << 4) | (pixel[1] & 0x0F),
+                None => return Ok(dest),
             }
         }
     }
+    Ok(dest)
+}
 
+/// The inverse of [`decode_fragment_pixels_8bpp`]
+pub fn encode_fragment_pixels_8bpp(
+    pixels: &[u8],
+    resolution: GeneralResolution,
+) -> anyhow::Result<Vec<u8>> {
+    if !resolution.x.is_multiple_of(8) || !resolution.y.is_multiple_of(8) {
+        bail!(
+            "The image resolution ({:?}) isn't a multiple of 8",
+            resolution
+        );
+    }
+    if resolution.x == 0 || resolution.y == 0 {
+        bail!(
+            "The image with the resolution {:?} have no pixel",
+            resolution
+        )
+    }
+    let pixel_amount = resolution.x as usize * resolution.y as usize;
+    if pixels.len() < pixel_amount {
+        bail!("The input buffer is too small")
+    }
+    let chunk_per_line = resolution.x as usize / 8;
+    let mut output_buffer = Vec::with_capacity(pixel_amount * 2);
+    for chunk_nb in 0..pixel_amount / 64 {
+        let chunk_x = chunk_nb % chunk_per_line;
+        let chunk_y = chunk_nb / chunk_per_line;
+        for line in 0..8 {
+            let line_start = (chunk_y * 8 + line) * resolution.x as usize + chunk_x * 8;
+            split_nibbles(&pixels[line_start..line_start + 8], &mut output_buffer);
+        }
+    }
     Ok(output_buffer)
 }
+
+impl FragmentBytes {
+    /// Encode the pixels with [`encode_fragment_pixels_8bpp`] if `is_256_color` is set, [`encode_fragment_pixels`] otherwise.
+    pub fn new_from_pixels(
+        pixels: &[u8],
+        resolution: GeneralResolution,
+        is_256_color: bool,
+        z_index: u32,
+    ) -> anyhow::Result<Self> {
+        let mixed_pixels = if is_256_color {
+            encode_fragment_pixels_8bpp(pixels, resolution)?
+        } else {
+            encode_fragment_pixels(pixels, resolution)?
+        };
+        Ok(Self {
+            mixed_pixels,
+            z_index,
+        })
+    }
+
+    /// Decode the pixels of this [`FragmentBytes`], with [`decode_fragment_pixels_8bpp`] if `is_256_color` is set, [`decode_fragment_pixels`] otherwise.
+    pub fn decode_pixels(
+        &self,
+        resolution: GeneralResolution,
+        is_256_color: bool,
+    ) -> Result<Vec<u8>, DecodeFragmentBytesError> {
+        if is_256_color {
+            decode_fragment_pixels_8bpp(&self.mixed_pixels, resolution)
+        } else {
+            decode_fragment_pixels(&self.mixed_pixels, resolution)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        decode_fragment_pixels, decode_fragment_pixels_8bpp, encode_fragment_pixels,
+        encode_fragment_pixels_8bpp, GeneralResolution,
+    };
+
+    #[test]
+    fn test_4bpp_chunk_order() {
+        let resolution = GeneralResolution::new(16, 8);
+        let pixels: Vec<u8> = (0..128).map(|pixel| (pixel % 16) as u8).collect();
+        let encoded = encode_fragment_pixels(&pixels, resolution.clone()).unwrap();
+        // each pair of pixel is swapped, and the first chunk contain the 8 first pixels of each line
+        assert_eq!(&encoded[..4], &[1, 0, 3, 2]);
+        assert_eq!(&encoded[8..10], &[1, 0]);
+        assert_eq!(&encoded[64..66], &[9, 8]);
+        assert_eq!(
+            decode_fragment_pixels(&encoded, resolution).unwrap(),
+            pixels
+        );
+        assert!(encode_fragment_pixels(&pixels[..127], GeneralResolution::new(16, 8)).is_err());
+    }
+
+    #[test]
+    fn test_8bpp_roundtrip() {
+        let pixels: Vec<u8> = (0..=255).collect();
+        let resolution = GeneralResolution::new(16, 16);
+        let encoded = encode_fragment_pixels_8bpp(&pixels, resolution.clone()).unwrap();
+        assert_eq!(encoded.len(), 512);
+        // the first chunk contain the top-left 8×8 pixels
+        assert_eq!(&encoded[16..18], &[1, 0]);
+        assert_eq!(
+            decode_fragment_pixels_8bpp(&encoded, resolution).unwrap(),
+            pixels
+        );
+    }
+}
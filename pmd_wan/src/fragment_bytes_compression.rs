@@ -1,20 +1,292 @@
-use std::io::{Seek, SeekFrom, Write};
+use std::io::{Seek, Write};
 
 use byteorder::WriteBytesExt;
 
-use crate::{fragment_bytes::FragmentBytesAssemblyEntry, FragmentBytes, WanError};
+use crate::{
+    fragment_bytes::FragmentBytesAssemblyEntry, EncodedSizeEstimate, FragmentBytes, WanError,
+    WanImage,
+};
+
+/// The maximum number of pixels a single [`CompressedRun`] can contain, as the number of bytes of an assembly entry is stored on 16 bits
+pub const MAX_RUN_PIXEL_AMOUNT: u32 = u16::MAX as u32 * 2;
+
+/// A part of a [`FragmentBytes`], as chosen by a [`FragmentCompressor`]. Each run is written as an assembly entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressedRun {
+    /// The number of pixels in this run. Should be even, and at most [`MAX_RUN_PIXEL_AMOUNT`].
+    pub pixel_amount: u32,
+    /// true if all the pixels of this run are transparent, so they are not stored in the file (only their number is)
+    pub is_transparent: bool,
+}
+
+/// A strategy to split the pixels of a [`FragmentBytes`] into assembly entries when writing a wan file (see [`crate::WanImage::write_to_vec_with_compressor`])
+///
+/// Transparent runs take no space in the file other than their assembly entry, but the game may have limitations on their placement.
+/// [`CompressionMethod`] implement the strategies used by the base game.
+pub trait FragmentCompressor {
+    /// Split the pixels into runs. The runs should cover all the pixels in order, and transparent runs should only contain transparent pixels (color 0).
+    fn split_in_runs(
+        &self,
+        fragment_bytes: &FragmentBytes,
+        pixel_list: &[u8],
+    ) -> Vec<CompressedRun>;
+}
+
+/// The size (in bytes) of an assembly entry
+pub const ASSEMBLY_ENTRY_SIZE: usize = 12;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CompressionMethod {
     /// The compression used to compress creatures in base game
     CompressionMethodOriginal,
     /// No compression, used for other sprites in base game
     NoCompression,
-    /* /// An original optimised compression algorithm (TODO: I think it is unfinished, need testing, or maybe just fuzzing)
+    /// An original optimised compression algorithm: transparent runs may start on every pixel that is a multiple of `multiple_of_value`, if there are at least `min_transparent_to_compress` transparent pixels.
+    ///
+    /// `multiple_of_value` should be a non-zero even number, otherwise no compression is used. It is unknown if every combination is correctly displayed by the game.
     CompressionMethodOptimised {
         multiple_of_value: usize,
         min_transparent_to_compress: usize,
-    },*/
+    },
+    /// Try [`CompressionMethod::CompressionMethodOriginal`], [`CompressionMethod::NoCompression`] and [`CompressionMethod::CompressionMethodOptimised`] with every combination of the given parameters, and use the smallest for each [`FragmentBytes`].
+    ///
+    /// See [`crate::WanImage::smallest_compression_report`] to know what has been chosen, and the saved space.
+    Smallest {
+        multiple_of_values: Vec<usize>,
+        min_transparent_to_compress_values: Vec<usize>,
+    },
+}
+
+impl CompressionMethod {
+    /// A [`CompressionMethod::Smallest`] with a parameter grid suitable for most sprites
+    pub fn smallest() -> Self {
+        Self::Smallest {
+            multiple_of_values: vec![2, 8, 16, 64],
+            min_transparent_to_compress_values: vec![32, 64, 128],
+        }
+    }
+
+    /// Return the [`CompressionMethod`] (other than [`CompressionMethod::Smallest`]) that would be used for the given pixels, with the runs it produce
+    pub fn choose_for(&self, pixel_list: &[u8]) -> (CompressionMethod, Vec<CompressedRun>) {
+        match self {
+            Self::Smallest {
+                multiple_of_values,
+                min_transparent_to_compress_values,
+            } => {
+                let mut candidates = vec![
+                    CompressionMethod::CompressionMethodOriginal,
+                    CompressionMethod::NoCompression,
+                ];
+                for multiple_of_value in multiple_of_values {
+                    for min_transparent_to_compress in min_transparent_to_compress_values {
+                        candidates.push(CompressionMethod::CompressionMethodOptimised {
+                            multiple_of_value: *multiple_of_value,
+                            min_transparent_to_compress: *min_transparent_to_compress,
+                        });
+                    }
+                }
+                let mut best: Option<(usize, CompressionMethod, Vec<CompressedRun>)> = None;
+                for candidate in candidates {
+                    let (candidate, runs) = candidate.choose_for(pixel_list);
+                    let size = compressed_size(&runs);
+                    if best.as_ref().map(|(best_size, _, _)| size < *best_size) != Some(false) {
+                        best = Some((size, candidate, runs));
+                    }
+                }
+                // there is always at least two candidates
+                let (_, method, runs) = best.unwrap();
+                (method, runs)
+            }
+            Self::CompressionMethodOriginal if !pixel_list.len().is_multiple_of(64) => {
+                Self::NoCompression.choose_for(pixel_list)
+            }
+            Self::CompressionMethodOriginal => {
+                // group the consecutive 8×8 tiles that are either all transparent or not
+                let mut runs: Vec<CompressedRun> = Vec::new();
+                for chunk in pixel_list.chunks_exact(64) {
+                    push_run(&mut runs, 64, chunk.iter().all(|pixel| *pixel == 0));
+                }
+                (self.clone(), runs)
+            }
+            Self::CompressionMethodOptimised {
+                multiple_of_value,
+                min_transparent_to_compress,
+            } => {
+                let multiple_of_value = *multiple_of_value;
+                if multiple_of_value == 0 || !multiple_of_value.is_multiple_of(2) {
+                    return Self::NoCompression.choose_for(pixel_list);
+                }
+                let mut runs: Vec<CompressedRun> = Vec::new();
+                let mut pixel_id = 0;
+                while pixel_id < pixel_list.len() {
+                    if pixel_id.is_multiple_of(multiple_of_value)
+                        && pixel_id + min_transparent_to_compress <= pixel_list.len()
+                        && pixel_list[pixel_id..pixel_id + min_transparent_to_compress]
+                            .iter()
+                            .all(|pixel| *pixel == 0)
+                    {
+                        let mut end = pixel_list[pixel_id..]
+                            .iter()
+                            .position(|pixel| *pixel != 0)
+                            .map(|position| pixel_id + position)
+                            .unwrap_or(pixel_list.len());
+                        if end != pixel_list.len() {
+                            end -= end % multiple_of_value;
+                        }
+                        if end > pixel_id {
+                            push_run(&mut runs, (end - pixel_id) as u32, true);
+                            pixel_id = end;
+                            continue;
+                        }
+                    }
+                    let end = (pixel_id + 2).min(pixel_list.len());
+                    push_run(&mut runs, (end - pixel_id) as u32, false);
+                    pixel_id = end;
+                }
+                (self.clone(), runs)
+            }
+            Self::NoCompression => (
+                Self::NoCompression,
+                vec![CompressedRun {
+                    pixel_amount: pixel_list.len() as u32,
+                    is_transparent: false,
+                }],
+            ),
+        }
+    }
+}
+
+/// Add the pixels to the last run if it is of the same kind, or start a new run
+fn push_run(runs: &mut Vec<CompressedRun>, pixel_amount: u32, is_transparent: bool) {
+    match runs.last_mut() {
+        Some(run) if run.is_transparent == is_transparent => run.pixel_amount += pixel_amount,
+        _ => runs.push(CompressedRun {
+            pixel_amount,
+            is_transparent,
+        }),
+    }
+}
+
+/// The size (in bytes) the runs take in the written file: the non-transparent pixels and the assembly table (including its null terminating entry).
+/// The SIR0 pointers of the assembly entries aren't counted.
+pub fn compressed_size(runs: &[CompressedRun]) -> usize {
+    runs.iter()
+        .filter(|run| !run.is_transparent)
+        .map(|run| run.pixel_amount as usize / 2)
+        .sum::<usize>()
+        + (runs.len() + 1) * ASSEMBLY_ENTRY_SIZE
+}
+
+impl FragmentCompressor for CompressionMethod {
+    fn split_in_runs(
+        &self,
+        _fragment_bytes: &FragmentBytes,
+        pixel_list: &[u8],
+    ) -> Vec<CompressedRun> {
+        self.choose_for(pixel_list).1
+    }
+}
+
+/// What [`CompressionMethod::Smallest`] (or any other [`CompressionMethod`]) choose for a [`crate::WanImage`], as returned by [`crate::WanImage::smallest_compression_report`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmallestCompressionReport {
+    /// The [`CompressionMethod`] used for each [`FragmentBytes`]
+    pub chosen: Vec<CompressionMethod>,
+    /// The size of the compressed [`FragmentBytes`] (as computed by [`compressed_size`]) with the default compression of the [`crate::SpriteType`]
+    pub default_size: usize,
+    /// The size of the compressed [`FragmentBytes`] with the chosen [`CompressionMethod`]s
+    pub chosen_size: usize,
+}
+
+impl SmallestCompressionReport {
+    /// The number of bytes saved compared to the default compression. Negative if the file is bigger.
+    pub fn saved_bytes(&self) -> isize {
+        self.default_size as isize - self.chosen_size as isize
+    }
+}
+
+impl WanImage {
+    /// Compute which [`CompressionMethod`] the given method use for each [`FragmentBytes`], and the space saved compared to the default compression of the [`crate::SpriteType`]
+    pub fn smallest_compression_report(
+        &self,
+        compression: &CompressionMethod,
+    ) -> SmallestCompressionReport {
+        let default_compression = self.sprite_type.default_compression_method();
+        let mut report = SmallestCompressionReport {
+            chosen: Vec::with_capacity(self.fragment_bytes_store.len()),
+            default_size: 0,
+            chosen_size: 0,
+        };
+        for fragment_bytes in &self.fragment_bytes_store.fragment_bytes {
+            let (chosen, runs) = compression.choose_for(&fragment_bytes.mixed_pixels);
+            report.chosen_size += compressed_size(&runs);
+            report.default_size += compressed_size(
+                &default_compression
+                    .choose_for(&fragment_bytes.mixed_pixels)
+                    .1,
+            );
+            report.chosen.push(chosen);
+        }
+        report
+    }
+}
+
+/// How a single [`FragmentBytes`] was compressed, as part of a [`CompressionReport`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FragmentBytesCompressionStats {
+    /// The number of pixels of the [`FragmentBytes`]
+    pub pixel_amount: usize,
+    /// The number of bytes written for this [`FragmentBytes`], that is its pixels and its assembly table
+    pub bytes_written: usize,
+    /// The number of assembly entries, without the null terminating one
+    pub assembly_entries: usize,
+    /// The number of transparent runs, whose pixels aren't stored
+    pub transparent_runs: usize,
+    /// The number of pixels in transparent runs
+    pub transparent_pixels: usize,
+}
+
+/// Statistics about a written wan file, as returned by [`WanImage::write_to_vec_with_report`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CompressionReport {
+    /// The statistics of each [`FragmentBytes`], in order
+    pub fragment_bytes: Vec<FragmentBytesCompressionStats>,
+    /// The size of each section of the written file
+    pub sections: EncodedSizeEstimate,
+}
+
+impl CompressionReport {
+    /// The size of the written file
+    pub fn file_size(&self) -> usize {
+        self.sections.total()
+    }
+
+    /// true if the written file is bigger than the given size, like the one of the file it replace in the ROM
+    pub fn exceeds(&self, allocation: usize) -> bool {
+        self.file_size() > allocation
+    }
+
+    pub fn total_assembly_entries(&self) -> usize {
+        self.fragment_bytes
+            .iter()
+            .map(|stats| stats.assembly_entries)
+            .sum()
+    }
+
+    pub fn total_transparent_runs(&self) -> usize {
+        self.fragment_bytes
+            .iter()
+            .map(|stats| stats.transparent_runs)
+            .sum()
+    }
+
+    pub fn total_transparent_pixels(&self) -> usize {
+        self.fragment_bytes
+            .iter()
+            .map(|stats| stats.transparent_pixels)
+            .sum()
+    }
 }
 
 impl CompressionMethod {
@@ -24,208 +296,272 @@ impl CompressionMethod {
         pixel_list: &[u8],
         file: &mut F,
     ) -> Result<Vec<FragmentBytesAssemblyEntry>, WanError> {
-        let compression = if pixel_list.len() % 64 != 0 {
-            CompressionMethod::NoCompression
+        compress_with(self, fragment_bytes, pixel_list, file)
+    }
+}
+
+/// Write the pixels that aren't in a transparent run, as split by the [`FragmentCompressor`], and return the corresponding assembly entries.
+///
+/// The runs are checked to be valid before anything is written.
+pub(crate) fn compress_with<F: Write + Seek>(
+    compressor: &dyn FragmentCompressor,
+    fragment_bytes: &FragmentBytes,
+    pixel_list: &[u8],
+    file: &mut F,
+) -> Result<Vec<FragmentBytesAssemblyEntry>, WanError> {
+    if pixel_list.is_empty() {
+        return Err(WanError::EmptyFragmentBytes);
+    }
+
+    let runs = compressor.split_in_runs(fragment_bytes, pixel_list);
+    let mut run_start = 0;
+    for run in &runs {
+        let run_end = run_start + run.pixel_amount as usize;
+        if run.pixel_amount == 0
+            || !run.pixel_amount.is_multiple_of(2)
+            || run.pixel_amount > MAX_RUN_PIXEL_AMOUNT
+            || run_end > pixel_list.len()
+            || (run.is_transparent && pixel_list[run_start..run_end].iter().any(|p| *p != 0))
+        {
+            return Err(WanError::InvalidCompressedRun(run_start));
+        }
+        run_start = run_end;
+    }
+    if run_start != pixel_list.len() {
+        return Err(WanError::InvalidCompressedRun(run_start));
+    }
+
+    let mut assembly_table: Vec<FragmentBytesAssemblyEntry> = Vec::with_capacity(runs.len());
+    let mut run_start = 0;
+    for run in runs {
+        let run_end = run_start + run.pixel_amount as usize;
+        let pixel_src = if run.is_transparent {
+            0
         } else {
-            self.clone()
+            let start_offset = file.stream_position()?;
+            for pixels in pixel_list[run_start..run_end].chunks_exact(2) {
+                file.write_u8((pixels[0] << 4) + pixels[1])?;
+            }
+            start_offset
         };
+        assembly_table.push(FragmentBytesAssemblyEntry {
+            pixel_src,
+            pixel_amount: run.pixel_amount,
+            byte_amount: (run.pixel_amount / 2) as u16,
+            _z_index: fragment_bytes.z_index,
+        });
+        run_start = run_end;
+    }
+    Ok(assembly_table)
+}
 
-        if pixel_list.is_empty() {
-            return Err(WanError::EmptyFragmentBytes);
-        }
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{animation_frame, insert_filled_frame, test_wan_image},
+        Animation, CompressedRun, CompressionMethod, FragmentBytes, FragmentBytesCompressionStats,
+        FragmentCompressor, SpriteType, WanImage, ASSEMBLY_ENTRY_SIZE,
+    };
 
-        let mut assembly_table: Vec<FragmentBytesAssemblyEntry> = vec![];
+    /// Store every line of 8 pixels in its own run
+    struct LineCompressor;
 
-        match compression {
-            Self::CompressionMethodOriginal => {
-                enum ActualEntry {
-                    Null(u32, u32),      //lenght (pixel), z_index
-                    Some(u64, u32, u32), // initial_offset, lenght (pixel), z_index
-                }
+    impl FragmentCompressor for LineCompressor {
+        fn split_in_runs(
+            &self,
+            _fragment_bytes: &FragmentBytes,
+            pixel_list: &[u8],
+        ) -> Vec<CompressedRun> {
+            pixel_list
+                .chunks(8)
+                .map(|line| CompressedRun {
+                    pixel_amount: line.len() as u32,
+                    is_transparent: line.iter().all(|pixel| *pixel == 0),
+                })
+                .collect()
+        }
+    }
 
-                impl ActualEntry {
-                    fn new(is_all_black: bool, start_offset: u64, z_index: u32) -> ActualEntry {
-                        if is_all_black {
-                            ActualEntry::Null(64, z_index)
-                        } else {
-                            ActualEntry::Some(start_offset, 64, z_index)
-                        }
-                    }
+    /// Pretend everything is transparent
+    struct InvalidCompressor;
 
-                    fn to_assembly(&self) -> FragmentBytesAssemblyEntry {
-                        match self {
-                            ActualEntry::Null(lenght, z_index) => FragmentBytesAssemblyEntry {
-                                pixel_src: 0,
-                                pixel_amount: *lenght,
-                                byte_amount: (*lenght / 2) as u16, //NOTE: lenght is always <= than 64x64
-                                _z_index: *z_index,
-                            },
-                            ActualEntry::Some(initial_offset, lenght, z_index) => {
-                                FragmentBytesAssemblyEntry {
-                                    pixel_src: *initial_offset,
-                                    pixel_amount: *lenght,
-                                    byte_amount: (*lenght / 2) as u16,
-                                    _z_index: *z_index,
-                                }
-                            }
-                        }
-                    }
+    impl FragmentCompressor for InvalidCompressor {
+        fn split_in_runs(
+            &self,
+            _fragment_bytes: &FragmentBytes,
+            pixel_list: &[u8],
+        ) -> Vec<CompressedRun> {
+            vec![CompressedRun {
+                pixel_amount: pixel_list.len() as u32,
+                is_transparent: true,
+            }]
+        }
+    }
 
-                    fn advance(&self, lenght: u32) -> ActualEntry {
-                        match self {
-                            ActualEntry::Null(l, z) => ActualEntry::Null(*l + lenght, *z),
-                            ActualEntry::Some(offset, l, z) => {
-                                ActualEntry::Some(*offset, *l + lenght, *z)
-                            }
-                        }
-                    }
-                }
+    #[test]
+    fn test_original_runs() {
+        let fragment_bytes = FragmentBytes {
+            mixed_pixels: Vec::new(),
+            z_index: 0,
+        };
+        let mut pixels = vec![0; 64 * 4];
+        pixels[64] = 1;
+        pixels[64 * 2] = 1;
+        assert_eq!(
+            CompressionMethod::CompressionMethodOriginal.split_in_runs(&fragment_bytes, &pixels),
+            vec![
+                CompressedRun {
+                    pixel_amount: 64,
+                    is_transparent: true
+                },
+                CompressedRun {
+                    pixel_amount: 128,
+                    is_transparent: false
+                },
+                CompressedRun {
+                    pixel_amount: 64,
+                    is_transparent: true
+                },
+            ]
+        );
+        assert_eq!(
+            CompressionMethod::NoCompression.split_in_runs(&fragment_bytes, &pixels),
+            vec![CompressedRun {
+                pixel_amount: 256,
+                is_transparent: false
+            }]
+        );
+    }
 
-                let mut actual_entry: Option<ActualEntry> = None;
-
-                for (loop_nb, _chunk) in pixel_list.chunks_exact(64).enumerate() {
-                    let mut this_area = vec![];
-                    let mut is_all_black = true;
-                    for l in 0..64 {
-                        let actual_pixel = pixel_list[loop_nb * 64 + l];
-                        this_area.push(actual_pixel);
-                        if actual_pixel != 0 {
-                            is_all_black = false;
-                        };
-                    }
+    #[test]
+    fn test_optimised_runs() {
+        let mut pixels = vec![0; 128];
+        pixels[0] = 1;
+        pixels[101] = 1;
+        let (_, runs) = CompressionMethod::CompressionMethodOptimised {
+            multiple_of_value: 16,
+            min_transparent_to_compress: 32,
+        }
+        .choose_for(&pixels);
+        assert_eq!(
+            runs,
+            vec![
+                CompressedRun {
+                    pixel_amount: 16,
+                    is_transparent: false
+                },
+                CompressedRun {
+                    pixel_amount: 80,
+                    is_transparent: true
+                },
+                CompressedRun {
+                    pixel_amount: 32,
+                    is_transparent: false
+                },
+            ]
+        );
+    }
 
-                    let pos_before_area = file.seek(SeekFrom::Current(0))?;
-                    if !is_all_black {
-                        for byte_id in 0..32 {
-                            file.write_u8(
-                                (this_area[byte_id * 2] << 4) + this_area[byte_id * 2 + 1],
-                            )?;
-                        }
-                    }
+    #[test]
+    fn test_smallest_compression() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        let mut pixels = vec![0; 64 * 64];
+        pixels[0] = 1;
+        pixels[64 * 64 - 1] = 1;
+        insert_frame_in_wanimage(pixels, 64, 64, &mut wanimage, 0).unwrap();
+        wanimage.animation_store.anim_groups = vec![vec![Animation::default()]];
 
-                    let need_to_create_new_entry = match &actual_entry {
-                        Some(ActualEntry::Null(_, _)) => !is_all_black,
-                        Some(ActualEntry::Some(_, _, _)) => is_all_black,
-                        None => true,
-                    };
+        let smallest = CompressionMethod::smallest();
+        let report = wanimage.smallest_compression_report(&smallest);
+        assert!(matches!(
+            report.chosen[0],
+            CompressionMethod::CompressionMethodOptimised { .. }
+        ));
+        assert!(report.saved_bytes() > 0);
 
-                    actual_entry = if need_to_create_new_entry {
-                        if let Some(entry) = actual_entry {
-                            assembly_table.push(entry.to_assembly())
-                        }
+        let bytes = wanimage.write_to_vec_with_compressor(&smallest).unwrap();
+        assert!(bytes.len() < wanimage.write_to_vec().unwrap().len());
+        let decoded = WanImage::decode_wan_from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.fragment_bytes_store, wanimage.fragment_bytes_store);
+    }
 
-                        Some(ActualEntry::new(
-                            is_all_black,
-                            pos_before_area,
-                            fragment_bytes.z_index,
-                        ))
-                    } else {
-                        // no panic : need_to_create_new_entry is false if actual_entry is none
-                        Some(actual_entry.unwrap().advance(64))
-                    }
-                }
-                assembly_table.push(actual_entry.unwrap().to_assembly())
+    #[test]
+    fn test_compression_report() {
+        let mut wanimage = test_wan_image(SpriteType::Chara);
+        insert_filled_frame(&mut wanimage, 8, 8);
+        let mut mixed_pixels = vec![0; 256];
+        mixed_pixels[0] = 1;
+        wanimage
+            .fragment_bytes_store
+            .fragment_bytes
+            .push(FragmentBytes {
+                mixed_pixels,
+                z_index: 0,
+            });
+        for frame in &mut wanimage.frame_store.frames {
+            frame.frame_offset = Some(crate::FrameOffset {
+                head: (0, 0),
+                hand_left: (0, 0),
+                hand_right: (0, 0),
+                center: (0, 0),
+            });
+        }
+        wanimage.animation_store.anim_groups = vec![vec![Animation::default()]];
+
+        let (bytes, report) = wanimage
+            .write_to_vec_with_report(&CompressionMethod::CompressionMethodOriginal)
+            .unwrap();
+        assert_eq!(report.file_size(), bytes.len());
+        assert!(report.exceeds(bytes.len() - 1));
+        assert!(!report.exceeds(bytes.len()));
+        assert_eq!(
+            report.fragment_bytes.len(),
+            wanimage.fragment_bytes_store.len()
+        );
+        // a single 8×8 tile with content, and the 3 others transparent
+        assert_eq!(
+            report.fragment_bytes[1],
+            FragmentBytesCompressionStats {
+                pixel_amount: 256,
+                bytes_written: 32 + 3 * ASSEMBLY_ENTRY_SIZE,
+                assembly_entries: 2,
+                transparent_runs: 1,
+                transparent_pixels: 192,
             }
-            /*Self::CompressionMethodOptimised {
-                multiple_of_value,
-                min_transparent_to_compress,
-            } => {
-                let mut number_of_byte_to_include: u16 = 0;
-                let mut byte_include_start = file.seek(SeekFrom::Current(0))?;
+        );
+        assert_eq!(
+            report
+                .fragment_bytes
+                .iter()
+                .map(|stats| stats.bytes_written)
+                .sum::<usize>(),
+            report.sections.fragment_bytes
+        );
+        // the first fragment bytes is fully opaque
+        assert_eq!(report.total_transparent_pixels(), 192);
+    }
 
-                let mut pixel_id = 0;
-                loop {
-                    debug_assert!(pixel_id % 2 == 0);
-                    let mut should_create_new_transparent_entry = false;
+    #[test]
+    fn test_custom_compressor() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        let mut pixels = vec![0; 64];
+        pixels[20] = 1;
+        insert_frame_in_wanimage(pixels, 8, 8, &mut wanimage, 0).unwrap();
+        wanimage.animation_store.anim_groups = vec![vec![Animation {
+            frames: vec![animation_frame(0, 1)],
+        }]];
 
-                    if (pixel_id % multiple_of_value == 0)
-                        && (pixel_id + min_transparent_to_compress < pixel_list.len())
-                    {
-                        let mut encontered_non_transparent = false;
-                        for l in 0..min_transparent_to_compress {
-                            if pixel_list[pixel_id + l] != 0 {
-                                encontered_non_transparent = true;
-                                break;
-                            };
-                        }
-                        if !encontered_non_transparent {
-                            should_create_new_transparent_entry = true;
-                        };
-                    };
-
-                    if should_create_new_transparent_entry {
-                        //push the actual content
-                        if number_of_byte_to_include > 0 {
-                            assembly_table.push(ImageAssemblyEntry {
-                                pixel_src: byte_include_start,
-                                pixel_amount: number_of_byte_to_include as u32 * 2,
-                                byte_amount: number_of_byte_to_include,
-                                _z_index: image.z_index,
-                            });
-                            number_of_byte_to_include = 0;
-                            byte_include_start = file.seek(SeekFrom::Current(0))?;
-                        };
-                        //create new entry for transparent stuff
-                        //count the number of transparent tile
-                        let mut transparent_tile_nb: u32 = 0; //TODO: somehow guarantee it never gets bigger than (16^2)*2
-                        loop {
-                            if pixel_id >= pixel_list.len() {
-                                break;
-                            };
-                            if pixel_list[pixel_id] == 0 {
-                                transparent_tile_nb += 1;
-                                pixel_id += 1;
-                            } else {
-                                break;
-                            };
-                        }
-                        if pixel_id % multiple_of_value != 0 {
-                            transparent_tile_nb -= (pixel_id % multiple_of_value) as u32;
-                            pixel_id -= pixel_id % multiple_of_value;
-                        };
-                        assembly_table.push(ImageAssemblyEntry {
-                            pixel_src: 0,
-                            pixel_amount: transparent_tile_nb,
-                            byte_amount: (transparent_tile_nb / 2) as u16, //TODO: take care of the tileset lenght
-                            _z_index: image.z_index,
-                        });
+        let bytes = wanimage
+            .write_to_vec_with_compressor(&LineCompressor)
+            .unwrap();
+        assert_ne!(bytes, wanimage.write_to_vec().unwrap());
+        let decoded = WanImage::decode_wan_from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.fragment_bytes_store, wanimage.fragment_bytes_store);
+        assert_eq!(decoded.frame_store, wanimage.frame_store);
 
-                        continue;
-                    };
-
-                    if pixel_id >= pixel_list.len() {
-                        break;
-                    };
-                    debug_assert!(pixel_list[pixel_id] < 16);
-                    debug_assert!(pixel_list[pixel_id + 1] < 16);
-                    file.write_u8(((pixel_list[pixel_id] << 4) + pixel_list[pixel_id + 1]) as u8)?;
-                    pixel_id += 2;
-                    number_of_byte_to_include += 1;
-                }
-                if number_of_byte_to_include > 0 {
-                    assembly_table.push(ImageAssemblyEntry {
-                        pixel_src: byte_include_start,
-                        pixel_amount: number_of_byte_to_include as u32 * 2,
-                        byte_amount: number_of_byte_to_include,
-                        _z_index: image.z_index,
-                    });
-                };
-            }*/
-            Self::NoCompression => {
-                let mut byte_len = 0;
-                let start_offset = file.seek(SeekFrom::Current(0))?;
-                for pixels in pixel_list.chunks_exact(2) {
-                    file.write_u8((pixels[0] << 4) + pixels[1])?;
-                    byte_len += 1;
-                }
-                assembly_table.push(FragmentBytesAssemblyEntry {
-                    pixel_src: start_offset,
-                    pixel_amount: byte_len * 2,
-                    byte_amount: byte_len as u16,
-                    _z_index: fragment_bytes.z_index,
-                })
-            }
-        };
-        Ok(assembly_table)
+        assert!(wanimage
+            .write_to_vec_with_compressor(&InvalidCompressor)
+            .is_err());
     }
 }
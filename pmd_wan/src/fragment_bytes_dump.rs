@@ -0,0 +1,298 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+};
+
+use thiserror::Error;
+
+use crate::{fragment_bytes::split_nibbles, FragmentBytes, OamShape, WanImage};
+
+const MANIFEST_HEADER: &str = "pmd_wan fragment bytes dump v1";
+
+#[derive(Error, Debug)]
+pub enum FragmentBytesDumpError {
+    #[error("an input/output error happened")]
+    IOError(#[from] io::Error),
+    #[error("The manifest doesn't start with the expected header (found {0:?})")]
+    InvalidManifestHeader(String),
+    #[error("The line {0} of the manifest is invalid")]
+    InvalidManifestLine(usize),
+    #[error("There is no dump for the FragmentBytes {0}")]
+    MissingDump(usize),
+    #[error("The dump of the FragmentBytes {index} has a checksum of {found:08x}, but the manifest expect {expected:08x}")]
+    ChecksumMismatch {
+        index: usize,
+        expected: u32,
+        found: u32,
+    },
+    #[error("The dump of the FragmentBytes {index} contain {found} pixels, but the manifest expect {expected}")]
+    SizeMismatch {
+        index: usize,
+        expected: u32,
+        found: u32,
+    },
+    #[error("The FragmentBytes {index} was exported for the shape {expected:?}, but a fragment use it with the shape {found:?}")]
+    ShapeMismatch {
+        index: usize,
+        expected: OamShape,
+        found: OamShape,
+    },
+    #[error("The FragmentBytes {0} can't be inserted, as it would leave a hole in the FragmentBytes list")]
+    IndexOutOfRange(usize),
+}
+
+/// The metadata of a single [`FragmentBytes`] in a [`FragmentBytesDumpManifest`]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FragmentBytesDumpEntry {
+    pub index: usize,
+    pub pixel_amount: u32,
+    pub z_index: u32,
+    /// The shape of the first [`crate::Fragment`] that used this [`FragmentBytes`] when it was exported, if any
+    pub shape: Option<OamShape>,
+    /// CRC-32 of the raw dump
+    pub checksum: u32,
+}
+
+/// The list of exported [`FragmentBytes`], used to check the raw dumps are valid when imported back.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct FragmentBytesDumpManifest {
+    pub entries: Vec<FragmentBytesDumpEntry>,
+}
+
+impl FragmentBytesDumpManifest {
+    /// Write the manifest in a simple line-based text format
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut result = String::new();
+        writeln!(result, "{}", MANIFEST_HEADER).unwrap();
+        for entry in &self.entries {
+            let shape = match entry.shape {
+                Some(shape) => format!("{},{}", shape.shape_indice(), shape.size_indice()),
+                None => "-".to_string(),
+            };
+            writeln!(
+                result,
+                "{} {} {} {} {:08x}",
+                entry.index, entry.pixel_amount, entry.z_index, shape, entry.checksum
+            )
+            .unwrap();
+        }
+        writer.write_all(result.as_bytes())
+    }
+
+    pub fn parse<R: Read>(reader: &mut R) -> Result<Self, FragmentBytesDumpError> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        let mut lines = content.lines();
+        let header = lines.next().unwrap_or_default();
+        if header != MANIFEST_HEADER {
+            return Err(FragmentBytesDumpError::InvalidManifestHeader(
+                header.to_string(),
+            ));
+        }
+        let mut entries = Vec::new();
+        for (line_nb, line) in lines.enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid_line = || FragmentBytesDumpError::InvalidManifestLine(line_nb + 2);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() != 5 {
+                return Err(invalid_line());
+            }
+            let shape = if parts[3] == "-" {
+                None
+            } else {
+                let (shape_indice, size_indice) =
+                    parts[3].split_once(',').ok_or_else(invalid_line)?;
+                Some(
+                    OamShape::new(
+                        shape_indice.parse().map_err(|_| invalid_line())?,
+                        size_indice.parse().map_err(|_| invalid_line())?,
+                    )
+                    .ok_or_else(invalid_line)?,
+                )
+            };
+            entries.push(FragmentBytesDumpEntry {
+                index: parts[0].parse().map_err(|_| invalid_line())?,
+                pixel_amount: parts[1].parse().map_err(|_| invalid_line())?,
+                z_index: parts[2].parse().map_err(|_| invalid_line())?,
+                shape,
+                checksum: u32::from_str_radix(parts[4], 16).map_err(|_| invalid_line())?,
+            });
+        }
+        Ok(Self { entries })
+    }
+}
+
+/// Compute the CRC-32 (as used by zip and png) of the given bytes
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+impl FragmentBytes {
+    /// Return the pixels packed two per byte, as they are stored in a wan file
+    pub fn to_raw_bytes(&self) -> Vec<u8> {
+        self.mixed_pixels
+            .chunks(2)
+            .map(|pair| (pair[0] << 4) + pair.get(1).copied().unwrap_or(0))
+            .collect()
+    }
+
+    /// The inverse of [`FragmentBytes::to_raw_bytes`]
+    pub fn new_from_raw_bytes(raw: &[u8], z_index: u32) -> Self {
+        let mut mixed_pixels = Vec::with_capacity(raw.len() * 2);
+        split_nibbles(raw, &mut mixed_pixels);
+        Self {
+            mixed_pixels,
+            z_index,
+        }
+    }
+}
+
+impl WanImage {
+    /// Export all the [`FragmentBytes`] as raw dumps (see [`FragmentBytes::to_raw_bytes`]), with a manifest that allow to check them when they are imported back with [`WanImage::import_fragment_bytes_dump`].
+    pub fn export_fragment_bytes_dump(&self) -> (FragmentBytesDumpManifest, Vec<Vec<u8>>) {
+        let mut manifest = FragmentBytesDumpManifest::default();
+        let mut dumps = Vec::new();
+        for (index, fragment_bytes) in self.fragment_bytes_store.fragment_bytes.iter().enumerate() {
+            let raw = fragment_bytes.to_raw_bytes();
+            manifest.entries.push(FragmentBytesDumpEntry {
+                index,
+                pixel_amount: fragment_bytes.mixed_pixels.len() as u32,
+                z_index: fragment_bytes.z_index,
+                shape: self.find_shape_of_fragment_bytes(index),
+                checksum: crc32(&raw),
+            });
+            dumps.push(raw);
+        }
+        (manifest, dumps)
+    }
+
+    /// Import raw dumps previously exported with [`WanImage::export_fragment_bytes_dump`], without re-encoding them.
+    /// `dumps` are in the same order as the entries of the manifest.
+    ///
+    /// Every dump is checked against its checksum, size and shape before anything is modified, so the [`WanImage`] is left untouched on error.
+    /// An entry either replace an existing [`FragmentBytes`] or, if its index is the number of [`FragmentBytes`], is appended.
+    pub fn import_fragment_bytes_dump(
+        &mut self,
+        manifest: &FragmentBytesDumpManifest,
+        dumps: &[Vec<u8>],
+    ) -> Result<(), FragmentBytesDumpError> {
+        let mut expected_len = self.fragment_bytes_store.len();
+        for (entry_nb, entry) in manifest.entries.iter().enumerate() {
+            let dump = dumps
+                .get(entry_nb)
+                .ok_or(FragmentBytesDumpError::MissingDump(entry.index))?;
+            let checksum = crc32(dump);
+            if checksum != entry.checksum {
+                return Err(FragmentBytesDumpError::ChecksumMismatch {
+                    index: entry.index,
+                    expected: entry.checksum,
+                    found: checksum,
+                });
+            }
+            let pixel_amount = dump.len() as u32 * 2;
+            if pixel_amount != entry.pixel_amount {
+                return Err(FragmentBytesDumpError::SizeMismatch {
+                    index: entry.index,
+                    expected: entry.pixel_amount,
+                    found: pixel_amount,
+                });
+            }
+            if let (Some(expected), Some(found)) =
+                (entry.shape, self.find_shape_of_fragment_bytes(entry.index))
+            {
+                if expected != found {
+                    return Err(FragmentBytesDumpError::ShapeMismatch {
+                        index: entry.index,
+                        expected,
+                        found,
+                    });
+                }
+            }
+            if entry.index > expected_len {
+                return Err(FragmentBytesDumpError::IndexOutOfRange(entry.index));
+            }
+            if entry.index == expected_len {
+                expected_len += 1;
+            }
+        }
+
+        for (entry, dump) in manifest.entries.iter().zip(dumps) {
+            let fragment_bytes = FragmentBytes::new_from_raw_bytes(dump, entry.z_index);
+            if entry.index == self.fragment_bytes_store.len() {
+                self.fragment_bytes_store
+                    .fragment_bytes
+                    .push(fragment_bytes);
+            } else {
+                self.fragment_bytes_store.fragment_bytes[entry.index] = fragment_bytes;
+            }
+        }
+        Ok(())
+    }
+
+    fn find_shape_of_fragment_bytes(&self, fragment_bytes_index: usize) -> Option<OamShape> {
+        self.frame_store
+            .frames
+            .iter()
+            .flat_map(|frame| frame.fragments.iter())
+            .find(|fragment| fragment.fragment_bytes_index == fragment_bytes_index)
+            .map(|fragment| fragment.resolution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::crc32;
+    use crate::{
+        insert_frame_in_wanimage, FragmentBytesDumpError, FragmentBytesDumpManifest, SpriteType,
+        WanImage,
+    };
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_dump_roundtrip() {
+        let mut wanimage = WanImage::new(SpriteType::PropsUI);
+        wanimage.palette.palette = vec![[0, 0, 0, 0], [255, 255, 255, 128]];
+        insert_frame_in_wanimage(vec![1, 0, 0, 1], 2, 2, &mut wanimage, 0).unwrap();
+        let (manifest, dumps) = wanimage.export_fragment_bytes_dump();
+
+        let mut manifest_bytes = Vec::new();
+        manifest.write(&mut manifest_bytes).unwrap();
+        let parsed = FragmentBytesDumpManifest::parse(&mut Cursor::new(manifest_bytes)).unwrap();
+        assert_eq!(parsed, manifest);
+
+        let original = wanimage.fragment_bytes_store.fragment_bytes[0]
+            .mixed_pixels
+            .clone();
+        wanimage.fragment_bytes_store.fragment_bytes[0].mixed_pixels = vec![0; 64];
+        wanimage
+            .import_fragment_bytes_dump(&parsed, &dumps)
+            .unwrap();
+        assert_eq!(
+            wanimage.fragment_bytes_store.fragment_bytes[0].mixed_pixels,
+            original
+        );
+
+        let mut corrupted = dumps;
+        corrupted[0][0] ^= 0x10;
+        assert!(matches!(
+            wanimage.import_fragment_bytes_dump(&parsed, &corrupted),
+            Err(FragmentBytesDumpError::ChecksumMismatch { index: 0, .. })
+        ));
+    }
+}
@@ -1,8 +1,18 @@
-use crate::{CompressionMethod, FragmentBytes, WanError};
+use crate::{
+    decode_options::check_limit,
+    progress::{no_progress, start_phase},
+    DecodeOptions, DecodeWarning, FragmentBytes, FragmentBytesCompressionStats, FragmentCompressor,
+    LocatedWanError, ProgressCallback, ProgressPhase, WanError, WanSection,
+};
 use byteorder::{ReadBytesExt, LE};
 use std::io::{Read, Seek, SeekFrom, Write};
 
+/// The address of the assembly table of each [`FragmentBytes`], the pointers to add to the SIR0 pointer list, and the compression statistics of each [`FragmentBytes`]
+pub(crate) type WrittenFragmentBytesStore =
+    (Vec<u64>, Vec<u64>, Vec<FragmentBytesCompressionStats>);
+
 #[derive(PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FragmentBytesStore {
     pub fragment_bytes: Vec<FragmentBytes>,
 }
@@ -12,18 +22,41 @@ impl FragmentBytesStore {
         file: &mut F,
         amount_fragments_bytes: u32,
     ) -> Result<FragmentBytesStore, WanError> {
+        Self::new_from_bytes_with_options(
+            file,
+            amount_fragments_bytes,
+            &DecodeOptions::default(),
+            &mut Vec::new(),
+        )
+        .map(|(store, _)| store)
+        .map_err(|error| error.error)
+    }
+
+    /// Like [`FragmentBytesStore::new_from_bytes`], but in lenient mode, the [`FragmentBytes`] that can't be read are left empty.
+    /// Also return their indices, so they can be repaired once their size is known.
+    pub(crate) fn new_from_bytes_with_options<F: Read + Seek>(
+        file: &mut F,
+        amount_fragments_bytes: u32,
+        options: &DecodeOptions,
+        warnings: &mut Vec<DecodeWarning>,
+    ) -> Result<(FragmentBytesStore, Vec<usize>), LocatedWanError> {
         trace!("will read {} FragmentBytes", amount_fragments_bytes);
+        check_limit(
+            amount_fragments_bytes as usize,
+            options.limits.max_fragment_bytes,
+            "FragmentBytes",
+        )?;
+        let pointer_table_start = file.stream_position()?;
         let mut fragment_bytes_pointers: Vec<u64> = Vec::new(); //list of reference to FragmentBytes
         for _ in 0..amount_fragments_bytes {
             let current_pointer = file.read_u32::<LE>()? as u64;
-            if current_pointer == 0 {
-                return Err(WanError::NullFragmentBytesPointer);
-            };
             fragment_bytes_pointers.push(current_pointer);
         }
 
         trace!("reading the FragmentBytes table");
         let mut fragment_bytes = Vec::new();
+        let mut invalid_fragment_bytes = Vec::new();
+        let mut remaining_image_bytes = options.limits.max_image_bytes;
 
         for (fragment_bytes_id, fragment_bytes_addr) in fragment_bytes_pointers.iter().enumerate() {
             trace!(
@@ -31,12 +64,53 @@ impl FragmentBytesStore {
                 fragment_bytes_id,
                 fragment_bytes_addr
             );
-            file.seek(SeekFrom::Start(*fragment_bytes_addr))?;
-            let img = FragmentBytes::new_from_bytes(file)?;
-            fragment_bytes.push(img);
+            let result = if *fragment_bytes_addr == 0 {
+                Err(WanError::NullFragmentBytesPointer)
+            } else {
+                file.seek(SeekFrom::Start(*fragment_bytes_addr))
+                    .map_err(WanError::from)
+                    .and_then(|_| {
+                        FragmentBytes::new_from_bytes_with_limit(file, remaining_image_bytes)
+                    })
+            };
+            match result {
+                Ok(img) => {
+                    remaining_image_bytes -= img.mixed_pixels.len();
+                    fragment_bytes.push(img)
+                }
+                Err(error) => {
+                    // a null pointer is located at its entry in the pointer table
+                    let offset = match *fragment_bytes_addr {
+                        0 => pointer_table_start + 4 * fragment_bytes_id as u64,
+                        addr => addr,
+                    };
+                    options
+                        .recover(error, warnings, |error| {
+                            DecodeWarning::InvalidFragmentBytes {
+                                index: fragment_bytes_id,
+                                error,
+                            }
+                        })
+                        .map_err(|error| {
+                            LocatedWanError::from(error).locate(
+                                WanSection::FragmentBytes,
+                                Some(fragment_bytes_id),
+                                offset,
+                            )
+                        })?;
+                    invalid_fragment_bytes.push(fragment_bytes_id);
+                    fragment_bytes.push(FragmentBytes {
+                        mixed_pixels: Vec::new(),
+                        z_index: 0,
+                    });
+                }
+            }
         }
 
-        Ok(FragmentBytesStore { fragment_bytes })
+        Ok((
+            FragmentBytesStore { fragment_bytes },
+            invalid_fragment_bytes,
+        ))
     }
 
     pub fn len(&self) -> usize {
@@ -50,23 +124,40 @@ impl FragmentBytesStore {
     pub fn write<F: Write + Seek>(
         &self,
         file: &mut F,
-        compression: &CompressionMethod,
+        compressor: &dyn FragmentCompressor,
     ) -> Result<(Vec<u64>, Vec<u64>), WanError> {
+        let (fragment_bytes_addr, sir0_pointer_fragments_bytes, _) =
+            self.write_with_stats(file, compressor, &no_progress)?;
+        Ok((fragment_bytes_addr, sir0_pointer_fragments_bytes))
+    }
+
+    /// Like [`FragmentBytesStore::write`], but also return the statistics of each [`FragmentBytes`]
+    pub(crate) fn write_with_stats<F: Write + Seek>(
+        &self,
+        file: &mut F,
+        compressor: &dyn FragmentCompressor,
+        progress: ProgressCallback,
+    ) -> Result<WrittenFragmentBytesStore, WanError> {
+        let step = start_phase(
+            progress,
+            ProgressPhase::EncodingFragmentBytes,
+            self.fragment_bytes.len(),
+        );
         let mut fragment_bytes_addr = vec![];
         let mut sir0_pointer_fragments_bytes = vec![];
+        let mut stats = Vec::with_capacity(self.fragment_bytes.len());
 
         for fragment_bytes in &self.fragment_bytes {
-            trace!(
-                "fragment bytes wrote at {}",
-                file.seek(SeekFrom::Current(0))?
-            );
-            let (assembly_table_offset, sir0_img_pointer) =
-                fragment_bytes.write(file, compression)?;
+            trace!("fragment bytes wrote at {}", file.stream_position()?);
+            let (assembly_table_offset, sir0_img_pointer, fragment_bytes_stats) =
+                fragment_bytes.write_with_stats(file, compressor)?;
             for pointer in sir0_img_pointer {
                 sir0_pointer_fragments_bytes.push(pointer)
             }
             fragment_bytes_addr.push(assembly_table_offset);
+            stats.push(fragment_bytes_stats);
+            step();
         }
-        Ok((fragment_bytes_addr, sir0_pointer_fragments_bytes))
+        Ok((fragment_bytes_addr, sir0_pointer_fragments_bytes, stats))
     }
 }
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::fs::File;
+use std::io;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::io::BufWriter;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::path::Path;
+
+use image::RgbaImage;
+use thiserror::Error;
+
+use crate::{
+    Fragment, FragmentBytesToImageError, FragmentFlip, IndexedPngError, OamShape, WanImage,
+};
+
+#[derive(Error, Debug)]
+pub enum FragmentExportError {
+    #[error("an input/output error happened")]
+    IOError(#[from] io::Error),
+    #[error("failed to write an image")]
+    ImageError(#[from] image::ImageError),
+    #[error("failed to decode a FragmentBytes")]
+    CantDecode(#[from] FragmentBytesToImageError),
+    #[error("failed to write an indexed png")]
+    CantWriteIndexedPng(#[from] IndexedPngError),
+}
+
+/// A decoded [`crate::FragmentBytes`], as returned by [`WanImage::fragment_images`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentImage {
+    /// The index of the [`crate::FragmentBytes`]
+    pub index: usize,
+    /// The shape of the first [`Fragment`] that use it
+    pub shape: OamShape,
+    /// The palette of the first [`Fragment`] that use it
+    pub pal_idx: u16,
+    /// Color indices in the palette row (or in the whole palette for 256 colors sprites), line by line from the top-left pixel
+    pub pixels: Vec<u8>,
+    pub image: RgbaImage,
+}
+
+impl WanImage {
+    /// Return the first [`Fragment`] that use each [`crate::FragmentBytes`], without its flip
+    fn first_fragment_of_fragment_bytes(&self) -> HashMap<usize, Fragment> {
+        let mut result = HashMap::new();
+        for fragment in self
+            .frame_store
+            .frames
+            .iter()
+            .flat_map(|frame| frame.fragments.iter())
+        {
+            result
+                .entry(fragment.fragment_bytes_index)
+                .or_insert_with(|| Fragment {
+                    flip: FragmentFlip::standard(),
+                    ..fragment.clone()
+                });
+        }
+        result
+    }
+
+    /// Decode every [`crate::FragmentBytes`], in order, with the shape and palette of the first [`Fragment`] that use it, and without flip.
+    ///
+    /// The [`crate::FragmentBytes`] not used by any [`Fragment`] are skipped, as their shape is unknown.
+    pub fn fragment_images(
+        &self,
+    ) -> impl Iterator<Item = Result<FragmentImage, FragmentBytesToImageError>> + '_ {
+        let first_fragments = self.first_fragment_of_fragment_bytes();
+        (0..self.fragment_bytes_store.len()).filter_map(move |index| {
+            let fragment = first_fragments.get(&index)?;
+            Some(self.fragment_image(index, fragment))
+        })
+    }
+
+    fn fragment_image(
+        &self,
+        index: usize,
+        fragment: &Fragment,
+    ) -> Result<FragmentImage, FragmentBytesToImageError> {
+        let pixels = fragment
+            .get_flipped_pixels_with_depth(&self.fragment_bytes_store, self.is_256_color)?;
+        Ok(FragmentImage {
+            index,
+            shape: fragment.resolution,
+            pal_idx: fragment.pal_idx,
+            pixels,
+            image: self.get_image_for_fragment(fragment)?,
+        })
+    }
+
+    /// Write every [`crate::FragmentBytes`] returned by [`WanImage::fragment_images`] in the given folder, both as `fragment_<index>.png` with colors and as `fragment_<index>_indexed.png` with the palette indices (see [`WanImage::write_fragment_indexed_png`]).
+    ///
+    /// The folder is created if needed. Return the number of exported [`crate::FragmentBytes`]. Not available on `wasm32-unknown-unknown`, that doesn't have a file system.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn export_fragments(&self, dir: &Path) -> Result<usize, FragmentExportError> {
+        std::fs::create_dir_all(dir)?;
+        let first_fragments = self.first_fragment_of_fragment_bytes();
+        let mut exported = 0;
+        for index in 0..self.fragment_bytes_store.len() {
+            let fragment = match first_fragments.get(&index) {
+                Some(fragment) => fragment,
+                None => continue,
+            };
+            let fragment_image = self.fragment_image(index, fragment)?;
+            fragment_image
+                .image
+                .save(dir.join(format!("fragment_{}.png", index)))?;
+            let indexed_file = File::create(dir.join(format!("fragment_{}_indexed.png", index)))?;
+            self.write_fragment_indexed_png(fragment, BufWriter::new(indexed_file))?;
+            exported += 1;
+        }
+        Ok(exported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{test_wan_image, TempDir},
+        FragmentBytes, SpriteType,
+    };
+
+    #[test]
+    fn test_fragment_images() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        let mut pixels = vec![1; 16 * 8];
+        pixels[1] = 0;
+        insert_frame_in_wanimage(pixels, 16, 8, &mut wanimage, 0).unwrap();
+        // not used by any fragment
+        wanimage
+            .fragment_bytes_store
+            .fragment_bytes
+            .push(FragmentBytes {
+                mixed_pixels: vec![0; 64],
+                z_index: 0,
+            });
+
+        let images: Vec<_> = wanimage
+            .fragment_images()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].index, 0);
+        assert_eq!(images[0].image.dimensions(), (16, 8));
+        assert_eq!(images[0].pixels[..2], [1, 0]);
+        assert_eq!(images[0].image.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+
+        let dir = TempDir::new("export_fragments");
+        assert_eq!(wanimage.export_fragments(&dir).unwrap(), 1);
+        assert!(dir.join("fragment_0.png").exists());
+        assert!(dir.join("fragment_0_indexed.png").exists());
+    }
+}
@@ -1,8 +1,12 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 
 use thiserror::Error;
 
-use crate::{FragmentFlip, GeneralResolution, NormalizedBytes};
+use crate::{
+    progress::{no_progress, start_phase},
+    CancellationToken, Cancelled, FragmentFlip, GeneralResolution, NormalizedBytes,
+    ProgressCallback, ProgressPhase,
+};
 
 #[derive(Debug, Error)]
 pub enum FragmentFinderError {
@@ -12,6 +16,8 @@ pub enum FragmentFinderError {
     TooMuchImage(usize),
     #[error("The image {0} has a too big resolution")]
     ImageTooBig(usize),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord)]
@@ -19,6 +25,7 @@ pub struct FragmentUse {
     pub x: i32,
     pub y: i32,
     pub image_id: u16,
+    /// The flip that, passed to [`FragmentFlip::apply`], transform the normalized pixels into the ones at this position
     pub flip: FragmentFlip,
 }
 
@@ -50,55 +57,116 @@ impl FragmentFinderData {
 /// See [`FragmentFinderData`] for more information on the output
 /// The image is filled on all sides (7 pixels) by 0s. Fragments consisting of only zeroes are discared.
 /// 0×0 images are skipped.
+///
+/// With the `rayon` feature, the images (and the columns of each image) are scanned in parallel. The result is the same as without it, including the order of the fragment uses.
 pub fn find_fragments_in_images(
     images: &[(&[u8], GeneralResolution)],
+) -> Result<FragmentFinderData, FragmentFinderError> {
+    find_fragments_in_images_with_progress(images, &no_progress, &CancellationToken::new())
+}
+
+/// Like [`find_fragments_in_images`], but report the [`ProgressPhase::FindingFragments`] progress after each image.
+///
+/// `cancellation` is checked before each image. Once it is cancelled, [`FragmentFinderError::Cancelled`] is returned.
+pub fn find_fragments_in_images_with_progress(
+    images: &[(&[u8], GeneralResolution)],
+    progress: ProgressCallback,
+    cancellation: &CancellationToken,
 ) -> Result<FragmentFinderData, FragmentFinderError> {
     if images.len() > u16::MAX as usize {
         return Err(FragmentFinderError::TooMuchImage(images.len()));
     }
-    let mut result = FragmentFinderData {
-        collected: BTreeMap::new(),
-    };
-    let mut fragment_buffer = [0; 64];
-    let zero_buffer = [0; 64];
     for (image_id, (image_pixels, resolution)) in images.iter().enumerate() {
         if image_pixels.len() as u64 != resolution.nb_pixels() {
             return Err(FragmentFinderError::InvalidResolution(image_id));
         };
-        if image_pixels.is_empty() {
-            continue;
+    }
+
+    let step = start_phase(progress, ProgressPhase::FindingFragments, images.len());
+    let find_in_image =
+        |(image_id, (image_pixels, resolution)): (usize, &(&[u8], GeneralResolution))| {
+            cancellation.check()?;
+            let found = find_fragments_in_image(image_pixels, resolution.clone(), image_id as u16);
+            step();
+            Ok(found)
         };
-        let (padded_image, padded_resolution) =
-            pad_seven_pixel(image_pixels, resolution.clone()).unwrap();
-        //no panic: checked just before the resolution is good
-        for x_base in 0..padded_resolution.x - 7 {
-            for y_base in 0..padded_resolution.y - 7 {
-                for special_line in 0..8 {
-                    let pixel_base = (special_line + y_base) * padded_resolution.x + x_base;
-                    fragment_buffer[special_line as usize * 8..special_line as usize * 8 + 8]
-                        .copy_from_slice(
-                            &padded_image[pixel_base as usize..pixel_base as usize + 8],
-                        );
-                }
-                // collected a 8×8 fragment
-                if fragment_buffer == zero_buffer {
-                    continue;
-                }
-                let (normalized, flip) = NormalizedBytes::new(fragment_buffer);
-                result.add_fragment_use(
-                    normalized,
-                    FragmentUse {
-                        x: x_base as i32 - 7,
-                        y: y_base as i32 - 7,
-                        // no overflow: already checked at the beggining of the function
-                        image_id: image_id as u16,
-                        flip,
-                    },
-                );
+    #[cfg(feature = "rayon")]
+    let found_by_image: Vec<Vec<(NormalizedBytes, FragmentUse)>> = {
+        use rayon::prelude::*;
+        images
+            .par_iter()
+            .enumerate()
+            .map(find_in_image)
+            .collect::<Result<_, Cancelled>>()?
+    };
+    #[cfg(not(feature = "rayon"))]
+    let found_by_image: Vec<Vec<(NormalizedBytes, FragmentUse)>> = images
+        .iter()
+        .enumerate()
+        .map(find_in_image)
+        .collect::<Result<_, Cancelled>>()?;
+
+    // most fragments are found many times, so they are first grouped in a hash index, and only the distinct ones are then sorted
+    let mut index: HashMap<NormalizedBytes, Vec<FragmentUse>> = HashMap::new();
+    for (normalized, usage) in found_by_image.into_iter().flatten() {
+        index.entry(normalized).or_default().push(usage);
+    }
+    Ok(FragmentFinderData {
+        collected: index.into_iter().collect(),
+    })
+}
+
+/// Find the fragments of a single image for [`find_fragments_in_images`], in the order they should be added. The resolution should already have been checked.
+fn find_fragments_in_image(
+    image_pixels: &[u8],
+    resolution: GeneralResolution,
+    image_id: u16,
+) -> Vec<(NormalizedBytes, FragmentUse)> {
+    if image_pixels.is_empty() {
+        return Vec::new();
+    };
+    //no panic: the resolution is checked by the caller
+    let (padded_image, padded_resolution) = pad_seven_pixel(image_pixels, resolution).unwrap();
+    let find_in_column = |x_base: u32| {
+        let mut found = Vec::new();
+        let mut fragment_buffer = [0; 64];
+        for y_base in 0..padded_resolution.y - 7 {
+            for special_line in 0..8 {
+                let pixel_base = (special_line + y_base) * padded_resolution.x + x_base;
+                fragment_buffer[special_line as usize * 8..special_line as usize * 8 + 8]
+                    .copy_from_slice(&padded_image[pixel_base as usize..pixel_base as usize + 8]);
             }
+            // collected a 8×8 fragment
+            if fragment_buffer == [0; 64] {
+                continue;
+            }
+            let (normalized, flip) = NormalizedBytes::new(fragment_buffer);
+            found.push((
+                normalized,
+                FragmentUse {
+                    x: x_base as i32 - 7,
+                    y: y_base as i32 - 7,
+                    image_id,
+                    flip,
+                },
+            ));
         }
-    }
-    Ok(result)
+        found
+    };
+
+    #[cfg(feature = "rayon")]
+    let found_by_column: Vec<Vec<(NormalizedBytes, FragmentUse)>> = {
+        use rayon::prelude::*;
+        (0..padded_resolution.x - 7)
+            .into_par_iter()
+            .map(find_in_column)
+            .collect()
+    };
+    #[cfg(not(feature = "rayon"))]
+    let found_by_column: Vec<Vec<(NormalizedBytes, FragmentUse)>> =
+        (0..padded_resolution.x - 7).map(find_in_column).collect();
+
+    found_by_column.into_iter().flatten().collect()
 }
 
 pub fn pad_seven_pixel(
@@ -123,9 +191,10 @@ pub fn pad_seven_pixel(
 #[cfg(test)]
 mod tests {
     use crate::{
-        find_fragments_in_images,
+        find_fragments_in_images, find_fragments_in_images_with_progress,
         fragment_finder::{pad_seven_pixel, FragmentUse},
-        FragmentFinderData, FragmentFlip, GeneralResolution, NormalizedBytes,
+        CancellationToken, FragmentFinderData, FragmentFinderError, FragmentFlip,
+        GeneralResolution, NormalizedBytes,
     };
 
     #[test]
@@ -173,13 +242,13 @@ mod tests {
     #[test]
     pub fn test_ignore_zeroes() {
         let would_contain_zeroes = [0, 0, 0, 0, 1, 0, 0, 0, 0];
-        assert!(
-            find_fragments_in_images(&[(&would_contain_zeroes, GeneralResolution::new(3, 3))])
-                .unwrap()
-                .collected
-                .get(&NormalizedBytes::new([0; 64]).0)
-                .is_none()
-        );
+        assert!(!find_fragments_in_images(&[(
+            &would_contain_zeroes,
+            GeneralResolution::new(3, 3)
+        )])
+        .unwrap()
+        .collected
+        .contains_key(&NormalizedBytes::new([0; 64]).0));
     }
 
     #[test]
@@ -217,6 +286,26 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn test_deterministic_order() {
+        let first = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let second = [9, 8, 7, 6, 5, 4, 3, 2, 1];
+        let images = [
+            (&first[..], GeneralResolution::new(3, 3)),
+            (&second[..], GeneralResolution::new(3, 3)),
+        ];
+        let found = find_fragments_in_images(&images).unwrap();
+        for usages in found.collected.values() {
+            let mut sorted = usages.clone();
+            sorted.sort_by_key(|usage| (usage.image_id, usage.x, usage.y));
+            assert_eq!(usages, &sorted);
+        }
+        assert_eq!(
+            found.collected,
+            find_fragments_in_images(&images).unwrap().collected
+        );
+    }
+
     #[test]
     fn test_pad_seven_pixel() {
         let image = [2, 3, 4, 5, 6, 7];
@@ -234,4 +323,18 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_cancellation() {
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        assert!(matches!(
+            find_fragments_in_images_with_progress(
+                &[(&[1; 64], GeneralResolution::new(8, 8))],
+                &|_| (),
+                &cancellation
+            ),
+            Err(FragmentFinderError::Cancelled(_))
+        ));
+    }
 }
@@ -14,6 +14,7 @@ pub enum FragmentFlipError {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FragmentFlip {
     pub flip_h: bool,
     pub flip_v: bool,
@@ -0,0 +1,356 @@
+use std::time::Duration;
+
+use crate::{clock::Deadline, BoundingBox, CancellationToken, Cancelled, OamShape};
+
+/// What [`find_fragment_layout`] should minimize first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FragmentLayoutGoal {
+    /// Use as few [`crate::Fragment`]s as possible, then as little VRAM as possible
+    MinimumFragments,
+    /// Use as little VRAM as possible (as computed by [`OamShape::chunk_to_allocate_for_fragment`]), then as few [`crate::Fragment`]s as possible
+    MinimumVram,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentLayoutOptions {
+    pub goal: FragmentLayoutGoal,
+    /// The search stop after this duration, returning the best layout found so far. A layout is always returned, even if finding the first one take longer.
+    ///
+    /// On `wasm32-unknown-unknown`, where there is no clock, the duration is estimated from the number of steps of the search.
+    pub time_budget: Duration,
+    /// Checked from time to time during the search. Once it is cancelled, [`find_fragment_layout`] return [`Cancelled`].
+    pub cancellation: CancellationToken,
+}
+
+impl Default for FragmentLayoutOptions {
+    fn default() -> Self {
+        Self {
+            goal: FragmentLayoutGoal::MinimumFragments,
+            time_budget: Duration::from_millis(100),
+            cancellation: CancellationToken::new(),
+        }
+    }
+}
+
+/// A fragment of a [`FragmentLayout`]. The position is the one of its top-left pixel, relative to the top-left pixel of the image, and may be negative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FragmentPlacement {
+    pub x: i32,
+    pub y: i32,
+    pub shape: OamShape,
+}
+
+/// The output of [`find_fragment_layout`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FragmentLayout {
+    pub placements: Vec<FragmentPlacement>,
+    /// true if the whole search space was explored before the end of the time budget, and not only the best layout found in time
+    pub is_optimal: bool,
+}
+
+impl FragmentLayout {
+    /// The total number of VRAM chunks used by the fragments, as computed by [`OamShape::chunk_to_allocate_for_fragment`]
+    pub fn vram_chunks(&self) -> u32 {
+        self.placements
+            .iter()
+            .map(|placement| placement.shape.chunk_to_allocate_for_fragment() as u32)
+            .sum()
+    }
+}
+
+/// (primary cost, secondary cost, pixels): lower is better
+type LayoutCost = (u32, u32, u32);
+
+/// The position (in cells) of the top-left cell of a fragment, with its shape
+type CellPlacement = (i32, i32, CellShape);
+
+/// A shape, with its size in 8×8 cells
+#[derive(Clone, Copy)]
+struct CellShape {
+    shape: OamShape,
+    width: usize,
+    height: usize,
+}
+
+struct LayoutSearch<'a> {
+    occupied: &'a [bool],
+    grid_width: usize,
+    grid_height: usize,
+    shapes: Vec<CellShape>,
+    goal: FragmentLayoutGoal,
+    deadline: Deadline,
+    timed_out: bool,
+    cancellation: &'a CancellationToken,
+    cancelled: bool,
+    visited_nodes: u64,
+    covered: Vec<bool>,
+    current: Vec<CellPlacement>,
+    best: Option<(LayoutCost, Vec<CellPlacement>)>,
+}
+
+impl<'a> LayoutSearch<'a> {
+    fn cost_of(&self, fragments: u32, chunks: u32, pixels: u32) -> LayoutCost {
+        match self.goal {
+            FragmentLayoutGoal::MinimumFragments => (fragments, chunks, pixels),
+            FragmentLayoutGoal::MinimumVram => (chunks, pixels, fragments),
+        }
+    }
+
+    fn is_covered(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x as usize >= self.grid_width || y as usize >= self.grid_height {
+            false
+        } else {
+            self.covered[y as usize * self.grid_width + x as usize]
+        }
+    }
+
+    fn set_covered(&mut self, x: i32, y: i32, shape: CellShape, value: bool) {
+        for cell_y in y..y + shape.height as i32 {
+            for cell_x in x..x + shape.width as i32 {
+                if cell_x >= 0
+                    && cell_y >= 0
+                    && (cell_x as usize) < self.grid_width
+                    && (cell_y as usize) < self.grid_height
+                {
+                    self.covered[cell_y as usize * self.grid_width + cell_x as usize] = value;
+                }
+            }
+        }
+    }
+
+    fn search(&mut self, first_cell: usize, cost: (u32, u32, u32), remaining: u32) {
+        self.visited_nodes += 1;
+        if self.visited_nodes.is_multiple_of(1024) {
+            if self.cancellation.is_cancelled() {
+                self.cancelled = true;
+            } else if self.best.is_some() && self.deadline.is_past() {
+                self.timed_out = true;
+            }
+        }
+        if self.timed_out || self.cancelled {
+            return;
+        }
+        let (fragments, chunks, pixels) = cost;
+        // each fragment cover at most 64 cells, and each VRAM chunk at most 4 cells
+        let lower_bound = self.cost_of(
+            fragments + remaining.div_ceil(64),
+            chunks + remaining.div_ceil(4),
+            pixels + remaining * 64,
+        );
+        if let Some((best_cost, _)) = &self.best {
+            if lower_bound >= *best_cost {
+                return;
+            }
+        }
+        let next_cell = (first_cell..self.occupied.len())
+            .find(|cell| self.occupied[*cell] && !self.covered[*cell]);
+        let next_cell = match next_cell {
+            Some(cell) => cell,
+            None => {
+                self.best = Some((
+                    self.cost_of(fragments, chunks, pixels),
+                    self.current.clone(),
+                ));
+                return;
+            }
+        };
+        let cell_x = (next_cell % self.grid_width) as i32;
+        let cell_y = (next_cell / self.grid_width) as i32;
+        for shape_nb in 0..self.shapes.len() {
+            let shape = self.shapes[shape_nb];
+            // The cell should be on the first line of the fragment, as every cell above is already handled
+            for start_x in (cell_x + 1 - shape.width as i32..=cell_x).rev() {
+                let mut newly_covered = 0;
+                let mut overlap = false;
+                'check: for y in cell_y..cell_y + shape.height as i32 {
+                    for x in start_x..start_x + shape.width as i32 {
+                        if self.is_covered(x, y) {
+                            overlap = true;
+                            break 'check;
+                        }
+                        if x >= 0
+                            && (x as usize) < self.grid_width
+                            && (y as usize) < self.grid_height
+                            && self.occupied[y as usize * self.grid_width + x as usize]
+                        {
+                            newly_covered += 1;
+                        }
+                    }
+                }
+                if overlap {
+                    continue;
+                }
+                self.set_covered(start_x, cell_y, shape, true);
+                self.current.push((start_x, cell_y, shape));
+                self.search(
+                    next_cell + 1,
+                    (
+                        fragments + 1,
+                        chunks + shape.shape.chunk_to_allocate_for_fragment() as u32,
+                        pixels + (shape.width * shape.height * 64) as u32,
+                    ),
+                    remaining - newly_covered,
+                );
+                self.current.pop();
+                self.set_covered(start_x, cell_y, shape, false);
+                if self.timed_out {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Search how to cover all the non-transparent pixels (non-0) of an image (line by line, from the top-left pixel) with the legal [`OamShape`]s, minimizing the number of fragments or the VRAM used.
+///
+/// The fragments are aligned on the 8×8 grid starting at the top-left non-transparent pixel, and doesn't overlap. The search is exhaustive, so it is stopped after the time budget of the options.
+/// Return an empty layout if the image doesn't have any non-transparent pixel, or if the image is smaller than its announced size.
+/// Fail only if [`FragmentLayoutOptions::cancellation`] is cancelled.
+pub fn find_fragment_layout(
+    pixels: &[u8],
+    width: u16,
+    height: u16,
+    options: &FragmentLayoutOptions,
+) -> Result<FragmentLayout, Cancelled> {
+    options.cancellation.check()?;
+    let width = width as usize;
+    let height = height as usize;
+    if pixels.len() < width * height {
+        return Ok(FragmentLayout::default());
+    }
+    let bounds = match BoundingBox::of_opaque_pixels(width as u32, height as u32, |x, y| {
+        pixels[y as usize * width + x as usize] != 0
+    }) {
+        Some(bounds) => bounds,
+        None => {
+            return Ok(FragmentLayout {
+                placements: Vec::new(),
+                is_optimal: true,
+            })
+        }
+    };
+    let (min_x, min_y) = (bounds.min_x as usize, bounds.min_y as usize);
+    let (max_x, max_y) = (bounds.max_x as usize, bounds.max_y as usize);
+    let grid_width = (max_x - min_x).div_ceil(8);
+    let grid_height = (max_y - min_y).div_ceil(8);
+    let mut occupied = vec![false; grid_width * grid_height];
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            if pixels[y * width + x] != 0 {
+                occupied[(y - min_y) / 8 * grid_width + (x - min_x) / 8] = true;
+            }
+        }
+    }
+
+    let mut shapes = Vec::new();
+    for shape_indice in 0..3 {
+        for size_indice in 0..4 {
+            // no panic: all those indices are valid
+            let shape = OamShape::new(shape_indice, size_indice).unwrap();
+            let size = shape.size();
+            shapes.push(CellShape {
+                shape,
+                width: size.x as usize / 8,
+                height: size.y as usize / 8,
+            });
+        }
+    }
+    // try the biggest shapes first, to quickly find a good first layout
+    shapes.sort_by_key(|shape| usize::MAX - shape.width * shape.height);
+
+    let remaining = occupied.iter().filter(|x| **x).count() as u32;
+    let mut search = LayoutSearch {
+        occupied: &occupied,
+        grid_width,
+        grid_height,
+        shapes,
+        goal: options.goal,
+        deadline: Deadline::after(options.time_budget),
+        timed_out: false,
+        cancellation: &options.cancellation,
+        cancelled: false,
+        visited_nodes: 0,
+        covered: vec![false; grid_width * grid_height],
+        current: Vec::new(),
+        best: None,
+    };
+    search.search(0, (0, 0, 0), remaining);
+    if search.cancelled {
+        return Err(Cancelled);
+    }
+
+    let placements = search
+        .best
+        .map(|(_, placements)| placements)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(cell_x, cell_y, shape)| FragmentPlacement {
+            x: min_x as i32 + cell_x * 8,
+            y: min_y as i32 + cell_y * 8,
+            shape: shape.shape,
+        })
+        .collect();
+    Ok(FragmentLayout {
+        placements,
+        is_optimal: !search.timed_out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        find_fragment_layout, CancellationToken, Cancelled, FragmentLayoutGoal,
+        FragmentLayoutOptions, OamShape,
+    };
+
+    #[test]
+    fn test_layout_minimum_fragments() {
+        // a 24×8 image: a 32×8 fragment is enough
+        let layout =
+            find_fragment_layout(&[1; 24 * 8], 24, 8, &FragmentLayoutOptions::default()).unwrap();
+        assert!(layout.is_optimal);
+        assert_eq!(layout.placements.len(), 1);
+        assert_eq!(layout.placements[0].shape, OamShape::new(1, 1).unwrap());
+        assert_eq!((layout.placements[0].x, layout.placements[0].y), (0, 0));
+    }
+
+    #[test]
+    fn test_layout_minimum_vram() {
+        // an L shape made of three 8×8 cells
+        let mut pixels = vec![0; 16 * 16];
+        for y in 0..16 {
+            for x in 0..16 {
+                if x < 8 || y < 8 {
+                    pixels[y * 16 + x] = 1;
+                }
+            }
+        }
+        let options = FragmentLayoutOptions {
+            goal: FragmentLayoutGoal::MinimumVram,
+            time_budget: Duration::from_secs(10),
+            ..Default::default()
+        };
+        let layout = find_fragment_layout(&pixels, 16, 16, &options).unwrap();
+        assert!(layout.is_optimal);
+        assert_eq!(layout.vram_chunks(), 1);
+        assert_eq!(layout.placements.len(), 1);
+
+        let layout = find_fragment_layout(&[0; 4], 2, 2, &options).unwrap();
+        assert!(layout.placements.is_empty());
+    }
+
+    #[test]
+    fn test_layout_cancellation() {
+        let options = FragmentLayoutOptions {
+            cancellation: CancellationToken::new(),
+            ..Default::default()
+        };
+        options.cancellation.clone().cancel();
+        assert_eq!(
+            find_fragment_layout(&[1; 64 * 64], 64, 64, &options),
+            Err(Cancelled)
+        );
+    }
+}
@@ -1,10 +1,11 @@
 use anyhow::{bail, Context};
 
-use crate::{Fragment, FrameOffset, WanError};
+use crate::{decode_options::check_limit, Fragment, FrameOffset, WanError};
 use std::io::{Read, Write};
 
 /// A single frame of animation
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Frame {
     pub fragments: Vec<Fragment>,
     /// While this is stored in a separate part of the file, they are mapped to a Frame
@@ -14,20 +15,30 @@ pub struct Frame {
 impl Frame {
     pub fn new_from_bytes<F: Read>(file: &mut F) -> Result<Frame, WanError> {
         let mut fragments = Vec::new();
+        Self::read_fragments(file, &mut fragments, usize::MAX)?;
+        Ok(Frame {
+            fragments,
+            frame_offset: None,
+        })
+    }
+
+    /// Read at most `max_fragments` fragments of a frame into `fragments`. On error, the fragments read before the faulty one are kept.
+    pub(crate) fn read_fragments<F: Read>(
+        file: &mut F,
+        fragments: &mut Vec<Fragment>,
+        max_fragments: usize,
+    ) -> Result<(), WanError> {
         let mut previous_fragment_bytes = None;
         loop {
+            check_limit(fragments.len() + 1, max_fragments, "fragments in a frame")?;
             let (fragment, is_last) = Fragment::new_from_bytes(file, previous_fragment_bytes)?;
             previous_fragment_bytes = Some(fragment.fragment_bytes_index);
             fragments.push(fragment);
             trace!("its data: {:?}", fragments[fragments.len() - 1]);
             if is_last {
-                break;
+                return Ok(());
             }
         }
-        Ok(Frame {
-            fragments,
-            frame_offset: None,
-        })
     }
 
     /// Returns: size to allocate for the fragments of this frame
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use image::imageops::crop_imm;
+
+use crate::{BoundingBox, FrameOffset, FrameRenderError, WanImage};
+
+/// What a frame display, independently of how it is split in [`crate::Fragment`]s
+#[derive(PartialEq, Eq, Hash)]
+struct RenderedFrameKey {
+    frame_offset: Option<FrameOffset>,
+    /// The area of the visible pixels, relative to the origin of the frame
+    bounds: Option<BoundingBox>,
+    pixels: Vec<u8>,
+}
+
+impl WanImage {
+    /// Merge the frames that are displayed identically (same visible pixels at the same position relative to the origin, and same [`FrameOffset`]) with the first of them, even if their [`crate::Fragment`]s differ.
+    /// The [`crate::AnimationFrame`]s that reference them are updated.
+    ///
+    /// Return the number of removed frames. The [`crate::FragmentBytes`] only used by the removed frames are left unused, see [`WanImage::gc_unused_fragment_bytes`].
+    pub fn merge_identically_rendered_frames(&mut self) -> Result<usize, FrameRenderError> {
+        let mut known: HashMap<RenderedFrameKey, usize> = HashMap::new();
+        let mut kept = Vec::new();
+        let mut remap = Vec::with_capacity(self.frame_store.frames.len());
+        for frame_id in 0..self.frame_store.frames.len() {
+            let key = self.rendered_frame_key(frame_id)?;
+            match known.get(&key) {
+                Some(new_id) => remap.push(*new_id),
+                None => {
+                    known.insert(key, kept.len());
+                    remap.push(kept.len());
+                    kept.push(frame_id);
+                }
+            }
+        }
+        let removed = self.frame_store.frames.len() - kept.len();
+        if removed != 0 {
+            self.remap_frames(&kept, &remap);
+        }
+        Ok(removed)
+    }
+
+    fn rendered_frame_key(&self, frame_id: usize) -> Result<RenderedFrameKey, FrameRenderError> {
+        let frame = &self.frame_store.frames[frame_id];
+        let image = self.render_frame(frame_id)?;
+        let (origin_x, origin_y) = frame.render_origin();
+        let bounds = BoundingBox::of_opaque_pixels(image.width(), image.height(), |x, y| {
+            image.get_pixel(x, y)[3] != 0
+        });
+        Ok(match bounds {
+            Some(bounds) => RenderedFrameKey {
+                frame_offset: frame.frame_offset.clone(),
+                bounds: Some(bounds.translate(-origin_x, -origin_y)),
+                pixels: crop_imm(
+                    &image,
+                    bounds.min_x as u32,
+                    bounds.min_y as u32,
+                    bounds.width(),
+                    bounds.height(),
+                )
+                .to_image()
+                .into_raw(),
+            },
+            None => RenderedFrameKey {
+                frame_offset: frame.frame_offset.clone(),
+                bounds: None,
+                pixels: Vec::new(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::animation_frame,
+        tests::fixtures::{insert_filled_frame, test_wan_image},
+        Animation, SpriteType,
+    };
+
+    #[test]
+    fn test_merge_identically_rendered_frames() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        // the same pixels
+        insert_filled_frame(&mut wanimage, 8, 8);
+        // with the same fragments
+        insert_filled_frame(&mut wanimage, 8, 8);
+        // with a transparent border, and so different fragments
+        let mut bordered = vec![0; 100];
+        for y in 1..9 {
+            for x in 1..9 {
+                bordered[y * 10 + x] = 1;
+            }
+        }
+        insert_frame_in_wanimage(bordered, 10, 10, &mut wanimage, 0).unwrap();
+        // a different image
+        insert_filled_frame(&mut wanimage, 4, 4);
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: (0..4)
+                .rev()
+                .map(|frame_id| animation_frame(frame_id, 1))
+                .collect(),
+        }]);
+
+        assert_eq!(wanimage.merge_identically_rendered_frames().unwrap(), 2);
+        assert_eq!(wanimage.frame_store.frames.len(), 2);
+        let frame_ids: Vec<u16> = wanimage.animation_store.anim_groups[0][0]
+            .frames
+            .iter()
+            .map(|animation_frame| animation_frame.frame_id)
+            .collect();
+        assert_eq!(frame_ids, vec![1, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,163 @@
+use std::io::{self, Write};
+
+use image::{GrayImage, Luma};
+
+use crate::{FragmentBytesStore, FragmentBytesToImageError, Frame, WanImage};
+
+/// A 1-bit silhouette of a [`Frame`]: a pixel is set if it is opaque once all the [`crate::Fragment`]s are composited.
+///
+/// The mask covers the area of all the [`crate::Fragment`]s of the frame, so it may have fully transparent borders.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FrameMask {
+    pub width: u32,
+    pub height: u32,
+    /// The position of the origin of the [`Frame`] in the mask (may be outside of it)
+    pub origin_x: i32,
+    pub origin_y: i32,
+    /// Line by line, from the top-left pixel. true for opaque.
+    pub pixels: Vec<bool>,
+}
+
+impl FrameMask {
+    pub fn new_from_frame(
+        frame: &Frame,
+        fragment_bytes_store: &FragmentBytesStore,
+    ) -> Result<Self, FragmentBytesToImageError> {
+        Self::new_from_frame_with_depth(frame, fragment_bytes_store, false)
+    }
+
+    /// Like [`FrameMask::new_from_frame`], but for 256 colors sprites if `is_256_color` is set
+    pub fn new_from_frame_with_depth(
+        frame: &Frame,
+        fragment_bytes_store: &FragmentBytesStore,
+        is_256_color: bool,
+    ) -> Result<Self, FragmentBytesToImageError> {
+        let (min_x, min_y, max_x, max_y) = match frame.fragments_extent() {
+            Some(extent) => extent,
+            None => {
+                return Ok(Self {
+                    width: 0,
+                    height: 0,
+                    origin_x: 0,
+                    origin_y: 0,
+                    pixels: Vec::new(),
+                })
+            }
+        };
+        let width = (max_x - min_x) as u32;
+        let height = (max_y - min_y) as u32;
+        let mut pixels = vec![false; width as usize * height as usize];
+        for fragment in &frame.fragments {
+            let fragment_pixels =
+                fragment.get_flipped_pixels_with_depth(fragment_bytes_store, is_256_color)?;
+            let fragment_width = fragment.resolution.size().x as usize;
+            let start_x = (fragment.offset_x as i32 - min_x) as usize;
+            let start_y = (fragment.offset_y as i32 - min_y) as usize;
+            for (pixel_nb, pixel) in fragment_pixels.iter().enumerate() {
+                if *pixel != 0 {
+                    let x = start_x + pixel_nb % fragment_width;
+                    let y = start_y + pixel_nb / fragment_width;
+                    pixels[y * width as usize + x] = true;
+                }
+            }
+        }
+        Ok(Self {
+            width,
+            height,
+            origin_x: -min_x,
+            origin_y: -min_y,
+            pixels,
+        })
+    }
+
+    /// Return whether the pixel is opaque. Pixels outside of the mask are transparent.
+    pub fn is_opaque(&self, x: u32, y: u32) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Write the mask as a binary PBM (P4) image. Opaque pixels are black, as is the convention for PBM.
+    pub fn write_pbm<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "P4\n{} {}\n", self.width, self.height)?;
+        if self.width == 0 {
+            return Ok(());
+        }
+        let mut line_buffer = Vec::with_capacity((self.width as usize).div_ceil(8));
+        for line in self.pixels.chunks_exact(self.width as usize) {
+            line_buffer.clear();
+            for bits in line.chunks(8) {
+                let mut byte = 0;
+                for (bit_nb, bit) in bits.iter().enumerate() {
+                    if *bit {
+                        byte |= 0x80 >> bit_nb;
+                    }
+                }
+                line_buffer.push(byte);
+            }
+            writer.write_all(&line_buffer)?;
+        }
+        Ok(())
+    }
+
+    /// Convert the mask to a grayscale image, with opaque pixels being white (255) and transparent ones black (0).
+    /// It can then be saved as a PNG with the `image` crate.
+    pub fn to_image(&self) -> GrayImage {
+        GrayImage::from_fn(self.width, self.height, |x, y| {
+            Luma([if self.is_opaque(x, y) { 255 } else { 0 }])
+        })
+    }
+}
+
+impl WanImage {
+    /// Compute the [`FrameMask`] of every [`Frame`] of this image, in the same order as the frames.
+    pub fn frame_masks(&self) -> Result<Vec<FrameMask>, FragmentBytesToImageError> {
+        self.frame_store
+            .frames
+            .iter()
+            .map(|frame| {
+                FrameMask::new_from_frame_with_depth(
+                    frame,
+                    &self.fragment_bytes_store,
+                    self.is_256_color,
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{insert_frame_in_wanimage, FrameMask, SpriteType, WanImage};
+
+    #[test]
+    fn test_frame_mask() {
+        let mut wanimage = WanImage::new(SpriteType::PropsUI);
+        wanimage.palette.palette = vec![[0, 0, 0, 0], [255, 255, 255, 128]];
+        #[rustfmt::skip]
+        let image = vec![
+            1, 0,
+            0, 1,
+        ];
+        let frame_id = insert_frame_in_wanimage(image, 2, 2, &mut wanimage, 0)
+            .unwrap()
+            .unwrap();
+        let mask = FrameMask::new_from_frame(
+            &wanimage.frame_store.frames[frame_id],
+            &wanimage.fragment_bytes_store,
+        )
+        .unwrap();
+        assert_eq!((mask.width, mask.height), (8, 8));
+        assert_eq!((mask.origin_x, mask.origin_y), (1, 1));
+        assert!(mask.is_opaque(0, 0));
+        assert!(!mask.is_opaque(1, 0));
+        assert!(mask.is_opaque(1, 1));
+
+        let mut pbm = Vec::new();
+        mask.write_pbm(&mut pbm).unwrap();
+        let mut expected = b"P4\n8 8\n".to_vec();
+        expected.extend([0x80, 0x40, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(pbm, expected);
+    }
+}
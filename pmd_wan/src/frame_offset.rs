@@ -1,8 +1,11 @@
 use binread::BinRead;
 use binwrite::BinWrite;
 
+use crate::{Frame, WanImage};
+
 /// The coordinate of some point in the Pokémon, in the form of X then Y
-#[derive(BinWrite, BinRead, Debug, PartialEq, Eq, Clone)]
+#[derive(BinWrite, BinRead, Debug, PartialEq, Eq, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[binwrite(little)]
 #[br(little)]
 pub struct FrameOffset {
@@ -11,3 +14,103 @@ pub struct FrameOffset {
     pub hand_right: (i16, i16),
     pub center: (i16, i16),
 }
+
+/// One of the anchor points stored in a [`FrameOffset`]. The game use them to place held items and effects relative to the [`Frame`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum FrameOffsetPoint {
+    Head,
+    HandLeft,
+    HandRight,
+    Center,
+}
+
+impl FrameOffsetPoint {
+    /// Every point, in the order they are stored in the file
+    pub const ALL: [FrameOffsetPoint; 4] = [
+        FrameOffsetPoint::Head,
+        FrameOffsetPoint::HandLeft,
+        FrameOffsetPoint::HandRight,
+        FrameOffsetPoint::Center,
+    ];
+}
+
+impl FrameOffset {
+    /// Return the position of the given point, relative to the origin of the [`Frame`]
+    pub fn get(&self, point: FrameOffsetPoint) -> (i16, i16) {
+        match point {
+            FrameOffsetPoint::Head => self.head,
+            FrameOffsetPoint::HandLeft => self.hand_left,
+            FrameOffsetPoint::HandRight => self.hand_right,
+            FrameOffsetPoint::Center => self.center,
+        }
+    }
+
+    pub fn get_mut(&mut self, point: FrameOffsetPoint) -> &mut (i16, i16) {
+        match point {
+            FrameOffsetPoint::Head => &mut self.head,
+            FrameOffsetPoint::HandLeft => &mut self.hand_left,
+            FrameOffsetPoint::HandRight => &mut self.hand_right,
+            FrameOffsetPoint::Center => &mut self.center,
+        }
+    }
+
+    pub fn set(&mut self, point: FrameOffsetPoint, position: (i16, i16)) {
+        *self.get_mut(point) = position;
+    }
+
+    /// Iterate over every point with its position, in the order of [`FrameOffsetPoint::ALL`]
+    pub fn points(&self) -> impl Iterator<Item = (FrameOffsetPoint, (i16, i16))> + '_ {
+        FrameOffsetPoint::ALL
+            .iter()
+            .map(move |point| (*point, self.get(*point)))
+    }
+
+    /// Move every point by the given amount. Return [`None`] if a point would go out of the range of an [`i16`].
+    pub fn translated(&self, x: i16, y: i16) -> Option<FrameOffset> {
+        let mut result = self.clone();
+        for point in FrameOffsetPoint::ALL.iter().copied() {
+            let (point_x, point_y) = self.get(point);
+            result.set(point, (point_x.checked_add(x)?, point_y.checked_add(y)?));
+        }
+        Some(result)
+    }
+}
+
+impl Frame {
+    /// Return the position of the given anchor point, or [`None`] if this [`Frame`] has no [`FrameOffset`]
+    pub fn anchor(&self, point: FrameOffsetPoint) -> Option<(i16, i16)> {
+        self.frame_offset
+            .as_ref()
+            .map(|frame_offset| frame_offset.get(point))
+    }
+
+    /// Set the position of the given anchor point. If this [`Frame`] has no [`FrameOffset`] yet, a new one is created with all the other points at the origin.
+    pub fn set_anchor(&mut self, point: FrameOffsetPoint, position: (i16, i16)) {
+        self.frame_offset
+            .get_or_insert_with(FrameOffset::default)
+            .set(point, position);
+    }
+}
+
+impl WanImage {
+    /// Return the position of the anchor point of the given frame, or [`None`] if the frame doesn't exist or has no [`FrameOffset`]
+    pub fn frame_anchor(&self, frame_id: usize, point: FrameOffsetPoint) -> Option<(i16, i16)> {
+        self.frame_store.frames.get(frame_id)?.anchor(point)
+    }
+
+    /// Set the position of the anchor point of the given frame (see [`Frame::set_anchor`]). Return `false` if the frame doesn't exist.
+    pub fn set_frame_anchor(
+        &mut self,
+        frame_id: usize,
+        point: FrameOffsetPoint,
+        position: (i16, i16),
+    ) -> bool {
+        match self.frame_store.frames.get_mut(frame_id) {
+            Some(frame) => {
+                frame.set_anchor(point, position);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use image::{Rgba, RgbaImage};
+
+use crate::{FrameRenderError, WanImage};
+
+/// The color of the pixels only visible in the new image, in [`FramePixelDiff::image`]
+pub const DIFF_ADDED_COLOR: Rgba<u8> = Rgba([0, 255, 0, 255]);
+/// The color of the pixels only visible in the old image
+pub const DIFF_REMOVED_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+/// The color of the pixels visible in both images, but with another color
+pub const DIFF_MODIFIED_COLOR: Rgba<u8> = Rgba([255, 255, 0, 255]);
+
+/// The difference between two rendered frames, as returned by [`diff_frames`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FramePixelDiff {
+    /// The changed pixels in [`DIFF_ADDED_COLOR`], [`DIFF_REMOVED_COLOR`] or [`DIFF_MODIFIED_COLOR`], over the unchanged ones at a quarter of their opacity
+    pub image: RgbaImage,
+    /// The position of the origin of both frames in [`FramePixelDiff::image`]
+    pub origin: (i32, i32),
+    pub added_pixels: usize,
+    pub removed_pixels: usize,
+    pub modified_pixels: usize,
+}
+
+impl FramePixelDiff {
+    /// The total number of pixels that differ
+    pub fn changed_pixels(&self) -> usize {
+        self.added_pixels + self.removed_pixels + self.modified_pixels
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.changed_pixels() == 0
+    }
+}
+
+/// Compare two images pixel by pixel, each given with the position of the origin in it, so they are aligned on their origin.
+///
+/// Fully transparent pixels are all considered equal, whatever their color channels.
+pub fn diff_images(
+    old: &RgbaImage,
+    old_origin: (i32, i32),
+    new: &RgbaImage,
+    new_origin: (i32, i32),
+) -> FramePixelDiff {
+    let min_x = (-old_origin.0).min(-new_origin.0);
+    let min_y = (-old_origin.1).min(-new_origin.1);
+    let max_x = (old.width() as i32 - old_origin.0).max(new.width() as i32 - new_origin.0);
+    let max_y = (old.height() as i32 - old_origin.1).max(new.height() as i32 - new_origin.1);
+    let mut result = FramePixelDiff {
+        image: RgbaImage::new((max_x - min_x) as u32, (max_y - min_y) as u32),
+        origin: (-min_x, -min_y),
+        added_pixels: 0,
+        removed_pixels: 0,
+        modified_pixels: 0,
+    };
+    let get = |image: &RgbaImage, origin: (i32, i32), x: i32, y: i32| {
+        let x = x + origin.0;
+        let y = y + origin.1;
+        if x < 0 || y < 0 || x >= image.width() as i32 || y >= image.height() as i32 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let pixel = *image.get_pixel(x as u32, y as u32);
+        if pixel[3] == 0 {
+            Rgba([0, 0, 0, 0])
+        } else {
+            pixel
+        }
+    };
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let old_pixel = get(old, old_origin, x, y);
+            let new_pixel = get(new, new_origin, x, y);
+            let color = if old_pixel == new_pixel {
+                Rgba([new_pixel[0], new_pixel[1], new_pixel[2], new_pixel[3] / 4])
+            } else if old_pixel[3] == 0 {
+                result.added_pixels += 1;
+                DIFF_ADDED_COLOR
+            } else if new_pixel[3] == 0 {
+                result.removed_pixels += 1;
+                DIFF_REMOVED_COLOR
+            } else {
+                result.modified_pixels += 1;
+                DIFF_MODIFIED_COLOR
+            };
+            result
+                .image
+                .put_pixel((x - min_x) as u32, (y - min_y) as u32, color);
+        }
+    }
+    result
+}
+
+/// Render a frame of each [`WanImage`] (that may be the same) with [`WanImage::render_frame`], and compare them with [`diff_images`], aligned on the origin of the frames
+pub fn diff_frames(
+    old: &WanImage,
+    old_frame: usize,
+    new: &WanImage,
+    new_frame: usize,
+) -> Result<FramePixelDiff, FrameRenderError> {
+    let old_image = old.render_frame(old_frame)?;
+    let new_image = new.render_frame(new_frame)?;
+    // no panic: render_frame checked the frames exist
+    Ok(diff_images(
+        &old_image,
+        old.frame_store.frames[old_frame].render_origin(),
+        &new_image,
+        new.frame_store.frames[new_frame].render_origin(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diff_frames, insert_frame_in_wanimage, tests::fixtures::test_wan_image, SpriteType,
+        DIFF_ADDED_COLOR, DIFF_MODIFIED_COLOR, DIFF_REMOVED_COLOR,
+    };
+
+    #[test]
+    fn test_diff_frames() {
+        let mut old = test_wan_image(SpriteType::PropsUI);
+        old.palette.palette.push([0, 0, 255, 128]);
+        let mut pixels = vec![1; 64];
+        pixels[0] = 0;
+        insert_frame_in_wanimage(pixels.clone(), 8, 8, &mut old, 0).unwrap();
+        pixels[0] = 1;
+        pixels[1] = 2;
+        pixels[2] = 0;
+        insert_frame_in_wanimage(pixels, 8, 8, &mut old, 0).unwrap();
+
+        let same = diff_frames(&old, 0, &old, 0).unwrap();
+        assert!(same.is_identical());
+        assert_eq!(same.image.get_pixel(1, 0)[3], 255 / 4);
+
+        let diff = diff_frames(&old, 0, &old, 1).unwrap();
+        assert_eq!(diff.image.dimensions(), (8, 8));
+        assert_eq!(diff.origin, (4, 4));
+        assert_eq!(diff.changed_pixels(), 3);
+        assert_eq!(diff.image.get_pixel(0, 0), &DIFF_ADDED_COLOR);
+        assert_eq!(diff.image.get_pixel(1, 0), &DIFF_MODIFIED_COLOR);
+        assert_eq!(diff.image.get_pixel(2, 0), &DIFF_REMOVED_COLOR);
+        assert!(diff_frames(&old, 0, &old, 2).is_err());
+    }
+}
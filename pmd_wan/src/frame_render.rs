@@ -0,0 +1,265 @@
+use image::{Rgba, RgbaImage};
+use thiserror::Error;
+
+use crate::{
+    palette_file::component_to_8bit, Fragment, FragmentBytesStore, FragmentBytesToImageError,
+    FragmentFlip, Frame, GeneralResolution, Palette, WanImage,
+};
+
+#[derive(Error, Debug)]
+pub enum FrameRenderError {
+    #[error("The frame {0} doesn't exist")]
+    NoFrame(usize),
+    #[error("Failed to render a fragment")]
+    CantRenderFragment(#[from] FragmentBytesToImageError),
+}
+
+/// Convert a color stored in a [`Palette`] (with alpha ranging from 0 to 128) to a fully opaque-able RGBA color, with the components expanded to the 0-255 range by the default [`crate::ColorConversion`]
+pub(crate) fn palette_color_to_rgba(color: [u8; 4]) -> Rgba<u8> {
+    Rgba([
+        component_to_8bit(color[0]),
+        component_to_8bit(color[1]),
+        component_to_8bit(color[2]),
+        color[3].saturating_mul(2),
+    ])
+}
+
+impl Fragment {
+    /// Return the palette indices of this [`Fragment`], line by line from the top-left pixel, after its flip has been applied.
+    ///
+    /// The flip are applied like the DS does: `flip_h` mirror the columns, and `flip_v` mirror the lines.
+    pub fn get_flipped_pixels(
+        &self,
+        fragment_bytes_store: &FragmentBytesStore,
+    ) -> Result<Vec<u8>, FragmentBytesToImageError> {
+        self.get_flipped_pixels_with_depth(fragment_bytes_store, false)
+    }
+
+    /// Like [`Fragment::get_flipped_pixels`], but decode the pixels as 256 colors ones if `is_256_color` is set (see [`crate::WanImage::is_256_color`]).
+    pub fn get_flipped_pixels_with_depth(
+        &self,
+        fragment_bytes_store: &FragmentBytesStore,
+        is_256_color: bool,
+    ) -> Result<Vec<u8>, FragmentBytesToImageError> {
+        let fragment_bytes = fragment_bytes_store
+            .fragment_bytes
+            .get(self.fragment_bytes_index)
+            .ok_or(FragmentBytesToImageError::NoFragmentBytes(
+                self.fragment_bytes_index,
+            ))?;
+        let resolution = self.resolution.size();
+        let pixels = fragment_bytes.decode_pixels(resolution.clone(), is_256_color)?;
+        Ok(flip_pixels(&pixels, resolution, self.flip))
+    }
+}
+
+/// Flip the pixels (line by line, from the top-left pixel) like the DS does: `flip_h` mirror the columns, and `flip_v` mirror the lines.
+///
+/// Note that this isn't the same convention as [`FragmentFlip::apply`].
+pub(crate) fn flip_pixels(
+    pixels: &[u8],
+    resolution: GeneralResolution,
+    flip: FragmentFlip,
+) -> Vec<u8> {
+    let width = resolution.x as usize;
+    let height = resolution.y as usize;
+    let mut result = Vec::with_capacity(pixels.len());
+    for y in 0..height {
+        let source_y = if flip.flip_v { height - 1 - y } else { y };
+        for x in 0..width {
+            let source_x = if flip.flip_h { width - 1 - x } else { x };
+            result.push(pixels[source_y * width + source_x]);
+        }
+    }
+    result
+}
+
+impl Frame {
+    /// Draw this [`Frame`] on the target image, with the origin of the frame (the point the [`Fragment`] offsets are relative to) at `origin_x` and `origin_y`.
+    ///
+    /// The first [`Fragment`] is displayed on top of the following ones, like OAM entries on the DS.
+    /// Transparent pixels (color 0) leave the target untouched. Pixels outside of the target are ignored.
+    pub fn draw_on_image(
+        &self,
+        fragment_bytes_store: &FragmentBytesStore,
+        palette: &Palette,
+        target: &mut RgbaImage,
+        origin_x: i32,
+        origin_y: i32,
+    ) -> Result<(), FrameRenderError> {
+        self.draw_on_image_with_depth(
+            fragment_bytes_store,
+            palette,
+            false,
+            target,
+            origin_x,
+            origin_y,
+        )
+    }
+
+    /// Like [`Frame::draw_on_image`], but for 256 colors sprites if `is_256_color` is set. The palette of the [`Fragment`]s are then ignored, as the color index the whole [`Palette`].
+    pub fn draw_on_image_with_depth(
+        &self,
+        fragment_bytes_store: &FragmentBytesStore,
+        palette: &Palette,
+        is_256_color: bool,
+        target: &mut RgbaImage,
+        origin_x: i32,
+        origin_y: i32,
+    ) -> Result<(), FrameRenderError> {
+        for fragment in self.fragments.iter().rev() {
+            let pixels =
+                fragment.get_flipped_pixels_with_depth(fragment_bytes_store, is_256_color)?;
+            let resolution = fragment.resolution.size();
+            let start_x = origin_x + fragment.offset_x as i32;
+            let start_y = origin_y + fragment.offset_y as i32;
+            for (pixel_nb, pixel) in pixels.iter().copied().enumerate() {
+                if pixel == 0 {
+                    continue;
+                }
+                let x = start_x + (pixel_nb as u32 % resolution.x) as i32;
+                let y = start_y + (pixel_nb as u32 / resolution.x) as i32;
+                if x < 0 || y < 0 || x >= target.width() as i32 || y >= target.height() as i32 {
+                    continue;
+                }
+                let color = palette
+                    .get_with_depth(pixel, fragment.pal_idx, is_256_color)
+                    .ok_or(FragmentBytesToImageError::UnknownColor(
+                        pixel,
+                        fragment.pal_idx,
+                    ))?;
+                target.put_pixel(x as u32, y as u32, palette_color_to_rgba(color));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Frame {
+    /// Render this [`Frame`] to an image just big enough to contain all its [`Fragment`]s, with their offsets, flips and palettes applied.
+    /// The origin of the frame is at [`Frame::render_origin`] in the returned image. An empty frame give an empty image.
+    pub fn render(
+        &self,
+        fragment_bytes_store: &FragmentBytesStore,
+        palette: &Palette,
+    ) -> Result<RgbaImage, FrameRenderError> {
+        self.render_with_depth(fragment_bytes_store, palette, false)
+    }
+
+    /// Like [`Frame::render`], but for 256 colors sprites if `is_256_color` is set (see [`Frame::draw_on_image_with_depth`])
+    pub fn render_with_depth(
+        &self,
+        fragment_bytes_store: &FragmentBytesStore,
+        palette: &Palette,
+        is_256_color: bool,
+    ) -> Result<RgbaImage, FrameRenderError> {
+        let (min_x, min_y, max_x, max_y) = self.fragments_extent().unwrap_or((0, 0, 0, 0));
+        let mut image = RgbaImage::new((max_x - min_x) as u32, (max_y - min_y) as u32);
+        self.draw_on_image_with_depth(
+            fragment_bytes_store,
+            palette,
+            is_256_color,
+            &mut image,
+            -min_x,
+            -min_y,
+        )?;
+        Ok(image)
+    }
+
+    /// The position of the origin of the frame in the image returned by [`Frame::render`]
+    pub fn render_origin(&self) -> (i32, i32) {
+        let (min_x, min_y, _, _) = self.fragments_extent().unwrap_or((0, 0, 0, 0));
+        (-min_x, -min_y)
+    }
+
+    /// Return the area covered by the [`Fragment`]s of this frame, relative to its origin, as `(min_x, min_y, max_x, max_y)`, with the max being exclusive.
+    /// Return [`None`] if the frame has no [`Fragment`].
+    pub(crate) fn fragments_extent(&self) -> Option<(i32, i32, i32, i32)> {
+        let mut extent: Option<(i32, i32, i32, i32)> = None;
+        for fragment in &self.fragments {
+            let resolution = fragment.resolution.size();
+            let start_x = fragment.offset_x as i32;
+            let start_y = fragment.offset_y as i32;
+            let end_x = start_x + resolution.x as i32;
+            let end_y = start_y + resolution.y as i32;
+            extent = Some(match extent {
+                None => (start_x, start_y, end_x, end_y),
+                Some((min_x, min_y, max_x, max_y)) => (
+                    min_x.min(start_x),
+                    min_y.min(start_y),
+                    max_x.max(end_x),
+                    max_y.max(end_y),
+                ),
+            });
+        }
+        extent
+    }
+}
+
+impl WanImage {
+    /// Render the frame with the given id, as done by [`Frame::render`] (or [`Frame::render_with_depth`] for 256 colors sprites)
+    pub fn render_frame(&self, frame_id: usize) -> Result<RgbaImage, FrameRenderError> {
+        self.frame_store
+            .frames
+            .get(frame_id)
+            .ok_or(FrameRenderError::NoFrame(frame_id))?
+            .render_with_depth(&self.fragment_bytes_store, &self.palette, self.is_256_color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use crate::{
+        encode_fragment_pixels, tests::fixtures::test_wan_image, Fragment, FragmentBytes,
+        FragmentFlip, Frame, GeneralResolution, OamShape, SpriteType, DEFAULT_FRAGMENT_PRIORITY,
+    };
+
+    #[test]
+    fn test_render_frame_with_flip_and_offset() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.push([0, 0, 255, 128]);
+        let mut pixels = [0; 64];
+        pixels[0] = 1;
+        pixels[63] = 2;
+        wanimage
+            .fragment_bytes_store
+            .fragment_bytes
+            .push(FragmentBytes {
+                mixed_pixels: encode_fragment_pixels(&pixels, GeneralResolution::new(8, 8))
+                    .unwrap(),
+                z_index: 0,
+            });
+        let fragment = |offset_x, flip_h| Fragment {
+            unk1: 0,
+            unk3_4: None,
+            unk5: false,
+            fragment_bytes_index: 0,
+            offset_y: -4,
+            offset_x,
+            flip: FragmentFlip {
+                flip_h,
+                flip_v: false,
+            },
+            is_mosaic: false,
+            priority: DEFAULT_FRAGMENT_PRIORITY,
+            pal_idx: 0,
+            resolution: OamShape::new(0, 0).unwrap(),
+        };
+        wanimage.frame_store.frames.push(Frame {
+            fragments: vec![fragment(-8, false), fragment(0, true)],
+            frame_offset: None,
+        });
+
+        let image = wanimage.render_frame(0).unwrap();
+        assert_eq!(image.dimensions(), (16, 8));
+        assert_eq!(wanimage.frame_store.frames[0].render_origin(), (8, 4));
+        assert_eq!(image.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(7, 7), &Rgba([0, 0, 255, 255]));
+        // the second fragment is mirrored horizontally
+        assert_eq!(image.get_pixel(15, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(8, 7), &Rgba([0, 0, 255, 255]));
+        assert!(wanimage.render_frame(1).is_err());
+    }
+}
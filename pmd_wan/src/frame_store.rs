@@ -1,9 +1,13 @@
-use crate::{Frame, WanError};
+use crate::{
+    decode_options::check_limit, DecodeOptions, DecodeWarning, Frame, LocatedWanError, WanError,
+    WanSection,
+};
 use anyhow::Context;
 use byteorder::{ReadBytesExt, LE};
 use std::io::{Read, Seek, SeekFrom, Write};
 
 #[derive(PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FrameStore {
     pub frames: Vec<Frame>,
 }
@@ -14,8 +18,26 @@ impl FrameStore {
         file: &mut F,
         nb_frames: u64,
     ) -> Result<FrameStore, WanError> {
+        Self::new_from_bytes_with_options(
+            file,
+            nb_frames,
+            &DecodeOptions::default(),
+            &mut Vec::new(),
+        )
+        .map_err(|error| error.error)
+    }
+
+    /// Like [`FrameStore::new_from_bytes`], but a faulty frame only keep the fragments read before the error in lenient mode
+    pub(crate) fn new_from_bytes_with_options<F: Read + Seek>(
+        file: &mut F,
+        nb_frames: u64,
+        options: &DecodeOptions,
+        warnings: &mut Vec<DecodeWarning>,
+    ) -> Result<FrameStore, LocatedWanError> {
         let mut frames = Vec::new();
         let mut last_pointer = None;
+        let mut irregular_pointers = false;
+        check_limit(nb_frames as usize, options.limits.max_frames, "frames")?;
 
         let mut fragment_reference: Vec<u64> = Vec::new();
         for _ in 0..nb_frames {
@@ -24,13 +46,15 @@ impl FrameStore {
             match last_pointer {
                 None => last_pointer = Some(actual_ptr),
                 Some(value) => {
-                    if actual_ptr
-                        .checked_sub(value)
-                        .ok_or(WanError::InvalidOffset)?
-                        % 10
-                        != 0
+                    if !irregular_pointers
+                        && actual_ptr
+                            .checked_sub(value)
+                            .is_none_or(|delta| !delta.is_multiple_of(10))
                     {
-                        return Err(WanError::InvalidOffset);
+                        options.recover(WanError::InvalidOffset, warnings, |_| {
+                            DecodeWarning::IrregularFramePointers
+                        })?;
+                        irregular_pointers = true;
                     }
                 }
             };
@@ -43,8 +67,34 @@ impl FrameStore {
                 frame_id,
                 fragment_reference[frame_id as usize]
             );
-            file.seek(SeekFrom::Start(fragment_reference[frame_id as usize]))?;
-            frames.push(Frame::new_from_bytes(file)?);
+            let mut frame = Frame::default();
+            let result = file
+                .seek(SeekFrom::Start(fragment_reference[frame_id as usize]))
+                .map_err(WanError::from)
+                .and_then(|_| {
+                    Frame::read_fragments(
+                        file,
+                        &mut frame.fragments,
+                        options.limits.max_fragments_per_frame,
+                    )
+                });
+            if let Err(error) = result {
+                let kept = frame.fragments.len();
+                options
+                    .recover(error, warnings, |error| DecodeWarning::TruncatedFrame {
+                        frame: frame_id as usize,
+                        kept,
+                        error,
+                    })
+                    .map_err(|error| {
+                        LocatedWanError::from(error).locate(
+                            WanSection::Frames,
+                            Some(frame_id as usize),
+                            fragment_reference[frame_id as usize],
+                        )
+                    })?;
+            }
+            frames.push(frame);
         }
         Ok(FrameStore { frames })
     }
@@ -55,7 +105,7 @@ impl FrameStore {
         let mut size_to_allocate = 0;
 
         for frame in &self.frames {
-            frame_references.push(file.seek(SeekFrom::Current(0))? as u32);
+            frame_references.push(file.stream_position()? as u32);
             let local_size_to_allocate = frame
                 .write(file)
                 .with_context(move || format!("can't write the frame group {:?}", frame))?;
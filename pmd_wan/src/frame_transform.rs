@@ -0,0 +1,261 @@
+use std::convert::TryInto;
+
+use thiserror::Error;
+
+use crate::{
+    BoundingBox, FragmentBytes, FragmentBytesToImageError, Frame, FrameOffsetPoint, WanImage,
+    FLIP_STANDARD,
+};
+
+#[derive(Error, Debug)]
+pub enum FrameTransformError {
+    #[error("The frame {0} doesn't exist")]
+    NoFrame(usize),
+    #[error("The fragment {0} would be placed at an offset that can't be encoded")]
+    FragmentOutOfRange(usize),
+    #[error("A fragment would be placed at an offset that can't be encoded once mirrored")]
+    MirroredOutOfRange,
+    #[error("A point of the frame offset would be placed at a position that can't be encoded")]
+    FrameOffsetOutOfRange,
+    #[error("Failed to read the pixels of a fragment")]
+    CantReadFragment(#[from] FragmentBytesToImageError),
+}
+
+impl Frame {
+    /// Mirror this [`Frame`] horizontally around its origin, in place. See [`Frame::mirrored`].
+    ///
+    /// The frame is left untouched on error.
+    pub fn flip_horizontal(&mut self) -> Result<(), FrameTransformError> {
+        *self = self
+            .mirrored()
+            .ok_or(FrameTransformError::MirroredOutOfRange)?;
+        Ok(())
+    }
+
+    /// Mirror this [`Frame`] vertically around its origin, in place.
+    ///
+    /// The [`crate::Fragment`]s keep using the same [`FragmentBytes`], with their vertical flip inverted, and the y coordinates of the [`crate::FrameOffset`] are mirrored, saturating like [`Frame::mirrored`] does.
+    /// The frame is left untouched on error.
+    pub fn flip_vertical(&mut self) -> Result<(), FrameTransformError> {
+        let mut fragments = self.fragments.clone();
+        for (fragment_id, fragment) in fragments.iter_mut().enumerate() {
+            let offset_y = -(fragment.offset_y as i32 + fragment.resolution.size().y as i32);
+            fragment.offset_y = offset_y
+                .try_into()
+                .map_err(|_| FrameTransformError::FragmentOutOfRange(fragment_id))?;
+            fragment.flip.flip_v = !fragment.flip.flip_v;
+        }
+        if let Some(frame_offset) = &mut self.frame_offset {
+            for point in FrameOffsetPoint::ALL.iter().copied() {
+                let (x, y) = frame_offset.get(point);
+                frame_offset.set(point, (x, y.saturating_neg()));
+            }
+        }
+        self.fragments = fragments;
+        Ok(())
+    }
+
+    /// Move the content of this [`Frame`] by `dx` pixels to the right and `dy` pixels to the bottom, including the [`crate::FrameOffset`].
+    ///
+    /// The frame is left untouched on error.
+    pub fn translate(&mut self, dx: i32, dy: i32) -> Result<(), FrameTransformError> {
+        let mut fragments = self.fragments.clone();
+        for (fragment_id, fragment) in fragments.iter_mut().enumerate() {
+            let offset_x = fragment.offset_x as i32 + dx;
+            if !(-256..256).contains(&offset_x) {
+                return Err(FrameTransformError::FragmentOutOfRange(fragment_id));
+            }
+            fragment.offset_x = offset_x as i16;
+            fragment.offset_y = (fragment.offset_y as i32 + dy)
+                .try_into()
+                .map_err(|_| FrameTransformError::FragmentOutOfRange(fragment_id))?;
+        }
+        if let Some(frame_offset) = &self.frame_offset {
+            self.frame_offset = Some(
+                dx.try_into()
+                    .ok()
+                    .zip(dy.try_into().ok())
+                    .and_then(|(dx, dy)| frame_offset.translated(dx, dy))
+                    .ok_or(FrameTransformError::FrameOffsetOutOfRange)?,
+            );
+        }
+        self.fragments = fragments;
+        Ok(())
+    }
+}
+
+impl WanImage {
+    /// Remove the pixels of the frame `frame_id` outside of `area` (relative to the origin of the frame).
+    ///
+    /// The [`crate::Fragment`]s fully outside of `area` are removed. The ones partially outside of it get a new [`FragmentBytes`], with the pixels outside made transparent, so the [`FragmentBytes`] shared with other frames are left untouched.
+    /// If every [`crate::Fragment`] is removed, the frame can't be encoded anymore.
+    pub fn crop_frame(
+        &mut self,
+        frame_id: usize,
+        area: BoundingBox,
+    ) -> Result<(), FrameTransformError> {
+        let frame = self
+            .frame_store
+            .frames
+            .get(frame_id)
+            .ok_or(FrameTransformError::NoFrame(frame_id))?;
+        let mut fragments = Vec::with_capacity(frame.fragments.len());
+        let mut new_fragment_bytes = Vec::new();
+        for fragment in &frame.fragments {
+            let size = fragment.resolution.size();
+            let fragment_box = BoundingBox {
+                min_x: fragment.offset_x as i32,
+                min_y: fragment.offset_y as i32,
+                max_x: fragment.offset_x as i32 + size.x as i32,
+                max_y: fragment.offset_y as i32 + size.y as i32,
+            };
+            if fragment_box.max_x <= area.min_x
+                || fragment_box.min_x >= area.max_x
+                || fragment_box.max_y <= area.min_y
+                || fragment_box.min_y >= area.max_y
+            {
+                continue;
+            }
+            if fragment_box.min_x >= area.min_x
+                && fragment_box.max_x <= area.max_x
+                && fragment_box.min_y >= area.min_y
+                && fragment_box.max_y <= area.max_y
+            {
+                fragments.push(fragment.clone());
+                continue;
+            }
+            let mut pixels = fragment
+                .get_flipped_pixels_with_depth(&self.fragment_bytes_store, self.is_256_color)?;
+            for (pixel_id, pixel) in pixels.iter_mut().enumerate() {
+                let x = fragment_box.min_x + (pixel_id % size.x as usize) as i32;
+                let y = fragment_box.min_y + (pixel_id / size.x as usize) as i32;
+                if x < area.min_x || x >= area.max_x || y < area.min_y || y >= area.max_y {
+                    *pixel = 0;
+                }
+            }
+            if pixels.iter().all(|pixel| *pixel == 0) {
+                continue;
+            }
+            // no panic: the fragment bytes exist, as their pixels have been read
+            let z_index =
+                self.fragment_bytes_store.fragment_bytes[fragment.fragment_bytes_index].z_index;
+            // no panic: the pixels have been decoded with the same resolution
+            let fragment_bytes =
+                FragmentBytes::new_from_pixels(&pixels, size, self.is_256_color, z_index).unwrap();
+            let mut cropped = fragment.clone();
+            cropped.fragment_bytes_index =
+                self.fragment_bytes_store.len() + new_fragment_bytes.len();
+            cropped.flip = FLIP_STANDARD;
+            new_fragment_bytes.push(fragment_bytes);
+            fragments.push(cropped);
+        }
+        self.fragment_bytes_store
+            .fragment_bytes
+            .extend(new_fragment_bytes);
+        self.frame_store.frames[frame_id].fragments = fragments;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage, tests::fixtures::test_wan_image, BoundingBox, FrameOffset,
+        FrameTransformError, SpriteType, WanImage,
+    };
+
+    fn two_colors_wan_image() -> WanImage {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.push([0, 255, 0, 128]);
+        // an 16×8 image, with the top-left pixel in a different color
+        let mut pixels = vec![1; 128];
+        pixels[0] = 2;
+        insert_frame_in_wanimage(pixels, 16, 8, &mut wanimage, 0).unwrap();
+        wanimage
+    }
+
+    #[test]
+    fn test_flip_frame() {
+        let mut wanimage = two_colors_wan_image();
+        let original = wanimage.render_frame(0).unwrap();
+        let frame = &mut wanimage.frame_store.frames[0];
+        frame.frame_offset = Some(FrameOffset {
+            head: (1, 2),
+            hand_left: (0, -3),
+            hand_right: (0, 0),
+            center: (0, 0),
+        });
+        frame.flip_vertical().unwrap();
+        assert_eq!(frame.frame_offset.as_ref().unwrap().hand_left, (0, 3));
+        frame.flip_horizontal().unwrap();
+        assert_eq!(frame.frame_offset.as_ref().unwrap().head, (-1, -2));
+        let flipped = wanimage.render_frame(0).unwrap();
+        let (width, height) = original.dimensions();
+        for (x, y, pixel) in original.enumerate_pixels() {
+            assert_eq!(flipped.get_pixel(width - 1 - x, height - 1 - y), pixel);
+        }
+    }
+
+    #[test]
+    fn test_flip_frame_out_of_range() {
+        let mut wanimage = two_colors_wan_image();
+        let frame = &mut wanimage.frame_store.frames[0];
+        frame.fragments[0].offset_x = 250;
+        let before = frame.clone();
+        assert!(matches!(
+            frame.flip_horizontal(),
+            Err(FrameTransformError::MirroredOutOfRange)
+        ));
+        assert_eq!(*frame, before);
+
+        // the frame offset saturate on both axis
+        frame.frame_offset = Some(FrameOffset {
+            head: (i16::MIN, i16::MIN),
+            ..Default::default()
+        });
+        frame.flip_vertical().unwrap();
+        assert_eq!(
+            frame.frame_offset.as_ref().unwrap().head,
+            (i16::MIN, i16::MAX)
+        );
+    }
+
+    #[test]
+    fn test_translate_frame() {
+        let mut wanimage = two_colors_wan_image();
+        let frame = &mut wanimage.frame_store.frames[0];
+        let offset_x = frame.fragments[0].offset_x;
+        frame.translate(3, -2).unwrap();
+        assert_eq!(frame.fragments[0].offset_x, offset_x + 3);
+        let before = frame.clone();
+        assert!(matches!(
+            frame.translate(0, 200),
+            Err(FrameTransformError::FragmentOutOfRange(0))
+        ));
+        assert_eq!(*frame, before);
+    }
+
+    #[test]
+    fn test_crop_frame() {
+        let mut wanimage = two_colors_wan_image();
+        let bounding_box = wanimage.frame_bounding_box(0).unwrap().unwrap();
+        wanimage
+            .crop_frame(
+                0,
+                BoundingBox {
+                    max_x: bounding_box.min_x + 4,
+                    ..bounding_box
+                },
+            )
+            .unwrap();
+        let cropped = wanimage.frame_bounding_box(0).unwrap().unwrap();
+        assert_eq!(cropped.width(), 4);
+        assert_eq!(cropped.height(), 8);
+        // the original FragmentBytes is kept for the other frames that may use it
+        assert_eq!(wanimage.fragment_bytes_store.len(), 2);
+        assert!(matches!(
+            wanimage.crop_frame(1, bounding_box),
+            Err(FrameTransformError::NoFrame(1))
+        ));
+    }
+}
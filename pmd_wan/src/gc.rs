@@ -0,0 +1,165 @@
+use crate::WanImage;
+
+/// What [`WanImage::gc_unused`] removed. The remaps give the new index of every old element, or [`None`] if it was removed.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GcReport {
+    pub frame_remap: Vec<Option<usize>>,
+    pub fragment_bytes_remap: Vec<Option<usize>>,
+}
+
+impl GcReport {
+    /// The old index of the removed frames
+    pub fn removed_frames(&self) -> Vec<usize> {
+        removed(&self.frame_remap)
+    }
+
+    /// The old index of the removed [`crate::FragmentBytes`]
+    pub fn removed_fragment_bytes(&self) -> Vec<usize> {
+        removed(&self.fragment_bytes_remap)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frame_remap.iter().all(Option::is_some)
+            && self.fragment_bytes_remap.iter().all(Option::is_some)
+    }
+}
+
+fn removed(remap: &[Option<usize>]) -> Vec<usize> {
+    remap
+        .iter()
+        .enumerate()
+        .filter(|(_, new_index)| new_index.is_none())
+        .map(|(old_index, _)| old_index)
+        .collect()
+}
+
+/// Keep the elements marked as used, and return the new index of every element
+fn retain_used<T>(elements: &mut Vec<T>, used: &[bool]) -> Vec<Option<usize>> {
+    let mut remap = Vec::with_capacity(used.len());
+    let mut next_index = 0;
+    for is_used in used {
+        if *is_used {
+            remap.push(Some(next_index));
+            next_index += 1;
+        } else {
+            remap.push(None);
+        }
+    }
+    let mut used = used.iter();
+    elements.retain(|_| *used.next().unwrap_or(&true));
+    remap
+}
+
+impl WanImage {
+    /// Remove the frames not referenced by any [`crate::AnimationFrame`], then the [`crate::FragmentBytes`] not referenced by any [`crate::Fragment`] of the remaining frames, and update every index pointing to them.
+    ///
+    /// Frames are only removed if there is at least one [`crate::AnimationFrame`], as sprites without animation still display their frames directly.
+    pub fn gc_unused(&mut self) -> GcReport {
+        let mut frame_used = vec![false; self.frame_store.frames.len()];
+        let mut has_animation_frame = false;
+        for animation_frame in self
+            .animation_store
+            .anim_groups
+            .iter()
+            .flatten()
+            .flat_map(|animation| animation.frames.iter())
+        {
+            has_animation_frame = true;
+            if let Some(used) = frame_used.get_mut(animation_frame.frame_id as usize) {
+                *used = true;
+            }
+        }
+        let frame_remap = if has_animation_frame {
+            retain_used(&mut self.frame_store.frames, &frame_used)
+        } else {
+            (0..self.frame_store.frames.len()).map(Some).collect()
+        };
+        for animation_frame in self
+            .animation_store
+            .anim_groups
+            .iter_mut()
+            .flatten()
+            .flat_map(|animation| animation.frames.iter_mut())
+        {
+            if let Some(Some(new_frame_id)) = frame_remap.get(animation_frame.frame_id as usize) {
+                animation_frame.frame_id = *new_frame_id as u16;
+            }
+        }
+
+        let fragment_bytes_remap = self.gc_unused_fragment_bytes();
+        GcReport {
+            frame_remap,
+            fragment_bytes_remap,
+        }
+    }
+
+    /// Remove the [`crate::FragmentBytes`] not referenced by any [`crate::Fragment`], and update the index of the [`crate::Fragment`]s. Return the new index of every old [`crate::FragmentBytes`].
+    pub fn gc_unused_fragment_bytes(&mut self) -> Vec<Option<usize>> {
+        let mut used = vec![false; self.fragment_bytes_store.len()];
+        for fragment in self
+            .frame_store
+            .frames
+            .iter()
+            .flat_map(|frame| frame.fragments.iter())
+        {
+            if let Some(used) = used.get_mut(fragment.fragment_bytes_index) {
+                *used = true;
+            }
+        }
+        let remap = retain_used(&mut self.fragment_bytes_store.fragment_bytes, &used);
+        for fragment in self
+            .frame_store
+            .frames
+            .iter_mut()
+            .flat_map(|frame| frame.fragments.iter_mut())
+        {
+            if let Some(Some(new_index)) = remap.get(fragment.fragment_bytes_index) {
+                fragment.fragment_bytes_index = *new_index;
+            }
+        }
+        remap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage, tests::fixtures::animation_frame,
+        tests::fixtures::test_wan_image, Animation, SpriteType,
+    };
+
+    #[test]
+    fn test_gc_unused() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.push([0, 255, 0, 128]);
+        for color in [1, 2, 1] {
+            let mut pixels = vec![0; 64];
+            pixels[color as usize] = color;
+            insert_frame_in_wanimage(pixels, 8, 8, &mut wanimage, 0).unwrap();
+        }
+        assert_eq!(wanimage.fragment_bytes_store.len(), 2);
+        // not referenced by anything
+        wanimage
+            .fragment_bytes_store
+            .fragment_bytes
+            .push(wanimage.fragment_bytes_store.fragment_bytes[0].clone());
+        let rendered = wanimage.render_frame(2).unwrap();
+
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![animation_frame(2, 1), animation_frame(2, 1)],
+        }]);
+
+        let report = wanimage.gc_unused();
+        assert_eq!(report.removed_frames(), vec![0, 1]);
+        assert_eq!(report.removed_fragment_bytes(), vec![1, 2]);
+        assert_eq!(report.frame_remap[2], Some(0));
+        assert_eq!(wanimage.frame_store.frames.len(), 1);
+        assert_eq!(wanimage.fragment_bytes_store.len(), 1);
+        assert_eq!(
+            wanimage.animation_store.anim_groups[0][0].frames[1].frame_id,
+            0
+        );
+        assert_eq!(wanimage.render_frame(0).unwrap(), rendered);
+        assert!(wanimage.gc_unused().is_empty());
+    }
+}
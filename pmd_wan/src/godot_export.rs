@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::{FrameRenderError, TextureAtlas, WanImage, ANIMATION_FRAMES_PER_SECOND};
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl WanImage {
+    /// Generate a Godot 4 `SpriteFrames` resource (a `.tres` file) with one animation per [`crate::AtlasSequence`] of `atlas`, whose image is loaded from `texture_path` (like `res://sprite.png`).
+    ///
+    /// `atlas` should have been created by [`WanImage::export_texture_atlas`] on this image.
+    /// Each frame is an `AtlasTexture` with a margin placing it at its offset, on a canvas shared by every animation with the origin at its center, so an `AnimatedSprite2D` with the default `centered` setting show the origin at its position.
+    /// The speed of each animation is the highest frame rate that represent all its durations exactly.
+    pub fn export_godot_sprite_frames(
+        &self,
+        atlas: &TextureAtlas,
+        texture_path: &str,
+    ) -> Result<String, FrameRenderError> {
+        let (mut half_width, mut half_height) = (1, 1);
+        for sequence in &atlas.sequences {
+            for animation_frame in &sequence.frames {
+                let frame_id = animation_frame.frame_id as usize;
+                let frame = self
+                    .frame_store
+                    .frames
+                    .get(frame_id)
+                    .ok_or(FrameRenderError::NoFrame(frame_id))?;
+                let atlas_frame = atlas
+                    .frames
+                    .get(frame_id)
+                    .ok_or(FrameRenderError::NoFrame(frame_id))?;
+                let (origin_x, origin_y) = frame.render_origin();
+                let min_x = animation_frame.offset_x as i32 - origin_x;
+                let min_y = animation_frame.offset_y as i32 - origin_y;
+                for x in [min_x, min_x + atlas_frame.width as i32] {
+                    half_width = half_width.max(x.abs());
+                }
+                for y in [min_y, min_y + atlas_frame.height as i32] {
+                    half_height = half_height.max(y.abs());
+                }
+            }
+        }
+
+        // one AtlasTexture for each frame and offset pair
+        let mut textures: HashMap<(u16, i16, i16), usize> = HashMap::new();
+        let mut sub_resources = String::new();
+        let mut animations = Vec::with_capacity(atlas.sequences.len());
+        for sequence in &atlas.sequences {
+            let step = sequence
+                .frames
+                .iter()
+                .fold(0, |step, frame| gcd(step, frame.duration as u32))
+                .max(1);
+            let mut frames = Vec::with_capacity(sequence.frames.len());
+            for animation_frame in &sequence.frames {
+                let key = (
+                    animation_frame.frame_id,
+                    animation_frame.offset_x,
+                    animation_frame.offset_y,
+                );
+                let texture_id = match textures.get(&key) {
+                    Some(texture_id) => *texture_id,
+                    None => {
+                        let texture_id = textures.len();
+                        let frame_id = animation_frame.frame_id as usize;
+                        // no panic: checked when computing the canvas
+                        let atlas_frame = &atlas.frames[frame_id];
+                        let (origin_x, origin_y) =
+                            self.frame_store.frames[frame_id].render_origin();
+                        let left = half_width + animation_frame.offset_x as i32 - origin_x;
+                        let top = half_height + animation_frame.offset_y as i32 - origin_y;
+                        write!(
+                            sub_resources,
+                            "[sub_resource type=\"AtlasTexture\" id=\"AtlasTexture_{}\"]\natlas = ExtResource(\"1\")\nregion = Rect2({}, {}, {}, {})\nmargin = Rect2({}, {}, {}, {})\n\n",
+                            texture_id,
+                            atlas_frame.x,
+                            atlas_frame.y,
+                            atlas_frame.width,
+                            atlas_frame.height,
+                            left,
+                            top,
+                            half_width * 2 - atlas_frame.width as i32,
+                            half_height * 2 - atlas_frame.height as i32
+                        )
+                        .unwrap();
+                        textures.insert(key, texture_id);
+                        texture_id
+                    }
+                };
+                frames.push(format!(
+                    "{{\n\"duration\": {:.1},\n\"texture\": SubResource(\"AtlasTexture_{}\")\n}}",
+                    (animation_frame.duration as u32 / step) as f32,
+                    texture_id
+                ));
+            }
+            animations.push(format!(
+                "{{\n\"frames\": [{}],\n\"loop\": true,\n\"name\": &\"{}\",\n\"speed\": {:.1}\n}}",
+                frames.join(", "),
+                sequence.name,
+                ANIMATION_FRAMES_PER_SECOND as f32 / step as f32
+            ));
+        }
+
+        let mut tres = String::new();
+        writeln!(
+            tres,
+            "[gd_resource type=\"SpriteFrames\" load_steps={} format=3]\n",
+            textures.len() + 2
+        )
+        .unwrap();
+        writeln!(
+            tres,
+            "[ext_resource type=\"Texture2D\" path=\"{}\" id=\"1\"]\n",
+            texture_path.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+        .unwrap();
+        tres.push_str(&sub_resources);
+        writeln!(tres, "[resource]\nanimations = [{}]", animations.join(", ")).unwrap();
+        Ok(tres)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::fixtures::{animation_frame, single_frame_wan_image},
+        Animation, AnimationFrame,
+    };
+
+    #[test]
+    fn test_export_godot_sprite_frames() {
+        let mut wanimage = single_frame_wan_image();
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![
+                animation_frame(0, 4),
+                AnimationFrame {
+                    offset_x: 2,
+                    ..animation_frame(0, 6)
+                },
+                animation_frame(0, 4),
+            ],
+        }]);
+        let atlas = wanimage.export_texture_atlas(None).unwrap();
+        let tres = wanimage
+            .export_godot_sprite_frames(&atlas, "res://sprite.png")
+            .unwrap();
+        assert!(tres.starts_with("[gd_resource type=\"SpriteFrames\" load_steps=4 format=3]\n\n"));
+        assert!(
+            tres.contains("[ext_resource type=\"Texture2D\" path=\"res://sprite.png\" id=\"1\"]")
+        );
+        // the canvas go from -6 to 6 horizontally, to contain the frame moved by 2 pixels
+        assert!(tres.contains("region = Rect2(0, 0, 8, 8)\nmargin = Rect2(2, 0, 4, 0)\n"));
+        assert!(tres.contains("region = Rect2(0, 0, 8, 8)\nmargin = Rect2(4, 0, 4, 0)\n"));
+        assert!(!tres.contains("AtlasTexture_2"));
+        // the durations are multiples of 2 ticks, so 30 frames per second
+        assert!(tres.contains("\"name\": &\"Walk_0\",\n\"speed\": 30.0\n"));
+        assert!(tres.contains("\"duration\": 3.0,\n\"texture\": SubResource(\"AtlasTexture_1\")"));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{FragmentBytesStore, FragmentBytesToImageError, Frame, FrameRenderError, WanImage};
+
+/// The [`crate::Fragment`] visible at a point of a [`Frame`], as returned by [`Frame::pick_fragment`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FragmentHit {
+    /// The index of the [`crate::Fragment`] in [`Frame::fragments`]
+    pub fragment: usize,
+    /// The position of the pixel in the [`crate::FragmentBytes`] of the fragment, before its flip is applied, so it can be edited directly
+    pub pixel_x: u32,
+    pub pixel_y: u32,
+    /// The palette index of the pixel (never 0, as transparent pixels can't be picked)
+    pub color: u8,
+}
+
+impl Frame {
+    /// Return the topmost [`crate::Fragment`] with an opaque pixel at the given position, relative to the origin of the frame.
+    /// Return [`None`] if every fragment is transparent there.
+    ///
+    /// The fragments are searched in the order [`Frame::draw_on_image`] stack them: the first one is on top, whatever its [`crate::Fragment::priority`].
+    pub fn pick_fragment(
+        &self,
+        fragment_bytes_store: &FragmentBytesStore,
+        is_256_color: bool,
+        x: i32,
+        y: i32,
+    ) -> Result<Option<FragmentHit>, FragmentBytesToImageError> {
+        for (fragment_id, fragment) in self.fragments.iter().enumerate() {
+            let resolution = fragment.resolution.size();
+            let local_x = x - fragment.offset_x as i32;
+            let local_y = y - fragment.offset_y as i32;
+            if local_x < 0
+                || local_y < 0
+                || local_x >= resolution.x as i32
+                || local_y >= resolution.y as i32
+            {
+                continue;
+            }
+            let pixel_x = if fragment.flip.flip_h {
+                resolution.x - 1 - local_x as u32
+            } else {
+                local_x as u32
+            };
+            let pixel_y = if fragment.flip.flip_v {
+                resolution.y - 1 - local_y as u32
+            } else {
+                local_y as u32
+            };
+            let pixels = fragment_bytes_store
+                .fragment_bytes
+                .get(fragment.fragment_bytes_index)
+                .ok_or(FragmentBytesToImageError::NoFragmentBytes(
+                    fragment.fragment_bytes_index,
+                ))?
+                .decode_pixels(resolution.clone(), is_256_color)?;
+            let color = pixels[(pixel_y * resolution.x + pixel_x) as usize];
+            if color != 0 {
+                return Ok(Some(FragmentHit {
+                    fragment: fragment_id,
+                    pixel_x,
+                    pixel_y,
+                    color,
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl WanImage {
+    /// Like [`Frame::pick_fragment`], but with the position of a pixel in the image returned by [`WanImage::render_frame`]
+    pub fn pick_fragment_in_render(
+        &self,
+        frame_id: usize,
+        x: u32,
+        y: u32,
+    ) -> Result<Option<FragmentHit>, FrameRenderError> {
+        let frame = self
+            .frame_store
+            .frames
+            .get(frame_id)
+            .ok_or(FrameRenderError::NoFrame(frame_id))?;
+        let (origin_x, origin_y) = frame.render_origin();
+        Ok(frame.pick_fragment(
+            &self.fragment_bytes_store,
+            self.is_256_color,
+            x as i32 - origin_x,
+            y as i32 - origin_y,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        encode_fragment_pixels, tests::fixtures::test_wan_image, Fragment, FragmentBytes,
+        FragmentFlip, FragmentHit, Frame, GeneralResolution, OamShape, SpriteType,
+        DEFAULT_FRAGMENT_PRIORITY,
+    };
+
+    #[test]
+    fn test_pick_fragment() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.push([0, 0, 255, 128]);
+        let mut pixels = [1; 64];
+        pixels[0] = 0;
+        pixels[7] = 2;
+        wanimage
+            .fragment_bytes_store
+            .fragment_bytes
+            .push(FragmentBytes {
+                mixed_pixels: encode_fragment_pixels(&pixels, GeneralResolution::new(8, 8))
+                    .unwrap(),
+                z_index: 0,
+            });
+        let fragment = |offset_x, flip_h, priority| Fragment {
+            unk1: 0,
+            unk3_4: None,
+            unk5: false,
+            fragment_bytes_index: 0,
+            offset_y: 0,
+            offset_x,
+            flip: FragmentFlip {
+                flip_h,
+                flip_v: false,
+            },
+            is_mosaic: false,
+            priority,
+            pal_idx: 0,
+            resolution: OamShape::new(0, 0).unwrap(),
+        };
+        // the second fragment overlap the right half of the first one, but is behind it
+        wanimage.frame_store.frames.push(Frame {
+            fragments: vec![
+                fragment(0, false, DEFAULT_FRAGMENT_PRIORITY),
+                fragment(4, true, DEFAULT_FRAGMENT_PRIORITY),
+            ],
+            frame_offset: None,
+        });
+        let frame = &wanimage.frame_store.frames[0];
+        let pick = |x, y| {
+            frame
+                .pick_fragment(&wanimage.fragment_bytes_store, false, x, y)
+                .unwrap()
+        };
+        assert_eq!(pick(0, 0), None);
+        assert_eq!(
+            pick(7, 0),
+            Some(FragmentHit {
+                fragment: 0,
+                pixel_x: 7,
+                pixel_y: 0,
+                color: 2
+            })
+        );
+        // the mirrored transparent pixel of the second fragment is at x = 11
+        assert_eq!(pick(11, 0), None);
+        assert_eq!(
+            pick(10, 0),
+            Some(FragmentHit {
+                fragment: 1,
+                pixel_x: 1,
+                pixel_y: 0,
+                color: 1
+            })
+        );
+        assert_eq!(pick(12, 0), None);
+
+        // the priority is ignored, like when rendering
+        wanimage.frame_store.frames[0].fragments[1].priority = 0;
+        assert_eq!(
+            wanimage
+                .pick_fragment_in_render(0, 7, 0)
+                .unwrap()
+                .map(|hit| (hit.fragment, hit.pixel_x)),
+            Some((0, 7))
+        );
+        assert_eq!(
+            wanimage.render_frame(0).unwrap().get_pixel(7, 0).0,
+            [0, 0, 255, 255]
+        );
+        assert!(wanimage.pick_fragment_in_render(1, 0, 0).is_err());
+    }
+}
@@ -1,13 +1,16 @@
 use crate::{
-    encode_fragment_pixels, Fragment, FragmentBytes, FragmentFlip, FragmentResolution, Frame,
-    WanImage,
+    find_fragment_layout, flip_duplicates::FlipDuplicates, Fragment, FragmentBytes, FragmentFlip,
+    FragmentLayoutOptions, Frame, GeneralResolution, OamShape, WanImage, DEFAULT_FRAGMENT_PRIORITY,
 };
 use anyhow::{bail, Context};
-use std::convert::TryInto;
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::{TryFrom, TryInto},
+};
 
 /// Images with no pixel are valid, but it is guarantted that width*height == buffer.len()
 #[derive(Debug, PartialEq, Eq)]
-struct ImageBuffer {
+pub(crate) struct ImageBuffer {
     buffer: Vec<u8>,
     width: u16,
     height: u16,
@@ -80,9 +83,10 @@ impl ImageBuffer {
         }
         let mut number_of_cut_line = 0;
         'main: for line_nb in (0..self.height).rev() {
-            for pixel_nb in (line_nb * self.width)..((line_nb + 1) * self.width) {
+            let width = self.width as usize;
+            for pixel_nb in (line_nb as usize * width)..((line_nb as usize + 1) * width) {
                 //no panic: pixel_nb should always be in the appropriate range
-                if self.buffer[pixel_nb as usize] != 0 {
+                if self.buffer[pixel_nb] != 0 {
                     break 'main;
                 };
             }
@@ -154,6 +158,22 @@ impl ImageBuffer {
         }
         ImageBuffer::new_from_vec(buffer, width, height).unwrap()
     }
+
+    /// Like [`ImageBuffer::get_fragment`], but the area may start outside of the image. Pixels outside of the image are 0.
+    pub fn get_area(&self, start_x: i32, start_y: i32, width: u16, height: u16) -> ImageBuffer {
+        let mut buffer = Vec::with_capacity(width as usize * height as usize);
+        for y in start_y..start_y + height as i32 {
+            for x in start_x..start_x + width as i32 {
+                let pixel = if x < 0 || y < 0 {
+                    None
+                } else {
+                    self.get_pixel(x as u16, y as u16)
+                };
+                buffer.push(pixel.unwrap_or(0));
+            }
+        }
+        ImageBuffer::new_from_vec(buffer, width, height).unwrap()
+    }
 }
 
 pub fn insert_frame_in_wanimage(
@@ -197,7 +217,109 @@ pub fn insert_frame_in_wanimage(
     })
 }
 
-fn insert_fragment_pos_in_wan_image(
+/// Like [`insert_frame_in_wanimage`], but the fully transparent borders of the whole image are cropped before it is cut into [`Fragment`]s, so the 64×64 sections are aligned on the visible pixels instead of the canvas.
+///
+/// The fragments are positioned so the frame is rendered at the same place as with [`insert_frame_in_wanimage`], relative to the center of the canvas.
+/// As only the visible part need to fit in a frame, the canvas itself may be bigger than 512×256.
+pub fn insert_frame_in_wanimage_trimmed(
+    image: Vec<u8>,
+    width: u16,
+    height: u16,
+    wanimage: &mut WanImage,
+    pal_id: u16,
+) -> anyhow::Result<Option<usize>> {
+    let mut image_buffer = ImageBuffer::new_from_vec(image, width, height)
+        .context("The input image don't correspond to the dimension of it")?;
+    let cut_top = image_buffer.cut_top();
+    image_buffer.cut_bottom();
+    let cut_left = image_buffer.cut_left();
+    image_buffer.cut_right();
+    if image_buffer.buffer().is_empty() {
+        return Ok(None);
+    }
+    if image_buffer.height() >= 256 {
+        bail!("The visible part of the image has a height of {}, while only image with a height inferior to 256 can be used", image_buffer.height());
+    }
+    if image_buffer.width() >= 512 {
+        bail!(
+            "The visible part of the image has a width of {}, while only image with a width less than 512 can be used",
+            image_buffer.width()
+        );
+    }
+    let position_x = -(width as i32) / 2 + cut_left as i32;
+    let position_y = -(height as i32) / 2 + cut_top as i32;
+
+    let fragments = if let Some(fragments) =
+        insert_fragment_pos_in_wan_image(wanimage, pal_id, &image_buffer, position_x, position_y)?
+    {
+        fragments
+    } else {
+        return Ok(None);
+    };
+
+    let frame_id = wanimage.frame_store.frames.len();
+    wanimage.frame_store.frames.push(Frame {
+        fragments,
+        frame_offset: None,
+    });
+    Ok(Some(frame_id))
+}
+
+/// Like [`insert_frame_in_wanimage`], but cut the image into [`Fragment`]s following the layout found by [`find_fragment_layout`], instead of the faster greedy placement.
+pub fn insert_frame_in_wanimage_with_layout(
+    image: Vec<u8>,
+    width: u16,
+    height: u16,
+    wanimage: &mut WanImage,
+    pal_id: u16,
+    options: &FragmentLayoutOptions,
+) -> anyhow::Result<Option<usize>> {
+    if height >= 256 {
+        bail!("The height of the image is {}, while only image with a height inferior to 256 can be used", height);
+    }
+    if width >= 512 {
+        bail!(
+            "The width of the image is {}, while only image with a width less than 512 can be used",
+            width
+        );
+    }
+    let position_x = -(width as i32) / 2;
+    let position_y = -(height as i32) / 2;
+    let image_buffer = ImageBuffer::new_from_vec(image, width, height)
+        .context("The input image don't correspond to the dimension of it")?;
+
+    let layout = find_fragment_layout(image_buffer.buffer(), width, height, options)?;
+    let mut fragments = Vec::with_capacity(layout.placements.len());
+    for placement in &layout.placements {
+        let size = placement.shape.size();
+        let mut cut_section =
+            image_buffer.get_area(placement.x, placement.y, size.x as u16, size.y as u16);
+        let fragment_y = position_y + placement.y + cut_section.cut_top() as i32;
+        cut_section.cut_bottom();
+        let fragment_x = position_x + placement.x + cut_section.cut_left() as i32;
+        cut_section.cut_right();
+        fragments.push(insert_cut_section(
+            wanimage,
+            pal_id,
+            &cut_section,
+            fragment_x,
+            fragment_y,
+        )?);
+    }
+
+    Ok(if !fragments.is_empty() {
+        let frame_id = wanimage.frame_store.frames.len();
+        wanimage.frame_store.frames.push(Frame {
+            fragments,
+            frame_offset: None,
+        });
+        Some(frame_id)
+    } else {
+        None
+    })
+}
+
+pub(crate) fn insert_fragment_pos_in_wan_image(
     wanimage: &mut WanImage,
     pal_id: u16,
     image_buffer: &ImageBuffer,
@@ -208,12 +330,8 @@ fn insert_fragment_pos_in_wan_image(
 
     // Chunk the image into 64x64 group, the max meta frame size
     const MAX_META_FRAME_SIZE: u16 = 64;
-    for fragment_segment_x in
-        0..(image_buffer.width() + MAX_META_FRAME_SIZE - 1) / MAX_META_FRAME_SIZE
-    {
-        for fragment_segment_y in
-            0..(image_buffer.height() + MAX_META_FRAME_SIZE - 1) / MAX_META_FRAME_SIZE
-        {
+    for fragment_segment_x in 0..image_buffer.width().div_ceil(MAX_META_FRAME_SIZE) {
+        for fragment_segment_y in 0..image_buffer.height().div_ceil(MAX_META_FRAME_SIZE) {
             let mut fragment_x =
                 upper_image_x + MAX_META_FRAME_SIZE as i32 * fragment_segment_x as i32;
             let mut fragment_y =
@@ -235,41 +353,13 @@ fn insert_fragment_pos_in_wan_image(
                 continue;
             }
 
-            //no panic: resolution should always be less than 64x64, and be an already valid resolution, to which it can fall back if no smaller images are avalaible
-            let fragment_size = FragmentResolution::find_smaller_containing(FragmentResolution {
-                x: cut_section.width() as u8,
-                y: cut_section.height() as u8,
-            })
-            .unwrap();
-
-            let buffer_to_write =
-                cut_section.get_fragment(0, 0, fragment_size.x as u16, fragment_size.y as u16, 0);
-
-            let image_bytes_index = wanimage.fragment_bytes_store.fragment_bytes.len();
-            wanimage
-                .fragment_bytes_store
-                .fragment_bytes
-                .push(FragmentBytes {
-                    mixed_pixels: encode_fragment_pixels(buffer_to_write.buffer(), fragment_size)
-                        .context(
-                        "failed to encode the input byte. This is an internal error",
-                    )?,
-                    z_index: 1,
-                });
-
-            let offset_y = fragment_y.try_into().context("The image is too large")?;
-            fragments.push(Fragment {
-                unk1: 0,
-                unk3_4: None,
-                unk5: false,
-                fragment_bytes_index: image_bytes_index,
-                offset_y,
-                offset_x: fragment_x.try_into().context("The image is too high")?,
-                flip: FragmentFlip::standard(),
-                is_mosaic: false,
-                pal_idx: pal_id,
-                resolution: fragment_size,
-            });
+            fragments.push(insert_cut_section(
+                wanimage,
+                pal_id,
+                &cut_section,
+                fragment_x,
+                fragment_y,
+            )?);
         }
     }
 
@@ -280,6 +370,165 @@ fn insert_fragment_pos_in_wan_image(
     Ok(Some(fragments))
 }
 
+/// Add the pixels of `cut_section` (which should have been cut on all sides, and contain at least one pixel) as a new [`Fragment`] with the top-left pixel at the given position, reusing an existing [`FragmentBytes`] if possible.
+fn insert_cut_section(
+    wanimage: &mut WanImage,
+    pal_id: u16,
+    cut_section: &ImageBuffer,
+    mut fragment_x: i32,
+    mut fragment_y: i32,
+) -> anyhow::Result<Fragment> {
+    //no panic: resolution should always be less than 64x64, and be an already valid resolution, to which it can fall back if no smaller images are avalaible
+    let fragment_size = OamShape::find_smallest_containing(GeneralResolution::new(
+        cut_section.width() as u32,
+        cut_section.height() as u32,
+    ))
+    .unwrap();
+    let fragment_resolution = fragment_size.size();
+
+    let buffer_to_write = cut_section.get_fragment(
+        0,
+        0,
+        fragment_resolution.x as u16,
+        fragment_resolution.y as u16,
+        0,
+    );
+
+    let reused = find_flipped_fragment_bytes(wanimage, cut_section, fragment_size).filter(
+        |(_, _, start_x, start_y)| {
+            i16::try_from(fragment_x - start_x).is_ok()
+                && i8::try_from(fragment_y - start_y).is_ok()
+        },
+    );
+    let (image_bytes_index, flip) = if let Some((index, flip, start_x, start_y)) = reused {
+        fragment_x -= start_x;
+        fragment_y -= start_y;
+        (index, flip)
+    } else {
+        let fragment_bytes = FragmentBytes::new_from_pixels(
+            buffer_to_write.buffer(),
+            fragment_resolution,
+            wanimage.is_256_color,
+            1,
+        )
+        .context("failed to encode the input byte. This is an internal error")?;
+        wanimage
+            .fragment_bytes_store
+            .fragment_bytes
+            .push(fragment_bytes);
+        (
+            wanimage.fragment_bytes_store.fragment_bytes.len() - 1,
+            FragmentFlip::standard(),
+        )
+    };
+
+    let offset_y = fragment_y.try_into().context("The image is too large")?;
+    Ok(Fragment {
+        unk1: 0,
+        unk3_4: None,
+        unk5: false,
+        fragment_bytes_index: image_bytes_index,
+        offset_y,
+        offset_x: fragment_x.try_into().context("The image is too high")?,
+        flip,
+        is_mosaic: false,
+        priority: DEFAULT_FRAGMENT_PRIORITY,
+        pal_idx: pal_id,
+        resolution: fragment_size,
+    })
+}
+
+/// Find a [`FragmentBytes`] already used by a [`Fragment`] with the same shape, that display the pixels of `cut_section` once flipped.
+/// Return its index, the flip to use, and the position of the top-left pixel of `cut_section` inside of the flipped [`FragmentBytes`].
+fn find_flipped_fragment_bytes(
+    wanimage: &WanImage,
+    cut_section: &ImageBuffer,
+    shape: OamShape,
+) -> Option<(usize, FragmentFlip, i32, i32)> {
+    let resolution = shape.size();
+    // the sum of the stored halves of bytes doesn't change with flips, and is used to quickly discard most candidates
+    let pixels_sum: u32 = if wanimage.is_256_color {
+        cut_section
+            .buffer()
+            .iter()
+            .map(|pixel| (pixel >> 4) as u32 + (pixel & 0x0F) as u32)
+            .sum()
+    } else {
+        cut_section.buffer().iter().map(|pixel| *pixel as u32).sum()
+    };
+    let mut checked = BTreeSet::new();
+    let mut flip_duplicates = FlipDuplicates::new();
+    // the position and size of the visible pixels of the candidates, without their transparent borders
+    let mut visible_areas = HashMap::new();
+    for fragment in wanimage
+        .frame_store
+        .frames
+        .iter()
+        .flat_map(|frame| frame.fragments.iter())
+    {
+        if fragment.resolution != shape || !checked.insert(fragment.fragment_bytes_index) {
+            continue;
+        }
+        let fragment_bytes = match wanimage
+            .fragment_bytes_store
+            .fragment_bytes
+            .get(fragment.fragment_bytes_index)
+        {
+            Some(fragment_bytes) => fragment_bytes,
+            None => continue,
+        };
+        if fragment_bytes
+            .mixed_pixels
+            .iter()
+            .map(|x| *x as u32)
+            .sum::<u32>()
+            != pixels_sum
+        {
+            continue;
+        }
+        let existing = match fragment_bytes.decode_pixels(resolution.clone(), wanimage.is_256_color)
+        {
+            Ok(existing) => existing,
+            Err(_) => continue,
+        };
+        // no panic: the decoded pixels always match the resolution
+        let mut visible =
+            ImageBuffer::new_from_vec(existing, resolution.x as u16, resolution.y as u16).unwrap();
+        let start_y = visible.cut_top();
+        visible.cut_bottom();
+        let start_x = visible.cut_left();
+        visible.cut_right();
+        visible_areas.insert(
+            fragment.fragment_bytes_index,
+            (start_x, start_y, visible.width(), visible.height()),
+        );
+        flip_duplicates.insert(
+            (visible.width(), visible.height()),
+            visible.buffer,
+            fragment.fragment_bytes_index,
+        );
+    }
+
+    let (index, flip) = flip_duplicates.find(
+        &(cut_section.width(), cut_section.height()),
+        cut_section.buffer(),
+        GeneralResolution::new(cut_section.width() as u32, cut_section.height() as u32),
+    )?;
+    let (start_x, start_y, width, height) = visible_areas[&index];
+    // where the visible pixels end up once the whole FragmentBytes is flipped
+    let start_x = if flip.flip_h {
+        resolution.x as usize - start_x - width as usize
+    } else {
+        start_x
+    };
+    let start_y = if flip.flip_v {
+        resolution.y as usize - start_y - height as usize
+    } else {
+        start_y
+    };
+    Some((index, flip, start_x as i32, start_y as i32))
+}
+
 #[test]
 fn imagebuffer_cut_test() {
     // (image_buffer, x_src, y_src, target_buffer, x_target, y_target, cut_top, cut_bottom, cut_left, cut_right)
@@ -364,7 +613,7 @@ fn insert_frame_flat_test() {
         .unwrap();
     let frame = &wanimage.frame_store.frames[frame_id];
     let fragment = &frame.fragments[0];
-    assert_eq!(fragment.resolution, FragmentResolution { x: 8, y: 8 });
+    assert_eq!(fragment.resolution, OamShape::new(0, 0).unwrap());
     assert_eq!(fragment.pal_idx, 0);
 }
 
@@ -375,3 +624,114 @@ fn insert_empty_image_test() {
         .unwrap()
         .is_none());
 }
+
+#[test]
+fn insert_frame_reuse_flipped_fragment_test() {
+    let mut wanimage = crate::tests::fixtures::test_wan_image(crate::SpriteType::PropsUI);
+    wanimage.palette.palette.push([0, 0, 255, 128]);
+    #[rustfmt::skip]
+    let image = vec![
+        1, 2, 0, 0,
+        1, 0, 0, 0,
+    ];
+    #[rustfmt::skip]
+    let mirrored = vec![
+        0, 0, 2, 1,
+        0, 0, 0, 1,
+    ];
+    let first = insert_frame_in_wanimage(image, 4, 2, &mut wanimage, 0)
+        .unwrap()
+        .unwrap();
+    let second = insert_frame_in_wanimage(mirrored, 4, 2, &mut wanimage, 0)
+        .unwrap()
+        .unwrap();
+    assert_eq!(wanimage.fragment_bytes_store.len(), 1);
+    let fragment = &wanimage.frame_store.frames[second].fragments[0];
+    assert_eq!(fragment.flip, FragmentFlip::from_bools(false, true));
+
+    let first_image = wanimage.render_frame(first).unwrap();
+    let second_image = wanimage.render_frame(second).unwrap();
+    assert_eq!(image::imageops::flip_horizontal(&first_image), second_image);
+    assert_eq!(
+        wanimage.frame_bounding_box(second).unwrap(),
+        wanimage
+            .frame_bounding_box(first)
+            .unwrap()
+            .map(|first_box| first_box.translate(2, 0))
+    );
+
+    #[rustfmt::skip]
+    let upside_down = vec![
+        1, 0, 0, 0,
+        1, 2, 0, 0,
+    ];
+    let third = insert_frame_in_wanimage(upside_down, 4, 2, &mut wanimage, 0)
+        .unwrap()
+        .unwrap();
+    assert_eq!(wanimage.fragment_bytes_store.len(), 1);
+    let third_image = wanimage.render_frame(third).unwrap();
+    assert_eq!(image::imageops::flip_vertical(&first_image), third_image);
+    assert_eq!(
+        wanimage.frame_bounding_box(third).unwrap(),
+        wanimage.frame_bounding_box(first).unwrap()
+    );
+}
+
+#[test]
+fn insert_frame_trimmed_test() {
+    let mut wanimage = crate::tests::fixtures::test_wan_image(crate::SpriteType::PropsUI);
+    // a 16×16 square straddling the 64×64 sections of a big canvas
+    let (width, height) = (600, 200);
+    let mut image = vec![0; width * height];
+    for y in 56..72 {
+        for x in 56..72 {
+            image[y * width + x] = 1;
+        }
+    }
+    assert!(insert_frame_in_wanimage(image.clone(), 600, 200, &mut wanimage, 0).is_err());
+    let frame_id = insert_frame_in_wanimage_trimmed(image, 600, 200, &mut wanimage, 0)
+        .unwrap()
+        .unwrap();
+    let frame = &wanimage.frame_store.frames[frame_id];
+    assert_eq!(frame.fragments.len(), 1);
+    assert_eq!(
+        frame.fragments[0].resolution.size(),
+        GeneralResolution::new(16, 16)
+    );
+    assert_eq!(frame.fragments[0].offset_x, 56 - 300);
+    assert_eq!(frame.fragments[0].offset_y, 56 - 100);
+
+    assert_eq!(
+        insert_frame_in_wanimage_trimmed(vec![0; 4], 2, 2, &mut wanimage, 0).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn insert_frame_with_layout_test() {
+    let chunks = |wanimage: &WanImage| -> u16 {
+        wanimage.frame_store.frames[0]
+            .fragments
+            .iter()
+            .map(|fragment| fragment.resolution.chunk_to_allocate_for_fragment())
+            .sum()
+    };
+    let mut greedy = WanImage::new(crate::SpriteType::PropsUI);
+    insert_frame_in_wanimage(vec![1; 16 * 64], 16, 64, &mut greedy, 0).unwrap();
+
+    let mut wanimage = crate::tests::fixtures::test_wan_image(crate::SpriteType::PropsUI);
+    let options = FragmentLayoutOptions {
+        goal: crate::FragmentLayoutGoal::MinimumVram,
+        ..Default::default()
+    };
+    let frame_id =
+        insert_frame_in_wanimage_with_layout(vec![1; 16 * 64], 16, 64, &mut wanimage, 0, &options)
+            .unwrap()
+            .unwrap();
+    // the greedy placement use a single 32×64 fragment
+    assert_eq!(chunks(&greedy), 8);
+    assert_eq!(chunks(&wanimage), 4);
+    let image = wanimage.render_frame(frame_id).unwrap();
+    assert_eq!(image.dimensions(), (16, 64));
+    assert!(image.pixels().all(|pixel| pixel.0 == [255, 0, 0, 255]));
+}
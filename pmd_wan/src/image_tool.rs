@@ -1,6 +1,15 @@
-use std::{collections::HashMap, convert::TryInto};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    io::{Read, Write},
+};
 
-use image::{GenericImageView, Rgba};
+use image::{GenericImageView, Rgba, RgbaImage};
+use png::{
+    BitDepth, BlendOp, ColorType, Decoder, DecodingError, Encoder, EncodingError, Transformations,
+};
+
+use crate::{frame_render::palette_color_to_rgba, Palette, ANIMATION_FRAMES_PER_SECOND};
 
 pub struct ImageToPaletteBytesData {
     pub map: HashMap<[u8; 4], u8>,
@@ -29,24 +38,562 @@ impl ImageToPaletteBytesData {
         };
         self.map.insert(color.0, number);
         self.ordered.push(color.0);
-        Some(self.map.len() as u8)
+        Some(number)
+    }
+}
+
+/// How the transparent pixels of an RGBA image are recognized when converting it to palette indices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransparencyOptions {
+    /// Pixels with an alpha strictly less than this are transparent. Pixels with an alpha of 0 are always transparent.
+    pub alpha_threshold: u8,
+    /// If set, the pixels of this RGB color are also transparent, whatever their alpha. This is useful for images without an alpha channel, that often use magenta (`[255, 0, 255]`) for this.
+    pub color_key: Option<[u8; 3]>,
+}
+
+impl Default for TransparencyOptions {
+    /// Only the fully opaque pixels are kept
+    fn default() -> Self {
+        Self {
+            alpha_threshold: 255,
+            color_key: None,
+        }
+    }
+}
+
+impl TransparencyOptions {
+    pub fn is_transparent(&self, color: Rgba<u8>) -> bool {
+        let [red, green, blue, alpha] = color.0;
+        alpha == 0 || alpha < self.alpha_threshold || self.color_key == Some([red, green, blue])
     }
 }
 
 /// Transform an [`ImageBuffer`] to a list of bytes (its pixels from top left to bottom right, line by line).
 /// The [`ImageToPaletteBytesData`] can be used on multiple image to make sure the same color have the same palette id.
 /// None is returned if the palette have reach its limit of 255 different color.
+///
+/// Only the fully opaque pixels are kept, see [`image_to_paletted_bytes_with_transparency`] to change this.
 pub fn image_to_paletted_bytes<I: GenericImageView<Pixel = Rgba<u8>>>(
     palette_data: &mut ImageToPaletteBytesData,
     img: &I,
+) -> Option<Vec<u8>> {
+    image_to_paletted_bytes_with_transparency(palette_data, img, &TransparencyOptions::default())
+}
+
+/// Like [`image_to_paletted_bytes`], but the transparent pixels (that all get the id 0) are chosen with the [`TransparencyOptions`].
+pub fn image_to_paletted_bytes_with_transparency<I: GenericImageView<Pixel = Rgba<u8>>>(
+    palette_data: &mut ImageToPaletteBytesData,
+    img: &I,
+    transparency: &TransparencyOptions,
 ) -> Option<Vec<u8>> {
     let mut result = Vec::with_capacity(img.width() as usize * img.height() as usize);
     for (_, _, color) in img.pixels() {
         let mut color = color;
-        if color.0[3] != 255 {
+        if transparency.is_transparent(color) {
             color = Rgba::from([0, 0, 0, 0])
         }
         result.push(palette_data.get_or_insert_id_for_color(color)?);
     }
     Some(result)
 }
+
+/// Write a looping APNG from images of the same size, each displayed for the given duration (in 1/60th of second, like [`crate::AnimationFrame::duration`]).
+/// Unlike GIF, APNG store the exact duration and the full alpha channel.
+/// Such images can be obtained with [`crate::Animation::render_frames`].
+pub fn write_apng<W: Write>(frames: &[(RgbaImage, u8)], writer: W) -> Result<(), EncodingError> {
+    let (width, height) = frames
+        .first()
+        .map(|(image, _)| image.dimensions())
+        .unwrap_or((1, 1));
+    let mut encoder = Encoder::new(writer, width, height);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for (image, duration) in frames {
+        writer.set_frame_delay(*duration as u16, ANIMATION_FRAMES_PER_SECOND as u16)?;
+        writer.set_blend_op(BlendOp::Source)?;
+        writer.write_image_data(image.as_raw())?;
+    }
+    writer.finish()
+}
+
+/// Draw `source` on `target` with its top-left corner at `x` and `y`, blending them with the alpha of `source` multiplied by `opacity` (from 0 to 1).
+/// Pixels outside of `target` are ignored.
+pub fn blend_over(target: &mut RgbaImage, source: &RgbaImage, x: i32, y: i32, opacity: f32) {
+    let opacity = opacity.clamp(0.0, 1.0);
+    for (source_x, source_y, color) in source.enumerate_pixels() {
+        let target_x = x + source_x as i32;
+        let target_y = y + source_y as i32;
+        if target_x < 0
+            || target_y < 0
+            || target_x >= target.width() as i32
+            || target_y >= target.height() as i32
+        {
+            continue;
+        }
+        let source_alpha = color[3] as f32 / 255.0 * opacity;
+        if source_alpha == 0.0 {
+            continue;
+        }
+        let under = target.get_pixel_mut(target_x as u32, target_y as u32);
+        let under_alpha = under[3] as f32 / 255.0;
+        let alpha = source_alpha + under_alpha * (1.0 - source_alpha);
+        for channel in 0..3 {
+            under[channel] = ((color[channel] as f32 * source_alpha
+                + under[channel] as f32 * under_alpha * (1.0 - source_alpha))
+                / alpha)
+                .round() as u8;
+        }
+        under[3] = (alpha * 255.0).round() as u8;
+    }
+}
+
+/// Return a copy of the image with the color of every pixel replaced by `tint`, keeping their alpha
+pub fn tint_image(image: &RgbaImage, tint: [u8; 3]) -> RgbaImage {
+    let mut result = image.clone();
+    for pixel in result.pixels_mut() {
+        *pixel = Rgba([tint[0], tint[1], tint[2], pixel[3]]);
+    }
+    result
+}
+
+/// Draw several images on a canvas just big enough to contain all of them, with their origins at the same place. The first one is at the bottom.
+///
+/// Each image is given with the position of its origin in it (like [`crate::Frame::render_origin`] for [`crate::Frame::render`]), so a sprite can be drawn over its shadow, or a sprite of another [`crate::WanImage`].
+/// Return the canvas and the position of the common origin in it.
+pub fn overlay_on_origin(layers: &[(&RgbaImage, (i32, i32))]) -> (RgbaImage, (i32, i32)) {
+    let mut extent: Option<(i32, i32, i32, i32)> = None;
+    for (image, (origin_x, origin_y)) in layers {
+        let layer = (
+            -origin_x,
+            -origin_y,
+            image.width() as i32 - origin_x,
+            image.height() as i32 - origin_y,
+        );
+        extent = Some(match extent {
+            None => layer,
+            Some(extent) => (
+                extent.0.min(layer.0),
+                extent.1.min(layer.1),
+                extent.2.max(layer.2),
+                extent.3.max(layer.3),
+            ),
+        });
+    }
+    let (min_x, min_y, max_x, max_y) = extent.unwrap_or((0, 0, 0, 0));
+    let mut canvas = RgbaImage::new((max_x - min_x) as u32, (max_y - min_y) as u32);
+    for (image, (origin_x, origin_y)) in layers {
+        blend_over(
+            &mut canvas,
+            image,
+            -min_x - origin_x,
+            -min_y - origin_y,
+            1.0,
+        );
+    }
+    (canvas, (-min_x, -min_y))
+}
+
+/// How the neighbouring frames are shown by [`render_onion_skin`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnionSkinOptions {
+    /// The number of previous frames to show
+    pub previous: usize,
+    /// The number of next frames to show
+    pub next: usize,
+    /// The opacity (from 0 to 1) of the nearest frames. The frame at a distance `n` have `opacity / n`.
+    pub opacity: f32,
+    /// The color the previous frames are tinted in, if any
+    pub previous_tint: Option<[u8; 3]>,
+    /// The color the next frames are tinted in, if any
+    pub next_tint: Option<[u8; 3]>,
+}
+
+impl Default for OnionSkinOptions {
+    /// The previous frame in red and the next frame in green, at 30% opacity
+    fn default() -> Self {
+        Self {
+            previous: 1,
+            next: 1,
+            opacity: 0.3,
+            previous_tint: Some([255, 0, 0]),
+            next_tint: Some([0, 255, 0]),
+        }
+    }
+}
+
+/// Draw the frame `current` of `frames` over the ghosts of the previous and next ones, as set by `options`. The frames don't wrap around.
+///
+/// The frames should all have the same size, like the ones returned by [`crate::Animation::render_frames`]. Return [`None`] if `current` is out of bounds.
+pub fn render_onion_skin(
+    frames: &[RgbaImage],
+    current: usize,
+    options: &OnionSkinOptions,
+) -> Option<RgbaImage> {
+    let current_image = frames.get(current)?;
+    let mut result = RgbaImage::new(current_image.width(), current_image.height());
+    let mut ghosts: Vec<(usize, usize, Option<[u8; 3]>)> = Vec::new();
+    for distance in 1..=options.previous {
+        if let Some(index) = current.checked_sub(distance) {
+            ghosts.push((distance, index, options.previous_tint));
+        }
+    }
+    for distance in 1..=options.next {
+        if current + distance < frames.len() {
+            ghosts.push((distance, current + distance, options.next_tint));
+        }
+    }
+    // the farthest frames are drawn first, so the nearest are above them
+    ghosts.sort_by_key(|(distance, _, _)| std::cmp::Reverse(*distance));
+    for (distance, index, tint) in ghosts {
+        let ghost = match tint {
+            Some(tint) => tint_image(&frames[index], tint),
+            None => frames[index].clone(),
+        };
+        blend_over(&mut result, &ghost, 0, 0, options.opacity / distance as f32);
+    }
+    blend_over(&mut result, current_image, 0, 0, 1.0);
+    Some(result)
+}
+
+/// How [`upscale_image`] fill the added pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    /// Each pixel become a square of the same color
+    NearestNeighbor,
+    /// Apply Scale2x for each factor of 2 and Scale3x for each factor of 3, which smooth the diagonal edges of the pixel art without adding new colors.
+    /// The remaining factor (as for 5 or 7) use [`UpscaleFilter::NearestNeighbor`].
+    ///
+    /// Without the `smart_upscale` feature, the whole image is enlarged with [`UpscaleFilter::NearestNeighbor`] instead.
+    Smart,
+}
+
+/// Enlarge the image `factor` times (a factor of 0 is treated as 1), so small sprites are still visible on high resolution screens
+pub fn upscale_image(image: &RgbaImage, factor: u32, filter: UpscaleFilter) -> RgbaImage {
+    let factor = factor.max(1);
+    match filter {
+        UpscaleFilter::NearestNeighbor => upscale_nearest(image, factor),
+        #[cfg(not(feature = "smart_upscale"))]
+        UpscaleFilter::Smart => upscale_nearest(image, factor),
+        #[cfg(feature = "smart_upscale")]
+        UpscaleFilter::Smart => {
+            let mut result = image.clone();
+            let mut remaining = factor;
+            while remaining.is_multiple_of(2) {
+                result = scale2x(&result);
+                remaining /= 2;
+            }
+            while remaining.is_multiple_of(3) {
+                result = scale3x(&result);
+                remaining /= 3;
+            }
+            upscale_nearest(&result, remaining)
+        }
+    }
+}
+
+fn upscale_nearest(image: &RgbaImage, factor: u32) -> RgbaImage {
+    if factor == 1 {
+        return image.clone();
+    }
+    RgbaImage::from_fn(image.width() * factor, image.height() * factor, |x, y| {
+        *image.get_pixel(x / factor, y / factor)
+    })
+}
+
+/// Return the pixel at the given position, with the coordinates clamped to the image
+#[cfg(feature = "smart_upscale")]
+fn clamped_pixel(image: &RgbaImage, x: i64, y: i64) -> Rgba<u8> {
+    *image.get_pixel(
+        x.clamp(0, image.width() as i64 - 1) as u32,
+        y.clamp(0, image.height() as i64 - 1) as u32,
+    )
+}
+
+#[cfg(feature = "smart_upscale")]
+fn scale2x(image: &RgbaImage) -> RgbaImage {
+    let mut result = RgbaImage::new(image.width() * 2, image.height() * 2);
+    for (x, y, center) in image.enumerate_pixels() {
+        let (x, y) = (x as i64, y as i64);
+        let up = clamped_pixel(image, x, y - 1);
+        let right = clamped_pixel(image, x + 1, y);
+        let left = clamped_pixel(image, x - 1, y);
+        let down = clamped_pixel(image, x, y + 1);
+        let pick = |first: Rgba<u8>, second: Rgba<u8>, other_first: Rgba<u8>, other_second| {
+            if first == second && first != other_first && second != other_second {
+                first
+            } else {
+                *center
+            }
+        };
+        let outputs = [
+            pick(left, up, down, right),
+            pick(up, right, left, down),
+            pick(down, left, right, up),
+            pick(right, down, up, left),
+        ];
+        for (index, pixel) in outputs.iter().enumerate() {
+            result.put_pixel(
+                x as u32 * 2 + (index % 2) as u32,
+                y as u32 * 2 + (index / 2) as u32,
+                *pixel,
+            );
+        }
+    }
+    result
+}
+
+#[cfg(feature = "smart_upscale")]
+fn scale3x(image: &RgbaImage) -> RgbaImage {
+    let mut result = RgbaImage::new(image.width() * 3, image.height() * 3);
+    for (x, y, _) in image.enumerate_pixels() {
+        let (xi, yi) = (x as i64, y as i64);
+        // the 3×3 neighbourhood, named as in the description of the algorithm
+        let [a, b, c, d, e, f, g, h, i] = [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (0, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ]
+        .map(|(dx, dy)| clamped_pixel(image, xi + dx, yi + dy));
+        let top_left = d == b && b != f && d != h;
+        let top_right = b == f && b != d && f != h;
+        let bottom_left = d == h && d != b && h != f;
+        let bottom_right = h == f && d != h && b != f;
+        let outputs = [
+            if top_left { d } else { e },
+            if (top_left && e != c) || (top_right && e != a) {
+                b
+            } else {
+                e
+            },
+            if top_right { f } else { e },
+            if (top_left && e != g) || (bottom_left && e != a) {
+                d
+            } else {
+                e
+            },
+            e,
+            if (top_right && e != i) || (bottom_right && e != c) {
+                f
+            } else {
+                e
+            },
+            if bottom_left { d } else { e },
+            if (bottom_left && e != i) || (bottom_right && e != g) {
+                h
+            } else {
+                e
+            },
+            if bottom_right { f } else { e },
+        ];
+        for (index, pixel) in outputs.iter().enumerate() {
+            result.put_pixel(
+                x * 3 + (index % 3) as u32,
+                y * 3 + (index / 3) as u32,
+                *pixel,
+            );
+        }
+    }
+    result
+}
+
+/// Write palette indices (line by line, from the top-left pixel) as an 8 bits indexed PNG, with the colors of the [`Palette`] in the same order, so the indices are preserved exactly.
+/// The alpha of the palette is stored in the tRNS chunk, converted to the 0-255 range.
+pub fn write_indexed_png<W: Write>(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    palette: &Palette,
+    writer: W,
+) -> Result<(), EncodingError> {
+    let mut encoder = Encoder::new(writer, width, height);
+    encoder.set_color(ColorType::Indexed);
+    encoder.set_depth(BitDepth::Eight);
+    let colors: Vec<[u8; 4]> = palette
+        .palette
+        .iter()
+        .take(256)
+        .map(|color| palette_color_to_rgba(*color).0)
+        .collect();
+    // a palette can't be empty
+    let colors = if colors.is_empty() {
+        vec![[0, 0, 0, 0]]
+    } else {
+        colors
+    };
+    encoder.set_palette(
+        colors
+            .iter()
+            .flat_map(|color| color[0..3].iter().copied())
+            .collect::<Vec<u8>>(),
+    );
+    encoder.set_trns(colors.iter().map(|color| color[3]).collect::<Vec<u8>>());
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()
+}
+
+/// An image made of palette indices, as read by [`read_indexed_png`]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct IndexedImage {
+    /// Line by line, from the top-left pixel
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// The RGBA colors of the palette of the image (alpha from 0 to 255)
+    pub palette: Vec<[u8; 4]>,
+}
+
+/// Read an indexed PNG, keeping the palette indices as is.
+/// Return [`None`] if the PNG isn't an indexed one.
+pub fn read_indexed_png<R: Read>(reader: R) -> Result<Option<IndexedImage>, DecodingError> {
+    let mut decoder = Decoder::new(reader);
+    decoder.set_transformations(Transformations::IDENTITY);
+    let mut reader = decoder.read_info()?;
+    let info = reader.info();
+    if info.color_type != ColorType::Indexed {
+        return Ok(None);
+    }
+    let (width, height) = (info.width, info.height);
+    let bits = info.bit_depth as u8 as usize;
+    let rgb = info.palette.as_deref().unwrap_or(&[]);
+    let trns = info.trns.as_deref().unwrap_or(&[]);
+    let palette = rgb
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(index, color)| {
+            [
+                color[0],
+                color[1],
+                color[2],
+                trns.get(index).copied().unwrap_or(255),
+            ]
+        })
+        .collect();
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame_info = reader.next_frame(&mut buffer)?;
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for line in buffer
+        .chunks_exact(frame_info.line_size)
+        .take(height as usize)
+    {
+        for x in 0..width as usize {
+            let bit_position = x * bits;
+            let byte = line[bit_position / 8];
+            let shift = 8 - bits - bit_position % 8;
+            pixels.push((byte >> shift) & ((1u16 << bits) - 1) as u8);
+        }
+    }
+    Ok(Some(IndexedImage {
+        pixels,
+        width,
+        height,
+        palette,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use crate::{
+        image_tool::{
+            overlay_on_origin, render_onion_skin, upscale_image, ImageToPaletteBytesData,
+            OnionSkinOptions, UpscaleFilter,
+        },
+        tests::fixtures::{animation_frame, insert_filled_frame, test_wan_image},
+        Animation, AnimationFrame, SpriteType,
+    };
+
+    #[test]
+    fn test_get_or_insert_id_for_color() {
+        let mut palette_data = ImageToPaletteBytesData::default();
+        let red = Rgba([255, 0, 0, 255]);
+        let green = Rgba([0, 255, 0, 255]);
+        // the id of a new color is its position in the ordered list, starting after the transparent one
+        assert_eq!(palette_data.get_or_insert_id_for_color(red), Some(1));
+        assert_eq!(palette_data.get_or_insert_id_for_color(green), Some(2));
+        assert_eq!(palette_data.get_or_insert_id_for_color(red), Some(1));
+        assert_eq!(palette_data.ordered[1], red.0);
+        assert_eq!(palette_data.ordered[2], green.0);
+    }
+
+    #[test]
+    fn test_onion_skin_and_overlay() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        insert_filled_frame(&mut wanimage, 2, 2);
+        let animation = Animation {
+            frames: [0, 2, 4]
+                .iter()
+                .map(|offset_x| AnimationFrame {
+                    offset_x: *offset_x,
+                    ..animation_frame(0, 1)
+                })
+                .collect(),
+        };
+        let frames: Vec<_> = animation
+            .render_frames(&wanimage)
+            .unwrap()
+            .into_iter()
+            .map(|(image, _)| image)
+            .collect();
+
+        let options = OnionSkinOptions {
+            opacity: 0.5,
+            ..Default::default()
+        };
+        let onion = render_onion_skin(&frames, 1, &options).unwrap();
+        // the previous frame in red, the current one fully opaque, the next one in green
+        assert_eq!(onion.get_pixel(0, 0), &Rgba([255, 0, 0, 128]));
+        assert_eq!(onion.get_pixel(2, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(onion.get_pixel(4, 0), &Rgba([0, 255, 0, 128]));
+        assert_eq!(onion.get_pixel(6, 0), &Rgba([0, 0, 0, 0]));
+        assert!(render_onion_skin(&frames, 3, &options).is_none());
+
+        let frame = wanimage.render_frame(0).unwrap();
+        let origin = wanimage.frame_store.frames[0].render_origin();
+        let (overlay, overlay_origin) =
+            overlay_on_origin(&[(&frame, origin), (&frame, (origin.0 + 4, origin.1))]);
+        assert_eq!(overlay.dimensions(), (12, 8));
+        assert_eq!(overlay_origin, (origin.0 + 4, origin.1));
+        assert_eq!(overlay.get_pixel(4, 0), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_upscale_image() {
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        // a black diagonal on a white background
+        let image = image::RgbaImage::from_fn(3, 3, |x, y| if x == y { black } else { white });
+        let scaled = upscale_image(&image, 2, UpscaleFilter::NearestNeighbor);
+        assert_eq!(scaled.dimensions(), (6, 6));
+        assert_eq!(scaled.get_pixel(3, 3), &black);
+        assert_eq!(scaled.get_pixel(2, 1), &white);
+        assert_eq!(
+            upscale_image(&image, 0, UpscaleFilter::NearestNeighbor),
+            image
+        );
+
+        #[cfg(feature = "smart_upscale")]
+        {
+            // the staircase of the diagonal is smoothed
+            let smart = upscale_image(&image, 2, UpscaleFilter::Smart);
+            assert_eq!(smart.get_pixel(2, 1), &black);
+            assert_eq!(smart.get_pixel(3, 3), &black);
+            let smart = upscale_image(&image, 3, UpscaleFilter::Smart);
+            assert_eq!(smart.dimensions(), (9, 9));
+            assert_eq!(smart.get_pixel(3, 2), &black);
+            assert_eq!(smart.get_pixel(8, 0), &white);
+            assert_eq!(upscale_image(&image, 5, UpscaleFilter::Smart).width(), 15);
+        }
+        #[cfg(not(feature = "smart_upscale"))]
+        assert_eq!(upscale_image(&image, 2, UpscaleFilter::Smart), scaled);
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::{BTreeSet, HashMap};
+
+use thiserror::Error;
+
+use crate::{Animation, WanImage, MAX_FRAGMENT_PALETTE_INDEX};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ImportError {
+    #[error("The frame {0} doesn't exist in the source image")]
+    NoFrame(usize),
+    #[error("The animation group {0} doesn't exist in the source image")]
+    NoGroup(usize),
+    #[error("The animation {animation} doesn't exist in the animation group {group} of the source image")]
+    NoAnimation { group: usize, animation: usize },
+    #[error("The frame {frame} of the source image use the FragmentBytes {fragment_bytes}, which doesn't exist")]
+    NoFragmentBytes { frame: usize, fragment_bytes: usize },
+    #[error("One of the image use 256 colors, but not the other")]
+    ColorDepthMismatch,
+    #[error("The images use 256 colors, but their palette are different")]
+    PaletteMismatch,
+    #[error("The imported frames need more palettes than the {} that can be used", MAX_FRAGMENT_PALETTE_INDEX + 1)]
+    TooManyPalettes,
+    #[error("There would be more frames than can be referenced by an animation")]
+    TooManyFrames,
+}
+
+/// Return the 16 colors of the given palette row, padded with transparent colors
+fn palette_row(palette: &[[u8; 4]], row: u16) -> Vec<[u8; 4]> {
+    let start = row as usize * 16;
+    (start..start + 16)
+        .map(|color_id| palette.get(color_id).copied().unwrap_or([0, 0, 0, 0]))
+        .collect()
+}
+
+/// Copy frames from one [`WanImage`] to another, remembering what has already been copied so shared [`crate::FragmentBytes`] and frames are only copied once
+pub(crate) struct Importer<'a> {
+    source: &'a WanImage,
+    frames: HashMap<usize, usize>,
+    fragment_bytes: HashMap<usize, usize>,
+    palette_rows: HashMap<u16, u16>,
+}
+
+impl<'a> Importer<'a> {
+    pub(crate) fn new(source: &'a WanImage) -> Self {
+        Self {
+            source,
+            frames: HashMap::new(),
+            fragment_bytes: HashMap::new(),
+            palette_rows: HashMap::new(),
+        }
+    }
+
+    /// Check every frame can be imported, and add the palettes they need to the target. Nothing is modified on error.
+    fn prepare(
+        &mut self,
+        target: &mut WanImage,
+        frame_ids: &BTreeSet<usize>,
+    ) -> Result<(), ImportError> {
+        let source = self.source;
+        if source.is_256_color != target.is_256_color {
+            return Err(ImportError::ColorDepthMismatch);
+        }
+        let mut used_rows = BTreeSet::new();
+        for frame_id in frame_ids.iter().copied() {
+            let frame = source
+                .frame_store
+                .frames
+                .get(frame_id)
+                .ok_or(ImportError::NoFrame(frame_id))?;
+            for fragment in &frame.fragments {
+                if fragment.fragment_bytes_index >= source.fragment_bytes_store.len() {
+                    return Err(ImportError::NoFragmentBytes {
+                        frame: frame_id,
+                        fragment_bytes: fragment.fragment_bytes_index,
+                    });
+                }
+                used_rows.insert(fragment.pal_idx);
+            }
+        }
+        if target.frame_store.frames.len() + frame_ids.len() > u16::MAX as usize + 1 {
+            return Err(ImportError::TooManyFrames);
+        }
+
+        if source.is_256_color {
+            if target.palette.palette.is_empty() {
+                target.palette.palette = source.palette.palette.clone();
+            } else if target.palette.palette != source.palette.palette {
+                return Err(ImportError::PaletteMismatch);
+            }
+            return Ok(());
+        }
+
+        let mut target_rows: Vec<Vec<[u8; 4]>> = (0..target.palette.palette.len().div_ceil(16))
+            .map(|row| palette_row(&target.palette.palette, row as u16))
+            .collect();
+        let existing_rows = target_rows.len();
+        let mut palette_rows = HashMap::new();
+        for row in used_rows {
+            let colors = palette_row(&source.palette.palette, row);
+            let new_row = match target_rows.iter().position(|target| *target == colors) {
+                Some(position) => position,
+                None => {
+                    target_rows.push(colors);
+                    target_rows.len() - 1
+                }
+            };
+            if new_row > MAX_FRAGMENT_PALETTE_INDEX as usize {
+                return Err(ImportError::TooManyPalettes);
+            }
+            palette_rows.insert(row, new_row as u16);
+        }
+        if target_rows.len() > existing_rows {
+            target
+                .palette
+                .palette
+                .resize(existing_rows * 16, [0, 0, 0, 0]);
+            for row in &target_rows[existing_rows..] {
+                target.palette.palette.extend(row);
+            }
+        }
+        self.palette_rows.extend(palette_rows);
+        Ok(())
+    }
+
+    /// Copy an already prepared frame, and return its id in the target
+    fn import_frame(&mut self, target: &mut WanImage, frame_id: usize) -> usize {
+        if let Some(new_id) = self.frames.get(&frame_id) {
+            return *new_id;
+        }
+        let mut frame = self.source.frame_store.frames[frame_id].clone();
+        for fragment in &mut frame.fragments {
+            let source_index = fragment.fragment_bytes_index;
+            fragment.fragment_bytes_index = match self.fragment_bytes.get(&source_index) {
+                Some(new_index) => *new_index,
+                None => {
+                    let fragment_bytes =
+                        &self.source.fragment_bytes_store.fragment_bytes[source_index];
+                    let new_index = match target
+                        .fragment_bytes_store
+                        .fragment_bytes
+                        .iter()
+                        .position(|existing| existing == fragment_bytes)
+                    {
+                        Some(position) => position,
+                        None => {
+                            target
+                                .fragment_bytes_store
+                                .fragment_bytes
+                                .push(fragment_bytes.clone());
+                            target.fragment_bytes_store.len() - 1
+                        }
+                    };
+                    self.fragment_bytes.insert(source_index, new_index);
+                    new_index
+                }
+            };
+            if let Some(new_row) = self.palette_rows.get(&fragment.pal_idx) {
+                fragment.pal_idx = *new_row;
+            }
+        }
+        target.frame_store.frames.push(frame);
+        let new_id = target.frame_store.frames.len() - 1;
+        self.frames.insert(frame_id, new_id);
+        new_id
+    }
+
+    /// Copy every frame of the source, even the unused ones, and return all its animation groups with their frame id updated
+    pub(crate) fn import_all(
+        &mut self,
+        target: &mut WanImage,
+    ) -> Result<Vec<Vec<Animation>>, ImportError> {
+        let frame_ids = (0..self.source.frame_store.frames.len()).collect();
+        self.prepare(target, &frame_ids)?;
+        for frame_id in frame_ids {
+            self.import_frame(target, frame_id);
+        }
+        self.source
+            .animation_store
+            .anim_groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|animation| {
+                        let mut animation = animation.clone();
+                        for animation_frame in &mut animation.frames {
+                            let frame_id = animation_frame.frame_id as usize;
+                            animation_frame.frame_id = *self
+                                .frames
+                                .get(&frame_id)
+                                .ok_or(ImportError::NoFrame(frame_id))?
+                                as u16;
+                        }
+                        Ok(animation)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Prepare and copy the frames used by the [`Animation`]s, then return them with their frame id updated
+    fn import_animations(
+        &mut self,
+        target: &mut WanImage,
+        animations: &[Animation],
+    ) -> Result<Vec<Animation>, ImportError> {
+        let frame_ids = animations
+            .iter()
+            .flat_map(|animation| animation.frames.iter())
+            .map(|animation_frame| animation_frame.frame_id as usize)
+            .collect();
+        self.prepare(target, &frame_ids)?;
+        Ok(animations
+            .iter()
+            .map(|animation| {
+                let mut animation = animation.clone();
+                for animation_frame in &mut animation.frames {
+                    animation_frame.frame_id =
+                        self.import_frame(target, animation_frame.frame_id as usize) as u16;
+                }
+                animation
+            })
+            .collect())
+    }
+}
+
+impl WanImage {
+    /// Copy a frame of another [`WanImage`] in this one, with the [`crate::FragmentBytes`] and palettes it use, and return its new id.
+    ///
+    /// Identical [`crate::FragmentBytes`] and palettes already present in this image are reused. For 256 colors sprites, both palettes should be identical (or this one empty).
+    /// This image is left untouched on error.
+    pub fn import_frame_from(
+        &mut self,
+        other: &WanImage,
+        frame_index: usize,
+    ) -> Result<usize, ImportError> {
+        let mut importer = Importer::new(other);
+        importer.prepare(self, &[frame_index].iter().copied().collect())?;
+        Ok(importer.import_frame(self, frame_index))
+    }
+
+    /// Copy the frames used by an [`Animation`] of another [`WanImage`] as done by [`WanImage::import_frame_from`], and return the [`Animation`] referencing the copied frames.
+    /// The returned [`Animation`] isn't added to any group, see [`crate::AnimationStore::insert_animation`].
+    pub fn import_animation_from(
+        &mut self,
+        other: &WanImage,
+        group: usize,
+        animation: usize,
+    ) -> Result<Animation, ImportError> {
+        let source = other
+            .animation_store
+            .anim_groups
+            .get(group)
+            .ok_or(ImportError::NoGroup(group))?
+            .get(animation)
+            .ok_or(ImportError::NoAnimation { group, animation })?;
+        let mut animations =
+            Importer::new(other).import_animations(self, std::slice::from_ref(source))?;
+        Ok(animations.remove(0))
+    }
+
+    /// Like [`WanImage::import_animation_from`], but for every [`Animation`] of a group (usually one per direction).
+    /// The returned [`Animation`]s aren't added to any group, see [`crate::AnimationStore::insert_group`].
+    pub fn import_animation_group_from(
+        &mut self,
+        other: &WanImage,
+        group: usize,
+    ) -> Result<Vec<Animation>, ImportError> {
+        let source = other
+            .animation_store
+            .anim_groups
+            .get(group)
+            .ok_or(ImportError::NoGroup(group))?;
+        Importer::new(other).import_animations(self, source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{animation_frame, insert_filled_frame, test_wan_image},
+        Animation, ImportError, SpriteType, WanImage,
+    };
+
+    fn animation(frame_ids: &[u16]) -> Animation {
+        Animation {
+            frames: frame_ids
+                .iter()
+                .map(|frame_id| animation_frame(*frame_id, 2))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_import_animation_from() {
+        let mut source = test_wan_image(SpriteType::Chara);
+        source.palette.palette.resize(16, [0, 0, 0, 0]);
+        source
+            .palette
+            .palette
+            .extend([[0, 0, 0, 0], [0, 255, 0, 128]]);
+        insert_filled_frame(&mut source, 8, 8);
+        insert_frame_in_wanimage(vec![1; 16], 4, 4, &mut source, 1).unwrap();
+        source
+            .animation_store
+            .anim_groups
+            .push(vec![animation(&[1, 0, 1])]);
+
+        let mut target = WanImage::new(SpriteType::Chara);
+        target.palette.palette = vec![[0, 0, 0, 0], [0, 255, 0, 128]];
+        insert_filled_frame(&mut target, 2, 2);
+
+        let imported = target.import_animation_from(&source, 0, 0).unwrap();
+        let frame_ids: Vec<u16> = imported
+            .frames
+            .iter()
+            .map(|animation_frame| animation_frame.frame_id)
+            .collect();
+        assert_eq!(frame_ids, vec![1, 2, 1]);
+        assert_eq!(target.frame_store.frames.len(), 3);
+        // the green palette already existed, the red one has been added after it
+        assert_eq!(target.palette.palette.len(), 32);
+        assert_eq!(target.frame_store.frames[1].fragments[0].pal_idx, 0);
+        assert_eq!(target.frame_store.frames[2].fragments[0].pal_idx, 1);
+        for (source_id, target_id) in [(1, 1), (0, 2)] {
+            assert_eq!(
+                target.render_frame(target_id).unwrap(),
+                source.render_frame(source_id).unwrap()
+            );
+        }
+
+        assert_eq!(
+            target.import_animation_from(&source, 0, 1),
+            Err(ImportError::NoAnimation {
+                group: 0,
+                animation: 1
+            })
+        );
+        assert_eq!(
+            target.import_frame_from(&source, 5),
+            Err(ImportError::NoFrame(5))
+        );
+        source.is_256_color = true;
+        assert_eq!(
+            target.import_frame_from(&source, 0),
+            Err(ImportError::ColorDepthMismatch)
+        );
+    }
+}
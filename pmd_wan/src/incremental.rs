@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Seek, Write};
+
+use byteorder::{ByteOrder, LE};
+
+use crate::{
+    fragment_bytes_store::WrittenFragmentBytesStore,
+    progress::{no_progress, start_phase},
+    CompressionMethod, FragmentBytes, FragmentBytesCompressionStats, FragmentBytesStore,
+    ProgressCallback, ProgressPhase, WanError, WanImage,
+};
+
+/// A [`FragmentBytes`] as it was written by a previous encoding
+#[derive(Debug, Clone)]
+struct EncodedFragmentBytes {
+    /// The offset the bytes were written at
+    start: u64,
+    /// The compressed pixels followed by the assembly table
+    bytes: Vec<u8>,
+    assembly_table_offset: u64,
+    /// The offsets of the pointers of the assembly table, each pointing into `bytes`
+    sir0_pointers: Vec<u64>,
+    stats: FragmentBytesCompressionStats,
+}
+
+impl EncodedFragmentBytes {
+    fn encode(
+        fragment_bytes: &FragmentBytes,
+        file: &mut Cursor<Vec<u8>>,
+        compression: &CompressionMethod,
+    ) -> Result<Self, WanError> {
+        let start = file.stream_position()?;
+        let (assembly_table_offset, sir0_pointers, stats) =
+            fragment_bytes.write_with_stats(file, compression)?;
+        let end = file.stream_position()?;
+        Ok(Self {
+            start,
+            bytes: file.get_ref()[start as usize..end as usize].to_vec(),
+            assembly_table_offset,
+            sir0_pointers,
+            stats,
+        })
+    }
+
+    /// Write the same bytes at the current position of `file`, with the pointers moved accordingly, and return where they were written
+    fn write_at(&self, file: &mut Cursor<Vec<u8>>) -> Result<Self, WanError> {
+        let start = file.stream_position()?;
+        let relocate = |offset: u64| offset - self.start + start;
+        let mut moved = Self {
+            start,
+            bytes: self.bytes.clone(),
+            assembly_table_offset: relocate(self.assembly_table_offset),
+            sir0_pointers: Vec::with_capacity(self.sir0_pointers.len()),
+            stats: self.stats.clone(),
+        };
+        for pointer in &self.sir0_pointers {
+            let position = (pointer - self.start) as usize;
+            let target = LE::read_u32(&moved.bytes[position..position + 4]) as u64;
+            LE::write_u32(
+                &mut moved.bytes[position..position + 4],
+                relocate(target) as u32,
+            );
+            moved.sir0_pointers.push(relocate(*pointer));
+        }
+        file.write_all(&moved.bytes)?;
+        Ok(moved)
+    }
+}
+
+/// The encoded [`FragmentBytes`] of a previous [`WanImage::write_incremental`] call.
+///
+/// The [`FragmentBytes`] are tracked by content: those that are still present (even at another index) aren't compressed again, only copied, while the new or modified ones are.
+/// Everything else is cheap to encode, so it is always written from scratch. The cache is emptied if the [`WanImage::compression`] changes.
+#[derive(Debug, Clone, Default)]
+pub struct EncodeCache {
+    compression: Option<CompressionMethod>,
+    fragment_bytes: HashMap<FragmentBytes, EncodedFragmentBytes>,
+    reused: usize,
+}
+
+impl EncodeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget every encoded [`FragmentBytes`], so the next write compress all of them
+    pub fn clear(&mut self) {
+        self.compression = None;
+        self.fragment_bytes.clear();
+        self.reused = 0;
+    }
+
+    /// The number of [`FragmentBytes`] whose encoded bytes were reused by the last write
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+
+    /// true if the given [`FragmentBytes`] would be compressed with `compression` by the next write, instead of being copied from the cache
+    pub fn is_dirty(
+        &self,
+        fragment_bytes: &FragmentBytes,
+        compression: &CompressionMethod,
+    ) -> bool {
+        self.compression.as_ref() != Some(compression)
+            || !self.fragment_bytes.contains_key(fragment_bytes)
+    }
+
+    /// Like [`FragmentBytesStore::write_with_stats`], but reuse the bytes of the [`FragmentBytes`] already encoded with `compression`, and remember the ones written for the next call.
+    ///
+    /// The [`FragmentBytes`] that aren't in `store` anymore are forgotten.
+    pub(crate) fn write_fragment_bytes_store(
+        &mut self,
+        store: &FragmentBytesStore,
+        file: &mut Cursor<Vec<u8>>,
+        compression: &CompressionMethod,
+        progress: ProgressCallback,
+    ) -> Result<WrittenFragmentBytesStore, WanError> {
+        let mut previous = std::mem::take(&mut self.fragment_bytes);
+        if self.compression.as_ref() != Some(compression) {
+            previous.clear();
+            self.compression = Some(compression.clone());
+        }
+        self.reused = 0;
+        let step = start_phase(
+            progress,
+            ProgressPhase::EncodingFragmentBytes,
+            store.fragment_bytes.len(),
+        );
+        let mut fragment_bytes_addr = Vec::with_capacity(store.fragment_bytes.len());
+        let mut sir0_pointer_fragments_bytes = Vec::new();
+        let mut stats = Vec::with_capacity(store.fragment_bytes.len());
+
+        for fragment_bytes in &store.fragment_bytes {
+            let cached = previous
+                .remove(fragment_bytes)
+                .or_else(|| self.fragment_bytes.get(fragment_bytes).cloned());
+            let encoded = match cached {
+                Some(cached) => {
+                    self.reused += 1;
+                    cached.write_at(file)?
+                }
+                None => EncodedFragmentBytes::encode(fragment_bytes, file, compression)?,
+            };
+            fragment_bytes_addr.push(encoded.assembly_table_offset);
+            sir0_pointer_fragments_bytes.extend_from_slice(&encoded.sir0_pointers);
+            stats.push(encoded.stats.clone());
+            self.fragment_bytes.insert(fragment_bytes.clone(), encoded);
+            step();
+        }
+        Ok((fragment_bytes_addr, sir0_pointer_fragments_bytes, stats))
+    }
+}
+
+impl WanImage {
+    /// Encode this [`WanImage`] like [`WanImage::write_to_vec`], giving the same bytes, but only compress the [`FragmentBytes`] that changed since the last write with the same `cache`.
+    ///
+    /// This make saving much faster when editing only the palette, the animations or a few frames of a big sprite.
+    pub fn write_incremental(&self, cache: &mut EncodeCache) -> anyhow::Result<Vec<u8>> {
+        Ok(self
+            .create_sir0_with_report(&self.compression, &no_progress, Some(cache))?
+            .0
+            .to_bytes()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{animation_frame, test_wan_image},
+        Animation, AnimationFrame, CompressionMethod, EncodeCache, SpriteType,
+    };
+
+    #[test]
+    fn test_write_incremental() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.compression = CompressionMethod::CompressionMethodOptimised {
+            multiple_of_value: 2,
+            min_transparent_to_compress: 8,
+        };
+        wanimage.palette.palette.push([0, 255, 0, 128]);
+        let mut pixels = vec![0; 16 * 16];
+        pixels[100..140].fill(1);
+        insert_frame_in_wanimage(pixels, 16, 16, &mut wanimage, 0).unwrap();
+        insert_frame_in_wanimage(vec![2; 64], 8, 8, &mut wanimage, 0).unwrap();
+        wanimage
+            .animation_store
+            .anim_groups
+            .push(vec![Animation::default()]);
+
+        let mut cache = EncodeCache::new();
+        assert_eq!(
+            wanimage.write_incremental(&mut cache).unwrap(),
+            wanimage.write_to_vec().unwrap()
+        );
+        assert_eq!(cache.reused(), 0);
+
+        // palette and animations only: every FragmentBytes is reused
+        wanimage.palette.palette[1] = [0, 0, 255, 128];
+        wanimage.animation_store.anim_groups[0][0]
+            .frames
+            .push(AnimationFrame {
+                offset_x: 2,
+                ..animation_frame(1, 3)
+            });
+        let total = wanimage.fragment_bytes_store.len();
+        assert_eq!(
+            wanimage.write_incremental(&mut cache).unwrap(),
+            wanimage.write_to_vec().unwrap()
+        );
+        assert_eq!(cache.reused(), total);
+
+        // the first FragmentBytes become transparent and shrink, so the following ones move
+        wanimage.fragment_bytes_store.fragment_bytes[0]
+            .mixed_pixels
+            .fill(0);
+        assert!(cache.is_dirty(
+            &wanimage.fragment_bytes_store.fragment_bytes[0],
+            &wanimage.compression
+        ));
+        assert_eq!(
+            wanimage.write_incremental(&mut cache).unwrap(),
+            wanimage.write_to_vec().unwrap()
+        );
+        assert_eq!(cache.reused(), total - 1);
+
+        // another compression invalidate the cache
+        wanimage.compression = CompressionMethod::NoCompression;
+        assert_eq!(
+            wanimage.write_incremental(&mut cache).unwrap(),
+            wanimage.write_to_vec().unwrap()
+        );
+        assert_eq!(cache.reused(), 0);
+    }
+}
@@ -0,0 +1,243 @@
+use std::io::{Read, Write};
+
+use thiserror::Error;
+
+use crate::{
+    frame_render::palette_color_to_rgba,
+    image_tool::{read_indexed_png, write_indexed_png},
+    insert_frame_in_wanimage, Fragment, FragmentBytesStore, FragmentBytesToImageError, Frame,
+    FrameRenderError, WanImage,
+};
+
+#[derive(Error, Debug)]
+pub enum IndexedPngError {
+    #[error("failed to encode the png")]
+    EncodingError(#[from] png::EncodingError),
+    #[error("failed to decode the png")]
+    DecodingError(#[from] png::DecodingError),
+    #[error("the png isn't an indexed png")]
+    NotIndexed,
+    #[error("the png use the colors {0} and {1}, that are in different rows of 16 colors")]
+    MultipleRows(u8, u8),
+    #[error("the color {0} of the png doesn't match the color of the palette (found {1:?}, expected {2:?})")]
+    PaletteMismatch(u8, [u8; 4], Option<[u8; 4]>),
+    #[error("the png is too big to be inserted ({0}×{1})")]
+    TooBig(u32, u32),
+    #[error("failed to insert the frame")]
+    CantInsertFrame(#[source] anyhow::Error),
+    #[error("failed to render a frame")]
+    FrameRenderError(#[from] FrameRenderError),
+    #[error("the color {0} of the palette {1} can't be stored in an indexed png, that only have 256 colors")]
+    ColorOutOfRange(u8, u16),
+}
+
+impl From<FragmentBytesToImageError> for IndexedPngError {
+    fn from(err: FragmentBytesToImageError) -> Self {
+        Self::FrameRenderError(FrameRenderError::CantRenderFragment(err))
+    }
+}
+
+/// Return the index in the whole [`crate::Palette`] of a color of a [`Fragment`]
+fn palette_index(
+    fragment: &Fragment,
+    pixel: u8,
+    is_256_color: bool,
+) -> Result<u8, IndexedPngError> {
+    if pixel == 0 || is_256_color {
+        return Ok(pixel);
+    }
+    let index = fragment.pal_idx as usize * 16 + pixel as usize;
+    if index > u8::MAX as usize {
+        return Err(IndexedPngError::ColorOutOfRange(pixel, fragment.pal_idx));
+    }
+    Ok(index as u8)
+}
+
+impl Frame {
+    /// Like [`Frame::render`], but return the indices in the whole [`crate::Palette`] (`pal_idx * 16 + color`) instead of colors, with the width and the height.
+    pub fn render_indexed(
+        &self,
+        fragment_bytes_store: &FragmentBytesStore,
+    ) -> Result<(Vec<u8>, u32, u32), IndexedPngError> {
+        self.render_indexed_with_depth(fragment_bytes_store, false)
+    }
+
+    /// Like [`Frame::render_indexed`], but for 256 colors sprites if `is_256_color` is set, in which case the indices are directly the colors
+    pub fn render_indexed_with_depth(
+        &self,
+        fragment_bytes_store: &FragmentBytesStore,
+        is_256_color: bool,
+    ) -> Result<(Vec<u8>, u32, u32), IndexedPngError> {
+        let (min_x, min_y, max_x, max_y) = self.fragments_extent().unwrap_or((0, 0, 0, 0));
+        let width = (max_x - min_x) as u32;
+        let height = (max_y - min_y) as u32;
+        let mut result = vec![0; width as usize * height as usize];
+        for fragment in self.fragments.iter().rev() {
+            let pixels =
+                fragment.get_flipped_pixels_with_depth(fragment_bytes_store, is_256_color)?;
+            let resolution = fragment.resolution.size();
+            for (pixel_nb, pixel) in pixels.iter().copied().enumerate() {
+                if pixel == 0 {
+                    continue;
+                }
+                let x = fragment.offset_x as i32 - min_x + (pixel_nb as u32 % resolution.x) as i32;
+                let y = fragment.offset_y as i32 - min_y + (pixel_nb as u32 / resolution.x) as i32;
+                result[y as usize * width as usize + x as usize] =
+                    palette_index(fragment, pixel, is_256_color)?;
+            }
+        }
+        Ok((result, width, height))
+    }
+}
+
+impl WanImage {
+    /// Write the frame as an indexed PNG, keeping the palette indices. See [`Frame::render_indexed`] and [`write_indexed_png`].
+    pub fn write_frame_indexed_png<W: Write>(
+        &self,
+        frame_id: usize,
+        writer: W,
+    ) -> Result<(), IndexedPngError> {
+        let frame = self
+            .frame_store
+            .frames
+            .get(frame_id)
+            .ok_or(FrameRenderError::NoFrame(frame_id))?;
+        let (pixels, width, height) =
+            frame.render_indexed_with_depth(&self.fragment_bytes_store, self.is_256_color)?;
+        write_indexed_png(&pixels, width, height, &self.palette, writer)?;
+        Ok(())
+    }
+
+    /// Write the [`crate::FragmentBytes`] used by the [`Fragment`], with its resolution, flip and palette, as an indexed PNG
+    pub fn write_fragment_indexed_png<W: Write>(
+        &self,
+        fragment: &Fragment,
+        writer: W,
+    ) -> Result<(), IndexedPngError> {
+        let resolution = fragment.resolution.size();
+        let pixels = fragment
+            .get_flipped_pixels_with_depth(&self.fragment_bytes_store, self.is_256_color)?
+            .into_iter()
+            .map(|pixel| palette_index(fragment, pixel, self.is_256_color))
+            .collect::<Result<Vec<u8>, _>>()?;
+        write_indexed_png(&pixels, resolution.x, resolution.y, &self.palette, writer)?;
+        Ok(())
+    }
+
+    /// Insert an indexed PNG as a new frame with [`insert_frame_in_wanimage`], using its indices directly instead of matching colors.
+    ///
+    /// The colors of the PNG palette used by the image must be the same as the ones of this [`WanImage`] palette at the same index.
+    /// All the used colors must be in the same row of 16 colors, which give the `pal_idx` of the [`Fragment`]s. The first color of each row is transparent.
+    /// Return the id of the inserted frame, or [`None`] if the image is fully transparent.
+    pub fn insert_indexed_png_frame<R: Read>(
+        &mut self,
+        reader: R,
+    ) -> Result<Option<usize>, IndexedPngError> {
+        let image = read_indexed_png(reader)?.ok_or(IndexedPngError::NotIndexed)?;
+        if image.width > u16::MAX as u32 || image.height > u16::MAX as u32 {
+            return Err(IndexedPngError::TooBig(image.width, image.height));
+        }
+        let mut row: Option<u8> = None;
+        let mut checked = [false; 256];
+        for index in image.pixels.iter().copied().filter(|index| index % 16 != 0) {
+            match row {
+                None => row = Some(index),
+                Some(first) if first / 16 != index / 16 => {
+                    return Err(IndexedPngError::MultipleRows(first, index))
+                }
+                _ => (),
+            }
+            if checked[index as usize] {
+                continue;
+            }
+            checked[index as usize] = true;
+            let png_color = image.palette.get(index as usize).copied().unwrap_or([0; 4]);
+            let wan_color = self
+                .palette
+                .palette
+                .get(index as usize)
+                .map(|color| palette_color_to_rgba(*color).0);
+            if wan_color != Some(png_color) {
+                return Err(IndexedPngError::PaletteMismatch(
+                    index, png_color, wan_color,
+                ));
+            }
+        }
+        let pixels = image.pixels.iter().map(|index| index % 16).collect();
+        insert_frame_in_wanimage(
+            pixels,
+            image.width as u16,
+            image.height as u16,
+            self,
+            row.map(|index| index / 16).unwrap_or(0) as u16,
+        )
+        .map_err(IndexedPngError::CantInsertFrame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        image_tool::write_indexed_png, insert_frame_in_wanimage, tests::fixtures::test_wan_image,
+        IndexedPngError, SpriteType, WanImage,
+    };
+
+    #[test]
+    fn test_write_frame_indexed_png() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.push([0, 255, 0, 64]);
+        insert_frame_in_wanimage(vec![1, 2, 2, 1], 2, 2, &mut wanimage, 0).unwrap();
+        let mut png_file = Vec::new();
+        wanimage.write_frame_indexed_png(0, &mut png_file).unwrap();
+
+        let mut decoder = png::Decoder::new(Cursor::new(png_file));
+        decoder.set_transformations(png::Transformations::IDENTITY);
+        let mut reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.color_type, png::ColorType::Indexed);
+        assert_eq!(
+            info.palette.as_deref().unwrap(),
+            &[0, 0, 0, 255, 0, 0, 0, 255, 0]
+        );
+        assert_eq!(info.trns.as_deref().unwrap(), &[0, 255, 128]);
+        let (width, height) = (info.width as usize, info.height as usize);
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        // the 2×2 image is stored in a 8×8 fragment, starting at the top-left
+        assert_eq!((width, height), (8, 8));
+        assert_eq!(&pixels[0..2], &[1, 2]);
+        assert_eq!(&pixels[width..width + 2], &[2, 1]);
+    }
+
+    #[test]
+    fn test_insert_indexed_png_frame() {
+        let mut wanimage = WanImage::new(SpriteType::PropsUI);
+        let mut palette = vec![[0, 0, 0, 0]; 32];
+        // two similar colors, that would be merged by color matching
+        palette[17] = [10, 10, 10, 128];
+        palette[18] = [10, 10, 10, 128];
+        wanimage.palette.palette = palette;
+        let mut png_file = Vec::new();
+        write_indexed_png(&[17, 18, 0, 18], 2, 2, &wanimage.palette, &mut png_file).unwrap();
+
+        let frame_id = wanimage
+            .insert_indexed_png_frame(Cursor::new(&png_file))
+            .unwrap()
+            .unwrap();
+        let fragment = &wanimage.frame_store.frames[frame_id].fragments[0];
+        assert_eq!(fragment.pal_idx, 1);
+        let (pixels, width, _) = wanimage.frame_store.frames[frame_id]
+            .render_indexed(&wanimage.fragment_bytes_store)
+            .unwrap();
+        assert_eq!(&pixels[0..2], &[17, 18]);
+        assert_eq!(&pixels[width as usize..width as usize + 2], &[0, 18]);
+
+        wanimage.palette.palette[18] = [20, 10, 10, 128];
+        assert!(matches!(
+            wanimage.insert_indexed_png_frame(Cursor::new(&png_file)),
+            Err(IndexedPngError::PaletteMismatch(18, _, _))
+        ));
+    }
+}
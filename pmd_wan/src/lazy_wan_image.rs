@@ -0,0 +1,196 @@
+use std::io::{Cursor, Seek, SeekFrom};
+use std::sync::Arc;
+
+use byteorder::{ReadBytesExt, LE};
+use image::RgbaImage;
+use thiserror::Error;
+
+use crate::{
+    wan_image::WanHeader, AnimationStore, DecodeOptions, FragmentBytes, FragmentBytesStore,
+    FrameRenderError, FrameStore, Palette, SpriteType, WanError, WanImage, WanUnknowns,
+};
+
+#[derive(Error, Debug)]
+pub enum LazyWanImageError {
+    #[error("The FragmentBytes {0} doesn't exist")]
+    NoFragmentBytes(usize),
+    #[error("Can't decode the FragmentBytes {0}")]
+    CantDecodeFragmentBytes(usize, #[source] WanError),
+    #[error(transparent)]
+    CantRenderFrame(#[from] FrameRenderError),
+}
+
+/// The [`FragmentBytes`] of a [`LazyWanImage`], that are only decoded when requested, from the buffer the image was decoded from
+#[derive(Debug, Clone)]
+pub struct LazyFragmentBytesStore {
+    buffer: Arc<[u8]>,
+    pointers: Vec<u64>,
+}
+
+impl LazyFragmentBytesStore {
+    pub fn len(&self) -> usize {
+        self.pointers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pointers.is_empty()
+    }
+
+    /// Decode the [`FragmentBytes`] with the given index. It isn't cached, so each call decode it again.
+    pub fn get(&self, index: usize) -> Result<FragmentBytes, LazyWanImageError> {
+        let pointer = *self
+            .pointers
+            .get(index)
+            .ok_or(LazyWanImageError::NoFragmentBytes(index))?;
+        let decode = || -> Result<FragmentBytes, WanError> {
+            if pointer == 0 {
+                return Err(WanError::NullFragmentBytesPointer);
+            }
+            let mut cursor = Cursor::new(&self.buffer[..]);
+            cursor.seek(SeekFrom::Start(pointer))?;
+            FragmentBytes::new_from_bytes(&mut cursor)
+        };
+        decode().map_err(|error| LazyWanImageError::CantDecodeFragmentBytes(index, error))
+    }
+
+    /// Decode the [`FragmentBytes`] with the given indices, and return a [`FragmentBytesStore`] with them at their original position, the others being left empty
+    pub fn load(&self, indices: &[usize]) -> Result<FragmentBytesStore, LazyWanImageError> {
+        let mut fragment_bytes = vec![
+            FragmentBytes {
+                mixed_pixels: Vec::new(),
+                z_index: 0,
+            };
+            self.len()
+        ];
+        for index in indices {
+            if *index >= fragment_bytes.len() {
+                return Err(LazyWanImageError::NoFragmentBytes(*index));
+            }
+            if fragment_bytes[*index].mixed_pixels.is_empty() {
+                fragment_bytes[*index] = self.get(*index)?;
+            }
+        }
+        Ok(FragmentBytesStore { fragment_bytes })
+    }
+
+    /// Decode every [`FragmentBytes`]
+    pub fn load_all(&self) -> Result<FragmentBytesStore, LazyWanImageError> {
+        Ok(FragmentBytesStore {
+            fragment_bytes: (0..self.len())
+                .map(|index| self.get(index))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// A [`WanImage`] whose [`FragmentBytes`] (that hold most of the pixel data) are only decoded on demand, from a shared buffer containing the wan file.
+///
+/// This is much faster to load when only a few frames of many sprites are needed, like to show an index of all of them.
+#[derive(Debug)]
+pub struct LazyWanImage {
+    pub fragment_bytes: LazyFragmentBytesStore,
+    pub frame_store: FrameStore,
+    pub animation_store: AnimationStore,
+    pub palette: Palette,
+    pub is_256_color: bool,
+    pub sprite_type: SpriteType,
+    pub unk2: u16,
+    pub unknowns: WanUnknowns,
+}
+
+impl LazyWanImage {
+    /// Decode the wan file (that should be decompressed) stored in `buffer`, like [`WanImage::decode_wan_from_bytes`], except for the [`FragmentBytes`].
+    ///
+    /// The buffer is kept alive as long as this image (and its [`LazyFragmentBytesStore`]) exists. Malformed [`FragmentBytes`] are only reported when they are decoded.
+    pub fn decode(buffer: impl Into<Arc<[u8]>>) -> Result<Self, WanError> {
+        let buffer: Arc<[u8]> = buffer.into();
+        let options = DecodeOptions::default();
+        let mut cursor = Cursor::new(&buffer[..]);
+        let (image, _) = WanImage::decode_wan_sections(&mut cursor, &options, None, false)
+            .map_err(|error| error.error)?;
+        let header = WanHeader::read(&mut cursor, &options, &mut Vec::new())?;
+        cursor.seek(SeekFrom::Start(header.pointer_image_data_pointer_table))?;
+        let pointers = (0..header.amount_fragments)
+            .map(|_| Ok(cursor.read_u32::<LE>()? as u64))
+            .collect::<Result<Vec<u64>, WanError>>()?;
+        Ok(Self {
+            fragment_bytes: LazyFragmentBytesStore { buffer, pointers },
+            frame_store: image.frame_store,
+            animation_store: image.animation_store,
+            palette: image.palette,
+            is_256_color: image.is_256_color,
+            sprite_type: image.sprite_type,
+            unk2: image.unk2,
+            unknowns: image.unknowns,
+        })
+    }
+
+    /// Render the frame with the given id like [`WanImage::render_frame`], decoding only the [`FragmentBytes`] it use
+    pub fn render_frame(&self, frame_id: usize) -> Result<RgbaImage, LazyWanImageError> {
+        let frame = self
+            .frame_store
+            .frames
+            .get(frame_id)
+            .ok_or(FrameRenderError::NoFrame(frame_id))?;
+        let used: Vec<usize> = frame
+            .fragments
+            .iter()
+            .map(|fragment| fragment.fragment_bytes_index)
+            .collect();
+        let fragment_bytes_store = self.fragment_bytes.load(&used)?;
+        Ok(frame.render_with_depth(&fragment_bytes_store, &self.palette, self.is_256_color)?)
+    }
+
+    /// Decode every [`FragmentBytes`], and return the fully decoded [`WanImage`]
+    pub fn into_wan_image(self) -> Result<WanImage, LazyWanImageError> {
+        Ok(WanImage {
+            fragment_bytes_store: self.fragment_bytes.load_all()?,
+            frame_store: self.frame_store,
+            animation_store: self.animation_store,
+            palette: self.palette,
+            is_256_color: self.is_256_color,
+            sprite_type: self.sprite_type,
+            unk2: self.unk2,
+            unknowns: self.unknowns,
+            compression: self.sprite_type.default_compression_method(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{insert_filled_frame, test_wan_image},
+        Animation, LazyWanImage, SpriteType, WanImage,
+    };
+
+    #[test]
+    fn test_lazy_wan_image() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.push([0, 255, 0, 128]);
+        insert_filled_frame(&mut wanimage, 8, 8);
+        insert_frame_in_wanimage(vec![2; 16 * 16], 16, 16, &mut wanimage, 0).unwrap();
+        wanimage
+            .animation_store
+            .anim_groups
+            .push(vec![Animation::default()]);
+        let bytes = wanimage.write_to_vec().unwrap();
+
+        let lazy = LazyWanImage::decode(bytes.clone()).unwrap();
+        assert_eq!(lazy.fragment_bytes.len(), 2);
+        assert_eq!(lazy.frame_store, wanimage.frame_store);
+        assert_eq!(
+            lazy.render_frame(1).unwrap(),
+            wanimage.render_frame(1).unwrap()
+        );
+        assert!(lazy.fragment_bytes.get(2).is_err());
+        assert_eq!(
+            lazy.fragment_bytes.load(&[1]).unwrap().fragment_bytes[0].mixed_pixels,
+            Vec::<u8>::new()
+        );
+
+        let decoded = WanImage::decode_wan_from_bytes(&bytes).unwrap();
+        assert_eq!(lazy.into_wan_image().unwrap(), decoded);
+    }
+}
@@ -5,16 +5,36 @@ extern crate log;
 pub mod tests;
 
 pub mod wan_image;
-pub use wan_image::WanImage;
+pub use wan_image::{EncodedSizeEstimate, WanImage};
 
 mod wan_error;
-pub use wan_error::WanError;
+pub use wan_error::{ErrorLocation, LocatedWanError, WanError};
+
+mod decode_options;
+pub use decode_options::{DecodeLimits, DecodeOptions, DecodeWarning};
+
+mod salvage;
+pub use salvage::{DamageReport, SectionDamage, WanSection};
+
+mod span;
+pub use span::SPAN_LOG_TARGET;
+
+mod clock;
+
+mod progress;
+pub use progress::{Progress, ProgressCallback, ProgressPhase};
+
+mod cancellation;
+pub use cancellation::{CancellationToken, Cancelled};
 
 mod frame;
 pub use frame::Frame;
 
 mod fragment;
-pub use fragment::Fragment;
+pub use fragment::{
+    Fragment, FragmentAttributeError, DEFAULT_FRAGMENT_PRIORITY, MAX_FRAGMENT_PALETTE_INDEX,
+    MAX_FRAGMENT_PRIORITY,
+};
 
 mod oam_shape;
 pub use oam_shape::OamShape;
@@ -25,27 +45,119 @@ pub use frame_store::FrameStore;
 mod sprite_type;
 pub use sprite_type::SpriteType;
 
+mod wan_unknowns;
+pub use wan_unknowns::WanUnknowns;
+
 mod fragment_bytes;
 pub use crate::fragment_bytes::{
-    decode_fragment_pixels, encode_fragment_pixels, DecodeFragmentBytesError, FragmentBytes,
+    decode_fragment_pixels, decode_fragment_pixels_8bpp, encode_fragment_pixels,
+    encode_fragment_pixels_8bpp, DecodeFragmentBytesError, FragmentBytes,
     FragmentBytesToImageError,
 };
 
 mod palette;
 pub use palette::Palette;
 
+mod palette_file;
+pub use palette_file::{ColorConversion, PaletteFileError, ACT_COLOR_COUNT};
+
+mod palette_color;
+pub use palette_color::{HsvShift, PaletteColorError};
+
+mod palette_rows;
+pub use palette_rows::PaletteRowError;
+
+mod palette_swatch;
+pub use palette_swatch::SWATCH_TRANSPARENT_COLORS;
+
+mod palette_from_image;
+pub use palette_from_image::PaletteFromImageError;
+
 mod fragment_bytes_store;
 pub use fragment_bytes_store::FragmentBytesStore;
 
+mod fragment_bytes_dump;
+pub use fragment_bytes_dump::{
+    FragmentBytesDumpEntry, FragmentBytesDumpError, FragmentBytesDumpManifest,
+};
+
+mod fragment_export;
+pub use fragment_export::{FragmentExportError, FragmentImage};
+
+mod duplicate_fragment_report;
+pub use duplicate_fragment_report::DuplicateFragmentReport;
+
+mod dump;
+pub use dump::{dump_wan, dump_wan_to_string};
+
+mod lazy_wan_image;
+pub use lazy_wan_image::{LazyFragmentBytesStore, LazyWanImage, LazyWanImageError};
+
+mod incremental;
+pub use incremental::EncodeCache;
+
+mod wan_edit_session;
+pub use wan_edit_session::{WanEdit, WanEditError, WanEditSession};
+
+mod bounding_box;
+pub use bounding_box::BoundingBox;
+
+mod hit_test;
+pub use hit_test::FragmentHit;
+
+mod shadow;
+pub use shadow::{ShadowRender, ShadowSize, DEFAULT_SHADOW_COLOR};
+
+mod frame_pixel_diff;
+pub use frame_pixel_diff::{
+    diff_frames, diff_images, FramePixelDiff, DIFF_ADDED_COLOR, DIFF_MODIFIED_COLOR,
+    DIFF_REMOVED_COLOR,
+};
+
+mod nds_export;
+pub use nds_export::{rgba_to_bgr555, NdsExport, NdsExportError, MAX_OAM_TILE_INDEX};
+
+mod texture_atlas;
+pub use texture_atlas::{AtlasFrame, AtlasSequence, TextureAtlas, ATLAS_PADDING};
+
+mod godot_export;
+
 mod animation_frame;
-pub use animation_frame::AnimationFrame;
+pub use animation_frame::{
+    AnimationFrame, AnimationFrameFlagError, AnimationFrameFlags, ANIMATION_FRAME_FLAG_HIT,
+    ANIMATION_FRAME_FLAG_RETURN_POINT, ANIMATION_FRAME_KNOWN_FLAGS,
+};
 
 mod animation_store;
-pub use animation_store::AnimationStore;
+pub use animation_store::{AnimationFrameReuse, AnimationStore};
+
+mod animation_edit;
+pub use animation_edit::AnimationEditError;
+
+mod animation_group_kind;
+pub use animation_group_kind::{AnimationGroupError, AnimationGroupKind, DIRECTION_COUNT};
+
+mod direction;
+pub use direction::Direction;
+
+mod animation_player;
+pub use animation_player::{AnimationPlayer, AnimationPlayerError, LoopMode, PlaybackState};
+
+mod mirror;
+pub use mirror::{MirrorError, MIRRORED_DIRECTIONS};
+
+mod frame_transform;
+pub use frame_transform::FrameTransformError;
+
+mod recenter;
+pub use recenter::{RecenterError, RecenterOptions, RecenterReport};
 
 mod animation;
 pub use animation::Animation;
 
+mod animation_render;
+pub use animation_render::{AnimationRenderError, ANIMATION_FRAMES_PER_SECOND};
+
 mod fragment_bytes_compression;
 pub use fragment_bytes_compression::*;
 
@@ -54,24 +166,141 @@ pub use fragment_flip::{
     FragmentFlip, FragmentFlipError, FLIP_BOTH, FLIP_HORIZONTAL, FLIP_STANDARD, FLIP_VERTICAL,
 };
 
+mod flip_duplicates;
+
 mod fragment_finder;
 pub use fragment_finder::{
-    find_fragments_in_images, pad_seven_pixel, FragmentFinderData, FragmentFinderError,
+    find_fragments_in_images, find_fragments_in_images_with_progress, pad_seven_pixel,
+    FragmentFinderData, FragmentFinderError,
 };
 
 mod image_to_wan;
-pub use image_to_wan::insert_frame_in_wanimage;
+pub use image_to_wan::{
+    insert_frame_in_wanimage, insert_frame_in_wanimage_trimmed,
+    insert_frame_in_wanimage_with_layout,
+};
+
+mod multi_palette;
+
+mod fragment_layout;
+pub use fragment_layout::{
+    find_fragment_layout, FragmentLayout, FragmentLayoutGoal, FragmentLayoutOptions,
+    FragmentPlacement,
+};
 
 pub mod image_tool;
 
 mod multi_images_to_wan;
-pub use multi_images_to_wan::create_wan_from_multiple_images;
+pub use multi_images_to_wan::{
+    create_wan_from_multiple_images, create_wan_from_multiple_images_with_animations,
+    create_wan_from_multiple_images_with_progress, insert_frames_in_wanimage, MultiImagesAnimation,
+};
+
+mod sprite_sheet;
+pub use sprite_sheet::{create_wan_from_sprite_sheet, SpriteSheetError, SpriteSheetGrid};
 
 mod normalized_bytes;
 pub use normalized_bytes::{NormalizedBytes, VariableNormalizedBytes};
 
 mod frame_offset;
-pub use frame_offset::FrameOffset;
+pub use frame_offset::{FrameOffset, FrameOffsetPoint};
+
+mod sir0;
+pub use sir0::{Sir0Container, Sir0ContainerError, SIR0_HEADER_SIZE};
+
+mod px_compression;
+pub use px_compression::{
+    compress_px_container, decompress_px_container, px_compress, px_decompress, PxContainerKind,
+    PxError, PX_CONTROL_FLAGS_AMOUNT,
+};
+
+mod pack_file;
+pub use pack_file::{PackFile, PackFileEntry, PackFileError};
+
+mod batch;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub use batch::{process_folder, BatchError};
+pub use batch::{process_pack_file, BatchFailure, BatchReport};
+
+mod frame_render;
+pub use frame_render::FrameRenderError;
+
+mod frame_mask;
+pub use frame_mask::FrameMask;
+
+mod scene_preview;
+pub use scene_preview::{
+    compose_animation_frame_on_background, compose_animation_on_background, render_scene,
+    tile_anchor, MapCamera, SceneSprite, MAP_TILE_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+mod spritebot;
+pub use spritebot::{
+    spritebot_animation_name, SpriteBotCopy, SpriteBotError, SpriteBotExport, SpriteBotSheet,
+    SPRITEBOT_ANIMATION_NAMES, SPRITEBOT_CENTER_COLOR, SPRITEBOT_HAND_LEFT_COLOR,
+    SPRITEBOT_HAND_RIGHT_COLOR, SPRITEBOT_HEAD_COLOR, SPRITEBOT_SHADOW_COLOR,
+};
+
+mod offsets_sheet;
+pub use offsets_sheet::OffsetsSheetError;
+
+mod anchor_detection;
+pub use anchor_detection::detect_anchors;
+
+mod aseprite;
+pub use aseprite::AsepriteError;
+
+mod indexed_png;
+pub use indexed_png::IndexedPngError;
+
+mod quantization;
+pub use quantization::{quantize_image, Dithering, QuantizationOptions, QuantizedImage};
+
+mod color_distance;
+pub use color_distance::ColorDistance;
+
+mod validation;
+pub use validation::{
+    ValidationIssue, ValidationReport, ValidationSeverity, MAX_FRAGMENT_ALLOC_COUNTER,
+    MAX_OAM_ENTRIES,
+};
+
+mod vram_budget;
+pub use vram_budget::{FrameBudgetUsage, HardwareBudget, HardwareBudgetReport, VRAM_CHUNK_SIDE};
+
+mod stats;
+pub use stats::{SectionStats, SpriteStats, LARGEST_FRAMES_AMOUNT};
+
+mod gc;
+pub use gc::GcReport;
+
+mod optimize;
+pub use optimize::{OptimizeError, OptimizeOptions, OptimizeReport};
+
+mod frame_dedup;
+
+mod import;
+pub use import::ImportError;
+
+mod merge;
+pub use merge::MergeError;
+
+mod roundtrip;
+pub use roundtrip::{verify_roundtrip, RoundtripDifference, RoundtripError, RoundtripReport};
+
+mod conformance;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub use conformance::check_folder_conformance;
+pub use conformance::{check_conformance, ConformanceEntry, ConformanceReport, ConformanceStatus};
+
+mod wan_diff;
+pub use wan_diff::{
+    wan_diff, AnimationDiff, ChangeKind, FragmentBytesDiff, FragmentDiff, FrameDiff,
+    PaletteEntryDiff, WanDiff,
+};
+
+mod preservation;
+pub use preservation::PreservedWanImage;
 
 use binwrite::WriterOption;
 pub fn get_opt_le() -> WriterOption {
@@ -81,6 +310,9 @@ pub fn get_opt_le() -> WriterOption {
 #[cfg(feature = "shiren_experimental")]
 pub mod shiren;
 
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
 pub struct GeneralResolution {
     pub x: u32,
@@ -96,7 +328,6 @@ impl GeneralResolution {
         (self.x as u64) * (self.y as u64)
     }
 
-
     pub fn can_contain(&self, other: Self) -> bool {
         self.x >= other.x && self.y >= other.y
     }
@@ -113,6 +344,7 @@ pub fn get_bit_u16(bytes: u16, id: u16) -> Option<bool> {
     }
 }
 
+#[cfg(feature = "shiren_experimental")]
 fn wan_read_raw_4<F: std::io::Read>(file: &mut F) -> Result<[u8; 4], WanError> {
     let mut buffer = [0; 4];
     file.read_exact(&mut buffer)?;
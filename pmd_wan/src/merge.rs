@@ -0,0 +1,137 @@
+use thiserror::Error;
+
+use crate::{import::Importer, ImportError, SpriteType, WanImage};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MergeError {
+    #[error("There is no image to merge")]
+    NoImage,
+    #[error("The image {index} is a {found:?} sprite, but the first one is a {expected:?} sprite")]
+    SpriteTypeMismatch {
+        index: usize,
+        expected: SpriteType,
+        found: SpriteType,
+    },
+    #[error("Failed to import the image {index}")]
+    CantImport {
+        index: usize,
+        #[source]
+        source: ImportError,
+    },
+}
+
+impl WanImage {
+    /// Merge multiple [`WanImage`]s into a single one.
+    ///
+    /// The frames and animation groups of each image are added after the ones of the previous images, so the group `n` of the second image become the group `n + <number of groups of the first image>`.
+    /// Identical [`crate::FragmentBytes`] and palettes are only stored once. Every image should have the same [`SpriteType`] and color depth.
+    /// The header values and [`WanImage::compression`] are taken from the first image.
+    pub fn merge(images: Vec<WanImage>) -> Result<WanImage, MergeError> {
+        let first = images.first().ok_or(MergeError::NoImage)?;
+        let mut result = WanImage::new(first.sprite_type);
+        result.is_256_color = first.is_256_color;
+        result.unk2 = first.unk2;
+        result.unknowns = first.unknowns;
+        result.compression = first.compression.clone();
+        for (index, image) in images.iter().enumerate() {
+            if image.sprite_type != result.sprite_type {
+                return Err(MergeError::SpriteTypeMismatch {
+                    index,
+                    expected: result.sprite_type,
+                    found: image.sprite_type,
+                });
+            }
+            let groups = Importer::new(image)
+                .import_all(&mut result)
+                .map_err(|source| MergeError::CantImport { index, source })?;
+            result.animation_store.anim_groups.extend(groups);
+        }
+        Ok(result)
+    }
+
+    /// Split this image in one [`WanImage`] per animation group, with only the frames, [`crate::FragmentBytes`] and palettes used by the group. This is the inverse of [`WanImage::merge`].
+    ///
+    /// Each image contain a single animation group. The header values and [`WanImage::compression`] are the same as this image.
+    pub fn split_by_animation(&self) -> Result<Vec<WanImage>, ImportError> {
+        (0..self.animation_store.anim_groups.len())
+            .map(|group| {
+                let mut result = WanImage::new(self.sprite_type);
+                result.is_256_color = self.is_256_color;
+                result.unk2 = self.unk2;
+                result.unknowns = self.unknowns;
+                result.compression = self.compression.clone();
+                let animations = result.import_animation_group_from(self, group)?;
+                result.animation_store.anim_groups.push(animations);
+                Ok(result)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{animation_frame, TEST_COLOR},
+        Animation, MergeError, SpriteType, WanImage,
+    };
+
+    fn single_frame_image(color: [u8; 4], pixels: Vec<u8>, side: u16) -> WanImage {
+        let mut wanimage = WanImage::new(SpriteType::PropsUI);
+        wanimage.palette.palette = vec![[0, 0, 0, 0], color];
+        let frame_id = insert_frame_in_wanimage(pixels, side, side, &mut wanimage, 0)
+            .unwrap()
+            .unwrap();
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![animation_frame(frame_id as u16, 1)],
+        }]);
+        wanimage
+    }
+
+    #[test]
+    fn test_merge() {
+        let red = single_frame_image(TEST_COLOR, vec![1; 64], 8);
+        let blue = single_frame_image([0, 0, 255, 128], vec![1; 16], 4);
+        let red_again = single_frame_image(TEST_COLOR, vec![1; 64], 8);
+        let images = vec![red, blue, red_again];
+        let rendered: Vec<_> = images
+            .iter()
+            .map(|image| image.render_frame(0).unwrap())
+            .collect();
+
+        let merged = WanImage::merge(images).unwrap();
+        assert_eq!(merged.frame_store.frames.len(), 3);
+        assert_eq!(merged.animation_store.anim_groups.len(), 3);
+        // the red palette and its pixels are shared
+        assert_eq!(merged.palette.palette.len(), 32);
+        assert_eq!(merged.fragment_bytes_store.len(), 2);
+        for (group, image) in rendered.iter().enumerate() {
+            let frame_id = merged.animation_store.anim_groups[group][0].frames[0].frame_id;
+            assert_eq!(&merged.render_frame(frame_id as usize).unwrap(), image);
+        }
+        merged.write_to_vec().unwrap();
+
+        assert_eq!(WanImage::merge(Vec::new()), Err(MergeError::NoImage));
+    }
+
+    #[test]
+    fn test_split_by_animation() {
+        let red = single_frame_image(TEST_COLOR, vec![1; 64], 8);
+        let blue = single_frame_image([0, 0, 255, 128], vec![1; 16], 4);
+        let merged = WanImage::merge(vec![red, blue]).unwrap();
+
+        let split = merged.split_by_animation().unwrap();
+        assert_eq!(split.len(), 2);
+        for (group, image) in split.iter().enumerate() {
+            assert_eq!(image.frame_store.frames.len(), 1);
+            assert_eq!(image.fragment_bytes_store.len(), 1);
+            assert_eq!(image.palette.palette.len(), 16);
+            assert_eq!(image.animation_store.anim_groups.len(), 1);
+            let merged_frame_id = merged.animation_store.anim_groups[group][0].frames[0].frame_id;
+            assert_eq!(
+                image.render_frame(0).unwrap(),
+                merged.render_frame(merged_frame_id as usize).unwrap()
+            );
+        }
+    }
+}
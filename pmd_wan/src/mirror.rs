@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{Animation, AnimationFrame, Frame, FrameOffset, WanImage, DIRECTION_COUNT};
+
+/// The pairs of directions that are the horizontal mirror of each other, as (right-facing, left-facing).
+/// Directions start at 0 for down, and turn counterclockwise (2 is right, 4 is up and 6 is left).
+pub const MIRRORED_DIRECTIONS: [(usize, usize); 3] = [(1, 7), (2, 6), (3, 5)];
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MirrorError {
+    #[error("The animation group {0} doesn't exist")]
+    NoGroup(usize),
+    #[error("The animation group {0} has {1} animations, instead of one per direction")]
+    NotDirectional(usize, usize),
+    #[error("The frame {0} doesn't exist")]
+    NoFrame(usize),
+    #[error("The frame {0} can't be mirrored, as a fragment would be placed too far on the left")]
+    OffsetOutOfRange(usize),
+}
+
+impl Frame {
+    /// Return the horizontal mirror of this [`Frame`], around its origin.
+    ///
+    /// The [`crate::Fragment`]s keep using the same [`crate::FragmentBytes`], with their horizontal flip inverted.
+    /// The x coordinates of the [`FrameOffset`] are also mirrored (the points keep their name).
+    /// Return [`None`] if a [`crate::Fragment`] would end at an offset that can't be encoded.
+    pub fn mirrored(&self) -> Option<Frame> {
+        let mut fragments = Vec::with_capacity(self.fragments.len());
+        for fragment in &self.fragments {
+            let mut mirrored = fragment.clone();
+            let offset_x = -(fragment.offset_x as i32 + fragment.resolution.size().x as i32);
+            if !(-256..256).contains(&offset_x) {
+                return None;
+            }
+            mirrored.offset_x = offset_x as i16;
+            mirrored.flip.flip_h = !fragment.flip.flip_h;
+            fragments.push(mirrored);
+        }
+        let mirror_point = |(x, y): (i16, i16)| (x.saturating_neg(), y);
+        Some(Frame {
+            fragments,
+            frame_offset: self.frame_offset.as_ref().map(|frame_offset| FrameOffset {
+                head: mirror_point(frame_offset.head),
+                hand_left: mirror_point(frame_offset.hand_left),
+                hand_right: mirror_point(frame_offset.hand_right),
+                center: mirror_point(frame_offset.center),
+            }),
+        })
+    }
+}
+
+impl WanImage {
+    /// Build the mirrored left-facing [`Animation`]s of `group`, as `(direction, animation)`, without modifying the [`WanImage`].
+    ///
+    /// The mirrored [`Frame`]s are added to `new_frames`, which will be appended to the frame store.
+    fn mirror_group(
+        &self,
+        group: usize,
+        new_frames: &mut Vec<Frame>,
+    ) -> Result<Vec<(usize, Animation)>, MirrorError> {
+        let animations = self
+            .animation_store
+            .anim_groups
+            .get(group)
+            .ok_or(MirrorError::NoGroup(group))?;
+        if animations.len() != DIRECTION_COUNT {
+            return Err(MirrorError::NotDirectional(group, animations.len()));
+        }
+
+        let mut mirrored_frames: HashMap<u16, u16> = HashMap::new();
+        let mut new_animations = Vec::new();
+        for (source, target) in MIRRORED_DIRECTIONS.iter().copied() {
+            let mut mirrored_animation = Animation::default();
+            for animation_frame in &animations[source].frames {
+                let frame_id = match mirrored_frames.get(&animation_frame.frame_id) {
+                    Some(frame_id) => *frame_id,
+                    None => {
+                        let source_id = animation_frame.frame_id as usize;
+                        let frame = self
+                            .frame_store
+                            .frames
+                            .get(source_id)
+                            .ok_or(MirrorError::NoFrame(source_id))?
+                            .mirrored()
+                            .ok_or(MirrorError::OffsetOutOfRange(source_id))?;
+                        let frame_id = (self.frame_store.frames.len() + new_frames.len()) as u16;
+                        new_frames.push(frame);
+                        mirrored_frames.insert(animation_frame.frame_id, frame_id);
+                        frame_id
+                    }
+                };
+                mirrored_animation.frames.push(AnimationFrame {
+                    frame_id,
+                    offset_x: animation_frame.offset_x.saturating_neg(),
+                    shadow_offset_x: animation_frame.shadow_offset_x.saturating_neg(),
+                    ..animation_frame.clone()
+                });
+            }
+            new_animations.push((target, mirrored_animation));
+        }
+        Ok(new_animations)
+    }
+
+    /// Create the left-facing [`Animation`]s of a directional animation group from the right-facing ones (see [`MIRRORED_DIRECTIONS`]), replacing the existing ones.
+    ///
+    /// Each [`Frame`] used by a right-facing [`Animation`] is mirrored (see [`Frame::mirrored`]) once and added to the [`WanImage`], and the horizontal offsets of the [`AnimationFrame`]s are mirrored.
+    /// The [`WanImage`] is left untouched on error.
+    pub fn fill_mirrored_directions(&mut self, group: usize) -> Result<(), MirrorError> {
+        let mut new_frames = Vec::new();
+        let new_animations = self.mirror_group(group, &mut new_frames)?;
+        self.frame_store.frames.extend(new_frames);
+        for (target, animation) in new_animations {
+            self.animation_store.anim_groups[group][target] = animation;
+        }
+        Ok(())
+    }
+
+    /// Call [`WanImage::fill_mirrored_directions`] on every animation group with one [`Animation`] per direction.
+    ///
+    /// If any group fails, none of them are modified.
+    pub fn fill_all_mirrored_directions(&mut self) -> Result<(), MirrorError> {
+        let mut new_frames = Vec::new();
+        let mut new_animations = Vec::new();
+        for group in 0..self.animation_store.anim_groups.len() {
+            if self.animation_store.anim_groups[group].len() == DIRECTION_COUNT {
+                new_animations.push((group, self.mirror_group(group, &mut new_frames)?));
+            }
+        }
+        self.frame_store.frames.extend(new_frames);
+        for (group, animations) in new_animations {
+            for (target, animation) in animations {
+                self.animation_store.anim_groups[group][target] = animation;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{animation_frame, insert_filled_frame, test_wan_image},
+        Animation, AnimationFrame, MirrorError, SpriteType, WanImage,
+    };
+
+    #[test]
+    fn test_fill_mirrored_directions() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.push([0, 0, 255, 128]);
+        // red on the left, blue on the right
+        let mut pixels = vec![1; 16 * 8];
+        for line in pixels.chunks_mut(16) {
+            line[8..].fill(2);
+        }
+        insert_frame_in_wanimage(pixels, 16, 8, &mut wanimage, 0).unwrap();
+        let animation = |offset_x| Animation {
+            frames: vec![AnimationFrame {
+                offset_x,
+                shadow_offset_x: 3,
+                ..animation_frame(0, 1)
+            }],
+        };
+        wanimage.animation_store.anim_groups = vec![vec![animation(4); 8]];
+
+        wanimage.fill_all_mirrored_directions().unwrap();
+        let group = &wanimage.animation_store.anim_groups[0];
+        assert_eq!(wanimage.frame_store.frames.len(), 2);
+        for (source, target) in [(1, 7), (2, 6), (3, 5)] {
+            assert_eq!(group[source], animation(4));
+            assert_eq!(group[target].frames[0].frame_id, 1);
+            assert_eq!(group[target].frames[0].offset_x, -4);
+            assert_eq!(group[target].frames[0].shadow_offset_x, -3);
+        }
+
+        let original = wanimage.render_frame(0).unwrap();
+        let mirrored = wanimage.render_frame(1).unwrap();
+        assert_eq!(original.dimensions(), mirrored.dimensions());
+        assert_eq!(original.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(mirrored.get_pixel(0, 0), &Rgba([0, 0, 255, 255]));
+        assert_eq!(
+            wanimage.frame_store.frames[0].render_origin(),
+            wanimage.frame_store.frames[1].render_origin()
+        );
+
+        wanimage
+            .animation_store
+            .anim_groups
+            .push(vec![animation(0)]);
+        assert_eq!(
+            wanimage.fill_mirrored_directions(1),
+            Err(MirrorError::NotDirectional(1, 1))
+        );
+    }
+    #[test]
+    fn test_fill_mirrored_directions_untouched_on_error() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        insert_filled_frame(&mut wanimage, 8, 8);
+        let animation = |frame_id| Animation {
+            frames: vec![animation_frame(frame_id, 1)],
+        };
+        // the second group mirror the frame 0, then fail on the missing frame 10
+        let mut broken_group = vec![animation(0); 8];
+        broken_group[2] = animation(10);
+        wanimage.animation_store.anim_groups = vec![vec![animation(0); 8], broken_group];
+        let frames_before = wanimage.frame_store.frames.clone();
+        let groups_before = wanimage.animation_store.anim_groups.clone();
+        let untouched = |wanimage: &WanImage| {
+            wanimage.frame_store.frames == frames_before
+                && wanimage.animation_store.anim_groups == groups_before
+        };
+
+        assert_eq!(
+            wanimage.fill_mirrored_directions(1),
+            Err(MirrorError::NoFrame(10))
+        );
+        assert!(untouched(&wanimage));
+        assert_eq!(
+            wanimage.fill_all_mirrored_directions(),
+            Err(MirrorError::NoFrame(10))
+        );
+        assert!(untouched(&wanimage));
+    }
+}
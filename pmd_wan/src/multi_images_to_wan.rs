@@ -5,9 +5,14 @@ use std::{
 };
 
 use crate::{
-    encode_fragment_pixels, find_fragments_in_images, fragment_finder::FragmentUse,
-    pad_seven_pixel, Fragment, FragmentBytes, FragmentFinderData, FragmentFlip, OamShape,
-    Frame, GeneralResolution, NormalizedBytes, SpriteType, VariableNormalizedBytes, WanImage,
+    find_fragments_in_images_with_progress,
+    fragment_finder::FragmentUse,
+    pad_seven_pixel,
+    progress::{no_progress, start_phase},
+    Animation, AnimationFrame, CancellationToken, Cancelled, Fragment, FragmentBytes,
+    FragmentFinderData, FragmentFlip, Frame, GeneralResolution, NormalizedBytes, OamShape,
+    ProgressCallback, ProgressPhase, SpriteType, VariableNormalizedBytes, WanImage,
+    ANIMATION_FRAME_FLAG_RETURN_POINT, DEFAULT_FRAGMENT_PRIORITY, DIRECTION_COUNT,
 };
 use anyhow::{bail, Context};
 
@@ -20,10 +25,11 @@ struct ImageStartDelta {
 impl ImageStartDelta {
     fn new(selected_x: i32, selected_y: i32) -> Self {
         fn get_appropriate_value(value: i32) -> i8 {
-            if value % 8 == 0 {
+            // the tile may start before the image, so value can be negative
+            if value.rem_euclid(8) == 0 {
                 0
             } else {
-                -8 + ((value % 8) as i8)
+                -8 + (value.rem_euclid(8) as i8)
             }
         }
         Self {
@@ -33,9 +39,126 @@ impl ImageStartDelta {
     }
 }
 
+/// An [`crate::Animation`] to create with [`create_wan_from_multiple_images_with_animations`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MultiImagesAnimation {
+    /// The images to display, as index in the input images, with their duration (in 1/60th of second, like [`AnimationFrame::duration`])
+    pub frames: Vec<(usize, u8)>,
+    /// The index in [`MultiImagesAnimation::frames`] the animation restart from when looping, marked with [`ANIMATION_FRAME_FLAG_RETURN_POINT`]. If [`None`], it restart from the first frame.
+    pub return_point: Option<usize>,
+}
+
+/// Create a [`WanImage`] with one frame per image, in the same order.
+///
+/// The result is the same on every run for the same images, so it can be encoded to the same bytes (see [`WanImage::write_to_vec`]).
 pub fn create_wan_from_multiple_images(
     images: &[(&[u8], GeneralResolution)],
     sprite_type: SpriteType,
+) -> anyhow::Result<WanImage> {
+    create_wan_from_multiple_images_with_animations(images, sprite_type, &[])
+}
+
+/// Like [`create_wan_from_multiple_images`], but also create the animation groups, so the image can be used without further editing.
+///
+/// Each group contain either 1 animation (used for all directions), [`DIRECTION_COUNT`] of them (one per direction), or none.
+pub fn create_wan_from_multiple_images_with_animations(
+    images: &[(&[u8], GeneralResolution)],
+    sprite_type: SpriteType,
+    groups: &[Vec<MultiImagesAnimation>],
+) -> anyhow::Result<WanImage> {
+    create_wan_from_multiple_images_with_progress(
+        images,
+        sprite_type,
+        groups,
+        &no_progress,
+        &CancellationToken::new(),
+    )
+}
+
+/// Like [`create_wan_from_multiple_images_with_animations`], but report the progress of the [`ProgressPhase::FindingFragments`], [`ProgressPhase::IndexingTiles`] and [`ProgressPhase::AssemblingFragments`] phases, in this order.
+///
+/// `cancellation` is checked after each step of those phases. Once it is cancelled, the returned error contain [`Cancelled`], that can be found with [`anyhow::Error::downcast_ref`].
+pub fn create_wan_from_multiple_images_with_progress(
+    images: &[(&[u8], GeneralResolution)],
+    sprite_type: SpriteType,
+    groups: &[Vec<MultiImagesAnimation>],
+    progress: ProgressCallback,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<WanImage> {
+    let mut anim_groups = Vec::with_capacity(groups.len());
+    for (group_id, group) in groups.iter().enumerate() {
+        if ![0, 1, DIRECTION_COUNT].contains(&group.len()) {
+            bail!(
+                "The animation group {} contain {} animations, but it should contain 1 (for all directions) or {} (one per direction)",
+                group_id,
+                group.len(),
+                DIRECTION_COUNT
+            );
+        }
+        let mut animations = Vec::with_capacity(group.len());
+        for (animation_id, animation) in group.iter().enumerate() {
+            if let Some(return_point) = animation.return_point {
+                if return_point >= animation.frames.len() {
+                    bail!(
+                        "The return point {} of the animation {} of the group {} is after its last frame",
+                        return_point,
+                        animation_id,
+                        group_id
+                    );
+                }
+            }
+            let mut frames = Vec::with_capacity(animation.frames.len());
+            for (frame_nb, (image_id, duration)) in animation.frames.iter().enumerate() {
+                if *image_id >= images.len() {
+                    bail!(
+                        "The animation {} of the group {} use the image {}, but there are only {} images",
+                        animation_id,
+                        group_id,
+                        image_id,
+                        images.len()
+                    );
+                }
+                if *duration == 0 {
+                    bail!(
+                        "The frame {} of the animation {} of the group {} has a duration of 0",
+                        frame_nb,
+                        animation_id,
+                        group_id
+                    );
+                }
+                frames.push(AnimationFrame {
+                    duration: *duration,
+                    flag: if animation.return_point == Some(frame_nb) {
+                        ANIMATION_FRAME_FLAG_RETURN_POINT
+                    } else {
+                        0
+                    },
+                    // the number of images is checked by cut_multiple_images
+                    frame_id: *image_id as u16,
+                    offset_x: 0,
+                    offset_y: 0,
+                    shadow_offset_x: 0,
+                    shadow_offset_y: 0,
+                });
+            }
+            animations.push(Animation { frames });
+        }
+        anim_groups.push(animations);
+    }
+
+    let mut wan = cut_multiple_images(images, sprite_type, false, progress, cancellation)?;
+    wan.fix_empty_frames();
+    wan.animation_store.anim_groups = anim_groups;
+    Ok(wan)
+}
+
+/// Cut the images in [`Fragment`]s as done by [`create_wan_from_multiple_images`], in a new [`WanImage`] with one frame per image. The frames of fully transparent images are empty.
+fn cut_multiple_images(
+    images: &[(&[u8], GeneralResolution)],
+    sprite_type: SpriteType,
+    is_256_color: bool,
+    progress: ProgressCallback,
+    cancellation: &CancellationToken,
 ) -> anyhow::Result<WanImage> {
     //high level overview of how this work :
     //1. Get fragments (8 by 8) usage stats
@@ -51,10 +174,11 @@ pub fn create_wan_from_multiple_images(
         )
     }
     // step 1 and 2
-    let images_deltas =
-        get_images_delta(images).context("while trying to get the images deltas")?;
+    let images_deltas = get_images_delta(images, progress, cancellation)
+        .context("while trying to get the images deltas")?;
 
     // step 3
+    let step = start_phase(progress, ProgressPhase::IndexingTiles, images.len());
     let mut bigger_fragment_finder_builder = BiggerFragmentFinderBuilder::new(images.len() as u16);
     for (image_id, (start_delta, (image_bytes, image_resolution))) in
         images_deltas.iter().zip(images).enumerate()
@@ -65,61 +189,143 @@ pub fn create_wan_from_multiple_images(
             *start_delta,
             image_id as u16,
         );
+        step();
+        cancellation.check()?;
     }
     let bigger_fragment_finder = bigger_fragment_finder_builder.build();
 
     // initialise wan and Frames
     let mut wan = WanImage::new(sprite_type);
+    wan.is_256_color = is_256_color;
     wan.frame_store.frames = vec![Frame::default(); images.len()];
 
     // step 4 and 5 are combined
-    bigger_fragment_finder.find_and_apply_on_wan(&mut wan);
+    bigger_fragment_finder.find_and_apply_on_wan(&mut wan, progress, cancellation)?;
 
-    wan.fix_empty_frames();
     Ok(wan)
 }
 
-fn get_images_delta(images: &[(&[u8], GeneralResolution)]) -> anyhow::Result<Vec<ImageStartDelta>> {
-    let fragments_use: FragmentFinderData = find_fragments_in_images(images)
-        .context("Trying to find statistic about fragments usage")?;
-    let fragment_ordered_by_usage = fragments_use.order_by_usage();
-    let mut result = Vec::new();
-    for (image_id, _image) in images.iter().enumerate() {
-        fn find_most_used_tile(
-            ordered: &[(&NormalizedBytes, &Vec<FragmentUse>)],
-            image_id: u16,
-            limit: bool,
-        ) -> Option<(NormalizedBytes, FragmentUse)> {
-            for (fragment, all_usage) in ordered.iter().map(|(x, y)| (*x, *y)) {
-                for usage in all_usage {
-                    if usage.image_id == image_id
-                        && !limit
-                        && fragment.0.iter().filter(|x| **x != 0).count() > (64 / 4) * 3
-                    {
-                        return Some((*fragment, *usage));
-                    }
-                }
-            }
-            None
+/// Insert multiple images (of palette indices, line by line from the top-left pixel) as new frames using the palette row `pal_id`, and return their frame ids.
+///
+/// As with [`crate::insert_frame_in_wanimage`], each image is centered on the origin of its frame, and fully transparent images aren't inserted (their id is [`None`]).
+/// Unlike inserting them one by one, the 8×8 tiles of every image are indexed together (see [`crate::find_fragments_in_images`]), so a tile that appear in multiple frames is only stored once.
+/// [`FragmentBytes`] identical to the ones already in the [`WanImage`] are reused. Nothing is modified on error.
+pub fn insert_frames_in_wanimage(
+    images: &[(&[u8], GeneralResolution)],
+    wanimage: &mut WanImage,
+    pal_id: u16,
+) -> anyhow::Result<Vec<Option<usize>>> {
+    for (image_id, (_, resolution)) in images.iter().enumerate() {
+        if resolution.y >= 256 || resolution.x >= 512 {
+            bail!(
+                "The image {} is {}×{}, while only images smaller than 512×256 can be used",
+                image_id,
+                resolution.x,
+                resolution.y
+            );
+        }
+    }
+    let cut = cut_multiple_images(
+        images,
+        wanimage.sprite_type,
+        wanimage.is_256_color,
+        &no_progress,
+        &CancellationToken::new(),
+    )?;
+
+    let mut frames = Vec::with_capacity(images.len());
+    for (image_id, (mut frame, (_, resolution))) in
+        cut.frame_store.frames.into_iter().zip(images).enumerate()
+    {
+        if frame.fragments.is_empty() {
+            frames.push(None);
+            continue;
+        }
+        let delta_x = -(resolution.x as i32) / 2;
+        let delta_y = -(resolution.y as i32) / 2;
+        for fragment in &mut frame.fragments {
+            fragment.pal_idx = pal_id;
+            fragment.offset_x = (fragment.offset_x as i32 + delta_x)
+                .try_into()
+                .with_context(|| format!("The image {} is too large", image_id))?;
+            fragment.offset_y = (fragment.offset_y as i32 + delta_y)
+                .try_into()
+                .with_context(|| format!("The image {} is too high", image_id))?;
         }
+        frames.push(Some(frame));
+    }
+
+    let mut existing: HashMap<Vec<u8>, usize> = HashMap::new();
+    for (index, fragment_bytes) in wanimage
+        .fragment_bytes_store
+        .fragment_bytes
+        .iter()
+        .enumerate()
+        .rev()
+    {
+        existing.insert(fragment_bytes.mixed_pixels.clone(), index);
+    }
+    let mut fragment_bytes_remap = Vec::with_capacity(cut.fragment_bytes_store.len());
+    for fragment_bytes in cut.fragment_bytes_store.fragment_bytes {
+        let store = &mut wanimage.fragment_bytes_store.fragment_bytes;
+        let index = *existing
+            .entry(fragment_bytes.mixed_pixels.clone())
+            .or_insert_with(|| {
+                store.push(fragment_bytes);
+                store.len() - 1
+            });
+        fragment_bytes_remap.push(index);
+    }
+
+    Ok(frames
+        .into_iter()
+        .map(|frame| {
+            let mut frame = frame?;
+            for fragment in &mut frame.fragments {
+                fragment.fragment_bytes_index = fragment_bytes_remap[fragment.fragment_bytes_index];
+            }
+            wanimage.frame_store.frames.push(frame);
+            Some(wanimage.frame_store.frames.len() - 1)
+        })
+        .collect())
+}
 
-        let mut appropriate_fragment =
-            find_most_used_tile(&fragment_ordered_by_usage, image_id as u16, true);
-        if appropriate_fragment.is_none() {
-            appropriate_fragment =
-                find_most_used_tile(&fragment_ordered_by_usage, image_id as u16, false)
+fn get_images_delta(
+    images: &[(&[u8], GeneralResolution)],
+    progress: ProgressCallback,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<Vec<ImageStartDelta>> {
+    let fragments_use: FragmentFinderData =
+        find_fragments_in_images_with_progress(images, progress, cancellation)
+            .context("Trying to find statistic about fragments usage")?;
+    // index the most used fragment that is mostly opaque of each image, in a single pass over the usages
+    let mut most_used_by_image: HashMap<u16, FragmentUse> = HashMap::new();
+    for (fragment, all_usage) in fragments_use.order_by_usage() {
+        if fragment.0.iter().filter(|x| **x != 0).count() <= (64 / 4) * 3 {
+            continue;
+        }
+        for usage in all_usage {
+            most_used_by_image.entry(usage.image_id).or_insert(*usage);
         }
-        let base_coordinates = if let Some((_, fragment_use)) = appropriate_fragment {
-            (fragment_use.x, fragment_use.y)
-        } else {
-            (0, 0)
-        };
-        result.push(ImageStartDelta::new(base_coordinates.0, base_coordinates.1))
     }
+    let result = (0..images.len())
+        .map(
+            |image_id| match most_used_by_image.get(&(image_id as u16)) {
+                Some(fragment_use) => ImageStartDelta::new(fragment_use.x, fragment_use.y),
+                None => ImageStartDelta::new(0, 0),
+            },
+        )
+        .collect();
 
     Ok(result)
 }
 
+/// Convert a flip used with [`FragmentFlip::apply`] (as returned by [`NormalizedBytes::new`]) to the one of a [`Fragment`].
+/// [`FragmentFlip::apply`] mirror the lines for `flip_h` and the columns for `flip_v`, while the DS does the opposite.
+fn to_fragment_flip(flip: FragmentFlip) -> FragmentFlip {
+    FragmentFlip::from_bools(flip.flip_h, flip.flip_v)
+}
+
 #[derive(Debug)]
 struct BiggerFragmentFinderBuilder {
     presence: HashMap<NormalizedBytes, (Vec<bool>, BTreeSet<FragmentUse>)>,
@@ -143,21 +349,11 @@ impl BiggerFragmentFinderBuilder {
     ) {
         let (padded_image, padded_resolution) =
             pad_seven_pixel(image_bytes, resolution.clone()).unwrap();
-        let pixel_start_in_padded_image = (
-            if delta.delta_x == 0 {
-                0
-            } else {
-                delta.delta_x + 7
-            },
-            if delta.delta_y == 0 {
-                0
-            } else {
-                delta.delta_y + 7
-            },
-        );
+        // the padded image start 7 pixels before the image
+        let pixel_start_in_padded_image = (delta.delta_x + 7, delta.delta_y + 7);
         let loop_number_by_side = (
-            (-delta.delta_x as u32 + resolution.x + 7) / 8,
-            (-delta.delta_y as u32 + resolution.y + 7) / 8,
+            (-delta.delta_x as u32 + resolution.x).div_ceil(8),
+            (-delta.delta_y as u32 + resolution.y).div_ceil(8),
         );
         for global_fragment_position_y in 0..loop_number_by_side.1 {
             let fragment_start_y =
@@ -209,7 +405,7 @@ impl BiggerFragmentFinderBuilder {
         for (normalized_bytes, (presence, usages)) in self.presence {
             usage_by_image
                 .entry(presence)
-                .or_insert_with(HashMap::new)
+                .or_insert_with(BTreeMap::new)
                 .insert(normalized_bytes, usages);
         }
 
@@ -220,14 +416,27 @@ impl BiggerFragmentFinderBuilder {
 #[derive(Debug, Clone)]
 struct BiggerFragmentFinder {
     //TODO: somehow use a bitset instead
-    usage_by_image: BTreeMap<Vec<bool>, HashMap<NormalizedBytes, BTreeSet<FragmentUse>>>,
+    usage_by_image: BTreeMap<Vec<bool>, BTreeMap<NormalizedBytes, BTreeSet<FragmentUse>>>,
 }
 
 impl BiggerFragmentFinder {
-    fn find_and_apply_on_wan(self, wan: &mut WanImage) {
+    fn find_and_apply_on_wan(
+        self,
+        wan: &mut WanImage,
+        progress: ProgressCallback,
+        cancellation: &CancellationToken,
+    ) -> Result<(), Cancelled> {
+        let step = start_phase(
+            progress,
+            ProgressPhase::AssemblingFragments,
+            self.usage_by_image.len(),
+        );
         for (_, group) in self.usage_by_image.into_iter() {
             FindBiggerFragmentOnSingleGroupStruct::process(group, wan);
+            step();
+            cancellation.check()?;
         }
+        Ok(())
     }
 }
 
@@ -250,13 +459,14 @@ impl FragmentPosition {
 }
 
 struct FindBiggerFragmentOnSingleGroupStruct<'a> {
-    group: HashMap<NormalizedBytes, BTreeSet<FragmentUse>>,
+    /// Sorted, so the [`FragmentBytes`] are added in the same order on every run
+    group: BTreeMap<NormalizedBytes, BTreeSet<FragmentUse>>,
     lookup_by_use: HashMap<FragmentPosition, (NormalizedBytes, FragmentFlip)>,
     wan: &'a mut WanImage,
 }
 
 impl<'a> FindBiggerFragmentOnSingleGroupStruct<'a> {
-    fn process(group: HashMap<NormalizedBytes, BTreeSet<FragmentUse>>, wan: &'a mut WanImage) {
+    fn process(group: BTreeMap<NormalizedBytes, BTreeSet<FragmentUse>>, wan: &'a mut WanImage) {
         let mut lookup_by_use = HashMap::new();
         for (key, value) in group.iter() {
             for usage in value {
@@ -289,7 +499,10 @@ impl<'a> FindBiggerFragmentOnSingleGroupStruct<'a> {
             (1, 1),
             (1, 0),
             (2, 0),
-        ].into_iter() {
+        ]
+        .iter()
+        .copied()
+        {
             let resolution = OamShape::new(shape_indice, size_indice).unwrap();
             s.process_resolution(resolution);
         }
@@ -300,14 +513,17 @@ impl<'a> FindBiggerFragmentOnSingleGroupStruct<'a> {
             // TODO: this is mostly copy–pasted from the process_resolution function
             // add the bytes
             let image_bytes_index = s.wan.fragment_bytes_store.len();
+            let fragment_bytes = FragmentBytes::new_from_pixels(
+                &bytes.0,
+                OamShape::new(0, 0).unwrap().size(),
+                s.wan.is_256_color,
+                0,
+            )
+            .unwrap();
             s.wan
                 .fragment_bytes_store
                 .fragment_bytes
-                .push(FragmentBytes {
-                    mixed_pixels: encode_fragment_pixels(&bytes.0, OamShape::new(0, 0).unwrap().size())
-                        .unwrap(),
-                    z_index: 0,
-                });
+                .push(fragment_bytes);
             // and their usage
             for usage in use_of_this_byte {
                 let frame = &mut s.wan.frame_store.frames[usage.image_id as usize];
@@ -318,8 +534,9 @@ impl<'a> FindBiggerFragmentOnSingleGroupStruct<'a> {
                     fragment_bytes_index: image_bytes_index,
                     offset_y: usage.y.try_into().unwrap(),
                     offset_x: usage.x.try_into().unwrap(),
-                    flip: usage.flip,
+                    flip: to_fragment_flip(usage.flip),
                     is_mosaic: false,
+                    priority: DEFAULT_FRAGMENT_PRIORITY,
                     pal_idx: 0,
                     resolution: OamShape::new(0, 0).unwrap(),
                 });
@@ -344,7 +561,8 @@ impl<'a> FindBiggerFragmentOnSingleGroupStruct<'a> {
         };
 
         let mut normal_chunk_line = vec![vec![0; 64]; nb_chunk_x as usize];
-        let mut bigger_fragment: Vec<u8> = Vec::with_capacity(resolution.size().nb_pixels() as usize);
+        let mut bigger_fragment: Vec<u8> =
+            Vec::with_capacity(resolution.size().nb_pixels() as usize);
         'next_fragment: while let Some(possible_fragment) = {
             //NOTE: use pop_last (or pop_first) when stabilized
             if let Some(selected) = { remaining_fragments_to_check.iter().next().copied() } {
@@ -386,7 +604,7 @@ impl<'a> FindBiggerFragmentOnSingleGroupStruct<'a> {
                                     .unwrap();
                                     used_fragments
                                         .entry(*norm_bytes)
-                                        .or_insert_with(BTreeSet::new)
+                                        .or_default()
                                         .insert(target_fragment_position.to_fragment_use(*flip));
                                 } else {
                                     normal_chunk_line[small_fragment_row as usize] = vec![0; 64];
@@ -434,17 +652,17 @@ impl<'a> FindBiggerFragmentOnSingleGroupStruct<'a> {
                     // Yay, we found a bunch of big fragment we can finally push that to Wan
                     // push the bytes
                     let image_bytes_index = self.wan.fragment_bytes_store.len();
+                    let fragment_bytes = FragmentBytes::new_from_pixels(
+                        &base_bigger_fragment.unwrap().0,
+                        resolution.size(),
+                        self.wan.is_256_color,
+                        0,
+                    )
+                    .unwrap();
                     self.wan
                         .fragment_bytes_store
                         .fragment_bytes
-                        .push(FragmentBytes {
-                            mixed_pixels: encode_fragment_pixels(
-                                &base_bigger_fragment.unwrap().0,
-                                resolution.size(),
-                            )
-                            .unwrap(),
-                            z_index: 0,
-                        });
+                        .push(fragment_bytes);
                     // and their usage
                     for (position, flip) in all_big_fragment {
                         self.wan.frame_store.frames[position.image_id as usize]
@@ -456,8 +674,9 @@ impl<'a> FindBiggerFragmentOnSingleGroupStruct<'a> {
                                 fragment_bytes_index: image_bytes_index,
                                 offset_y: position.y.try_into().unwrap(),
                                 offset_x: position.x.try_into().unwrap(),
-                                flip,
+                                flip: to_fragment_flip(flip),
                                 is_mosaic: false,
+                                priority: DEFAULT_FRAGMENT_PRIORITY,
                                 pal_idx: 0,
                                 resolution,
                             });
@@ -480,3 +699,254 @@ impl<'a> FindBiggerFragmentOnSingleGroupStruct<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::sync::Mutex;
+
+    use crate::{
+        create_wan_from_multiple_images, create_wan_from_multiple_images_with_animations,
+        create_wan_from_multiple_images_with_progress, insert_frame_in_wanimage,
+        insert_frames_in_wanimage, tests::fixtures::test_wan_image, Animation, CancellationToken,
+        Cancelled, CompressionMethod, GeneralResolution, MultiImagesAnimation, Progress,
+        ProgressPhase, SpriteType, WanImage, DIRECTION_COUNT,
+    };
+
+    /// A 16×16 image with the same pattern in its top-left 8×8 tile, and another one elsewhere
+    fn image_with_shared_tile(other_tile: usize) -> Vec<u8> {
+        let mut image = vec![0; 16 * 16];
+        for y in 0..8 {
+            for x in 0..8 {
+                image[y * 16 + x] = ((x + y * 3) % 3 + 1) as u8;
+                let (other_x, other_y) = [(8, 0), (8, 8), (0, 8)][other_tile];
+                image[(y + other_y) * 16 + x + other_x] = ((x * y) % 2 + 1) as u8;
+            }
+        }
+        image
+    }
+
+    /// The opaque pixels of a frame, relative to its origin
+    fn visible_pixels(wanimage: &WanImage, frame_id: usize) -> BTreeSet<(i32, i32, [u8; 4])> {
+        let (origin_x, origin_y) = wanimage.frame_store.frames[frame_id].render_origin();
+        wanimage
+            .render_frame(frame_id)
+            .unwrap()
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel.0[3] != 0)
+            .map(|(x, y, pixel)| (x as i32 - origin_x, y as i32 - origin_y, pixel.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_frames_in_wanimage() {
+        let images = [
+            image_with_shared_tile(0),
+            image_with_shared_tile(1),
+            vec![0; 64],
+            image_with_shared_tile(2),
+        ];
+        let resolutions = [16, 16, 8, 16];
+        let inputs: Vec<(&[u8], GeneralResolution)> = images
+            .iter()
+            .zip(resolutions)
+            .map(|(image, side)| (&image[..], GeneralResolution::new(side, side)))
+            .collect();
+
+        let mut one_by_one = test_wan_image(SpriteType::PropsUI);
+        one_by_one
+            .palette
+            .palette
+            .extend([[0, 255, 0, 128], [0, 0, 255, 128]]);
+        let mut batch = WanImage::new(SpriteType::PropsUI);
+        batch.palette.palette = one_by_one.palette.palette.clone();
+
+        let mut expected_ids = Vec::new();
+        for (image, side) in images.iter().zip(resolutions) {
+            expected_ids.push(
+                insert_frame_in_wanimage(
+                    image.clone(),
+                    side as u16,
+                    side as u16,
+                    &mut one_by_one,
+                    0,
+                )
+                .unwrap(),
+            );
+        }
+        let ids = insert_frames_in_wanimage(&inputs, &mut batch, 0).unwrap();
+        assert_eq!(ids, expected_ids);
+        assert_eq!(ids, vec![Some(0), Some(1), None, Some(2)]);
+
+        for frame_id in 0..3 {
+            assert_eq!(
+                visible_pixels(&batch, frame_id),
+                visible_pixels(&one_by_one, frame_id)
+            );
+        }
+        // the shared tile is only stored once
+        let used_by = |frame_id: usize| -> BTreeSet<usize> {
+            batch.frame_store.frames[frame_id]
+                .fragments
+                .iter()
+                .map(|fragment| fragment.fragment_bytes_index)
+                .collect()
+        };
+        assert!(used_by(0)
+            .intersection(&used_by(1))
+            .any(|index| used_by(2).contains(index)));
+
+        // the FragmentBytes are reused when inserting the same images again
+        let fragment_bytes_count = batch.fragment_bytes_store.len();
+        insert_frames_in_wanimage(&inputs, &mut batch, 0).unwrap();
+        assert_eq!(batch.fragment_bytes_store.len(), fragment_bytes_count);
+        assert_eq!(batch.frame_store.frames.len(), 6);
+    }
+
+    #[test]
+    fn test_reproducible_conversion() {
+        let images: Vec<Vec<u8>> = (0..3).map(image_with_shared_tile).collect();
+        let inputs: Vec<(&[u8], GeneralResolution)> = images
+            .iter()
+            .map(|image| (&image[..], GeneralResolution::new(16, 16)))
+            .collect();
+        let encode = || {
+            let mut wanimage =
+                create_wan_from_multiple_images(&inputs, SpriteType::PropsUI).unwrap();
+            wanimage.palette.palette = vec![[0, 0, 0, 0], [255, 0, 0, 128], [0, 255, 0, 128]];
+            wanimage
+                .animation_store
+                .anim_groups
+                .push(vec![Animation::default()]);
+            wanimage.write_to_vec().unwrap()
+        };
+        let first = encode();
+        for _ in 0..8 {
+            assert_eq!(encode(), first);
+        }
+    }
+
+    #[test]
+    fn test_create_wan_with_animations() {
+        let images = [image_with_shared_tile(0), image_with_shared_tile(1)];
+        let inputs: Vec<(&[u8], GeneralResolution)> = images
+            .iter()
+            .map(|image| (&image[..], GeneralResolution::new(16, 16)))
+            .collect();
+        let walk = MultiImagesAnimation {
+            frames: vec![(0, 4), (1, 6), (0, 2)],
+            return_point: Some(1),
+        };
+        let idle = MultiImagesAnimation {
+            frames: vec![(1, 10)],
+            return_point: None,
+        };
+        let wan = create_wan_from_multiple_images_with_animations(
+            &inputs,
+            SpriteType::PropsUI,
+            &[vec![walk; DIRECTION_COUNT], vec![idle]],
+        )
+        .unwrap();
+        assert_eq!(wan.frame_store.frames.len(), 2);
+        assert_eq!(wan.animation_store.anim_groups[0].len(), DIRECTION_COUNT);
+        let walk = &wan.animation_store.anim_groups[0][3];
+        assert_eq!(walk.total_duration(), 12);
+        assert_eq!(walk.frames[1].frame_id, 1);
+        assert!(walk.frames[1].is_return_point());
+        assert!(!walk.frames[0].is_return_point());
+        assert_eq!(wan.animation_store.anim_groups[1][0].frames[0].duration, 10);
+        wan.write_to_vec().unwrap();
+
+        for groups in [
+            vec![vec![MultiImagesAnimation::default(); 2]],
+            vec![vec![MultiImagesAnimation {
+                frames: vec![(2, 1)],
+                return_point: None,
+            }]],
+            vec![vec![MultiImagesAnimation {
+                frames: vec![(0, 0)],
+                return_point: None,
+            }]],
+            vec![vec![MultiImagesAnimation {
+                frames: vec![(0, 1)],
+                return_point: Some(1),
+            }]],
+        ] {
+            assert!(create_wan_from_multiple_images_with_animations(
+                &inputs,
+                SpriteType::PropsUI,
+                &groups
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let images = [image_with_shared_tile(0), image_with_shared_tile(1)];
+        let images: Vec<_> = images
+            .iter()
+            .map(|image| (image.as_slice(), GeneralResolution::new(16, 16)))
+            .collect();
+        let reported: Mutex<Vec<Progress>> = Mutex::new(Vec::new());
+        let callback = |progress| reported.lock().unwrap().push(progress);
+        let wanimage = create_wan_from_multiple_images_with_progress(
+            &images,
+            SpriteType::PropsUI,
+            &[],
+            &callback,
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        wanimage
+            .write_to_vec_with_progress(&CompressionMethod::NoCompression, &callback)
+            .unwrap();
+
+        let reported = reported.into_inner().unwrap();
+        let mut phases: Vec<ProgressPhase> =
+            reported.iter().map(|progress| progress.phase).collect();
+        phases.dedup();
+        assert_eq!(
+            phases,
+            vec![
+                ProgressPhase::FindingFragments,
+                ProgressPhase::IndexingTiles,
+                ProgressPhase::AssemblingFragments,
+                ProgressPhase::EncodingFragmentBytes
+            ]
+        );
+        for phase in phases {
+            let last = reported
+                .iter()
+                .rfind(|progress| progress.phase == phase)
+                .unwrap();
+            assert_eq!(last.done, last.total);
+        }
+        let encoding = reported.last().unwrap();
+        assert_eq!(encoding.total, wanimage.fragment_bytes_store.len());
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let images = [image_with_shared_tile(0), image_with_shared_tile(1)];
+        let images: Vec<_> = images
+            .iter()
+            .map(|image| (image.as_slice(), GeneralResolution::new(16, 16)))
+            .collect();
+        let cancellation = CancellationToken::new();
+        let callback = |progress: Progress| {
+            if progress.phase == ProgressPhase::IndexingTiles {
+                cancellation.cancel();
+            }
+        };
+        let error = create_wan_from_multiple_images_with_progress(
+            &images,
+            SpriteType::PropsUI,
+            &[],
+            &callback,
+            &cancellation,
+        )
+        .unwrap_err();
+        assert_eq!(error.downcast_ref::<Cancelled>(), Some(&Cancelled));
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::bail;
+use image::RgbaImage;
+
+use crate::{
+    image_to_wan::{insert_fragment_pos_in_wan_image, ImageBuffer},
+    image_tool::TransparencyOptions,
+    palette_from_image::rgba_to_palette_color,
+    Frame, WanImage, MAX_FRAGMENT_PALETTE_INDEX,
+};
+
+/// The size of the tiles [`WanImage::insert_frame_with_multiple_palettes`] assign a palette row to
+const TILE_SIZE: u32 = 8;
+
+/// Assign each tile (by index, `None` for the fully transparent ones) to a palette row, so the colors of each row fit in 15 opaque colors, using as few rows as possible.
+///
+/// The tiles with the most colors are placed first, each in the row it add the fewest new colors to.
+fn assign_rows(tiles: &[Vec<[u8; 3]>]) -> (Vec<Option<usize>>, Vec<Vec<[u8; 3]>>) {
+    let mut order: Vec<usize> = (0..tiles.len())
+        .filter(|tile| !tiles[*tile].is_empty())
+        .collect();
+    order.sort_by_key(|tile| std::cmp::Reverse(tiles[*tile].len()));
+    let mut rows: Vec<Vec<[u8; 3]>> = Vec::new();
+    let mut assignment = vec![None; tiles.len()];
+    for tile in order {
+        let colors = &tiles[tile];
+        let best = rows
+            .iter()
+            .enumerate()
+            .map(|(row_id, row)| {
+                let added = colors.iter().filter(|color| !row.contains(color)).count();
+                (row_id, row.len() + added, added)
+            })
+            .filter(|(_, total, _)| *total <= 15)
+            .min_by_key(|(_, _, added)| *added)
+            .map(|(row_id, _, _)| row_id);
+        let row_id = best.unwrap_or_else(|| {
+            rows.push(Vec::new());
+            rows.len() - 1
+        });
+        for color in colors {
+            if !rows[row_id].contains(color) {
+                rows[row_id].push(*color);
+            }
+        }
+        assignment[tile] = Some(row_id);
+    }
+    (assignment, rows)
+}
+
+impl WanImage {
+    /// Insert an RGBA image with as many colors as needed as a new frame, by giving each 8×8 tile of the image the palette row that contain its colors.
+    ///
+    /// The tiles are grouped in rows of at most 15 opaque colors, that are added after the existing rows of the [`crate::Palette`], and the tiles of each row are cut in [`crate::Fragment`]s like [`crate::insert_frame_in_wanimage`] does.
+    /// Fail if a tile has more than 15 colors, if there is not enough palette rows left, or for a 256 colors sprite. Return [`None`] if the image is fully transparent.
+    pub fn insert_frame_with_multiple_palettes(
+        &mut self,
+        image: &RgbaImage,
+        transparency: &TransparencyOptions,
+    ) -> anyhow::Result<Option<usize>> {
+        if self.is_256_color {
+            bail!("A 256 colors sprite only has a single palette, that can't be split in rows");
+        }
+        let (width, height) = image.dimensions();
+        if height >= 256 {
+            bail!("The height of the image is {}, while only image with a height inferior to 256 can be used", height);
+        }
+        if width >= 512 {
+            bail!(
+                "The width of the image is {}, while only image with a width less than 512 can be used",
+                width
+            );
+        }
+
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = height.div_ceil(TILE_SIZE);
+        let tile_of = |x: u32, y: u32| (y / TILE_SIZE * tiles_x + x / TILE_SIZE) as usize;
+        let mut tiles: Vec<Vec<[u8; 3]>> = vec![Vec::new(); (tiles_x * tiles_y) as usize];
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if transparency.is_transparent(*pixel) {
+                continue;
+            }
+            let color = [pixel.0[0], pixel.0[1], pixel.0[2]];
+            let tile = &mut tiles[tile_of(x, y)];
+            if !tile.contains(&color) {
+                tile.push(color);
+            }
+        }
+        for (tile_id, tile) in tiles.iter().enumerate() {
+            if tile.len() > 15 {
+                bail!(
+                    "The 8×8 tile at ({}, {}) has {} colors, but a palette row can only contain 15 of them",
+                    (tile_id as u32 % tiles_x) * TILE_SIZE,
+                    (tile_id as u32 / tiles_x) * TILE_SIZE,
+                    tile.len()
+                );
+            }
+        }
+
+        let (assignment, rows) = assign_rows(&tiles);
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        let available_rows =
+            (MAX_FRAGMENT_PALETTE_INDEX + 1).saturating_sub(self.palette_row_count());
+        if rows.len() > available_rows as usize {
+            bail!(
+                "The image need {} palette rows, but only {} are left",
+                rows.len(),
+                available_rows
+            );
+        }
+
+        let position_x = -(width as i32) / 2;
+        let position_y = -(height as i32) / 2;
+        let mut fragments = Vec::new();
+        for (row_id, row) in rows.iter().enumerate() {
+            let colors: Vec<[u8; 4]> = std::iter::once([0, 0, 0, 0])
+                .chain(
+                    row.iter()
+                        .map(|color| rgba_to_palette_color([color[0], color[1], color[2], 255])),
+                )
+                .collect();
+            let pal_id = self.add_palette_row(&colors)?;
+            let pixels = image
+                .enumerate_pixels()
+                .map(|(x, y, pixel)| {
+                    if assignment[tile_of(x, y)] != Some(row_id)
+                        || transparency.is_transparent(*pixel)
+                    {
+                        return 0;
+                    }
+                    let color = [pixel.0[0], pixel.0[1], pixel.0[2]];
+                    // no panic: every color of the tiles of the row is in the row
+                    row.iter()
+                        .position(|row_color| *row_color == color)
+                        .unwrap() as u8
+                        + 1
+                })
+                .collect();
+            // no panic: the buffer has the size of the image
+            let image_buffer =
+                ImageBuffer::new_from_vec(pixels, width as u16, height as u16).unwrap();
+            if let Some(row_fragments) = insert_fragment_pos_in_wan_image(
+                self,
+                pal_id,
+                &image_buffer,
+                position_x,
+                position_y,
+            )? {
+                fragments.extend(row_fragments);
+            }
+        }
+
+        let frame_id = self.frame_store.frames.len();
+        self.frame_store.frames.push(Frame {
+            fragments,
+            frame_offset: None,
+        });
+        Ok(Some(frame_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{image_tool::TransparencyOptions, ColorConversion, SpriteType, WanImage};
+
+    #[test]
+    fn test_insert_frame_with_multiple_palettes() {
+        // each 8×8 tile has 10 colors, different from the other tiles: 2 tiles can't share a row
+        let expand = |value| ColorConversion::default().from_5bit(value);
+        let image = RgbaImage::from_fn(16, 16, |x, y| {
+            let tile = (y / 8 * 2 + x / 8) as u8;
+            Rgba([
+                expand(tile * 5),
+                expand((x % 8 + y % 8 * 8) as u8 % 10),
+                0,
+                255,
+            ])
+        });
+        let mut wanimage = WanImage::new(SpriteType::PropsUI);
+        wanimage.palette.palette = vec![[0, 0, 0, 0]; 16];
+        let frame_id = wanimage
+            .insert_frame_with_multiple_palettes(&image, &TransparencyOptions::default())
+            .unwrap()
+            .unwrap();
+        // the existing row is kept, and one is added per tile
+        assert_eq!(wanimage.palette_row_count(), 5);
+        let rendered = wanimage.render_frame(frame_id).unwrap();
+        assert_eq!(rendered, image);
+        let frame = &wanimage.frame_store.frames[frame_id];
+        let mut rows: Vec<u16> = frame.fragments.iter().map(|f| f.pal_idx).collect();
+        rows.sort_unstable();
+        assert_eq!(rows, vec![1, 2, 3, 4]);
+
+        // tiles with few colors share a row
+        let image = RgbaImage::from_fn(16, 8, |x, y| {
+            Rgba([expand((x / 8) as u8), expand((y % 2) as u8), 0, 255])
+        });
+        let frame_id = wanimage
+            .insert_frame_with_multiple_palettes(&image, &TransparencyOptions::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(wanimage.palette_row_count(), 6);
+        assert_eq!(wanimage.render_frame(frame_id).unwrap(), image);
+
+        let too_many = RgbaImage::from_fn(8, 8, |x, y| Rgba([(x + y * 8) as u8, 0, 0, 255]));
+        assert!(wanimage
+            .insert_frame_with_multiple_palettes(&too_many, &TransparencyOptions::default())
+            .is_err());
+    }
+}
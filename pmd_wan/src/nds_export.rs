@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::{io, path::Path};
+
+use thiserror::Error;
+
+use crate::{FragmentBytesToImageError, OamShape, WanImage};
+
+/// The highest tile index an OAM entry can refer to, in 32 bytes units
+pub const MAX_OAM_TILE_INDEX: usize = 1023;
+
+#[derive(Error, Debug)]
+pub enum NdsExportError {
+    #[error("failed to decode a FragmentBytes")]
+    CantDecode(#[from] FragmentBytesToImageError),
+    #[error("The tiles take more than {} blocks of 32 bytes, and can't all be referred by OAM entries", MAX_OAM_TILE_INDEX + 1)]
+    TooManyTiles,
+}
+
+/// A [`WanImage`] converted to the raw formats used by GBA and NDS homebrew, as done by `grit` or `usenti`, with [`WanImage::export_nds`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NdsExport {
+    /// The tiles of every [`crate::FragmentBytes`], in 1D mapping: the 8×8 tiles of each sprite follow each other, line by line.
+    /// Each tile take 32 bytes in 16 colors mode (the left pixel of each pair in the low nibble), or 64 bytes in 256 colors mode.
+    pub tiles: Vec<u8>,
+    /// The colors of the [`crate::Palette`], as little endian BGR555 values (the alpha channel is dropped)
+    pub palette: Vec<u16>,
+    /// For each frame, the three OAM attributes of each [`crate::Fragment`], in order, as described in the OBJ Attributes section of GBATEK.
+    ///
+    /// The positions are relative to the origin of the frame, wrapped to 9 bits for x and 8 bits for y, so the position of the sprite on screen should be added to them.
+    /// The tile indices are in 32 bytes units in both color modes.
+    pub frames: Vec<Vec<[u16; 3]>>,
+    pub is_256_color: bool,
+}
+
+/// Convert a [`crate::Palette`] color to BGR555
+pub fn rgba_to_bgr555(color: [u8; 4]) -> u16 {
+    ((color[2] as u16 >> 3) << 10) | ((color[1] as u16 >> 3) << 5) | (color[0] as u16 >> 3)
+}
+
+impl NdsExport {
+    /// The tile data, to write as `<name>.img.bin`
+    pub fn tiles_bytes(&self) -> &[u8] {
+        &self.tiles
+    }
+
+    /// The palette, to write as `<name>.pal.bin`
+    pub fn palette_bytes(&self) -> Vec<u8> {
+        self.palette
+            .iter()
+            .flat_map(|color| color.to_le_bytes())
+            .collect()
+    }
+
+    /// The OAM entries of every frame one after the other, each taking 8 bytes like in the OAM memory (the last 2 bytes, used by the rotation parameters, are 0).
+    /// As the number of entries of each frame isn't stored, it can be found in [`NdsExport::frames`].
+    pub fn oam_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        for attributes in self.frames.iter().flatten() {
+            for attribute in attributes.iter().chain(&[0]) {
+                result.extend_from_slice(&attribute.to_le_bytes());
+            }
+        }
+        result
+    }
+
+    /// Write the `<name>.img.bin`, `<name>.pal.bin` and `<name>.oam.bin` files in the given folder, that should exist.
+    ///
+    /// Not available on `wasm32-unknown-unknown`, that doesn't have a file system.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn write_to_folder(&self, folder: &Path, name: &str) -> io::Result<()> {
+        std::fs::write(folder.join(format!("{}.img.bin", name)), self.tiles_bytes())?;
+        std::fs::write(
+            folder.join(format!("{}.pal.bin", name)),
+            self.palette_bytes(),
+        )?;
+        std::fs::write(folder.join(format!("{}.oam.bin", name)), self.oam_bytes())?;
+        Ok(())
+    }
+}
+
+impl WanImage {
+    /// Convert this image to the tiles, palette and OAM entries of an [`NdsExport`].
+    ///
+    /// Each [`crate::FragmentBytes`] is stored once for every shape it is used with, without flip, as flips are done by the OAM attributes.
+    /// The [`crate::FragmentBytes`] not used by any [`crate::Fragment`] are skipped, as their shape is unknown.
+    pub fn export_nds(&self) -> Result<NdsExport, NdsExportError> {
+        let mut tiles: Vec<u8> = Vec::new();
+        let mut tile_indices: HashMap<(usize, OamShape), usize> = HashMap::new();
+        let mut frames = Vec::with_capacity(self.frame_store.frames.len());
+        for frame in &self.frame_store.frames {
+            let mut entries = Vec::with_capacity(frame.fragments.len());
+            for fragment in &frame.fragments {
+                let key = (fragment.fragment_bytes_index, fragment.resolution);
+                let tile_index = match tile_indices.get(&key) {
+                    Some(tile_index) => *tile_index,
+                    None => {
+                        let tile_index = tiles.len() / 32;
+                        let resolution = fragment.resolution.size();
+                        let pixels = self
+                            .fragment_bytes_store
+                            .fragment_bytes
+                            .get(fragment.fragment_bytes_index)
+                            .ok_or(FragmentBytesToImageError::NoFragmentBytes(
+                                fragment.fragment_bytes_index,
+                            ))?
+                            .decode_pixels(resolution.clone(), self.is_256_color)
+                            .map_err(FragmentBytesToImageError::from)?;
+                        let width = resolution.x as usize;
+                        for tile_y in 0..resolution.y as usize / 8 {
+                            for tile_x in 0..width / 8 {
+                                for line in 0..8 {
+                                    let start = (tile_y * 8 + line) * width + tile_x * 8;
+                                    let line_pixels = &pixels[start..start + 8];
+                                    if self.is_256_color {
+                                        tiles.extend_from_slice(line_pixels);
+                                    } else {
+                                        tiles.extend(
+                                            line_pixels
+                                                .chunks_exact(2)
+                                                .map(|pair| (pair[0] & 0xF) | (pair[1] << 4)),
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                        tile_indices.insert(key, tile_index);
+                        tile_index
+                    }
+                };
+                if tile_index > MAX_OAM_TILE_INDEX {
+                    return Err(NdsExportError::TooManyTiles);
+                }
+                let attribute_0 = (fragment.offset_y as u16 & 0xFF)
+                    | (u16::from(fragment.is_mosaic) << 12)
+                    | (u16::from(self.is_256_color) << 13)
+                    | ((fragment.resolution.shape_indice() as u16) << 14);
+                let attribute_1 = (fragment.offset_x as u16 & 0x1FF)
+                    | (u16::from(fragment.flip.flip_h) << 12)
+                    | (u16::from(fragment.flip.flip_v) << 13)
+                    | ((fragment.resolution.size_indice() as u16) << 14);
+                let attribute_2 = tile_index as u16
+                    | ((fragment.priority as u16 & 0x3) << 10)
+                    | (if self.is_256_color {
+                        0
+                    } else {
+                        (fragment.pal_idx & 0xF) << 12
+                    });
+                entries.push([attribute_0, attribute_1, attribute_2]);
+            }
+            frames.push(entries);
+        }
+        Ok(NdsExport {
+            tiles,
+            palette: self
+                .palette
+                .palette
+                .iter()
+                .copied()
+                .map(rgba_to_bgr555)
+                .collect(),
+            frames,
+            is_256_color: self.is_256_color,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage, rgba_to_bgr555, tests::fixtures::test_wan_image, SpriteType,
+    };
+
+    #[test]
+    fn test_export_nds() {
+        assert_eq!(rgba_to_bgr555([255, 0, 0, 128]), 0x001F);
+        assert_eq!(rgba_to_bgr555([0, 0, 255, 128]), 0x7C00);
+
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.push([0, 255, 0, 128]);
+        let mut pixels = vec![1; 16 * 8];
+        pixels[0] = 2;
+        insert_frame_in_wanimage(pixels, 16, 8, &mut wanimage, 0).unwrap();
+        let mut flipped = wanimage.frame_store.frames[0].clone();
+        flipped.fragments[0].flip.flip_h = true;
+        wanimage.frame_store.frames.push(flipped);
+
+        let export = wanimage.export_nds().unwrap();
+        // a 16×8 fragment is two tiles, shared by both frames
+        assert_eq!(export.tiles.len(), 64);
+        assert_eq!(export.tiles[0], 0x12);
+        assert_eq!(export.tiles[1], 0x11);
+        assert_eq!(export.palette_bytes(), vec![0, 0, 0x1F, 0, 0xE0, 0x03]);
+        let fragment = &wanimage.frame_store.frames[0].fragments[0];
+        let [attribute_0, attribute_1, attribute_2] = export.frames[0][0];
+        assert_eq!(attribute_0 & 0xFF, fragment.offset_y as u16 & 0xFF);
+        // wide shape, smallest size
+        assert_eq!(attribute_0 >> 14, 1);
+        assert_eq!(attribute_1 >> 14, 0);
+        assert_eq!(attribute_1 & 0x1FF, fragment.offset_x as u16 & 0x1FF);
+        assert_eq!(attribute_2 & 0x3FF, 0);
+        assert_eq!(export.frames[1][0][1] & (1 << 12), 1 << 12);
+        assert_eq!(export.frames[1][0][2], attribute_2);
+        assert_eq!(export.oam_bytes().len(), 2 * 8);
+    }
+}
@@ -17,24 +17,67 @@ static INDICE_TO_SIZE_MAP: [Option<(u8, u8)>; 16] = [
     None,
     None,
     None,
-    None
+    None,
 ];
 
 /// One of the possible shape usable by the DS’s OAM
 /// See LCD OBJ - OAM Attributes of GBATEK.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "OamShapeIndices"))]
 pub struct OamShape {
     // Make sure both of them are valid when setting them.
     shape_indice: u8,
-    size_indice: u8
+    size_indice: u8,
+}
+
+/// The unchecked serialized form of an [`OamShape`]
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct OamShapeIndices {
+    shape_indice: u8,
+    size_indice: u8,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<OamShapeIndices> for OamShape {
+    type Error = &'static str;
+
+    fn try_from(indices: OamShapeIndices) -> Result<Self, Self::Error> {
+        OamShape::new(indices.shape_indice, indices.size_indice)
+            .ok_or("the shape indice should be at most 2, and the size indice at most 3")
+    }
 }
 
 impl OamShape {
+    /// Every legal shape, in the order of their indices (square, then wide, then tall, from the smallest to the biggest)
+    pub const ALL: [OamShape; 12] = [
+        OamShape::new_unchecked(0, 0),
+        OamShape::new_unchecked(0, 1),
+        OamShape::new_unchecked(0, 2),
+        OamShape::new_unchecked(0, 3),
+        OamShape::new_unchecked(1, 0),
+        OamShape::new_unchecked(1, 1),
+        OamShape::new_unchecked(1, 2),
+        OamShape::new_unchecked(1, 3),
+        OamShape::new_unchecked(2, 0),
+        OamShape::new_unchecked(2, 1),
+        OamShape::new_unchecked(2, 2),
+        OamShape::new_unchecked(2, 3),
+    ];
+
+    const fn new_unchecked(shape_indice: u8, size_indice: u8) -> Self {
+        Self {
+            shape_indice,
+            size_indice,
+        }
+    }
+
     pub fn new(shape_indice: u8, size_indice: u8) -> Option<Self> {
         if shape_indice <= 2 && size_indice <= 3 {
             Some(Self {
                 shape_indice,
-                size_indice
+                size_indice,
             })
         } else {
             None
@@ -49,6 +92,14 @@ impl OamShape {
         self.size_indice
     }
 
+    /// Return the shape with exactly the given width and height, or [`None`] if the DS can't display a sprite of that size
+    pub fn from_size(width: u32, height: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|shape| {
+            let size = shape.size();
+            size.x == width && size.y == height
+        })
+    }
+
     pub fn size(&self) -> GeneralResolution {
         let indice = (self.shape_indice << 2) + self.size_indice;
         let size_raw = INDICE_TO_SIZE_MAP[indice as usize].unwrap(); // No unwrap: it is always checked that the values are inside the valid range.
@@ -64,9 +115,7 @@ impl OamShape {
     /// Return the smallest resolution (in term of allocation) that can contain the target resolution.
     ///
     /// If there are multiple posible resolution with the same number of size to allocate, returnt the one with the lesser amount of pixel. If there are still multiple remaining resolution, return any possible one (implementation detail: they aren't random).
-    pub fn find_smallest_containing(
-        target_resolution: GeneralResolution,
-    ) -> Option<OamShape> {
+    pub fn find_smallest_containing(target_resolution: GeneralResolution) -> Option<OamShape> {
         let mut optimal_result: Option<(u16, u16, OamShape)> = None; // first u16 is number of chunk to allocate for the frame, second u16 is the number of pixel, third is the optimal resolution right now
         for (indice_compressed, entry) in INDICE_TO_SIZE_MAP.iter().copied().enumerate() {
             if let Some(entry) = entry {
@@ -74,11 +123,12 @@ impl OamShape {
                 if resolution_entry.can_contain(target_resolution.clone()) {
                     let entry_oam = OamShape {
                         shape_indice: (indice_compressed as u8) >> 2,
-                        size_indice: (indice_compressed as u8) & 0b11
+                        size_indice: (indice_compressed as u8) & 0b11,
                     };
                     let chunk_to_allocate_entry = entry_oam.chunk_to_allocate_for_fragment();
                     let pixel_nb_entry = (resolution_entry.x as u16) * (resolution_entry.y as u16);
-                    if let Some((chunk_to_allocate_optimal, pixel_nb_optimal, _)) = &optimal_result {
+                    if let Some((chunk_to_allocate_optimal, pixel_nb_optimal, _)) = &optimal_result
+                    {
                         if *chunk_to_allocate_optimal > chunk_to_allocate_entry
                             || (*chunk_to_allocate_optimal == chunk_to_allocate_entry
                                 && *pixel_nb_optimal > pixel_nb_entry)
@@ -87,8 +137,7 @@ impl OamShape {
                                 Some((chunk_to_allocate_entry, pixel_nb_entry, entry_oam));
                         }
                     } else {
-                        optimal_result =
-                            Some((chunk_to_allocate_entry, pixel_nb_entry, entry_oam));
+                        optimal_result = Some((chunk_to_allocate_entry, pixel_nb_entry, entry_oam));
                     };
                 }
             }
@@ -103,7 +152,8 @@ mod tests {
     use crate::{GeneralResolution, OamShape};
     #[test]
     fn test_resolution_chunk_allocation() {
-        for ((shape, size), expected_output) in [((0, 2), 4), ((1, 1), 2), ((0, 3), 16)].into_iter()
+        for ((shape, size), expected_output) in
+            [((0, 2), 4), ((1, 1), 2), ((0, 3), 16)].iter().copied()
         {
             let resolution = OamShape::new(shape, size).unwrap();
             let got = resolution.chunk_to_allocate_for_fragment();
@@ -123,11 +173,38 @@ mod tests {
         assert_eq!(OamShape::new(12, 255), None);
     }
 
+    #[test]
+    pub fn test_all_shapes() {
+        for (shape_indice, size_indice) in
+            (0..3).flat_map(|shape| (0..4).map(move |size| (shape, size)))
+        {
+            let shape = OamShape::new(shape_indice, size_indice).unwrap();
+            assert!(OamShape::ALL.contains(&shape));
+            let size = shape.size();
+            assert_eq!(OamShape::from_size(size.x, size.y), Some(shape));
+        }
+        assert_eq!(
+            OamShape::from_size(8, 32),
+            Some(OamShape::new(2, 1).unwrap())
+        );
+        assert_eq!(OamShape::from_size(8, 64), None);
+        assert_eq!(OamShape::from_size(24, 24), None);
+    }
+
     #[test]
     pub fn test_size() {
-        assert_eq!(OamShape::new(0, 3).unwrap().size(), GeneralResolution::new(64, 64));
-        assert_eq!(OamShape::new(1, 2).unwrap().size(), GeneralResolution::new(32, 16));
-        assert_eq!(OamShape::new(2, 3).unwrap().size(), GeneralResolution::new(32, 64));
+        assert_eq!(
+            OamShape::new(0, 3).unwrap().size(),
+            GeneralResolution::new(64, 64)
+        );
+        assert_eq!(
+            OamShape::new(1, 2).unwrap().size(),
+            GeneralResolution::new(32, 16)
+        );
+        assert_eq!(
+            OamShape::new(2, 3).unwrap().size(),
+            GeneralResolution::new(32, 64)
+        );
     }
 
     #[test]
@@ -0,0 +1,225 @@
+use image::{Rgba, RgbaImage};
+use thiserror::Error;
+
+use crate::{
+    FrameOffset, FrameOffsetPoint, WanImage, SPRITEBOT_CENTER_COLOR, SPRITEBOT_HAND_LEFT_COLOR,
+    SPRITEBOT_HAND_RIGHT_COLOR, SPRITEBOT_HEAD_COLOR,
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum OffsetsSheetError {
+    #[error("The cells of the offsets sheet can't be {0}×{1} pixels large")]
+    InvalidCellSize(u32, u32),
+    #[error("The offsets sheet has {found} cells, but there are {expected} frames")]
+    NotEnoughCells { expected: usize, found: usize },
+}
+
+impl FrameOffsetPoint {
+    /// The color used to mark this point in a SpriteBot offsets sheet
+    pub fn spritebot_color(self) -> Rgba<u8> {
+        match self {
+            FrameOffsetPoint::Head => SPRITEBOT_HEAD_COLOR,
+            FrameOffsetPoint::HandLeft => SPRITEBOT_HAND_LEFT_COLOR,
+            FrameOffsetPoint::HandRight => SPRITEBOT_HAND_RIGHT_COLOR,
+            FrameOffsetPoint::Center => SPRITEBOT_CENTER_COLOR,
+        }
+    }
+}
+
+pub(crate) fn put_marker(image: &mut RgbaImage, x: i32, y: i32, color: Rgba<u8>) {
+    if x >= 0 && y >= 0 && (x as u32) < image.width() && (y as u32) < image.height() {
+        image.put_pixel(x as u32, y as u32, color);
+    }
+}
+
+impl FrameOffset {
+    /// Draw a pixel of the SpriteBot color of each point (see [`FrameOffsetPoint::spritebot_color`]), with the origin of the frame at `origin_x` and `origin_y`.
+    /// Points outside of the image are ignored.
+    pub fn draw_markers(&self, image: &mut RgbaImage, origin_x: i32, origin_y: i32) {
+        for (point, (x, y)) in self.points() {
+            put_marker(
+                image,
+                origin_x + x as i32,
+                origin_y + y as i32,
+                point.spritebot_color(),
+            );
+        }
+    }
+
+    /// Find the points marked by [`FrameOffset::draw_markers`] in the given area of the image, with the origin of the frame in the center of the area.
+    ///
+    /// Return [`None`] if the area contains no marker at all. Otherwise, a missing point is placed at the origin. If a color appears multiple times, the first one from the top-left is used.
+    pub fn read_markers(
+        image: &RgbaImage,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Option<FrameOffset> {
+        let half_width = (width / 2) as i16;
+        let half_height = (height / 2) as i16;
+        let mut result = FrameOffset::default();
+        let mut found_any = false;
+        for point in FrameOffsetPoint::ALL.iter().copied() {
+            let color = point.spritebot_color();
+            let found = (0..height.min(image.height().saturating_sub(y)))
+                .flat_map(|cell_y| {
+                    (0..width.min(image.width().saturating_sub(x)))
+                        .map(move |cell_x| (cell_x, cell_y))
+                })
+                .find(|(cell_x, cell_y)| *image.get_pixel(x + cell_x, y + cell_y) == color);
+            if let Some((cell_x, cell_y)) = found {
+                result.set(
+                    point,
+                    (cell_x as i16 - half_width, cell_y as i16 - half_height),
+                );
+                found_any = true;
+            }
+        }
+        if found_any {
+            Some(result)
+        } else {
+            None
+        }
+    }
+}
+
+impl WanImage {
+    /// Export the [`FrameOffset`] of every frame as a SpriteBot-style offsets sheet.
+    ///
+    /// Each frame get a `cell_width`×`cell_height` cell, from left to right then top to bottom with `columns` cells per row, with the origin of the frame in the center of the cell.
+    /// Frames without [`FrameOffset`] leave their cell empty.
+    pub fn export_offsets_sheet(
+        &self,
+        cell_width: u32,
+        cell_height: u32,
+        columns: u32,
+    ) -> Result<RgbaImage, OffsetsSheetError> {
+        if cell_width == 0 || cell_height == 0 || columns == 0 {
+            return Err(OffsetsSheetError::InvalidCellSize(cell_width, cell_height));
+        }
+        let rows = (self.frame_store.frames.len() as u32).div_ceil(columns);
+        let mut sheet = RgbaImage::new(cell_width * columns, cell_height * rows);
+        for (frame_id, frame) in self.frame_store.frames.iter().enumerate() {
+            if let Some(frame_offset) = &frame.frame_offset {
+                let column = frame_id as u32 % columns;
+                let row = frame_id as u32 / columns;
+                frame_offset.draw_markers(
+                    &mut sheet,
+                    (column * cell_width + cell_width / 2) as i32,
+                    (row * cell_height + cell_height / 2) as i32,
+                );
+            }
+        }
+        Ok(sheet)
+    }
+
+    /// Import an offsets sheet in the layout of [`WanImage::export_offsets_sheet`], replacing the [`FrameOffset`] of every frame (see [`FrameOffset::read_markers`]).
+    ///
+    /// The number of columns is deduced from the width of the sheet. The [`WanImage`] is left untouched on error.
+    pub fn import_offsets_sheet(
+        &mut self,
+        sheet: &RgbaImage,
+        cell_width: u32,
+        cell_height: u32,
+    ) -> Result<(), OffsetsSheetError> {
+        if cell_width == 0
+            || cell_height == 0
+            || !sheet.width().is_multiple_of(cell_width)
+            || !sheet.height().is_multiple_of(cell_height)
+        {
+            return Err(OffsetsSheetError::InvalidCellSize(cell_width, cell_height));
+        }
+        let columns = sheet.width() / cell_width;
+        let cell_amount = (columns * (sheet.height() / cell_height)) as usize;
+        if cell_amount < self.frame_store.frames.len() {
+            return Err(OffsetsSheetError::NotEnoughCells {
+                expected: self.frame_store.frames.len(),
+                found: cell_amount,
+            });
+        }
+        for (frame_id, frame) in self.frame_store.frames.iter_mut().enumerate() {
+            let column = frame_id as u32 % columns;
+            let row = frame_id as u32 / columns;
+            frame.frame_offset = FrameOffset::read_markers(
+                sheet,
+                column * cell_width,
+                row * cell_height,
+                cell_width,
+                cell_height,
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbaImage;
+
+    use crate::{
+        Frame, FrameOffset, FrameOffsetPoint, OffsetsSheetError, SpriteType, WanImage,
+        SPRITEBOT_HAND_LEFT_COLOR,
+    };
+
+    #[test]
+    fn test_frame_anchor_accessors() {
+        let mut wanimage = WanImage::new(SpriteType::Chara);
+        wanimage.frame_store.frames.push(Frame::default());
+        assert_eq!(wanimage.frame_anchor(0, FrameOffsetPoint::Head), None);
+        assert!(wanimage.set_frame_anchor(0, FrameOffsetPoint::Head, (1, -12)));
+        assert!(!wanimage.set_frame_anchor(1, FrameOffsetPoint::Head, (1, -12)));
+        assert_eq!(
+            wanimage.frame_anchor(0, FrameOffsetPoint::Head),
+            Some((1, -12))
+        );
+        assert_eq!(
+            wanimage.frame_anchor(0, FrameOffsetPoint::Center),
+            Some((0, 0))
+        );
+
+        let frame_offset = wanimage.frame_store.frames[0].frame_offset.clone().unwrap();
+        assert_eq!(frame_offset.translated(2, 2).unwrap().head, (3, -10));
+        assert_eq!(frame_offset.translated(i16::MAX, 0), None);
+    }
+
+    #[test]
+    fn test_offsets_sheet_roundtrip() {
+        let mut wanimage = WanImage::new(SpriteType::Chara);
+        let frame_offset = FrameOffset {
+            head: (0, -10),
+            hand_left: (-6, 2),
+            hand_right: (6, 3),
+            center: (0, 1),
+        };
+        for frame_offset in [Some(frame_offset.clone()), None, Some(frame_offset)] {
+            wanimage.frame_store.frames.push(Frame {
+                fragments: Vec::new(),
+                frame_offset,
+            });
+        }
+        let sheet = wanimage.export_offsets_sheet(32, 32, 2).unwrap();
+        assert_eq!(sheet.dimensions(), (64, 64));
+        assert_eq!(sheet.get_pixel(16 - 6, 16 + 2), &SPRITEBOT_HAND_LEFT_COLOR);
+
+        let original = wanimage.frame_store.frames.clone();
+        let mut imported = wanimage;
+        for frame in &mut imported.frame_store.frames {
+            frame.frame_offset = None;
+        }
+        imported.import_offsets_sheet(&sheet, 32, 32).unwrap();
+        assert_eq!(imported.frame_store.frames, original);
+
+        assert_eq!(
+            imported.import_offsets_sheet(&RgbaImage::new(64, 32), 32, 32),
+            Err(OffsetsSheetError::NotEnoughCells {
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            imported.import_offsets_sheet(&sheet, 30, 32),
+            Err(OffsetsSheetError::InvalidCellSize(30, 32))
+        );
+    }
+}
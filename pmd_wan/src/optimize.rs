@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{
+    flip_duplicates::FlipDuplicates, CancellationToken, Cancelled, CompressionMethod, FragmentFlip,
+    FrameRenderError, GcReport, OamShape, WanImage,
+};
+
+#[derive(Error, Debug)]
+pub enum OptimizeError {
+    #[error("Failed to encode the wan image")]
+    CantEncode(#[source] anyhow::Error),
+    #[error("Failed to render a frame")]
+    CantRenderFrame(#[from] FrameRenderError),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
+}
+
+/// What [`WanImage::optimize`] should do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimizeOptions {
+    /// Merge the [`crate::FragmentBytes`] that are exactly identical
+    pub deduplicate_fragment_bytes: bool,
+    /// Merge the [`crate::FragmentBytes`] that are a flipped version of another one, flipping the [`crate::Fragment`]s that use them instead
+    pub deduplicate_flipped_fragment_bytes: bool,
+    /// Merge the frames that are exactly identical (see [`WanImage::merge_identical_frames`])
+    pub merge_identical_frames: bool,
+    /// Merge the frames that are displayed identically (see [`WanImage::merge_identically_rendered_frames`])
+    pub merge_identically_rendered_frames: bool,
+    /// Remove the frames and [`crate::FragmentBytes`] that aren't used (see [`WanImage::gc_unused`])
+    pub remove_unused: bool,
+    /// The [`CompressionMethod`] to use from now on, if any. The default is [`CompressionMethod::smallest`].
+    pub compression: Option<CompressionMethod>,
+    /// Checked before each step. Once it is cancelled, [`WanImage::optimize`] return [`OptimizeError::Cancelled`], keeping the changes of the steps already done.
+    pub cancellation: CancellationToken,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            deduplicate_fragment_bytes: true,
+            deduplicate_flipped_fragment_bytes: true,
+            merge_identical_frames: true,
+            merge_identically_rendered_frames: true,
+            remove_unused: true,
+            compression: Some(CompressionMethod::smallest()),
+            cancellation: CancellationToken::new(),
+        }
+    }
+}
+
+/// The output of [`WanImage::optimize`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OptimizeReport {
+    /// The size of the encoded file before the optimisation
+    pub size_before: usize,
+    /// The size of the encoded file after the optimisation
+    pub size_after: usize,
+    /// The number of [`crate::FragmentBytes`] merged with an identical one
+    pub merged_fragment_bytes: usize,
+    /// The number of [`crate::FragmentBytes`] merged with a flipped version of them
+    pub merged_flipped_fragment_bytes: usize,
+    /// The number of frames merged with an identical one
+    pub merged_frames: usize,
+    /// The number of frames merged with one that is displayed identically
+    pub merged_rendered_frames: usize,
+    /// What has been removed as unused, with [`OptimizeOptions::remove_unused`]
+    pub gc: GcReport,
+}
+
+impl OptimizeReport {
+    /// The number of bytes saved. Negative if the file is bigger.
+    pub fn saved_bytes(&self) -> isize {
+        self.size_before as isize - self.size_after as isize
+    }
+}
+
+impl WanImage {
+    /// Make the encoded file as small as possible without changing how the sprite is displayed, and report the size before and after.
+    ///
+    /// Merged [`crate::FragmentBytes`] are removed, as well as the ones that were already unused. If the options change the [`WanImage::compression`], it is kept for the following writes.
+    pub fn optimize(&mut self, options: &OptimizeOptions) -> Result<OptimizeReport, OptimizeError> {
+        let mut report = OptimizeReport {
+            size_before: self.encoded_len()?,
+            ..Default::default()
+        };
+        let cancellation = &options.cancellation;
+        if options.deduplicate_fragment_bytes || options.deduplicate_flipped_fragment_bytes {
+            cancellation.check()?;
+            let (merged, merged_flipped) =
+                self.merge_fragment_bytes(options.deduplicate_flipped_fragment_bytes);
+            report.merged_fragment_bytes = merged;
+            report.merged_flipped_fragment_bytes = merged_flipped;
+            self.gc_unused_fragment_bytes();
+        }
+        if options.merge_identical_frames {
+            cancellation.check()?;
+            report.merged_frames = self.merge_identical_frames();
+        }
+        if options.merge_identically_rendered_frames {
+            cancellation.check()?;
+            report.merged_rendered_frames = self.merge_identically_rendered_frames()?;
+        }
+        if options.remove_unused {
+            cancellation.check()?;
+            report.gc = self.gc_unused();
+        }
+        if let Some(compression) = &options.compression {
+            self.compression = compression.clone();
+        }
+        cancellation.check()?;
+        report.size_after = self.encoded_len()?;
+        Ok(report)
+    }
+
+    /// Merge the frames that are exactly identical (same [`crate::Fragment`]s and [`crate::FrameOffset`]) with the first of them, updating the [`crate::AnimationFrame`]s that reference them.
+    /// Return the number of removed frames.
+    pub fn merge_identical_frames(&mut self) -> usize {
+        let mut remap = Vec::with_capacity(self.frame_store.frames.len());
+        let mut kept = Vec::new();
+        for (frame_id, frame) in self.frame_store.frames.iter().enumerate() {
+            match kept
+                .iter()
+                .position(|kept_id: &usize| self.frame_store.frames[*kept_id] == *frame)
+            {
+                Some(new_id) => remap.push(new_id),
+                None => {
+                    remap.push(kept.len());
+                    kept.push(frame_id);
+                }
+            }
+        }
+        let removed = self.frame_store.frames.len() - kept.len();
+        if removed == 0 {
+            return 0;
+        }
+        self.remap_frames(&kept, &remap);
+        removed
+    }
+
+    /// Keep only the frames at the indices of `kept` (in order), and update the [`crate::AnimationFrame`]s so the old frame `n` become `remap[n]`
+    pub(crate) fn remap_frames(&mut self, kept: &[usize], remap: &[usize]) {
+        let mut frames: Vec<_> = std::mem::take(&mut self.frame_store.frames)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.frame_store.frames = kept
+            .iter()
+            .map(|frame_id| frames[*frame_id].take().unwrap_or_default())
+            .collect();
+        for animation_frame in self
+            .animation_store
+            .anim_groups
+            .iter_mut()
+            .flatten()
+            .flat_map(|animation| animation.frames.iter_mut())
+        {
+            if let Some(new_id) = remap.get(animation_frame.frame_id as usize) {
+                animation_frame.frame_id = *new_id as u16;
+            }
+        }
+    }
+
+    /// Make the [`crate::Fragment`]s reference the first of the identical (or flipped if `with_flip` is set) [`crate::FragmentBytes`]. The merged ones are left unused.
+    /// Return the number of exactly identical and of flipped [`crate::FragmentBytes`] merged.
+    fn merge_fragment_bytes(&mut self, with_flip: bool) -> (usize, usize) {
+        // The shape of every FragmentBytes, if they are always used with the same one
+        let mut shapes: HashMap<usize, Option<OamShape>> = HashMap::new();
+        for fragment in self
+            .frame_store
+            .frames
+            .iter()
+            .flat_map(|frame| frame.fragments.iter())
+        {
+            let shape = shapes
+                .entry(fragment.fragment_bytes_index)
+                .or_insert(Some(fragment.resolution));
+            if *shape != Some(fragment.resolution) {
+                *shape = None;
+            }
+        }
+
+        // For every FragmentBytes, the one to use instead, with the flip to apply
+        let mut replacements: Vec<(usize, FragmentFlip)> = Vec::new();
+        let mut merged = (0, 0);
+        let mut exact_seen: HashMap<(&[u8], u32), usize> = HashMap::new();
+        let mut flip_duplicates = FlipDuplicates::new();
+        for (index, fragment_bytes) in self.fragment_bytes_store.fragment_bytes.iter().enumerate() {
+            let key = (
+                fragment_bytes.mixed_pixels.as_slice(),
+                fragment_bytes.z_index,
+            );
+            if let Some(original) = exact_seen.get(&key) {
+                replacements.push((*original, FragmentFlip::standard()));
+                merged.0 += 1;
+                continue;
+            }
+            exact_seen.insert(key, index);
+            replacements.push((index, FragmentFlip::standard()));
+
+            let shape = match shapes.get(&index) {
+                Some(Some(shape)) if with_flip => *shape,
+                _ => continue,
+            };
+            let pixels = match fragment_bytes.decode_pixels(shape.size(), self.is_256_color) {
+                Ok(pixels) => pixels,
+                Err(_) => continue,
+            };
+            let key = (shape, fragment_bytes.z_index);
+            match flip_duplicates.find(&key, &pixels, shape.size()) {
+                Some(replacement) => {
+                    replacements[index] = replacement;
+                    merged.1 += 1;
+                }
+                None => flip_duplicates.insert(key, pixels, index),
+            }
+        }
+
+        for fragment in self
+            .frame_store
+            .frames
+            .iter_mut()
+            .flat_map(|frame| frame.fragments.iter_mut())
+        {
+            if let Some((new_index, flip)) = replacements.get(fragment.fragment_bytes_index) {
+                fragment.fragment_bytes_index = *new_index;
+                fragment.flip = FragmentFlip {
+                    flip_h: fragment.flip.flip_h ^ flip.flip_h,
+                    flip_v: fragment.flip.flip_v ^ flip.flip_v,
+                };
+            }
+        }
+        merged
+    }
+
+    fn encoded_len(&self) -> Result<usize, OptimizeError> {
+        self.write_to_vec()
+            .map(|bytes| bytes.len())
+            .map_err(OptimizeError::CantEncode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        encode_fragment_pixels, tests::fixtures::animation_frame, tests::fixtures::test_wan_image,
+        Animation, Fragment, FragmentBytes, FragmentFlip, Frame, GeneralResolution, OamShape,
+        OptimizeOptions, SpriteType, DEFAULT_FRAGMENT_PRIORITY,
+    };
+
+    #[test]
+    fn test_optimize() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.push([0, 0, 255, 128]);
+        let mut pixels = [0; 64];
+        pixels[0] = 1;
+        pixels[1] = 2;
+        let mut mirrored = [0; 64];
+        mirrored[7] = 1;
+        mirrored[6] = 2;
+        for pixels in [pixels, mirrored, pixels] {
+            wanimage
+                .fragment_bytes_store
+                .fragment_bytes
+                .push(FragmentBytes {
+                    mixed_pixels: encode_fragment_pixels(&pixels, GeneralResolution::new(8, 8))
+                        .unwrap(),
+                    z_index: 0,
+                });
+        }
+        let fragment = |fragment_bytes_index, flip_v| Fragment {
+            unk1: 0,
+            unk3_4: None,
+            unk5: false,
+            fragment_bytes_index,
+            offset_y: -4,
+            offset_x: -4,
+            flip: FragmentFlip {
+                flip_h: false,
+                flip_v,
+            },
+            is_mosaic: false,
+            priority: DEFAULT_FRAGMENT_PRIORITY,
+            pal_idx: 0,
+            resolution: OamShape::new(0, 0).unwrap(),
+        };
+        for fragment in [fragment(0, false), fragment(1, true), fragment(2, false)] {
+            wanimage.frame_store.frames.push(Frame {
+                fragments: vec![fragment],
+                frame_offset: None,
+            });
+        }
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: (0..3)
+                .map(|frame_id| animation_frame(frame_id, 1))
+                .collect(),
+        }]);
+        let rendered: Vec<_> = (0..3)
+            .map(|frame_id| wanimage.render_frame(frame_id).unwrap())
+            .collect();
+
+        let report = wanimage.optimize(&OptimizeOptions::default()).unwrap();
+        assert_eq!(report.merged_fragment_bytes, 1);
+        assert_eq!(report.merged_flipped_fragment_bytes, 1);
+        assert_eq!(report.merged_frames, 1);
+        assert!(report.saved_bytes() > 0);
+        assert_eq!(wanimage.fragment_bytes_store.len(), 1);
+        assert_eq!(wanimage.frame_store.frames.len(), 2);
+        let frame_ids: Vec<u16> = wanimage.animation_store.anim_groups[0][0]
+            .frames
+            .iter()
+            .map(|animation_frame| animation_frame.frame_id)
+            .collect();
+        assert_eq!(frame_ids, vec![0, 1, 0]);
+        for (image, frame_id) in rendered.iter().zip(frame_ids) {
+            assert_eq!(&wanimage.render_frame(frame_id as usize).unwrap(), image);
+        }
+        assert_eq!(report.size_after, wanimage.write_to_vec().unwrap().len());
+    }
+}
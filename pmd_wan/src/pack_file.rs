@@ -0,0 +1,268 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use thiserror::Error;
+
+use crate::{compress_px_container, decompress_px_container, PxContainerKind, WanError, WanImage};
+
+const DEFAULT_PACK_ALIGNMENT: u32 = 16;
+const DEFAULT_PACK_PADDING_BYTE: u8 = 0xFF;
+const MAX_DETECTED_ALIGNMENT_SHIFT: u32 = 12;
+
+#[derive(Error, Debug)]
+pub enum PackFileError {
+    #[error("an input/output error happened")]
+    IOError(#[from] io::Error),
+    #[error("a pack file should start with four 0, found {0:?}")]
+    InvalidHeader([u8; 4]),
+    #[error("the entry {0} (at {1}, of length {2}) is outside of the pack file")]
+    EntryOutOfFile(usize, u32, u32),
+    #[error("there is no entry {0} in this pack file")]
+    NoEntry(usize),
+    #[error("the pack file is too big to be written ({0} bytes)")]
+    TooBig(u64),
+    #[error("the entry can't be decoded as a wan file")]
+    WanError(#[from] WanError),
+}
+
+/// The position of an entry in a pack file
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PackFileEntry {
+    pub offset: u32,
+    pub length: u32,
+}
+
+/// A pack archive, like monster.bin, m_ground.bin or m_attack.bin.
+///
+/// It start with four 0, the number of entries, and a table of offset and length for each entry (terminated by an empty one). Entries are padded to [`PackFile::alignment`] with [`PackFile::padding_byte`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PackFile {
+    pub entries: Vec<Vec<u8>>,
+    /// The alignment of the start of each entry
+    pub alignment: u32,
+    /// The byte used to fill the space between the entries
+    pub padding_byte: u8,
+    /// The offset of the first entry. The table of content is padded up to it if it is smaller, like in the game files.
+    pub data_start: u32,
+}
+
+impl Default for PackFile {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            alignment: DEFAULT_PACK_ALIGNMENT,
+            padding_byte: DEFAULT_PACK_PADDING_BYTE,
+            data_start: 0,
+        }
+    }
+}
+
+impl PackFile {
+    pub fn new_from_bytes<F: Read + Seek>(mut file: F) -> Result<Self, PackFileError> {
+        let file_lenght = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut header = [0; 4];
+        file.read_exact(&mut header)?;
+        if header != [0, 0, 0, 0] {
+            return Err(PackFileError::InvalidHeader(header));
+        }
+        let entries_amount = file.read_u32::<LE>()?;
+        let mut table = Vec::new();
+        for entry_nb in 0..entries_amount as usize {
+            let entry = PackFileEntry {
+                offset: file.read_u32::<LE>()?,
+                length: file.read_u32::<LE>()?,
+            };
+            if entry.offset as u64 + entry.length as u64 > file_lenght {
+                return Err(PackFileError::EntryOutOfFile(
+                    entry_nb,
+                    entry.offset,
+                    entry.length,
+                ));
+            }
+            table.push(entry);
+        }
+
+        let mut pack = PackFile::default();
+        let non_empty = || table.iter().filter(|entry| entry.length != 0);
+        pack.data_start = non_empty().map(|entry| entry.offset).min().unwrap_or(0);
+        // deduce the alignment from the offsets of the entries, and the padding from the first entry that is padded
+        if let Some(alignment) = non_empty()
+            .map(|entry| {
+                1 << entry
+                    .offset
+                    .trailing_zeros()
+                    .min(MAX_DETECTED_ALIGNMENT_SHIFT)
+            })
+            .min()
+        {
+            pack.alignment = alignment;
+        }
+        for entry in non_empty() {
+            let end = entry.offset as u64 + entry.length as u64;
+            if !end.is_multiple_of(pack.alignment as u64) && end < file_lenght {
+                file.seek(SeekFrom::Start(end))?;
+                pack.padding_byte = file.read_u8()?;
+                break;
+            }
+        }
+
+        for entry in &table {
+            let mut content = vec![0; entry.length as usize];
+            file.seek(SeekFrom::Start(entry.offset as u64))?;
+            file.read_exact(&mut content)?;
+            pack.entries.push(content);
+        }
+        Ok(pack)
+    }
+
+    fn align(&self, position: u64) -> u64 {
+        let alignment = self.alignment.max(1) as u64;
+        position.div_ceil(alignment) * alignment
+    }
+
+    /// Return the position of every entry, as they are (or would be) written by [`PackFile::write`]
+    ///
+    /// Empty entries are stored with an offset of 0, like in the game files.
+    pub fn index(&self) -> Result<Vec<PackFileEntry>, PackFileError> {
+        // header, entry amount, table and the empty terminating entry
+        let table_end = 8 + 8 * (self.entries.len() as u64 + 1);
+        let mut position = self.align(table_end.max(self.data_start as u64));
+        let mut index = Vec::new();
+        for entry in &self.entries {
+            let length = entry.len() as u64;
+            if position + length > u32::MAX as u64 {
+                return Err(PackFileError::TooBig(position + length));
+            }
+            if length == 0 {
+                index.push(PackFileEntry {
+                    offset: 0,
+                    length: 0,
+                });
+                continue;
+            }
+            index.push(PackFileEntry {
+                offset: position as u32,
+                length: length as u32,
+            });
+            position = self.align(position + length);
+        }
+        Ok(index)
+    }
+
+    pub fn write<F: Write>(&self, file: &mut F) -> Result<(), PackFileError> {
+        let index = self.index()?;
+        let mut result = Vec::new();
+        result.write_u32::<LE>(0)?;
+        result.write_u32::<LE>(self.entries.len() as u32)?;
+        for entry in &index {
+            result.write_u32::<LE>(entry.offset)?;
+            result.write_u32::<LE>(entry.length)?;
+        }
+        result.write_all(&[0; 8])?;
+        for (entry, content) in index.iter().zip(&self.entries) {
+            if content.is_empty() {
+                continue;
+            }
+            result.resize(entry.offset as usize, self.padding_byte);
+            result.extend_from_slice(content);
+        }
+        result.resize(self.align(result.len() as u64) as usize, self.padding_byte);
+        file.write_all(&result)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Decode the wan file stored in the given entry, decompressing it first if it is in a PX container
+    pub fn extract_wan(&self, entry_nb: usize) -> Result<WanImage, PackFileError> {
+        let content = self
+            .entries
+            .get(entry_nb)
+            .ok_or(PackFileError::NoEntry(entry_nb))?;
+        Ok(match decompress_px_container(content) {
+            Ok((_, decompressed)) => WanImage::decode_wan(io::Cursor::new(decompressed))?,
+            Err(_) => WanImage::decode_wan(io::Cursor::new(content))?,
+        })
+    }
+
+    /// Replace the given entry with the [`WanImage`]. It is compressed with the same PX container as the previous content, if it was compressed.
+    pub fn replace_wan(&mut self, entry_nb: usize, wan_image: &WanImage) -> anyhow::Result<()> {
+        self.entries[entry_nb] = self.encode_entry(entry_nb, wan_image)?;
+        Ok(())
+    }
+
+    /// Encode the [`WanImage`] as [`PackFile::replace_wan`] would store it in the given entry
+    pub(crate) fn encode_entry(
+        &self,
+        entry_nb: usize,
+        wan_image: &WanImage,
+    ) -> anyhow::Result<Vec<u8>> {
+        let previous = self
+            .entries
+            .get(entry_nb)
+            .ok_or(PackFileError::NoEntry(entry_nb))?;
+        let previous_kind = [PxContainerKind::Pkdpx, PxContainerKind::At4px]
+            .iter()
+            .copied()
+            .find(|kind| previous.starts_with(kind.magic()));
+        let mut wan = io::Cursor::new(Vec::new());
+        wan_image.create_wan(&mut wan)?;
+        Ok(match previous_kind {
+            Some(kind) => compress_px_container(wan.get_ref(), kind)?,
+            None => wan.into_inner(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{PackFile, PackFileEntry};
+
+    #[test]
+    fn test_pack_file_roundtrip() {
+        let pack = PackFile {
+            entries: vec![vec![1; 5], vec![], vec![2; 32]],
+            padding_byte: 0xAA,
+            data_start: 0x40,
+            ..Default::default()
+        };
+        assert_eq!(
+            pack.index().unwrap(),
+            vec![
+                PackFileEntry {
+                    offset: 0x40,
+                    length: 5
+                },
+                PackFileEntry {
+                    offset: 0,
+                    length: 0
+                },
+                PackFileEntry {
+                    offset: 0x50,
+                    length: 32
+                }
+            ]
+        );
+        let mut written = Vec::new();
+        pack.write(&mut written).unwrap();
+        assert_eq!(written.len(), 0x70);
+        assert_eq!(written[0x45], 0xAA);
+        // the empty entry is at offset 0 in the table
+        assert_eq!(written[16..24], [0; 8]);
+        let reread = PackFile::new_from_bytes(Cursor::new(&written)).unwrap();
+        assert_eq!(reread, pack);
+
+        let mut rewritten = Vec::new();
+        reread.write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, written);
+    }
+}
@@ -1,9 +1,10 @@
-use crate::{wan_read_raw_4, WanError};
+use crate::WanError;
 use binwrite::BinWrite;
 use byteorder::{ReadBytesExt, LE};
 use std::io::{Read, Seek, SeekFrom, Write};
 
 #[derive(PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A palette, composed of group of 16 color when the first is transparent. Colors are RGBA.
 pub struct Palette {
     pub palette: Vec<[u8; 4]>,
@@ -12,13 +13,20 @@ pub struct Palette {
 impl Palette {
     /// load the Palette. Assume the cursor it located at the palette header
     pub fn new_from_bytes<F: Read + Seek>(file: &mut F) -> Result<Palette, WanError> {
+        Self::new_from_bytes_with_unknowns(file).map(|(palette, _)| palette)
+    }
+
+    /// Like [`Palette::new_from_bytes`], also returning the two unknown values of the header (see [`crate::WanUnknowns`])
+    pub(crate) fn new_from_bytes_with_unknowns<F: Read + Seek>(
+        file: &mut F,
+    ) -> Result<(Palette, (u16, u32)), WanError> {
         let mut palette = Vec::new();
         let pointer_palette_start = file.read_u32::<LE>()? as u64;
         trace!("start of palette : {}", pointer_palette_start);
 
-        file.read_u16::<LE>()?;
+        let unk1 = file.read_u16::<LE>()?;
         let nb_color = file.read_u16::<LE>()?;
-        wan_read_raw_4(file)?;
+        let unk2 = file.read_u32::<LE>()?;
         trace!(
             "palette_start: {}, nb_color: {}",
             pointer_palette_start,
@@ -35,7 +43,7 @@ impl Palette {
             let alpha = file.read_u8()?;
             palette.push([red, green, blue, alpha]);
         }
-        Ok(Palette { palette })
+        Ok((Palette { palette }, (unk1, unk2)))
     }
 
     /// Return the rgba color for the given color id and palette id.
@@ -49,6 +57,15 @@ impl Palette {
         Some(self.palette[id])
     }
 
+    /// Like [`Palette::get`], but for a 256 colors sprite (when `is_256_color` is set) the color id directly index the whole palette, and `palette_id` is ignored.
+    pub fn get_with_depth(&self, id: u8, palette_id: u16, is_256_color: bool) -> Option<[u8; 4]> {
+        if is_256_color {
+            self.get(id, 0)
+        } else {
+            self.get(id, palette_id)
+        }
+    }
+
     #[allow(dead_code)]
     pub fn color_id(&self, target_color: [u8; 4], palette_id: u16) -> Result<usize, WanError> {
         for color_id in (palette_id as usize) * 16..(palette_id as usize) * 16 + self.palette.len()
@@ -66,18 +83,29 @@ impl Palette {
     }
 
     pub fn write<F: Write + Seek>(&self, file: &mut F) -> Result<u64, WanError> {
-        let start_offset = file.seek(SeekFrom::Current(0))?;
+        let unknowns = crate::WanUnknowns::default();
+        self.write_with_unknowns(file, unknowns.palette_header, unknowns.palette_header_2)
+    }
+
+    /// Like [`Palette::write`], with the given unknown values in the header (see [`crate::WanUnknowns`])
+    pub(crate) fn write_with_unknowns<F: Write + Seek>(
+        &self,
+        file: &mut F,
+        unk1: u16,
+        unk2: u32,
+    ) -> Result<u64, WanError> {
+        let start_offset = file.stream_position()?;
         for color in &self.palette {
             color.write(file)?;
         }
 
-        let header_offset = file.seek(SeekFrom::Current(0))?;
+        let header_offset = file.stream_position()?;
         (
             start_offset as u32,
-            0u16, //unk
+            unk1,
             self.palette.len() as u16,
-            (0xFF << 16) as u32, //unk
-            0u32,                //magic
+            unk2,
+            0u32, //magic
         )
             .write(file)?;
 
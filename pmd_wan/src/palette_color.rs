@@ -0,0 +1,243 @@
+use thiserror::Error;
+
+use crate::{
+    palette_file::{component_from_8bit, component_to_8bit},
+    Palette,
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PaletteColorError {
+    #[error("The palette row {0} doesn't exist")]
+    NoRow(u16),
+    #[error("The slot {0} is out of a row of 16 colors")]
+    InvalidSlot(u8),
+}
+
+/// A change of hue, saturation and value, to apply to the colors of a [`Palette`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HsvShift {
+    /// In degree, added to the hue of the colors
+    pub hue: f32,
+    /// Multiply the saturation of the colors. The result is clamped to 1.
+    pub saturation: f32,
+    /// Multiply the value (brightness) of the colors. The result is clamped to 1.
+    pub value: f32,
+}
+
+impl Default for HsvShift {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            value: 1.0,
+        }
+    }
+}
+
+impl HsvShift {
+    /// Apply this shift to a color stored in a [`Palette`]. The alpha is kept, and the result is reduced to the 5 bits per component the DS can display.
+    pub fn apply(&self, color: [u8; 4]) -> [u8; 4] {
+        let (hue, saturation, value) = rgb_to_hsv([
+            component_to_8bit(color[0]),
+            component_to_8bit(color[1]),
+            component_to_8bit(color[2]),
+        ]);
+        let [red, green, blue] = hsv_to_rgb(
+            (hue + self.hue).rem_euclid(360.0),
+            (saturation * self.saturation).clamp(0.0, 1.0),
+            (value * self.value).clamp(0.0, 1.0),
+        );
+        [
+            component_from_8bit(red),
+            component_from_8bit(green),
+            component_from_8bit(blue),
+            color[3],
+        ]
+    }
+}
+
+/// Return the hue (in degree), the saturation and the value (between 0 and 1) of a color
+fn rgb_to_hsv(color: [u8; 3]) -> (f32, f32, f32) {
+    let [red, green, blue] = color.map(|component| component as f32 / 255.0);
+    let max = red.max(green).max(blue);
+    let min = red.min(green).min(blue);
+    let delta = max - min;
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == red {
+        60.0 * ((green - blue) / delta).rem_euclid(6.0)
+    } else if max == green {
+        60.0 * ((blue - red) / delta + 2.0)
+    } else {
+        60.0 * ((red - green) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    (hue, saturation, max)
+}
+
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [u8; 3] {
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (red, green, blue) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let min = value - chroma;
+    [red, green, blue].map(|component| ((component + min) * 255.0).round() as u8)
+}
+
+impl Palette {
+    /// Return the range of the colors of the row `row` in [`Palette::palette`]
+    fn row_range(&self, row: u16) -> Result<std::ops::Range<usize>, PaletteColorError> {
+        let start = row as usize * 16;
+        if start >= self.palette.len() {
+            return Err(PaletteColorError::NoRow(row));
+        }
+        Ok(start..(start + 16).min(self.palette.len()))
+    }
+
+    /// Apply the [`HsvShift`] to every color of the palette, except the first (transparent) one of each row
+    pub fn shift_hsv(&mut self, shift: &HsvShift) {
+        for (index, color) in self.palette.iter_mut().enumerate() {
+            if index % 16 != 0 {
+                *color = shift.apply(*color);
+            }
+        }
+    }
+
+    /// Apply the [`HsvShift`] to the colors of the row `row`, except the first (transparent) one
+    pub fn shift_row_hsv(&mut self, row: u16, shift: &HsvShift) -> Result<(), PaletteColorError> {
+        let range = self.row_range(row)?;
+        for color in self.palette[range].iter_mut().skip(1) {
+            *color = shift.apply(*color);
+        }
+        Ok(())
+    }
+
+    /// Replace the color `slot` (from 0 to 15) of the row `row` with `color`
+    pub fn replace_color(
+        &mut self,
+        row: u16,
+        slot: u8,
+        color: [u8; 4],
+    ) -> Result<(), PaletteColorError> {
+        if slot >= 16 {
+            return Err(PaletteColorError::InvalidSlot(slot));
+        }
+        let range = self.row_range(row)?;
+        let index = range.start + slot as usize;
+        if index >= range.end {
+            return Err(PaletteColorError::InvalidSlot(slot));
+        }
+        self.palette[index] = color;
+        Ok(())
+    }
+
+    /// Add a copy of the row `row` with the [`HsvShift`] applied, like the alternate colors of a shiny Pokémon, and return the index of the new row.
+    ///
+    /// The first color of the new row is kept transparent. If the last row is incomplete, it is padded with transparent colors first, so the new row start on a row boundary.
+    pub fn add_shifted_row(
+        &mut self,
+        row: u16,
+        shift: &HsvShift,
+    ) -> Result<u16, PaletteColorError> {
+        let range = self.row_range(row)?;
+        let mut new_row: Vec<[u8; 4]> = self.palette[range].to_vec();
+        new_row.resize(16, [0, 0, 0, 0]);
+        for color in new_row.iter_mut().skip(1) {
+            *color = shift.apply(*color);
+        }
+        new_row[0][3] = 0;
+        let padded_len = self.palette.len().div_ceil(16) * 16;
+        self.palette.resize(padded_len, [0, 0, 0, 0]);
+        self.palette.extend(new_row);
+        Ok((padded_len / 16) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hsv_to_rgb, rgb_to_hsv};
+    use crate::{HsvShift, Palette, PaletteColorError};
+
+    #[test]
+    fn test_hsv_conversion() {
+        for color in [
+            [255, 0, 0],
+            [0, 128, 255],
+            [12, 200, 40],
+            [0, 0, 0],
+            [90, 90, 90],
+        ] {
+            let (hue, saturation, value) = rgb_to_hsv(color);
+            assert_eq!(hsv_to_rgb(hue, saturation, value), color);
+        }
+        assert_eq!(rgb_to_hsv([0, 255, 0]), (120.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_shift_hsv() {
+        let shift = HsvShift {
+            hue: 120.0,
+            ..Default::default()
+        };
+        assert_eq!(shift.apply([248, 0, 0, 128]), [0, 248, 0, 128]);
+        let darker = HsvShift {
+            value: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(darker.apply([248, 248, 248, 128]), [128, 128, 128, 128]);
+
+        let mut palette = Palette {
+            palette: vec![[248, 0, 0, 0], [248, 0, 0, 128]],
+        };
+        palette.shift_hsv(&shift);
+        assert_eq!(palette.palette, vec![[248, 0, 0, 0], [0, 248, 0, 128]]);
+        assert_eq!(
+            palette.shift_row_hsv(1, &shift),
+            Err(PaletteColorError::NoRow(1))
+        );
+    }
+
+    #[test]
+    fn test_replace_color() {
+        let mut palette = Palette {
+            palette: vec![[0, 0, 0, 0]; 16],
+        };
+        palette.replace_color(0, 3, [8, 16, 24, 128]).unwrap();
+        assert_eq!(palette.palette[3], [8, 16, 24, 128]);
+        assert_eq!(
+            palette.replace_color(0, 16, [0, 0, 0, 0]),
+            Err(PaletteColorError::InvalidSlot(16))
+        );
+        assert_eq!(
+            palette.replace_color(1, 0, [0, 0, 0, 0]),
+            Err(PaletteColorError::NoRow(1))
+        );
+    }
+
+    #[test]
+    fn test_add_shifted_row() {
+        let mut palette = Palette {
+            palette: vec![[0, 0, 0, 0], [248, 0, 0, 128], [0, 0, 248, 128]],
+        };
+        let shiny = palette
+            .add_shifted_row(
+                0,
+                &HsvShift {
+                    hue: 120.0,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(shiny, 1);
+        assert_eq!(palette.palette.len(), 32);
+        assert_eq!(palette.palette[16], [0, 0, 0, 0]);
+        assert_eq!(palette.palette[17], [0, 248, 0, 128]);
+        assert_eq!(palette.palette[18], [248, 0, 0, 128]);
+    }
+}
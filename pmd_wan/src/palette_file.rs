@@ -0,0 +1,462 @@
+use std::io::{self, BufRead, Read, Write};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+use thiserror::Error;
+
+use crate::Palette;
+
+/// The number of colors stored in an Adobe Color Table
+pub const ACT_COLOR_COUNT: usize = 256;
+
+#[derive(Error, Debug)]
+pub enum PaletteFileError {
+    #[error("an input/output error happened")]
+    IOError(#[from] io::Error),
+    #[error("the file doesn't start with the {0:?} header")]
+    InvalidHeader(&'static str),
+    #[error("the line {0} doesn't contain a valid color")]
+    InvalidColor(usize),
+    #[error("the file declare {expected} colors, but {found} have been found")]
+    WrongColorCount { expected: usize, found: usize },
+    #[error("the palette has {0} colors, but this format can only store {ACT_COLOR_COUNT}")]
+    TooManyColors(usize),
+}
+
+/// How a color component is converted between the full 0-255 range and the 5 bits the DS store, kept in the 5 upper bits of a [`Palette`] color.
+///
+/// Tools don't agree on it, so a palette written with one policy and displayed by a tool using another can look slightly different.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorConversion {
+    /// Drop the 3 lower bits, and fill them with the upper ones when expanding, so the 5-bit white become 255 and not 248
+    #[default]
+    Truncate,
+    /// Use the 5-bit value that expand to the nearest value, filling the 3 lower bits with the upper ones when expanding
+    Round,
+    /// Multiply by 31/255 and by 255/31, rounding to the nearest value
+    Scale,
+    /// Drop the 3 lower bits, and leave them at 0 when expanding, so the 5-bit white become 248. This is the value stored in the file.
+    Shift,
+}
+
+impl ColorConversion {
+    /// Convert a component in the 0-255 range to a 5-bit value, from 0 to 31
+    pub fn to_5bit(self, component: u8) -> u8 {
+        match self {
+            Self::Truncate | Self::Shift => component >> 3,
+            Self::Round => {
+                let low = component >> 3;
+                (low.saturating_sub(1)..=(low + 1).min(31))
+                    .min_by_key(|value| (self.from_5bit(*value) as i16 - component as i16).abs())
+                    .unwrap_or(low)
+            }
+            Self::Scale => ((component as u16 * 31 + 127) / 255) as u8,
+        }
+    }
+
+    /// Convert a 5-bit value (only the 5 lower bits are used) to the 0-255 range
+    pub fn from_5bit(self, value: u8) -> u8 {
+        let value = value & 0x1F;
+        match self {
+            Self::Truncate | Self::Round => (value << 3) | (value >> 2),
+            Self::Scale => ((value as u16 * 255 + 15) / 31) as u8,
+            Self::Shift => value << 3,
+        }
+    }
+
+    /// Convert a color component stored in a [`Palette`] to the full 0-255 range
+    pub fn decode_component(self, component: u8) -> u8 {
+        self.from_5bit(component >> 3)
+    }
+
+    /// Convert a color component in the 0-255 range to the value stored in a [`Palette`]
+    pub fn encode_component(self, component: u8) -> u8 {
+        self.to_5bit(component) << 3
+    }
+}
+
+/// Convert a color component stored in a [`Palette`] to the full 0-255 range, with the default [`ColorConversion`]
+pub(crate) fn component_to_8bit(component: u8) -> u8 {
+    ColorConversion::default().decode_component(component)
+}
+
+/// Convert a color component in the 0-255 range to the 5-bit value stored in a [`Palette`], with the default [`ColorConversion`]. This is the inverse of [`component_to_8bit`].
+pub(crate) fn component_from_8bit(component: u8) -> u8 {
+    ColorConversion::default().encode_component(component)
+}
+
+impl Palette {
+    /// The colors with their components converted to the 0-255 range with `conversion`, without the alpha
+    pub fn rgb_colors(&self, conversion: ColorConversion) -> impl Iterator<Item = [u8; 3]> + '_ {
+        self.palette.iter().map(move |color| {
+            [
+                conversion.decode_component(color[0]),
+                conversion.decode_component(color[1]),
+                conversion.decode_component(color[2]),
+            ]
+        })
+    }
+
+    /// Build a [`Palette`] from colors in the 0-255 range, reduced to 5 bits with `conversion`. The first color of each row of 16 colors is transparent, the others are opaque.
+    pub fn from_rgb_colors(
+        colors: impl Iterator<Item = [u8; 3]>,
+        conversion: ColorConversion,
+    ) -> Palette {
+        Palette {
+            palette: colors
+                .enumerate()
+                .map(|(index, [red, green, blue])| {
+                    [
+                        conversion.encode_component(red),
+                        conversion.encode_component(green),
+                        conversion.encode_component(blue),
+                        if index % 16 == 0 { 0 } else { 128 },
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    /// Write this palette as a JASC-PAL file, as used by Paint Shop Pro.
+    ///
+    /// As for every palette file format, the alpha is lost, and the components are scaled to the 0-255 range.
+    pub fn write_jasc_pal<W: Write>(&self, writer: W) -> Result<(), PaletteFileError> {
+        self.write_jasc_pal_with_conversion(writer, ColorConversion::default())
+    }
+
+    /// Like [`Palette::write_jasc_pal`], with the components scaled with `conversion`
+    pub fn write_jasc_pal_with_conversion<W: Write>(
+        &self,
+        mut writer: W,
+        conversion: ColorConversion,
+    ) -> Result<(), PaletteFileError> {
+        write!(writer, "JASC-PAL\r\n0100\r\n{}\r\n", self.palette.len())?;
+        for [red, green, blue] in self.rgb_colors(conversion) {
+            write!(writer, "{} {} {}\r\n", red, green, blue)?;
+        }
+        Ok(())
+    }
+
+    /// Read a JASC-PAL file, as written by [`Palette::write_jasc_pal`].
+    ///
+    /// The first color of each row of 16 colors is made transparent, and the components are reduced to the 5 bits the DS can display.
+    pub fn read_jasc_pal<R: BufRead>(reader: R) -> Result<Palette, PaletteFileError> {
+        Self::read_jasc_pal_with_conversion(reader, ColorConversion::default())
+    }
+
+    /// Like [`Palette::read_jasc_pal`], with the components reduced with `conversion`
+    pub fn read_jasc_pal_with_conversion<R: BufRead>(
+        reader: R,
+        conversion: ColorConversion,
+    ) -> Result<Palette, PaletteFileError> {
+        let mut lines = reader.lines();
+        let mut next_line = || -> Result<Option<String>, PaletteFileError> {
+            Ok(lines
+                .next()
+                .transpose()?
+                .map(|line| line.trim().to_string()))
+        };
+        if next_line()?.as_deref() != Some("JASC-PAL") {
+            return Err(PaletteFileError::InvalidHeader("JASC-PAL"));
+        }
+        if next_line()?.as_deref() != Some("0100") {
+            return Err(PaletteFileError::InvalidHeader("0100"));
+        }
+        let expected = next_line()?
+            .and_then(|line| line.parse::<usize>().ok())
+            .ok_or(PaletteFileError::InvalidColor(3))?;
+        let mut colors = Vec::with_capacity(expected);
+        let mut line_number = 3;
+        while let Some(line) = next_line()? {
+            line_number += 1;
+            if line.is_empty() {
+                continue;
+            }
+            colors.push(parse_rgb(&line).ok_or(PaletteFileError::InvalidColor(line_number))?);
+        }
+        if colors.len() != expected {
+            return Err(PaletteFileError::WrongColorCount {
+                expected,
+                found: colors.len(),
+            });
+        }
+        Ok(Palette::from_rgb_colors(colors.into_iter(), conversion))
+    }
+
+    /// Write this palette as a GIMP palette (`.gpl`) named `name`, with 16 columns so each row of the palette is on its own line in GIMP.
+    ///
+    /// The alpha is lost, and the components are scaled to the 0-255 range.
+    pub fn write_gpl<W: Write>(&self, writer: W, name: &str) -> Result<(), PaletteFileError> {
+        self.write_gpl_with_conversion(writer, name, ColorConversion::default())
+    }
+
+    /// Like [`Palette::write_gpl`], with the components scaled with `conversion`
+    pub fn write_gpl_with_conversion<W: Write>(
+        &self,
+        mut writer: W,
+        name: &str,
+        conversion: ColorConversion,
+    ) -> Result<(), PaletteFileError> {
+        write!(writer, "GIMP Palette\nName: {}\nColumns: 16\n#\n", name)?;
+        for (index, [red, green, blue]) in self.rgb_colors(conversion).enumerate() {
+            writeln!(
+                writer,
+                "{:3} {:3} {:3}\tRow {} color {}",
+                red,
+                green,
+                blue,
+                index / 16,
+                index % 16
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Read a GIMP palette (`.gpl`). The name of the colors and of the palette are ignored.
+    ///
+    /// The first color of each row of 16 colors is made transparent, and the components are reduced to the 5 bits the DS can display.
+    pub fn read_gpl<R: BufRead>(reader: R) -> Result<Palette, PaletteFileError> {
+        Self::read_gpl_with_conversion(reader, ColorConversion::default())
+    }
+
+    /// Like [`Palette::read_gpl`], with the components reduced with `conversion`
+    pub fn read_gpl_with_conversion<R: BufRead>(
+        reader: R,
+        conversion: ColorConversion,
+    ) -> Result<Palette, PaletteFileError> {
+        let mut lines = reader.lines();
+        match lines.next().transpose()? {
+            Some(line) if line.trim() == "GIMP Palette" => (),
+            _ => return Err(PaletteFileError::InvalidHeader("GIMP Palette")),
+        };
+        let mut colors = Vec::new();
+        for (line_number, line) in lines.enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("Name:")
+                || line.starts_with("Columns:")
+            {
+                continue;
+            }
+            colors.push(parse_rgb(line).ok_or(PaletteFileError::InvalidColor(line_number + 2))?);
+        }
+        Ok(Palette::from_rgb_colors(colors.into_iter(), conversion))
+    }
+
+    /// Write this palette as an Adobe Color Table (`.act`), with the number of colors and the first color as the transparent one.
+    ///
+    /// At most [`ACT_COLOR_COUNT`] colors can be stored. The alpha is lost, and the components are scaled to the 0-255 range.
+    pub fn write_act<W: Write>(&self, writer: W) -> Result<(), PaletteFileError> {
+        self.write_act_with_conversion(writer, ColorConversion::default())
+    }
+
+    /// Like [`Palette::write_act`], with the components scaled with `conversion`
+    pub fn write_act_with_conversion<W: Write>(
+        &self,
+        mut writer: W,
+        conversion: ColorConversion,
+    ) -> Result<(), PaletteFileError> {
+        if self.palette.len() > ACT_COLOR_COUNT {
+            return Err(PaletteFileError::TooManyColors(self.palette.len()));
+        }
+        let mut table = [0; ACT_COLOR_COUNT * 3];
+        for (index, color) in self.rgb_colors(conversion).enumerate() {
+            table[index * 3..index * 3 + 3].copy_from_slice(&color);
+        }
+        writer.write_all(&table)?;
+        writer.write_u16::<BE>(self.palette.len() as u16)?;
+        writer.write_u16::<BE>(0)?;
+        Ok(())
+    }
+
+    /// Read an Adobe Color Table (`.act`). If the file doesn't contain the number of colors, all of the [`ACT_COLOR_COUNT`] colors are read. The transparent color is ignored.
+    ///
+    /// The first color of each row of 16 colors is made transparent, and the components are reduced to the 5 bits the DS can display.
+    pub fn read_act<R: Read>(reader: R) -> Result<Palette, PaletteFileError> {
+        Self::read_act_with_conversion(reader, ColorConversion::default())
+    }
+
+    /// Like [`Palette::read_act`], with the components reduced with `conversion`
+    pub fn read_act_with_conversion<R: Read>(
+        mut reader: R,
+        conversion: ColorConversion,
+    ) -> Result<Palette, PaletteFileError> {
+        let mut table = [0; ACT_COLOR_COUNT * 3];
+        reader.read_exact(&mut table)?;
+        let count = match reader.read_u16::<BE>() {
+            Ok(count) => (count as usize).min(ACT_COLOR_COUNT),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => ACT_COLOR_COUNT,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Palette::from_rgb_colors(
+            table
+                .chunks_exact(3)
+                .take(count)
+                .map(|color| [color[0], color[1], color[2]]),
+            conversion,
+        ))
+    }
+}
+
+/// Parse the three first whitespace-separated components of a line
+fn parse_rgb(line: &str) -> Option<[u8; 3]> {
+    let mut components = line.split_whitespace().map(|part| part.parse::<u8>());
+    Some([
+        components.next()?.ok()?,
+        components.next()?.ok()?,
+        components.next()?.ok()?,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{component_from_8bit, component_to_8bit};
+    use crate::{
+        image_tool::TransparencyOptions,
+        tests::fixtures::{insert_filled_frame, test_wan_image},
+        ColorConversion, Palette, PaletteFileError, SpriteType,
+    };
+
+    fn test_palette() -> Palette {
+        Palette {
+            palette: (0..20)
+                .map(|index| {
+                    [
+                        index * 8,
+                        248 - index * 8,
+                        (index * 12) & 0xF8,
+                        if index % 16 == 0 { 0 } else { 128 },
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_component_scaling() {
+        assert_eq!(component_to_8bit(0), 0);
+        assert_eq!(component_to_8bit(248), 255);
+        for component in 0..=255 {
+            let stored = component_from_8bit(component);
+            assert_eq!(component_from_8bit(component_to_8bit(stored)), stored);
+        }
+    }
+
+    #[test]
+    fn test_color_conversion() {
+        let all = [
+            ColorConversion::Truncate,
+            ColorConversion::Round,
+            ColorConversion::Scale,
+            ColorConversion::Shift,
+        ];
+        for conversion in all {
+            assert_eq!(conversion.to_5bit(0), 0);
+            assert_eq!(conversion.to_5bit(255), 31);
+            assert_eq!(conversion.from_5bit(0), 0);
+            for value in 0..32 {
+                assert_eq!(conversion.to_5bit(conversion.from_5bit(value)), value);
+            }
+        }
+        assert_eq!(ColorConversion::Truncate.to_5bit(7), 0);
+        assert_eq!(ColorConversion::Round.to_5bit(7), 1);
+        assert_eq!(ColorConversion::Scale.to_5bit(132), 16);
+        assert_eq!(ColorConversion::Truncate.from_5bit(31), 255);
+        assert_eq!(ColorConversion::Shift.from_5bit(31), 248);
+        assert_eq!(ColorConversion::Scale.from_5bit(16), 132);
+        assert_eq!(ColorConversion::Round.encode_component(255), 248);
+
+        let palette = Palette {
+            palette: vec![[0, 0, 0, 0], [248, 128, 8, 128]],
+        };
+        let mut file = Vec::new();
+        palette
+            .write_jasc_pal_with_conversion(&mut file, ColorConversion::Shift)
+            .unwrap();
+        assert!(file.ends_with(b"248 128 8\r\n"));
+        assert_eq!(
+            Palette::read_jasc_pal_with_conversion(Cursor::new(&file), ColorConversion::Shift)
+                .unwrap(),
+            palette
+        );
+        let file = "JASC-PAL\n0100\n2\n0 0 0\n7 250 255\n";
+        assert_eq!(
+            Palette::read_jasc_pal(Cursor::new(file)).unwrap().palette[1],
+            [0, 248, 248, 128]
+        );
+        assert_eq!(
+            Palette::read_jasc_pal_with_conversion(Cursor::new(file), ColorConversion::Round)
+                .unwrap()
+                .palette[1],
+            [8, 240, 248, 128]
+        );
+    }
+
+    #[test]
+    fn test_jasc_pal() {
+        let palette = test_palette();
+        let mut file = Vec::new();
+        palette.write_jasc_pal(&mut file).unwrap();
+        assert!(file.starts_with(b"JASC-PAL\r\n0100\r\n20\r\n0 255 0\r\n"));
+        assert_eq!(Palette::read_jasc_pal(Cursor::new(file)).unwrap(), palette);
+
+        assert!(matches!(
+            Palette::read_jasc_pal(Cursor::new("JASC-PAL\n0100\n2\n1 2 3\n")),
+            Err(PaletteFileError::WrongColorCount {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_gpl() {
+        let palette = test_palette();
+        let mut file = Vec::new();
+        palette.write_gpl(&mut file, "test").unwrap();
+        assert_eq!(Palette::read_gpl(Cursor::new(file)).unwrap(), palette);
+
+        assert!(matches!(
+            Palette::read_gpl(Cursor::new("GIMP Palette\n1 2\n")),
+            Err(PaletteFileError::InvalidColor(2))
+        ));
+    }
+
+    #[test]
+    fn test_act() {
+        let palette = test_palette();
+        let mut file = Vec::new();
+        palette.write_act(&mut file).unwrap();
+        assert_eq!(file.len(), 772);
+        assert_eq!(Palette::read_act(Cursor::new(&file)).unwrap(), palette);
+        // without the color count
+        assert_eq!(
+            Palette::read_act(Cursor::new(&file[..768]))
+                .unwrap()
+                .palette
+                .len(),
+            256
+        );
+    }
+    #[test]
+    fn test_render_agree_with_export() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette[1] = [248, 0, 0, 128];
+        let frame_id = insert_filled_frame(&mut wanimage, 8, 8);
+        let rendered = wanimage.render_frame(frame_id as usize).unwrap();
+        assert_eq!(rendered.get_pixel(0, 0).0, [255, 0, 0, 255]);
+
+        let mut file = Vec::new();
+        wanimage.palette.write_jasc_pal(&mut file).unwrap();
+        assert!(file.ends_with(b"\r\n255 0 0\r\n"));
+
+        // importing the rendered frame give back the same palette
+        let imported =
+            Palette::from_rgba_image(&rendered, &TransparencyOptions::default()).unwrap();
+        assert_eq!(imported.palette, wanimage.palette.palette);
+    }
+}
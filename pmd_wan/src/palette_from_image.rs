@@ -0,0 +1,152 @@
+use image::RgbaImage;
+use thiserror::Error;
+
+use crate::{
+    image_tool::{IndexedImage, TransparencyOptions},
+    palette_file::component_from_8bit,
+    quantize_image, Palette, QuantizationOptions, MAX_FRAGMENT_PALETTE_INDEX,
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PaletteFromImageError {
+    #[error("The image has {0} opaque colors, but a palette row can only contain 15 of them")]
+    TooManyColors(usize),
+    #[error("The palette of the image has {0} colors, but a palette can only contain {} rows of 16 colors", MAX_FRAGMENT_PALETTE_INDEX + 1)]
+    TooManyRows(usize),
+    #[error("The image use the color {0}, which doesn't exist in its palette")]
+    MissingColor(u8),
+}
+
+/// Convert an RGBA color (alpha from 0 to 255) to a [`Palette`] one (alpha from 0 to 128), with the components reduced to 5 bits by the default [`crate::ColorConversion`]
+pub(crate) fn rgba_to_palette_color(color: [u8; 4]) -> [u8; 4] {
+    [
+        component_from_8bit(color[0]),
+        component_from_8bit(color[1]),
+        component_from_8bit(color[2]),
+        color[3].div_ceil(2),
+    ]
+}
+
+impl Palette {
+    /// Create a palette with the colors of an indexed image, keeping their indices, so the pixels of the image can be used as is with the palette rows.
+    ///
+    /// The color `n` is in the row `n / 16`. The first color of each row is transparent when used by a 16 colors [`crate::Fragment`], whatever it is.
+    pub fn from_indexed_image(image: &IndexedImage) -> Result<Palette, PaletteFromImageError> {
+        if image.palette.len() > (MAX_FRAGMENT_PALETTE_INDEX as usize + 1) * 16 {
+            return Err(PaletteFromImageError::TooManyRows(image.palette.len()));
+        }
+        if let Some(pixel) = image
+            .pixels
+            .iter()
+            .find(|pixel| **pixel as usize >= image.palette.len())
+        {
+            return Err(PaletteFromImageError::MissingColor(*pixel));
+        }
+        Ok(Palette {
+            palette: image
+                .palette
+                .iter()
+                .map(|color| rgba_to_palette_color(*color))
+                .collect(),
+        })
+    }
+
+    /// Create a single row palette with the opaque colors of the image, in order of first appearance, after the transparent color.
+    ///
+    /// Fail if there are more than 15 of them. See [`Palette::from_rgba_image_quantized`] to reduce them instead.
+    pub fn from_rgba_image(
+        image: &RgbaImage,
+        transparency: &TransparencyOptions,
+    ) -> Result<Palette, PaletteFromImageError> {
+        let mut palette = vec![[0, 0, 0, 0]];
+        for pixel in image.pixels() {
+            let color = rgba_to_palette_color([pixel.0[0], pixel.0[1], pixel.0[2], 255]);
+            if !transparency.is_transparent(*pixel) && !palette[1..].contains(&color) {
+                palette.push(color);
+            }
+        }
+        if palette.len() > 16 {
+            return Err(PaletteFromImageError::TooManyColors(palette.len() - 1));
+        }
+        Ok(Palette { palette })
+    }
+
+    /// Create a single row palette with the colors chosen by [`quantize_image`], after the transparent color.
+    ///
+    /// At most 15 colors can fit in a row, so [`QuantizationOptions::max_colors`] is capped at that.
+    pub fn from_rgba_image_quantized(image: &RgbaImage, options: &QuantizationOptions) -> Palette {
+        let options = QuantizationOptions {
+            max_colors: options.max_colors.min(15),
+            ..*options
+        };
+        let quantized = quantize_image(image, &options);
+        let mut palette = vec![[0, 0, 0, 0]];
+        palette.extend(
+            quantized
+                .colors
+                .iter()
+                .map(|color| rgba_to_palette_color([color[0], color[1], color[2], 255])),
+        );
+        Palette { palette }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{
+        image_tool::{IndexedImage, TransparencyOptions},
+        Palette, PaletteFromImageError, QuantizationOptions,
+    };
+
+    #[test]
+    fn test_palette_from_indexed_image() {
+        let mut image = IndexedImage {
+            pixels: vec![0, 1, 17],
+            width: 3,
+            height: 1,
+            palette: vec![[10, 20, 30, 255]; 18],
+        };
+        image.palette[0] = [0, 0, 0, 0];
+        let palette = Palette::from_indexed_image(&image).unwrap();
+        assert_eq!(palette.row_count(), 2);
+        // the components are reduced to 5 bits
+        assert_eq!(palette.get(1, 1), Some([8, 16, 24, 128]));
+        assert_eq!(palette.palette[0], [0, 0, 0, 0]);
+
+        image.pixels.push(18);
+        assert_eq!(
+            Palette::from_indexed_image(&image),
+            Err(PaletteFromImageError::MissingColor(18))
+        );
+    }
+
+    #[test]
+    fn test_palette_from_rgba_image() {
+        let mut image = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0]));
+        image.put_pixel(1, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(0, 1, Rgba([0, 255, 0, 255]));
+        image.put_pixel(1, 1, Rgba([255, 0, 0, 255]));
+        let palette = Palette::from_rgba_image(&image, &TransparencyOptions::default()).unwrap();
+        assert_eq!(
+            palette.palette,
+            vec![[0, 0, 0, 0], [248, 0, 0, 128], [0, 248, 0, 128]]
+        );
+
+        let gradient = RgbaImage::from_fn(4, 5, |x, y| Rgba([(x * 5 + y) as u8 * 8, 0, 0, 255]));
+        assert_eq!(
+            Palette::from_rgba_image(&gradient, &TransparencyOptions::default()),
+            Err(PaletteFromImageError::TooManyColors(20))
+        );
+        let palette = Palette::from_rgba_image_quantized(
+            &gradient,
+            &QuantizationOptions {
+                max_colors: 200,
+                ..Default::default()
+            },
+        );
+        assert_eq!(palette.palette.len(), 16);
+        assert_eq!(palette.palette[0], [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,284 @@
+use thiserror::Error;
+
+use crate::{Palette, WanImage, MAX_FRAGMENT_PALETTE_INDEX};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PaletteRowError {
+    #[error("The palette row {0} doesn't exist")]
+    NoRow(u16),
+    #[error("There would be more palette rows than the {} that can be used", MAX_FRAGMENT_PALETTE_INDEX + 1)]
+    TooManyRows,
+    #[error("A row should contain at most 16 colors, but {0} were given")]
+    RowTooBig(usize),
+    #[error("The palette row {row} is still used by the fragment {fragment} of the frame {frame}")]
+    RowInUse {
+        row: u16,
+        frame: usize,
+        fragment: usize,
+    },
+    #[error("The order should contain each of the {0} rows exactly once")]
+    InvalidOrder(u16),
+    #[error("The frame {0} doesn't exist")]
+    NoFrame(usize),
+    #[error("The fragment {fragment} doesn't exist in the frame {frame}")]
+    NoFragment { frame: usize, fragment: usize },
+    #[error("The fragment {fragment} of the frame {frame} use the palette row {row}, which doesn't exist")]
+    MissingRow {
+        frame: usize,
+        fragment: usize,
+        row: u16,
+    },
+}
+
+impl Palette {
+    /// The number of rows of 16 colors of this palette, counting an incomplete last row
+    pub fn row_count(&self) -> u16 {
+        self.palette.len().div_ceil(16) as u16
+    }
+}
+
+impl WanImage {
+    /// The number of rows of 16 colors of the [`Palette`], that can each be used by [`crate::Fragment`]s with [`crate::Fragment::pal_idx`]
+    pub fn palette_row_count(&self) -> u16 {
+        self.palette.row_count()
+    }
+
+    fn check_row_exist(&self, row: u16) -> Result<(), PaletteRowError> {
+        if row >= self.palette_row_count() {
+            return Err(PaletteRowError::NoRow(row));
+        }
+        Ok(())
+    }
+
+    /// Add a palette row with the given colors (at most 16, the remaining ones being transparent), and return its index.
+    ///
+    /// If the last row is incomplete, it is padded with transparent colors first. The first color of the row is considered transparent.
+    pub fn add_palette_row(&mut self, colors: &[[u8; 4]]) -> Result<u16, PaletteRowError> {
+        if colors.len() > 16 {
+            return Err(PaletteRowError::RowTooBig(colors.len()));
+        }
+        let row = self.palette_row_count();
+        if row > MAX_FRAGMENT_PALETTE_INDEX {
+            return Err(PaletteRowError::TooManyRows);
+        }
+        let start = row as usize * 16;
+        self.palette.palette.resize(start, [0, 0, 0, 0]);
+        self.palette.palette.extend_from_slice(colors);
+        self.palette.palette.resize(start + 16, [0, 0, 0, 0]);
+        Ok(row)
+    }
+
+    /// Remove the palette row `row`. The [`crate::Fragment`]s that use a following row are updated to use the same colors.
+    ///
+    /// Fail if a [`crate::Fragment`] still use this row. Always fail for 256 colors sprites that have fragments, as their palette is shared.
+    pub fn remove_palette_row(&mut self, row: u16) -> Result<(), PaletteRowError> {
+        self.check_row_exist(row)?;
+        for (frame_id, frame) in self.frame_store.frames.iter().enumerate() {
+            for (fragment_id, fragment) in frame.fragments.iter().enumerate() {
+                if fragment.pal_idx == row || self.is_256_color {
+                    return Err(PaletteRowError::RowInUse {
+                        row,
+                        frame: frame_id,
+                        fragment: fragment_id,
+                    });
+                }
+            }
+        }
+        let start = row as usize * 16;
+        let end = (start + 16).min(self.palette.palette.len());
+        self.palette.palette.drain(start..end);
+        for fragment in self
+            .frame_store
+            .frames
+            .iter_mut()
+            .flat_map(|frame| frame.fragments.iter_mut())
+        {
+            if fragment.pal_idx > row {
+                fragment.pal_idx -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reorder the palette rows, so the new row `n` is the old row `order[n]`. The [`crate::Fragment`]s are updated to keep their colors.
+    ///
+    /// `order` should contain each existing row exactly once. An incomplete last row is padded with transparent colors.
+    pub fn reorder_palette_rows(&mut self, order: &[u16]) -> Result<(), PaletteRowError> {
+        let row_count = self.palette_row_count();
+        let mut remap = vec![None; row_count as usize];
+        if order.len() != row_count as usize {
+            return Err(PaletteRowError::InvalidOrder(row_count));
+        }
+        for (new_row, old_row) in order.iter().enumerate() {
+            match remap.get_mut(*old_row as usize) {
+                Some(slot @ None) => *slot = Some(new_row as u16),
+                _ => return Err(PaletteRowError::InvalidOrder(row_count)),
+            }
+        }
+        let mut reordered = Vec::with_capacity(self.palette.palette.len());
+        for old_row in order {
+            let start = *old_row as usize * 16;
+            let end = (start + 16).min(self.palette.palette.len());
+            reordered.extend_from_slice(&self.palette.palette[start..end]);
+            reordered.resize(reordered.len().div_ceil(16) * 16, [0, 0, 0, 0]);
+        }
+        self.palette.palette = reordered;
+        for fragment in self
+            .frame_store
+            .frames
+            .iter_mut()
+            .flat_map(|frame| frame.fragments.iter_mut())
+        {
+            if let Some(Some(new_row)) = remap.get(fragment.pal_idx as usize) {
+                fragment.pal_idx = *new_row;
+            }
+        }
+        Ok(())
+    }
+
+    /// Make the fragment `fragment` of the frame `frame` use the palette row `row`
+    pub fn set_fragment_palette_row(
+        &mut self,
+        frame: usize,
+        fragment: usize,
+        row: u16,
+    ) -> Result<(), PaletteRowError> {
+        self.check_row_exist(row)?;
+        let fragment = self
+            .frame_store
+            .frames
+            .get_mut(frame)
+            .ok_or(PaletteRowError::NoFrame(frame))?
+            .fragments
+            .get_mut(fragment)
+            .ok_or(PaletteRowError::NoFragment { frame, fragment })?;
+        fragment.pal_idx = row;
+        Ok(())
+    }
+
+    /// Make every fragment of the frame `frame` use the palette row `row`
+    pub fn set_frame_palette_row(&mut self, frame: usize, row: u16) -> Result<(), PaletteRowError> {
+        self.check_row_exist(row)?;
+        let frame = self
+            .frame_store
+            .frames
+            .get_mut(frame)
+            .ok_or(PaletteRowError::NoFrame(frame))?;
+        for fragment in &mut frame.fragments {
+            fragment.pal_idx = row;
+        }
+        Ok(())
+    }
+
+    /// Check that every palette row used by a [`crate::Fragment`] exist. 256 colors sprites are always valid, as they ignore [`crate::Fragment::pal_idx`].
+    pub fn check_palette_rows(&self) -> Result<(), PaletteRowError> {
+        if self.is_256_color {
+            return Ok(());
+        }
+        let row_count = self.palette_row_count();
+        for (frame_id, frame) in self.frame_store.frames.iter().enumerate() {
+            for (fragment_id, fragment) in frame.fragments.iter().enumerate() {
+                if fragment.pal_idx >= row_count {
+                    return Err(PaletteRowError::MissingRow {
+                        frame: frame_id,
+                        fragment: fragment_id,
+                        row: fragment.pal_idx,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{insert_filled_frame, test_wan_image},
+        PaletteRowError, SpriteType, WanImage,
+    };
+
+    fn two_rows_image() -> WanImage {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage
+            .add_palette_row(&[[0, 0, 0, 0], [0, 0, 248, 128]])
+            .unwrap();
+        insert_filled_frame(&mut wanimage, 8, 8);
+        insert_frame_in_wanimage(vec![1; 64], 8, 8, &mut wanimage, 1).unwrap();
+        wanimage
+    }
+
+    #[test]
+    fn test_add_palette_row() {
+        let wanimage = two_rows_image();
+        assert_eq!(wanimage.palette_row_count(), 2);
+        assert_eq!(wanimage.palette.palette.len(), 32);
+        assert_eq!(wanimage.palette.palette[17], [0, 0, 248, 128]);
+        assert!(wanimage.check_palette_rows().is_ok());
+    }
+
+    #[test]
+    fn test_remove_palette_row() {
+        let mut wanimage = two_rows_image();
+        assert_eq!(
+            wanimage.remove_palette_row(0),
+            Err(PaletteRowError::RowInUse {
+                row: 0,
+                frame: 0,
+                fragment: 0
+            })
+        );
+        wanimage.frame_store.frames.remove(0);
+        let before = wanimage.render_frame(0).unwrap();
+        wanimage.remove_palette_row(0).unwrap();
+        assert_eq!(wanimage.palette_row_count(), 1);
+        assert_eq!(wanimage.frame_store.frames[0].fragments[0].pal_idx, 0);
+        assert_eq!(wanimage.render_frame(0).unwrap(), before);
+    }
+
+    #[test]
+    fn test_reorder_palette_rows() {
+        let mut wanimage = two_rows_image();
+        let before = [
+            wanimage.render_frame(0).unwrap(),
+            wanimage.render_frame(1).unwrap(),
+        ];
+        wanimage.reorder_palette_rows(&[1, 0]).unwrap();
+        assert_eq!(wanimage.palette.palette[1], [0, 0, 248, 128]);
+        assert_eq!(wanimage.frame_store.frames[0].fragments[0].pal_idx, 1);
+        assert_eq!(wanimage.render_frame(0).unwrap(), before[0]);
+        assert_eq!(wanimage.render_frame(1).unwrap(), before[1]);
+        assert_eq!(
+            wanimage.reorder_palette_rows(&[0, 0]),
+            Err(PaletteRowError::InvalidOrder(2))
+        );
+    }
+
+    #[test]
+    fn test_set_palette_row() {
+        let mut wanimage = two_rows_image();
+        wanimage.set_frame_palette_row(0, 1).unwrap();
+        assert_eq!(wanimage.frame_store.frames[0].fragments[0].pal_idx, 1);
+        assert_eq!(
+            wanimage.set_fragment_palette_row(0, 0, 2),
+            Err(PaletteRowError::NoRow(2))
+        );
+        assert_eq!(
+            wanimage.set_fragment_palette_row(0, 5, 0),
+            Err(PaletteRowError::NoFragment {
+                frame: 0,
+                fragment: 5
+            })
+        );
+        wanimage.frame_store.frames[1].fragments[0].pal_idx = 3;
+        assert_eq!(
+            wanimage.check_palette_rows(),
+            Err(PaletteRowError::MissingRow {
+                frame: 1,
+                fragment: 0,
+                row: 3
+            })
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use image::{Rgba, RgbaImage};
+
+use crate::{frame_render::palette_color_to_rgba, Palette};
+
+/// The two colors of the checkerboard drawn on the transparent colors by [`Palette::render_swatch`]
+pub const SWATCH_TRANSPARENT_COLORS: [Rgba<u8>; 2] =
+    [Rgba([255, 255, 255, 255]), Rgba([192, 192, 192, 255])];
+
+impl Palette {
+    /// Draw every color of this palette as a square of `cell_size` pixels, with one line per row of 16 colors.
+    ///
+    /// The first color of each row, which is transparent when used by a 16 colors [`crate::Fragment`], is covered by a checkerboard of [`SWATCH_TRANSPARENT_COLORS`] on its bottom-right half, so its color is still visible.
+    /// The missing colors of an incomplete last row are left transparent.
+    pub fn render_swatch(&self, cell_size: u32) -> RgbaImage {
+        let cell_size = cell_size.max(1);
+        let square_size = (cell_size / 4).max(1);
+        let mut image = RgbaImage::new(16 * cell_size, self.row_count() as u32 * cell_size);
+        for (color_id, color) in self.palette.iter().enumerate() {
+            let start_x = (color_id % 16) as u32 * cell_size;
+            let start_y = (color_id / 16) as u32 * cell_size;
+            let color = palette_color_to_rgba(*color);
+            for y in 0..cell_size {
+                for x in 0..cell_size {
+                    let pixel = if color_id % 16 == 0 && x + y >= cell_size {
+                        SWATCH_TRANSPARENT_COLORS
+                            [((x / square_size + y / square_size) % 2) as usize]
+                    } else {
+                        color
+                    };
+                    image.put_pixel(start_x + x, start_y + y, pixel);
+                }
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use crate::{Palette, SWATCH_TRANSPARENT_COLORS};
+
+    #[test]
+    fn test_render_swatch() {
+        let mut palette = Palette {
+            palette: vec![[0, 0, 255, 128]; 17],
+        };
+        palette.palette[1] = [255, 0, 0, 128];
+        let swatch = palette.render_swatch(8);
+        assert_eq!(swatch.dimensions(), (16 * 8, 2 * 8));
+        assert_eq!(swatch.get_pixel(8, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(swatch.get_pixel(16 + 7, 7), &Rgba([0, 0, 255, 255]));
+        // the transparent colors
+        assert_eq!(swatch.get_pixel(0, 0), &Rgba([0, 0, 255, 255]));
+        assert_eq!(swatch.get_pixel(7, 7), &SWATCH_TRANSPARENT_COLORS[0]);
+        assert_eq!(swatch.get_pixel(5, 7), &SWATCH_TRANSPARENT_COLORS[1]);
+        assert_eq!(swatch.get_pixel(7, 8 + 7), &SWATCH_TRANSPARENT_COLORS[0]);
+        // the missing colors
+        assert_eq!(swatch.get_pixel(8, 8), &Rgba([0, 0, 0, 0]));
+    }
+}
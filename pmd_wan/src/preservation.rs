@@ -0,0 +1,101 @@
+use std::io::Write;
+
+use crate::{WanError, WanImage};
+
+/// A [`WanImage`] that remember the file it was decoded from, so re-saving it without modification give back exactly the same bytes.
+///
+/// The encoder of this library doesn't reproduce the layout of the original files (padding, compression entry boundaries, ordering of the sections...).
+/// This is a problem for patch formats based on binary diffs, which would otherwise contain the whole sprite even when nothing changed.
+///
+/// Modification are detected by comparing the encoding of the current [`WanImage`] with the encoding of the freshly decoded one.
+/// As such, any modification that change the encoded file (including [`WanImage::compression`]) cause it to be written with the normal encoder.
+pub struct PreservedWanImage {
+    pub wan_image: WanImage,
+    original_bytes: Vec<u8>,
+    /// The encoding of the [`WanImage`] just after it was decoded. None if it couldn't be encoded.
+    reference_encoding: Option<Vec<u8>>,
+}
+
+impl PreservedWanImage {
+    /// Decode the wan file, and keep its content to write it back unchanged if the [`WanImage`] isn't modified.
+    pub fn decode(original_bytes: Vec<u8>) -> Result<Self, WanError> {
+        let wan_image = WanImage::decode_wan_from_bytes(&original_bytes)?;
+        let reference_encoding = wan_image.write_to_vec().ok();
+        Ok(Self {
+            wan_image,
+            original_bytes,
+            reference_encoding,
+        })
+    }
+
+    /// The bytes of the file this [`PreservedWanImage`] was decoded from
+    pub fn original_bytes(&self) -> &[u8] {
+        &self.original_bytes
+    }
+
+    /// Return true if the [`WanImage`] was modified in a way that change the encoded file since it was decoded. This need to encode it.
+    pub fn is_modified(&self) -> anyhow::Result<bool> {
+        Ok(self.reference_encoding.as_ref() != Some(&self.wan_image.write_to_vec()?))
+    }
+
+    /// Return the original bytes if the [`WanImage`] is unmodified, or the result of [`WanImage::write_to_vec`] otherwise.
+    pub fn write_to_vec(&self) -> anyhow::Result<Vec<u8>> {
+        let encoded = self.wan_image.write_to_vec()?;
+        if self.reference_encoding.as_ref() == Some(&encoded) {
+            Ok(self.original_bytes.clone())
+        } else {
+            Ok(encoded)
+        }
+    }
+
+    /// Write the file returned by [`PreservedWanImage::write_to_vec`]
+    pub fn write<W: Write>(&self, writer: &mut W) -> anyhow::Result<()> {
+        writer.write_all(&self.write_to_vec()?)?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> WanImage {
+        self.wan_image
+    }
+}
+
+impl WanImage {
+    /// Decode a wan file in preservation mode (see [`PreservedWanImage`])
+    pub fn decode_wan_preserving(bytes: Vec<u8>) -> Result<PreservedWanImage, WanError> {
+        PreservedWanImage::decode(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{animation_frame, test_wan_image},
+        Animation, SpriteType, WanImage,
+    };
+
+    #[test]
+    fn test_preservation_mode() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        insert_frame_in_wanimage(vec![1, 0, 0, 1], 2, 2, &mut wanimage, 0).unwrap();
+        wanimage.animation_store.anim_groups = vec![vec![Animation {
+            frames: vec![animation_frame(0, 1)],
+        }]];
+        // simulate a file with a layout the encoder doesn't reproduce
+        let mut original = wanimage.write_to_vec().unwrap();
+        original.extend([0xAA; 16]);
+
+        let mut preserved = WanImage::decode_wan_preserving(original.clone()).unwrap();
+        assert!(!preserved.is_modified().unwrap());
+        assert_eq!(preserved.write_to_vec().unwrap(), original);
+
+        preserved.wan_image.palette.palette[1] = [0, 255, 0, 128];
+        assert!(preserved.is_modified().unwrap());
+        let written = preserved.write_to_vec().unwrap();
+        assert_eq!(written, preserved.wan_image.write_to_vec().unwrap());
+        assert_eq!(
+            WanImage::decode_wan_from_bytes(&written).unwrap().palette,
+            preserved.wan_image.palette
+        );
+    }
+}
@@ -0,0 +1,99 @@
+/// A phase of a long operation, reported in a [`Progress`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressPhase {
+    /// Searching every 8×8 fragment of the images (see [`crate::find_fragments_in_images`]). Counted in images.
+    FindingFragments,
+    /// Cutting the images in 8×8 tiles aligned on the chosen grid, when creating a [`crate::WanImage`] from multiple images. Counted in images.
+    IndexingTiles,
+    /// Merging the tiles in bigger [`crate::Fragment`]s, and adding them to the [`crate::WanImage`]. Counted in groups of tiles used by the same images.
+    AssemblingFragments,
+    /// Compressing and writing the [`crate::FragmentBytes`] while encoding a [`crate::WanImage`]. Counted in [`crate::FragmentBytes`].
+    EncodingFragmentBytes,
+}
+
+/// How far a long operation went in one of its [`ProgressPhase`]s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub phase: ProgressPhase,
+    /// The number of items of the phase already processed
+    pub done: usize,
+    /// The number of items of the phase
+    pub total: usize,
+}
+
+impl Progress {
+    /// The proportion of the phase that has been processed, from 0 to 1. An empty phase is fully processed.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+
+    /// The percentage of the phase that has been processed, rounded down
+    pub fn percentage(&self) -> u8 {
+        (self.fraction() * 100.0) as u8
+    }
+}
+
+/// A function called with the [`Progress`] of a long operation, for example to display a progress bar.
+///
+/// It is called once at the start of each phase (with [`Progress::done`] at 0), then after each processed item.
+/// With the `rayon` feature, it may be called from multiple threads at once, so it should be quick, and refresh the display from another thread if needed (for example by sending the [`Progress`] in a [`std::sync::mpsc::Sender`]).
+pub type ProgressCallback<'a> = &'a (dyn Fn(Progress) + Sync);
+
+/// A [`ProgressCallback`] that does nothing
+pub(crate) fn no_progress(_: Progress) {}
+
+/// Report the start of `phase`, and return a function to call after each processed item, that may be shared between threads
+pub(crate) fn start_phase<'a>(
+    progress: ProgressCallback<'a>,
+    phase: ProgressPhase,
+    total: usize,
+) -> impl Fn() + Sync + 'a {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    progress(Progress {
+        phase,
+        done: 0,
+        total,
+    });
+    let done = AtomicUsize::new(0);
+    move || {
+        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+        progress(Progress { phase, done, total });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::start_phase;
+    use crate::{Progress, ProgressPhase};
+
+    #[test]
+    fn test_progress() {
+        let reported = Mutex::new(Vec::new());
+        let callback = |progress: Progress| reported.lock().unwrap().push(progress);
+        {
+            let step = start_phase(&callback, ProgressPhase::FindingFragments, 2);
+            step();
+            step();
+        }
+        let reported = reported.into_inner().unwrap();
+        assert_eq!(
+            reported.iter().map(|p| p.done).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(reported[1].percentage(), 50);
+        assert_eq!(reported[2].fraction(), 1.0);
+        let empty = Progress {
+            phase: ProgressPhase::IndexingTiles,
+            done: 0,
+            total: 0,
+        };
+        assert_eq!(empty.percentage(), 100);
+    }
+}
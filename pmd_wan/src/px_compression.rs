@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::Cursor;
+
+use thiserror::Error;
+
+use crate::{WanError, WanImage};
+
+/// The number of control flags stored in a PX header
+pub const PX_CONTROL_FLAGS_AMOUNT: usize = 9;
+const PX_MIN_MATCH_LENGTH: usize = 3;
+const PX_MAX_MATCH_LENGTH: usize = 15 + PX_MIN_MATCH_LENGTH;
+const PX_MAX_DISTANCE: usize = 0x1000;
+/// How many previous occurrences of a 3 bytes sequence are tried when searching for a back-reference
+const PX_MAX_CANDIDATES: usize = 64;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PxError {
+    #[error("the magic of the PX container is not reconized: found {0:?}")]
+    InvalidMagic([u8; 5]),
+    #[error("the PX container is too short to contain its header")]
+    TooShort,
+    #[error("the PX header indicate a container length of {0}, but it is {1} bytes long")]
+    ContainerLengthMismatch(usize, usize),
+    #[error("the compressed data ended before the {0} bytes of decompressed data were produced (only {1} were)")]
+    TruncatedData(usize, usize),
+    #[error(
+        "a back-reference at {0} point {1} bytes back, before the start of the decompressed data"
+    )]
+    ReferenceBeforeStart(usize, usize),
+    #[error("the data is too big to be stored in this PX container ({0} bytes)")]
+    TooBig(usize),
+}
+
+/// The two kind of PX container, that only differ by their header
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PxContainerKind {
+    /// Used for generic files, like the sprites in m_ground.bin and m_attack.bin
+    Pkdpx,
+    /// Used for images, with a decompressed size stored on 16 bits
+    At4px,
+}
+
+impl PxContainerKind {
+    pub fn magic(&self) -> &'static [u8; 5] {
+        match self {
+            Self::Pkdpx => b"PKDPX",
+            Self::At4px => b"AT4PX",
+        }
+    }
+
+    /// The length of the header, before the compressed data
+    pub fn header_length(&self) -> usize {
+        match self {
+            Self::Pkdpx => 20,
+            Self::At4px => 18,
+        }
+    }
+
+    fn max_decompressed_size(&self) -> usize {
+        match self {
+            Self::Pkdpx => u32::MAX as usize,
+            Self::At4px => u16::MAX as usize,
+        }
+    }
+}
+
+/// Decompress the content of a PKDPX or AT4PX container, returning the decompressed data
+pub fn decompress_px_container(data: &[u8]) -> Result<(PxContainerKind, Vec<u8>), PxError> {
+    let mut magic = [0; 5];
+    magic.copy_from_slice(data.get(0..5).ok_or(PxError::TooShort)?);
+    let kind = if &magic == PxContainerKind::Pkdpx.magic() {
+        PxContainerKind::Pkdpx
+    } else if &magic == PxContainerKind::At4px.magic() {
+        PxContainerKind::At4px
+    } else {
+        return Err(PxError::InvalidMagic(magic));
+    };
+    let header = data.get(..kind.header_length()).ok_or(PxError::TooShort)?;
+    let container_length = u16::from_le_bytes([header[5], header[6]]) as usize;
+    if container_length != data.len() {
+        return Err(PxError::ContainerLengthMismatch(
+            container_length,
+            data.len(),
+        ));
+    }
+    let mut control_flags = [0; PX_CONTROL_FLAGS_AMOUNT];
+    control_flags.copy_from_slice(&header[7..16]);
+    let decompressed_size = match kind {
+        PxContainerKind::Pkdpx => {
+            u32::from_le_bytes([header[16], header[17], header[18], header[19]]) as usize
+        }
+        PxContainerKind::At4px => u16::from_le_bytes([header[16], header[17]]) as usize,
+    };
+    let decompressed = px_decompress(
+        &data[kind.header_length()..],
+        &control_flags,
+        decompressed_size,
+    )?;
+    Ok((kind, decompressed))
+}
+
+/// Compress the data and wrap it in a PX container of the given kind
+pub fn compress_px_container(data: &[u8], kind: PxContainerKind) -> Result<Vec<u8>, PxError> {
+    if data.len() > kind.max_decompressed_size() {
+        return Err(PxError::TooBig(data.len()));
+    }
+    let (control_flags, compressed) = px_compress(data);
+    let container_length = kind.header_length() + compressed.len();
+    if container_length > u16::MAX as usize {
+        return Err(PxError::TooBig(container_length));
+    }
+    let mut result = Vec::with_capacity(container_length);
+    result.extend_from_slice(kind.magic());
+    result.extend_from_slice(&(container_length as u16).to_le_bytes());
+    result.extend_from_slice(&control_flags);
+    match kind {
+        PxContainerKind::Pkdpx => result.extend_from_slice(&(data.len() as u32).to_le_bytes()),
+        PxContainerKind::At4px => result.extend_from_slice(&(data.len() as u16).to_le_bytes()),
+    };
+    result.extend_from_slice(&compressed);
+    Ok(result)
+}
+
+/// Return the 4 nibbles a control flag at the given index produce for the given low nibble.
+/// Nibbles may be out of the 0..16 range, and are masked when decompressing.
+fn px_pattern(control_flag_index: usize, low_nibble: u8) -> [i8; 4] {
+    let low_nibble = low_nibble as i8;
+    if control_flag_index == 0 {
+        return [low_nibble; 4];
+    }
+    let base = match control_flag_index {
+        1 => low_nibble + 1,
+        5 => low_nibble - 1,
+        _ => low_nibble,
+    };
+    let mut nibbles = [base; 4];
+    if control_flag_index <= 4 {
+        nibbles[control_flag_index - 1] -= 1;
+    } else {
+        nibbles[control_flag_index - 5] += 1;
+    }
+    nibbles
+}
+
+/// Decompress raw PX compressed data (without header)
+pub fn px_decompress(
+    compressed: &[u8],
+    control_flags: &[u8; PX_CONTROL_FLAGS_AMOUNT],
+    decompressed_size: usize,
+) -> Result<Vec<u8>, PxError> {
+    let mut result = Vec::with_capacity(decompressed_size);
+    let mut input = compressed.iter().copied();
+    let truncated = |result: &Vec<u8>| PxError::TruncatedData(decompressed_size, result.len());
+    'outer: while result.len() < decompressed_size {
+        let command = input.next().ok_or_else(|| truncated(&result))?;
+        for bit in (0..8).rev() {
+            if result.len() >= decompressed_size {
+                break 'outer;
+            }
+            if command & (1 << bit) != 0 {
+                result.push(input.next().ok_or_else(|| truncated(&result))?);
+                continue;
+            }
+            let value = input.next().ok_or_else(|| truncated(&result))?;
+            let high_nibble = value >> 4;
+            let low_nibble = value & 0x0F;
+            if let Some(control_flag_index) =
+                control_flags.iter().position(|flag| *flag == high_nibble)
+            {
+                let nibbles = px_pattern(control_flag_index, low_nibble);
+                result.push(((nibbles[0] as u8 & 0x0F) << 4) | (nibbles[1] as u8 & 0x0F));
+                result.push(((nibbles[2] as u8 & 0x0F) << 4) | (nibbles[3] as u8 & 0x0F));
+            } else {
+                let next = input.next().ok_or_else(|| truncated(&result))?;
+                let distance = PX_MAX_DISTANCE - (((low_nibble as usize) << 8) | next as usize);
+                let start = result
+                    .len()
+                    .checked_sub(distance)
+                    .ok_or(PxError::ReferenceBeforeStart(result.len(), distance))?;
+                for position in start..start + high_nibble as usize + PX_MIN_MATCH_LENGTH {
+                    result.push(result[position]);
+                }
+            }
+        }
+    }
+    result.truncate(decompressed_size);
+    Ok(result)
+}
+
+enum PxOperation {
+    Literal(u8),
+    Pattern(usize, u8),
+    Reference { distance: usize, length: usize },
+}
+
+/// Search an encoding of the two bytes as a pattern, returning the index of the control flag and the low nibble
+fn find_px_pattern(bytes: &[u8]) -> Option<(usize, u8)> {
+    if bytes.len() < 2 {
+        return None;
+    }
+    let nibbles = [
+        (bytes[0] >> 4) as i8,
+        (bytes[0] & 0x0F) as i8,
+        (bytes[1] >> 4) as i8,
+        (bytes[1] & 0x0F) as i8,
+    ];
+    (0..PX_CONTROL_FLAGS_AMOUNT).find_map(|control_flag_index| {
+        (0..16).find_map(|low_nibble| {
+            if px_pattern(control_flag_index, low_nibble) == nibbles {
+                Some((control_flag_index, low_nibble))
+            } else {
+                None
+            }
+        })
+    })
+}
+
+/// Find the longest back-reference for the data at `position`, with a length whose nibble is allowed
+fn find_px_reference(
+    data: &[u8],
+    position: usize,
+    previous: &HashMap<[u8; 3], Vec<usize>>,
+    allowed_length: &[bool; 16],
+) -> Option<(usize, usize)> {
+    let key: [u8; 3] = data.get(position..position + 3)?.try_into().unwrap();
+    let max_length = (data.len() - position).min(PX_MAX_MATCH_LENGTH);
+    let mut best: Option<(usize, usize)> = None;
+    for candidate in previous.get(&key)?.iter().rev().take(PX_MAX_CANDIDATES) {
+        let distance = position - candidate;
+        if distance > PX_MAX_DISTANCE {
+            break;
+        }
+        let mut length = 0;
+        while length < max_length && data[candidate + length] == data[position + length] {
+            length += 1;
+        }
+        while length >= PX_MIN_MATCH_LENGTH && !allowed_length[length - PX_MIN_MATCH_LENGTH] {
+            length -= 1;
+        }
+        if length >= PX_MIN_MATCH_LENGTH && best.map(|(_, l)| length > l).unwrap_or(true) {
+            best = Some((distance, length));
+        }
+    }
+    best
+}
+
+fn px_parse(data: &[u8], allowed_length: &[bool; 16], use_patterns: bool) -> Vec<PxOperation> {
+    let mut operations = Vec::new();
+    let mut previous: HashMap<[u8; 3], Vec<usize>> = HashMap::new();
+    let mut position = 0;
+    let mut indexed_until = 0;
+    while position < data.len() {
+        let reference = find_px_reference(data, position, &previous, allowed_length);
+        let pattern = if use_patterns {
+            find_px_pattern(&data[position..])
+        } else {
+            None
+        };
+        let (operation, consumed) = match (reference, pattern) {
+            (Some((distance, length)), _) if length > PX_MIN_MATCH_LENGTH || pattern.is_none() => {
+                (PxOperation::Reference { distance, length }, length)
+            }
+            (_, Some((control_flag_index, low_nibble))) => {
+                (PxOperation::Pattern(control_flag_index, low_nibble), 2)
+            }
+            _ => (PxOperation::Literal(data[position]), 1),
+        };
+        operations.push(operation);
+        position += consumed;
+        while indexed_until < position {
+            if let Some(key) = data.get(indexed_until..indexed_until + 3) {
+                previous
+                    .entry(key.try_into().unwrap())
+                    .or_default()
+                    .push(indexed_until);
+            }
+            indexed_until += 1;
+        }
+    }
+    operations
+}
+
+/// Compress the data with the PX algorithm, returning the control flags and the compressed data (without header)
+pub fn px_compress(data: &[u8]) -> ([u8; PX_CONTROL_FLAGS_AMOUNT], Vec<u8>) {
+    // first pass, to find which length are the least useful, and use their nibble as control flags
+    let mut length_usage = [0usize; 16];
+    for operation in px_parse(data, &[true; 16], false) {
+        if let PxOperation::Reference { length, .. } = operation {
+            length_usage[length - PX_MIN_MATCH_LENGTH] += 1;
+        }
+    }
+    let mut nibbles_by_usage: Vec<u8> = (0..16).collect();
+    nibbles_by_usage.sort_by_key(|nibble| length_usage[*nibble as usize]);
+    let mut control_flags = [0; PX_CONTROL_FLAGS_AMOUNT];
+    control_flags.copy_from_slice(&nibbles_by_usage[..PX_CONTROL_FLAGS_AMOUNT]);
+    let mut allowed_length = [true; 16];
+    for flag in control_flags {
+        allowed_length[flag as usize] = false;
+    }
+
+    let mut result = Vec::new();
+    for chunk in px_parse(data, &allowed_length, true).chunks(8) {
+        let command_position = result.len();
+        result.push(0);
+        for (operation_nb, operation) in chunk.iter().enumerate() {
+            match operation {
+                PxOperation::Literal(value) => {
+                    result[command_position] |= 0x80 >> operation_nb;
+                    result.push(*value);
+                }
+                PxOperation::Pattern(control_flag_index, low_nibble) => {
+                    result.push((control_flags[*control_flag_index] << 4) | low_nibble);
+                }
+                PxOperation::Reference { distance, length } => {
+                    let encoded_distance = PX_MAX_DISTANCE - distance;
+                    result.push(
+                        (((length - PX_MIN_MATCH_LENGTH) as u8) << 4)
+                            | (encoded_distance >> 8) as u8,
+                    );
+                    result.push((encoded_distance & 0xFF) as u8);
+                }
+            }
+        }
+    }
+    (control_flags, result)
+}
+
+impl WanImage {
+    /// Decode a wan file stored in a PKDPX or AT4PX container, as in m_ground.bin or m_attack.bin
+    pub fn decode_px_wan(data: &[u8]) -> Result<WanImage, WanError> {
+        let (_, decompressed) = decompress_px_container(data)?;
+        WanImage::decode_wan(Cursor::new(decompressed))
+    }
+
+    /// Encode this [`WanImage`] and compress it in a PX container of the given kind
+    pub fn create_px_wan(&self, kind: PxContainerKind) -> anyhow::Result<Vec<u8>> {
+        let mut wan = Cursor::new(Vec::new());
+        self.create_wan(&mut wan)?;
+        Ok(compress_px_container(wan.get_ref(), kind)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compress_px_container, decompress_px_container, px_compress, px_decompress,
+        PxContainerKind, PxError,
+    };
+
+    fn check_roundtrip(data: &[u8]) {
+        let (control_flags, compressed) = px_compress(data);
+        assert_eq!(
+            px_decompress(&compressed, &control_flags, data.len()).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_px_roundtrip() {
+        check_roundtrip(&[]);
+        check_roundtrip(&[1, 2, 3]);
+        check_roundtrip(&[0x11; 100]);
+        check_roundtrip(&[0x12, 0x22, 0x33, 0x23, 0x45, 0x45, 0x45, 0x45, 0xF0]);
+        let pseudo_random: Vec<u8> = (0..5000u32)
+            .map(|x| (x.wrapping_mul(2_654_435_761) >> 13) as u8 % 7)
+            .collect();
+        check_roundtrip(&pseudo_random);
+    }
+
+    #[test]
+    fn test_px_compress_repetitive() {
+        let data = [0xAB, 0xCD, 0xEF].repeat(200);
+        let (_, compressed) = px_compress(&data);
+        assert!(compressed.len() < data.len() / 4);
+    }
+
+    #[test]
+    fn test_px_container() {
+        let data: Vec<u8> = (0..300u32).map(|x| (x % 17) as u8).collect();
+        for kind in [PxContainerKind::Pkdpx, PxContainerKind::At4px] {
+            let container = compress_px_container(&data, kind).unwrap();
+            assert_eq!(&container[0..5], kind.magic());
+            assert_eq!(
+                decompress_px_container(&container).unwrap(),
+                (kind, data.clone())
+            );
+        }
+        assert_eq!(
+            decompress_px_container(b"SIR0 and more"),
+            Err(PxError::InvalidMagic(*b"SIR0 "))
+        );
+    }
+
+    #[test]
+    fn test_px_known_data() {
+        // a literal, a pattern with the first control flag, and a back-reference of 4 bytes, 3 bytes back
+        let control_flags = [0xF, 0xE, 0xD, 0xC, 0xB, 0xA, 0x9, 0x8, 0x7];
+        let compressed = [0b1000_0000, 0x42, 0xF7, 0x1F, 0xFD];
+        assert_eq!(
+            px_decompress(&compressed, &control_flags, 7).unwrap(),
+            vec![0x42, 0x77, 0x77, 0x42, 0x77, 0x77, 0x42]
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+
+use image::RgbaImage;
+
+use crate::{
+    image_tool::TransparencyOptions, insert_frame_in_wanimage,
+    palette_from_image::rgba_to_palette_color, ColorDistance, WanImage,
+};
+
+/// How the error between the original color and the quantized one is spread to the neighbouring pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dithering {
+    /// Each pixel use the nearest color
+    None,
+    /// Floyd–Steinberg error diffusion
+    FloydSteinberg,
+    /// Ordered dithering with a 4×4 Bayer matrix. It keep flat areas stable between frames, where error diffusion may produce different patterns on each frame.
+    Ordered,
+}
+
+/// The thresholds of the ordered dithering, in sixteenths
+const BAYER_MATRIX: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Settings used by [`quantize_image`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuantizationOptions {
+    /// The maximum number of opaque colors. A row of a [`crate::Palette`] can store 15 of them.
+    ///
+    /// It is clamped to `1..=255` by [`quantize_image`], as the pixels are stored in a byte with 0 for transparency.
+    pub max_colors: usize,
+    pub dithering: Dithering,
+    /// The strength of the [`Dithering`], in percent. 100 is the usual amount, lower values reduce the noise at the cost of more banding.
+    pub dithering_strength: u8,
+    /// Which pixels are transparent, and left out of the palette
+    pub transparency: TransparencyOptions,
+    /// How the nearest color of each pixel is chosen
+    pub color_distance: ColorDistance,
+}
+
+impl Default for QuantizationOptions {
+    fn default() -> Self {
+        Self {
+            max_colors: 15,
+            dithering: Dithering::None,
+            dithering_strength: 100,
+            transparency: TransparencyOptions::default(),
+            color_distance: ColorDistance::Euclidean,
+        }
+    }
+}
+
+/// The result of [`quantize_image`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuantizedImage {
+    /// Line by line, from the top-left pixel. 0 is transparent, other values are the index of the color in [`QuantizedImage::colors`] plus one.
+    pub pixels: Vec<u8>,
+    /// The opaque RGB colors
+    pub colors: Vec<[u8; 3]>,
+}
+
+/// A box of the median-cut algorithm, with the colors it contain and their number of pixels
+struct ColorBox {
+    colors: Vec<([u8; 3], usize)>,
+}
+
+impl ColorBox {
+    /// Return the channel with the biggest range, and this range
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3)
+            .map(|channel| {
+                let min = self.colors.iter().map(|(color, _)| color[channel]).min();
+                let max = self.colors.iter().map(|(color, _)| color[channel]).max();
+                (channel, max.unwrap_or(0) - min.unwrap_or(0))
+            })
+            .max_by_key(|(_, range)| *range)
+            .unwrap_or((0, 0))
+    }
+
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        self.colors.sort_by_key(|(color, _)| color[channel]);
+        let total: usize = self.colors.iter().map(|(_, count)| count).sum();
+        let mut accumulated = 0;
+        let mut split_at = 1;
+        for (index, (_, count)) in self.colors.iter().enumerate() {
+            accumulated += count;
+            if accumulated * 2 >= total {
+                split_at = (index + 1).clamp(1, self.colors.len() - 1);
+                break;
+            }
+        }
+        let second = self.colors.split_off(split_at);
+        (self, ColorBox { colors: second })
+    }
+
+    fn average(&self) -> [u8; 3] {
+        let total: usize = self.colors.iter().map(|(_, count)| count).sum();
+        let mut sum = [0usize; 3];
+        for (color, count) in &self.colors {
+            for channel in 0..3 {
+                sum[channel] += color[channel] as usize * count;
+            }
+        }
+        let total = total.max(1);
+        [
+            ((sum[0] + total / 2) / total) as u8,
+            ((sum[1] + total / 2) / total) as u8,
+            ((sum[2] + total / 2) / total) as u8,
+        ]
+    }
+}
+
+fn nearest_color(colors: &[[u8; 3]], target: [i32; 3], color_distance: ColorDistance) -> usize {
+    if color_distance != ColorDistance::Euclidean {
+        let target = target.map(|component| component.clamp(0, 255) as u8);
+        return color_distance.nearest(colors, target).unwrap_or(0);
+    }
+    colors
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, color)| {
+            (0..3)
+                .map(|channel| (color[channel] as i32 - target[channel]).pow(2))
+                .sum::<i32>()
+        })
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
+
+/// Reduce the colors of the image with the median-cut algorithm.
+/// If the image already has few enough colors, they are kept exactly.
+pub fn quantize_image(image: &RgbaImage, options: &QuantizationOptions) -> QuantizedImage {
+    let max_colors = options.max_colors.clamp(1, 255);
+    let transparency = &options.transparency;
+    let mut histogram: HashMap<[u8; 3], usize> = HashMap::new();
+    for pixel in image.pixels() {
+        if !transparency.is_transparent(*pixel) {
+            *histogram
+                .entry([pixel.0[0], pixel.0[1], pixel.0[2]])
+                .or_default() += 1;
+        }
+    }
+    let mut histogram: Vec<([u8; 3], usize)> = histogram.into_iter().collect();
+    histogram.sort_unstable();
+
+    let colors: Vec<[u8; 3]> = if histogram.len() <= max_colors {
+        histogram.iter().map(|(color, _)| *color).collect()
+    } else {
+        let mut boxes = vec![ColorBox { colors: histogram }];
+        while boxes.len() < max_colors {
+            let to_split = boxes
+                .iter()
+                .enumerate()
+                .filter(|(_, color_box)| color_box.colors.len() > 1)
+                .max_by_key(|(_, color_box)| color_box.widest_channel().1)
+                .map(|(index, _)| index);
+            match to_split {
+                Some(index) => {
+                    let (first, second) = boxes.swap_remove(index).split();
+                    boxes.push(first);
+                    boxes.push(second);
+                }
+                None => break,
+            }
+        }
+        boxes.iter().map(|color_box| color_box.average()).collect()
+    };
+
+    let width = image.width() as usize;
+    let strength = options.dithering_strength as i32;
+    // roughly the distance between neighbouring colors, that the ordered dithering noise should cover
+    let ordered_spread = 255.0 / (colors.len().max(1) as f32).cbrt();
+    let mut errors = vec![[0i32; 3]; image.width() as usize * image.height() as usize];
+    let mut pixels = Vec::with_capacity(errors.len());
+    for (pixel_nb, pixel) in image.pixels().enumerate() {
+        if transparency.is_transparent(*pixel) || colors.is_empty() {
+            pixels.push(0);
+            continue;
+        }
+        let x = pixel_nb % width;
+        let mut target = [
+            pixel.0[0] as i32 + errors[pixel_nb][0],
+            pixel.0[1] as i32 + errors[pixel_nb][1],
+            pixel.0[2] as i32 + errors[pixel_nb][2],
+        ];
+        if options.dithering == Dithering::Ordered {
+            let threshold = BAYER_MATRIX[pixel_nb / width % 4][x % 4] as f32 / 16.0 + 1.0 / 32.0;
+            let offset = ((threshold - 0.5) * ordered_spread * strength as f32 / 100.0) as i32;
+            for component in &mut target {
+                *component += offset;
+            }
+        }
+        let index = nearest_color(&colors, target, options.color_distance);
+        pixels.push(index as u8 + 1);
+        if options.dithering == Dithering::FloydSteinberg {
+            let error: Vec<i32> = (0..3)
+                .map(|channel| target[channel] - colors[index][channel] as i32)
+                .collect();
+            let mut spread = |neighbour: Option<usize>, weight: i32| {
+                if let Some(neighbour) = neighbour.filter(|neighbour| *neighbour < errors.len()) {
+                    for channel in 0..3 {
+                        errors[neighbour][channel] += error[channel] * weight * strength / 1600;
+                    }
+                }
+            };
+            spread(Some(pixel_nb + 1).filter(|_| x + 1 < width), 7);
+            spread((pixel_nb + width).checked_sub(1).filter(|_| x > 0), 3);
+            spread(Some(pixel_nb + width), 5);
+            spread(Some(pixel_nb + width + 1).filter(|_| x + 1 < width), 1);
+        }
+    }
+
+    QuantizedImage { pixels, colors }
+}
+
+impl WanImage {
+    /// Quantize the image with [`quantize_image`], store the resulting colors in the row `pal_id` of the [`crate::Palette`] (overwriting it), and insert it as a new frame with [`insert_frame_in_wanimage`].
+    ///
+    /// At most 15 colors can fit in a row, so [`QuantizationOptions::max_colors`] is capped at that.
+    pub fn insert_quantized_frame(
+        &mut self,
+        image: &RgbaImage,
+        pal_id: u16,
+        options: &QuantizationOptions,
+    ) -> anyhow::Result<Option<usize>> {
+        let options = QuantizationOptions {
+            max_colors: options.max_colors.min(15),
+            ..*options
+        };
+        let quantized = quantize_image(image, &options);
+        let row_start = pal_id as usize * 16;
+        if self.palette.palette.len() < row_start + 16 {
+            self.palette.palette.resize(row_start + 16, [0, 0, 0, 0]);
+        }
+        for (index, color) in quantized.colors.iter().enumerate() {
+            self.palette.palette[row_start + 1 + index] =
+                rgba_to_palette_color([color[0], color[1], color[2], 255]);
+        }
+        insert_frame_in_wanimage(
+            quantized.pixels,
+            image.width() as u16,
+            image.height() as u16,
+            self,
+            pal_id,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{
+        image_tool::TransparencyOptions, quantize_image, ColorDistance, Dithering,
+        QuantizationOptions, SpriteType, WanImage,
+    };
+
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(32, 32, |x, y| {
+            Rgba([(x * 8) as u8, (y * 8) as u8, 100, 255])
+        })
+    }
+
+    #[test]
+    fn test_quantize_keep_few_colors() {
+        let mut image = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 255]));
+        image.put_pixel(1, 1, Rgba([0, 0, 0, 0]));
+        let quantized = quantize_image(&image, &QuantizationOptions::default());
+        assert_eq!(quantized.colors, vec![[1, 2, 3]]);
+        assert_eq!(quantized.pixels, vec![1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_quantize_max_colors_clamped() {
+        // 512 different colors
+        let image = RgbaImage::from_fn(32, 16, |x, y| Rgba([x as u8 * 8, y as u8 * 16, 0, 255]));
+        for (max_colors, expected) in [(0, 1), (1000, 255)] {
+            let quantized = quantize_image(
+                &image,
+                &QuantizationOptions {
+                    max_colors,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(quantized.colors.len(), expected);
+            assert!(quantized.pixels.iter().all(|pixel| *pixel != 0));
+        }
+    }
+
+    #[test]
+    fn test_quantize_transparency() {
+        let mut image = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 255]));
+        image.put_pixel(0, 0, Rgba([255, 0, 255, 255]));
+        image.put_pixel(1, 0, Rgba([1, 2, 3, 100]));
+        let quantized = quantize_image(
+            &image,
+            &QuantizationOptions {
+                transparency: TransparencyOptions {
+                    alpha_threshold: 50,
+                    color_key: Some([255, 0, 255]),
+                },
+                ..Default::default()
+            },
+        );
+        assert_eq!(quantized.colors, vec![[1, 2, 3]]);
+        assert_eq!(quantized.pixels, vec![0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_quantize_gradient() {
+        for dithering in [
+            Dithering::None,
+            Dithering::FloydSteinberg,
+            Dithering::Ordered,
+        ] {
+            for color_distance in [ColorDistance::Euclidean, ColorDistance::Ciede2000] {
+                let quantized = quantize_image(
+                    &gradient(),
+                    &QuantizationOptions {
+                        dithering,
+                        color_distance,
+                        ..Default::default()
+                    },
+                );
+                assert_eq!(quantized.colors.len(), 15);
+                assert!(quantized
+                    .pixels
+                    .iter()
+                    .all(|pixel| (1..=15).contains(pixel)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_dithering_strength() {
+        let gradient = RgbaImage::from_fn(64, 4, |x, _| Rgba([x as u8 * 4, 0, 0, 255]));
+        // true if the pixels of each line use the first color, then only the second one
+        let is_banded = |options: QuantizationOptions| {
+            let quantized = quantize_image(&gradient, &options);
+            assert_eq!(quantized.colors.len(), 2);
+            quantized.pixels.chunks_exact(64).all(|line| {
+                line.windows(2)
+                    .all(|pair| pair[0] == pair[1] || pair[1] != line[0])
+            })
+        };
+        let options = QuantizationOptions {
+            max_colors: 2,
+            ..Default::default()
+        };
+        assert!(is_banded(options));
+        for dithering in [Dithering::FloydSteinberg, Dithering::Ordered] {
+            assert!(!is_banded(QuantizationOptions {
+                dithering,
+                ..options
+            }));
+            assert!(is_banded(QuantizationOptions {
+                dithering,
+                dithering_strength: 0,
+                ..options
+            }));
+        }
+    }
+
+    #[test]
+    fn test_insert_quantized_frame() {
+        let mut wanimage = WanImage::new(SpriteType::PropsUI);
+        let frame_id = wanimage
+            .insert_quantized_frame(&gradient(), 1, &QuantizationOptions::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(wanimage.palette.palette.len(), 32);
+        assert!(wanimage.render_frame(frame_id).is_ok());
+    }
+}
@@ -0,0 +1,132 @@
+use thiserror::Error;
+
+use crate::{FrameRenderError, WanImage};
+
+#[derive(Error, Debug)]
+pub enum RecenterError {
+    #[error("The reference frame {0} is fully transparent")]
+    TransparentReference(usize),
+    #[error("Failed to compute the bounding box of the reference frame")]
+    CantComputeBoundingBox(#[from] FrameRenderError),
+}
+
+/// Settings used by [`WanImage::recenter_frames`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RecenterOptions {
+    /// The frame the position of the character is measured on, usually the first frame of the idle animation
+    pub reference_frame: usize,
+    /// Where the bottom of the opaque pixels of the reference frame (its feet) should be placed, relative to the origin. The default of 0 put the feet on the origin, where the shadow is drawn.
+    pub feet_y: i32,
+}
+
+/// The output of [`WanImage::recenter_frames`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RecenterReport {
+    /// The movement applied to the frames, as `(dx, dy)`
+    pub shift: (i32, i32),
+    /// The frames that would have had a [`crate::Fragment`] or a [`crate::FrameOffset`] point out of the range the format allows. If there is any, no frame is moved.
+    pub out_of_range_frames: Vec<usize>,
+}
+
+impl WanImage {
+    /// Move every frame by the same amount, so the opaque pixels of the reference frame are horizontally centered on the origin, with their bottom at [`RecenterOptions::feet_y`].
+    ///
+    /// The frames are all moved together, so the movements within an animation (like an attack lunge) are kept. The [`crate::FrameOffset`]s are moved with them. If a frame can't be moved, none are, and it is listed in [`RecenterReport::out_of_range_frames`].
+    pub fn recenter_frames(
+        &mut self,
+        options: &RecenterOptions,
+    ) -> Result<RecenterReport, RecenterError> {
+        let reference = self
+            .frame_bounding_box(options.reference_frame)?
+            .ok_or(RecenterError::TransparentReference(options.reference_frame))?;
+        let center_x = (reference.min_x + reference.max_x).div_euclid(2);
+        let shift = (-center_x, options.feet_y - reference.max_y);
+        let mut report = RecenterReport {
+            shift,
+            ..Default::default()
+        };
+        if shift == (0, 0) {
+            return Ok(report);
+        }
+        let mut translated_frames = Vec::with_capacity(self.frame_store.frames.len());
+        for (frame_id, frame) in self.frame_store.frames.iter().enumerate() {
+            let mut translated = frame.clone();
+            if translated.translate(shift.0, shift.1).is_err() {
+                report.out_of_range_frames.push(frame_id);
+            }
+            translated_frames.push(translated);
+        }
+        if report.out_of_range_frames.is_empty() {
+            self.frame_store.frames = translated_frames;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{insert_filled_frame, test_wan_image},
+        RecenterOptions, RecenterReport, SpriteType,
+    };
+
+    #[test]
+    fn test_recenter_frames() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        // only the 4 left columns of the 8 first lines are opaque
+        let pixels = (0..16 * 16)
+            .map(|pixel| (pixel % 16 < 4 && pixel / 16 < 8) as u8)
+            .collect();
+        insert_frame_in_wanimage(pixels, 16, 16, &mut wanimage, 0).unwrap();
+        insert_filled_frame(&mut wanimage, 8, 8);
+        let before = wanimage.frame_bounding_box(0).unwrap().unwrap();
+
+        let report = wanimage
+            .recenter_frames(&RecenterOptions::default())
+            .unwrap();
+        let after = wanimage.frame_bounding_box(0).unwrap().unwrap();
+        assert_eq!((after.min_x, after.max_x, after.max_y), (-2, 2, 0));
+        assert_eq!(after, before.translate(report.shift.0, report.shift.1));
+        assert!(report.out_of_range_frames.is_empty());
+        // the other frames are moved by the same amount
+        let second = wanimage.frame_bounding_box(1).unwrap().unwrap();
+        assert_eq!(
+            (second.min_x, second.max_y),
+            (-4 + report.shift.0, 4 + report.shift.1)
+        );
+
+        assert_eq!(
+            wanimage
+                .recenter_frames(&RecenterOptions::default())
+                .unwrap(),
+            RecenterReport::default()
+        );
+
+        let report = wanimage
+            .recenter_frames(&RecenterOptions {
+                feet_y: 200,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(report.out_of_range_frames, vec![0, 1]);
+        assert_eq!(wanimage.frame_bounding_box(0).unwrap().unwrap(), after);
+    }
+    #[test]
+    fn test_recenter_frames_all_or_none() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        insert_filled_frame(&mut wanimage, 8, 8);
+        insert_filled_frame(&mut wanimage, 8, 8);
+        // move the reference frame to the left, so it is shifted to the right, pushing the second frame too far
+        wanimage.frame_store.frames[0].fragments[0].offset_x = -100;
+        wanimage.frame_store.frames[1].fragments[0].offset_x = 200;
+        let frames_before = wanimage.frame_store.frames.clone();
+
+        let report = wanimage
+            .recenter_frames(&RecenterOptions::default())
+            .unwrap();
+        assert_eq!(report.shift.0, 96);
+        assert_eq!(report.out_of_range_frames, vec![1]);
+        assert_eq!(wanimage.frame_store.frames, frames_before);
+    }
+}
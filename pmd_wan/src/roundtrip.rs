@@ -0,0 +1,234 @@
+use std::io::Read;
+
+use thiserror::Error;
+
+use crate::{wan_diff, ChangeKind, WanError, WanImage};
+
+#[derive(Error, Debug)]
+pub enum RoundtripError {
+    #[error("Failed to decode the original wan file")]
+    CantDecodeOriginal(#[source] WanError),
+    #[error("Failed to encode the decoded wan image")]
+    CantEncode(#[source] anyhow::Error),
+    #[error("Failed to decode the re-encoded wan file")]
+    CantDecodeReencoded(#[source] WanError),
+}
+
+/// A semantic difference between a decoded [`WanImage`] and the result of re-encoding and decoding it again
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RoundtripDifference {
+    /// The sprite type, color depth or unknown header value differ
+    Header,
+    FragmentBytesCount {
+        original: usize,
+        reencoded: usize,
+    },
+    /// The pixels or the z index of the [`crate::FragmentBytes`] differ
+    FragmentBytes(usize),
+    FrameCount {
+        original: usize,
+        reencoded: usize,
+    },
+    /// The number of [`crate::Fragment`] or the [`crate::FrameOffset`] differ
+    Frame(usize),
+    Fragment {
+        frame: usize,
+        fragment: usize,
+    },
+    AnimationGroupCount {
+        original: usize,
+        reencoded: usize,
+    },
+    /// The number of [`crate::Animation`] in the group differ
+    AnimationGroup(usize),
+    Animation {
+        group: usize,
+        animation: usize,
+    },
+    PaletteLength {
+        original: usize,
+        reencoded: usize,
+    },
+    PaletteColor(usize),
+}
+
+/// The result of [`verify_roundtrip`]
+#[derive(Debug)]
+pub struct RoundtripReport {
+    /// The [`WanImage`] decoded from the original file
+    pub original: WanImage,
+    /// The size of the re-encoded file
+    pub reencoded_size: usize,
+    pub differences: Vec<RoundtripDifference>,
+}
+
+impl RoundtripReport {
+    /// true if decoding the re-encoded file gave back exactly the same [`WanImage`]
+    pub fn is_lossless(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// Decode a wan file, re-encode it, decode the result, and report every semantic difference between the two decoded [`WanImage`].
+///
+/// This check that the encoder doesn't lose information. Bit-for-bit identity of the files isn't checked, as the layout of the encoded file may change.
+pub fn verify_roundtrip<R: Read>(reader: R) -> Result<RoundtripReport, RoundtripError> {
+    let original =
+        WanImage::decode_wan_from_reader(reader).map_err(RoundtripError::CantDecodeOriginal)?;
+    let reencoded_bytes = original
+        .write_to_vec()
+        .map_err(RoundtripError::CantEncode)?;
+    let reencoded = WanImage::decode_wan_from_bytes(&reencoded_bytes)
+        .map_err(RoundtripError::CantDecodeReencoded)?;
+    let differences = compare_wan_images(&original, &reencoded);
+    Ok(RoundtripReport {
+        original,
+        reencoded_size: reencoded_bytes.len(),
+        differences,
+    })
+}
+
+/// Convert the [`crate::WanDiff`] of the two images to a list of [`RoundtripDifference`]
+pub(crate) fn compare_wan_images(
+    original: &WanImage,
+    reencoded: &WanImage,
+) -> Vec<RoundtripDifference> {
+    let diff = wan_diff(original, reencoded);
+    let mut differences = Vec::new();
+    if diff.header_changed {
+        differences.push(RoundtripDifference::Header);
+    }
+
+    let original_bytes = &original.fragment_bytes_store.fragment_bytes;
+    let reencoded_bytes = &reencoded.fragment_bytes_store.fragment_bytes;
+    if original_bytes.len() != reencoded_bytes.len() {
+        differences.push(RoundtripDifference::FragmentBytesCount {
+            original: original_bytes.len(),
+            reencoded: reencoded_bytes.len(),
+        });
+    }
+    for fragment_bytes in &diff.fragment_bytes {
+        if fragment_bytes.kind == ChangeKind::Changed {
+            differences.push(RoundtripDifference::FragmentBytes(fragment_bytes.index));
+        }
+    }
+
+    let original_frames = &original.frame_store.frames;
+    let reencoded_frames = &reencoded.frame_store.frames;
+    if original_frames.len() != reencoded_frames.len() {
+        differences.push(RoundtripDifference::FrameCount {
+            original: original_frames.len(),
+            reencoded: reencoded_frames.len(),
+        });
+    }
+    for frame in &diff.frames {
+        if frame.kind != ChangeKind::Changed {
+            continue;
+        }
+        if original_frames[frame.frame].fragments.len()
+            != reencoded_frames[frame.frame].fragments.len()
+            || frame.frame_offset_changed
+        {
+            differences.push(RoundtripDifference::Frame(frame.frame));
+        }
+        for fragment in &frame.fragments {
+            if fragment.kind == ChangeKind::Changed {
+                differences.push(RoundtripDifference::Fragment {
+                    frame: frame.frame,
+                    fragment: fragment.fragment,
+                });
+            }
+        }
+    }
+
+    let original_groups = &original.animation_store.anim_groups;
+    let reencoded_groups = &reencoded.animation_store.anim_groups;
+    if original_groups.len() != reencoded_groups.len() {
+        differences.push(RoundtripDifference::AnimationGroupCount {
+            original: original_groups.len(),
+            reencoded: reencoded_groups.len(),
+        });
+    }
+    for (group_nb, (a, b)) in original_groups.iter().zip(reencoded_groups).enumerate() {
+        if a.len() != b.len() {
+            differences.push(RoundtripDifference::AnimationGroup(group_nb));
+        }
+        for animation in &diff.animations {
+            if animation.group == group_nb && animation.kind == ChangeKind::Changed {
+                differences.push(RoundtripDifference::Animation {
+                    group: group_nb,
+                    animation: animation.animation,
+                });
+            }
+        }
+    }
+
+    let original_palette = &original.palette.palette;
+    let reencoded_palette = &reencoded.palette.palette;
+    if original_palette.len() != reencoded_palette.len() {
+        differences.push(RoundtripDifference::PaletteLength {
+            original: original_palette.len(),
+            reencoded: reencoded_palette.len(),
+        });
+    }
+    for color in &diff.palette {
+        if color.kind == ChangeKind::Changed {
+            differences.push(RoundtripDifference::PaletteColor(color.color));
+        }
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{animation_frame, test_wan_image},
+        verify_roundtrip, Animation, RoundtripDifference, SpriteType, WanImage,
+    };
+
+    use super::compare_wan_images;
+
+    fn create_wan_image() -> WanImage {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.resize(16, [0, 0, 0, 128]);
+        insert_frame_in_wanimage(vec![1, 0, 0, 1], 2, 2, &mut wanimage, 0).unwrap();
+        wanimage.animation_store.anim_groups = vec![vec![Animation {
+            frames: vec![animation_frame(0, 2)],
+        }]];
+        wanimage
+    }
+
+    #[test]
+    fn test_verify_roundtrip() {
+        let bytes = create_wan_image().write_to_vec().unwrap();
+        let report = verify_roundtrip(&bytes[..]).unwrap();
+        assert!(report.is_lossless(), "{:?}", report.differences);
+        assert_eq!(report.reencoded_size, bytes.len());
+    }
+
+    #[test]
+    fn test_compare_wan_images() {
+        let original = create_wan_image();
+        let mut modified = create_wan_image();
+        modified.palette.palette[1] = [0, 255, 0, 128];
+        modified.frame_store.frames[0].fragments[0].offset_x += 1;
+        modified.animation_store.anim_groups.push(Vec::new());
+        assert_eq!(
+            compare_wan_images(&original, &modified),
+            vec![
+                RoundtripDifference::Fragment {
+                    frame: 0,
+                    fragment: 0
+                },
+                RoundtripDifference::AnimationGroupCount {
+                    original: 1,
+                    reencoded: 2
+                },
+                RoundtripDifference::PaletteColor(1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::io::Seek;
+
+use crate::{span::SectionSpan, DecodeWarning, ErrorLocation, LocatedWanError, WanError};
+
+/// A part of a wan file, that is decoded separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WanSection {
+    /// The sir0 header, the wan header, and the animation and image data info blocks. Every other section is found with them.
+    Header,
+    Palette,
+    /// The frame reference table and the [`crate::Frame`]s with their [`crate::Fragment`]s
+    Frames,
+    /// The image data pointer table and the [`crate::FragmentBytes`]
+    FragmentBytes,
+    /// The animation group table and the [`crate::Animation`]s
+    Animations,
+    /// The [`crate::FrameOffset`]s of a [`crate::SpriteType::Chara`] sprite
+    FrameOffsets,
+}
+
+impl WanSection {
+    /// The name of this section, in lowercase
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Header => "header",
+            Self::Palette => "palette",
+            Self::Frames => "frames",
+            Self::FragmentBytes => "fragment bytes",
+            Self::Animations => "animations",
+            Self::FrameOffsets => "frame offsets",
+        }
+    }
+}
+
+impl fmt::Display for WanSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A section of a wan file that couldn't be read by [`crate::WanImage::salvage_wan`]
+#[derive(Debug)]
+pub struct SectionDamage {
+    pub section: WanSection,
+    /// The error that stopped the decoding of this section
+    pub error: WanError,
+    /// Where the error happened in the file
+    pub location: Option<ErrorLocation>,
+}
+
+/// What went wrong while decoding a file with [`crate::WanImage::salvage_wan`]
+#[derive(Debug, Default)]
+pub struct DamageReport {
+    /// The sections that couldn't be read, and have been left empty (or partially filled)
+    pub damaged_sections: Vec<SectionDamage>,
+    /// The items that have been skipped or repaired in the sections that could be read
+    pub warnings: Vec<DecodeWarning>,
+}
+
+impl DamageReport {
+    /// true if the file has been fully decoded, without any damage or repair
+    pub fn is_intact(&self) -> bool {
+        self.damaged_sections.is_empty() && self.warnings.is_empty()
+    }
+
+    /// true if the given section couldn't be read
+    pub fn is_damaged(&self, section: WanSection) -> bool {
+        self.damaged_sections
+            .iter()
+            .any(|damage| damage.section == section)
+    }
+}
+
+impl fmt::Display for DamageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_intact() {
+            return writeln!(f, "no damage found");
+        }
+        for damage in &self.damaged_sections {
+            writeln!(f, "damaged {} section: {}", damage.section, damage.error)?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "repaired: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode a section starting at `start` with `decode`, adding the location of the error if it fails, and logging it as a [`SectionSpan`]
+pub(crate) fn decode_section<F: Seek, T>(
+    file: &mut F,
+    section: WanSection,
+    start: u64,
+    decode: impl FnOnce(&mut F) -> Result<T, LocatedWanError>,
+) -> Result<T, LocatedWanError> {
+    let span = SectionSpan::enter("decode", section.name(), start);
+    let result = decode(file).map_err(|error| error.locate(section, None, start));
+    span.exit(
+        file.stream_position().unwrap_or(0),
+        format_args!("ok={}", result.is_ok()),
+    );
+    result
+}
+
+/// Decode a section like [`decode_section`].
+/// If it fails and `damaged_sections` is provided, the damage is recorded there and an empty section is returned instead of the error.
+pub(crate) fn salvage_section<F: Seek, T: Default>(
+    file: &mut F,
+    section: WanSection,
+    start: u64,
+    damaged_sections: &mut Option<&mut Vec<SectionDamage>>,
+    decode: impl FnOnce(&mut F) -> Result<T, LocatedWanError>,
+) -> Result<T, LocatedWanError> {
+    match (
+        decode_section(file, section, start, decode),
+        damaged_sections,
+    ) {
+        (Ok(value), _) => Ok(value),
+        (Err(error), Some(damaged_sections)) => {
+            trace!("the {} section is damaged: {}", section, error.error);
+            damaged_sections.push(SectionDamage {
+                section,
+                error: error.error,
+                location: error.location,
+            });
+            Ok(T::default())
+        }
+        (Err(error), None) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        tests::fixtures::{read_u32, single_frame_wan_bytes, TEST_COLOR},
+        WanImage, WanSection,
+    };
+
+    #[test]
+    fn test_salvage_intact() {
+        let bytes = single_frame_wan_bytes();
+        let (wanimage, report) = WanImage::salvage_wan(Cursor::new(&bytes));
+        assert!(report.is_intact());
+        assert_eq!(
+            wanimage,
+            Some(WanImage::decode_wan_from_bytes(&bytes).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_salvage_damaged_animations() {
+        let mut bytes = single_frame_wan_bytes();
+        let anim_info = read_u32(&bytes, read_u32(&bytes, 4));
+        let animation_table = read_u32(&bytes, anim_info + 8);
+        // an animation group way bigger than the file
+        bytes[animation_table + 4..animation_table + 8].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0]);
+        assert!(WanImage::decode_wan_from_bytes(&bytes).is_err());
+
+        let (wanimage, report) = WanImage::salvage_wan(Cursor::new(&bytes));
+        let wanimage = wanimage.unwrap();
+        assert_eq!(report.damaged_sections.len(), 1);
+        assert!(report.is_damaged(WanSection::Animations));
+        assert!(wanimage.animation_store.anim_groups.is_empty());
+        assert_eq!(wanimage.frame_store.frames.len(), 1);
+        assert_eq!(wanimage.palette.palette[1], TEST_COLOR);
+        assert!(wanimage.render_frame(0).is_ok());
+    }
+
+    #[test]
+    fn test_salvage_truncated() {
+        let bytes = single_frame_wan_bytes();
+        let (wanimage, report) = WanImage::salvage_wan(Cursor::new(&bytes[..bytes.len() / 2]));
+        assert!(report.is_damaged(WanSection::Header));
+        assert!(wanimage.is_none());
+        assert!(report.to_string().starts_with("damaged header section"));
+    }
+}
@@ -0,0 +1,237 @@
+use image::{ImageBuffer, Rgba, RgbaImage};
+
+use crate::{Animation, AnimationFrame, FrameRenderError, WanImage};
+
+/// The size (in pixel) of the side of a map tile
+pub const MAP_TILE_SIZE: i32 = 24;
+/// The width of a DS screen
+pub const SCREEN_WIDTH: u32 = 256;
+/// The height of a DS screen
+pub const SCREEN_HEIGHT: u32 = 192;
+
+/// The color used for parts of the screen that are outside of the background
+const OUT_OF_MAP_COLOR: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// The position of the top-left pixel of the screen on the map background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapCamera {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl MapCamera {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// Create a camera centered on the given tile, like the game does when following the player.
+    pub fn centered_on_tile(tile_x: i32, tile_y: i32) -> Self {
+        let (anchor_x, anchor_y) = tile_anchor(tile_x, tile_y);
+        Self {
+            x: anchor_x - SCREEN_WIDTH as i32 / 2,
+            y: anchor_y - SCREEN_HEIGHT as i32 / 2,
+        }
+    }
+
+    /// Transform a position on the map background to a position on the screen
+    pub fn map_to_screen(&self, x: i32, y: i32) -> (i32, i32) {
+        (x - self.x, y - self.y)
+    }
+}
+
+/// Return the position (in pixel, on the map background) where the origin of a sprite standing on the given tile is placed.
+/// That is the center of the tile.
+pub fn tile_anchor(tile_x: i32, tile_y: i32) -> (i32, i32) {
+    (
+        tile_x * MAP_TILE_SIZE + MAP_TILE_SIZE / 2,
+        tile_y * MAP_TILE_SIZE + MAP_TILE_SIZE / 2,
+    )
+}
+
+/// Render what the screen would display with the [`AnimationFrame`] drawn on the given tile of the map background.
+///
+/// The returned image has the resolution of a DS screen. The origin of the [`crate::Frame`] is placed at the [`tile_anchor`], shifted by the offset of the [`AnimationFrame`].
+pub fn compose_animation_frame_on_background(
+    wan_image: &WanImage,
+    animation_frame: &AnimationFrame,
+    background: &RgbaImage,
+    tile_x: i32,
+    tile_y: i32,
+    camera: MapCamera,
+) -> Result<RgbaImage, FrameRenderError> {
+    let frame = wan_image
+        .frame_store
+        .frames
+        .get(animation_frame.frame_id as usize)
+        .ok_or(FrameRenderError::NoFrame(animation_frame.frame_id as usize))?;
+
+    let mut screen = ImageBuffer::from_fn(SCREEN_WIDTH, SCREEN_HEIGHT, |x, y| {
+        let map_x = x as i32 + camera.x;
+        let map_y = y as i32 + camera.y;
+        if map_x < 0
+            || map_y < 0
+            || map_x >= background.width() as i32
+            || map_y >= background.height() as i32
+        {
+            OUT_OF_MAP_COLOR
+        } else {
+            *background.get_pixel(map_x as u32, map_y as u32)
+        }
+    });
+
+    let (anchor_x, anchor_y) = tile_anchor(tile_x, tile_y);
+    let (origin_x, origin_y) = camera.map_to_screen(
+        anchor_x + animation_frame.offset_x as i32,
+        anchor_y + animation_frame.offset_y as i32,
+    );
+    frame.draw_on_image_with_depth(
+        &wan_image.fragment_bytes_store,
+        &wan_image.palette,
+        wan_image.is_256_color,
+        &mut screen,
+        origin_x,
+        origin_y,
+    )?;
+    Ok(screen)
+}
+
+/// Render each [`AnimationFrame`] of the [`Animation`] with [`compose_animation_frame_on_background`].
+/// Each image is returned with the duration of the [`AnimationFrame`].
+pub fn compose_animation_on_background(
+    wan_image: &WanImage,
+    animation: &Animation,
+    background: &RgbaImage,
+    tile_x: i32,
+    tile_y: i32,
+    camera: MapCamera,
+) -> Result<Vec<(RgbaImage, u8)>, FrameRenderError> {
+    animation
+        .frames
+        .iter()
+        .map(|animation_frame| {
+            compose_animation_frame_on_background(
+                wan_image,
+                animation_frame,
+                background,
+                tile_x,
+                tile_y,
+                camera,
+            )
+            .map(|image| (image, animation_frame.duration))
+        })
+        .collect()
+}
+
+/// A [`crate::Frame`] of a [`WanImage`] to draw with [`render_scene`]
+#[derive(Debug, Clone, Copy)]
+pub struct SceneSprite<'a> {
+    pub wan_image: &'a WanImage,
+    pub frame_id: usize,
+    /// Position of the origin of the frame in the scene
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Draw multiple sprites (that may come from different [`WanImage`]s) into a single RGBA image.
+///
+/// Each sprite is resolved with its own [`crate::Palette`] before being drawn, so their colors never interfere. Sprites are drawn in order, so the last one is on top.
+pub fn render_scene(
+    sprites: &[SceneSprite],
+    width: u32,
+    height: u32,
+    background: Rgba<u8>,
+) -> Result<RgbaImage, FrameRenderError> {
+    let mut scene = ImageBuffer::from_pixel(width, height, background);
+    for sprite in sprites {
+        let frame = sprite
+            .wan_image
+            .frame_store
+            .frames
+            .get(sprite.frame_id)
+            .ok_or(FrameRenderError::NoFrame(sprite.frame_id))?;
+        frame.draw_on_image_with_depth(
+            &sprite.wan_image.fragment_bytes_store,
+            &sprite.wan_image.palette,
+            sprite.wan_image.is_256_color,
+            &mut scene,
+            sprite.x,
+            sprite.y,
+        )?;
+    }
+    Ok(scene)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Rgba};
+
+    use crate::{
+        compose_animation_frame_on_background, insert_frame_in_wanimage, render_scene,
+        tests::fixtures::{animation_frame, insert_filled_frame, test_wan_image},
+        tile_anchor, MapCamera, SceneSprite, SpriteType, WanImage,
+    };
+
+    #[test]
+    fn test_camera_centered_on_tile() {
+        let camera = MapCamera::centered_on_tile(10, 10);
+        let (anchor_x, anchor_y) = tile_anchor(10, 10);
+        assert_eq!(camera.map_to_screen(anchor_x, anchor_y), (128, 96));
+    }
+
+    #[test]
+    fn test_compose_on_background() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        let frame_id = insert_frame_in_wanimage(vec![1; 4], 2, 2, &mut wanimage, 0)
+            .unwrap()
+            .unwrap();
+        let background = ImageBuffer::from_pixel(48, 48, Rgba([0, 255, 0, 255]));
+        let animation_frame = animation_frame(frame_id as u16, 1);
+        let screen = compose_animation_frame_on_background(
+            &wanimage,
+            &animation_frame,
+            &background,
+            0,
+            0,
+            MapCamera::new(0, 0),
+        )
+        .unwrap();
+        // the 2×2 image is centered on the origin, itself at the center of the tile
+        assert_eq!(screen.get_pixel(11, 11), &Rgba([255, 0, 0, 255]));
+        assert_eq!(screen.get_pixel(12, 12), &Rgba([255, 0, 0, 255]));
+        assert_eq!(screen.get_pixel(13, 13), &Rgba([0, 255, 0, 255]));
+        assert_eq!(screen.get_pixel(100, 100), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_render_scene_with_independent_palettes() {
+        let mut red = test_wan_image(SpriteType::PropsUI);
+        insert_filled_frame(&mut red, 2, 2);
+        let mut blue = WanImage::new(SpriteType::PropsUI);
+        blue.palette.palette = vec![[0, 0, 0, 0], [0, 0, 255, 128]];
+        insert_filled_frame(&mut blue, 2, 2);
+
+        let scene = render_scene(
+            &[
+                SceneSprite {
+                    wan_image: &red,
+                    frame_id: 0,
+                    x: 1,
+                    y: 1,
+                },
+                SceneSprite {
+                    wan_image: &blue,
+                    frame_id: 0,
+                    x: 2,
+                    y: 2,
+                },
+            ],
+            4,
+            4,
+            Rgba([0, 0, 0, 0]),
+        )
+        .unwrap();
+        assert_eq!(scene.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(scene.get_pixel(1, 1), &Rgba([0, 0, 255, 255]));
+        assert_eq!(scene.get_pixel(3, 3), &Rgba([0, 0, 0, 0]));
+    }
+}
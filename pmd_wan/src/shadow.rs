@@ -0,0 +1,177 @@
+use image::{Rgba, RgbaImage};
+
+use crate::{image_tool::blend_over, Animation, FrameRenderError, WanImage};
+
+/// The color of the shadows drawn by [`ShadowSize::render`], a half transparent black
+pub const DEFAULT_SHADOW_COLOR: Rgba<u8> = Rgba([0, 0, 0, 128]);
+
+/// The size of the shadow drawn under a creature.
+///
+/// It isn't stored in the wan file, but in the `monster.md` entry of the creature, and as `ShadowSize` in the AnimData.xml of SpriteBot (see [`crate::SpriteBotExport::shadow_size`]), with the same ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl ShadowSize {
+    pub fn from_id(id: u8) -> Option<ShadowSize> {
+        Some(match id {
+            0 => Self::Small,
+            1 => Self::Medium,
+            2 => Self::Large,
+            _ => return None,
+        })
+    }
+
+    pub fn get_id(self) -> u8 {
+        match self {
+            Self::Small => 0,
+            Self::Medium => 1,
+            Self::Large => 2,
+        }
+    }
+
+    /// The width and height of the ellipse drawn by [`ShadowSize::render`]
+    pub fn dimensions(self) -> (u32, u32) {
+        match self {
+            Self::Small => (12, 6),
+            Self::Medium => (20, 8),
+            Self::Large => (28, 10),
+        }
+    }
+
+    /// Draw an ellipse of [`ShadowSize::dimensions`] in the given color, approximating the shadow of the game
+    pub fn render(self, color: Rgba<u8>) -> RgbaImage {
+        let (width, height) = self.dimensions();
+        let mut image = RgbaImage::new(width, height);
+        let radius_x = width as f32 / 2.0;
+        let radius_y = height as f32 / 2.0;
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let distance_x = (x as f32 + 0.5 - radius_x) / radius_x;
+            let distance_y = (y as f32 + 0.5 - radius_y) / radius_y;
+            if distance_x * distance_x + distance_y * distance_y <= 1.0 {
+                *pixel = color;
+            }
+        }
+        image
+    }
+}
+
+/// A shadow image drawn under the frames by [`Animation::render_frames_with_shadow`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowRender {
+    pub image: RgbaImage,
+    /// The position of the point placed at [`crate::AnimationFrame::shadow_offset_x`] and [`crate::AnimationFrame::shadow_offset_y`] in [`ShadowRender::image`]
+    pub center: (i32, i32),
+}
+
+impl ShadowRender {
+    /// The ellipse of [`ShadowSize::render`] in [`DEFAULT_SHADOW_COLOR`], centered on the shadow position
+    pub fn new(size: ShadowSize) -> Self {
+        let image = size.render(DEFAULT_SHADOW_COLOR);
+        let center = ((image.width() / 2) as i32, (image.height() / 2) as i32);
+        Self { image, center }
+    }
+
+    /// Use a frame of another sprite (like the one of the game `shadow.wan`) as the shadow, with the origin of the frame on the shadow position
+    pub fn from_frame(shadow_sprite: &WanImage, frame_id: usize) -> Result<Self, FrameRenderError> {
+        let image = shadow_sprite.render_frame(frame_id)?;
+        // no panic: render_frame checked the frame exist
+        let center = shadow_sprite.frame_store.frames[frame_id].render_origin();
+        Ok(Self { image, center })
+    }
+}
+
+impl Animation {
+    /// Like [`Animation::render_frames`], but draw the shadow under each frame, at the position given by [`crate::AnimationFrame::shadow_offset_x`] and [`crate::AnimationFrame::shadow_offset_y`], like in game.
+    ///
+    /// The images are big enough to contain the shadows too.
+    pub fn render_frames_with_shadow(
+        &self,
+        wan_image: &WanImage,
+        shadow: &ShadowRender,
+    ) -> Result<Vec<(RgbaImage, u8)>, FrameRenderError> {
+        let mut extent = self.extent(wan_image)?;
+        for animation_frame in &self.frames {
+            let start_x = animation_frame.shadow_offset_x as i32 - shadow.center.0;
+            let start_y = animation_frame.shadow_offset_y as i32 - shadow.center.1;
+            let shadow_extent = (
+                start_x,
+                start_y,
+                start_x + shadow.image.width() as i32,
+                start_y + shadow.image.height() as i32,
+            );
+            extent = Some(match extent {
+                None => shadow_extent,
+                Some(extent) => (
+                    extent.0.min(shadow_extent.0),
+                    extent.1.min(shadow_extent.1),
+                    extent.2.max(shadow_extent.2),
+                    extent.3.max(shadow_extent.3),
+                ),
+            });
+        }
+        let (min_x, min_y, max_x, max_y) = extent.unwrap_or((0, 0, 1, 1));
+        let mut result = Vec::with_capacity(self.frames.len());
+        for animation_frame in &self.frames {
+            let mut image = RgbaImage::new((max_x - min_x) as u32, (max_y - min_y) as u32);
+            blend_over(
+                &mut image,
+                &shadow.image,
+                animation_frame.shadow_offset_x as i32 - shadow.center.0 - min_x,
+                animation_frame.shadow_offset_y as i32 - shadow.center.1 - min_y,
+                1.0,
+            );
+            // no panic: the frame existence is checked when computing the extent
+            wan_image.frame_store.frames[animation_frame.frame_id as usize]
+                .draw_on_image_with_depth(
+                    &wan_image.fragment_bytes_store,
+                    &wan_image.palette,
+                    wan_image.is_256_color,
+                    &mut image,
+                    animation_frame.offset_x as i32 - min_x,
+                    animation_frame.offset_y as i32 - min_y,
+                )?;
+            result.push((image, animation_frame.duration));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use crate::{
+        tests::fixtures::{animation_frame, insert_filled_frame, test_wan_image},
+        Animation, AnimationFrame, ShadowRender, ShadowSize, SpriteType,
+    };
+
+    #[test]
+    fn test_render_with_shadow() {
+        assert_eq!(ShadowSize::from_id(2), Some(ShadowSize::Large));
+        assert_eq!(ShadowSize::from_id(3), None);
+        assert_eq!(ShadowSize::Medium.get_id(), 1);
+
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        insert_filled_frame(&mut wanimage, 2, 2);
+        let animation = Animation {
+            frames: vec![AnimationFrame {
+                shadow_offset_y: 8,
+                ..animation_frame(0, 1)
+            }],
+        };
+        let shadow = ShadowRender::new(ShadowSize::Small);
+        let frames = animation
+            .render_frames_with_shadow(&wanimage, &shadow)
+            .unwrap();
+        let (image, _) = &frames[0];
+        // the 8×8 fragment start at (-1, -1), the 12×6 shadow at (-6, 5)
+        assert_eq!(image.dimensions(), (13, 12));
+        assert_eq!(image.get_pixel(5, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(6, 9), &Rgba([0, 0, 0, 128]));
+        assert_eq!(image.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+    }
+}
@@ -18,12 +18,12 @@ impl ShirenAnimationFrame {
         let frame_id = reader.read_u16::<LittleEndian>()?;
         let mut unk2 = [0; 8];
         reader.read_exact(&mut unk2)?;
-        return Ok(Self {
+        Ok(Self {
             frame_duration,
             unk3,
             frame_id,
             unk2,
-        });
+        })
     }
 
     pub fn is_end_marker(&self) -> bool {
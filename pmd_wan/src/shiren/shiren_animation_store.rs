@@ -24,8 +24,8 @@ impl ShirenAnimationStore {
         for animation_pointer_pointer in animation_pointer_pointers {
             reader.seek(SeekFrom::Start(animation_pointer_pointer.into()))?;
             let mut animation_pointers = [0; 8];
-            for pointer_count in 0..8 {
-                animation_pointers[pointer_count] = reader.read_u32::<LittleEndian>()?;
+            for animation_pointer in &mut animation_pointers {
+                *animation_pointer = reader.read_u32::<LittleEndian>()?;
             }
 
             let mut counter = 0;
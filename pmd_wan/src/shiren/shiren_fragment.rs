@@ -21,29 +21,27 @@ impl ShirenFragment {
     pub fn new<T: Read>(reader: &mut T) -> Result<Option<Self>, WanError> {
         let fragment_bytes_id = reader.read_u16::<LE>()?;
         let unk1 = reader.read_u16::<LE>()?;
-        if fragment_bytes_id == 0xFFFF &&
-            unk1 == 0xFFFF {
-                return Ok(None)
-            }
-        let unk3;
-        if unk1 & 0x0080 == 0 || fragment_bytes_id == 0xFFFF {
-            unk3 = Some(reader.read_u16::<LE>()?);
-        } else {
-            unk3 = None;
+        if fragment_bytes_id == 0xFFFF && unk1 == 0xFFFF {
+            return Ok(None);
         }
+        let unk3 = if unk1 & 0x0080 == 0 || fragment_bytes_id == 0xFFFF {
+            Some(reader.read_u16::<LE>()?)
+        } else {
+            None
+        };
         let unk4 = reader.read_u16::<LE>()?;
         let unk5 = reader.read_u16::<LE>()?;
-        
+
         let is_h_flip = get_bit_u16(unk4, 3).unwrap();
-        let some_transformed_unk = unk4 & 0xe00 >> 9;
+        let _some_transformed_unk = unk4 & 0xe00 >> 9;
         //TODO: there’s probably a vertical flip too
         let size_indice = (unk4 >> 14) as u8;
         let shape_indice = unk3.map(|x| (x >> 14) as u8).unwrap_or(0);
         let oam_shape = if let Some(oam_shape) = OamShape::new(shape_indice, size_indice) {
-                oam_shape
-            } else {
-                return Err(WanError::InvalidResolutionIndice(shape_indice, size_indice));
-            };
+            oam_shape
+        } else {
+            return Err(WanError::InvalidResolutionIndice(shape_indice, size_indice));
+        };
         //TODO: size indice stuff
         Ok(Some(Self {
             fragment_bytes_id: if fragment_bytes_id == 0xFFFF {
@@ -56,7 +54,7 @@ impl ShirenFragment {
             unk4,
             is_h_flip,
             unk5,
-            oam_shape
+            oam_shape,
         }))
     }
 }
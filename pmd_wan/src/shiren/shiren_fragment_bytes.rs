@@ -14,7 +14,7 @@ struct ShirenAssemblyEntry {
 
 impl ShirenAssemblyEntry {
     pub fn is_empty(&self) -> bool {
-        return self.pointer_to_bytes == 0 && self.bytes_amount == 0;
+        self.pointer_to_bytes == 0 && self.bytes_amount == 0
     }
 }
 
@@ -42,7 +42,7 @@ impl ShirenFragmentBytes {
         for entry in assembly_table.iter() {
             if entry.pointer_to_bytes != 0 {
                 reader.seek(SeekFrom::Start(entry.pointer_to_bytes as u64))?;
-                reader.read(&mut bytes[position..position + entry.bytes_amount as usize])?;
+                reader.read_exact(&mut bytes[position..position + entry.bytes_amount as usize])?;
             }
             position += entry.bytes_amount as usize;
         }
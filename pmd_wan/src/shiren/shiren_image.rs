@@ -13,14 +13,14 @@ pub fn shiren_export_fragment(
     }
 
     let resolution = fragment.oam_shape.size();
-    let mut image = ImageBuffer::new(resolution.x as u32, resolution.y as u32);
+    let mut image = ImageBuffer::new(resolution.x, resolution.y);
 
     //TODO: error handling
     let mut iterator = fragment_bytes.bytes.iter().copied();
 
     fn transform_color(mut color: [u8; 4]) -> [u8; 4] {
-        color[3] = color[3].checked_mul(2).unwrap_or(255);
-        return color;
+        color[3] = color[3].saturating_mul(2);
+        color
     }
 
     'end: for chunk_y in 0..resolution.y / 8 {
@@ -33,19 +33,19 @@ pub fn shiren_export_fragment(
                         //TODO: this case is only for testing purpose. It should otherwise error out.
                         break 'end;
                     };
-                    let pixel_id_1 = ((byte & 0xF0) >> 4) + 0 * 16;
-                    let pixel_id_2 = (byte & 0x0F) + 0 * 16;
+                    let pixel_id_1 = (byte & 0xF0) >> 4;
+                    let pixel_id_2 = byte & 0x0F;
                     let x1 = chunk_x * 8 + x_nb * 2;
                     let y1 = chunk_y * 8 + y;
 
                     image.put_pixel(
-                        x1 as u32 + 1,
-                        y1 as u32,
+                        x1 + 1,
+                        y1,
                         Rgba::from(transform_color(palette.colors[pixel_id_1 as usize])),
                     );
                     image.put_pixel(
-                        x1 as u32,
-                        y1 as u32,
+                        x1,
+                        y1,
                         Rgba::from(transform_color(palette.colors[pixel_id_2 as usize])),
                     );
                 }
@@ -53,5 +53,5 @@ pub fn shiren_export_fragment(
         }
     }
 
-    return Ok(image);
+    Ok(image)
 }
@@ -14,10 +14,10 @@ pub struct ShirenPalette {
 impl ShirenPalette {
     pub fn new<T: Read + Seek>(reader: &mut T) -> Result<Self, WanError> {
         let mut colors = [[0; 4]; 192];
-        for color_key in 0..colors.len() {
-            colors[color_key] = reader.read_le()?;
+        for (color_key, color) in colors.iter_mut().enumerate() {
+            *color = reader.read_le()?;
             if color_key % 16 == 0 {
-                colors[color_key] = [0, 0, 0, 0];
+                *color = [0, 0, 0, 0];
             }
         }
         Ok(Self { colors })
@@ -42,17 +42,16 @@ impl ShirenWan {
         ): (u32, u32, u32, u32, u32, u32) = reader.read_le()?;
 
         // read fragment bytes store
-        let fragment_bytes_store;
-        if fragment_bytes_store_pointer != 0 {
+        let fragment_bytes_store = if fragment_bytes_store_pointer != 0 {
             if unk21 == 0 {
                 todo!();
             }
             let nb_fragments: usize = ((unk21 - fragment_bytes_store_pointer) / 4) as usize;
             reader.seek(SeekFrom::Start(fragment_bytes_store_pointer.into()))?;
-            fragment_bytes_store = ShirenFragmentBytesStore::new(reader, nb_fragments)?;
+            ShirenFragmentBytesStore::new(reader, nb_fragments)?
         } else {
-            fragment_bytes_store = ShirenFragmentBytesStore::default();
-        }
+            ShirenFragmentBytesStore::default()
+        };
 
         if frame_store_ptr == 0 || animation_store_ptr == 0 {
             todo!();
@@ -0,0 +1,268 @@
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use pmd_sir0::{write_sir0_footer, Sir0WriteFooterError};
+use thiserror::Error;
+
+use crate::{WanError, WanImage};
+
+const SIR0_MAGIC: [u8; 4] = [0x53, 0x49, 0x52, 0x30];
+/// The size of the SIR0 header, that is before the content of the container
+pub const SIR0_HEADER_SIZE: u32 = 16;
+
+#[derive(Error, Debug)]
+pub enum Sir0ContainerError {
+    #[error("an input/output error happened")]
+    IOError(#[from] io::Error),
+    #[error("the sir0 header in invalid, expected SIR0, found {0:?}")]
+    InvalidMagic([u8; 4]),
+    #[error("the end of the sir0 header should be four 0, found {0:?}")]
+    InvalidEndOfHeader([u8; 4]),
+    #[error("the pointer list is at {0}, which is inside the sir0 header")]
+    PointerListInHeader(u32),
+    #[error("the pointer list is at {0}, after the end of the file ({1})")]
+    PointerListAfterEndOfFile(u32, u64),
+    #[error("a pointer of the pointer list overflow")]
+    PointerOverflow,
+    #[error("the pointer list doesn't start with the two pointers of the sir0 header")]
+    MissingHeaderPointers,
+    #[error("the pointer at {0} is inside the sir0 header or after the content")]
+    PointerOutOfContent(u32),
+    #[error("failed to write the pointer list")]
+    WriteFooterError(#[from] Sir0WriteFooterError),
+}
+
+/// Read the 16 bytes SIR0 header at the current position, and return the offset of the wrapped file header and of the pointer list
+pub(crate) fn read_sir0_header<F: Read>(file: &mut F) -> Result<(u32, u32), WanError> {
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    if magic != SIR0_MAGIC {
+        return Err(WanError::InvalidSir0(magic));
+    };
+    let header_offset = file.read_u32::<LE>()?;
+    let pointer_list_offset = file.read_u32::<LE>()?;
+    let mut header_end = [0; 4];
+    file.read_exact(&mut header_end)?;
+    if header_end != [0, 0, 0, 0] {
+        return Err(WanError::InvalidEndOfSir0Header(header_end));
+    };
+    Ok((header_offset, pointer_list_offset))
+}
+
+/// A file wrapped in a SIR0 container, as the wan files stored in the ROM are.
+///
+/// Every offset is relative to the start of the SIR0 file (and so of the 16 bytes SIR0 header), like the pointers stored in the wrapped file.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Sir0Container {
+    /// The wrapped file, that is everything between the SIR0 header and the pointer list
+    pub content: Vec<u8>,
+    /// Offset of the header of the wrapped file
+    pub header_offset: u32,
+    /// Offsets of all the pointers in the wrapped file, sorted. Doesn't include the two pointers of the SIR0 header.
+    pub pointers: Vec<u32>,
+}
+
+impl Sir0Container {
+    /// Parse a SIR0 container, decoding its pointer list
+    pub fn read<F: Read + Seek>(mut file: F) -> Result<Self, Sir0ContainerError> {
+        let file_lenght = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        if magic != SIR0_MAGIC {
+            return Err(Sir0ContainerError::InvalidMagic(magic));
+        }
+        let header_offset = file.read_u32::<LE>()?;
+        let pointer_list_offset = file.read_u32::<LE>()?;
+        let mut header_end = [0; 4];
+        file.read_exact(&mut header_end)?;
+        if header_end != [0, 0, 0, 0] {
+            return Err(Sir0ContainerError::InvalidEndOfHeader(header_end));
+        }
+        if pointer_list_offset < SIR0_HEADER_SIZE {
+            return Err(Sir0ContainerError::PointerListInHeader(pointer_list_offset));
+        }
+        if pointer_list_offset as u64 > file_lenght {
+            return Err(Sir0ContainerError::PointerListAfterEndOfFile(
+                pointer_list_offset,
+                file_lenght,
+            ));
+        }
+
+        let mut content = vec![0; (pointer_list_offset - SIR0_HEADER_SIZE) as usize];
+        file.read_exact(&mut content)?;
+
+        let mut pointers = Vec::new();
+        let mut absolute_position: u32 = 0;
+        let mut constructed_pointer: u32 = 0;
+        // the list end with a 0, but some files end without it
+        loop {
+            let current = match file.read_u8() {
+                Ok(current) => current,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            };
+            if current == 0 && constructed_pointer == 0 {
+                break;
+            }
+            constructed_pointer = constructed_pointer
+                .checked_mul(128)
+                .ok_or(Sir0ContainerError::PointerOverflow)?
+                | (current & 0x7F) as u32;
+            if current & 0x80 == 0 {
+                absolute_position = absolute_position
+                    .checked_add(constructed_pointer)
+                    .ok_or(Sir0ContainerError::PointerOverflow)?;
+                pointers.push(absolute_position);
+                constructed_pointer = 0;
+            }
+        }
+
+        if pointers.get(0..2) != Some(&[4, 8]) {
+            return Err(Sir0ContainerError::MissingHeaderPointers);
+        }
+        pointers.drain(0..2);
+
+        Ok(Self {
+            content,
+            header_offset,
+            pointers,
+        })
+    }
+
+    /// Write the SIR0 container, rebuilding the pointer list from [`Sir0Container::pointers`].
+    ///
+    /// The content is padded to 16 bytes before the pointer list, and the file is padded to 16 bytes after it, like the game files are.
+    pub fn write<F: Write>(&self, file: &mut F) -> Result<(), Sir0ContainerError> {
+        let mut pointers = self.pointers.clone();
+        pointers.sort_unstable();
+        let content_end = SIR0_HEADER_SIZE as usize + self.content.len();
+        for pointer in &pointers {
+            if *pointer < SIR0_HEADER_SIZE || *pointer as usize + 4 > content_end {
+                return Err(Sir0ContainerError::PointerOutOfContent(*pointer));
+            }
+        }
+        let padding_before_list = (16 - content_end % 16) % 16;
+        let pointer_list_offset = (content_end + padding_before_list) as u32;
+
+        file.write_all(&SIR0_MAGIC)?;
+        file.write_u32::<LE>(self.header_offset)?;
+        file.write_u32::<LE>(pointer_list_offset)?;
+        file.write_all(&[0; 4])?;
+        file.write_all(&self.content)?;
+        file.write_all(&vec![0xAA; padding_before_list])?;
+
+        let mut footer = Vec::new();
+        let mut full_list = vec![4, 8];
+        full_list.extend(pointers);
+        write_sir0_footer(&mut footer, &full_list)?;
+        footer.push(0);
+        while !(pointer_list_offset as usize + footer.len()).is_multiple_of(16) {
+            footer.push(0xAA);
+        }
+        file.write_all(&footer)?;
+        Ok(())
+    }
+
+    /// Return the complete SIR0 file
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Sir0ContainerError> {
+        let mut result = Vec::new();
+        self.write(&mut result)?;
+        Ok(result)
+    }
+}
+
+impl WanImage {
+    /// Decode a [`WanImage`] stored in an already parsed [`Sir0Container`]
+    pub fn decode_wan_from_sir0(sir0: &Sir0Container) -> Result<WanImage, WanError> {
+        WanImage::decode_wan(Cursor::new(sir0.to_bytes()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+    use crate::{
+        insert_frame_in_wanimage, tests::fixtures::animation_frame, Animation, Sir0Container,
+        Sir0ContainerError, SpriteType, WanImage,
+    };
+
+    /// A reader that fail with an [`io::ErrorKind::Other`] error once `fail_at` bytes have been read
+    struct FailingReader {
+        inner: Cursor<Vec<u8>>,
+        fail_at: u64,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.inner.position() >= self.fail_at {
+                return Err(io::Error::other("broken reader"));
+            }
+            let max = (self.fail_at - self.inner.position()) as usize;
+            let len = buf.len().min(max);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    impl Seek for FailingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_sir0_roundtrip() {
+        let mut content = vec![0; 32];
+        content[0..4].copy_from_slice(&24u32.to_le_bytes());
+        let sir0 = Sir0Container {
+            content,
+            header_offset: 16,
+            pointers: vec![16, 24],
+        };
+        let bytes = sir0.to_bytes().unwrap();
+        assert_eq!(bytes.len() % 16, 0);
+        assert_eq!(Sir0Container::read(Cursor::new(&bytes)).unwrap(), sir0);
+
+        // a file without the final 0 of the pointer list, made of the 4 one-byte deltas 4, 4, 8, 8
+        let pointer_list_offset = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        let truncated = bytes[..pointer_list_offset as usize + 4].to_vec();
+        assert_eq!(Sir0Container::read(Cursor::new(truncated)).unwrap(), sir0);
+
+        // other errors aren't mistaken for the end of the file
+        let failing = FailingReader {
+            inner: Cursor::new(bytes),
+            fail_at: pointer_list_offset as u64 + 1,
+        };
+        assert!(matches!(
+            Sir0Container::read(failing),
+            Err(Sir0ContainerError::IOError(_))
+        ));
+    }
+
+    #[test]
+    fn test_wan_in_sir0() {
+        let mut wanimage = WanImage::new(SpriteType::PropsUI);
+        wanimage.palette.palette = vec![[0, 0, 0, 0], [255, 255, 255, 128]];
+        insert_frame_in_wanimage(vec![1, 0, 0, 1], 2, 2, &mut wanimage, 0).unwrap();
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![animation_frame(0, 1)],
+        }]);
+
+        let mut original = Cursor::new(Vec::new());
+        wanimage.create_wan(&mut original).unwrap();
+        let sir0 = Sir0Container::read(&mut original).unwrap();
+        // rebuilding the pointer list give back the exact same file
+        assert_eq!(sir0.to_bytes().unwrap(), original.get_ref().as_slice());
+        assert_eq!(
+            wanimage.create_sir0().unwrap().to_bytes().unwrap(),
+            original.into_inner()
+        );
+        let decoded = WanImage::decode_wan_from_sir0(&sir0).unwrap();
+        assert_eq!(decoded.frame_store, wanimage.frame_store);
+        assert_eq!(
+            decoded.animation_store.anim_groups,
+            wanimage.animation_store.anim_groups
+        );
+    }
+}
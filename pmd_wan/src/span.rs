@@ -0,0 +1,108 @@
+use std::fmt;
+
+#[cfg(not(feature = "tracing"))]
+use log::Level;
+
+use crate::clock::Stopwatch;
+
+/// The target of the spans of each section of a decoding or an encoding, at the debug level.
+///
+/// With the `tracing` feature, they are `tracing` spans named `section`, with the `operation`, the `section` and its start `offset` in the file. The `end` offset, the `size` in bytes, the time spent on it as `elapsed_us` and the other details are recorded when the section is exited.
+///
+/// Otherwise, they are [`log`] messages emitted at the start and the end of the section, formatted as `key=value` pairs with the same fields and the time spent on it, so they can be filtered and parsed to profile each section.
+/// On `wasm32-unknown-unknown`, where there is no clock, the time is `elapsed_us=unknown`.
+pub const SPAN_LOG_TARGET: &str = "pmd_wan::span";
+
+/// A section of a decoding or an encoding, traced under [`SPAN_LOG_TARGET`] while it is entered
+pub(crate) struct SectionSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(not(feature = "tracing"))]
+    operation: &'static str,
+    #[cfg(not(feature = "tracing"))]
+    section: &'static str,
+    start_offset: u64,
+    /// Only measured when the span is enabled
+    start: Option<Stopwatch>,
+}
+
+impl SectionSpan {
+    #[cfg(feature = "tracing")]
+    pub(crate) fn enter(operation: &'static str, section: &'static str, offset: u64) -> Self {
+        let span = tracing::debug_span!(
+            target: SPAN_LOG_TARGET,
+            "section",
+            operation,
+            section,
+            offset,
+            end = tracing::field::Empty,
+            size = tracing::field::Empty,
+            elapsed_us = tracing::field::Empty,
+            details = tracing::field::Empty,
+        );
+        Self {
+            start: if span.is_disabled() {
+                None
+            } else {
+                Some(Stopwatch::start())
+            },
+            span: span.entered(),
+            start_offset: offset,
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn enter(operation: &'static str, section: &'static str, offset: u64) -> Self {
+        let enabled = log_enabled!(target: SPAN_LOG_TARGET, Level::Debug);
+        if enabled {
+            debug!(
+                target: SPAN_LOG_TARGET,
+                "enter operation={} section={} offset=0x{:X}", operation, section, offset
+            );
+        }
+        Self {
+            operation,
+            section,
+            start_offset: offset,
+            start: if enabled {
+                Some(Stopwatch::start())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Record the offset the file ended at and the additional details, then exit the span
+    #[cfg(feature = "tracing")]
+    pub(crate) fn exit(self, end_offset: u64, details: fmt::Arguments) {
+        self.span.record("end", end_offset);
+        self.span
+            .record("size", end_offset.saturating_sub(self.start_offset));
+        self.span
+            .record("details", tracing::field::display(details));
+        if let Some(elapsed) = self.start.and_then(|start| start.elapsed()) {
+            self.span.record("elapsed_us", elapsed.as_micros() as u64);
+        }
+    }
+
+    /// Log the end of the section, with the offset the file ended at, and additional `key=value` pairs
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn exit(self, end_offset: u64, details: fmt::Arguments) {
+        if let Some(start) = self.start {
+            debug!(
+                target: SPAN_LOG_TARGET,
+                "exit operation={} section={} offset=0x{:X} end=0x{:X} size={} elapsed_us={} {}",
+                self.operation,
+                self.section,
+                self.start_offset,
+                end_offset,
+                end_offset.saturating_sub(self.start_offset),
+                start
+                    .elapsed()
+                    .map(|elapsed| elapsed.as_micros().to_string())
+                    .unwrap_or_else(|| "unknown".to_string()),
+                details
+            );
+        }
+    }
+}
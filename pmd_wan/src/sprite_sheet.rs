@@ -0,0 +1,285 @@
+use anyhow::Context;
+use image::{imageops::crop_imm, RgbaImage};
+use thiserror::Error;
+
+use crate::{
+    create_wan_from_multiple_images,
+    image_tool::{
+        image_to_paletted_bytes_with_transparency, ImageToPaletteBytesData, TransparencyOptions,
+    },
+    GeneralResolution, SpriteType, WanImage,
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SpriteSheetError {
+    #[error("The sprite sheet doesn't contain any opaque pixel")]
+    NoOpaquePixel,
+    #[error("The cells can't have a size of 0")]
+    EmptyCell,
+    #[error("The sprite sheet is {0} pixels long, which can't be split in {1} cells")]
+    CountDoesNotDivide(u32, u32),
+}
+
+/// The layout of a sprite sheet made of a grid of cells of the same size, all in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteSheetGrid {
+    pub cell_width: u32,
+    pub cell_height: u32,
+    /// The space before the first column
+    pub margin_x: u32,
+    /// The space before the first row
+    pub margin_y: u32,
+    /// The space between two columns
+    pub spacing_x: u32,
+    /// The space between two rows
+    pub spacing_y: u32,
+}
+
+/// Return the smallest length that split `occupied` in parts such that no run of occupied values cross two parts, and the empty parts are only at the end
+fn detect_cell_length(occupied: &[bool]) -> u32 {
+    let len = occupied.len();
+    (1..=len)
+        .filter(|cell_length| len.is_multiple_of(*cell_length))
+        .find(|cell_length| {
+            let crossed = (*cell_length..len)
+                .step_by(*cell_length)
+                .any(|boundary| occupied[boundary - 1] && occupied[boundary]);
+            let mut cells_occupied = occupied
+                .chunks(*cell_length)
+                .map(|cell| cell.iter().any(|occupied| *occupied));
+            // the empty cells are only at the end if all the following ones are empty too
+            let ordered = cells_occupied.by_ref().any(|occupied| !occupied)
+                && cells_occupied.any(|occupied| occupied);
+            !crossed && !ordered
+        })
+        // the whole length always match
+        .unwrap_or(len) as u32
+}
+
+impl SpriteSheetGrid {
+    /// A grid of `columns` × `rows` cells covering the whole sheet, without margin nor spacing
+    pub fn from_counts(
+        sheet_width: u32,
+        sheet_height: u32,
+        columns: u32,
+        rows: u32,
+    ) -> Result<Self, SpriteSheetError> {
+        if columns == 0 || rows == 0 {
+            return Err(SpriteSheetError::EmptyCell);
+        }
+        if !sheet_width.is_multiple_of(columns) {
+            return Err(SpriteSheetError::CountDoesNotDivide(sheet_width, columns));
+        }
+        if !sheet_height.is_multiple_of(rows) {
+            return Err(SpriteSheetError::CountDoesNotDivide(sheet_height, rows));
+        }
+        if sheet_width < columns || sheet_height < rows {
+            return Err(SpriteSheetError::EmptyCell);
+        }
+        Ok(Self {
+            cell_width: sheet_width / columns,
+            cell_height: sheet_height / rows,
+            margin_x: 0,
+            margin_y: 0,
+            spacing_x: 0,
+            spacing_y: 0,
+        })
+    }
+
+    /// Guess the size of the cells of a sheet without margin nor spacing, with the smallest cells that don't cut a sprite.
+    ///
+    /// This need the sprites to be separated by fully transparent columns and lines, as the border of the cells is placed between them. Empty cells are only allowed at the end of the lines and columns.
+    /// If the sprites touch each other, the whole sheet end up in a single cell: use [`SpriteSheetGrid::from_counts`] instead.
+    pub fn detect(
+        sheet: &RgbaImage,
+        transparency: &TransparencyOptions,
+    ) -> Result<Self, SpriteSheetError> {
+        let mut occupied_columns = vec![false; sheet.width() as usize];
+        let mut occupied_lines = vec![false; sheet.height() as usize];
+        for (x, y, pixel) in sheet.enumerate_pixels() {
+            if !transparency.is_transparent(*pixel) {
+                occupied_columns[x as usize] = true;
+                occupied_lines[y as usize] = true;
+            }
+        }
+        if !occupied_columns.contains(&true) {
+            return Err(SpriteSheetError::NoOpaquePixel);
+        }
+        Ok(Self {
+            cell_width: detect_cell_length(&occupied_columns),
+            cell_height: detect_cell_length(&occupied_lines),
+            margin_x: 0,
+            margin_y: 0,
+            spacing_x: 0,
+            spacing_y: 0,
+        })
+    }
+
+    /// The number of full columns of cells in a sheet of the given width
+    pub fn columns(&self, sheet_width: u32) -> u32 {
+        Self::count(sheet_width, self.margin_x, self.cell_width, self.spacing_x)
+    }
+
+    /// The number of full rows of cells in a sheet of the given height
+    pub fn rows(&self, sheet_height: u32) -> u32 {
+        Self::count(
+            sheet_height,
+            self.margin_y,
+            self.cell_height,
+            self.spacing_y,
+        )
+    }
+
+    fn count(length: u32, margin: u32, cell: u32, spacing: u32) -> u32 {
+        if cell == 0 || length < margin + cell {
+            return 0;
+        }
+        (length - margin - cell) / (cell + spacing) + 1
+    }
+
+    /// The position of the top-left pixel of a cell in the sheet
+    pub fn cell_position(&self, column: u32, row: u32) -> (u32, u32) {
+        (
+            self.margin_x + column * (self.cell_width + self.spacing_x),
+            self.margin_y + row * (self.cell_height + self.spacing_y),
+        )
+    }
+
+    /// Cut the full cells of the sheet, line by line from the top-left one
+    pub fn split(&self, sheet: &RgbaImage) -> Vec<RgbaImage> {
+        let mut cells = Vec::new();
+        for row in 0..self.rows(sheet.height()) {
+            for column in 0..self.columns(sheet.width()) {
+                let (x, y) = self.cell_position(column, row);
+                cells.push(crop_imm(sheet, x, y, self.cell_width, self.cell_height).to_image());
+            }
+        }
+        cells
+    }
+}
+
+/// Create a [`WanImage`] with [`create_wan_from_multiple_images`], with one frame per cell of the sheet that isn't fully transparent, in the order of [`SpriteSheetGrid::split`].
+///
+/// The sheet should use at most 15 opaque colors, that become the single row of the [`crate::Palette`].
+pub fn create_wan_from_sprite_sheet(
+    sheet: &RgbaImage,
+    grid: &SpriteSheetGrid,
+    sprite_type: SpriteType,
+    transparency: &TransparencyOptions,
+) -> anyhow::Result<WanImage> {
+    let mut palette_data = ImageToPaletteBytesData::default();
+    let mut images = Vec::new();
+    for (cell_id, cell) in grid.split(sheet).iter().enumerate() {
+        let pixels =
+            image_to_paletted_bytes_with_transparency(&mut palette_data, cell, transparency)
+                .filter(|_| palette_data.ordered.len() <= 16)
+                .with_context(|| {
+                    format!(
+                    "The sprite sheet use more than 15 colors, the last one being in the cell {}",
+                    cell_id
+                )
+                })?;
+        if pixels.iter().any(|pixel| *pixel != 0) {
+            images.push((
+                pixels,
+                GeneralResolution::new(grid.cell_width, grid.cell_height),
+            ));
+        }
+    }
+    let images: Vec<(&[u8], GeneralResolution)> = images
+        .iter()
+        .map(|(pixels, resolution)| (pixels.as_slice(), resolution.clone()))
+        .collect();
+    let mut wanimage = create_wan_from_multiple_images(&images, sprite_type)?;
+    palette_data.ordered.resize(16, [0, 0, 0, 0]);
+    wanimage.palette.palette = palette_data
+        .ordered
+        .iter()
+        .map(|color| [color[0], color[1], color[2], color[3].div_ceil(2)])
+        .collect();
+    Ok(wanimage)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::detect_cell_length;
+    use crate::{
+        create_wan_from_sprite_sheet, image_tool::TransparencyOptions, SpriteSheetError,
+        SpriteSheetGrid, SpriteType,
+    };
+
+    /// A sheet of 3×2 cells of 20×16 pixels, with a sprite of 8×8 in the middle of each cell except the last one
+    fn test_sheet() -> RgbaImage {
+        RgbaImage::from_fn(60, 32, |x, y| {
+            let (column, row) = (x / 20, y / 16);
+            let inside = (6..14).contains(&(x % 20)) && (4..12).contains(&(y % 16));
+            if inside && (column, row) != (2, 1) {
+                Rgba([255, (column * 66) as u8, (row * 66) as u8, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        })
+    }
+
+    #[test]
+    fn test_detect_cell_length() {
+        let occupied = |pattern: &str| -> Vec<bool> { pattern.chars().map(|c| c == '#').collect() };
+        assert_eq!(detect_cell_length(&occupied(".##..##..##.")), 4);
+        // the empty cells can only be at the end
+        assert_eq!(detect_cell_length(&occupied(".##..##.....")), 4);
+        assert_eq!(detect_cell_length(&occupied("..##....##..")), 6);
+        assert_eq!(detect_cell_length(&occupied("####")), 4);
+    }
+
+    #[test]
+    fn test_sprite_sheet_grid() {
+        let sheet = test_sheet();
+        let grid = SpriteSheetGrid::detect(&sheet, &TransparencyOptions::default()).unwrap();
+        assert_eq!(grid, SpriteSheetGrid::from_counts(60, 32, 3, 2).unwrap());
+        assert_eq!((grid.cell_width, grid.cell_height), (20, 16));
+        let cells = grid.split(&sheet);
+        assert_eq!(cells.len(), 6);
+        assert_eq!(cells[1].get_pixel(6, 4), &Rgba([255, 66, 0, 255]));
+
+        let spaced = SpriteSheetGrid {
+            margin_x: 4,
+            spacing_x: 2,
+            ..grid
+        };
+        assert_eq!(spaced.columns(60), 2);
+        assert_eq!(spaced.cell_position(1, 1), (26, 16));
+
+        assert_eq!(
+            SpriteSheetGrid::from_counts(60, 32, 7, 2),
+            Err(SpriteSheetError::CountDoesNotDivide(60, 7))
+        );
+        assert_eq!(
+            SpriteSheetGrid::detect(&RgbaImage::new(8, 8), &TransparencyOptions::default()),
+            Err(SpriteSheetError::NoOpaquePixel)
+        );
+    }
+
+    #[test]
+    fn test_create_wan_from_sprite_sheet() {
+        let sheet = test_sheet();
+        let grid = SpriteSheetGrid::detect(&sheet, &TransparencyOptions::default()).unwrap();
+        let wanimage = create_wan_from_sprite_sheet(
+            &sheet,
+            &grid,
+            SpriteType::PropsUI,
+            &TransparencyOptions::default(),
+        )
+        .unwrap();
+        // the empty cell is skipped
+        assert_eq!(wanimage.frame_store.frames.len(), 5);
+        assert_eq!(wanimage.palette.palette[1], [255, 0, 0, 128]);
+        let rendered = wanimage.render_frame(4).unwrap();
+        let bounding_box = wanimage.frame_bounding_box(4).unwrap().unwrap();
+        assert_eq!((bounding_box.width(), bounding_box.height()), (8, 8));
+        assert!(rendered
+            .pixels()
+            .any(|pixel| *pixel == Rgba([255, 66, 66, 255])));
+    }
+}
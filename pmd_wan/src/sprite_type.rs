@@ -1,9 +1,12 @@
 use crate::CompressionMethod;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SpriteType {
     PropsUI,
     Chara,
+    /// The sprites of `effect.bin`, with the sprite type id 2. They don't have [`crate::FrameOffset`]s, and are otherwise stored like [`SpriteType::PropsUI`] sprites.
+    Effect,
     Unknown,
 }
 
@@ -12,6 +15,7 @@ impl SpriteType {
         match self {
             SpriteType::PropsUI => 0,
             SpriteType::Chara => 1,
+            SpriteType::Effect => 2,
             SpriteType::Unknown => 3,
         }
     }
@@ -0,0 +1,616 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::fs;
+use std::io;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::path::Path;
+
+use image::{imageops::crop_imm, ImageFormat, Rgba, RgbaImage};
+use thiserror::Error;
+
+use crate::{
+    image_tool::{image_to_paletted_bytes, ImageToPaletteBytesData},
+    insert_frame_in_wanimage,
+    offsets_sheet::put_marker,
+    Animation, AnimationFrame, Frame, FrameOffset, FrameRenderError, SpriteType, WanImage,
+};
+
+/// The name used by SpriteCollab for each animation group, by index. Groups after the end of the list are named `Anim<index>`.
+pub const SPRITEBOT_ANIMATION_NAMES: [&str; 35] = [
+    "Walk",
+    "Attack",
+    "Kick",
+    "Shoot",
+    "Strike",
+    "Sleep",
+    "Hurt",
+    "Idle",
+    "Swing",
+    "Double",
+    "Hop",
+    "Charge",
+    "Rotate",
+    "EventSleep",
+    "Wake",
+    "Eat",
+    "Tumble",
+    "Pose",
+    "Pull",
+    "Pain",
+    "Float",
+    "DeepBreath",
+    "Nod",
+    "Sit",
+    "LookUp",
+    "Sink",
+    "Trip",
+    "Laying",
+    "LeapForth",
+    "Head",
+    "Cringe",
+    "LostBalance",
+    "TumbleBack",
+    "Faint",
+    "HitGround",
+];
+
+/// Colors used in the offsets sheet to mark each point of the [`crate::FrameOffset`]
+pub const SPRITEBOT_HEAD_COLOR: Rgba<u8> = Rgba([0, 0, 0, 255]);
+pub const SPRITEBOT_HAND_LEFT_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+pub const SPRITEBOT_HAND_RIGHT_COLOR: Rgba<u8> = Rgba([0, 0, 255, 255]);
+pub const SPRITEBOT_CENTER_COLOR: Rgba<u8> = Rgba([0, 255, 0, 255]);
+/// Color used in the shadow sheet to mark the position of the shadow
+pub const SPRITEBOT_SHADOW_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+#[derive(Error, Debug)]
+pub enum SpriteBotError {
+    #[error("an input/output error happened")]
+    IOError(#[from] io::Error),
+    #[error("failed to read or write an image")]
+    ImageError(#[from] image::ImageError),
+    #[error("failed to render a frame")]
+    FrameRenderError(#[from] FrameRenderError),
+    #[error("the AnimData.xml file is invalid: {0}")]
+    InvalidAnimData(String),
+    #[error("the animation {0} is a copy of {1}, which doesn't exist")]
+    UnknownCopyOf(String, String),
+    #[error("the sheets of the animation {0} have a size that isn't a multiple of the frame size")]
+    InvalidSheetSize(String),
+    #[error("the sheets use more than 15 different colors")]
+    TooManyColors,
+    #[error("failed to insert a frame")]
+    CantInsertFrame(#[source] anyhow::Error),
+}
+
+/// Return the name SpriteCollab use for the animation group with this index
+pub fn spritebot_animation_name(group_id: usize) -> String {
+    SPRITEBOT_ANIMATION_NAMES
+        .get(group_id)
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| format!("Anim{}", group_id))
+}
+
+/// The three sheets of an animation group. Each row is a direction (in the order of the animation group), and each column a frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteBotSheet {
+    pub name: String,
+    /// Index of the animation group in the [`crate::AnimationStore`]
+    pub index: usize,
+    pub frame_width: u32,
+    pub frame_height: u32,
+    /// The duration of each column
+    pub durations: Vec<u8>,
+    pub anim: RgbaImage,
+    pub offsets: RgbaImage,
+    pub shadow: RgbaImage,
+}
+
+/// An animation group that is identical to a previous one, and is stored as a `CopyOf` in AnimData.xml
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteBotCopy {
+    pub name: String,
+    pub index: usize,
+    pub copy_of: String,
+}
+
+/// A [`WanImage`] converted to SpriteCollab-style sheets, created with [`WanImage::export_spritebot`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SpriteBotExport {
+    pub shadow_size: u8,
+    pub sheets: Vec<SpriteBotSheet>,
+    pub copies: Vec<SpriteBotCopy>,
+}
+
+impl SpriteBotExport {
+    /// Generate the content of the AnimData.xml file
+    pub fn anim_data_xml(&self) -> String {
+        let mut anims: Vec<(usize, String)> = Vec::new();
+        for sheet in &self.sheets {
+            let mut anim = String::new();
+            writeln!(anim, "        <Anim>").unwrap();
+            writeln!(anim, "            <Name>{}</Name>", sheet.name).unwrap();
+            writeln!(anim, "            <Index>{}</Index>", sheet.index).unwrap();
+            writeln!(
+                anim,
+                "            <FrameWidth>{}</FrameWidth>",
+                sheet.frame_width
+            )
+            .unwrap();
+            writeln!(
+                anim,
+                "            <FrameHeight>{}</FrameHeight>",
+                sheet.frame_height
+            )
+            .unwrap();
+            writeln!(anim, "            <Durations>").unwrap();
+            for duration in &sheet.durations {
+                writeln!(anim, "                <Duration>{}</Duration>", duration).unwrap();
+            }
+            writeln!(anim, "            </Durations>").unwrap();
+            writeln!(anim, "        </Anim>").unwrap();
+            anims.push((sheet.index, anim));
+        }
+        for copy in &self.copies {
+            let mut anim = String::new();
+            writeln!(anim, "        <Anim>").unwrap();
+            writeln!(anim, "            <Name>{}</Name>", copy.name).unwrap();
+            writeln!(anim, "            <Index>{}</Index>", copy.index).unwrap();
+            writeln!(anim, "            <CopyOf>{}</CopyOf>", copy.copy_of).unwrap();
+            writeln!(anim, "        </Anim>").unwrap();
+            anims.push((copy.index, anim));
+        }
+        anims.sort_by_key(|(index, _)| *index);
+
+        let mut result = String::new();
+        writeln!(result, "<?xml version=\"1.0\" ?>").unwrap();
+        writeln!(result, "<AnimData>").unwrap();
+        writeln!(result, "    <ShadowSize>{}</ShadowSize>", self.shadow_size).unwrap();
+        writeln!(result, "    <Anims>").unwrap();
+        for (_, anim) in anims {
+            result.push_str(&anim);
+        }
+        writeln!(result, "    </Anims>").unwrap();
+        writeln!(result, "</AnimData>").unwrap();
+        result
+    }
+
+    /// Write the AnimData.xml file and the `<Name>-Anim.png`, `<Name>-Offsets.png` and `<Name>-Shadow.png` sheets in the given folder.
+    /// Not available on `wasm32-unknown-unknown`, that doesn't have a file system: use [`SpriteBotExport::anim_data_xml`] and [`SpriteBotExport::sheets`] instead.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn write_to_folder(&self, folder: &Path) -> Result<(), SpriteBotError> {
+        fs::write(folder.join("AnimData.xml"), self.anim_data_xml())?;
+        for sheet in &self.sheets {
+            for (suffix, image) in [
+                ("Anim", &sheet.anim),
+                ("Offsets", &sheet.offsets),
+                ("Shadow", &sheet.shadow),
+            ] {
+                image.save_with_format(
+                    folder.join(format!("{}-{}.png", sheet.name, suffix)),
+                    ImageFormat::Png,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Return the text inside of each occurrence of the given tag, not supporting nested tags with the same name
+fn xml_tags<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut result = Vec::new();
+    let mut remaining = xml;
+    while let Some(start) = remaining.find(&open) {
+        let after_open = &remaining[start + open.len()..];
+        match after_open.find(&close) {
+            Some(end) => {
+                result.push(after_open[..end].trim());
+                remaining = &after_open[end + close.len()..];
+            }
+            None => break,
+        }
+    }
+    result
+}
+
+fn xml_number<T: std::str::FromStr>(xml: &str, tag: &str) -> Result<T, SpriteBotError> {
+    xml_tags(xml, tag)
+        .first()
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| SpriteBotError::InvalidAnimData(format!("missing or invalid <{}>", tag)))
+}
+
+impl SpriteBotExport {
+    /// Read the AnimData.xml file and the sheets it reference from a folder, as written by [`SpriteBotExport::write_to_folder`].
+    /// Not available on `wasm32-unknown-unknown`, that doesn't have a file system: use [`SpriteBotExport::new_from_anim_data_xml`] instead.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn read_from_folder(folder: &Path) -> Result<Self, SpriteBotError> {
+        let xml = fs::read_to_string(folder.join("AnimData.xml"))?;
+        Self::new_from_anim_data_xml(&xml, |name, suffix| {
+            Ok(image::open(folder.join(format!("{}-{}.png", name, suffix)))?.into_rgba8())
+        })
+    }
+
+    /// Parse an AnimData.xml file, using `load_sheet` to get the sheet of an animation by its name and suffix (`Anim`, `Offsets` or `Shadow`)
+    pub fn new_from_anim_data_xml<L: FnMut(&str, &str) -> Result<RgbaImage, SpriteBotError>>(
+        xml: &str,
+        mut load_sheet: L,
+    ) -> Result<Self, SpriteBotError> {
+        let mut result = Self {
+            shadow_size: xml_number(xml, "ShadowSize").unwrap_or(1),
+            ..Default::default()
+        };
+        for anim in xml_tags(xml, "Anim") {
+            let name = xml_tags(anim, "Name")
+                .first()
+                .ok_or_else(|| SpriteBotError::InvalidAnimData("missing <Name>".to_string()))?
+                .to_string();
+            let index = xml_number(anim, "Index")?;
+            if let Some(copy_of) = xml_tags(anim, "CopyOf").first() {
+                result.copies.push(SpriteBotCopy {
+                    name,
+                    index,
+                    copy_of: copy_of.to_string(),
+                });
+                continue;
+            }
+            let durations = xml_tags(anim, "Duration")
+                .iter()
+                .map(|duration| duration.parse())
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| SpriteBotError::InvalidAnimData("invalid <Duration>".to_string()))?;
+            result.sheets.push(SpriteBotSheet {
+                frame_width: xml_number(anim, "FrameWidth")?,
+                frame_height: xml_number(anim, "FrameHeight")?,
+                durations,
+                anim: load_sheet(&name, "Anim")?,
+                offsets: load_sheet(&name, "Offsets")?,
+                shadow: load_sheet(&name, "Shadow")?,
+                name,
+                index,
+            });
+        }
+        Ok(result)
+    }
+
+    /// Build a Chara [`WanImage`] from the sheets. This is the inverse of [`WanImage::export_spritebot`].
+    ///
+    /// The center of each cell is used as the origin of the frame. Identical cells share the same [`Frame`].
+    /// The points of the [`FrameOffset`] and the shadow are found with the colors used by [`WanImage::export_spritebot`], and default to the center of the cell if there is no such pixel.
+    pub fn to_wan_image(&self) -> Result<WanImage, SpriteBotError> {
+        let mut wanimage = WanImage::new(SpriteType::Chara);
+        let mut palette_data = ImageToPaletteBytesData::default();
+        let mut known_frames: HashMap<(Vec<u8>, FrameOffset), u16> = HashMap::new();
+        let mut groups: Vec<(usize, Vec<Animation>)> = Vec::new();
+        for sheet in &self.sheets {
+            let (frame_width, frame_height) = (sheet.frame_width, sheet.frame_height);
+            if frame_width == 0
+                || frame_height == 0
+                || sheet.anim.width() % frame_width != 0
+                || sheet.anim.height() % frame_height != 0
+                || sheet.offsets.dimensions() != sheet.anim.dimensions()
+                || sheet.shadow.dimensions() != sheet.anim.dimensions()
+            {
+                return Err(SpriteBotError::InvalidSheetSize(sheet.name.clone()));
+            }
+            let half_width = (frame_width / 2) as i16;
+            let half_height = (frame_height / 2) as i16;
+            let find_point = |image: &RgbaImage, x: u32, y: u32, color: Rgba<u8>| {
+                crop_imm(image, x, y, frame_width, frame_height)
+                    .to_image()
+                    .enumerate_pixels()
+                    .find(|(_, _, pixel)| **pixel == color)
+                    .map(|(x, y, _)| (x as i16 - half_width, y as i16 - half_height))
+                    .unwrap_or((0, 0))
+            };
+
+            let mut group = Vec::new();
+            for row in 0..sheet.anim.height() / frame_height {
+                let mut animation = Animation::default();
+                for (column, duration) in sheet.durations.iter().enumerate() {
+                    let x = column as u32 * frame_width;
+                    let y = row * frame_height;
+                    if x >= sheet.anim.width() {
+                        break;
+                    }
+                    let cell = crop_imm(&sheet.anim, x, y, frame_width, frame_height).to_image();
+                    let pixels = image_to_paletted_bytes(&mut palette_data, &cell)
+                        .filter(|_| palette_data.ordered.len() <= 16)
+                        .ok_or(SpriteBotError::TooManyColors)?;
+                    let frame_offset =
+                        FrameOffset::read_markers(&sheet.offsets, x, y, frame_width, frame_height)
+                            .unwrap_or_default();
+                    let shadow = find_point(&sheet.shadow, x, y, SPRITEBOT_SHADOW_COLOR);
+                    let key = (pixels, frame_offset);
+                    let frame_id = match known_frames.get(&key) {
+                        Some(frame_id) => *frame_id,
+                        None => {
+                            let frame_id = match insert_frame_in_wanimage(
+                                key.0.clone(),
+                                frame_width as u16,
+                                frame_height as u16,
+                                &mut wanimage,
+                                0,
+                            )
+                            .map_err(SpriteBotError::CantInsertFrame)?
+                            {
+                                Some(frame_id) => frame_id,
+                                None => {
+                                    wanimage.frame_store.frames.push(Frame::default());
+                                    wanimage.frame_store.frames.len() - 1
+                                }
+                            } as u16;
+                            wanimage.frame_store.frames[frame_id as usize].frame_offset =
+                                Some(key.1.clone());
+                            known_frames.insert(key, frame_id);
+                            frame_id
+                        }
+                    };
+                    animation.frames.push(AnimationFrame {
+                        duration: *duration,
+                        flag: 0,
+                        frame_id,
+                        offset_x: 0,
+                        offset_y: 0,
+                        shadow_offset_x: shadow.0,
+                        shadow_offset_y: shadow.1,
+                    });
+                }
+                group.push(animation);
+            }
+            groups.push((sheet.index, group));
+        }
+        wanimage.fix_empty_frames();
+
+        let group_amount = groups
+            .iter()
+            .map(|(index, _)| index + 1)
+            .chain(self.copies.iter().map(|copy| copy.index + 1))
+            .max()
+            .unwrap_or(0);
+        wanimage.animation_store.anim_groups = (0..group_amount).map(|_| Vec::new()).collect();
+        for copy in &self.copies {
+            let original = self
+                .sheets
+                .iter()
+                .position(|sheet| sheet.name == copy.copy_of)
+                .ok_or_else(|| {
+                    SpriteBotError::UnknownCopyOf(copy.name.clone(), copy.copy_of.clone())
+                })?;
+            let copied = groups[original]
+                .1
+                .iter()
+                .map(|animation| Animation {
+                    frames: animation.frames.clone(),
+                })
+                .collect();
+            wanimage.animation_store.anim_groups[copy.index] = copied;
+        }
+        for (index, group) in groups {
+            wanimage.animation_store.anim_groups[index] = group;
+        }
+
+        palette_data.ordered.resize(16, [0, 0, 0, 0]);
+        wanimage.palette.palette = palette_data
+            .ordered
+            .iter()
+            .map(|color| [color[0], color[1], color[2], color[3] / 2 + color[3] % 2])
+            .collect();
+        Ok(wanimage)
+    }
+}
+
+impl WanImage {
+    /// Return the half size of the cells needed to contains every frame of the group, with its origin in the center of the cell
+    fn spritebot_half_cell_size(
+        &self,
+        group: &[Animation],
+    ) -> Result<(i32, i32), FrameRenderError> {
+        let mut half_width = 1;
+        let mut half_height = 1;
+        let mut include = |x: i32, y: i32| {
+            half_width = half_width.max(x.abs() + 1);
+            half_height = half_height.max(y.abs() + 1);
+        };
+        for animation_frame in group.iter().flat_map(|animation| animation.frames.iter()) {
+            let frame = self
+                .frame_store
+                .frames
+                .get(animation_frame.frame_id as usize)
+                .ok_or(FrameRenderError::NoFrame(animation_frame.frame_id as usize))?;
+            let offset_x = animation_frame.offset_x as i32;
+            let offset_y = animation_frame.offset_y as i32;
+            if let Some((min_x, min_y, max_x, max_y)) = frame.fragments_extent() {
+                include(min_x + offset_x, min_y + offset_y);
+                include(max_x + offset_x - 1, max_y + offset_y - 1);
+            }
+            include(
+                animation_frame.shadow_offset_x as i32,
+                animation_frame.shadow_offset_y as i32,
+            );
+        }
+        Ok((half_width, half_height))
+    }
+
+    /// Convert this [`WanImage`] to SpriteCollab-style sheets.
+    ///
+    /// Each non-empty animation group become a sheet, with one row per [`Animation`] (one per direction for Chara sprites). The origin of the animation is at the center of each cell.
+    /// Groups identical to a previous one are exported as a copy of it.
+    /// The offsets sheet mark each point of the [`crate::FrameOffset`] of the frame with a single pixel, and the shadow sheet mark the position of the shadow.
+    pub fn export_spritebot(&self) -> Result<SpriteBotExport, SpriteBotError> {
+        let mut export = SpriteBotExport {
+            shadow_size: 1,
+            ..Default::default()
+        };
+        let groups = &self.animation_store.anim_groups;
+        for (index, group) in groups.iter().enumerate() {
+            if group.iter().all(|animation| animation.frames.is_empty()) {
+                continue;
+            }
+            if let Some(original) = groups[..index]
+                .iter()
+                .position(|previous| previous == group)
+            {
+                export.copies.push(SpriteBotCopy {
+                    name: spritebot_animation_name(index),
+                    index,
+                    copy_of: spritebot_animation_name(original),
+                });
+                continue;
+            }
+
+            let (half_width, half_height) = self.spritebot_half_cell_size(group)?;
+            let frame_width = half_width as u32 * 2;
+            let frame_height = half_height as u32 * 2;
+            let columns = group
+                .iter()
+                .map(|animation| animation.len())
+                .max()
+                .unwrap_or(0);
+            let durations: Vec<u8> = (0..columns)
+                .map(|column| {
+                    group
+                        .iter()
+                        .find_map(|animation| animation.frames.get(column))
+                        .map(|animation_frame| animation_frame.duration)
+                        .unwrap_or(0)
+                })
+                .collect();
+
+            let sheet_width = frame_width * columns as u32;
+            let sheet_height = frame_height * group.len() as u32;
+            let mut anim = RgbaImage::new(sheet_width, sheet_height);
+            let mut offsets = RgbaImage::new(sheet_width, sheet_height);
+            let mut shadow = RgbaImage::new(sheet_width, sheet_height);
+            for (row, animation) in group.iter().enumerate() {
+                for (column, animation_frame) in animation.frames.iter().enumerate() {
+                    let center_x = (column as u32 * frame_width) as i32 + half_width;
+                    let center_y = (row as u32 * frame_height) as i32 + half_height;
+                    let origin_x = center_x + animation_frame.offset_x as i32;
+                    let origin_y = center_y + animation_frame.offset_y as i32;
+                    // no panic: the frame existence is checked when computing the cell size
+                    let frame = &self.frame_store.frames[animation_frame.frame_id as usize];
+                    frame.draw_on_image_with_depth(
+                        &self.fragment_bytes_store,
+                        &self.palette,
+                        self.is_256_color,
+                        &mut anim,
+                        origin_x,
+                        origin_y,
+                    )?;
+                    if let Some(frame_offset) = &frame.frame_offset {
+                        frame_offset.draw_markers(&mut offsets, origin_x, origin_y);
+                    }
+                    put_marker(
+                        &mut shadow,
+                        center_x + animation_frame.shadow_offset_x as i32,
+                        center_y + animation_frame.shadow_offset_y as i32,
+                        SPRITEBOT_SHADOW_COLOR,
+                    );
+                }
+            }
+
+            export.sheets.push(SpriteBotSheet {
+                name: spritebot_animation_name(index),
+                index,
+                frame_width,
+                frame_height,
+                durations,
+                anim,
+                offsets,
+                shadow,
+            });
+        }
+        Ok(export)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use crate::{
+        tests::fixtures::{animation_frame, insert_filled_frame, test_wan_image},
+        Animation, AnimationFrame, FrameOffset, SpriteBotExport, SpriteType,
+        SPRITEBOT_CENTER_COLOR, SPRITEBOT_SHADOW_COLOR,
+    };
+
+    #[test]
+    fn test_export_spritebot() {
+        let mut wanimage = test_wan_image(SpriteType::Chara);
+        insert_filled_frame(&mut wanimage, 2, 2);
+        wanimage.frame_store.frames[0].frame_offset = Some(FrameOffset {
+            head: (0, -1),
+            hand_left: (-1, 0),
+            hand_right: (1, 0),
+            center: (0, 0),
+        });
+        let animation = || Animation {
+            frames: vec![AnimationFrame {
+                shadow_offset_y: 2,
+                ..animation_frame(0, 4)
+            }],
+        };
+        wanimage.animation_store.anim_groups = vec![
+            (0..8).map(|_| animation()).collect(),
+            Vec::new(),
+            (0..8).map(|_| animation()).collect(),
+        ];
+
+        let export = wanimage.export_spritebot().unwrap();
+        assert_eq!(export.sheets.len(), 1);
+        assert_eq!(export.copies.len(), 1);
+        assert_eq!(export.copies[0].copy_of, "Walk");
+        let sheet = &export.sheets[0];
+        assert_eq!(sheet.name, "Walk");
+        assert_eq!(sheet.durations, vec![4]);
+        assert_eq!(sheet.anim.height(), sheet.frame_height * 8);
+        let half_width = sheet.frame_width / 2;
+        let half_height = sheet.frame_height / 2;
+        assert_eq!(
+            sheet.anim.get_pixel(half_width - 1, half_height - 1),
+            &Rgba([255, 0, 0, 255])
+        );
+        assert_eq!(
+            sheet.offsets.get_pixel(half_width, half_height),
+            &SPRITEBOT_CENTER_COLOR
+        );
+        assert_eq!(
+            sheet.shadow.get_pixel(half_width, half_height + 2),
+            &SPRITEBOT_SHADOW_COLOR
+        );
+
+        let xml = export.anim_data_xml();
+        assert!(xml.contains("<Name>Walk</Name>"));
+        assert!(xml.contains("<CopyOf>Walk</CopyOf>"));
+        assert!(xml.contains("<Duration>4</Duration>"));
+
+        let reimported = SpriteBotExport::new_from_anim_data_xml(&xml, |name, suffix| {
+            assert_eq!(name, "Walk");
+            Ok(match suffix {
+                "Anim" => sheet.anim.clone(),
+                "Offsets" => sheet.offsets.clone(),
+                _ => sheet.shadow.clone(),
+            })
+        })
+        .unwrap();
+        assert_eq!(reimported, export);
+        let wan = reimported.to_wan_image().unwrap();
+        assert_eq!(wan.frame_store.frames.len(), 1);
+        assert_eq!(
+            wan.frame_store.frames[0].frame_offset,
+            wanimage.frame_store.frames[0].frame_offset
+        );
+        assert_eq!(wan.animation_store.anim_groups.len(), 3);
+        assert_eq!(
+            wan.animation_store.anim_groups[2],
+            wanimage.animation_store.anim_groups[0]
+        );
+        assert_eq!(
+            wan.render_frame(0).unwrap(),
+            wanimage.render_frame(0).unwrap()
+        );
+    }
+}
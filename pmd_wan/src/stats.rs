@@ -0,0 +1,204 @@
+use std::fmt;
+
+use crate::{DuplicateFragmentReport, EncodedSizeEstimate, FrameBudgetUsage, WanImage};
+
+/// The number of frames listed in [`SpriteStats::largest_frames`]
+pub const LARGEST_FRAMES_AMOUNT: usize = 5;
+
+/// The size of an encoded [`crate::Fragment`]
+const FRAGMENT_ENCODED_SIZE: usize = 10;
+
+/// The number of elements in a section of a wan file, and the size it take once encoded
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct SectionStats {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+/// An overview of the content of a [`WanImage`], as returned by [`WanImage::stats`], to find which sprites are worth optimizing.
+///
+/// Its [`fmt::Display`] implementation write a human-readable summary.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpriteStats {
+    /// The [`crate::Fragment`]s of all the frames. Their size is included in [`SpriteStats::frames`].
+    pub fragments: SectionStats,
+    /// The [`crate::FragmentBytes`], with their encoded size (after compression)
+    pub fragment_bytes: SectionStats,
+    /// The [`crate::Frame`]s, with their [`crate::Fragment`]s
+    pub frames: SectionStats,
+    /// The number of animation groups
+    pub animation_groups: usize,
+    /// The [`crate::Animation`]s of all the groups
+    pub animations: SectionStats,
+    /// The number of [`crate::AnimationFrame`]s of all the animations
+    pub animation_frames: usize,
+    /// The colors of the [`crate::Palette`]
+    pub palette: SectionStats,
+    /// The number of 16 colors rows of the palette
+    pub palette_rows: u16,
+    /// The size of every section of the encoded file
+    pub encoded_size: EncodedSizeEstimate,
+    /// The frames using the most VRAM (then the most fragments), at most [`LARGEST_FRAMES_AMOUNT`], the biggest first
+    pub largest_frames: Vec<FrameBudgetUsage>,
+    /// The duplicated [`crate::FragmentBytes`]
+    pub duplicate_fragment_bytes: DuplicateFragmentReport,
+    /// The number of frames that are exactly identical to another one that appear before them
+    pub duplicate_frames: usize,
+}
+
+impl SpriteStats {
+    /// Proportion (from 0 to 1) of the frames that are exact duplicates
+    pub fn duplicate_frame_ratio(&self) -> f32 {
+        if self.frames.count == 0 {
+            0.0
+        } else {
+            self.duplicate_frames as f32 / self.frames.count as f32
+        }
+    }
+}
+
+impl fmt::Display for SpriteStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "total size: {} bytes", self.encoded_size.total())?;
+        writeln!(
+            f,
+            "frames: {} ({} bytes), {} fragments, {} duplicated frames ({:.1}%)",
+            self.frames.count,
+            self.frames.bytes,
+            self.fragments.count,
+            self.duplicate_frames,
+            self.duplicate_frame_ratio() * 100.0
+        )?;
+        writeln!(
+            f,
+            "image bytes: {} ({} bytes), {} exact and {} flipped duplicates ({:.1}% of the pixel data)",
+            self.fragment_bytes.count,
+            self.fragment_bytes.bytes,
+            self.duplicate_fragment_bytes.exact_duplicates,
+            self.duplicate_fragment_bytes.flip_duplicates,
+            self.duplicate_fragment_bytes.duplicate_ratio() * 100.0
+        )?;
+        writeln!(
+            f,
+            "animations: {} in {} groups ({} bytes), {} animation frames",
+            self.animations.count,
+            self.animation_groups,
+            self.animations.bytes,
+            self.animation_frames
+        )?;
+        writeln!(
+            f,
+            "palette: {} colors in {} rows ({} bytes)",
+            self.palette.count, self.palette_rows, self.palette.bytes
+        )?;
+        writeln!(
+            f,
+            "other: {} bytes of frame offsets, {} bytes of headers and tables, {} bytes of sir0",
+            self.encoded_size.frame_offsets,
+            self.encoded_size.headers_and_tables,
+            self.encoded_size.sir0
+        )?;
+        writeln!(f, "largest frames:")?;
+        for usage in &self.largest_frames {
+            writeln!(
+                f,
+                "  frame {}: {} bytes of VRAM, {} fragments",
+                usage.frame, usage.vram_bytes, usage.oam_entries
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl WanImage {
+    /// Compute the [`SpriteStats`] of this image. This encode it to measure its sections, failing if it can't be encoded.
+    pub fn stats(&self) -> anyhow::Result<SpriteStats> {
+        let encoded_size = self.estimate_encoded_size()?;
+        let frames = &self.frame_store.frames;
+        let animations = self.animation_store.anim_groups.iter().flatten();
+
+        let mut largest_frames: Vec<FrameBudgetUsage> = frames
+            .iter()
+            .enumerate()
+            .map(|(frame_id, frame)| FrameBudgetUsage::new(frame_id, frame, self.is_256_color))
+            .collect();
+        largest_frames.sort_by(|a, b| {
+            (b.vram_bytes, b.oam_entries)
+                .cmp(&(a.vram_bytes, a.oam_entries))
+                .then(a.frame.cmp(&b.frame))
+        });
+        largest_frames.truncate(LARGEST_FRAMES_AMOUNT);
+
+        let duplicate_frames = frames
+            .iter()
+            .enumerate()
+            .filter(|(frame_id, frame)| frames[..*frame_id].contains(frame))
+            .count();
+        let fragment_count = frames.iter().map(|frame| frame.fragments.len()).sum();
+
+        Ok(SpriteStats {
+            fragments: SectionStats {
+                count: fragment_count,
+                bytes: fragment_count * FRAGMENT_ENCODED_SIZE,
+            },
+            fragment_bytes: SectionStats {
+                count: self.fragment_bytes_store.fragment_bytes.len(),
+                bytes: encoded_size.fragment_bytes,
+            },
+            frames: SectionStats {
+                count: frames.len(),
+                bytes: encoded_size.frames,
+            },
+            animation_groups: self.animation_store.anim_groups.len(),
+            animations: SectionStats {
+                count: animations.clone().count(),
+                bytes: encoded_size.animations,
+            },
+            animation_frames: animations.map(|animation| animation.frames.len()).sum(),
+            palette: SectionStats {
+                count: self.palette.palette.len(),
+                bytes: encoded_size.palette,
+            },
+            palette_rows: self.palette_row_count(),
+            largest_frames,
+            duplicate_fragment_bytes: self.duplicate_fragment_report(),
+            duplicate_frames,
+            encoded_size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::fixtures::{insert_filled_frame, single_frame_wan_image},
+        Animation,
+    };
+
+    #[test]
+    fn test_stats() {
+        let mut wanimage = single_frame_wan_image();
+        insert_filled_frame(&mut wanimage, 32, 32);
+        insert_filled_frame(&mut wanimage, 8, 8);
+        wanimage
+            .animation_store
+            .anim_groups
+            .push(vec![Animation::default(), Animation::default()]);
+
+        let stats = wanimage.stats().unwrap();
+        assert_eq!(stats.frames.count, 3);
+        assert_eq!(stats.animations.count, 2);
+        assert_eq!(stats.animation_groups, 1);
+        assert_eq!(stats.palette.count, 2);
+        assert_eq!(stats.duplicate_frames, 1);
+        assert_eq!(stats.largest_frames.len(), 3);
+        assert_eq!(stats.largest_frames[0].frame, 1);
+        assert_eq!(
+            stats.encoded_size.total(),
+            wanimage.write_to_vec().unwrap().len()
+        );
+        let text = stats.to_string();
+        assert!(text.contains("frames: 3"));
+        assert!(text.contains("  frame 1: "));
+    }
+}
@@ -4,7 +4,13 @@ mod tests {
 
     use crate::{
         image_tool::{image_to_paletted_bytes, ImageToPaletteBytesData},
-        insert_frame_in_wanimage, Animation, AnimationFrame, WanImage,
+        insert_frame_in_wanimage,
+        palette_file::component_to_8bit,
+        tests::fixtures::{
+            animation_frame, insert_filled_frame, single_frame_wan_bytes, single_frame_wan_image,
+            test_wan_image,
+        },
+        Animation, AnimationFrame, FragmentAttributeError, OamShape, SpriteType, WanImage,
     };
 
     #[test]
@@ -59,4 +65,179 @@ mod tests {
             3
         );
     }
+
+    #[test]
+    fn decode_wan_from_bytes_and_reader() {
+        let wan_bytes = single_frame_wan_bytes();
+        let decoded_wanimage = WanImage::decode_wan(&mut Cursor::new(&wan_bytes)).unwrap();
+
+        assert_eq!(
+            WanImage::decode_wan_from_bytes(&wan_bytes).unwrap(),
+            decoded_wanimage
+        );
+        assert_eq!(
+            WanImage::decode_wan_from_reader(wan_bytes.as_slice()).unwrap(),
+            decoded_wanimage
+        );
+    }
+
+    #[test]
+    fn write_to_vec_and_estimate_encoded_size() {
+        let mut wanimage = single_frame_wan_image();
+        wanimage.palette.palette.resize(16, [0, 0, 0, 128]);
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![animation_frame(0, 1)],
+        }]);
+        let mut wan_cursor = Cursor::new(Vec::new());
+        wanimage.create_wan(&mut wan_cursor).unwrap();
+        let wan_bytes = wan_cursor.into_inner();
+
+        assert_eq!(wanimage.write_to_vec().unwrap(), wan_bytes);
+        let size = wanimage.estimate_encoded_size().unwrap();
+        assert_eq!(size.total(), wan_bytes.len());
+        assert_eq!(size.palette, 16 * 4 + 16);
+    }
+
+    #[test]
+    fn encode_and_decode_256_color_wan() {
+        let mut wanimage = WanImage::new(crate::SpriteType::PropsUI);
+        wanimage.is_256_color = true;
+        wanimage.palette.palette = (0..=255).map(|color| [color, 0, 0, 128]).collect();
+        let pixels: Vec<u8> = (0..64).map(|pixel| pixel * 4 + 1).collect();
+        let frame_id = insert_frame_in_wanimage(pixels.clone(), 8, 8, &mut wanimage, 0)
+            .unwrap()
+            .unwrap();
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![animation_frame(frame_id as u16, 1)],
+        }]);
+
+        let mut wan_cursor = Cursor::new(Vec::new());
+        wanimage.create_wan(&mut wan_cursor).unwrap();
+        let decoded_wanimage = WanImage::decode_wan(&mut wan_cursor).unwrap();
+        assert!(decoded_wanimage.is_256_color);
+        assert_eq!(
+            decoded_wanimage.fragment_bytes_store,
+            wanimage.fragment_bytes_store
+        );
+
+        let image = decoded_wanimage.render_frame(frame_id).unwrap();
+        assert_eq!(image.dimensions(), (8, 8));
+        for (pixel_nb, pixel) in pixels.iter().enumerate() {
+            assert_eq!(
+                image.get_pixel(pixel_nb as u32 % 8, pixel_nb as u32 / 8),
+                &image::Rgba([component_to_8bit(*pixel), 0, 0, 255])
+            );
+        }
+    }
+
+    #[test]
+    fn encode_and_decode_fragment_attributes() {
+        let mut wanimage = WanImage::new(crate::SpriteType::PropsUI);
+        wanimage.palette.palette = vec![[255, 0, 0, 128]; 32];
+        let frame_id = insert_frame_in_wanimage(vec![1; 64], 8, 8, &mut wanimage, 0)
+            .unwrap()
+            .unwrap();
+        let fragment = &mut wanimage.frame_store.frames[frame_id].fragments[0];
+        fragment.set_priority(1).unwrap();
+        fragment.set_palette_index(1).unwrap();
+        fragment.set_mosaic(true);
+        assert_eq!(
+            fragment.set_priority(4),
+            Err(FragmentAttributeError::InvalidPriority(4))
+        );
+        assert_eq!(
+            fragment.set_palette_index(16),
+            Err(FragmentAttributeError::InvalidPaletteIndex(16))
+        );
+        let fragment = fragment.clone();
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![animation_frame(frame_id as u16, 1)],
+        }]);
+
+        let wan_bytes = wanimage.write_to_vec().unwrap();
+        let decoded_wanimage = WanImage::decode_wan_from_bytes(&wan_bytes).unwrap();
+        assert_eq!(
+            decoded_wanimage.frame_store.frames[frame_id].fragments[0],
+            fragment
+        );
+
+        wanimage.frame_store.frames[frame_id].fragments[0].priority = 4;
+        assert!(wanimage.write_to_vec().is_err());
+    }
+
+    #[test]
+    fn encode_and_decode_every_oam_shape() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        let mut animation = Animation::default();
+        for shape in &OamShape::ALL {
+            let size = shape.size();
+            let frame_id = insert_frame_in_wanimage(
+                vec![1; (size.x * size.y) as usize],
+                size.x as u16,
+                size.y as u16,
+                &mut wanimage,
+                0,
+            )
+            .unwrap()
+            .unwrap();
+            let fragments = &wanimage.frame_store.frames[frame_id].fragments;
+            assert_eq!(fragments.len(), 1);
+            assert_eq!(fragments[0].resolution, *shape);
+            animation.frames.push(animation_frame(frame_id as u16, 1));
+        }
+        wanimage.animation_store.anim_groups.push(vec![animation]);
+
+        let wan_bytes = wanimage.write_to_vec().unwrap();
+        let decoded_wanimage = WanImage::decode_wan_from_bytes(&wan_bytes).unwrap();
+        assert_eq!(decoded_wanimage.frame_store, wanimage.frame_store);
+    }
+
+    #[test]
+    fn encode_and_decode_effect_wan() {
+        let mut wanimage = test_wan_image(SpriteType::Effect);
+        insert_filled_frame(&mut wanimage, 8, 8);
+        wanimage
+            .animation_store
+            .anim_groups
+            .push(vec![Animation::default()]);
+
+        let wan_bytes = wanimage.write_to_vec().unwrap();
+        // the sprite type id, at the end of the wan header
+        let header_offset =
+            u32::from_le_bytes([wan_bytes[4], wan_bytes[5], wan_bytes[6], wan_bytes[7]]) as usize;
+        assert_eq!(&wan_bytes[header_offset + 8..header_offset + 10], &[2, 0]);
+        let decoded_wanimage = WanImage::decode_wan_from_bytes(&wan_bytes).unwrap();
+        assert_eq!(decoded_wanimage.sprite_type, crate::SpriteType::Effect);
+        assert_eq!(decoded_wanimage.frame_store, wanimage.frame_store);
+        assert_eq!(decoded_wanimage.write_to_vec().unwrap(), wan_bytes);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_and_deserialize_wan_image_with_serde() {
+        let mut wanimage = test_wan_image(SpriteType::Chara);
+        insert_frame_in_wanimage(vec![1, 0, 0, 1], 2, 2, &mut wanimage, 0).unwrap();
+        wanimage.frame_store.frames[0].frame_offset = Some(crate::FrameOffset {
+            head: (0, -2),
+            hand_left: (-1, 0),
+            hand_right: (1, 0),
+            center: (0, 0),
+        });
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![AnimationFrame {
+                offset_x: 1,
+                offset_y: 2,
+                shadow_offset_y: 4,
+                ..animation_frame(0, 3)
+            }],
+        }]);
+
+        let json = serde_json::to_string(&wanimage).unwrap();
+        let deserialized: WanImage = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, wanimage);
+
+        let invalid = json.replace("\"shape_indice\":0", "\"shape_indice\":3");
+        assert_ne!(invalid, json);
+        assert!(serde_json::from_str::<WanImage>(&invalid).is_err());
+    }
 }
@@ -0,0 +1,92 @@
+//! Small [`WanImage`]s and helpers shared by the tests of the other modules
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use crate::{insert_frame_in_wanimage, Animation, AnimationFrame, SpriteType, WanImage};
+
+/// The opaque color at the index 1 of the palette of [`test_wan_image`]
+pub const TEST_COLOR: [u8; 4] = [255, 0, 0, 128];
+
+/// An empty [`WanImage`], with a palette made of a transparent color followed by [`TEST_COLOR`]
+pub fn test_wan_image(sprite_type: SpriteType) -> WanImage {
+    let mut wanimage = WanImage::new(sprite_type);
+    wanimage.palette.palette = vec![[0, 0, 0, 0], TEST_COLOR];
+    wanimage
+}
+
+/// A [`SpriteType::PropsUI`] [`test_wan_image`] with a single 8×8 frame of [`TEST_COLOR`]
+pub fn single_frame_wan_image() -> WanImage {
+    let mut wanimage = test_wan_image(SpriteType::PropsUI);
+    insert_filled_frame(&mut wanimage, 8, 8);
+    wanimage
+}
+
+/// A [`single_frame_wan_image`] with an animation showing its frame, encoded as a wan file
+pub fn single_frame_wan_bytes() -> Vec<u8> {
+    let mut wanimage = single_frame_wan_image();
+    wanimage.animation_store.anim_groups.push(vec![Animation {
+        frames: vec![animation_frame(0, 1)],
+    }]);
+    wanimage.write_to_vec().unwrap()
+}
+
+/// Insert a frame of `width`×`height` pixels of the color 1, and return its id
+pub fn insert_filled_frame(wanimage: &mut WanImage, width: u16, height: u16) -> u16 {
+    let pixels = vec![1; width as usize * height as usize];
+    insert_frame_in_wanimage(pixels, width, height, wanimage, 0)
+        .unwrap()
+        .unwrap() as u16
+}
+
+/// An [`AnimationFrame`] that display `frame_id` for `duration` ticks, without offset nor flag
+pub fn animation_frame(frame_id: u16, duration: u8) -> AnimationFrame {
+    AnimationFrame {
+        duration,
+        flag: 0,
+        frame_id,
+        offset_x: 0,
+        offset_y: 0,
+        shadow_offset_x: 0,
+        shadow_offset_y: 0,
+    }
+}
+
+/// Read the little endian 32 bits integer at `offset`, usually a pointer in a wan file
+pub fn read_u32(bytes: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ]) as usize
+}
+
+/// An empty folder in the temporary directory, unique to the test process, removed when dropped (even if the test panic)
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path =
+            std::env::temp_dir().join(format!("pmd_wan_test_{}_{}", name, std::process::id()));
+        if path.exists() {
+            std::fs::remove_dir_all(&path).unwrap();
+        }
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
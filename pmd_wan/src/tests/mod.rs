@@ -1 +1,2 @@
 pub mod encodedecode;
+pub mod fixtures;
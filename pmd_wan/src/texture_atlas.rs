@@ -0,0 +1,254 @@
+use std::fmt::Write;
+
+use image::RgbaImage;
+
+use crate::{spritebot_animation_name, AnimationFrame, FrameRenderError, WanImage};
+
+/// The transparent space left between the frames of a [`TextureAtlas`]
+pub const ATLAS_PADDING: u32 = 1;
+
+/// The place of a [`crate::Frame`] in a [`TextureAtlas`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasFrame {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The pivot of the frame, relative to its top-left corner: the [`crate::FrameOffset::center`] if the frame has one, or the origin of the frame
+    pub pivot: (i32, i32),
+}
+
+/// An animation of a [`TextureAtlas`], named like the animations of the SpriteBot and Aseprite exports (`<group name>_<animation index>`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtlasSequence {
+    pub name: String,
+    pub frames: Vec<AnimationFrame>,
+}
+
+/// Every frame of a [`WanImage`] packed in a single image, as created by [`WanImage::export_texture_atlas`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextureAtlas {
+    pub image: RgbaImage,
+    /// The place of each frame in [`TextureAtlas::image`], by frame id. A frame without any [`crate::Fragment`] take a single transparent pixel.
+    pub frames: Vec<AtlasFrame>,
+    /// Every non-empty animation, in order
+    pub sequences: Vec<AtlasSequence>,
+}
+
+impl TextureAtlas {
+    /// The name of the frame with the given id in [`TextureAtlas::texture_packer_json`]
+    pub fn frame_name(frame_id: usize) -> String {
+        format!("frame_{}", frame_id)
+    }
+
+    /// Generate a TexturePacker JSON (hash) description of the atlas, as read by Unity, Phaser and most engines, for the atlas image saved as `image_name`.
+    ///
+    /// The pivots are normalized like TexturePacker does. The animations are listed in `animations` with their frame names, as used by Phaser.
+    /// As durations and offsets don't have a standard place, each animation is also listed in `sequences` with them (durations being in 1/60th of second).
+    pub fn texture_packer_json(&self, image_name: &str) -> String {
+        let mut json = String::new();
+        json.push_str("{\n  \"frames\": {");
+        for (frame_id, frame) in self.frames.iter().enumerate() {
+            if frame_id != 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "\n    \"{}\": {{\"frame\": {{\"x\": {}, \"y\": {}, \"w\": {}, \"h\": {}}}, \"rotated\": false, \"trimmed\": false, \"spriteSourceSize\": {{\"x\": 0, \"y\": 0, \"w\": {}, \"h\": {}}}, \"sourceSize\": {{\"w\": {}, \"h\": {}}}, \"pivot\": {{\"x\": {}, \"y\": {}}}}}",
+                Self::frame_name(frame_id),
+                frame.x,
+                frame.y,
+                frame.width,
+                frame.height,
+                frame.width,
+                frame.height,
+                frame.width,
+                frame.height,
+                frame.pivot.0 as f32 / frame.width as f32,
+                frame.pivot.1 as f32 / frame.height as f32
+            )
+            .unwrap();
+        }
+        json.push_str("\n  },\n  \"animations\": {");
+        for (sequence_id, sequence) in self.sequences.iter().enumerate() {
+            if sequence_id != 0 {
+                json.push(',');
+            }
+            let names: Vec<String> = sequence
+                .frames
+                .iter()
+                .map(|frame| format!("\"{}\"", Self::frame_name(frame.frame_id as usize)))
+                .collect();
+            write!(json, "\n    \"{}\": [{}]", sequence.name, names.join(", ")).unwrap();
+        }
+        json.push_str("\n  },\n  \"sequences\": {");
+        for (sequence_id, sequence) in self.sequences.iter().enumerate() {
+            if sequence_id != 0 {
+                json.push(',');
+            }
+            let frames: Vec<String> = sequence
+                .frames
+                .iter()
+                .map(|frame| {
+                    format!(
+                        "{{\"frame\": \"{}\", \"duration\": {}, \"offset\": {{\"x\": {}, \"y\": {}}}}}",
+                        Self::frame_name(frame.frame_id as usize),
+                        frame.duration,
+                        frame.offset_x,
+                        frame.offset_y
+                    )
+                })
+                .collect();
+            write!(json, "\n    \"{}\": [{}]", sequence.name, frames.join(", ")).unwrap();
+        }
+        write!(
+            json,
+            "\n  }},\n  \"meta\": {{\"app\": \"pmd_wan\", \"version\": \"{}\", \"image\": \"{}\", \"format\": \"RGBA8888\", \"size\": {{\"w\": {}, \"h\": {}}}, \"scale\": \"1\"}}\n}}\n",
+            env!("CARGO_PKG_VERSION"),
+            image_name.replace('\\', "\\\\").replace('"', "\\\""),
+            self.image.width(),
+            self.image.height()
+        )
+        .unwrap();
+        json
+    }
+}
+
+impl WanImage {
+    /// Render every frame and pack them in a single image, with [`ATLAS_PADDING`] pixels between them. The frames are placed on rows, from the tallest to the smallest.
+    ///
+    /// `max_width` is the width of the rows. If [`None`], it is chosen to make the atlas roughly square. It is increased if a frame is wider.
+    pub fn export_texture_atlas(
+        &self,
+        max_width: Option<u32>,
+    ) -> Result<TextureAtlas, FrameRenderError> {
+        let mut images = Vec::with_capacity(self.frame_store.frames.len());
+        for frame_id in 0..self.frame_store.frames.len() {
+            let image = self.render_frame(frame_id)?;
+            images.push(if image.width() == 0 || image.height() == 0 {
+                RgbaImage::new(1, 1)
+            } else {
+                image
+            });
+        }
+        let widest = images.iter().map(|image| image.width()).max().unwrap_or(1);
+        let area: u64 = images
+            .iter()
+            .map(|image| {
+                (image.width() + ATLAS_PADDING) as u64 * (image.height() + ATLAS_PADDING) as u64
+            })
+            .sum();
+        let row_width = max_width
+            .unwrap_or_else(|| (area as f64).sqrt().ceil() as u32)
+            .max(widest);
+
+        let mut order: Vec<usize> = (0..images.len()).collect();
+        order.sort_by_key(|frame_id| std::cmp::Reverse(images[*frame_id].height()));
+        let mut places = vec![(0, 0); images.len()];
+        let (mut x, mut y, mut row_height, mut width) = (0, 0, 0, 0);
+        for frame_id in order {
+            let image = &images[frame_id];
+            if x != 0 && x + image.width() > row_width {
+                x = 0;
+                y += row_height + ATLAS_PADDING;
+                row_height = 0;
+            }
+            places[frame_id] = (x, y);
+            width = width.max(x + image.width());
+            row_height = row_height.max(image.height());
+            x += image.width() + ATLAS_PADDING;
+        }
+
+        let mut atlas = RgbaImage::new(width.max(1), (y + row_height).max(1));
+        let mut frames = Vec::with_capacity(images.len());
+        for (frame_id, (image, (x, y))) in images.iter().zip(places).enumerate() {
+            image::imageops::replace(&mut atlas, image, x as i64, y as i64);
+            let frame = &self.frame_store.frames[frame_id];
+            let (origin_x, origin_y) = frame.render_origin();
+            let pivot = match &frame.frame_offset {
+                Some(offset) => (
+                    origin_x + offset.center.0 as i32,
+                    origin_y + offset.center.1 as i32,
+                ),
+                None => (origin_x, origin_y),
+            };
+            frames.push(AtlasFrame {
+                x,
+                y,
+                width: image.width(),
+                height: image.height(),
+                pivot,
+            });
+        }
+
+        let mut sequences = Vec::new();
+        for (group_id, group) in self.animation_store.anim_groups.iter().enumerate() {
+            for (animation_id, animation) in group.iter().enumerate() {
+                if animation.frames.is_empty() {
+                    continue;
+                }
+                if let Some(animation_frame) = animation
+                    .frames
+                    .iter()
+                    .find(|frame| frame.frame_id as usize >= frames.len())
+                {
+                    return Err(FrameRenderError::NoFrame(animation_frame.frame_id as usize));
+                }
+                sequences.push(AtlasSequence {
+                    name: format!("{}_{}", spritebot_animation_name(group_id), animation_id),
+                    frames: animation.frames.clone(),
+                });
+            }
+        }
+        Ok(TextureAtlas {
+            image: atlas,
+            frames,
+            sequences,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::fixtures::{animation_frame, insert_filled_frame, single_frame_wan_image},
+        Animation, AnimationFrame,
+    };
+
+    #[test]
+    fn test_texture_atlas() {
+        let mut wanimage = single_frame_wan_image();
+        insert_filled_frame(&mut wanimage, 16, 16);
+        wanimage.animation_store.anim_groups.push(vec![Animation {
+            frames: vec![
+                animation_frame(1, 4),
+                AnimationFrame {
+                    offset_x: 1,
+                    ..animation_frame(0, 2)
+                },
+            ],
+        }]);
+
+        let atlas = wanimage.export_texture_atlas(Some(32)).unwrap();
+        // the tallest frame is placed first
+        assert_eq!((atlas.frames[1].x, atlas.frames[1].y), (0, 0));
+        assert_eq!((atlas.frames[0].x, atlas.frames[0].y), (16 + 1, 0));
+        assert_eq!(atlas.image.dimensions(), (25, 16));
+        assert_eq!(atlas.frames[1].pivot, (8, 8));
+        assert_eq!(atlas.sequences[0].name, "Walk_0");
+
+        let json = atlas.texture_packer_json("atlas.png");
+        assert!(json.contains("\"frame_0\": {\"frame\": {\"x\": 17, \"y\": 0, \"w\": 8, \"h\": 8}"));
+        assert!(json.contains("\"pivot\": {\"x\": 0.5, \"y\": 0.5}"));
+        assert!(json.contains("\"Walk_0\": [\"frame_1\", \"frame_0\"]"));
+        assert!(json.contains(
+            "{\"frame\": \"frame_0\", \"duration\": 2, \"offset\": {\"x\": 1, \"y\": 0}}"
+        ));
+        assert!(json.contains("\"image\": \"atlas.png\""));
+
+        // the default width make a square atlas, so the frames don't fit on the same row
+        let square = wanimage.export_texture_atlas(None).unwrap();
+        assert_eq!((square.frames[0].x, square.frames[0].y), (0, 16 + 1));
+        assert_eq!(square.image.dimensions(), (16, 25));
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{
+    FrameBudgetUsage, HardwareBudget, OamShape, SpriteType, WanImage, MAX_FRAGMENT_PALETTE_INDEX,
+    MAX_FRAGMENT_PRIORITY,
+};
+
+/// The maximum number of hardware sprites the DS can display at once
+pub const MAX_OAM_ENTRIES: usize = 128;
+/// The maximum number of VRAM chunks a [`crate::Frame`] can allocate, as the allocation counter of a [`crate::Fragment`] is stored on 10 bits. See [`crate::Frame::compute_fragment_alloc_counter`].
+pub const MAX_FRAGMENT_ALLOC_COUNTER: u16 = 0x3FF;
+
+/// Whether a [`ValidationIssue`] prevent the [`WanImage`] from being written (or loaded by the game), or is only suspicious
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationSeverity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    #[error("The frame {0} has no fragment")]
+    EmptyFrame(usize),
+    #[error("The fragment {fragment} of the frame {frame} reference the FragmentBytes {fragment_bytes}, which doesn't exist")]
+    MissingFragmentBytes {
+        frame: usize,
+        fragment: usize,
+        fragment_bytes: usize,
+    },
+    #[error("The fragment {fragment} of the frame {frame} reference the FragmentBytes {fragment_bytes}, which can't be encoded (the maximum is {})", i16::MAX)]
+    FragmentBytesIndexTooBig {
+        frame: usize,
+        fragment: usize,
+        fragment_bytes: usize,
+    },
+    #[error("The fragment {fragment} of the frame {frame} has the x offset {offset_x}, which isn't in the range -256 to 255")]
+    OffsetXOutOfRange {
+        frame: usize,
+        fragment: usize,
+        offset_x: i16,
+    },
+    #[error("The fragment {fragment} of the frame {frame} use the palette {pal_idx}, while only 16 palettes can be encoded")]
+    PaletteIndexTooBig {
+        frame: usize,
+        fragment: usize,
+        pal_idx: u16,
+    },
+    #[error("The fragment {fragment} of the frame {frame} has the priority {priority}, while only 4 priorities exist")]
+    PriorityTooBig {
+        frame: usize,
+        fragment: usize,
+        priority: u8,
+    },
+    #[error("The FragmentBytes {fragment_bytes} contain {found} pixels, but the fragment {fragment} of the frame {frame} need {expected} of them for its shape {shape:?}")]
+    FragmentBytesSizeMismatch {
+        frame: usize,
+        fragment: usize,
+        fragment_bytes: usize,
+        shape: OamShape,
+        expected: usize,
+        found: usize,
+    },
+    #[error(
+        "The FragmentBytes {fragment_bytes} is used with both the shapes {first:?} and {second:?}"
+    )]
+    InconsistentShape {
+        fragment_bytes: usize,
+        first: OamShape,
+        second: OamShape,
+    },
+    #[error("The fragment {fragment} of the frame {frame} use the color {color} of the palette {pal_idx}, but the palette only has {palette_len} colors")]
+    ColorOutOfPalette {
+        frame: usize,
+        fragment: usize,
+        color: u8,
+        pal_idx: u16,
+        palette_len: usize,
+    },
+    #[error("The frame {frame} use {chunks} VRAM chunks, more than the {} the allocation counter can store", MAX_FRAGMENT_ALLOC_COUNTER)]
+    VramOverflow { frame: usize, chunks: u16 },
+    #[error(
+        "The frame {frame} has {fragments} fragments, more than the {} hardware sprites of the DS",
+        MAX_OAM_ENTRIES
+    )]
+    TooManyFragments { frame: usize, fragments: usize },
+    #[error("The frame {0} has no frame offset, which is required for Chara sprites")]
+    MissingFrameOffset(usize),
+    #[error("The animation frame {animation_frame} of the animation {animation} of the group {group} reference the frame {frame}, which doesn't exist")]
+    MissingFrame {
+        group: usize,
+        animation: usize,
+        animation_frame: usize,
+        frame: usize,
+    },
+    #[error("The animation frame {animation_frame} of the animation {animation} of the group {group} has a duration of 0")]
+    ZeroDuration {
+        group: usize,
+        animation: usize,
+        animation_frame: usize,
+    },
+    #[error("The palette has {0} colors, which isn't a multiple of 16")]
+    PartialPalette(usize),
+    #[error("The color {color} of the palette has an alpha of {alpha}, while the maximum is 128")]
+    InvalidAlpha { color: usize, alpha: u8 },
+    #[error("The FragmentBytes {0} isn't used by any fragment")]
+    UnusedFragmentBytes(usize),
+}
+
+impl ValidationIssue {
+    pub fn severity(&self) -> ValidationSeverity {
+        match self {
+            Self::EmptyFrame(_)
+            | Self::MissingFragmentBytes { .. }
+            | Self::FragmentBytesIndexTooBig { .. }
+            | Self::OffsetXOutOfRange { .. }
+            | Self::PaletteIndexTooBig { .. }
+            | Self::PriorityTooBig { .. }
+            | Self::FragmentBytesSizeMismatch { .. }
+            | Self::VramOverflow { .. }
+            | Self::MissingFrameOffset(_)
+            | Self::MissingFrame { .. } => ValidationSeverity::Error,
+            Self::InconsistentShape { .. }
+            | Self::ColorOutOfPalette { .. }
+            | Self::TooManyFragments { .. }
+            | Self::ZeroDuration { .. }
+            | Self::PartialPalette(_)
+            | Self::InvalidAlpha { .. }
+            | Self::UnusedFragmentBytes(_) => ValidationSeverity::Warning,
+        }
+    }
+}
+
+/// The output of [`WanImage::validate`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity() == ValidationSeverity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity() == ValidationSeverity::Warning)
+    }
+
+    /// true if there is no [`ValidationSeverity::Error`] (there may still be warnings)
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+}
+
+impl WanImage {
+    /// Check this [`WanImage`] for problems that would make it fail to be written, be rejected by the game, or display incorrectly.
+    ///
+    /// All the problems are reported, instead of stopping at the first one.
+    pub fn validate(&self) -> ValidationReport {
+        let mut issues = Vec::new();
+        let fragment_bytes_list = &self.fragment_bytes_store.fragment_bytes;
+        let mut shape_of_fragment_bytes: HashMap<usize, OamShape> = HashMap::new();
+
+        for (frame_nb, frame) in self.frame_store.frames.iter().enumerate() {
+            if frame.fragments.is_empty() {
+                issues.push(ValidationIssue::EmptyFrame(frame_nb));
+            }
+            if self.sprite_type == SpriteType::Chara && frame.frame_offset.is_none() {
+                issues.push(ValidationIssue::MissingFrameOffset(frame_nb));
+            }
+            if frame.fragments.len() > MAX_OAM_ENTRIES {
+                issues.push(ValidationIssue::TooManyFragments {
+                    frame: frame_nb,
+                    fragments: frame.fragments.len(),
+                });
+            }
+            let usage = FrameBudgetUsage::new(frame_nb, frame, self.is_256_color);
+            if usage.exceeds_vram(&HardwareBudget::default()) {
+                issues.push(ValidationIssue::VramOverflow {
+                    frame: frame_nb,
+                    chunks: usage.vram_chunks,
+                });
+            }
+
+            for (fragment_nb, fragment) in frame.fragments.iter().enumerate() {
+                if !(-256..256).contains(&fragment.offset_x) {
+                    issues.push(ValidationIssue::OffsetXOutOfRange {
+                        frame: frame_nb,
+                        fragment: fragment_nb,
+                        offset_x: fragment.offset_x,
+                    });
+                }
+                if fragment.pal_idx > MAX_FRAGMENT_PALETTE_INDEX {
+                    issues.push(ValidationIssue::PaletteIndexTooBig {
+                        frame: frame_nb,
+                        fragment: fragment_nb,
+                        pal_idx: fragment.pal_idx,
+                    });
+                }
+                if fragment.priority > MAX_FRAGMENT_PRIORITY {
+                    issues.push(ValidationIssue::PriorityTooBig {
+                        frame: frame_nb,
+                        fragment: fragment_nb,
+                        priority: fragment.priority,
+                    });
+                }
+                if fragment.fragment_bytes_index > i16::MAX as usize {
+                    issues.push(ValidationIssue::FragmentBytesIndexTooBig {
+                        frame: frame_nb,
+                        fragment: fragment_nb,
+                        fragment_bytes: fragment.fragment_bytes_index,
+                    });
+                }
+                let fragment_bytes = match fragment_bytes_list.get(fragment.fragment_bytes_index) {
+                    Some(fragment_bytes) => fragment_bytes,
+                    None => {
+                        issues.push(ValidationIssue::MissingFragmentBytes {
+                            frame: frame_nb,
+                            fragment: fragment_nb,
+                            fragment_bytes: fragment.fragment_bytes_index,
+                        });
+                        continue;
+                    }
+                };
+
+                match shape_of_fragment_bytes.get(&fragment.fragment_bytes_index) {
+                    Some(first) if *first != fragment.resolution => {
+                        issues.push(ValidationIssue::InconsistentShape {
+                            fragment_bytes: fragment.fragment_bytes_index,
+                            first: *first,
+                            second: fragment.resolution,
+                        })
+                    }
+                    Some(_) => (),
+                    None => {
+                        shape_of_fragment_bytes
+                            .insert(fragment.fragment_bytes_index, fragment.resolution);
+                    }
+                }
+
+                let resolution = fragment.resolution.size();
+                let pixel_amount = resolution.nb_pixels() as usize;
+                let expected = if self.is_256_color {
+                    pixel_amount * 2
+                } else {
+                    pixel_amount
+                };
+                if fragment_bytes.mixed_pixels.len() < expected {
+                    issues.push(ValidationIssue::FragmentBytesSizeMismatch {
+                        frame: frame_nb,
+                        fragment: fragment_nb,
+                        fragment_bytes: fragment.fragment_bytes_index,
+                        shape: fragment.resolution,
+                        expected,
+                        found: fragment_bytes.mixed_pixels.len(),
+                    });
+                    continue;
+                }
+
+                if let Ok(pixels) = fragment_bytes.decode_pixels(resolution, self.is_256_color) {
+                    if let Some(color) = pixels.iter().copied().max() {
+                        if color != 0
+                            && self
+                                .palette
+                                .get_with_depth(color, fragment.pal_idx, self.is_256_color)
+                                .is_none()
+                        {
+                            issues.push(ValidationIssue::ColorOutOfPalette {
+                                frame: frame_nb,
+                                fragment: fragment_nb,
+                                color,
+                                pal_idx: fragment.pal_idx,
+                                palette_len: self.palette.palette.len(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        for (group_nb, group) in self.animation_store.anim_groups.iter().enumerate() {
+            for (animation_nb, animation) in group.iter().enumerate() {
+                for (animation_frame_nb, animation_frame) in animation.frames.iter().enumerate() {
+                    if animation_frame.frame_id as usize >= self.frame_store.frames.len() {
+                        issues.push(ValidationIssue::MissingFrame {
+                            group: group_nb,
+                            animation: animation_nb,
+                            animation_frame: animation_frame_nb,
+                            frame: animation_frame.frame_id as usize,
+                        });
+                    }
+                    if animation_frame.duration == 0 {
+                        issues.push(ValidationIssue::ZeroDuration {
+                            group: group_nb,
+                            animation: animation_nb,
+                            animation_frame: animation_frame_nb,
+                        });
+                    }
+                }
+            }
+        }
+
+        if !self.palette.palette.len().is_multiple_of(16) {
+            issues.push(ValidationIssue::PartialPalette(self.palette.palette.len()));
+        }
+        for (color_nb, color) in self.palette.palette.iter().enumerate() {
+            if color[3] > 128 {
+                issues.push(ValidationIssue::InvalidAlpha {
+                    color: color_nb,
+                    alpha: color[3],
+                });
+            }
+        }
+
+        for fragment_bytes_nb in 0..fragment_bytes_list.len() {
+            if !shape_of_fragment_bytes.contains_key(&fragment_bytes_nb) {
+                issues.push(ValidationIssue::UnusedFragmentBytes(fragment_bytes_nb));
+            }
+        }
+
+        ValidationReport { issues }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::fixtures::{animation_frame, insert_filled_frame, test_wan_image},
+        Frame, SpriteType, ValidationIssue, ValidationSeverity,
+    };
+
+    #[test]
+    fn test_validate() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.resize(16, [0, 0, 0, 0]);
+        insert_filled_frame(&mut wanimage, 2, 2);
+        assert!(wanimage.validate().issues.is_empty());
+
+        wanimage.frame_store.frames[0].fragments[0].pal_idx = 1;
+        wanimage.frame_store.frames.push(Frame::default());
+        wanimage
+            .animation_store
+            .anim_groups
+            .push(vec![Default::default()]);
+        wanimage.animation_store.anim_groups[0][0]
+            .frames
+            .push(animation_frame(5, 0));
+        let report = wanimage.validate();
+        assert!(!report.is_valid());
+        assert_eq!(
+            report.issues,
+            vec![
+                ValidationIssue::ColorOutOfPalette {
+                    frame: 0,
+                    fragment: 0,
+                    color: 1,
+                    pal_idx: 1,
+                    palette_len: 16
+                },
+                ValidationIssue::EmptyFrame(1),
+                ValidationIssue::MissingFrame {
+                    group: 0,
+                    animation: 0,
+                    animation_frame: 0,
+                    frame: 5
+                },
+                ValidationIssue::ZeroDuration {
+                    group: 0,
+                    animation: 0,
+                    animation_frame: 0
+                },
+            ]
+        );
+        assert_eq!(report.errors().count(), 2);
+        assert_eq!(report.issues[0].severity(), ValidationSeverity::Warning);
+    }
+
+    #[test]
+    fn test_validate_vram_overflow() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        wanimage.palette.palette.resize(16, [0, 0, 0, 0]);
+        insert_filled_frame(&mut wanimage, 64, 64);
+        let frame = &mut wanimage.frame_store.frames[0];
+        // each 64×64 fragment allocate 16 chunks
+        frame.fragments = vec![frame.fragments[0].clone(); 64];
+        assert_eq!(
+            wanimage.validate().issues,
+            vec![ValidationIssue::VramOverflow {
+                frame: 0,
+                chunks: 1024
+            }]
+        );
+        wanimage.frame_store.frames[0].fragments.pop();
+        assert!(wanimage.validate().issues.is_empty());
+    }
+}
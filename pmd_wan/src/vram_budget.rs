@@ -0,0 +1,153 @@
+use crate::{Frame, WanImage, MAX_FRAGMENT_ALLOC_COUNTER, MAX_OAM_ENTRIES};
+
+/// The side (in pixel) of the square area a VRAM chunk hold, as counted by [`crate::OamShape::chunk_to_allocate_for_fragment`]
+pub const VRAM_CHUNK_SIDE: usize = 16;
+
+/// The limits a single [`Frame`] should fit in to be displayed.
+///
+/// The default limits are the ones of the hardware and of the file format. The game share them between every sprite on screen, so callers that know how many sprites will be displayed at once may want to use lower ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HardwareBudget {
+    /// The maximum number of hardware sprites (and so of [`crate::Fragment`]s) of a frame
+    pub max_oam_entries: usize,
+    /// The maximum number of VRAM chunks a frame can allocate
+    pub max_vram_chunks: u16,
+}
+
+impl Default for HardwareBudget {
+    fn default() -> Self {
+        Self {
+            max_oam_entries: MAX_OAM_ENTRIES,
+            max_vram_chunks: MAX_FRAGMENT_ALLOC_COUNTER,
+        }
+    }
+}
+
+/// The hardware resources used to display a single [`Frame`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameBudgetUsage {
+    pub frame: usize,
+    pub oam_entries: usize,
+    pub vram_chunks: u16,
+    /// The size of the tile data of the allocated chunks
+    pub vram_bytes: usize,
+}
+
+impl FrameBudgetUsage {
+    pub fn new(frame_id: usize, frame: &Frame, is_256_color: bool) -> Self {
+        let vram_chunks = frame.compute_fragment_alloc_counter();
+        let bytes_per_chunk = if is_256_color {
+            VRAM_CHUNK_SIDE * VRAM_CHUNK_SIDE
+        } else {
+            VRAM_CHUNK_SIDE * VRAM_CHUNK_SIDE / 2
+        };
+        Self {
+            frame: frame_id,
+            oam_entries: frame.fragments.len(),
+            vram_chunks,
+            vram_bytes: vram_chunks as usize * bytes_per_chunk,
+        }
+    }
+
+    pub fn exceeds_oam(&self, budget: &HardwareBudget) -> bool {
+        self.oam_entries > budget.max_oam_entries
+    }
+
+    pub fn exceeds_vram(&self, budget: &HardwareBudget) -> bool {
+        self.vram_chunks > budget.max_vram_chunks
+    }
+
+    pub fn exceeds(&self, budget: &HardwareBudget) -> bool {
+        self.exceeds_oam(budget) || self.exceeds_vram(budget)
+    }
+}
+
+/// The output of [`WanImage::check_hardware_budget`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HardwareBudgetReport {
+    pub budget: HardwareBudget,
+    /// The usage of every frame, in the same order as the frames
+    pub frames: Vec<FrameBudgetUsage>,
+}
+
+impl HardwareBudgetReport {
+    /// Return the frames that doesn't fit in the budget
+    pub fn over_budget(&self) -> impl Iterator<Item = &FrameBudgetUsage> {
+        self.frames
+            .iter()
+            .filter(move |usage| usage.exceeds(&self.budget))
+    }
+
+    pub fn is_within_budget(&self) -> bool {
+        self.over_budget().next().is_none()
+    }
+
+    /// The most hardware sprites used by a single frame
+    pub fn max_oam_entries(&self) -> usize {
+        self.frames
+            .iter()
+            .map(|usage| usage.oam_entries)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The most VRAM chunks used by a single frame
+    pub fn max_vram_chunks(&self) -> u16 {
+        self.frames
+            .iter()
+            .map(|usage| usage.vram_chunks)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl WanImage {
+    /// Compute the number of hardware sprites and VRAM chunks used by every frame, and check them against the given budget.
+    ///
+    /// Unlike [`WanImage::validate`], frames that exceed the budget can still be written, but may be displayed incorrectly or crash the game.
+    pub fn check_hardware_budget(&self, budget: HardwareBudget) -> HardwareBudgetReport {
+        HardwareBudgetReport {
+            budget,
+            frames: self
+                .frame_store
+                .frames
+                .iter()
+                .enumerate()
+                .map(|(frame_id, frame)| FrameBudgetUsage::new(frame_id, frame, self.is_256_color))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::fixtures::{insert_filled_frame, test_wan_image},
+        HardwareBudget, SpriteType,
+    };
+
+    #[test]
+    fn test_hardware_budget() {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        // a single 8×8 fragment
+        insert_filled_frame(&mut wanimage, 8, 8);
+        // four 64×64 fragments
+        insert_filled_frame(&mut wanimage, 128, 128);
+
+        let report = wanimage.check_hardware_budget(HardwareBudget::default());
+        assert!(report.is_within_budget());
+        assert_eq!(report.frames[0].vram_chunks, 1);
+        assert_eq!(report.frames[0].vram_bytes, 128);
+        assert_eq!(report.frames[1].oam_entries, 4);
+        assert_eq!(report.max_vram_chunks(), 64);
+
+        let report = wanimage.check_hardware_budget(HardwareBudget {
+            max_oam_entries: 2,
+            max_vram_chunks: 32,
+        });
+        let over: Vec<usize> = report.over_budget().map(|usage| usage.frame).collect();
+        assert_eq!(over, vec![1]);
+        assert!(report.frames[1].exceeds_oam(&report.budget));
+        assert!(report.frames[1].exceeds_vram(&report.budget));
+    }
+}
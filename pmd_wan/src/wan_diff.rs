@@ -0,0 +1,250 @@
+use crate::{Fragment, WanImage};
+
+/// How an element differ between the old and the new [`WanImage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The element only exist in the new [`WanImage`]
+    Added,
+    /// The element only exist in the old [`WanImage`]
+    Removed,
+    /// The element exist in both, but differ
+    Changed,
+}
+
+impl ChangeKind {
+    fn from_presence<T>(old: Option<T>, new: Option<T>) -> Self {
+        match (old, new) {
+            (None, _) => Self::Added,
+            (_, None) => Self::Removed,
+            _ => Self::Changed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentBytesDiff {
+    pub index: usize,
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentDiff {
+    pub fragment: usize,
+    pub kind: ChangeKind,
+    pub old: Option<Fragment>,
+    pub new: Option<Fragment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameDiff {
+    pub frame: usize,
+    pub kind: ChangeKind,
+    /// true if the [`crate::FrameOffset`] of a changed frame differ
+    pub frame_offset_changed: bool,
+    /// The changes to the [`Fragment`]s of a changed frame. Empty for added and removed frames.
+    pub fragments: Vec<FragmentDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnimationDiff {
+    pub group: usize,
+    pub animation: usize,
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteEntryDiff {
+    pub color: usize,
+    pub kind: ChangeKind,
+    pub old: Option<[u8; 4]>,
+    pub new: Option<[u8; 4]>,
+}
+
+/// The differences between two [`WanImage`], as computed by [`wan_diff`].
+///
+/// Elements are compared by position: inserting an element in the middle of a list will show all the following ones as changed.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WanDiff {
+    /// true if the sprite type, color depth, unknown header value or compression differ
+    pub header_changed: bool,
+    pub fragment_bytes: Vec<FragmentBytesDiff>,
+    pub frames: Vec<FrameDiff>,
+    pub animations: Vec<AnimationDiff>,
+    pub palette: Vec<PaletteEntryDiff>,
+}
+
+impl WanDiff {
+    /// true if the two [`WanImage`] are identical
+    pub fn is_empty(&self) -> bool {
+        !self.header_changed
+            && self.fragment_bytes.is_empty()
+            && self.frames.is_empty()
+            && self.animations.is_empty()
+            && self.palette.is_empty()
+    }
+}
+
+/// Call `on_difference` with the index and the old and new value of every position where the two lists differ
+fn diff_lists<'a, T: PartialEq, F: FnMut(usize, Option<&'a T>, Option<&'a T>)>(
+    old: &'a [T],
+    new: &'a [T],
+    mut on_difference: F,
+) {
+    for index in 0..old.len().max(new.len()) {
+        let old_value = old.get(index);
+        let new_value = new.get(index);
+        if old_value != new_value {
+            on_difference(index, old_value, new_value);
+        }
+    }
+}
+
+/// Compute the differences between two [`WanImage`], to review what an edit changed.
+pub fn wan_diff(old: &WanImage, new: &WanImage) -> WanDiff {
+    let mut diff = WanDiff {
+        header_changed: old.sprite_type != new.sprite_type
+            || old.is_256_color != new.is_256_color
+            || old.unk2 != new.unk2
+            || old.unknowns != new.unknowns
+            || old.compression != new.compression,
+        ..Default::default()
+    };
+
+    diff_lists(
+        &old.fragment_bytes_store.fragment_bytes,
+        &new.fragment_bytes_store.fragment_bytes,
+        |index, old, new| {
+            diff.fragment_bytes.push(FragmentBytesDiff {
+                index,
+                kind: ChangeKind::from_presence(old, new),
+            })
+        },
+    );
+
+    diff_lists(
+        &old.frame_store.frames,
+        &new.frame_store.frames,
+        |frame, old_frame, new_frame| {
+            let mut frame_diff = FrameDiff {
+                frame,
+                kind: ChangeKind::from_presence(old_frame, new_frame),
+                frame_offset_changed: false,
+                fragments: Vec::new(),
+            };
+            if let (Some(old_frame), Some(new_frame)) = (old_frame, new_frame) {
+                frame_diff.frame_offset_changed = old_frame.frame_offset != new_frame.frame_offset;
+                diff_lists(
+                    &old_frame.fragments,
+                    &new_frame.fragments,
+                    |fragment, old, new| {
+                        frame_diff.fragments.push(FragmentDiff {
+                            fragment,
+                            kind: ChangeKind::from_presence(old, new),
+                            old: old.cloned(),
+                            new: new.cloned(),
+                        })
+                    },
+                );
+            }
+            diff.frames.push(frame_diff);
+        },
+    );
+
+    let empty_group = Vec::new();
+    let old_groups = &old.animation_store.anim_groups;
+    let new_groups = &new.animation_store.anim_groups;
+    for group in 0..old_groups.len().max(new_groups.len()) {
+        diff_lists(
+            old_groups.get(group).unwrap_or(&empty_group),
+            new_groups.get(group).unwrap_or(&empty_group),
+            |animation, old, new| {
+                diff.animations.push(AnimationDiff {
+                    group,
+                    animation,
+                    kind: ChangeKind::from_presence(old, new),
+                })
+            },
+        );
+    }
+
+    diff_lists(
+        &old.palette.palette,
+        &new.palette.palette,
+        |color, old, new| {
+            diff.palette.push(PaletteEntryDiff {
+                color,
+                kind: ChangeKind::from_presence(old, new),
+                old: old.copied(),
+                new: new.copied(),
+            })
+        },
+    );
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        insert_frame_in_wanimage,
+        tests::fixtures::{insert_filled_frame, test_wan_image},
+        wan_diff, Animation, ChangeKind, FrameDiff, PaletteEntryDiff, SpriteType, WanImage,
+    };
+
+    fn create_wan_image() -> WanImage {
+        let mut wanimage = test_wan_image(SpriteType::PropsUI);
+        insert_frame_in_wanimage(vec![1, 0, 0, 1], 2, 2, &mut wanimage, 0).unwrap();
+        wanimage
+    }
+
+    #[test]
+    fn test_wan_diff() {
+        let old = create_wan_image();
+        assert!(wan_diff(&old, &create_wan_image()).is_empty());
+
+        let mut new = create_wan_image();
+        new.palette.palette[1] = [0, 255, 0, 128];
+        new.palette.palette.push([0, 0, 255, 128]);
+        new.frame_store.frames[0].fragments[0].offset_y -= 1;
+        new.animation_store.anim_groups = vec![vec![Animation::default()]];
+        insert_filled_frame(&mut new, 8, 8);
+
+        let diff = wan_diff(&old, &new);
+        assert!(!diff.header_changed);
+        assert_eq!(diff.fragment_bytes.len(), 1);
+        assert_eq!(diff.fragment_bytes[0].kind, ChangeKind::Added);
+        assert_eq!(diff.frames.len(), 2);
+        assert_eq!(diff.frames[0].kind, ChangeKind::Changed);
+        assert_eq!(diff.frames[0].fragments.len(), 1);
+        assert_eq!(diff.frames[0].fragments[0].kind, ChangeKind::Changed);
+        assert_eq!(
+            diff.frames[1],
+            FrameDiff {
+                frame: 1,
+                kind: ChangeKind::Added,
+                frame_offset_changed: false,
+                fragments: Vec::new(),
+            }
+        );
+        assert_eq!(diff.animations.len(), 1);
+        assert_eq!(diff.animations[0].kind, ChangeKind::Added);
+        assert_eq!(
+            diff.palette,
+            vec![
+                PaletteEntryDiff {
+                    color: 1,
+                    kind: ChangeKind::Changed,
+                    old: Some([255, 0, 0, 128]),
+                    new: Some([0, 255, 0, 128]),
+                },
+                PaletteEntryDiff {
+                    color: 2,
+                    kind: ChangeKind::Added,
+                    old: None,
+                    new: Some([0, 0, 255, 128]),
+                },
+            ]
+        );
+        assert_eq!(wan_diff(&new, &old).palette[1].kind, ChangeKind::Removed);
+    }
+}
@@ -0,0 +1,416 @@
+use thiserror::Error;
+
+use crate::{Animation, AnimationEditError, Fragment, FragmentBytes, Frame, WanImage};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum WanEditError {
+    #[error("The frame {0} doesn't exist")]
+    NoFrame(usize),
+    #[error("The fragment {fragment} doesn't exist in the frame {frame}")]
+    NoFragment { frame: usize, fragment: usize },
+    #[error("The FragmentBytes {0} doesn't exist")]
+    NoFragmentBytes(usize),
+    #[error("The palette color {0} doesn't exist")]
+    NoPaletteColor(usize),
+    #[error(transparent)]
+    Animation(#[from] AnimationEditError),
+}
+
+/// A reversible modification of a [`WanImage`], applied with a [`WanEditSession`].
+///
+/// Insertion indices can be equal to the length of the list, to add at the end. Inserting or removing a [`Frame`] doesn't update the [`crate::AnimationFrame::frame_id`] referring to the following ones.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum WanEdit {
+    SetFrame {
+        frame: usize,
+        value: Frame,
+    },
+    InsertFrame {
+        frame: usize,
+        value: Frame,
+    },
+    RemoveFrame {
+        frame: usize,
+    },
+    SetFragment {
+        frame: usize,
+        fragment: usize,
+        value: Fragment,
+    },
+    InsertFragment {
+        frame: usize,
+        fragment: usize,
+        value: Fragment,
+    },
+    RemoveFragment {
+        frame: usize,
+        fragment: usize,
+    },
+    SetFragmentBytes {
+        fragment_bytes: usize,
+        value: FragmentBytes,
+    },
+    /// Add a [`FragmentBytes`] at the end of the [`crate::FragmentBytesStore`]
+    PushFragmentBytes {
+        value: FragmentBytes,
+    },
+    /// Remove the last [`FragmentBytes`] of the [`crate::FragmentBytesStore`]
+    PopFragmentBytes,
+    SetAnimation {
+        group: usize,
+        animation: usize,
+        value: Animation,
+    },
+    InsertAnimation {
+        group: usize,
+        animation: usize,
+        value: Animation,
+    },
+    RemoveAnimation {
+        group: usize,
+        animation: usize,
+    },
+    InsertAnimationGroup {
+        group: usize,
+        animations: Vec<Animation>,
+    },
+    RemoveAnimationGroup {
+        group: usize,
+    },
+    SetPaletteColor {
+        color: usize,
+        value: [u8; 4],
+    },
+    /// Replace every color of the [`crate::Palette`]
+    SetPalette {
+        colors: Vec<[u8; 4]>,
+    },
+}
+
+impl WanEdit {
+    /// Apply this edit on `image`, and return the edit that revert it
+    fn apply(self, image: &mut WanImage) -> Result<WanEdit, WanEditError> {
+        let frames = &mut image.frame_store.frames;
+        Ok(match self {
+            Self::SetFrame { frame, value } => {
+                let target = frames.get_mut(frame).ok_or(WanEditError::NoFrame(frame))?;
+                Self::SetFrame {
+                    frame,
+                    value: std::mem::replace(target, value),
+                }
+            }
+            Self::InsertFrame { frame, value } => {
+                if frame > frames.len() {
+                    return Err(WanEditError::NoFrame(frame));
+                }
+                frames.insert(frame, value);
+                Self::RemoveFrame { frame }
+            }
+            Self::RemoveFrame { frame } => {
+                if frame >= frames.len() {
+                    return Err(WanEditError::NoFrame(frame));
+                }
+                Self::InsertFrame {
+                    frame,
+                    value: frames.remove(frame),
+                }
+            }
+            Self::SetFragment {
+                frame,
+                fragment,
+                value,
+            } => {
+                let target = frames
+                    .get_mut(frame)
+                    .ok_or(WanEditError::NoFrame(frame))?
+                    .fragments
+                    .get_mut(fragment)
+                    .ok_or(WanEditError::NoFragment { frame, fragment })?;
+                Self::SetFragment {
+                    frame,
+                    fragment,
+                    value: std::mem::replace(target, value),
+                }
+            }
+            Self::InsertFragment {
+                frame,
+                fragment,
+                value,
+            } => {
+                let fragments = &mut frames
+                    .get_mut(frame)
+                    .ok_or(WanEditError::NoFrame(frame))?
+                    .fragments;
+                if fragment > fragments.len() {
+                    return Err(WanEditError::NoFragment { frame, fragment });
+                }
+                fragments.insert(fragment, value);
+                Self::RemoveFragment { frame, fragment }
+            }
+            Self::RemoveFragment { frame, fragment } => {
+                let fragments = &mut frames
+                    .get_mut(frame)
+                    .ok_or(WanEditError::NoFrame(frame))?
+                    .fragments;
+                if fragment >= fragments.len() {
+                    return Err(WanEditError::NoFragment { frame, fragment });
+                }
+                Self::InsertFragment {
+                    frame,
+                    fragment,
+                    value: fragments.remove(fragment),
+                }
+            }
+            Self::SetFragmentBytes {
+                fragment_bytes,
+                value,
+            } => {
+                let target = image
+                    .fragment_bytes_store
+                    .fragment_bytes
+                    .get_mut(fragment_bytes)
+                    .ok_or(WanEditError::NoFragmentBytes(fragment_bytes))?;
+                Self::SetFragmentBytes {
+                    fragment_bytes,
+                    value: std::mem::replace(target, value),
+                }
+            }
+            Self::PushFragmentBytes { value } => {
+                image.fragment_bytes_store.fragment_bytes.push(value);
+                Self::PopFragmentBytes
+            }
+            Self::PopFragmentBytes => Self::PushFragmentBytes {
+                value: image
+                    .fragment_bytes_store
+                    .fragment_bytes
+                    .pop()
+                    .ok_or(WanEditError::NoFragmentBytes(0))?,
+            },
+            Self::SetAnimation {
+                group,
+                animation,
+                value,
+            } => {
+                let target = image
+                    .animation_store
+                    .anim_groups
+                    .get_mut(group)
+                    .ok_or(AnimationEditError::NoGroup(group))?
+                    .get_mut(animation)
+                    .ok_or(AnimationEditError::NoAnimation { group, animation })?;
+                Self::SetAnimation {
+                    group,
+                    animation,
+                    value: std::mem::replace(target, value),
+                }
+            }
+            Self::InsertAnimation {
+                group,
+                animation,
+                value,
+            } => {
+                image
+                    .animation_store
+                    .insert_animation(group, animation, value)?;
+                Self::RemoveAnimation { group, animation }
+            }
+            Self::RemoveAnimation { group, animation } => Self::InsertAnimation {
+                group,
+                animation,
+                value: image.animation_store.remove_animation(group, animation)?,
+            },
+            Self::InsertAnimationGroup { group, animations } => {
+                image.animation_store.insert_group(group, animations)?;
+                Self::RemoveAnimationGroup { group }
+            }
+            Self::RemoveAnimationGroup { group } => Self::InsertAnimationGroup {
+                group,
+                animations: image.animation_store.remove_group(group)?,
+            },
+            Self::SetPaletteColor { color, value } => {
+                let target = image
+                    .palette
+                    .palette
+                    .get_mut(color)
+                    .ok_or(WanEditError::NoPaletteColor(color))?;
+                Self::SetPaletteColor {
+                    color,
+                    value: std::mem::replace(target, value),
+                }
+            }
+            Self::SetPalette { colors } => Self::SetPalette {
+                colors: std::mem::replace(&mut image.palette.palette, colors),
+            },
+        })
+    }
+}
+
+/// Apply the edits in order, and return the edits that revert all of them (to apply in order too). If one fail, the previous ones are reverted.
+fn apply_transaction(
+    image: &mut WanImage,
+    edits: Vec<WanEdit>,
+) -> Result<Vec<WanEdit>, WanEditError> {
+    let mut reverts = Vec::with_capacity(edits.len());
+    for edit in edits {
+        match edit.apply(image) {
+            Ok(revert) => reverts.push(revert),
+            Err(error) => {
+                for revert in reverts.into_iter().rev() {
+                    // no panic: a revert always apply on the state its edit left
+                    revert.apply(image).unwrap();
+                }
+                return Err(error);
+            }
+        }
+    }
+    reverts.reverse();
+    Ok(reverts)
+}
+
+/// Edit a [`WanImage`] with [`WanEdit`]s, recording how to revert them, so they can be undone and redone without keeping a copy of the whole image.
+///
+/// The image is mutably borrowed for the lifetime of the session, so nothing else can modify it in between. [`crate::AnimationStore::copied_on_previous`] may not be restored exactly when undoing the removal of an [`Animation`].
+#[derive(Debug)]
+pub struct WanEditSession<'a> {
+    image: &'a mut WanImage,
+    undo_stack: Vec<Vec<WanEdit>>,
+    redo_stack: Vec<Vec<WanEdit>>,
+}
+
+impl<'a> WanEditSession<'a> {
+    pub fn new(image: &'a mut WanImage) -> Self {
+        Self {
+            image,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+
+    /// The edited image, in its current state
+    pub fn image(&self) -> &WanImage {
+        self.image
+    }
+
+    /// Apply an edit, as a single undo step. This discard the edits that could be redone.
+    pub fn apply(&mut self, edit: WanEdit) -> Result<(), WanEditError> {
+        self.apply_all(vec![edit])
+    }
+
+    /// Apply several edits in order, as a single undo step. If one of them fail, none is applied.
+    pub fn apply_all(&mut self, edits: Vec<WanEdit>) -> Result<(), WanEditError> {
+        let reverts = apply_transaction(self.image, edits)?;
+        self.undo_stack.push(reverts);
+        self.redo_stack.clear();
+        Ok(())
+    }
+
+    /// Revert the last applied step. Return false if there is nothing to undo.
+    pub fn undo(&mut self) -> Result<bool, WanEditError> {
+        match self.undo_stack.pop() {
+            Some(reverts) => {
+                self.redo_stack
+                    .push(apply_transaction(self.image, reverts)?);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Apply again the last undone step. Return false if there is nothing to redo.
+    pub fn redo(&mut self) -> Result<bool, WanEditError> {
+        match self.redo_stack.pop() {
+            Some(edits) => {
+                self.undo_stack.push(apply_transaction(self.image, edits)?);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Forget every recorded step, keeping the image as it is
+    pub fn clear_history(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::fixtures::{animation_frame, single_frame_wan_image},
+        Animation, WanEdit, WanEditError, WanEditSession,
+    };
+
+    #[test]
+    fn test_edit_session() {
+        let mut wanimage = single_frame_wan_image();
+        wanimage
+            .animation_store
+            .anim_groups
+            .push(vec![Animation::default()]);
+        let original_frames = wanimage.frame_store.frames.clone();
+
+        let mut session = WanEditSession::new(&mut wanimage);
+        assert!(!session.undo().unwrap());
+        session
+            .apply(WanEdit::SetPaletteColor {
+                color: 1,
+                value: [0, 0, 255, 128],
+            })
+            .unwrap();
+        let mut moved = session.image().frame_store.frames[0].fragments[0].clone();
+        moved.offset_x += 4;
+        session
+            .apply_all(vec![
+                WanEdit::SetFragment {
+                    frame: 0,
+                    fragment: 0,
+                    value: moved,
+                },
+                WanEdit::InsertAnimation {
+                    group: 0,
+                    animation: 1,
+                    value: Animation {
+                        frames: vec![animation_frame(0, 2)],
+                    },
+                },
+            ])
+            .unwrap();
+        assert_eq!(session.image().animation_store.anim_groups[0].len(), 2);
+
+        // a failing step is not applied at all
+        assert_eq!(
+            session.apply_all(vec![
+                WanEdit::RemoveFrame { frame: 0 },
+                WanEdit::RemoveFrame { frame: 0 }
+            ]),
+            Err(WanEditError::NoFrame(0))
+        );
+        assert_eq!(session.image().frame_store.frames.len(), 1);
+
+        assert!(session.undo().unwrap());
+        assert_eq!(session.image().frame_store.frames, original_frames);
+        assert_eq!(session.image().animation_store.anim_groups[0].len(), 1);
+        assert!(session.undo().unwrap());
+        assert_eq!(session.image().palette.palette[1], [255, 0, 0, 128]);
+        assert!(!session.can_undo());
+
+        assert!(session.redo().unwrap());
+        assert!(session.redo().unwrap());
+        assert!(!session.can_redo());
+        assert_eq!(session.image().palette.palette[1], [0, 0, 255, 128]);
+        assert_eq!(
+            session.image().frame_store.frames[0].fragments[0].offset_x,
+            original_frames[0].fragments[0].offset_x + 4
+        );
+        assert_eq!(session.image().animation_store.anim_groups[0].len(), 2);
+    }
+}
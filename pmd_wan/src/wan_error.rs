@@ -1,6 +1,29 @@
+use std::fmt::{self, Write};
 use std::io;
 use thiserror::Error;
 
+use crate::WanSection;
+
+/// Where a [`WanError`] happened while decoding a wan file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorLocation {
+    /// The absolute position in the file where the error was detected
+    pub offset: u64,
+    pub section: WanSection,
+    /// The index of the item being parsed in the section, if any: the frame, the [`crate::FragmentBytes`] or the animation group
+    pub item: Option<usize>,
+}
+
+impl fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} section", self.section)?;
+        if let Some(item) = self.item {
+            write!(f, " (item {})", item)?;
+        }
+        write!(f, ", at offset 0x{:X} ({})", self.offset, self.offset)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum WanError {
     #[error("an input/output error happened")]
@@ -21,6 +44,8 @@ pub enum WanError {
     IncoherentPointerToFragmentBytesPart,
     #[error("An FragmentBytes buffer is empty")]
     EmptyFragmentBytes,
+    #[error("The compressor split the FragmentBytes in invalid runs, starting at the pixel {0}. Runs should cover all the pixels, contain an even number of pixels, and transparent runs should only contain transparent pixels")]
+    InvalidCompressedRun(usize),
     #[error("an invalid alpha level was found in the picture")]
     ImpossibleAlphaLevel,
     #[error("an fragment bytes pointer is null")]
@@ -37,7 +62,11 @@ pub enum WanError {
     InvalidSir0([u8; 4]),
     #[error("the end of the sir0 header should be four 0, found {0:?}")]
     InvalidEndOfSir0Header([u8; 4]),
-    #[error("the type of sprite is unknown (found the sprite type id {0}, but this program only known sprite for [0, 1, 3])")]
+    #[error("the sir0 container is invalid")]
+    Sir0ContainerError(#[from] crate::Sir0ContainerError),
+    #[error("the PX container is invalid")]
+    PxError(#[from] crate::PxError),
+    #[error("the type of sprite is unknown (found the sprite type id {0}, but this program only known sprite for [0, 1, 2, 3])")]
     TypeOfSpriteUnknown(u16),
     #[error("the 2 byte that indicate the number of color is invalid (found {0}, expected 0 or 1")]
     InvalidColorNumber(u16),
@@ -61,4 +90,166 @@ pub enum WanError {
     NonExistenceFrameOffsetForChara,
     #[error("There is a frame that doesn’t have a frame offset in a Chara sprite")]
     NoOffsetDataForFrame,
+    #[error("there are more {0} than the limit of {1} set in the DecodeLimits")]
+    LimitExceeded(&'static str, usize),
+}
+
+/// The number of bytes shown on each line of [`WanError::annotated_report`]
+const REPORT_BYTES_PER_LINE: u64 = 16;
+
+/// A [`WanError`], with where it happened in the decoded file. Returned by [`crate::WanImage::decode_wan_with_location`].
+#[derive(Debug)]
+pub struct LocatedWanError {
+    pub error: WanError,
+    /// [`None`] if the error didn't happen while decoding a section
+    pub location: Option<ErrorLocation>,
+}
+
+impl<E: Into<WanError>> From<E> for LocatedWanError {
+    fn from(error: E) -> Self {
+        Self {
+            error: error.into(),
+            location: None,
+        }
+    }
+}
+
+impl fmt::Display for LocatedWanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "can't decode the {}", location),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for LocatedWanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.location {
+            Some(_) => Some(&self.error),
+            None => self.error.source(),
+        }
+    }
+}
+
+impl LocatedWanError {
+    /// Add the location to this error, unless it already has one. `offset` is the start of the item (or of the section if there is no item) being decoded.
+    pub(crate) fn locate(mut self, section: WanSection, item: Option<usize>, offset: u64) -> Self {
+        if self.location.is_none() {
+            self.location = Some(ErrorLocation {
+                offset,
+                section,
+                item,
+            });
+        }
+        self
+    }
+
+    /// A human readable description of this error, with its causes, its location, and an hexadecimal dump of `file` (the decoded file) around the location
+    pub fn annotated_report(&self, file: &[u8]) -> String {
+        let mut report = String::new();
+        // no panic: writing to a String can't fail
+        writeln!(report, "error: {}", self.error).unwrap();
+        let mut source = std::error::Error::source(&self.error);
+        while let Some(error) = source {
+            writeln!(report, "caused by: {}", error).unwrap();
+            source = error.source();
+        }
+        let location = match &self.location {
+            Some(location) => location,
+            None => return report,
+        };
+        writeln!(report, "section: {}", location.section).unwrap();
+        if let Some(item) = location.item {
+            writeln!(report, "item: {}", item).unwrap();
+        }
+        writeln!(
+            report,
+            "offset: 0x{:08X} ({})",
+            location.offset, location.offset
+        )
+        .unwrap();
+        let file_len = file.len() as u64;
+        if location.offset >= file_len {
+            writeln!(
+                report,
+                "the offset is after the end of the file, which is {} bytes long",
+                file_len
+            )
+            .unwrap();
+        }
+        if file.is_empty() {
+            return report;
+        }
+
+        let error_offset = location.offset.min(file_len - 1);
+        let error_line = error_offset / REPORT_BYTES_PER_LINE;
+        report.push('\n');
+        for line in error_line.saturating_sub(2)..=error_line + 2 {
+            let start = line * REPORT_BYTES_PER_LINE;
+            if start >= file_len {
+                break;
+            }
+            write!(report, "{:08X} ", start).unwrap();
+            for offset in start..(start + REPORT_BYTES_PER_LINE).min(file_len) {
+                let byte = file[offset as usize];
+                if offset == location.offset {
+                    write!(report, "[{:02X}]", byte).unwrap();
+                } else if offset == location.offset + 1 {
+                    write!(report, "{:02X}", byte).unwrap();
+                } else {
+                    write!(report, " {:02X}", byte).unwrap();
+                }
+            }
+            if line == error_line {
+                report.push_str("  <--");
+            }
+            report.push('\n');
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        tests::fixtures::{read_u32, single_frame_wan_image},
+        DecodeOptions, WanError, WanImage, WanSection,
+    };
+
+    #[test]
+    fn test_error_location() {
+        let wanimage = single_frame_wan_image();
+        let mut bytes = wanimage.write_to_vec().unwrap();
+        let image_data_info = read_u32(&bytes, read_u32(&bytes, 4) + 4);
+        let pointer_table = read_u32(&bytes, image_data_info);
+        bytes[pointer_table..pointer_table + 4].copy_from_slice(&[0; 4]);
+
+        assert!(matches!(
+            WanImage::decode_wan_from_bytes(&bytes),
+            Err(WanError::NullFragmentBytesPointer)
+        ));
+
+        let error =
+            WanImage::decode_wan_with_location(Cursor::new(&bytes), &DecodeOptions::default())
+                .unwrap_err();
+        assert!(matches!(error.error, WanError::NullFragmentBytesPointer));
+        let location = error.location.as_ref().unwrap();
+        assert_eq!(location.section, WanSection::FragmentBytes);
+        assert_eq!(location.item, Some(0));
+        // the entry of the null pointer in the table
+        assert_eq!(location.offset, pointer_table as u64);
+        assert!(error
+            .to_string()
+            .starts_with("can't decode the fragment bytes section (item 0)"));
+
+        let report = error.annotated_report(&bytes);
+        assert!(report.starts_with(
+            "error: an fragment bytes pointer is null\nsection: fragment bytes\nitem: 0\n"
+        ));
+        assert!(report.contains(&format!("[{:02X}]", bytes[pointer_table])));
+        assert!(report.contains("  <--\n"));
+    }
 }
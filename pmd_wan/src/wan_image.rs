@@ -1,66 +1,93 @@
 use crate::{
-    encode_fragment_pixels, get_opt_le, wan_read_raw_4, AnimationStore, CompressionMethod,
-    Fragment, FragmentBytes, FragmentBytesToImageError, FragmentFlip, OamShape, Frame,
+    get_opt_le,
+    progress::no_progress,
+    salvage::{decode_section, salvage_section},
+    sir0::read_sir0_header,
+    span::SectionSpan,
+    AnimationStore, CompressionMethod, CompressionReport, DecodeOptions, DecodeWarning,
+    EncodeCache, Fragment, FragmentBytes, FragmentBytesToImageError, FragmentCompressor,
+    FragmentFlip, Frame, FrameOffset, OamShape, Sir0Container, DEFAULT_FRAGMENT_PRIORITY,
+    SIR0_HEADER_SIZE,
+};
+use crate::{
+    DamageReport, FragmentBytesStore, FrameStore, LocatedWanError, Palette, ProgressCallback,
+    SectionDamage, SpriteType, WanError, WanSection, WanUnknowns,
 };
-use crate::{FragmentBytesStore, FrameStore, Palette, SpriteType, WanError};
 
 use anyhow::Context;
 use binread::BinReaderExt;
 use binwrite::BinWrite;
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use image::{ImageBuffer, Rgba};
-use pmd_sir0::write_sir0_footer;
-use std::io::{Read, Seek, SeekFrom, Write};
-
-#[derive(PartialEq, Eq, Debug)]
-pub struct WanImage {
-    pub fragment_bytes_store: FragmentBytesStore,
-    pub frame_store: FrameStore,
-    pub animation_store: AnimationStore,
-    pub palette: Palette,
-    /// true if the picture have 256 color, false if it only have 16
-    pub is_256_color: bool,
-    pub sprite_type: SpriteType,
-    pub unk2: u16,
-    /// How the imagebytes should be compressed, only affect writing
-    pub compression: CompressionMethod,
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+/// The size (in bytes) of each section of an encoded wan file, as returned by [`WanImage::estimate_encoded_size`]
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct EncodedSizeEstimate {
+    /// The [`Frame`]s, with their [`Fragment`]s
+    pub frames: usize,
+    /// The [`crate::Animation`]s, with their padding
+    pub animations: usize,
+    /// The [`FragmentBytes`], with their assembly tables
+    pub fragment_bytes: usize,
+    /// The [`Palette`] colors and header
+    pub palette: usize,
+    /// The [`crate::FrameOffset`]s (only for [`SpriteType::Chara`])
+    pub frame_offsets: usize,
+    /// The reference tables and the wan headers
+    pub headers_and_tables: usize,
+    /// The SIR0 header, pointer list and padding
+    pub sir0: usize,
 }
 
-impl WanImage {
-    /// Create an empty 16 color sprite for the given [`SpriteType`]
-    pub fn new(sprite_type: SpriteType) -> Self {
-        Self {
-            fragment_bytes_store: FragmentBytesStore::default(),
-            frame_store: FrameStore::default(),
-            animation_store: AnimationStore::default(),
-            palette: Palette::default(),
-            is_256_color: false,
-            sprite_type,
-            unk2: 0,
-            compression: sprite_type.default_compression_method(),
-        }
+impl EncodedSizeEstimate {
+    /// The size of the whole file
+    pub fn total(&self) -> usize {
+        self.frames
+            + self.animations
+            + self.fragment_bytes
+            + self.palette
+            + self.frame_offsets
+            + self.headers_and_tables
+            + self.sir0
     }
+}
 
-    /// parse an image in the wan/wat format stored in the input file
-    /// It assume that the file is decompressed
-    pub fn decode_wan<F: Read + Seek>(mut file: F) -> Result<WanImage, WanError> {
+/// The content of the sir0 header, the wan header, and the animation and image data info blocks, that locate every other section
+pub(crate) struct WanHeader {
+    pub(crate) sir0_pointer_header: u64,
+    pub(crate) sir0_pointer_offset: u64,
+    pub(crate) pointer_to_anim_info: u64,
+    pub(crate) pointer_to_image_data_info: u64,
+    pub(crate) source_file_lenght: u64,
+    pub(crate) sprite_type: SpriteType,
+    pub(crate) pointer_frames_table: u64,
+    pub(crate) frame_offset_table: u64,
+    pub(crate) pointer_animation_table: u64,
+    pub(crate) amount_animation_group: u16,
+    pub(crate) pointer_image_data_pointer_table: u64,
+    pub(crate) pointer_palette: u64,
+    pub(crate) is_256_color: bool,
+    pub(crate) unk2: u16,
+    pub(crate) amount_fragments: u16,
+    pub(crate) unk_wan_header: u16,
+    pub(crate) unk_animation_info: [u16; 3],
+    pub(crate) unk_image_data_info: u16,
+}
+
+impl WanHeader {
+    pub(crate) fn read<F: Read + Seek>(
+        file: &mut F,
+        options: &DecodeOptions,
+        warnings: &mut Vec<DecodeWarning>,
+    ) -> Result<WanHeader, WanError> {
         let source_file_lenght = file.seek(SeekFrom::End(0))?;
         file.seek(SeekFrom::Start(0))?;
-        debug!("start to decode a wan image");
 
         // first step: decode the sir0 header
         trace!("decoding the sir0 header");
-        let sir0_header = wan_read_raw_4(&mut file)?;
-        if sir0_header != [0x53, 0x49, 0x52, 0x30] {
-            return Err(WanError::InvalidSir0(sir0_header));
-        };
-        let sir0_pointer_header = file.read_u32::<LE>()? as u64;
-        let _sir0_pointer_offset = file.read_u32::<LE>()? as u64;
-
-        let sir0_header_end = wan_read_raw_4(&mut file)?;
-        if sir0_header_end != [0, 0, 0, 0] {
-            return Err(WanError::InvalidEndOfSir0Header(sir0_header_end));
-        };
+        let (sir0_pointer_header, sir0_pointer_offset) = read_sir0_header(file)?;
+        let sir0_pointer_header = sir0_pointer_header as u64;
 
         // second step: decode the wan header
         trace!("reading the wan header");
@@ -70,10 +97,11 @@ impl WanImage {
         let sprite_type = match file.read_u16::<LE>()? {
             0 => SpriteType::PropsUI,
             1 => SpriteType::Chara,
+            2 => SpriteType::Effect,
             3 => SpriteType::Unknown,
             value => return Err(WanError::TypeOfSpriteUnknown(value)),
         };
-        //unk #12
+        let unk_wan_header = file.read_u16::<LE>()?;
 
         // third step: decode animation info block
         trace!("reading the animation info block");
@@ -86,11 +114,14 @@ impl WanImage {
         if frame_offset_table > source_file_lenght {
             return Err(WanError::PostFilePointer("particule offset table"));
         };
-        #[allow(unused_parens)]
         if sprite_type == SpriteType::Chara && frame_offset_table == 0 {
-            return Err(WanError::NonExistenceFrameOffsetForChara);
+            options.recover(WanError::NonExistenceFrameOffsetForChara, warnings, |_| {
+                DecodeWarning::MissingFrameOffsets
+            })?;
         } else if sprite_type != SpriteType::Chara && frame_offset_table != 0 {
-            return Err(WanError::ExistenceFrameOffsetForNonChara);
+            options.recover(WanError::ExistenceFrameOffsetForNonChara, warnings, |_| {
+                DecodeWarning::UnexpectedFrameOffsets
+            })?;
         };
         let pointer_animation_table = file.read_u32::<LE>()? as u64;
         if pointer_animation_table > source_file_lenght {
@@ -99,13 +130,18 @@ impl WanImage {
         let amount_animation_group = file.read_u16::<LE>()?;
 
         let _size_to_allocate_for_max_frame = file.read_u32::<LE>()?;
+        let unk_animation_info = [
+            file.read_u16::<LE>()?,
+            file.read_u16::<LE>()?,
+            file.read_u16::<LE>()?,
+        ];
 
         // fourth: decode image data info
         trace!("reading the image data info");
         file.seek(SeekFrom::Start(pointer_to_image_data_info))?;
         let pointer_image_data_pointer_table = file.read_u32::<LE>()? as u64;
         let pointer_palette = file.read_u32::<LE>()? as u64;
-        file.read_u16::<LE>()?; //unk
+        let unk_image_data_info = file.read_u16::<LE>()?;
         let is_256_color = match file.read_u16::<LE>()? {
             0 => false,
             1 => true,
@@ -114,75 +150,281 @@ impl WanImage {
         let unk2 = file.read_u16::<LE>()?;
         let amount_fragments = file.read_u16::<LE>()?;
 
-        trace!("parsing the palette");
+        Ok(WanHeader {
+            sir0_pointer_header,
+            sir0_pointer_offset: sir0_pointer_offset as u64,
+            pointer_to_anim_info,
+            pointer_to_image_data_info,
+            source_file_lenght,
+            sprite_type,
+            pointer_frames_table,
+            frame_offset_table,
+            pointer_animation_table,
+            amount_animation_group,
+            pointer_image_data_pointer_table,
+            pointer_palette,
+            is_256_color,
+            unk2,
+            amount_fragments,
+            unk_wan_header,
+            unk_animation_info,
+            unk_image_data_info,
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WanImage {
+    pub fragment_bytes_store: FragmentBytesStore,
+    pub frame_store: FrameStore,
+    pub animation_store: AnimationStore,
+    pub palette: Palette,
+    /// true if the picture have 256 color, false if it only have 16
+    pub is_256_color: bool,
+    pub sprite_type: SpriteType,
+    pub unk2: u16,
+    /// The other header values whose meaning is unknown
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unknowns: WanUnknowns,
+    /// How the imagebytes should be compressed, only affect writing
+    pub compression: CompressionMethod,
+}
 
-        file.seek(SeekFrom::Start(pointer_palette))?;
-        let palette = Palette::new_from_bytes(&mut file)?;
+impl WanImage {
+    /// Create an empty 16 color sprite for the given [`SpriteType`]
+    pub fn new(sprite_type: SpriteType) -> Self {
+        Self {
+            fragment_bytes_store: FragmentBytesStore::default(),
+            frame_store: FrameStore::default(),
+            animation_store: AnimationStore::default(),
+            palette: Palette::default(),
+            is_256_color: false,
+            sprite_type,
+            unk2: 0,
+            unknowns: WanUnknowns::default(),
+            compression: sprite_type.default_compression_method(),
+        }
+    }
 
-        // decode fragments
-        trace!("decoding meta-frame");
-        let frames_end_pointer: u64 = match frame_offset_table {
-            0 => match WanImage::find_first_non_null_animation_seq_entry(
-                &mut file,
-                pointer_animation_table,
-            ) {
-                Some(v) => v,
-                // Fall back to animation group offset
-                None => pointer_animation_table,
-            },
-            value => value,
+    /// parse an image in the wan/wat format stored in the input file
+    /// It assume that the file is decompressed
+    pub fn decode_wan<F: Read + Seek>(file: F) -> Result<WanImage, WanError> {
+        WanImage::decode_wan_with_options(file, &DecodeOptions::default()).map(|(image, _)| image)
+    }
+
+    /// Like [`WanImage::decode_wan`], but with the given [`DecodeOptions`].
+    ///
+    /// Also return the [`DecodeWarning`]s for the malformed data that has been skipped or repaired in lenient mode. There are none in strict mode.
+    pub fn decode_wan_with_options<F: Read + Seek>(
+        file: F,
+        options: &DecodeOptions,
+    ) -> Result<(WanImage, Vec<DecodeWarning>), WanError> {
+        WanImage::decode_wan_with_location(file, options).map_err(|error| error.error)
+    }
+
+    /// Like [`WanImage::decode_wan_with_options`], but the error also tell where it happened in the file
+    pub fn decode_wan_with_location<F: Read + Seek>(
+        file: F,
+        options: &DecodeOptions,
+    ) -> Result<(WanImage, Vec<DecodeWarning>), LocatedWanError> {
+        WanImage::decode_wan_sections(file, options, None, true)
+    }
+
+    /// Decode as much as possible of a truncated or corrupted wan file, like [`WanImage::decode_wan`] in lenient mode (see [`DecodeOptions`]).
+    ///
+    /// When a whole section can't be read, it is left empty (or partially filled), and the decoding continue with the next section.
+    /// If the headers can't be read, the other sections (and the [`SpriteType`]) can't be found, and [`None`] is returned.
+    /// The [`DamageReport`] list the damaged sections and the repaired items.
+    pub fn salvage_wan<F: Read + Seek>(file: F) -> (Option<WanImage>, DamageReport) {
+        let mut damaged_sections = Vec::new();
+        let (image, warnings) = match WanImage::decode_wan_sections(
+            file,
+            &DecodeOptions::lenient(),
+            Some(&mut damaged_sections),
+            true,
+        ) {
+            Ok((image, warnings)) => (Some(image), warnings),
+            // only happen if the header section is damaged, as it is fatal
+            Err(error) => {
+                damaged_sections.push(SectionDamage {
+                    section: WanSection::Header,
+                    error: error.error,
+                    location: error.location,
+                });
+                (None, Vec::new())
+            }
         };
+        (
+            image,
+            DamageReport {
+                damaged_sections,
+                warnings,
+            },
+        )
+    }
 
-        let space_frame_raw = frames_end_pointer.checked_sub(pointer_frames_table).ok_or(
-            WanError::OverflowSubstraction(
-                frames_end_pointer,
-                pointer_frames_table,
-                "fragment reference end pointer",
-                "pointer fragment reference table",
-            ),
-        )?;
+    /// Decode each section of the file. If `damaged_sections` is provided, the sections (except the header) that can't be read are recorded there instead of returning an error.
+    /// If `load_fragment_bytes` is false, the [`FragmentBytes`] are left for the caller to read, and [`WanImage::fragment_bytes_store`] is empty.
+    pub(crate) fn decode_wan_sections<F: Read + Seek>(
+        mut file: F,
+        options: &DecodeOptions,
+        mut damaged_sections: Option<&mut Vec<SectionDamage>>,
+        load_fragment_bytes: bool,
+    ) -> Result<(WanImage, Vec<DecodeWarning>), LocatedWanError> {
+        let mut warnings = Vec::new();
+        debug!("start to decode a wan image");
+        let header = decode_section(&mut file, WanSection::Header, 0, |file| {
+            Ok(WanHeader::read(file, options, &mut warnings)?)
+        })?;
+        let sprite_type = header.sprite_type;
 
-        let nb_frames = space_frame_raw / 4;
+        trace!("parsing the palette");
+        let (palette, palette_unknowns) = salvage_section(
+            &mut file,
+            WanSection::Palette,
+            header.pointer_palette,
+            &mut damaged_sections,
+            |file| {
+                file.seek(SeekFrom::Start(header.pointer_palette))?;
+                let (palette, unknowns) = Palette::new_from_bytes_with_unknowns(file)?;
+                Ok((palette, Some(unknowns)))
+            },
+        )?;
+        let (palette_header, palette_header_2) = palette_unknowns.unwrap_or_else(|| {
+            let default = WanUnknowns::default();
+            (default.palette_header, default.palette_header_2)
+        });
 
-        file.seek(SeekFrom::Start(pointer_frames_table))?;
-        let mut frames_store = FrameStore::new_from_bytes(&mut file, nb_frames)?;
+        // decode fragments
+        trace!("decoding meta-frame");
+        let mut frames_store = salvage_section(
+            &mut file,
+            WanSection::Frames,
+            header.pointer_frames_table,
+            &mut damaged_sections,
+            |file| {
+                let frames_end_pointer: u64 = match header.frame_offset_table {
+                    0 => match WanImage::find_first_non_null_animation_seq_entry(
+                        file,
+                        header.pointer_animation_table,
+                    ) {
+                        Some(v) => v,
+                        // Fall back to animation group offset
+                        None => header.pointer_animation_table,
+                    },
+                    value => value,
+                };
+
+                let space_frame_raw = frames_end_pointer
+                    .checked_sub(header.pointer_frames_table)
+                    .ok_or(WanError::OverflowSubstraction(
+                        frames_end_pointer,
+                        header.pointer_frames_table,
+                        "fragment reference end pointer",
+                        "pointer fragment reference table",
+                    ))?;
+
+                let nb_frames = space_frame_raw / 4;
+
+                file.seek(SeekFrom::Start(header.pointer_frames_table))?;
+                FrameStore::new_from_bytes_with_options(file, nb_frames, options, &mut warnings)
+            },
+        )?;
 
         // decode image
         trace!("reading the image data pointer table");
-        file.seek(SeekFrom::Start(pointer_image_data_pointer_table))?;
         trace!(
             "start of the image part (source) : {}",
-            pointer_image_data_pointer_table
+            header.pointer_image_data_pointer_table
         );
-        let fragment_store =
-            FragmentBytesStore::new_from_bytes(&mut file, amount_fragments as u32)?;
+        let (fragment_store, invalid_fragment_bytes) = if load_fragment_bytes {
+            salvage_section(
+                &mut file,
+                WanSection::FragmentBytes,
+                header.pointer_image_data_pointer_table,
+                &mut damaged_sections,
+                |file| {
+                    file.seek(SeekFrom::Start(header.pointer_image_data_pointer_table))?;
+                    FragmentBytesStore::new_from_bytes_with_options(
+                        file,
+                        header.amount_fragments as u32,
+                        options,
+                        &mut warnings,
+                    )
+                },
+            )?
+        } else {
+            (FragmentBytesStore::default(), Vec::new())
+        };
 
         // decode animation
-        let (anim_store, particule_table_end) =
-            AnimationStore::new(&mut file, pointer_animation_table, amount_animation_group)?;
+        let (anim_store, particule_table_end) = salvage_section(
+            &mut file,
+            WanSection::Animations,
+            header.pointer_animation_table,
+            &mut damaged_sections,
+            |file| {
+                AnimationStore::new_with_options(
+                    file,
+                    header.pointer_animation_table,
+                    header.amount_animation_group,
+                    options,
+                    &mut warnings,
+                )
+            },
+        )?;
 
         // decode the frame offsets table
-        if frame_offset_table != 0 {
-            trace!("decoding frames offset at {:?}", frame_offset_table);
-            file.seek(SeekFrom::Start(frame_offset_table))?;
+        if sprite_type == SpriteType::Chara && header.frame_offset_table != 0 {
+            salvage_section(
+                &mut file,
+                WanSection::FrameOffsets,
+                header.frame_offset_table,
+                &mut damaged_sections,
+                |file| {
+                    trace!("decoding frames offset at {:?}", header.frame_offset_table);
+                    file.seek(SeekFrom::Start(header.frame_offset_table))?;
+                    for frame in &mut frames_store.frames {
+                        frame.frame_offset = Some(file.read_le()?);
+                    }
+                    if particule_table_end > header.source_file_lenght {
+                        return Err(WanError::PostFilePointer("particle table end").into());
+                    };
+                    Ok(())
+                },
+            )?;
+        }
+        if sprite_type == SpriteType::Chara {
+            // either there is no table, and this has been accepted in lenient mode, or it is damaged in salvage mode
             for frame in &mut frames_store.frames {
-                frame.frame_offset = Some(file.read_le()?);
+                if frame.frame_offset.is_none() {
+                    frame.frame_offset = Some(FrameOffset::default());
+                }
             }
-            if particule_table_end > source_file_lenght {
-                return Err(WanError::PostFilePointer("particle table end"));
-            };
         }
 
-        Ok(WanImage {
+        let mut image = WanImage {
             fragment_bytes_store: fragment_store,
             frame_store: frames_store,
             animation_store: anim_store,
             palette,
-            is_256_color,
+            is_256_color: header.is_256_color,
             sprite_type,
-            unk2,
+            unk2: header.unk2,
+            unknowns: WanUnknowns {
+                wan_header: header.unk_wan_header,
+                animation_info: header.unk_animation_info,
+                image_data_info: header.unk_image_data_info,
+                palette_header,
+                palette_header_2,
+            },
             compression: sprite_type.default_compression_method(),
-        })
+        };
+        if !options.strict {
+            image.repair_references(&invalid_fragment_bytes, &mut warnings);
+        }
+        Ok((image, warnings))
     }
 
     /// If the file doesn't have an entity effect particle list, we ned to instead search
@@ -201,78 +443,216 @@ impl WanImage {
         None
     }
 
+    /// Parse a wan image stored in memory, like [`WanImage::decode_wan`], without copying it.
+    ///
+    /// With [`WanImage::write_to_vec`], this allow to use the crate without any file or [`Seek`] implementation, like in a browser with `wasm32-unknown-unknown`.
+    pub fn decode_wan_from_bytes(bytes: &[u8]) -> Result<WanImage, WanError> {
+        WanImage::decode_wan(Cursor::new(bytes))
+    }
+
+    /// Like [`WanImage::decode_wan_with_options`], for a wan image stored in memory
+    pub fn decode_wan_from_bytes_with_options(
+        bytes: &[u8],
+        options: &DecodeOptions,
+    ) -> Result<(WanImage, Vec<DecodeWarning>), WanError> {
+        WanImage::decode_wan_with_options(Cursor::new(bytes), options)
+    }
+
+    /// Like [`WanImage::salvage_wan`], for a wan image stored in memory
+    pub fn salvage_wan_from_bytes(bytes: &[u8]) -> (Option<WanImage>, DamageReport) {
+        WanImage::salvage_wan(Cursor::new(bytes))
+    }
+
+    /// Parse a wan image from a reader that doesn't implement [`Seek`], like a network stream.
+    ///
+    /// As the format need random access, the whole content of the reader is first read into memory.
+    pub fn decode_wan_from_reader<R: Read>(mut reader: R) -> Result<WanImage, WanError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        WanImage::decode_wan_from_bytes(&bytes)
+    }
+
     pub fn create_wan<F: Write + Seek>(&self, file: &mut F) -> anyhow::Result<()> {
-        let opt_le = get_opt_le();
-        debug!("start creating a wan image");
+        let sir0 = self.create_sir0()?;
+        trace!("writing the sir0 container");
+        sir0.write(file)
+            .context("failed to write the Sir0 container")?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
 
-        let mut sir0_offsets: Vec<u32> = vec![];
-        // create the sir0 header
-        trace!("creating the sir0 header");
-        file.write_all(&[0x53, 0x49, 0x52, 0x30])?;
+    /// Encode this [`WanImage`] in a [`Sir0Container`], with the pointer list rebuilt from the written pointers
+    pub fn create_sir0(&self) -> anyhow::Result<Sir0Container> {
+        self.create_sir0_with_compressor(&self.compression)
+    }
+
+    /// Like [`WanImage::create_sir0`], but compress the [`FragmentBytes`] with the given [`FragmentCompressor`] instead of [`WanImage::compression`]
+    pub fn create_sir0_with_compressor(
+        &self,
+        compressor: &dyn FragmentCompressor,
+    ) -> anyhow::Result<Sir0Container> {
+        Ok(self
+            .create_sir0_with_report(compressor, &no_progress, None)?
+            .0)
+    }
+
+    /// Encode this [`WanImage`] into a new complete wan file.
+    ///
+    /// The encoding is reproducible: the output only depend on the content of the [`WanImage`], so encoding the same image always give the same bytes.
+    pub fn write_to_vec(&self) -> anyhow::Result<Vec<u8>> {
+        self.write_to_vec_with_compressor(&self.compression)
+    }
+
+    /// Like [`WanImage::write_to_vec`], but compress the [`FragmentBytes`] with the given [`FragmentCompressor`] instead of [`WanImage::compression`]
+    pub fn write_to_vec_with_compressor(
+        &self,
+        compressor: &dyn FragmentCompressor,
+    ) -> anyhow::Result<Vec<u8>> {
+        Ok(self.create_sir0_with_compressor(compressor)?.to_bytes()?)
+    }
+
+    /// Like [`WanImage::write_to_vec_with_compressor`], but also return statistics about the written file, and how each [`FragmentBytes`] was compressed
+    pub fn write_to_vec_with_report(
+        &self,
+        compressor: &dyn FragmentCompressor,
+    ) -> anyhow::Result<(Vec<u8>, CompressionReport)> {
+        let (sir0, mut report) = self.create_sir0_with_report(compressor, &no_progress, None)?;
+        let bytes = sir0.to_bytes()?;
+        report.sections.sir0 = bytes.len() - sir0.content.len();
+        Ok((bytes, report))
+    }
+
+    /// Like [`WanImage::write_to_vec_with_compressor`], but report the [`crate::ProgressPhase::EncodingFragmentBytes`] progress, the longest part of the encoding
+    pub fn write_to_vec_with_progress(
+        &self,
+        compressor: &dyn FragmentCompressor,
+        progress: ProgressCallback,
+    ) -> anyhow::Result<Vec<u8>> {
+        Ok(self
+            .create_sir0_with_report(compressor, progress, None)?
+            .0
+            .to_bytes()?)
+    }
 
-        let sir0_pointer_header = file.seek(SeekFrom::Current(0))?;
-        sir0_offsets.push(sir0_pointer_header as u32);
-        0u32.write(file)?; //sir0_pointer_header
+    /// Compute the size of the file [`WanImage::create_wan`] would write, section by section. This allow to check if it fit in a given space before writing it.
+    ///
+    /// The sections are encoded in memory to compute their size, so it is exact, but not faster than encoding the file.
+    pub fn estimate_encoded_size(&self) -> anyhow::Result<EncodedSizeEstimate> {
+        Ok(self.write_to_vec_with_report(&self.compression)?.1.sections)
+    }
 
-        let sir0_pointer_offset = file.seek(SeekFrom::Current(0))?;
-        sir0_offsets.push(sir0_pointer_offset as u32);
+    /// Encode this [`WanImage`], returning the statistics of the written file. [`EncodedSizeEstimate::sir0`] isn't set.
+    ///
+    /// If a `cache` is given, the [`FragmentBytes`] are written with [`EncodeCache::write_fragment_bytes_store`] instead, and `compressor` should be [`WanImage::compression`].
+    pub(crate) fn create_sir0_with_report(
+        &self,
+        compressor: &dyn FragmentCompressor,
+        progress: ProgressCallback,
+        cache: Option<&mut EncodeCache>,
+    ) -> anyhow::Result<(Sir0Container, CompressionReport)> {
+        let mut sizes = EncodedSizeEstimate::default();
+        let opt_le = get_opt_le();
+        debug!("start creating a wan image");
 
-        file.write_all(&[0; 8])?; //magic
+        let mut sir0_offsets: Vec<u32> = vec![];
+        // keep space for the sir0 header, so offsets are relative to the start of the sir0 file
+        let file = &mut Cursor::new(vec![0; SIR0_HEADER_SIZE as usize]);
+        file.seek(SeekFrom::End(0))?;
 
         // write frames
-        trace!(
-            "start of frames reference: {}",
-            file.seek(SeekFrom::Current(0))?
-        );
+        trace!("start of frames reference: {}", file.stream_position()?);
+        let span = SectionSpan::enter("encode", WanSection::Frames.name(), file.stream_position()?);
         let (frames_references, size_to_allocate_for_max_frame) = self.frame_store.write(file)?;
-
-        trace!(
-            "start of the animation offset: {}",
-            file.seek(SeekFrom::Current(0))?
+        span.exit(
+            file.stream_position()?,
+            format_args!("frames={}", self.frame_store.frames.len()),
         );
+        sizes.frames = file.stream_position()? as usize - SIR0_HEADER_SIZE as usize;
+
+        trace!("start of the animation offset: {}", file.stream_position()?);
+        let animations_start = file.stream_position()?;
+        let span = SectionSpan::enter("encode", WanSection::Animations.name(), animations_start);
         let animations_pointer = self.animation_store.write(file)?;
 
-        while file.seek(SeekFrom::Current(0))? % 4 != 0 {
+        while file.stream_position()? % 4 != 0 {
             file.write_all(&[0xAA])?;
         }
-
-        trace!(
-            "start of the image offset: {}",
-            file.seek(SeekFrom::Current(0))?
+        span.exit(
+            file.stream_position()?,
+            format_args!("groups={}", self.animation_store.anim_groups.len()),
         );
+        sizes.animations = (file.stream_position()? - animations_start) as usize;
+
+        trace!("start of the image offset: {}", file.stream_position()?);
+        let fragment_bytes_start = file.stream_position()?;
 
-        let (image_offset, sir0_pointer_images) =
-            self.fragment_bytes_store.write(file, &self.compression)?;
+        let span = SectionSpan::enter(
+            "encode",
+            WanSection::FragmentBytes.name(),
+            fragment_bytes_start,
+        );
+        let (image_offset, sir0_pointer_images, fragment_bytes_stats) = match cache {
+            Some(cache) => cache.write_fragment_bytes_store(
+                &self.fragment_bytes_store,
+                file,
+                &self.compression,
+                progress,
+            )?,
+            None => self
+                .fragment_bytes_store
+                .write_with_stats(file, compressor, progress)?,
+        };
+        span.exit(
+            file.stream_position()?,
+            format_args!(
+                "fragment_bytes={} raw_pixels={}",
+                self.fragment_bytes_store.len(),
+                self.fragment_bytes_store
+                    .fragment_bytes
+                    .iter()
+                    .map(|fragment_bytes| fragment_bytes.mixed_pixels.len())
+                    .sum::<usize>()
+            ),
+        );
+        sizes.fragment_bytes = (file.stream_position()? - fragment_bytes_start) as usize;
 
         for pointer in sir0_pointer_images {
             sir0_offsets.push(pointer as u32);
         }
 
-        trace!("start of the palette: {}", file.seek(SeekFrom::Current(0))?);
+        trace!("start of the palette: {}", file.stream_position()?);
+        let palette_start = file.stream_position()?;
+        let span = SectionSpan::enter("encode", WanSection::Palette.name(), palette_start);
         let pointer_palette = self
             .palette
-            .write(file)
+            .write_with_unknowns(
+                file,
+                self.unknowns.palette_header,
+                self.unknowns.palette_header_2,
+            )
             .context("Failed to write the palette")?;
+        span.exit(
+            file.stream_position()?,
+            format_args!("colors={}", self.palette.palette.len()),
+        );
+        sizes.palette = (file.stream_position()? - palette_start) as usize;
         //sir0_offsets.push(pointer_palette);
 
         sir0_offsets.push(pointer_palette as u32);
 
         trace!(
             "start of the fragment reference offset: {}",
-            file.seek(SeekFrom::Current(0))?
+            file.stream_position()?
         );
-        let frame_reference_offset = file.seek(SeekFrom::Current(0))?;
+        let frame_reference_offset = file.stream_position()?;
         for reference in frames_references {
-            sir0_offsets.push(file.seek(SeekFrom::Current(0))? as u32);
+            sir0_offsets.push(file.stream_position()? as u32);
             file.write_u32::<LE>(reference)?;
         }
 
         let particule_offset = if self.sprite_type == SpriteType::Chara {
-            let particule_offset = file.seek(SeekFrom::Current(0))?;
-            trace!(
-                "start of the frame offsets: {}",
-                file.seek(SeekFrom::Current(0))?
-            );
+            let particule_offset = file.stream_position()?;
+            trace!("start of the frame offsets: {}", file.stream_position()?);
             for frame in &self.frame_store.frames {
                 if let Some(frame_offset) = frame.frame_offset.as_ref() {
                     frame_offset
@@ -282,7 +662,8 @@ impl WanImage {
                     return Err(WanError::NoOffsetDataForFrame)?;
                 }
             }
-            sir0_offsets.push(file.seek(SeekFrom::Current(0))? as u32);
+            sir0_offsets.push(file.stream_position()? as u32);
+            sizes.frame_offsets = (file.stream_position()? - particule_offset) as usize;
             Some(particule_offset)
         } else {
             None
@@ -290,7 +671,7 @@ impl WanImage {
 
         trace!(
             "start of the animation group reference: {}",
-            file.seek(SeekFrom::Current(0))?
+            file.stream_position()?
         );
         let (animation_group_reference_offset, sir0_animation_pointer) = self
             .animation_store
@@ -301,53 +682,46 @@ impl WanImage {
         }
 
         //image offset
-        let pointer_image_data_pointer_table = file.seek(SeekFrom::Current(0))?;
-        trace!(
-            "start of the image offset: {}",
-            file.seek(SeekFrom::Current(0))?
-        );
+        let pointer_image_data_pointer_table = file.stream_position()?;
+        trace!("start of the image offset: {}", file.stream_position()?);
         for offset in image_offset {
-            sir0_offsets.push(file.seek(SeekFrom::Current(0))? as u32);
+            sir0_offsets.push(file.stream_position()? as u32);
             file.write_u32::<LE>(offset as u32)?;
         }
 
         // animation header
-        let animation_info_offset = file.seek(SeekFrom::Current(0))?;
-        trace!(
-            "start of the animation header: {}",
-            file.seek(SeekFrom::Current(0))?
-        );
-        sir0_offsets.push(file.seek(SeekFrom::Current(0))? as u32);
+        let animation_info_offset = file.stream_position()?;
+        trace!("start of the animation header: {}", file.stream_position()?);
+        sir0_offsets.push(file.stream_position()? as u32);
         file.write_u32::<LE>(frame_reference_offset as u32)?;
 
         if let Some(particule_offset) = particule_offset {
-            sir0_offsets.push(file.seek(SeekFrom::Current(0))? as u32);
+            sir0_offsets.push(file.stream_position()? as u32);
             file.write_u32::<LE>(particule_offset as u32)?;
         } else {
             file.write_all(&[0; 4])?;
         }
 
-        sir0_offsets.push(file.seek(SeekFrom::Current(0))? as u32);
+        sir0_offsets.push(file.stream_position()? as u32);
         file.write_u32::<LE>(animation_group_reference_offset as u32)?;
 
         file.write_u16::<LE>(self.animation_store.anim_groups.len() as u16)?;
 
         file.write_u32::<LE>(size_to_allocate_for_max_frame as u32)?;
-        file.write_all(&[0; 6])?;
+        for value in self.unknowns.animation_info {
+            file.write_u16::<LE>(value)?;
+        }
 
         // images header
-        trace!(
-            "start of the images header: {}",
-            file.seek(SeekFrom::Current(0))?
-        );
-        let image_info_offset = file.seek(SeekFrom::Current(0))?;
-        sir0_offsets.push(file.seek(SeekFrom::Current(0))? as u32);
+        trace!("start of the images header: {}", file.stream_position()?);
+        let image_info_offset = file.stream_position()?;
+        sir0_offsets.push(file.stream_position()? as u32);
         file.write_u32::<LE>(pointer_image_data_pointer_table as u32)?;
 
-        sir0_offsets.push(file.seek(SeekFrom::Current(0))? as u32);
+        sir0_offsets.push(file.stream_position()? as u32);
         (
             pointer_palette as u32,
-            0u16,
+            self.unknowns.image_data_info,
             u16::from(self.is_256_color),
             self.unk2,
             self.fragment_bytes_store.len() as u16,
@@ -355,43 +729,33 @@ impl WanImage {
             .write_options(file, &opt_le)?;
 
         // wan header
-        let wan_header_pos = file.seek(SeekFrom::Current(0))?;
-        sir0_offsets.push(file.seek(SeekFrom::Current(0))? as u32);
+        let wan_header_pos = file.stream_position()?;
+        sir0_offsets.push(file.stream_position()? as u32);
         file.write_u32::<LE>(animation_info_offset as u32)?;
-        sir0_offsets.push(file.seek(SeekFrom::Current(0))? as u32);
+        sir0_offsets.push(file.stream_position()? as u32);
         file.write_u32::<LE>(image_info_offset as u32)?;
         file.write_u16::<LE>(self.sprite_type.get_id() as u16)?;
-
-        file.write_all(&[0, 0])?;
-
-        while file.seek(SeekFrom::Current(0))? % 16 != 0 {
-            file.write_all(&[0xAA])?;
-        }
-
-        let sir0_offset_pos = file.seek(SeekFrom::Current(0))?;
-        // write the sir0 ending
-
-        trace!(
-            "start of the sir0 list: {}",
-            file.seek(SeekFrom::Current(0))?
-        );
-        write_sir0_footer(file, &sir0_offsets).context("failed to write the Sir0 footer")?;
-
-        //padding
-        file.write_all(&[0x00])?;
-        while file.seek(SeekFrom::Current(0))? % 16 != 0 {
-            file.write_all(&[0xAA])?;
-        }
-
-        // write the sir0 header
-        file.seek(SeekFrom::Start(sir0_pointer_header))?;
-        file.write_u32::<LE>(wan_header_pos as u32)?;
-
-        file.seek(SeekFrom::Start(sir0_pointer_offset))?;
-        file.write_u32::<LE>(sir0_offset_pos as u32)?;
-
-        file.seek(SeekFrom::Start(0))?;
-        Ok(())
+        file.write_u16::<LE>(self.unknowns.wan_header)?;
+
+        let mut content = file.get_ref().clone();
+        content.drain(0..SIR0_HEADER_SIZE as usize);
+        sizes.headers_and_tables = content.len()
+            - sizes.frames
+            - sizes.animations
+            - sizes.fragment_bytes
+            - sizes.palette
+            - sizes.frame_offsets;
+        Ok((
+            Sir0Container {
+                content,
+                header_offset: wan_header_pos as u32,
+                pointers: sir0_offsets,
+            },
+            CompressionReport {
+                fragment_bytes: fragment_bytes_stats,
+                sections: sizes,
+            },
+        ))
     }
 
     /// Return the image corresponding to the resolution and the palette of given meta-frame.
@@ -413,7 +777,12 @@ impl WanImage {
             }
         };
 
-        image_bytes.get_image(&self.palette, fragment.resolution.size(), fragment.pal_idx)
+        image_bytes.get_image_with_depth(
+            &self.palette,
+            fragment.resolution.size(),
+            fragment.pal_idx,
+            self.is_256_color,
+        )
     }
 
     pub fn fix_empty_frames(&mut self) {
@@ -428,13 +797,13 @@ impl WanImage {
         }
         let image_bytes_index = self.fragment_bytes_store.fragment_bytes.len();
         let resolution = OamShape::new(0, 0).unwrap();
+        // no panic: We guarantee input parameters are valid
+        let fragment_bytes =
+            FragmentBytes::new_from_pixels(&[0; 64], resolution.size(), self.is_256_color, 0)
+                .unwrap();
         self.fragment_bytes_store
             .fragment_bytes
-            .push(FragmentBytes {
-                // no panic: We guarantee input parameters are valid
-                mixed_pixels: encode_fragment_pixels(&[0; 256], resolution.size()).unwrap(),
-                z_index: 0,
-            });
+            .push(fragment_bytes);
         for empty_frame in collected {
             empty_frame.fragments.push(Fragment {
                 unk1: 0,
@@ -445,6 +814,7 @@ impl WanImage {
                 offset_x: 0,
                 flip: FragmentFlip::standard(),
                 is_mosaic: false,
+                priority: DEFAULT_FRAGMENT_PRIORITY,
                 pal_idx: 0,
                 resolution,
             })
@@ -0,0 +1,65 @@
+/// The values of the headers whose meaning is unknown, read from the file and written back unchanged, so re-saving a sprite doesn't alter them.
+///
+/// The [`Default`] values are the ones this library used to always write, that are also the most common in the game files.
+/// [`crate::WanImage::unk2`] is also an unknown header value, that predate this struct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WanUnknowns {
+    /// The 2 bytes after the sprite type, at the end of the wan header
+    pub wan_header: u16,
+    /// The 6 bytes at the end of the animation info block, after the size to allocate for the biggest frame
+    pub animation_info: [u16; 3],
+    /// The 2 bytes after the palette pointer, in the image data info block
+    pub image_data_info: u16,
+    /// The 2 bytes after the colors pointer, in the palette header
+    pub palette_header: u16,
+    /// The 4 bytes after the number of colors, in the palette header
+    pub palette_header_2: u32,
+}
+
+impl Default for WanUnknowns {
+    fn default() -> Self {
+        Self {
+            wan_header: 0,
+            animation_info: [0; 3],
+            image_data_info: 0,
+            palette_header: 0,
+            palette_header_2: 0xFF << 16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::fixtures::single_frame_wan_image, Animation, WanImage, WanUnknowns};
+
+    #[test]
+    fn test_unknowns_roundtrip() {
+        let mut wanimage = single_frame_wan_image();
+        wanimage
+            .animation_store
+            .anim_groups
+            .push(vec![Animation::default()]);
+        let default_bytes = wanimage.write_to_vec().unwrap();
+        assert_eq!(
+            WanImage::decode_wan_from_bytes(&default_bytes)
+                .unwrap()
+                .unknowns,
+            WanUnknowns::default()
+        );
+
+        wanimage.unknowns = WanUnknowns {
+            wan_header: 1,
+            animation_info: [2, 3, 4],
+            image_data_info: 5,
+            palette_header: 6,
+            palette_header_2: 7,
+        };
+        let bytes = wanimage.write_to_vec().unwrap();
+        assert_eq!(bytes.len(), default_bytes.len());
+        assert_ne!(bytes, default_bytes);
+        let decoded = WanImage::decode_wan_from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.unknowns, wanimage.unknowns);
+        assert_eq!(decoded.write_to_vec().unwrap(), bytes);
+    }
+}
@@ -0,0 +1,192 @@
+//! Python bindings for pmd_wan, built with `maturin build --release` (or `maturin develop` in a virtualenv).
+//!
+//! ```python
+//! import pmd_wan_py
+//! image = pmd_wan_py.WanImage.decode(open("bulbasaur.wan", "rb").read())
+//! width, height, rgba = image.render_frame(0)
+//! image.export_spritebot("bulbasaur/")
+//! ```
+
+use std::path::Path;
+
+use pmd_wan::{SpriteBotExport, SpriteType, WanImage};
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+fn to_py_error(error: impl Into<anyhow::Error>) -> PyErr {
+    PyValueError::new_err(format!("{:#}", error.into()))
+}
+
+fn sprite_type_name(sprite_type: SpriteType) -> &'static str {
+    match sprite_type {
+        SpriteType::PropsUI => "props_ui",
+        SpriteType::Chara => "chara",
+        SpriteType::Effect => "effect",
+        SpriteType::Unknown => "unknown",
+    }
+}
+
+/// A frame of an animation: the displayed frame, its duration in 1/60th of second, and its offset
+#[pyclass(name = "AnimationFrame", get_all)]
+#[derive(Clone)]
+struct PyAnimationFrame {
+    frame_id: u16,
+    duration: u8,
+    flag: u8,
+    offset_x: i16,
+    offset_y: i16,
+    shadow_offset_x: i16,
+    shadow_offset_y: i16,
+}
+
+/// A decoded wan sprite
+#[pyclass(name = "WanImage")]
+struct PyWanImage {
+    image: WanImage,
+}
+
+#[pymethods]
+impl PyWanImage {
+    /// Decode the content of a wan file
+    #[staticmethod]
+    fn decode(data: &[u8]) -> PyResult<Self> {
+        Ok(Self {
+            image: WanImage::decode_wan_from_bytes(data).map_err(to_py_error)?,
+        })
+    }
+
+    /// Encode this image in a new wan file
+    fn encode<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        let bytes = self.image.write_to_vec().map_err(to_py_error)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Read a SpriteBot folder (AnimData.xml and the sheets it reference)
+    #[staticmethod]
+    fn import_spritebot(folder: &str) -> PyResult<Self> {
+        let export = SpriteBotExport::read_from_folder(Path::new(folder)).map_err(to_py_error)?;
+        Ok(Self {
+            image: export.to_wan_image().map_err(to_py_error)?,
+        })
+    }
+
+    /// Write this image as a SpriteBot folder, that should already exist
+    fn export_spritebot(&self, folder: &str) -> PyResult<()> {
+        self.image
+            .export_spritebot()
+            .and_then(|export| export.write_to_folder(Path::new(folder)))
+            .map_err(to_py_error)
+    }
+
+    /// `"props_ui"`, `"chara"`, `"effect"` or `"unknown"`
+    #[getter]
+    fn sprite_type(&self) -> &'static str {
+        sprite_type_name(self.image.sprite_type)
+    }
+
+    #[getter]
+    fn is_256_color(&self) -> bool {
+        self.image.is_256_color
+    }
+
+    #[getter]
+    fn frame_count(&self) -> usize {
+        self.image.frame_store.frames.len()
+    }
+
+    /// The colors of the palette, as (red, green, blue, alpha) tuples. The alpha is 128 for opaque colors.
+    #[getter]
+    fn palette(&self) -> Vec<(u8, u8, u8, u8)> {
+        self.image
+            .palette
+            .palette
+            .iter()
+            .map(|color| (color[0], color[1], color[2], color[3]))
+            .collect()
+    }
+
+    /// The animation groups, each being a list of animations, that are lists of [`PyAnimationFrame`]
+    #[getter]
+    fn animation_groups(&self) -> Vec<Vec<Vec<PyAnimationFrame>>> {
+        self.image
+            .animation_store
+            .anim_groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|animation| {
+                        animation
+                            .frames
+                            .iter()
+                            .map(|frame| PyAnimationFrame {
+                                frame_id: frame.frame_id,
+                                duration: frame.duration,
+                                flag: frame.flag,
+                                offset_x: frame.offset_x,
+                                offset_y: frame.offset_y,
+                                shadow_offset_x: frame.shadow_offset_x,
+                                shadow_offset_y: frame.shadow_offset_y,
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Render a frame, returning its width, its height and its RGBA pixels, line by line from the top-left one
+    fn render_frame<'py>(
+        &self,
+        py: Python<'py>,
+        frame_id: usize,
+    ) -> PyResult<(u32, u32, &'py PyBytes)> {
+        let rendered = self.image.render_frame(frame_id).map_err(to_py_error)?;
+        Ok((
+            rendered.width(),
+            rendered.height(),
+            PyBytes::new(py, rendered.as_raw()),
+        ))
+    }
+
+    /// Render every frame of an animation with its offset applied, all with the same size, as (width, height, RGBA pixels, duration) tuples
+    fn render_animation<'py>(
+        &self,
+        py: Python<'py>,
+        group: usize,
+        animation: usize,
+    ) -> PyResult<Vec<(u32, u32, &'py PyBytes, u8)>> {
+        let animation = self
+            .image
+            .animation_store
+            .anim_groups
+            .get(group)
+            .and_then(|animations| animations.get(animation))
+            .ok_or_else(|| {
+                PyIndexError::new_err(format!(
+                    "the animation {} of the group {} doesn't exist",
+                    animation, group
+                ))
+            })?;
+        let frames = animation.render_frames(&self.image).map_err(to_py_error)?;
+        Ok(frames
+            .into_iter()
+            .map(|(image, duration)| {
+                (
+                    image.width(),
+                    image.height(),
+                    PyBytes::new(py, image.as_raw()),
+                    duration,
+                )
+            })
+            .collect())
+    }
+}
+
+#[pymodule]
+fn pmd_wan_py(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyWanImage>()?;
+    module.add_class::<PyAnimationFrame>()?;
+    Ok(())
+}
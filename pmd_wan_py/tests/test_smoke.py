@@ -0,0 +1,59 @@
+"""Smoke test of the bindings. Run with `python -m unittest discover tests` once the module is installed with `maturin develop`."""
+
+import os
+import tempfile
+import unittest
+
+import pmd_wan_py
+
+DATA = os.path.join(os.path.dirname(__file__), "data")
+
+
+def read_props():
+    with open(os.path.join(DATA, "props.wan"), "rb") as file:
+        return file.read()
+
+
+class TestWanImage(unittest.TestCase):
+    def test_decode(self):
+        image = pmd_wan_py.WanImage.decode(read_props())
+        self.assertEqual(image.sprite_type, "props_ui")
+        self.assertFalse(image.is_256_color)
+        self.assertEqual(image.frame_count, 1)
+        self.assertEqual(len(image.palette), 16)
+        frame = image.animation_groups[0][0][0]
+        self.assertEqual((frame.frame_id, frame.duration), (0, 4))
+
+    def test_decode_invalid(self):
+        with self.assertRaises(ValueError):
+            pmd_wan_py.WanImage.decode(b"not a wan file")
+
+    def test_encode(self):
+        image = pmd_wan_py.WanImage.decode(read_props())
+        decoded = pmd_wan_py.WanImage.decode(image.encode())
+        self.assertEqual(decoded.frame_count, image.frame_count)
+        self.assertEqual(decoded.palette, image.palette)
+
+    def test_render(self):
+        image = pmd_wan_py.WanImage.decode(read_props())
+        width, height, rgba = image.render_frame(0)
+        self.assertEqual(len(rgba), width * height * 4)
+        self.assertTrue(any(rgba[3::4]))
+
+        frames = image.render_animation(0, 0)
+        self.assertEqual(len(frames), 1)
+        self.assertEqual(frames[0][3], 4)
+        with self.assertRaises(IndexError):
+            image.render_animation(1, 0)
+
+    def test_spritebot(self):
+        image = pmd_wan_py.WanImage.decode(read_props())
+        with tempfile.TemporaryDirectory() as folder:
+            image.export_spritebot(folder)
+            imported = pmd_wan_py.WanImage.import_spritebot(folder)
+        self.assertEqual(imported.sprite_type, "chara")
+        self.assertEqual(len(imported.animation_groups[0][0]), 1)
+
+
+if __name__ == "__main__":
+    unittest.main()
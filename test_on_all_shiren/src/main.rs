@@ -1,3 +1,6 @@
+// Experimental research binary: most of the analysis code is toggled by commenting it in and out.
+#![allow(unused)]
+
 use std::{
     collections::{HashMap, HashSet},
     fs::{read_dir, File},
@@ -6,7 +9,10 @@ use std::{
 };
 
 use clap::Parser;
-use pmd_wan::{shiren::{ShirenFragment, ShirenWan, shiren_export_fragment, ShirenPalette}, get_bit_u16};
+use pmd_wan::{
+    get_bit_u16,
+    shiren::{shiren_export_fragment, ShirenFragment, ShirenPalette, ShirenWan},
+};
 
 #[derive(Parser, Debug)]
 struct Opts {
@@ -26,9 +32,9 @@ struct TestSizeIndices {
 impl TestSizeIndices {
     fn add(&mut self, fragment: &ShirenFragment, len: usize) {
         /*self.sizes
-            .entry(len)
-            .or_default()
-            .insert(fragment.size_indice_y);*/
+        .entry(len)
+        .or_default()
+        .insert(fragment.size_indice_y);*/
         /*self.count.entry(len).or_default() += 1;
         if true /*len == 128*/ {
             for pos in 0..16 {
@@ -62,7 +68,6 @@ fn perform_test(path: &Path, test: &mut TestSizeIndices) {
         let mut previous_fragment = None;
         for fragment in &frame.fragments {
             if let Some(fragment_bytes_id) = fragment.fragment_bytes_id {
-                
                 let fragment_bytes_size = wan.fragment_bytes_store.fragment_bytes
                     [fragment_bytes_id as usize]
                     .bytes
@@ -74,17 +79,28 @@ fn perform_test(path: &Path, test: &mut TestSizeIndices) {
                     512 => 3,
                     _ => todo!(),
                 });*/
-                
+
                 if true {
-                    let export_file_name = format!("testimage/{}-{}-{}-{}-{}.png", fragment_bytes_size, fragment.oam_shape.shape_indice(), fragment.oam_shape.size_indice(), fragment_uid, path.file_name().unwrap().to_string_lossy());
-                    let image = shiren_export_fragment(fragment,  &wan.fragment_bytes_store.fragment_bytes[fragment_bytes_id as usize], &shiren_palette).unwrap();
+                    let export_file_name = format!(
+                        "testimage/{}-{}-{}-{}-{}.png",
+                        fragment_bytes_size,
+                        fragment.oam_shape.shape_indice(),
+                        fragment.oam_shape.size_indice(),
+                        fragment_uid,
+                        path.file_name().unwrap().to_string_lossy()
+                    );
+                    let image = shiren_export_fragment(
+                        fragment,
+                        &wan.fragment_bytes_store.fragment_bytes[fragment_bytes_id as usize],
+                        &shiren_palette,
+                    )
+                    .unwrap();
                     image.save(&export_file_name).unwrap();
                     fragment_uid += 1;
                 }
             }
 
-            test.add(&fragment, 0);
-
+            test.add(fragment, 0);
 
             /*if let Some(previous_fragment) = previous_fragment {
                 test.add(previous_fragment, 0);